@@ -0,0 +1,151 @@
+// Timings of representative scripts on both backends, and of parsing, so
+// that changes to the evaluator, the vm, and `Context` can be measured.
+// Run with `cargo bench`; criterion compares each run with the last one.
+
+use criterion::{
+  BatchSize, BenchmarkId, Criterion, criterion_group, criterion_main,
+};
+use sam::config::Backend;
+use sam::{Interpreter, Value};
+use std::collections::HashMap;
+use std::hint::black_box;
+
+const BACKENDS: [(&str, Backend); 2] =
+  [("tree", Backend::Tree), ("vm", Backend::Vm)];
+
+// a fresh session with `items`, the numbers below `n`, and `record(i)`,
+// which makes an object
+fn session(backend: Backend, n: i64) -> Interpreter {
+  let mut sam = Interpreter::options().backend(backend).build();
+
+  sam.set_global("items", (0..n).map(Value::from).collect::<Vec<_>>());
+  sam.register_fn("record", |args| {
+    let mut fields = HashMap::new();
+    fields.insert("id".to_owned(), args[0].clone());
+    fields.insert("name".to_owned(), Value::from("record"));
+    fields.insert("size".to_owned(), Value::from(64i64));
+    return Ok(Value::from(fields));
+  });
+
+  return sam;
+}
+
+fn bench_program(c: &mut Criterion, name: &str, n: i64, source: &str) {
+  let mut group = c.benchmark_group(name);
+
+  for (label, backend) in BACKENDS {
+    group.bench_function(BenchmarkId::from_parameter(label), |b| {
+      b.iter_batched(
+        || session(backend, n),
+        |mut sam| black_box(sam.eval(source).unwrap()),
+        BatchSize::SmallInput,
+      );
+    });
+  }
+
+  group.finish();
+}
+
+fn arithmetic(c: &mut Criterion) {
+  let source = "
+    let total = 0;
+    for i in items {
+      for j in items {
+        total = total + i * j % 7 - 1;
+      }
+    }
+    total;
+  ";
+
+  bench_program(c, "arithmetic", 200, source);
+}
+
+fn recursion(c: &mut Criterion) {
+  let source = "
+    let fib = (n) => {
+      if (n < 2) { return n; }
+      return fib(n - 1) + fib(n - 2);
+    };
+    fib(18);
+  ";
+  bench_program(c, "recursion/fib", 0, source);
+
+  // deep rather than wide
+  let source = "
+    let down = (n) => {
+      if (n == 0) { return 0; }
+      return 1 + down(n - 1);
+    };
+    for i in items { down(200); }
+  ";
+  bench_program(c, "recursion/deep", 50, source);
+}
+
+fn strings(c: &mut Criterion) {
+  let source = "
+    let s = '';
+    for i in items {
+      s = s + 'line ' + 'of text';
+    }
+    s;
+  ";
+
+  bench_program(c, "strings", 5000, source);
+}
+
+fn objects(c: &mut Criterion) {
+  let source = "
+    let total = 0;
+    for i in items {
+      let r = record(i);
+      let pair = [r, record(i + 1)];
+      let next = pair[1];
+      total = total + r.size + next.id;
+    }
+    total;
+  ";
+
+  bench_program(c, "objects", 5000, source);
+}
+
+// a long script of functions, loops, and calls
+fn long_source() -> String {
+  let mut source = String::new();
+
+  for n in 0..500 {
+    source.push_str(&format!(
+      "let f{n} = (a, b) => {{
+         let total = a * {n};
+         for x in [a, b, {n}] {{
+           if (x > b) {{ total = total + x; }} else {{ total = total - 1; }}
+         }}
+         return total;
+       }};
+       let r{n} = f{n}({n}, 'text' + 'more');\n"
+    ));
+  }
+
+  return source;
+}
+
+fn parsing(c: &mut Criterion) {
+  let source = long_source();
+  let mut group = c.benchmark_group("parsing");
+
+  group.bench_function("parse", |b| {
+    let mut parser = sam::parser();
+    b.iter(|| black_box(parser.parse(&source, None).unwrap()));
+  });
+
+  group.bench_function("lower", |b| {
+    let tree = sam::parser().parse(&source, None).unwrap();
+    b.iter(|| {
+      black_box(sam::ast::lower(tree.root_node(), source.as_bytes()).unwrap())
+    });
+  });
+
+  group.finish();
+}
+
+criterion_group!(benches, arithmetic, recursion, strings, objects, parsing);
+criterion_main!(benches);
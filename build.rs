@@ -4,8 +4,17 @@ fn main() {
   let source_dir = format!("{}/src", package);
   let source_file = format!("{}/parser.c", source_dir);
 
-  cc::Build::new()
-    .include(&source_dir)
-    .file(&source_file)
-    .compile(&package);
+  let mut build = cc::Build::new();
+  build.include(&source_dir).file(&source_file);
+
+  // WebAssembly has no libc, tree-sitter-language provides the little the
+  // parser needs
+  let target = std::env::var("TARGET").unwrap_or_default();
+  if target.starts_with("wasm32-unknown") {
+    let headers = std::env::var("DEP_TREE_SITTER_LANGUAGE_WASM_HEADERS")
+      .expect("tree-sitter-language provides the wasm headers");
+    build.include(headers);
+  }
+
+  build.compile(&package);
 }
@@ -0,0 +1,14 @@
+#![no_main]
+
+// any text runs on both backends without panicking. Limits keep every run
+// short and small, so a hang is a limit that is not checked somewhere.
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+  let Ok(source) = std::str::from_utf8(data) else {
+    return;
+  };
+
+  sam_fuzz::run(source);
+});
@@ -0,0 +1,26 @@
+#![no_main]
+
+// any text must either lower to a program or fail with a syntax or parse
+// error; a panic is a crash
+
+use libfuzzer_sys::fuzz_target;
+use sam::ast;
+use sam::syntax::syntax_errors;
+
+fuzz_target!(|data: &[u8]| {
+  let Ok(source) = std::str::from_utf8(data) else {
+    return;
+  };
+
+  let mut parser = sam::parser();
+  let Some(tree) = parser.parse(source, None) else {
+    return;
+  };
+
+  let root = tree.root_node();
+  if !syntax_errors(root, source.as_bytes()).is_empty() {
+    return;
+  }
+
+  let _ = ast::lower(root, source.as_bytes());
+});
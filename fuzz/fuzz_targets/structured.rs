@@ -0,0 +1,161 @@
+#![no_main]
+
+// programs that always parse, so the input is spent on the evaluator rather
+// than on syntax errors. The input picks a small tree of statements from a
+// few names, which is rendered to source and run on both backends.
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use sam::ast::BinaryOp;
+
+// few enough that declarations and uses meet
+const NAMES: [&str; 4] = ["a", "b", "f", "xs"];
+
+#[derive(Debug, Arbitrary)]
+enum Stmt {
+  Let(u8, Expr),
+  Assign(u8, Expr),
+  Function(u8, Vec<u8>, Vec<Stmt>),
+  If(Expr, Vec<Stmt>, Vec<Stmt>),
+  For(u8, Expr, Vec<Stmt>),
+  Return(Expr),
+  Break,
+  Continue,
+  Expr(Expr),
+}
+
+#[derive(Debug, Arbitrary)]
+enum Expr {
+  Int(u16),
+  Float(u8),
+  Str(u8),
+  Name(u8),
+  Binary(u8, Box<Expr>, Box<Expr>),
+  Array(Vec<Expr>),
+  Index(u8, Box<Expr>),
+  Call(u8, Vec<Expr>),
+}
+
+#[derive(Debug, Arbitrary)]
+struct Program(Vec<Stmt>);
+
+fn name(n: u8) -> &'static str {
+  return NAMES[n as usize % NAMES.len()];
+}
+
+// `//` is left out, it reads as the start of a comment
+fn operator(n: u8) -> &'static str {
+  let ops: Vec<BinaryOp> = BinaryOp::ALL
+    .into_iter()
+    .filter(|op| *op != BinaryOp::FloorDiv)
+    .collect();
+  return ops[n as usize % ops.len()].as_str();
+}
+
+fn expression(expr: &Expr, out: &mut String) {
+  match expr {
+    Expr::Int(n) => out.push_str(&n.to_string()),
+    Expr::Float(n) => out.push_str(&format!("{}.5", n)),
+    Expr::Str(n) => out.push_str(&format!("'s{}'", n)),
+    Expr::Name(n) => out.push_str(name(*n)),
+    Expr::Binary(op, left, right) => {
+      expression(left, out);
+      out.push_str(&format!(" {} ", operator(*op)));
+      expression(right, out);
+    }
+    Expr::Array(items) => {
+      out.push('[');
+      for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+          out.push_str(", ");
+        }
+        expression(item, out);
+      }
+      out.push(']');
+    }
+    Expr::Index(array, index) => {
+      out.push_str(name(*array));
+      out.push('[');
+      expression(index, out);
+      out.push(']');
+    }
+    Expr::Call(f, args) => {
+      out.push_str(name(*f));
+      out.push('(');
+      for (i, arg) in args.iter().enumerate() {
+        if i > 0 {
+          out.push_str(", ");
+        }
+        expression(arg, out);
+      }
+      out.push(')');
+    }
+  }
+}
+
+fn block(statements: &[Stmt], out: &mut String) {
+  out.push_str("{ ");
+  for stmt in statements {
+    statement(stmt, out);
+  }
+  out.push('}');
+}
+
+fn statement(stmt: &Stmt, out: &mut String) {
+  match stmt {
+    Stmt::Let(n, value) => {
+      out.push_str(&format!("let {} = ", name(*n)));
+      expression(value, out);
+      out.push_str("; ");
+    }
+    Stmt::Assign(n, value) => {
+      out.push_str(&format!("{} = ", name(*n)));
+      expression(value, out);
+      out.push_str("; ");
+    }
+    Stmt::Function(n, params, body) => {
+      let params: Vec<&str> = params.iter().map(|p| name(*p)).collect();
+      out.push_str(&format!("let {} = ({}) => ", name(*n), params.join(", ")));
+      block(body, out);
+      out.push_str("; ");
+    }
+    Stmt::If(condition, then, otherwise) => {
+      out.push_str("if (");
+      expression(condition, out);
+      out.push_str(") ");
+      block(then, out);
+      if !otherwise.is_empty() {
+        out.push_str(" else ");
+        block(otherwise, out);
+      }
+      out.push(' ');
+    }
+    Stmt::For(n, items, body) => {
+      out.push_str(&format!("for {} in ", name(*n)));
+      expression(items, out);
+      out.push(' ');
+      block(body, out);
+      out.push(' ');
+    }
+    Stmt::Return(value) => {
+      out.push_str("return ");
+      expression(value, out);
+      out.push_str("; ");
+    }
+    Stmt::Break => out.push_str("break; "),
+    Stmt::Continue => out.push_str("continue; "),
+    Stmt::Expr(value) => {
+      expression(value, out);
+      out.push_str("; ");
+    }
+  }
+}
+
+fuzz_target!(|program: Program| {
+  let mut source = String::new();
+  for stmt in &program.0 {
+    statement(stmt, &mut source);
+  }
+
+  sam_fuzz::run(&source);
+});
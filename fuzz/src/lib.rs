@@ -0,0 +1,56 @@
+// what the evaluating targets share: a session that can not touch the
+// machine it runs on and stops itself early
+
+use sam::Interpreter;
+use sam::codes;
+use sam::config::{Backend, Limits};
+use sam::output::Writer;
+use sam::permissions::{Allow, Permissions};
+
+const BACKENDS: [Backend; 2] = [Backend::Tree, Backend::Vm];
+
+// small enough for libFuzzer's default timeout even on the tree walker
+fn limits() -> Limits {
+  return Limits {
+    steps: Some(100_000),
+    loop_iterations: Some(10_000),
+    call_depth: Some(64),
+    value_size: Some(1 << 16),
+  };
+}
+
+fn session(backend: Backend) -> Interpreter {
+  // no commands, files, or hosts, so calls to unbound names fail instead of
+  // running whatever the input names
+  let permissions = Permissions {
+    run: Allow::Nothing,
+    read: Allow::Nothing,
+    net: Allow::Nothing,
+  };
+
+  return Interpreter::options()
+    .backend(backend)
+    .limits(limits())
+    .permissions(permissions)
+    .stdout(Writer::new(std::io::sink()))
+    .stderr(Writer::new(std::io::sink()))
+    .build();
+}
+
+// evaluate `source` on every backend. Errors are expected, but `eval`
+// catches panics and reports them as INTERNAL_ERROR, which is a crash here.
+pub fn run(source: &str) {
+  for backend in BACKENDS {
+    let mut sam = session(backend);
+
+    if let Err(err) = sam.eval(source) {
+      assert_ne!(
+        err.code(),
+        Some(codes::INTERNAL_ERROR),
+        "{:?} backend panicked: {}",
+        backend,
+        err
+      );
+    }
+  }
+}
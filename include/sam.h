@@ -0,0 +1,73 @@
+#ifndef SAM_H
+#define SAM_H
+
+/* Generated by cbindgen from src/capi.rs, do not edit. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * An interpreter session, created by `sam_new` and freed by `sam_free`.
+ */
+typedef struct SamInterpreter SamInterpreter;
+
+/**
+ * Create an interpreter with the default settings.
+ */
+SamInterpreter *sam_new(void);
+
+/**
+ * Run `source` after everything evaluated before and return the value of
+ * its last statement as JSON, to be freed with `sam_string_free`. Returns
+ * NULL if it failed, `sam_error` tells why.
+ *
+ * # Safety
+ *
+ * `sam` must come from `sam_new` and `source` must be a NUL-terminated
+ * string.
+ */
+char *sam_eval(SamInterpreter *sam, const char *source);
+
+/**
+ * The value of the global variable `name` as JSON, to be freed with
+ * `sam_string_free`. Returns NULL if there is no such variable or its
+ * value has no JSON form, e.g. a function; `sam_error` tells which.
+ *
+ * # Safety
+ *
+ * `sam` must come from `sam_new` and `name` must be a NUL-terminated
+ * string.
+ */
+char *sam_get_var(SamInterpreter *sam, const char *name);
+
+/**
+ * Why the last call on `sam` returned NULL, or NULL if it succeeded. The
+ * message belongs to `sam` and is valid until its next call.
+ *
+ * # Safety
+ *
+ * `sam` must come from `sam_new`.
+ */
+const char *sam_error(const SamInterpreter *sam);
+
+/**
+ * Free a string returned by `sam_eval` or `sam_get_var`. NULL is ignored.
+ *
+ * # Safety
+ *
+ * `s` must come from one of those functions and not be freed twice.
+ */
+void sam_string_free(char *s);
+
+/**
+ * Free an interpreter and everything it holds. NULL is ignored.
+ *
+ * # Safety
+ *
+ * `sam` must come from `sam_new` and not be freed twice.
+ */
+void sam_free(SamInterpreter *sam);
+
+#endif  /* SAM_H */
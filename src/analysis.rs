@@ -0,0 +1,353 @@
+#![allow(dead_code)]
+
+use crate::ast;
+use crate::codes;
+use crate::error::{SamError, Span};
+use std::collections::{HashMap, HashSet};
+use tree_sitter::Node;
+
+// errors that can be found without running the script: names used before
+// their declaration, assignments to constants, duplicate parameter names,
+// and break or continue outside of a loop. Everything is collected so the
+// user sees all of them at once.
+pub fn check(root: Node, source: &[u8]) -> Vec<SamError> {
+  let mut analyzer = Analyzer {
+    source,
+    scopes: Vec::new(),
+    loops: 0,
+    in_function: false,
+    errors: Vec::new(),
+  };
+
+  analyzer.block(root, Vec::new());
+
+  let mut errors = analyzer.errors;
+  errors.sort_by_key(|e| e.span().map(|s| s.start));
+
+  return errors;
+}
+
+struct Scope {
+  // declared names, and whether they are constants
+  declared: HashMap<String, bool>,
+  // names declared further down in this block, with the declaration
+  pending: HashMap<String, Span>,
+}
+
+struct Analyzer<'s> {
+  source: &'s [u8],
+  scopes: Vec<Scope>,
+  // loops enclosing the current node within the current function
+  loops: usize,
+  // function bodies run later, in the scope of their caller, so names
+  // declared after them may well exist by then
+  in_function: bool,
+  errors: Vec<SamError>,
+}
+
+impl Analyzer<'_> {
+  /* =========================
+  Scopes
+  ========================= */
+
+  // statements of a block, with the given names already declared in it
+  fn block(&mut self, node: Node, declared: Vec<(String, bool)>) {
+    let mut pending = HashMap::new();
+    let mut walker = node.walk();
+
+    for stmt in node.named_children(&mut walker) {
+      if stmt.kind() != "variable_declaration" {
+        continue;
+      }
+
+      let mut inner = stmt.walk();
+      for declarator in stmt.named_children(&mut inner) {
+        if let Some(variable) = declarator.child_by_field_name("variable") {
+          pending
+            .entry(self.text(variable))
+            .or_insert(variable.into());
+        }
+      }
+    }
+
+    self.scopes.push(Scope {
+      declared: declared.into_iter().collect(),
+      pending,
+    });
+
+    for stmt in node.named_children(&mut walker) {
+      self.statement(stmt);
+    }
+
+    self.scopes.pop();
+  }
+
+  fn declare(&mut self, name: String, constant: bool) {
+    let scope = self.scopes.last_mut().unwrap();
+    scope.pending.remove(&name);
+    scope.declared.insert(name, constant);
+  }
+
+  // whether the name is declared, and as a constant, innermost scope first
+  fn lookup(&self, name: &str) -> Option<bool> {
+    return self
+      .scopes
+      .iter()
+      .rev()
+      .find_map(|scope| scope.declared.get(name).copied());
+  }
+
+  // where a name that is not declared yet will be declared
+  fn declared_later(&self, name: &str) -> Option<Span> {
+    return self
+      .scopes
+      .iter()
+      .rev()
+      .find_map(|scope| scope.pending.get(name).copied());
+  }
+
+  fn text(&self, node: Node) -> String {
+    node.utf8_text(self.source).unwrap_or("").to_owned()
+  }
+
+  /* =========================
+  Statements
+  ========================= */
+
+  fn statement(&mut self, node: Node) {
+    if let Some(keyword) = ast::jump(node, self.source) {
+      if self.loops == 0 {
+        self.errors.push(
+          SamError::parse(
+            codes::BREAK_OUTSIDE_LOOP,
+            format!("`{}` outside of a loop", keyword),
+          )
+          .at(node),
+        );
+      }
+      return;
+    }
+
+    match node.kind() {
+      "interfaces" => {
+        let mut walker = node.walk();
+        for interface in node.named_children(&mut walker) {
+          if let Some(module) = interface.child_by_field_name("module") {
+            self.declare(self.text(module), false);
+          }
+        }
+      }
+
+      "variable_declaration" => {
+        let constant = node
+          .child(0)
+          .is_some_and(|keyword| self.text(keyword) == "const");
+
+        let mut walker = node.walk();
+        for declarator in node.named_children(&mut walker) {
+          if let Some(value) = declarator.child_by_field_name("value") {
+            self.expression(value);
+          }
+          if let Some(variable) = declarator.child_by_field_name("variable") {
+            self.declare(self.text(variable), constant);
+          }
+        }
+      }
+
+      "assignment" => {
+        if let Some(rhs) = node.child_by_field_name("rhs") {
+          self.expression(rhs);
+        }
+        if let Some(lhs) = node.child_by_field_name("lhs") {
+          self.assign(lhs);
+        }
+      }
+
+      _ => self.children(node),
+    }
+  }
+
+  fn assign(&mut self, lhs: Node) {
+    let name = self.text(lhs);
+
+    match self.lookup(&name) {
+      Some(true) => self.errors.push(
+        SamError::name(
+          codes::ASSIGN_TO_CONSTANT,
+          format!("Cannot assign to constant {}", name),
+        )
+        .at(lhs)
+        .with_help(format!("declare `{}` with `let` to allow changes", name)),
+      ),
+      Some(false) => {}
+      None => self.use_name(lhs),
+    }
+  }
+
+  /* =========================
+  Expressions
+  ========================= */
+
+  fn expression(&mut self, node: Node) {
+    match node.kind() {
+      "identifier" => self.use_name(node),
+
+      "lambda_expression" => self.lambda(node),
+
+      "statement_block" => self.block(node, Vec::new()),
+
+      "for_expression" => {
+        if let Some(iterable) = node.child_by_field_name("iterable") {
+          self.expression(iterable);
+        }
+
+        let variable = node
+          .child_by_field_name("variable")
+          .map(|v| vec![(self.text(v), false)])
+          .unwrap_or_default();
+
+        if let Some(body) = node.child_by_field_name("body") {
+          self.loops += 1;
+          self.block(body, variable);
+          self.loops -= 1;
+        }
+      }
+
+      // only the object is a variable, the property name is not
+      "nested_identifier" => {
+        if let Some(parent) = node.child_by_field_name("parent") {
+          self.expression(parent);
+        }
+      }
+
+      _ => self.children(node),
+    }
+  }
+
+  fn use_name(&mut self, node: Node) {
+    if self.in_function {
+      return;
+    }
+
+    let name = self.text(node);
+    if self.lookup(&name).is_some() {
+      return;
+    }
+
+    if let Some(declaration) = self.declared_later(&name) {
+      self.errors.push(
+        SamError::name(
+          codes::USE_BEFORE_DECLARATION,
+          format!("Variable {} used before its declaration", name),
+        )
+        .at(node)
+        .with_help(format!(
+          "move the declaration at {} above this use",
+          declaration
+        )),
+      );
+    }
+  }
+
+  fn lambda(&mut self, node: Node) {
+    let mut params = Vec::new();
+    let mut seen = HashSet::new();
+
+    if let Some(params_node) = node.child_by_field_name("parameters") {
+      let mut walker = params_node.walk();
+      for param in params_node.named_children(&mut walker) {
+        if param.kind() != "identifier" {
+          continue;
+        }
+
+        let name = self.text(param);
+        if !seen.insert(name.clone()) {
+          self.errors.push(
+            SamError::parse(
+              codes::DUPLICATE_PARAMETER,
+              format!("Duplicate parameter {}", name),
+            )
+            .at(param),
+          );
+        }
+
+        params.push((name, false));
+      }
+    }
+
+    // loops outside of the function cannot be broken from inside it
+    let loops = std::mem::replace(&mut self.loops, 0);
+    let in_function = std::mem::replace(&mut self.in_function, true);
+
+    if let Some(body) = node.child_by_field_name("body") {
+      self.block(body, params);
+    }
+
+    self.loops = loops;
+    self.in_function = in_function;
+  }
+
+  fn children(&mut self, node: Node) {
+    let mut walker = node.walk();
+    for child in node.named_children(&mut walker) {
+      self.expression(child);
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use tree_sitter::{Language, Parser};
+
+  // retrieve Language struct from C code
+  unsafe extern "C" {
+    fn tree_sitter_sam() -> Language;
+  }
+
+  fn errors_for(source: &[u8]) -> Vec<String> {
+    let language = unsafe { tree_sitter_sam() };
+    let mut parser = Parser::new();
+    parser.set_language(&language).unwrap();
+
+    let tree = parser.parse(source, None).unwrap();
+
+    return check(tree.root_node(), source)
+      .iter()
+      .map(|e| e.message().to_owned())
+      .collect();
+  }
+
+  #[test]
+  fn test_use_before_declaration() {
+    let errors = errors_for(
+      b"
+      let a = b + 1;
+      let b = 2;
+      let f = () => { return c; };
+      let c = 3;
+    ",
+    );
+    assert_eq!(errors, vec!["Variable b used before its declaration"]);
+  }
+
+  #[test]
+  fn test_all_errors_reported() {
+    let errors = errors_for(
+      b"
+      const limit = 10;
+      limit = 20;
+      let f = (x, x) => { break; };
+      for i in [1, 2] { continue; }
+    ",
+    );
+    assert_eq!(
+      errors,
+      vec![
+        "Cannot assign to constant limit",
+        "Duplicate parameter x",
+        "`break` outside of a loop",
+      ]
+    );
+  }
+}
@@ -0,0 +1,944 @@
+#![allow(dead_code)]
+
+// a typed, owned syntax tree of sam, lowered once from the tree-sitter
+// tree so that tools (formatters, linters, code generators) can work on
+// plain data instead of node kinds and field names:
+//
+//   let program = sam::ast::parse("let a = 1 + b;")?;
+//   for stmt in &program.statements { ... }
+//
+// Literals are already parsed and operators resolved. Every node keeps the
+// span it was lowered from, for diagnostics. The evaluator runs this tree
+// too, the source is only read once.
+
+use crate::codes;
+use crate::error::{SamError, Span};
+use crate::suggest::did_you_mean;
+use crate::symbol::Symbol;
+use crate::syntax::syntax_errors;
+use crate::value::{Number, Value};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tree_sitter::Node;
+
+/* =========================
+Nodes
+========================= */
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Program {
+  pub statements: Vec<Stmt>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Stmt {
+  pub kind: StmtKind,
+  pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum StmtKind {
+  Expression(Expr),
+  // `let a = 1, b;` or `const a = 1;`
+  Declaration {
+    constant: bool,
+    declarators: Vec<Declarator>,
+  },
+  Assignment {
+    target: Ident,
+    value: Expr,
+  },
+  Return(Option<Expr>),
+  Break,
+  Continue,
+  // `interface 'git.json' load git;`
+  Interfaces(Vec<Interface>),
+  // `import('util/paths');`
+  Import(Import),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Declarator {
+  pub name: Ident,
+  pub value: Option<Expr>,
+  pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Interface {
+  pub path: String,
+  pub path_span: Span,
+  pub module: Ident,
+  pub span: Span,
+}
+
+// a module whose top level names are defined where it is imported, see
+// src/modules.rs
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Import {
+  pub path: String,
+  pub path_span: Span,
+  pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Ident {
+  pub name: Symbol,
+  pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Block {
+  pub statements: Vec<Stmt>,
+  pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Expr {
+  pub kind: ExprKind,
+  pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ExprKind {
+  Literal(Literal),
+  Identifier(Symbol),
+  Binary {
+    op: BinaryOp,
+    left: Box<Expr>,
+    right: Box<Expr>,
+  },
+  If(If),
+  For {
+    variable: Ident,
+    iterable: Box<Expr>,
+    body: Block,
+  },
+  // the body is shared with the functions created from it
+  Lambda {
+    params: Vec<Ident>,
+    body: Arc<Block>,
+  },
+  Call(Call),
+  // `obj.name`
+  Member {
+    object: Box<Expr>,
+    property: Ident,
+  },
+  Array(Vec<Expr>),
+  // `items[i]`, only variables can be indexed
+  Index {
+    array: Ident,
+    index: Box<Expr>,
+  },
+  // `spawn(f(x))`
+  Spawn(Call),
+  // `await(h)`
+  Await(Box<Expr>),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct If {
+  pub condition: Box<Expr>,
+  pub consequence: Block,
+  pub alternative: Option<Else>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Else {
+  Block(Block),
+  If(Box<If>, Span),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Call {
+  pub function: Box<Expr>,
+  pub args: Vec<Expr>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Literal {
+  Number(Number),
+  String(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum BinaryOp {
+  Add,
+  Sub,
+  Mul,
+  Div,
+  Rem,
+  Lt,
+  Gt,
+  Eq,
+  Le,
+  Ge,
+  Ne,
+  And,
+  Or,
+}
+
+impl BinaryOp {
+  pub const ALL: [BinaryOp; 13] = [
+    BinaryOp::Add,
+    BinaryOp::Sub,
+    BinaryOp::Mul,
+    BinaryOp::Div,
+    BinaryOp::Rem,
+    BinaryOp::Lt,
+    BinaryOp::Gt,
+    BinaryOp::Eq,
+    BinaryOp::Le,
+    BinaryOp::Ge,
+    BinaryOp::Ne,
+    BinaryOp::And,
+    BinaryOp::Or,
+  ];
+
+  pub fn as_str(self) -> &'static str {
+    match self {
+      BinaryOp::Add => "+",
+      BinaryOp::Sub => "-",
+      BinaryOp::Mul => "*",
+      BinaryOp::Div => "/",
+      BinaryOp::Rem => "%",
+      BinaryOp::Lt => "<",
+      BinaryOp::Gt => ">",
+      BinaryOp::Eq => "==",
+      BinaryOp::Le => "<=",
+      BinaryOp::Ge => ">=",
+      BinaryOp::Ne => "!=",
+      BinaryOp::And => "&&",
+      BinaryOp::Or => "||",
+    }
+  }
+
+  pub fn from_symbol(op: &str) -> Option<BinaryOp> {
+    return BinaryOp::ALL.into_iter().find(|o| o.as_str() == op);
+  }
+}
+
+impl Literal {
+  pub fn to_value(&self) -> Value {
+    match self {
+      Literal::Number(n) => Value::SamNumber(*n),
+      Literal::String(s) => Value::from(s.clone()),
+    }
+  }
+
+  // the literal writing a value, if there is one
+  pub fn from_value(v: &Value) -> Option<Literal> {
+    match v {
+      Value::SamNumber(n) => Some(Literal::Number(*n)),
+      Value::SamString(s) => Some(Literal::String(s.to_string())),
+      _ => None,
+    }
+  }
+}
+
+/* =========================
+Lowering
+========================= */
+
+// parse and lower `source`, failing on its first syntax error
+pub fn parse(source: &str) -> Result<Program, SamError> {
+  let tree = crate::parser().parse(source, None).unwrap();
+  let root = tree.root_node();
+
+  if let Some(err) = syntax_errors(root, source.as_bytes()).into_iter().next() {
+    return Err(err);
+  }
+
+  return lower(root, source.as_bytes());
+}
+
+// the program of a `source_file` node without syntax errors
+pub fn lower(root: Node, source: &[u8]) -> Result<Program, SamError> {
+  expect(&root, "source_file")?;
+
+  return Ok(Program {
+    statements: statements(root, source)?,
+  });
+}
+
+fn statements(node: Node, source: &[u8]) -> Result<Vec<Stmt>, SamError> {
+  let mut walker = node.walk();
+
+  return node
+    .named_children(&mut walker)
+    .map(|stmt| lower_statement(stmt, source))
+    .collect();
+}
+
+pub fn lower_statement(node: Node, source: &[u8]) -> Result<Stmt, SamError> {
+  let kind = match node.kind() {
+    "expression_statement" => match jump(node, source) {
+      Some("break") => StmtKind::Break,
+      Some(_) => StmtKind::Continue,
+      None => match import_call(node, source) {
+        Some(call) => import(call, node, source)?,
+        None => {
+          StmtKind::Expression(lower_expression(child(&node, 0)?, source)?)
+        }
+      },
+    },
+
+    "variable_declaration" => {
+      let constant = text(&child(&node, 0)?, source)? == "const";
+      let mut declarators = Vec::new();
+
+      let mut walker = node.walk();
+      for declarator in node.named_children(&mut walker) {
+        expect(&declarator, "variable_declarator")?;
+        declarators.push(Declarator {
+          name: ident(field(&declarator, "variable")?, source)?,
+          value: declarator
+            .child_by_field_name("value")
+            .map(|v| lower_expression(v, source))
+            .transpose()?,
+          span: declarator.into(),
+        });
+      }
+
+      StmtKind::Declaration {
+        constant,
+        declarators,
+      }
+    }
+
+    "assignment" => StmtKind::Assignment {
+      target: ident(field(&node, "lhs")?, source)?,
+      value: lower_expression(field(&node, "rhs")?, source)?,
+    },
+
+    "return_statement" => StmtKind::Return(
+      node
+        .child_by_field_name("value")
+        .map(|v| lower_expression(v, source))
+        .transpose()?,
+    ),
+
+    "interfaces" => {
+      let mut interfaces = Vec::new();
+
+      let mut walker = node.walk();
+      for interface in node.named_children(&mut walker) {
+        expect(&interface, "interface")?;
+        let path = field(&interface, "path")?;
+        interfaces.push(Interface {
+          path: string(path, source)?,
+          path_span: path.into(),
+          module: ident(field(&interface, "module")?, source)?,
+          span: interface.into(),
+        });
+      }
+
+      StmtKind::Interfaces(interfaces)
+    }
+
+    _ => {
+      return Err(
+        SamError::parse(codes::MALFORMED_TREE, "Unknown statement").at(node),
+      );
+    }
+  };
+
+  return Ok(Stmt {
+    kind,
+    span: node.into(),
+  });
+}
+
+// `break` or `continue` for a statement of that keyword alone. The grammar
+// has no rule for them: `break;` is an expression statement of an
+// identifier, which is a keyword in that position.
+pub fn jump(node: Node, source: &[u8]) -> Option<&'static str> {
+  if node.kind() != "expression_statement" {
+    return None;
+  }
+
+  let expr = node.named_child(0).filter(|n| n.kind() == "identifier")?;
+  let name = expr.utf8_text(source).ok()?;
+
+  return ["break", "continue"].into_iter().find(|k| *k == name);
+}
+
+// the call of `import('util/paths');`, a statement of its own. The grammar
+// has no rule for imports either, so one is written as a call of `import`,
+// which is a keyword in that position.
+pub fn import_call<'t>(node: Node<'t>, source: &[u8]) -> Option<Node<'t>> {
+  if node.kind() != "expression_statement" {
+    return None;
+  }
+
+  let call = node
+    .named_child(0)
+    .filter(|n| n.kind() == "call_expression")?;
+  let function = call.child_by_field_name("function")?;
+
+  return match function.kind() == "identifier"
+    && function.utf8_text(source).ok()? == "import"
+  {
+    true => Some(call),
+    false => None,
+  };
+}
+
+// the import of the statement `node`, given its call of `import`, whose
+// one argument must be a string literal: modules are found before the
+// script runs
+fn import(call: Node, node: Node, source: &[u8]) -> Result<StmtKind, SamError> {
+  let mut args = Vec::new();
+  if let Some(args_node) = call.child_by_field_name("arguments") {
+    let mut walker = args_node.walk();
+    args.extend(args_node.named_children(&mut walker));
+  }
+
+  if args.len() != 1 {
+    return Err(
+      SamError::runtime(
+        codes::ARGUMENT_COUNT,
+        format!("`import` takes 1 argument, found {}", args.len()),
+      )
+      .at(call),
+    );
+  }
+
+  let Some(path) = args[0]
+    .named_child(0)
+    .filter(|n| args[0].kind() == "literal" && n.kind() == "string")
+  else {
+    return Err(
+      SamError::type_error(
+        codes::ARGUMENT_TYPE,
+        "The path of an import must be a string literal",
+      )
+      .at(args[0])
+      .with_help("write the path out, e.g. `import('util/paths');`"),
+    );
+  };
+
+  return Ok(StmtKind::Import(Import {
+    path: string(path, source)?,
+    path_span: path.into(),
+    span: node.into(),
+  }));
+}
+
+pub fn lower_expression(node: Node, source: &[u8]) -> Result<Expr, SamError> {
+  let boxed = |n: Node| lower_expression(n, source).map(Box::new);
+
+  let kind = match node.kind() {
+    "literal" => {
+      let literal = child(&node, 0)?;
+      match literal.kind() {
+        "number" => {
+          ExprKind::Literal(Literal::Number(number(literal, source)?))
+        }
+        "string" => {
+          ExprKind::Literal(Literal::String(string(literal, source)?))
+        }
+        _ => {
+          return Err(
+            SamError::parse(codes::MALFORMED_TREE, "Unknown literal").at(node),
+          );
+        }
+      }
+    }
+
+    "identifier" => ExprKind::Identifier(text(&node, source)?.into()),
+
+    "binary_expression" => ExprKind::Binary {
+      op: operator(child(&node, 1)?, source)?,
+      left: boxed(field(&node, "left")?)?,
+      right: boxed(field(&node, "right")?)?,
+    },
+
+    "if_expression" => ExprKind::If(lower_if(node, source)?),
+
+    "for_expression" => ExprKind::For {
+      variable: ident(field(&node, "variable")?, source)?,
+      iterable: boxed(field(&node, "iterable")?)?,
+      body: block(field(&node, "body")?, source)?,
+    },
+
+    "lambda_expression" => {
+      let mut params = Vec::new();
+
+      if let Some(params_node) = node.child_by_field_name("parameters") {
+        let mut walker = params_node.walk();
+        for param in params_node.named_children(&mut walker) {
+          if param.kind() == "identifier" {
+            params.push(ident(param, source)?);
+          }
+        }
+      }
+
+      ExprKind::Lambda {
+        params,
+        body: Arc::new(block(field(&node, "body")?, source)?),
+      }
+    }
+
+    "call_expression" => keyword_call(call(node, source)?, node)?,
+
+    "nested_identifier" => ExprKind::Member {
+      object: boxed(field(&node, "parent")?)?,
+      property: Ident {
+        name: text(&field(&node, "name")?, source)?.into(),
+        span: field(&node, "name")?.into(),
+      },
+    },
+
+    "array_expression" => {
+      let mut walker = node.walk();
+      let items = node
+        .named_children(&mut walker)
+        .map(|item| lower_expression(item, source))
+        .collect::<Result<_, _>>()?;
+
+      ExprKind::Array(items)
+    }
+
+    "array_access_expression" => ExprKind::Index {
+      array: ident(field(&node, "array")?, source)?,
+      index: boxed(field(&node, "index")?)?,
+    },
+
+    _ => {
+      return Err(
+        SamError::parse(codes::MALFORMED_TREE, "Unknown expression").at(node),
+      );
+    }
+  };
+
+  return Ok(Expr {
+    kind,
+    span: node.into(),
+  });
+}
+
+// `spawn(f(x))` and `await(h)`, which the grammar has no keywords for:
+// calls of those names are spawns and awaits, of their one argument
+fn keyword_call(call: Call, node: Node) -> Result<ExprKind, SamError> {
+  let keyword = match &call.function.kind {
+    ExprKind::Identifier(name) if *name == "spawn" || *name == "await" => {
+      name.as_str()
+    }
+    _ => return Ok(ExprKind::Call(call)),
+  };
+
+  let mut args = call.args;
+  if args.len() != 1 {
+    return Err(
+      SamError::runtime(
+        codes::ARGUMENT_COUNT,
+        format!("`{}` takes 1 argument, found {}", keyword, args.len()),
+      )
+      .at(node),
+    );
+  }
+  let arg = args.pop().unwrap();
+
+  if keyword == "await" {
+    return Ok(ExprKind::Await(Box::new(arg)));
+  }
+
+  return match arg.kind {
+    ExprKind::Call(call) => Ok(ExprKind::Spawn(call)),
+    _ => Err(
+      SamError::type_error(
+        codes::NOT_CALLABLE,
+        "The argument of spawn must be a call",
+      )
+      .at(arg.span)
+      .with_help("spawn a call, e.g. `spawn(ls())`"),
+    ),
+  };
+}
+
+fn lower_if(node: Node, source: &[u8]) -> Result<If, SamError> {
+  let alternative = match node.child_by_field_name("else") {
+    None => None,
+    Some(arm) => Some(match arm.kind() {
+      "statement_block" => Else::Block(block(arm, source)?),
+      "if_expression" => Else::If(Box::new(lower_if(arm, source)?), arm.into()),
+      _ => {
+        return Err(
+          SamError::parse(codes::MALFORMED_TREE, "Invalid else").at(arm),
+        );
+      }
+    }),
+  };
+
+  return Ok(If {
+    condition: Box::new(lower_expression(field(&node, "condition")?, source)?),
+    consequence: block(field(&node, "consequence")?, source)?,
+    alternative,
+  });
+}
+
+fn call(node: Node, source: &[u8]) -> Result<Call, SamError> {
+  let mut args = Vec::new();
+
+  if let Some(args_node) = node.child_by_field_name("arguments") {
+    let mut walker = args_node.walk();
+    for arg in args_node.named_children(&mut walker) {
+      args.push(lower_expression(arg, source)?);
+    }
+  }
+
+  return Ok(Call {
+    function: Box::new(lower_expression(field(&node, "function")?, source)?),
+    args,
+  });
+}
+
+fn block(node: Node, source: &[u8]) -> Result<Block, SamError> {
+  expect(&node, "statement_block")?;
+
+  return Ok(Block {
+    statements: statements(node, source)?,
+    span: node.into(),
+  });
+}
+
+fn ident(node: Node, source: &[u8]) -> Result<Ident, SamError> {
+  expect(&node, "identifier")?;
+
+  return Ok(Ident {
+    name: text(&node, source)?.into(),
+    span: node.into(),
+  });
+}
+
+fn operator(node: Node, source: &[u8]) -> Result<BinaryOp, SamError> {
+  let op = text(&node, source)?.trim();
+
+  return BinaryOp::from_symbol(op).ok_or_else(|| {
+    let err = SamError::parse(
+      codes::UNKNOWN_OPERATOR,
+      format!("Unknown operator {}", op),
+    )
+    .at(node);
+
+    match did_you_mean(op, BinaryOp::ALL.map(BinaryOp::as_str)) {
+      Some(help) => err.with_help(help),
+      None => err,
+    }
+  });
+}
+
+fn string(node: Node, source: &[u8]) -> Result<String, SamError> {
+  expect(&node, "string")?;
+
+  let mut result = String::new();
+  let mut walker = node.walk();
+
+  for child in node.named_children(&mut walker) {
+    match child.kind() {
+      "string_fragment" => result.push_str(text(&child, source)?),
+      "escape_sequence" => {
+        let esc = text(&child, source)?;
+        result.push(Value::decode_escape(esc).map_err(|e| e.at(child))?);
+      }
+      _ => {}
+    }
+  }
+
+  return Ok(result);
+}
+
+fn number(node: Node, source: &[u8]) -> Result<Number, SamError> {
+  let digits = text(&node, source)?;
+  let invalid = || {
+    SamError::parse(codes::INVALID_NUMBER, format!("Invalid number {}", digits))
+      .at(node)
+  };
+
+  if digits.contains('.') {
+    digits
+      .parse()
+      .map(Number::SamFloat)
+      .map_err(|e| invalid().caused_by(e))
+  } else {
+    digits
+      .parse()
+      .map(Number::SamInt)
+      .map_err(|e| invalid().caused_by(e))
+  }
+}
+
+fn expect(node: &Node, kind: &str) -> Result<(), SamError> {
+  if node.kind() != kind {
+    return Err(
+      SamError::parse(
+        codes::MALFORMED_TREE,
+        format!("Expected {}, found {}", kind, node.kind()),
+      )
+      .at(node),
+    );
+  }
+
+  return Ok(());
+}
+
+// required named field of a node, reported against the node if absent
+fn field<'t>(node: &Node<'t>, name: &str) -> Result<Node<'t>, SamError> {
+  node.child_by_field_name(name).ok_or_else(|| {
+    SamError::parse(
+      codes::MALFORMED_TREE,
+      format!("Missing {} in {}", name, node.kind()),
+    )
+    .at(node)
+  })
+}
+
+// required positional child of a node, reported against the node if absent
+fn child<'t>(node: &Node<'t>, index: u32) -> Result<Node<'t>, SamError> {
+  node.child(index).ok_or_else(|| {
+    SamError::parse(codes::MALFORMED_TREE, format!("Malformed {}", node.kind()))
+      .at(node)
+  })
+}
+
+// source text of a node, which must be valid UTF-8
+fn text<'s>(node: &Node, source: &'s [u8]) -> Result<&'s str, SamError> {
+  node.utf8_text(source).map_err(|e| {
+    SamError::parse(codes::MALFORMED_TREE, "Source text is not valid UTF-8")
+      .at(node)
+      .caused_by(e)
+  })
+}
+
+/* =========================
+Visitor
+========================= */
+
+// walks a program, calling back for every node. Each method defaults to
+// visiting the children of its node through the matching `walk_` function,
+// so implementations override what they care about and call `walk_` to
+// keep descending:
+//
+//   struct Calls(usize);
+//
+//   impl Visitor for Calls {
+//     fn visit_expr(&mut self, expr: &Expr) {
+//       if let ExprKind::Call(_) = expr.kind {
+//         self.0 += 1;
+//       }
+//       walk_expr(self, expr);
+//     }
+//   }
+pub trait Visitor {
+  fn visit_program(&mut self, program: &Program) {
+    walk_program(self, program);
+  }
+
+  fn visit_stmt(&mut self, stmt: &Stmt) {
+    walk_stmt(self, stmt);
+  }
+
+  fn visit_block(&mut self, block: &Block) {
+    walk_block(self, block);
+  }
+
+  fn visit_expr(&mut self, expr: &Expr) {
+    walk_expr(self, expr);
+  }
+
+  // a name being declared or assigned, not an identifier expression
+  fn visit_ident(&mut self, _ident: &Ident) {}
+}
+
+pub fn walk_program<V: Visitor + ?Sized>(v: &mut V, program: &Program) {
+  for stmt in &program.statements {
+    v.visit_stmt(stmt);
+  }
+}
+
+pub fn walk_block<V: Visitor + ?Sized>(v: &mut V, block: &Block) {
+  for stmt in &block.statements {
+    v.visit_stmt(stmt);
+  }
+}
+
+pub fn walk_stmt<V: Visitor + ?Sized>(v: &mut V, stmt: &Stmt) {
+  match &stmt.kind {
+    StmtKind::Expression(expr) => v.visit_expr(expr),
+    StmtKind::Declaration { declarators, .. } => {
+      for declarator in declarators {
+        if let Some(value) = &declarator.value {
+          v.visit_expr(value);
+        }
+        v.visit_ident(&declarator.name);
+      }
+    }
+    StmtKind::Assignment { target, value } => {
+      v.visit_expr(value);
+      v.visit_ident(target);
+    }
+    StmtKind::Return(value) => {
+      if let Some(value) = value {
+        v.visit_expr(value);
+      }
+    }
+    StmtKind::Break | StmtKind::Continue | StmtKind::Import(_) => {}
+    StmtKind::Interfaces(interfaces) => {
+      for interface in interfaces {
+        v.visit_ident(&interface.module);
+      }
+    }
+  }
+}
+
+pub fn walk_expr<V: Visitor + ?Sized>(v: &mut V, expr: &Expr) {
+  match &expr.kind {
+    ExprKind::Literal(_) | ExprKind::Identifier(_) => {}
+    ExprKind::Binary { left, right, .. } => {
+      v.visit_expr(left);
+      v.visit_expr(right);
+    }
+    ExprKind::If(branch) => walk_if(v, branch),
+    ExprKind::For {
+      variable,
+      iterable,
+      body,
+    } => {
+      v.visit_expr(iterable);
+      v.visit_ident(variable);
+      v.visit_block(body);
+    }
+    ExprKind::Lambda { params, body } => {
+      for param in params {
+        v.visit_ident(param);
+      }
+      v.visit_block(body);
+    }
+    ExprKind::Call(call) | ExprKind::Spawn(call) => {
+      v.visit_expr(&call.function);
+      for arg in &call.args {
+        v.visit_expr(arg);
+      }
+    }
+    ExprKind::Member { object, .. } => v.visit_expr(object),
+    ExprKind::Array(items) => {
+      for item in items {
+        v.visit_expr(item);
+      }
+    }
+    ExprKind::Index { array, index } => {
+      v.visit_ident(array);
+      v.visit_expr(index);
+    }
+    ExprKind::Await(handle) => v.visit_expr(handle),
+  }
+}
+
+fn walk_if<V: Visitor + ?Sized>(v: &mut V, branch: &If) {
+  v.visit_expr(&branch.condition);
+  v.visit_block(&branch.consequence);
+
+  match &branch.alternative {
+    Some(Else::Block(block)) => v.visit_block(block),
+    Some(Else::If(branch, _)) => walk_if(v, branch),
+    None => {}
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_lower() {
+    let program = parse("let a = 1 + 2.5; a = 'x\\n';").unwrap();
+
+    let StmtKind::Declaration {
+      constant: false,
+      declarators,
+    } = &program.statements[0].kind
+    else {
+      panic!("expected a declaration");
+    };
+    assert_eq!(declarators[0].name.name, "a");
+
+    let Some(Expr {
+      kind: ExprKind::Binary { op, left, right },
+      ..
+    }) = &declarators[0].value
+    else {
+      panic!("expected a binary expression");
+    };
+    assert_eq!(*op, BinaryOp::Add);
+    assert_eq!(
+      left.kind,
+      ExprKind::Literal(Literal::Number(Number::SamInt(1)))
+    );
+    assert_eq!(
+      right.kind,
+      ExprKind::Literal(Literal::Number(Number::SamFloat(2.5)))
+    );
+
+    let StmtKind::Assignment { value, .. } = &program.statements[1].kind else {
+      panic!("expected an assignment");
+    };
+    assert_eq!(value.kind, ExprKind::Literal(Literal::String("x\n".into())));
+  }
+
+  #[test]
+  fn test_import() {
+    let program = parse("import('util/paths'); imports('a');").unwrap();
+
+    let StmtKind::Import(import) = &program.statements[0].kind else {
+      panic!("expected an import");
+    };
+    assert_eq!(import.path, "util/paths");
+    assert!(matches!(
+      program.statements[1].kind,
+      StmtKind::Expression(_)
+    ));
+
+    let e = parse("import('a', 'b');").unwrap_err();
+    assert_eq!(e.code(), Some(codes::ARGUMENT_COUNT));
+    let e = parse("let p = 'a'; import(p);").unwrap_err();
+    assert_eq!(e.code(), Some(codes::ARGUMENT_TYPE));
+  }
+
+  #[test]
+  fn test_visitor() {
+    // every name read, in order
+    struct Names(Vec<Symbol>);
+
+    impl Visitor for Names {
+      fn visit_expr(&mut self, expr: &Expr) {
+        if let ExprKind::Identifier(name) = &expr.kind {
+          self.0.push(*name);
+        }
+        walk_expr(self, expr);
+      }
+    }
+
+    let program = parse(
+      "
+      let f = (x) => { return x + y; };
+      for item in items { if (item > 1) { f(item); } }
+    ",
+    )
+    .unwrap();
+
+    let mut names = Names(Vec::new());
+    names.visit_program(&program);
+
+    assert_eq!(names.0, ["x", "y", "items", "item", "f", "item"]);
+  }
+
+  #[test]
+  fn test_binary_op_names() {
+    for op in BinaryOp::ALL {
+      assert_eq!(BinaryOp::from_symbol(op.as_str()), Some(op));
+    }
+    assert_eq!(BinaryOp::from_symbol("=>"), None);
+  }
+}
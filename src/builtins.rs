@@ -0,0 +1,2809 @@
+#![allow(dead_code)]
+
+use crate::codes;
+use crate::context::Context;
+use crate::csv;
+use crate::digest;
+use crate::encoding;
+use crate::error::{SamError, Span};
+use crate::evaluate::condition;
+use crate::ffi::FFI;
+use crate::http;
+use crate::random::Random;
+use crate::template::Template;
+use crate::time::{DateTime, ISO_8601};
+use crate::value::{Division, Number, Value};
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// functions every script can call without declaring them. Variables and
+// interface entries of the same name hide them.
+pub type Builtin =
+  fn(Vec<Value>, &mut Context, Caller) -> Result<Value, SamError>;
+
+// calls a function value the way the backend running the script does
+pub type Call =
+  fn(Value, Vec<Value>, Span, &mut Context) -> Result<Value, SamError>;
+
+// how a builtin calls the functions it is given, e.g. by `par_map`: with
+// the backend's call, from the builtin's call site
+#[derive(Clone, Copy)]
+pub struct Caller {
+  pub call: Call,
+  pub span: Span,
+}
+
+impl Caller {
+  pub fn call(
+    &self,
+    f: Value,
+    args: Vec<Value>,
+    ctx: &mut Context,
+  ) -> Result<Value, SamError> {
+    return (self.call)(f, args, self.span, ctx);
+  }
+}
+
+const BUILTINS: &[(&str, Builtin)] = &[
+  ("cwd", cwd),
+  ("chdir", chdir),
+  ("print", print),
+  ("println", println),
+  ("input", input),
+  ("format", format),
+  ("abs", abs),
+  ("floor", floor),
+  ("ceil", ceil),
+  ("round", round),
+  ("sqrt", sqrt),
+  ("pow", pow),
+  ("floor_div", floor_div),
+  ("log", log),
+  ("exp", exp),
+  ("sin", sin),
+  ("cos", cos),
+  ("tan", tan),
+  ("min", min),
+  ("max", max),
+  ("len", len),
+  ("upper", upper),
+  ("lower", lower),
+  ("trim", trim),
+  ("split", split),
+  ("join", join),
+  ("replace", replace),
+  ("contains", contains),
+  ("starts_with", starts_with),
+  ("ends_with", ends_with),
+  ("find", find),
+  ("pad", pad),
+  ("push", push),
+  ("pop", pop),
+  ("insert", insert),
+  ("remove", remove),
+  ("sort", sort),
+  ("reverse", reverse),
+  ("slice", slice),
+  ("index_of", index_of),
+  ("concat", concat),
+  ("flatten", flatten),
+  ("keys", keys),
+  ("values", values),
+  ("has", has),
+  ("get", get),
+  ("merge", merge),
+  ("json_parse", json_parse),
+  ("json_stringify", json_stringify),
+  ("csv_parse", csv_parse),
+  ("csv_write", csv_write),
+  ("http_get", http_get),
+  ("http_post", http_post),
+  ("sha256", sha256),
+  ("md5", md5),
+  ("base64_encode", base64_encode),
+  ("base64_decode", base64_decode),
+  ("hex_encode", hex_encode),
+  ("hex_decode", hex_decode),
+  ("url_encode", url_encode),
+  ("url_decode", url_decode),
+  ("map", map),
+  ("filter", filter),
+  ("reduce", reduce),
+  ("each", each),
+  ("lazy", lazy),
+  ("rand", rand),
+  ("rand_int", rand_int),
+  ("shuffle", shuffle),
+  ("seed", seed),
+  ("now", now),
+  ("clock", clock),
+  ("sleep", sleep),
+  ("format_time", format_time),
+  ("par_map", par_map),
+  ("par_filter", par_filter),
+  ("exit", exit),
+  ("assert", assert),
+  ("assert_eq", assert_eq),
+];
+
+// the names of every builtin, e.g. for completion
+pub fn names() -> impl Iterator<Item = &'static str> {
+  return BUILTINS.iter().map(|(name, _)| *name);
+}
+
+pub fn lookup(name: &str) -> Option<Builtin> {
+  return BUILTINS
+    .iter()
+    .find(|(builtin, _)| *builtin == name)
+    .map(|(_, f)| *f);
+}
+
+fn expect_args(
+  name: &str,
+  args: &[Value],
+  count: usize,
+) -> Result<(), SamError> {
+  if args.len() != count {
+    return Err(SamError::runtime(
+      codes::ARGUMENT_COUNT,
+      format!(
+        "`{}` takes {} arguments but {} were given",
+        name,
+        count,
+        args.len()
+      ),
+    ));
+  }
+
+  return Ok(());
+}
+
+// for builtins with optional arguments
+fn expect_args_between(
+  name: &str,
+  args: &[Value],
+  min: usize,
+  max: usize,
+) -> Result<(), SamError> {
+  if args.len() < min || args.len() > max {
+    let counts = match max - min {
+      1 => format!("{} or {}", min, max),
+      _ => format!("{} to {}", min, max),
+    };
+    return Err(SamError::runtime(
+      codes::ARGUMENT_COUNT,
+      format!(
+        "`{}` takes {} arguments but {} were given",
+        name,
+        counts,
+        args.len()
+      ),
+    ));
+  }
+
+  return Ok(());
+}
+
+// argument `position` of `name`, counted from 1, was not what it takes
+fn wrong_type(
+  name: &str,
+  position: usize,
+  expected: &str,
+  found: &Value,
+) -> SamError {
+  return SamError::type_error(
+    codes::ARGUMENT_TYPE,
+    format!(
+      "Argument {} of `{}` must be {}, found {}",
+      position,
+      name,
+      expected,
+      found.type_name()
+    ),
+  );
+}
+
+// the most bytes of a string, or elements of an array, a builtin makes
+// when no limit on the size of values is set, so that a stray width or
+// count fails instead of taking all the memory of the host
+const MAX_SIZE: usize = 1 << 30;
+
+// the size of the string or array `name` is about to make, none if
+// counting it overflowed, checked before it is made as it could be huge
+fn check_size(
+  name: &str,
+  size: Option<usize>,
+  ctx: &Context,
+) -> Result<(), SamError> {
+  let max = size_limit(ctx);
+
+  return match size {
+    Some(size) if size <= max => Ok(()),
+    Some(size) => Err(SamError::runtime(
+      codes::LIMIT_EXCEEDED,
+      format!(
+        "The result of `{}` would be of size {}, past the limit of {}",
+        name, size, max
+      ),
+    )),
+    None => Err(SamError::runtime(
+      codes::LIMIT_EXCEEDED,
+      format!("The result of `{}` would be too large to make", name),
+    )),
+  };
+}
+
+// the largest string or array a builtin may make
+fn size_limit(ctx: &Context) -> usize {
+  let max = ctx.config.limits.value_size.unwrap_or(MAX_SIZE);
+  return max.min(isize::MAX as usize);
+}
+
+// `cwd()`, the working directory of external calls
+fn cwd(
+  args: Vec<Value>,
+  ctx: &mut Context,
+  _: Caller,
+) -> Result<Value, SamError> {
+  expect_args("cwd", &args, 0)?;
+
+  return Ok(Value::from(ctx.config.working_dir().display().to_string()));
+}
+
+// `chdir('build')`, run the following external calls in another directory
+fn chdir(
+  args: Vec<Value>,
+  ctx: &mut Context,
+  _: Caller,
+) -> Result<Value, SamError> {
+  expect_args("chdir", &args, 1)?;
+
+  let Value::SamString(path) = &args[0] else {
+    return Err(wrong_type("chdir", 1, "a string", &args[0]));
+  };
+
+  ctx.config.chdir(path)?;
+
+  return Ok(Value::Undefined);
+}
+
+/* =========================
+Output
+========================= */
+
+// `print('total:', n)`, the arguments written to the script's stdout,
+// separated by spaces: strings as they are, numbers in decimal, and arrays
+// and objects spelled out. Or `print('{:>8} {:.2}', name, pct)`, formatted
+// as by `format` when the first argument has placeholders for the rest.
+fn print(
+  args: Vec<Value>,
+  ctx: &mut Context,
+  _: Caller,
+) -> Result<Value, SamError> {
+  let text = printed("print", &args, ctx)?;
+  ctx.config.output.stdout.write(text.as_bytes());
+
+  return Ok(Value::Undefined);
+}
+
+// `println('done')`, `print` and a newline
+fn println(
+  args: Vec<Value>,
+  ctx: &mut Context,
+  _: Caller,
+) -> Result<Value, SamError> {
+  let text = printed("println", &args, ctx)?;
+  ctx.config.output.stdout.write_line(&text);
+
+  return Ok(Value::Undefined);
+}
+
+// `format('{:<10}{:>6.1}%', name, share)`, the string with its placeholders
+// replaced by the other arguments, see template.rs
+fn format(
+  args: Vec<Value>,
+  ctx: &mut Context,
+  _: Caller,
+) -> Result<Value, SamError> {
+  if args.is_empty() {
+    return Err(SamError::runtime(
+      codes::ARGUMENT_COUNT,
+      "`format` takes a format string and its values, but none were given",
+    ));
+  }
+  let template = Template::parse(string("format", &args, 0)?)?;
+  let text = template.render("format", &args[1..], size_limit(ctx))?;
+  check_size("format", Some(text.len()), ctx)?;
+
+  return Ok(Value::from(text));
+}
+
+// what `print` writes: a string that is not a valid format string, or has
+// no placeholders, is printed as it is
+fn printed(
+  name: &str,
+  args: &[Value],
+  ctx: &Context,
+) -> Result<String, SamError> {
+  let template = match args {
+    [Value::SamString(first), _, ..] => Template::parse(first).ok(),
+    _ => None,
+  };
+
+  let text = match template.filter(|template| template.has_fields()) {
+    Some(template) => template.render(name, &args[1..], size_limit(ctx))?,
+    None => joined(args),
+  };
+  check_size(name, Some(text.len()), ctx)?;
+
+  return Ok(text);
+}
+
+// `input('name? ')`, a line of the script's stdin without its line ending,
+// after writing the prompt if given; undefined at the end of the input
+fn input(
+  args: Vec<Value>,
+  ctx: &mut Context,
+  _: Caller,
+) -> Result<Value, SamError> {
+  expect_args_between("input", &args, 0, 1)?;
+
+  if let Some(prompt) = args.first() {
+    ctx
+      .config
+      .output
+      .stdout
+      .write(prompt.to_string().as_bytes());
+  }
+
+  return match ctx.config.output.stdin.read_line() {
+    Ok(Some(line)) => Ok(Value::from(line)),
+    Ok(None) => Ok(Value::Undefined),
+    Err(e) => Err(SamError::runtime(
+      codes::INPUT_FAILED,
+      format!("Could not read the input: {}", e),
+    )),
+  };
+}
+
+fn joined(args: &[Value]) -> String {
+  return args
+    .iter()
+    .map(|arg| arg.to_string())
+    .collect::<Vec<_>>()
+    .join(" ");
+}
+
+/* =========================
+Math
+========================= */
+
+// Integers stay integers where the result is one: `abs`, `floor`, `ceil`,
+// `round`, `pow` of a whole power, `min`, and `max`. Results too big for an
+// integer are floats instead. The rest are always floats.
+
+fn number(name: &str, args: &[Value], i: usize) -> Result<Number, SamError> {
+  return match &args[i] {
+    Value::SamNumber(n) => Ok(*n),
+    other => Err(wrong_type(name, i + 1, "a number", other)),
+  };
+}
+
+fn integer(name: &str, args: &[Value], i: usize) -> Result<i64, SamError> {
+  return match &args[i] {
+    Value::SamNumber(Number::SamInt(n)) => Ok(*n),
+    other => Err(wrong_type(name, i + 1, "an integer", other)),
+  };
+}
+
+// the builtin `name` of one number
+fn unary(
+  name: &str,
+  args: &[Value],
+  f: impl Fn(Number) -> Number,
+) -> Result<Value, SamError> {
+  expect_args(name, args, 1)?;
+
+  return Ok(Value::SamNumber(f(number(name, args, 0)?)));
+}
+
+// a float rounded by `f` as an integer, if it fits one
+fn to_integer(x: f64, f: fn(f64) -> f64) -> Number {
+  let rounded = f(x);
+  // i64::MAX is not a float, the next power of two, 2^63, is
+  if rounded.is_finite()
+    && rounded >= i64::MIN as f64
+    && rounded < 9223372036854775808.0
+  {
+    return Number::SamInt(rounded as i64);
+  }
+  return Number::SamFloat(rounded);
+}
+
+// the builtin `name` rounding to an integer with `f`
+fn rounding(
+  name: &str,
+  args: &[Value],
+  f: fn(f64) -> f64,
+) -> Result<Value, SamError> {
+  return unary(name, args, |n| match n {
+    Number::SamInt(i) => Number::SamInt(i),
+    Number::SamFloat(x) => to_integer(x, f),
+  });
+}
+
+// the builtin `name` of a float
+fn float(
+  name: &str,
+  args: &[Value],
+  f: fn(f64) -> f64,
+) -> Result<Value, SamError> {
+  return unary(name, args, |n| Number::SamFloat(f(n.as_f64())));
+}
+
+// `abs(-3)`, 3
+fn abs(
+  args: Vec<Value>,
+  _: &mut Context,
+  _: Caller,
+) -> Result<Value, SamError> {
+  return unary("abs", &args, |n| match n {
+    Number::SamInt(i) => i
+      .checked_abs()
+      .map_or(Number::SamFloat((i as f64).abs()), Number::SamInt),
+    Number::SamFloat(x) => Number::SamFloat(x.abs()),
+  });
+}
+
+// `floor(2.7)`, 2
+fn floor(
+  args: Vec<Value>,
+  _: &mut Context,
+  _: Caller,
+) -> Result<Value, SamError> {
+  return rounding("floor", &args, f64::floor);
+}
+
+// `ceil(2.1)`, 3
+fn ceil(
+  args: Vec<Value>,
+  _: &mut Context,
+  _: Caller,
+) -> Result<Value, SamError> {
+  return rounding("ceil", &args, f64::ceil);
+}
+
+// `round(2.5)`, 3: halves round away from zero
+fn round(
+  args: Vec<Value>,
+  _: &mut Context,
+  _: Caller,
+) -> Result<Value, SamError> {
+  return rounding("round", &args, f64::round);
+}
+
+// `sqrt(2)`, NaN for negative numbers
+fn sqrt(
+  args: Vec<Value>,
+  _: &mut Context,
+  _: Caller,
+) -> Result<Value, SamError> {
+  return float("sqrt", &args, f64::sqrt);
+}
+
+// `pow(2, 10)`, 1024
+fn pow(
+  args: Vec<Value>,
+  _: &mut Context,
+  _: Caller,
+) -> Result<Value, SamError> {
+  expect_args("pow", &args, 2)?;
+  let (base, exponent) = (number("pow", &args, 0)?, number("pow", &args, 1)?);
+
+  let whole = match (base, exponent) {
+    (Number::SamInt(b), Number::SamInt(e)) => u32::try_from(e)
+      .ok()
+      .and_then(|e| b.checked_pow(e))
+      .map(Number::SamInt),
+    _ => None,
+  };
+
+  return Ok(Value::SamNumber(whole.unwrap_or_else(|| {
+    Number::SamFloat(base.as_f64().powf(exponent.as_f64()))
+  })));
+}
+
+// `floor_div(-7, 2)`, -4: the quotient rounded down, with a zero divisor
+// handled by the division policy
+fn floor_div(
+  args: Vec<Value>,
+  ctx: &mut Context,
+  _: Caller,
+) -> Result<Value, SamError> {
+  expect_args("floor_div", &args, 2)?;
+  let (a, b) = (
+    number("floor_div", &args, 0)?,
+    number("floor_div", &args, 1)?,
+  );
+
+  return Value::SamNumber(a).divide(
+    Division::Floor,
+    Value::SamNumber(b),
+    ctx.config.division_by_zero,
+  );
+}
+
+// `log(x)`, the natural logarithm, or `log(x, 10)` of another base
+fn log(
+  args: Vec<Value>,
+  _: &mut Context,
+  _: Caller,
+) -> Result<Value, SamError> {
+  expect_args_between("log", &args, 1, 2)?;
+
+  if args.len() == 2 {
+    let (x, base) = (number("log", &args, 0)?, number("log", &args, 1)?);
+    return Ok(Value::from(x.as_f64().log(base.as_f64())));
+  }
+
+  return float("log", &args, f64::ln);
+}
+
+// `exp(1)`, e
+fn exp(
+  args: Vec<Value>,
+  _: &mut Context,
+  _: Caller,
+) -> Result<Value, SamError> {
+  return float("exp", &args, f64::exp);
+}
+
+// `sin(x)`, of an angle in radians, as are `cos` and `tan`
+fn sin(
+  args: Vec<Value>,
+  _: &mut Context,
+  _: Caller,
+) -> Result<Value, SamError> {
+  return float("sin", &args, f64::sin);
+}
+
+fn cos(
+  args: Vec<Value>,
+  _: &mut Context,
+  _: Caller,
+) -> Result<Value, SamError> {
+  return float("cos", &args, f64::cos);
+}
+
+fn tan(
+  args: Vec<Value>,
+  _: &mut Context,
+  _: Caller,
+) -> Result<Value, SamError> {
+  return float("tan", &args, f64::tan);
+}
+
+// `min(3, 1, 2)` or `min([3, 1, 2])`, 1
+fn min(
+  args: Vec<Value>,
+  _: &mut Context,
+  _: Caller,
+) -> Result<Value, SamError> {
+  return extreme("min", &args, |a, b| b < a);
+}
+
+// `max(3, 1, 2)` or `max([3, 1, 2])`, 3
+fn max(
+  args: Vec<Value>,
+  _: &mut Context,
+  _: Caller,
+) -> Result<Value, SamError> {
+  return extreme("max", &args, |a, b| b > a);
+}
+
+// the first number `better` prefers over every other, of the arguments or
+// of the one array given
+fn extreme(
+  name: &str,
+  args: &[Value],
+  better: fn(Number, Number) -> bool,
+) -> Result<Value, SamError> {
+  let numbers = match args {
+    [Value::SamArray(items)] => {
+      let numbers = items.iter().map(|item| match item {
+        Value::SamNumber(n) => Ok(*n),
+        _ => Err(wrong_type(name, 1, "an array of numbers", item)),
+      });
+      numbers.collect::<Result<Vec<_>, _>>()?
+    }
+    _ => (0..args.len())
+      .map(|i| number(name, args, i))
+      .collect::<Result<Vec<_>, _>>()?,
+  };
+
+  let mut best: Option<Number> = None;
+  for n in numbers {
+    if best.is_none_or(|best| better(best, n)) {
+      best = Some(n);
+    }
+  }
+
+  return match best {
+    Some(best) => Ok(Value::SamNumber(best)),
+    None => Err(SamError::runtime(
+      codes::ARGUMENT_COUNT,
+      format!("`{}` takes at least 1 number but none were given", name),
+    )),
+  };
+}
+
+/* =========================
+Strings
+========================= */
+
+// Lengths and positions count characters, not bytes, so that `pad` lines
+// up text that is not ASCII.
+
+fn string<'a>(
+  name: &str,
+  args: &'a [Value],
+  i: usize,
+) -> Result<&'a str, SamError> {
+  return match &args[i] {
+    Value::SamString(s) => Ok(s),
+    other => Err(wrong_type(name, i + 1, "a string", other)),
+  };
+}
+
+// `len('héllo')`, 5: the characters of a string, the elements of an array
+// or object, or the bytes of bytes
+fn len(
+  args: Vec<Value>,
+  _: &mut Context,
+  _: Caller,
+) -> Result<Value, SamError> {
+  expect_args("len", &args, 1)?;
+
+  let len = match &args[0] {
+    Value::SamString(s) => s.chars().count(),
+    Value::SamArray(items) => items.len(),
+    Value::SamObject(fields) => fields.len(),
+    Value::SamBytes(bytes) => bytes.len(),
+    other => {
+      return Err(wrong_type("len", 1, "a string or collection", other));
+    }
+  };
+
+  return Ok(Value::from(len as i64));
+}
+
+// `upper('abc')`, 'ABC'
+fn upper(
+  args: Vec<Value>,
+  _: &mut Context,
+  _: Caller,
+) -> Result<Value, SamError> {
+  expect_args("upper", &args, 1)?;
+
+  return Ok(Value::from(string("upper", &args, 0)?.to_uppercase()));
+}
+
+// `lower('ABC')`, 'abc'
+fn lower(
+  args: Vec<Value>,
+  _: &mut Context,
+  _: Caller,
+) -> Result<Value, SamError> {
+  expect_args("lower", &args, 1)?;
+
+  return Ok(Value::from(string("lower", &args, 0)?.to_lowercase()));
+}
+
+// `trim(out.stdout)`, without the whitespace at either end
+fn trim(
+  args: Vec<Value>,
+  _: &mut Context,
+  _: Caller,
+) -> Result<Value, SamError> {
+  expect_args("trim", &args, 1)?;
+
+  return Ok(Value::from(string("trim", &args, 0)?.trim()));
+}
+
+// `split('a,b,,c', ',')`, ['a', 'b', '', 'c']; without a separator, the
+// words between whitespace, and with an empty one, the characters
+fn split(
+  args: Vec<Value>,
+  _: &mut Context,
+  _: Caller,
+) -> Result<Value, SamError> {
+  expect_args_between("split", &args, 1, 2)?;
+  let s = string("split", &args, 0)?;
+
+  let parts: Vec<Value> = match args.len() {
+    1 => s.split_whitespace().map(Value::from).collect(),
+    _ => match string("split", &args, 1)? {
+      "" => s.chars().map(|c| Value::from(c.to_string())).collect(),
+      separator => s.split(separator).map(Value::from).collect(),
+    },
+  };
+
+  return Ok(Value::from(parts));
+}
+
+// `join(names, ', ')`, the elements written as `print` writes them, with
+// the separator, if any, between them
+fn join(
+  args: Vec<Value>,
+  ctx: &mut Context,
+  _: Caller,
+) -> Result<Value, SamError> {
+  expect_args_between("join", &args, 1, 2)?;
+
+  let Value::SamArray(items) = &args[0] else {
+    return Err(wrong_type("join", 1, "an array", &args[0]));
+  };
+  let separator = match args.len() {
+    1 => "",
+    _ => string("join", &args, 1)?,
+  };
+
+  let texts: Vec<String> = items.iter().map(|item| item.to_string()).collect();
+
+  let separators = separator.len().checked_mul(texts.len().saturating_sub(1));
+  let size = separators.and_then(|n| {
+    return texts
+      .iter()
+      .try_fold(n, |size, t| size.checked_add(t.len()));
+  });
+  check_size("join", size, ctx)?;
+
+  return Ok(Value::from(texts.join(separator)));
+}
+
+// `replace(path, '/', '\\')`, every occurrence replaced; an empty string
+// is not found anywhere
+fn replace(
+  args: Vec<Value>,
+  ctx: &mut Context,
+  _: Caller,
+) -> Result<Value, SamError> {
+  expect_args("replace", &args, 3)?;
+  let s = string("replace", &args, 0)?;
+  let from = string("replace", &args, 1)?;
+  let to = string("replace", &args, 2)?;
+
+  if from.is_empty() {
+    return Ok(Value::from(s));
+  }
+
+  let count = s.matches(from).count();
+  let size = count
+    .checked_mul(to.len())
+    .and_then(|added| (s.len() - count * from.len()).checked_add(added));
+  check_size("replace", size, ctx)?;
+
+  return Ok(Value::from(s.replace(from, to)));
+}
+
+// `contains(out, 'error')`, whether the string has the other in it, or
+// `contains(names, 'sam')`, whether the array has an element equal to it
+fn contains(
+  args: Vec<Value>,
+  _: &mut Context,
+  _: Caller,
+) -> Result<Value, SamError> {
+  expect_args("contains", &args, 2)?;
+
+  let found = match &args[0] {
+    Value::SamArray(items) => items.contains(&args[1]),
+    Value::SamString(s) => s.contains(string("contains", &args, 1)?),
+    other => return Err(wrong_type("contains", 1, "a string or array", other)),
+  };
+
+  return Ok(Value::from(found));
+}
+
+// `starts_with(line, '#')`
+fn starts_with(
+  args: Vec<Value>,
+  _: &mut Context,
+  _: Caller,
+) -> Result<Value, SamError> {
+  expect_args("starts_with", &args, 2)?;
+  let s = string("starts_with", &args, 0)?;
+
+  return Ok(Value::from(s.starts_with(string("starts_with", &args, 1)?)));
+}
+
+// `ends_with(file, '.sam')`
+fn ends_with(
+  args: Vec<Value>,
+  _: &mut Context,
+  _: Caller,
+) -> Result<Value, SamError> {
+  expect_args("ends_with", &args, 2)?;
+  let s = string("ends_with", &args, 0)?;
+
+  return Ok(Value::from(s.ends_with(string("ends_with", &args, 1)?)));
+}
+
+// `find('key=value', '=')`, 3: the position of the first occurrence, -1 if
+// there is none
+fn find(
+  args: Vec<Value>,
+  _: &mut Context,
+  _: Caller,
+) -> Result<Value, SamError> {
+  expect_args("find", &args, 2)?;
+  let s = string("find", &args, 0)?;
+  let needle = string("find", &args, 1)?;
+
+  let position = match s.find(needle) {
+    Some(byte) => s[..byte].chars().count() as i64,
+    None => -1,
+  };
+
+  return Ok(Value::from(position));
+}
+
+// `pad(name, 10)`, the string made at least 10 characters long with spaces
+// before it, right-aligning it as printf's `%10s` does; a negative width
+// pads after it instead, and a third argument pads with another character,
+// e.g. `pad(n, 4, '0')`
+fn pad(
+  args: Vec<Value>,
+  ctx: &mut Context,
+  _: Caller,
+) -> Result<Value, SamError> {
+  expect_args_between("pad", &args, 2, 3)?;
+  let s = match &args[0] {
+    Value::SamString(s) => s.to_string(),
+    // numbers too, as they are often what is lined up
+    Value::SamNumber(n) => n.to_string(),
+    other => return Err(wrong_type("pad", 1, "a string or number", other)),
+  };
+  let width = integer("pad", &args, 1)?;
+
+  let fill = match args.len() {
+    2 => ' ',
+    _ => {
+      let fill = string("pad", &args, 2)?;
+      let mut chars = fill.chars();
+      match (chars.next(), chars.next()) {
+        (Some(c), None) => c,
+        _ => {
+          return Err(SamError::type_error(
+            codes::ARGUMENT_TYPE,
+            format!(
+              "Argument 3 of `pad` must be a single character, found '{}'",
+              fill
+            ),
+          ));
+        }
+      }
+    }
+  };
+
+  let width_chars = usize::try_from(width.unsigned_abs()).unwrap_or(usize::MAX);
+  let missing = width_chars.saturating_sub(s.chars().count());
+  let size = missing
+    .checked_mul(fill.len_utf8())
+    .and_then(|padding| padding.checked_add(s.len()));
+  check_size("pad", size, ctx)?;
+  let padding: String = std::iter::repeat_n(fill, missing).collect();
+
+  return Ok(Value::from(match width < 0 {
+    true => s + &padding,
+    false => padding + &s,
+  }));
+}
+
+/* =========================
+Arrays
+========================= */
+
+// Arrays are values, as everywhere in sam: these return a new array and
+// leave the one given as it was, so a variable is changed by assigning the
+// result to it, e.g. `items = push(items, 4);`. The elements are only
+// copied when the array is shared, e.g. with that variable.
+
+// argument `i` of `name` as an array to change, taken out of `args`
+fn take_array(
+  name: &str,
+  args: &mut [Value],
+  i: usize,
+) -> Result<Arc<Vec<Value>>, SamError> {
+  return match std::mem::replace(&mut args[i], Value::Undefined) {
+    Value::SamArray(items) => Ok(items),
+    other => Err(wrong_type(name, i + 1, "an array", &other)),
+  };
+}
+
+// `index` of `name` as a position in `0..=len`, where an element can be
+// inserted, or in `0..len` unless `past_end`
+fn position(
+  name: &str,
+  index: i64,
+  len: usize,
+  past_end: bool,
+) -> Result<usize, SamError> {
+  let end = if past_end { len + 1 } else { len };
+  if index < 0 || index as usize >= end {
+    return Err(SamError::value(
+      codes::INDEX_OUT_OF_BOUNDS,
+      format!(
+        "`{}` has no position {} in an array of {}",
+        name, index, len
+      ),
+    ));
+  }
+
+  return Ok(index as usize);
+}
+
+// `push(items, 4, 5)`, the array with the values after its elements
+fn push(
+  mut args: Vec<Value>,
+  _: &mut Context,
+  _: Caller,
+) -> Result<Value, SamError> {
+  if args.is_empty() {
+    return Err(SamError::runtime(
+      codes::ARGUMENT_COUNT,
+      "`push` takes an array and the values to add, but none were given",
+    ));
+  }
+
+  let mut items = take_array("push", &mut args, 0)?;
+  Arc::make_mut(&mut items).extend(args.drain(1..));
+
+  return Ok(Value::SamArray(items));
+}
+
+// `pop(items)`, `[rest, last]`: the array without its last element, and
+// that element, e.g. `let popped = pop(items); items = popped[0];` with
+// the element in `popped[1]`
+fn pop(
+  mut args: Vec<Value>,
+  _: &mut Context,
+  _: Caller,
+) -> Result<Value, SamError> {
+  expect_args("pop", &args, 1)?;
+  let mut items = take_array("pop", &mut args, 0)?;
+
+  let Some(last) = Arc::make_mut(&mut items).pop() else {
+    return Err(SamError::value(
+      codes::INDEX_OUT_OF_BOUNDS,
+      "`pop` of an empty array",
+    ));
+  };
+
+  return Ok(Value::from(vec![Value::SamArray(items), last]));
+}
+
+// `insert(items, 0, 'first')`, the array with the value at the position,
+// the elements from there on one further
+fn insert(
+  mut args: Vec<Value>,
+  _: &mut Context,
+  _: Caller,
+) -> Result<Value, SamError> {
+  expect_args("insert", &args, 3)?;
+  let index = integer("insert", &args, 1)?;
+  let value = args.pop().unwrap();
+  let mut items = take_array("insert", &mut args, 0)?;
+
+  let at = position("insert", index, items.len(), true)?;
+  Arc::make_mut(&mut items).insert(at, value);
+
+  return Ok(Value::SamArray(items));
+}
+
+// `remove(items, 0)`, the array without the element at the position, or
+// `remove(config, 'debug')`, the object without the field
+fn remove(
+  mut args: Vec<Value>,
+  _: &mut Context,
+  _: Caller,
+) -> Result<Value, SamError> {
+  expect_args("remove", &args, 2)?;
+
+  if let Value::SamObject(fields) = &args[0] {
+    let key = string("remove", &args, 1)?;
+    // the copy of a shared object only if it has the field
+    let mut fields = fields.clone();
+    if fields.contains_key(key) {
+      Arc::make_mut(&mut fields).remove(key);
+    }
+    return Ok(Value::SamObject(fields));
+  }
+
+  let index = integer("remove", &args, 1)?;
+  let mut items = take_array("remove", &mut args, 0)?;
+
+  let at = position("remove", index, items.len(), false)?;
+  Arc::make_mut(&mut items).remove(at);
+
+  return Ok(Value::SamArray(items));
+}
+
+// `sort(sizes)`, the array in ascending order, of numbers or of strings
+fn sort(
+  mut args: Vec<Value>,
+  _: &mut Context,
+  _: Caller,
+) -> Result<Value, SamError> {
+  expect_args("sort", &args, 1)?;
+  let mut items = take_array("sort", &mut args, 0)?;
+
+  // every pair compares if all are numbers or all are strings
+  let comparable = items.iter().all(|v| matches!(v, Value::SamNumber(_)))
+    || items.iter().all(|v| matches!(v, Value::SamString(_)));
+  if !comparable {
+    return Err(SamError::type_error(
+      codes::ARGUMENT_TYPE,
+      "Argument 1 of `sort` must be an array of numbers or of strings",
+    ));
+  }
+
+  // NaN, which compares to nothing, goes last
+  Arc::make_mut(&mut items).sort_by(|a, b| {
+    a.partial_cmp(b).unwrap_or_else(|| {
+      let nan = |v: &Value| v.partial_cmp(v).is_none();
+      nan(a).cmp(&nan(b))
+    })
+  });
+
+  return Ok(Value::SamArray(items));
+}
+
+// `reverse(items)`, the elements from last to first, or the characters of
+// a string
+fn reverse(
+  mut args: Vec<Value>,
+  _: &mut Context,
+  _: Caller,
+) -> Result<Value, SamError> {
+  expect_args("reverse", &args, 1)?;
+
+  if let Value::SamString(s) = &args[0] {
+    return Ok(Value::from(s.chars().rev().collect::<String>()));
+  }
+
+  let mut items = take_array("reverse", &mut args, 0)?;
+  Arc::make_mut(&mut items).reverse();
+
+  return Ok(Value::SamArray(items));
+}
+
+// `slice(items, 1, 3)`, the elements from the first position up to the
+// second, or to the end if there is none; negative positions count from the
+// end, e.g. `slice(items, -2)` for the last two. Strings are sliced by
+// character.
+fn slice(
+  args: Vec<Value>,
+  _: &mut Context,
+  _: Caller,
+) -> Result<Value, SamError> {
+  expect_args_between("slice", &args, 2, 3)?;
+
+  let len = match &args[0] {
+    Value::SamArray(items) => items.len(),
+    Value::SamString(s) => s.chars().count(),
+    other => return Err(wrong_type("slice", 1, "an array or string", other)),
+  };
+
+  // positions out of range are clamped, as an empty slice is still a slice
+  let bound = |i: usize| -> Result<usize, SamError> {
+    let n = integer("slice", &args, i)?;
+    return Ok(match n < 0 {
+      true => len.saturating_sub(n.unsigned_abs() as usize),
+      false => (n as usize).min(len),
+    });
+  };
+  let start = bound(1)?;
+  let end = match args.len() {
+    3 => bound(2)?.max(start),
+    _ => len,
+  };
+
+  return Ok(match &args[0] {
+    Value::SamString(s) => {
+      Value::from(s.chars().skip(start).take(end - start).collect::<String>())
+    }
+    Value::SamArray(items) => Value::from(items[start..end].to_vec()),
+    _ => unreachable!(),
+  });
+}
+
+// `index_of(names, 'sam')`, the position of the first element equal to the
+// value, -1 if there is none
+fn index_of(
+  args: Vec<Value>,
+  _: &mut Context,
+  _: Caller,
+) -> Result<Value, SamError> {
+  expect_args("index_of", &args, 2)?;
+  let Value::SamArray(items) = &args[0] else {
+    return Err(wrong_type("index_of", 1, "an array", &args[0]));
+  };
+
+  let position = items.iter().position(|item| *item == args[1]);
+
+  return Ok(Value::from(position.map_or(-1, |i| i as i64)));
+}
+
+// `concat(a, b, c)`, the elements of every array, in order
+fn concat(
+  args: Vec<Value>,
+  ctx: &mut Context,
+  _: Caller,
+) -> Result<Value, SamError> {
+  let mut arrays = Vec::with_capacity(args.len());
+  for (i, arg) in args.iter().enumerate() {
+    let Value::SamArray(items) = arg else {
+      return Err(wrong_type("concat", i + 1, "an array", arg));
+    };
+    arrays.push(items);
+  }
+
+  let size = arrays
+    .iter()
+    .try_fold(0usize, |size, items| size.checked_add(items.len()));
+  check_size("concat", size, ctx)?;
+
+  let mut all = Vec::with_capacity(size.unwrap_or_default());
+  for items in arrays {
+    all.extend(items.iter().cloned());
+  }
+
+  return Ok(Value::from(all));
+}
+
+// `flatten([[1, 2], 3, [4]])`, [1, 2, 3, 4]: the elements of the arrays
+// in the array in their place, one level deep
+fn flatten(
+  args: Vec<Value>,
+  _: &mut Context,
+  _: Caller,
+) -> Result<Value, SamError> {
+  expect_args("flatten", &args, 1)?;
+  let Value::SamArray(items) = &args[0] else {
+    return Err(wrong_type("flatten", 1, "an array", &args[0]));
+  };
+
+  let mut flat = Vec::new();
+  for item in items.iter() {
+    match item {
+      Value::SamArray(inner) => flat.extend(inner.iter().cloned()),
+      other => flat.push(other.clone()),
+    }
+  }
+
+  return Ok(Value::from(flat));
+}
+
+/* =========================
+Random
+========================= */
+
+// `rand()`, a float in [0, 1)
+fn rand(
+  args: Vec<Value>,
+  ctx: &mut Context,
+  _: Caller,
+) -> Result<Value, SamError> {
+  expect_args("rand", &args, 0)?;
+
+  return Ok(Value::from(ctx.random.next_f64()));
+}
+
+// `rand_int(1, 6)`, an integer from the first to the second, both included
+fn rand_int(
+  args: Vec<Value>,
+  ctx: &mut Context,
+  _: Caller,
+) -> Result<Value, SamError> {
+  expect_args("rand_int", &args, 2)?;
+  let low = integer("rand_int", &args, 0)?;
+  let high = integer("rand_int", &args, 1)?;
+
+  if low > high {
+    return Err(SamError::value(
+      codes::EMPTY_RANGE,
+      format!("`rand_int` has no integer from {} to {}", low, high),
+    ));
+  }
+
+  return Ok(Value::from(ctx.random.range(low, high)));
+}
+
+// `shuffle(cards)`, a copy of the array in a random order
+fn shuffle(
+  args: Vec<Value>,
+  ctx: &mut Context,
+  _: Caller,
+) -> Result<Value, SamError> {
+  expect_args("shuffle", &args, 1)?;
+  let Value::SamArray(items) = &args[0] else {
+    return Err(wrong_type("shuffle", 1, "an array", &args[0]));
+  };
+
+  let mut items = items.to_vec();
+  ctx.random.shuffle(&mut items);
+
+  return Ok(Value::from(items));
+}
+
+// `seed(42)`, draw the same numbers every run from here on
+fn seed(
+  args: Vec<Value>,
+  ctx: &mut Context,
+  _: Caller,
+) -> Result<Value, SamError> {
+  expect_args("seed", &args, 1)?;
+  let seed = integer("seed", &args, 0)?;
+
+  ctx.random = Random::new(seed as u64);
+
+  return Ok(Value::Undefined);
+}
+
+/* =========================
+Time
+========================= */
+
+// `now()`, the seconds since the Unix epoch, with microseconds
+fn now(
+  args: Vec<Value>,
+  _: &mut Context,
+  _: Caller,
+) -> Result<Value, SamError> {
+  expect_args("now", &args, 0)?;
+  let since = SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .unwrap_or_default();
+
+  return Ok(Value::from(since.as_secs_f64()));
+}
+
+// `clock()`, seconds from a fixed point, for measuring how long something
+// took: unlike `now`, it never goes back when the system time is set
+fn clock(
+  args: Vec<Value>,
+  _: &mut Context,
+  _: Caller,
+) -> Result<Value, SamError> {
+  expect_args("clock", &args, 0)?;
+  // the first call of the process
+  static START: OnceLock<Instant> = OnceLock::new();
+  let start = START.get_or_init(Instant::now);
+
+  return Ok(Value::from(start.elapsed().as_secs_f64()));
+}
+
+// `sleep(0.5)`, waits for that many seconds, or until the script is
+// interrupted
+fn sleep(
+  args: Vec<Value>,
+  ctx: &mut Context,
+  _: Caller,
+) -> Result<Value, SamError> {
+  expect_args("sleep", &args, 1)?;
+  let seconds = number("sleep", &args, 0)?.as_f64();
+  // negative durations are none, infinite ones last until interrupted
+  let duration =
+    Duration::try_from_secs_f64(seconds.max(0.0)).unwrap_or(Duration::MAX);
+
+  if !ctx.config.interrupt.sleep(duration) {
+    return Err(SamError::runtime(
+      codes::CANCELLED,
+      "`sleep` was interrupted",
+    ));
+  }
+
+  return Ok(Value::Undefined);
+}
+
+// `format_time(now(), '%H:%M')`, the time given in seconds since the Unix
+// epoch, in UTC, as ISO 8601 unless a format is given
+fn format_time(
+  args: Vec<Value>,
+  _: &mut Context,
+  _: Caller,
+) -> Result<Value, SamError> {
+  expect_args_between("format_time", &args, 1, 2)?;
+  let seconds = number("format_time", &args, 0)?.as_f64();
+  let pattern = match args.len() {
+    2 => string("format_time", &args, 1)?,
+    _ => ISO_8601,
+  };
+
+  let whole = seconds.floor();
+  let micros = ((seconds - whole) * 1e6) as u32;
+  let date = DateTime::from_unix(whole as i64, micros.min(999_999));
+
+  let formatted = date.format(pattern).map_err(|directive| {
+    SamError::value(
+      codes::INVALID_FORMAT,
+      format!("`format_time` has no directive `{}`", directive),
+    )
+    .with_help("use %Y, %m, %d, %H, %M, %S, %f, or %%")
+  })?;
+
+  return Ok(Value::from(formatted));
+}
+
+/* =========================
+Exiting
+========================= */
+
+// `exit(1)`, ends the script with that status, 0 if none is given. It
+// fails with an error every caller passes on, so spawned calls and
+// imports end the script too, and the reports of `--profile` and
+// `--coverage` are still written.
+fn exit(
+  args: Vec<Value>,
+  _: &mut Context,
+  _: Caller,
+) -> Result<Value, SamError> {
+  expect_args_between("exit", &args, 0, 1)?;
+  let status = match args.len() {
+    1 => integer("exit", &args, 0)?,
+    _ => 0,
+  };
+
+  let Ok(status) = u8::try_from(status) else {
+    return Err(
+      SamError::value(
+        codes::EXIT_STATUS,
+        format!("`exit` has no status {}", status),
+      )
+      .with_help("exit with a status from 0 to 255"),
+    );
+  };
+
+  return Err(
+    SamError::runtime(
+      codes::SCRIPT_EXIT,
+      format!("Script exited with status {}", status),
+    )
+    .with_exit_status(status),
+  );
+}
+
+/* =========================
+Assertions
+========================= */
+
+// `assert(found, 'no match')`, fail unless the condition holds
+fn assert(
+  args: Vec<Value>,
+  _: &mut Context,
+  caller: Caller,
+) -> Result<Value, SamError> {
+  let (cond, message) = match args.as_slice() {
+    [cond] => (cond, None),
+    [cond, message] => (cond, Some(message)),
+    _ => {
+      return Err(SamError::runtime(
+        codes::ARGUMENT_COUNT,
+        format!(
+          "`assert` takes 1 or 2 arguments but {} were given",
+          args.len()
+        ),
+      ));
+    }
+  };
+
+  if condition(cond.clone(), caller.span)? {
+    return Ok(Value::Undefined);
+  }
+
+  return Err(SamError::runtime(
+    codes::ASSERTION_FAILED,
+    match message {
+      Some(message) => format!("Assertion failed: {}", message),
+      None => "Assertion failed".to_owned(),
+    },
+  ));
+}
+
+// `assert_eq(total, 3)`, fail unless both are equal
+fn assert_eq(
+  args: Vec<Value>,
+  _: &mut Context,
+  _: Caller,
+) -> Result<Value, SamError> {
+  expect_args("assert_eq", &args, 2)?;
+
+  if args[0] == args[1] {
+    return Ok(Value::Undefined);
+  }
+
+  return Err(SamError::runtime(
+    codes::ASSERTION_FAILED,
+    format!("Assertion failed: {} != {}", args[0], args[1]),
+  ));
+}
+
+/* =========================
+Objects
+========================= */
+
+// Objects are values like arrays, see above. Their fields have no order,
+// so `keys` and `values` list them by key to give the same result every
+// run.
+
+fn object<'a>(
+  name: &str,
+  args: &'a [Value],
+  i: usize,
+) -> Result<&'a HashMap<String, Value>, SamError> {
+  return match &args[i] {
+    Value::SamObject(fields) => Ok(fields),
+    other => Err(wrong_type(name, i + 1, "an object", other)),
+  };
+}
+
+fn sorted_fields(fields: &HashMap<String, Value>) -> Vec<(&String, &Value)> {
+  let mut sorted: Vec<_> = fields.iter().collect();
+  sorted.sort_by_key(|(key, _)| *key);
+  return sorted;
+}
+
+// `keys(config)`, the names of the fields, in order
+fn keys(
+  args: Vec<Value>,
+  _: &mut Context,
+  _: Caller,
+) -> Result<Value, SamError> {
+  expect_args("keys", &args, 1)?;
+  let fields = object("keys", &args, 0)?;
+
+  let keys: Vec<Value> = sorted_fields(fields)
+    .into_iter()
+    .map(|(key, _)| Value::from(key.as_str()))
+    .collect();
+
+  return Ok(Value::from(keys));
+}
+
+// `values(config)`, the values of the fields, in the order of their names
+fn values(
+  args: Vec<Value>,
+  _: &mut Context,
+  _: Caller,
+) -> Result<Value, SamError> {
+  expect_args("values", &args, 1)?;
+  let fields = object("values", &args, 0)?;
+
+  let values: Vec<Value> = sorted_fields(fields)
+    .into_iter()
+    .map(|(_, value)| value.clone())
+    .collect();
+
+  return Ok(Value::from(values));
+}
+
+// `has(config, 'debug')`, whether the object has the field, even if it is
+// undefined
+fn has(
+  args: Vec<Value>,
+  _: &mut Context,
+  _: Caller,
+) -> Result<Value, SamError> {
+  expect_args("has", &args, 2)?;
+  let fields = object("has", &args, 0)?;
+
+  return Ok(Value::from(fields.contains_key(string("has", &args, 1)?)));
+}
+
+// `get(release, 'assets.0.name', 'none')`, the value at the end of a path
+// of field names and array positions, or the third argument, undefined if
+// there is none, where the path leads nowhere
+fn get(
+  args: Vec<Value>,
+  _: &mut Context,
+  _: Caller,
+) -> Result<Value, SamError> {
+  expect_args_between("get", &args, 2, 3)?;
+  let path = string("get", &args, 1)?;
+
+  let mut value = Some(&args[0]);
+  for step in path.split('.') {
+    value = match value {
+      Some(Value::SamObject(fields)) => fields.get(step),
+      Some(Value::SamArray(items)) => {
+        step.parse::<usize>().ok().and_then(|i| items.get(i))
+      }
+      _ => None,
+    };
+  }
+
+  let fallback = args.get(2).unwrap_or(&Value::Undefined);
+  return Ok(value.unwrap_or(fallback).clone());
+}
+
+// `merge(defaults, settings)`, the fields of every object, later ones
+// winning; fields that are objects in both are merged in turn
+fn merge(
+  args: Vec<Value>,
+  _: &mut Context,
+  _: Caller,
+) -> Result<Value, SamError> {
+  let mut merged = Value::from(HashMap::<String, Value>::new());
+  for i in 0..args.len() {
+    object("merge", &args, i)?;
+    merged = merge_values(merged, args[i].clone());
+  }
+
+  return Ok(merged);
+}
+
+fn merge_values(base: Value, over: Value) -> Value {
+  let (Value::SamObject(mut base), Value::SamObject(over)) = (base, over)
+  else {
+    unreachable!("only objects are merged");
+  };
+
+  let fields = Arc::make_mut(&mut base);
+  for (key, value) in over.iter() {
+    let merged = match (fields.remove(key), value) {
+      (Some(old @ Value::SamObject(_)), Value::SamObject(_)) => {
+        merge_values(old, value.clone())
+      }
+      _ => value.clone(),
+    };
+    fields.insert(key.clone(), merged);
+  }
+
+  return Value::SamObject(base);
+}
+
+/* =========================
+JSON
+========================= */
+
+// the same conversions as for foreign functions: null is undefined, and
+// true and false are 1 and 0
+
+// `json_parse(out.stdout)`, the value the JSON text stands for
+fn json_parse(
+  args: Vec<Value>,
+  _: &mut Context,
+  _: Caller,
+) -> Result<Value, SamError> {
+  expect_args("json_parse", &args, 1)?;
+  let text = string("json_parse", &args, 0)?;
+
+  let json = serde_json::from_str(text).map_err(|e| {
+    SamError::value(codes::INVALID_JSON, format!("Invalid JSON: {}", e))
+  })?;
+
+  return FFI::json_to_value(json);
+}
+
+// `json_stringify(config, 1)`, the value as JSON, indented if the second
+// argument is true
+fn json_stringify(
+  args: Vec<Value>,
+  _: &mut Context,
+  caller: Caller,
+) -> Result<Value, SamError> {
+  expect_args_between("json_stringify", &args, 1, 2)?;
+  let pretty = match args.get(1) {
+    Some(pretty) => condition(pretty.clone(), caller.span)?,
+    None => false,
+  };
+
+  let json = FFI::value_to_json(&args[0])?;
+  let text = match pretty {
+    true => serde_json::to_string_pretty(&json),
+    false => serde_json::to_string(&json),
+  };
+
+  return Ok(Value::from(text.unwrap()));
+}
+
+/* =========================
+CSV
+========================= */
+
+struct CsvOptions {
+  // whether the first row names the fields of the others
+  headers: bool,
+  delimiter: char,
+  // the fields written for rows that are objects, and their order
+  columns: Option<Vec<String>>,
+}
+
+// the options object of argument `i`, if given
+fn csv_options(
+  name: &str,
+  args: &[Value],
+  i: usize,
+  caller: Caller,
+) -> Result<CsvOptions, SamError> {
+  let mut options = CsvOptions {
+    headers: false,
+    delimiter: ',',
+    columns: None,
+  };
+  if args.len() <= i {
+    return Ok(options);
+  }
+
+  let fields = object(name, args, i)?;
+  let invalid = |option: &str, expected: &str| {
+    return SamError::type_error(
+      codes::ARGUMENT_TYPE,
+      format!("The `{}` of `{}` must be {}", option, name, expected),
+    );
+  };
+
+  if let Some(headers) = fields.get("headers") {
+    options.headers = condition(headers.clone(), caller.span)?;
+  }
+
+  if let Some(delimiter) = fields.get("delimiter") {
+    let one = |s: &str| {
+      let mut chars = s.chars();
+      return chars.next().filter(|_| chars.next().is_none());
+    };
+    options.delimiter = match delimiter {
+      Value::SamString(s) => one(s),
+      _ => None,
+    }
+    .ok_or_else(|| invalid("delimiter", "a single character"))?;
+  }
+
+  if let Some(columns) = fields.get("columns") {
+    let Value::SamArray(columns) = columns else {
+      return Err(invalid("columns", "an array of strings"));
+    };
+    let names = columns.iter().map(|column| match column {
+      Value::SamString(s) => Ok(s.to_string()),
+      _ => Err(invalid("columns", "an array of strings")),
+    });
+    options.columns = Some(names.collect::<Result<_, _>>()?);
+  }
+
+  return Ok(options);
+}
+
+// `csv_parse(out.stdout, {headers: 1})`, the rows of the text as arrays of
+// strings, or as objects named by the first row with `headers`. A
+// `delimiter` other than a comma may be given too.
+fn csv_parse(
+  args: Vec<Value>,
+  _: &mut Context,
+  caller: Caller,
+) -> Result<Value, SamError> {
+  expect_args_between("csv_parse", &args, 1, 2)?;
+  let text = string("csv_parse", &args, 0)?;
+  let options = csv_options("csv_parse", &args, 1, caller)?;
+
+  let mut records = csv::parse(text, options.delimiter)?.into_iter();
+  let to_strings = |record: Vec<String>| -> Vec<Value> {
+    return record.into_iter().map(Value::from).collect();
+  };
+
+  if !options.headers {
+    let rows: Vec<Value> =
+      records.map(|r| Value::from(to_strings(r))).collect();
+    return Ok(Value::from(rows));
+  }
+
+  let header = records.next().unwrap_or_default();
+  let mut rows = Vec::new();
+  for (i, record) in records.enumerate() {
+    if record.len() != header.len() {
+      return Err(SamError::value(
+        codes::INVALID_CSV,
+        format!(
+          "Row {} has {} fields but the header has {}",
+          i + 2,
+          record.len(),
+          header.len()
+        ),
+      ));
+    }
+
+    let row: HashMap<String, Value> =
+      header.iter().cloned().zip(to_strings(record)).collect();
+    rows.push(Value::from(row));
+  }
+
+  return Ok(Value::from(rows));
+}
+
+// `csv_write(rows)`, the rows as CSV text. Rows that are objects are
+// written after a header of their fields: the `columns` given, or else
+// the fields of the first row, in order.
+fn csv_write(
+  args: Vec<Value>,
+  _: &mut Context,
+  caller: Caller,
+) -> Result<Value, SamError> {
+  expect_args_between("csv_write", &args, 1, 2)?;
+  let options = csv_options("csv_write", &args, 1, caller)?;
+  let Value::SamArray(rows) = &args[0] else {
+    return Err(wrong_type("csv_write", 1, "an array", &args[0]));
+  };
+
+  let field = |value: &Value| match value {
+    Value::Undefined => String::new(),
+    value => value.to_string(),
+  };
+
+  let columns = match (rows.first(), options.columns) {
+    (Some(Value::SamObject(first)), None) => Some(
+      sorted_fields(first)
+        .into_iter()
+        .map(|(key, _)| key.clone())
+        .collect::<Vec<_>>(),
+    ),
+    (_, columns) => columns,
+  };
+
+  let mut records = Vec::new();
+  if let Some(columns) = &columns {
+    records.push(columns.clone());
+  }
+
+  for row in rows.iter() {
+    let record = match (row, &columns) {
+      (Value::SamArray(items), _) => items.iter().map(field).collect(),
+      (Value::SamObject(fields), Some(columns)) => columns
+        .iter()
+        .map(|column| fields.get(column).map(field).unwrap_or_default())
+        .collect(),
+      _ => {
+        let expected = "an array of arrays or of objects";
+        return Err(wrong_type("csv_write", 1, expected, row));
+      }
+    };
+    records.push(record);
+  }
+
+  return Ok(Value::from(csv::write(&records, options.delimiter)));
+}
+
+/* =========================
+HTTP
+========================= */
+
+// Requests are allowed by `--allow-net` like HTTP interface entries, and
+// take as long as `timeout` in sam.toml allows. Statuses of errors are
+// answered like any other, for the script to check.
+
+// `http_get('https://api.github.com/zen')`, the response of a GET request:
+// an object of its `status`, `headers`, and `body`. Headers to send may
+// follow as an object.
+fn http_get(
+  args: Vec<Value>,
+  ctx: &mut Context,
+  _: Caller,
+) -> Result<Value, SamError> {
+  expect_args_between("http_get", &args, 1, 2)?;
+  let url = string("http_get", &args, 0)?;
+  let headers = headers("http_get", &args, 1)?;
+
+  let config = &ctx.config;
+  let timeout = config.call_options().timeout;
+  return http::request(
+    "GET",
+    url,
+    None,
+    &headers,
+    timeout,
+    &config.permissions,
+  );
+}
+
+// `http_post(url, {name: 'sam'}, {Authorization: token})`, likewise for a
+// POST of the body: a string as it is, anything else as JSON
+fn http_post(
+  args: Vec<Value>,
+  ctx: &mut Context,
+  _: Caller,
+) -> Result<Value, SamError> {
+  expect_args_between("http_post", &args, 2, 3)?;
+  let url = string("http_post", &args, 0)?;
+  let mut headers = headers("http_post", &args, 2)?;
+
+  let body = match &args[1] {
+    Value::SamString(body) => body.to_string(),
+    other => {
+      let typed = headers
+        .iter()
+        .any(|(name, _)| name.eq_ignore_ascii_case("content-type"));
+      if !typed {
+        let json = ("Content-Type", "application/json");
+        headers.push((json.0.to_owned(), json.1.to_owned()));
+      }
+      FFI::value_to_json(other)?.to_string()
+    }
+  };
+
+  let config = &ctx.config;
+  let timeout = config.call_options().timeout;
+  let body = Some(body.as_str());
+  return http::request(
+    "POST",
+    url,
+    body,
+    &headers,
+    timeout,
+    &config.permissions,
+  );
+}
+
+// the headers to send, the fields of argument `i` if given
+fn headers(
+  name: &str,
+  args: &[Value],
+  i: usize,
+) -> Result<Vec<(String, String)>, SamError> {
+  if args.len() <= i {
+    return Ok(Vec::new());
+  }
+
+  let fields = object(name, args, i)?;
+  return Ok(
+    sorted_fields(fields)
+      .into_iter()
+      .map(|(key, value)| (key.clone(), value.to_string()))
+      .collect(),
+  );
+}
+
+/* =========================
+Hashing and encoding
+========================= */
+
+// Strings are taken as their UTF-8 bytes. Decoding gives back a string if
+// the bytes are valid UTF-8, and bytes otherwise.
+
+// the bytes of argument `i`, a string or bytes
+fn bytes<'a>(
+  name: &str,
+  args: &'a [Value],
+  i: usize,
+) -> Result<&'a [u8], SamError> {
+  return match &args[i] {
+    Value::SamString(s) => Ok(s.as_bytes()),
+    Value::SamBytes(bytes) => Ok(bytes),
+    other => Err(wrong_type(name, i + 1, "a string or bytes", other)),
+  };
+}
+
+fn decoded(bytes: Vec<u8>) -> Value {
+  return match String::from_utf8(bytes) {
+    Ok(text) => Value::from(text),
+    Err(e) => Value::SamBytes(Arc::new(e.into_bytes())),
+  };
+}
+
+// `sha256(archive)`, the SHA-256 digest in hexadecimal, e.g. of the bytes
+// an interface entry outputs
+fn sha256(
+  args: Vec<Value>,
+  _: &mut Context,
+  _: Caller,
+) -> Result<Value, SamError> {
+  expect_args("sha256", &args, 1)?;
+  let digest = digest::sha256(bytes("sha256", &args, 0)?);
+  return Ok(Value::from(digest::hex(&digest)));
+}
+
+// `md5(body)`, the MD5 digest in hexadecimal, for checksums published as
+// one; not for anything that has to be secure
+fn md5(
+  args: Vec<Value>,
+  _: &mut Context,
+  _: Caller,
+) -> Result<Value, SamError> {
+  expect_args("md5", &args, 1)?;
+  let digest = digest::md5(bytes("md5", &args, 0)?);
+  return Ok(Value::from(digest::hex(&digest)));
+}
+
+// `base64_encode(user + ':' + password)`, padded with `=`
+fn base64_encode(
+  args: Vec<Value>,
+  _: &mut Context,
+  _: Caller,
+) -> Result<Value, SamError> {
+  expect_args("base64_encode", &args, 1)?;
+  let text = encoding::base64_encode(bytes("base64_encode", &args, 0)?);
+  return Ok(Value::from(text));
+}
+
+// `base64_decode(token)`, with or without padding
+fn base64_decode(
+  args: Vec<Value>,
+  _: &mut Context,
+  _: Caller,
+) -> Result<Value, SamError> {
+  expect_args("base64_decode", &args, 1)?;
+  let text = string("base64_decode", &args, 0)?;
+  return Ok(decoded(encoding::base64_decode(text)?));
+}
+
+// `hex_encode(key)`, two lowercase digits per byte
+fn hex_encode(
+  args: Vec<Value>,
+  _: &mut Context,
+  _: Caller,
+) -> Result<Value, SamError> {
+  expect_args("hex_encode", &args, 1)?;
+  let text = encoding::hex_encode(bytes("hex_encode", &args, 0)?);
+  return Ok(Value::from(text));
+}
+
+// `hex_decode('cafe')`, digits in either case
+fn hex_decode(
+  args: Vec<Value>,
+  _: &mut Context,
+  _: Caller,
+) -> Result<Value, SamError> {
+  expect_args("hex_decode", &args, 1)?;
+  let text = string("hex_decode", &args, 0)?;
+  return Ok(decoded(encoding::hex_decode(text)?));
+}
+
+// `url + '?q=' + url_encode(query)`, every byte but letters, digits, and
+// `-_.~` as a `%XX` escape
+fn url_encode(
+  args: Vec<Value>,
+  _: &mut Context,
+  _: Caller,
+) -> Result<Value, SamError> {
+  expect_args("url_encode", &args, 1)?;
+  let text = encoding::url_encode(bytes("url_encode", &args, 0)?);
+  return Ok(Value::from(text));
+}
+
+// `url_decode('a%20b')`, the escapes back to bytes; `+` is left as is
+fn url_decode(
+  args: Vec<Value>,
+  _: &mut Context,
+  _: Caller,
+) -> Result<Value, SamError> {
+  expect_args("url_decode", &args, 1)?;
+  let text = string("url_decode", &args, 0)?;
+  return Ok(decoded(encoding::url_decode(text)?));
+}
+
+/* =========================
+Functions of elements
+========================= */
+
+// The function is called the way the script calls functions, so sam
+// functions, interface entries, and functions of the host all work, and an
+// error in it fails the builtin at the call of the builtin.
+
+// `map(files, (f) => { return f + '.bak'; })`, `f` of every element
+fn map(
+  args: Vec<Value>,
+  ctx: &mut Context,
+  caller: Caller,
+) -> Result<Value, SamError> {
+  let (items, f) = array_and_function("map", args)?;
+
+  let mut mapped = Vec::with_capacity(items.len());
+  for item in items {
+    mapped.push(caller.call(f.clone(), vec![item], ctx)?);
+  }
+
+  return Ok(Value::from(mapped));
+}
+
+// `filter(lines, (l) => { return l != ''; })`, the elements `f` holds for;
+// `f` returns a condition, as `if` takes
+fn filter(
+  args: Vec<Value>,
+  ctx: &mut Context,
+  caller: Caller,
+) -> Result<Value, SamError> {
+  let (items, f) = array_and_function("filter", args)?;
+
+  let mut kept = Vec::new();
+  for item in items {
+    let held = caller.call(f.clone(), vec![item.clone()], ctx)?;
+    if condition(held, caller.span)? {
+      kept.push(item);
+    }
+  }
+
+  return Ok(Value::from(kept));
+}
+
+// `reduce(sizes, (total, n) => { return total + n; }, 0)`, `f` of the
+// result so far and each element in turn, starting from the third argument
+// or, without one, from the first element
+fn reduce(
+  args: Vec<Value>,
+  ctx: &mut Context,
+  caller: Caller,
+) -> Result<Value, SamError> {
+  expect_args_between("reduce", &args, 2, 3)?;
+
+  let mut args = args.into_iter();
+  let (items, f) = (args.next().unwrap(), args.next().unwrap());
+  let Value::SamArray(items) = items else {
+    return Err(wrong_type("reduce", 1, "an array", &items));
+  };
+  let f = function("reduce", f, 2)?;
+
+  let mut items = items.iter().cloned();
+  let Some(mut result) = args.next().or_else(|| items.next()) else {
+    return Err(SamError::value(
+      codes::EMPTY_RANGE,
+      "`reduce` of an empty array needs a value to start from",
+    ));
+  };
+
+  for item in items {
+    result = caller.call(f.clone(), vec![result, item], ctx)?;
+  }
+
+  return Ok(result);
+}
+
+// `each(files, (f) => { rm(f); })`, `f` of every element for what it does
+fn each(
+  args: Vec<Value>,
+  ctx: &mut Context,
+  caller: Caller,
+) -> Result<Value, SamError> {
+  let (items, f) = array_and_function("each", args)?;
+
+  for item in items {
+    caller.call(f.clone(), vec![item], ctx)?;
+  }
+
+  return Ok(Value::Undefined);
+}
+
+// `let config = lazy(() => { return load(); });`, a value holding `f`
+// until the variable it is kept in is first read, which runs `f` once and
+// keeps its result in the variable instead
+fn lazy(
+  args: Vec<Value>,
+  _: &mut Context,
+  _: Caller,
+) -> Result<Value, SamError> {
+  expect_args("lazy", &args, 1)?;
+
+  return match &args[0] {
+    Value::SamFunction(f) if f.params.is_empty() => {
+      Ok(Value::SamLazy(f.clone()))
+    }
+    other => Err(wrong_type(
+      "lazy",
+      1,
+      "a function without parameters",
+      other,
+    )),
+  };
+}
+
+/* =========================
+Parallel
+========================= */
+
+// `par_map(files, f)`, `f` of every element, worked on by a pool of
+// threads, e.g. to run an external tool on each file at once. Every thread
+// runs on a copy of the context, so the functions see the script's
+// variables, but what they declare or assign is dropped.
+fn par_map(
+  args: Vec<Value>,
+  ctx: &mut Context,
+  caller: Caller,
+) -> Result<Value, SamError> {
+  let (items, f) = array_and_function("par_map", args)?;
+
+  return Ok(Value::from(in_parallel(&items, &f, ctx, caller)?));
+}
+
+// `par_filter(files, f)`, the elements `f` holds for, worked on like
+// `par_map`; `f` returns a condition, as `if` takes
+fn par_filter(
+  args: Vec<Value>,
+  ctx: &mut Context,
+  caller: Caller,
+) -> Result<Value, SamError> {
+  let (items, f) = array_and_function("par_filter", args)?;
+
+  let mut kept = Vec::new();
+  for (item, held) in items.iter().zip(in_parallel(&items, &f, ctx, caller)?) {
+    if condition(held, caller.span)? {
+      kept.push(item.clone());
+    }
+  }
+
+  return Ok(Value::from(kept));
+}
+
+fn array_and_function(
+  name: &str,
+  args: Vec<Value>,
+) -> Result<(Vec<Value>, Value), SamError> {
+  expect_args(name, &args, 2)?;
+
+  let mut args = args.into_iter();
+  let (items, f) = (args.next().unwrap(), args.next().unwrap());
+
+  let Value::SamArray(items) = items else {
+    return Err(wrong_type(name, 1, "an array", &items));
+  };
+
+  return Ok((items.to_vec(), function(name, f, 2)?));
+}
+
+// `f`, argument `position` of `name`, if it can be called
+fn function(name: &str, f: Value, position: usize) -> Result<Value, SamError> {
+  return match f {
+    Value::SamFunction(_)
+    | Value::SamForeignFunction(_)
+    | Value::SamNativeFunction(_) => Ok(f),
+    _ => Err(wrong_type(name, position, "a function", &f)),
+  };
+}
+
+// `f` of every item, in order; the first item to fail fails the whole
+fn in_parallel(
+  items: &[Value],
+  f: &Value,
+  ctx: &Context,
+  caller: Caller,
+) -> Result<Vec<Value>, SamError> {
+  let results: Vec<Result<Value, SamError>> = items
+    .par_iter()
+    .map_init(
+      || ctx.child(),
+      |child, item| caller.call(f.clone(), vec![item.clone()], child),
+    )
+    .collect();
+
+  return results.into_iter().collect();
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::ast::Block;
+  use crate::config::DivisionPolicy;
+  use crate::output::{Captured, Reader, Writer};
+  use crate::symbol::Symbol;
+  use crate::value::{Function, NativeFunction, Number};
+  use std::io;
+  use tree_sitter::Point;
+
+  fn caller() -> Caller {
+    let span = Span {
+      start: 0,
+      end: 0,
+      start_point: Point::default(),
+      end_point: Point::default(),
+    };
+
+    // natives are all these tests call
+    let call: Call = |f, args, _, _| match f {
+      Value::SamNativeFunction(f) => f.call(&args),
+      _ => unreachable!(),
+    };
+
+    return Caller { call, span };
+  }
+
+  fn numbers(n: i64) -> Value {
+    return Value::from((0..n).map(Value::from).collect::<Vec<_>>());
+  }
+
+  #[test]
+  fn test_print() {
+    let out = Captured::default();
+    let mut ctx = Context::new();
+    ctx.config.output.stdout = Writer::new(out.clone());
+
+    let args = vec![Value::from("total:"), Value::from(3i64)];
+    print(args, &mut ctx, caller()).unwrap();
+    println(vec![Value::from("!")], &mut ctx, caller()).unwrap();
+    println(vec![], &mut ctx, caller()).unwrap();
+
+    assert_eq!(out.contents(), "total: 3!\n\n");
+  }
+
+  #[test]
+  fn test_input() {
+    let out = Captured::default();
+    let mut ctx = Context::new();
+    ctx.config.output.stdin = Reader::new(io::Cursor::new("sam\n"));
+    ctx.config.output.stdout = Writer::new(out.clone());
+
+    let name = input(vec![Value::from("name? ")], &mut ctx, caller());
+    assert_eq!(name.unwrap(), Value::from("sam"));
+    assert_eq!(out.contents(), "name? ");
+
+    // the end of the input
+    let eof = input(vec![], &mut ctx, caller());
+    assert_eq!(eof.unwrap(), Value::Undefined);
+  }
+
+  #[test]
+  fn test_math() {
+    let mut ctx = Context::new();
+    let mut call = |f: Builtin, args: &[Value]| {
+      return f(args.to_vec(), &mut ctx, caller()).unwrap();
+    };
+    let (int, float) = (|i: i64| Value::from(i), |x: f64| Value::from(x));
+
+    assert_eq!(call(abs, &[int(-3)]), int(3));
+    assert_eq!(call(abs, &[int(i64::MIN)]), float(-(i64::MIN as f64)));
+    assert_eq!(call(floor, &[float(-2.5)]), int(-3));
+    assert_eq!(call(round, &[float(2.5)]), int(3));
+    assert!(matches!(
+      call(ceil, &[float(f64::INFINITY)]),
+      Value::SamNumber(Number::SamFloat(_))
+    ));
+
+    // at the ends of the integer range; the float just below 2^63 is the
+    // largest to fit
+    let below = 9223372036854774784.0;
+    assert!(matches!(
+      to_integer(below, f64::floor),
+      Number::SamInt(9223372036854774784)
+    ));
+    assert!(matches!(
+      to_integer(9223372036854775808.0, f64::floor),
+      Number::SamFloat(_)
+    ));
+    assert!(matches!(
+      to_integer(i64::MIN as f64, f64::floor),
+      Number::SamInt(i64::MIN)
+    ));
+    assert!(matches!(
+      to_integer(-9223372036854777856.0, f64::floor),
+      Number::SamFloat(_)
+    ));
+
+    assert_eq!(call(pow, &[int(2), int(10)]), int(1024));
+    assert_eq!(call(pow, &[int(2), int(-1)]), float(0.5));
+    assert_eq!(call(pow, &[int(10), int(30)]), float(1e30));
+    assert_eq!(call(sqrt, &[int(9)]), float(3.0));
+    assert_eq!(call(log, &[int(8), int(2)]), float(3.0));
+    assert_eq!(call(floor_div, &[int(-7), int(2)]), int(-4));
+    assert_eq!(call(floor_div, &[float(7.5), int(2)]), float(3.0));
+    assert_eq!(call(floor_div, &[int(1), int(0)]), Value::Undefined);
+
+    assert_eq!(call(min, &[int(3), float(1.5), int(2)]), float(1.5));
+    assert_eq!(call(max, &[Value::from(vec![int(3), int(7)])]), int(7));
+
+    let e = max(vec![], &mut ctx, caller()).unwrap_err();
+    assert_eq!(e.code(), Some(codes::ARGUMENT_COUNT));
+    ctx.config.division_by_zero = DivisionPolicy::Error;
+    let e = floor_div(vec![int(1), int(0)], &mut ctx, caller()).unwrap_err();
+    assert_eq!(e.message(), "Division by zero in `floor_div(1, 0)`");
+    let e = sqrt(vec![Value::from("4")], &mut ctx, caller()).unwrap_err();
+    assert_eq!(
+      e.message(),
+      "Argument 1 of `sqrt` must be a number, found string"
+    );
+  }
+
+  #[test]
+  fn test_strings() {
+    let mut ctx = Context::new();
+    let mut call = |f: Builtin, args: &[&str]| {
+      let args = args.iter().map(|a| Value::from(*a)).collect();
+      return f(args, &mut ctx, caller()).unwrap();
+    };
+    let strings = |items: &[&str]| {
+      return Value::from(
+        items.iter().map(|s| Value::from(*s)).collect::<Vec<_>>(),
+      );
+    };
+
+    assert_eq!(call(len, &["héllo"]), Value::from(5i64));
+    assert_eq!(call(upper, &["héllo"]), Value::from("HÉLLO"));
+    assert_eq!(call(trim, &[" a b\n"]), Value::from("a b"));
+    assert_eq!(call(split, &["a,b,,c", ","]), strings(&["a", "b", "", "c"]));
+    assert_eq!(call(split, &[" a  b\n"]), strings(&["a", "b"]));
+    assert_eq!(call(split, &["ab", ""]), strings(&["a", "b"]));
+    assert_eq!(call(replace, &["a-b-c", "-", "+"]), Value::from("a+b+c"));
+    assert_eq!(call(contains, &["error: x", "error"]), Value::from(1i64));
+    assert_eq!(call(ends_with, &["a.sam", ".py"]), Value::from(0i64));
+    assert_eq!(call(find, &["é=1", "="]), Value::from(1i64));
+    assert_eq!(call(find, &["a", "="]), Value::from(-1i64));
+
+    let joined = join(
+      vec![strings(&["a", "b"]), Value::from(", ")],
+      &mut ctx,
+      caller(),
+    );
+    assert_eq!(joined.unwrap(), Value::from("a, b"));
+
+    let pad_args = |s: &str, width: i64, fill: &str| {
+      return vec![Value::from(s), Value::from(width), Value::from(fill)];
+    };
+    let padded = pad(pad_args("7", 3, "0"), &mut ctx, caller()).unwrap();
+    assert_eq!(padded, Value::from("007"));
+    let padded = pad(pad_args("é", -3, "."), &mut ctx, caller()).unwrap();
+    assert_eq!(padded, Value::from("é.."));
+    let e = pad(pad_args("a", 3, "ab"), &mut ctx, caller()).unwrap_err();
+    assert_eq!(e.code(), Some(codes::ARGUMENT_TYPE));
+  }
+
+  #[test]
+  fn test_size_limits() {
+    let mut ctx = Context::new();
+    let pad_args = |width: i64, fill: &str| {
+      return vec![Value::from("a"), Value::from(width), Value::from(fill)];
+    };
+
+    // failing before anything is allocated, with no limit set too
+    let e = pad(pad_args(i64::MAX, " "), &mut ctx, caller()).unwrap_err();
+    assert_eq!(e.code(), Some(codes::LIMIT_EXCEEDED));
+    let e = pad(pad_args(i64::MIN, "🦀"), &mut ctx, caller()).unwrap_err();
+    assert_eq!(
+      e.message(),
+      "The result of `pad` would be too large to make"
+    );
+    for spec in ["{:99999999999}", "{:.99999999999}"] {
+      let args = vec![Value::from(spec), Value::from(1i64)];
+      let e = format(args.clone(), &mut ctx, caller()).unwrap_err();
+      assert_eq!(e.code(), Some(codes::LIMIT_EXCEEDED));
+      let e = print(args, &mut ctx, caller()).unwrap_err();
+      assert_eq!(e.code(), Some(codes::LIMIT_EXCEEDED));
+    }
+
+    ctx.config.limits.value_size = Some(4);
+    let strings = |items: &[&str]| {
+      return items.iter().map(|s| Value::from(*s)).collect::<Vec<_>>();
+    };
+    let over = [
+      pad(pad_args(5, "0"), &mut ctx, caller()),
+      replace(strings(&["aaa", "a", "bb"]), &mut ctx, caller()),
+      join(
+        vec![Value::from(strings(&["ab", "c"])), Value::from(", ")],
+        &mut ctx,
+        caller(),
+      ),
+      concat(vec![numbers(3), numbers(2)], &mut ctx, caller()),
+      // fields within the limit, but not all of them together
+      format(strings(&["{:3}{:3}", "a", "b"]), &mut ctx, caller()),
+      println(strings(&["abc", "de"]), &mut ctx, caller()),
+    ];
+    for result in over {
+      assert_eq!(result.unwrap_err().code(), Some(codes::LIMIT_EXCEEDED));
+    }
+
+    let e = pad(pad_args(5, "0"), &mut ctx, caller()).unwrap_err();
+    assert_eq!(
+      e.message(),
+      "The result of `pad` would be of size 5, past the limit of 4"
+    );
+    let padded = pad(pad_args(4, "0"), &mut ctx, caller()).unwrap();
+    assert_eq!(padded, Value::from("000a"));
+    let joined = join(
+      vec![Value::from(strings(&["a", "b"])), Value::from(", ")],
+      &mut ctx,
+      caller(),
+    );
+    assert_eq!(joined.unwrap(), Value::from("a, b"));
+  }
+
+  #[test]
+  fn test_arrays() {
+    let mut ctx = Context::new();
+    let mut call = |f: Builtin, args: Vec<Value>| {
+      return f(args, &mut ctx, caller());
+    };
+    let int = |i: i64| Value::from(i);
+
+    let items = numbers(3);
+    let pushed = call(push, vec![items.clone(), int(3), int(4)]).unwrap();
+    assert_eq!(pushed, numbers(5));
+    // the array given is left as it was
+    assert_eq!(items, numbers(3));
+
+    let popped = call(pop, vec![items.clone()]).unwrap();
+    assert_eq!(popped, Value::from(vec![numbers(2), int(2)]));
+    assert_eq!(items, numbers(3));
+    let e = call(pop, vec![numbers(0)]).unwrap_err();
+    assert_eq!(e.code(), Some(codes::INDEX_OUT_OF_BOUNDS));
+
+    let inserted = call(insert, vec![numbers(2), int(2), int(2)]).unwrap();
+    assert_eq!(inserted, numbers(3));
+    let removed = call(remove, vec![numbers(3), int(0)]).unwrap();
+    assert_eq!(removed, Value::from(vec![int(1), int(2)]));
+    assert!(call(remove, vec![numbers(3), int(3)]).is_err());
+
+    let mixed = Value::from(vec![int(2), Value::from(0.5), int(1)]);
+    let sorted = Value::from(vec![Value::from(0.5), int(1), int(2)]);
+    assert_eq!(call(sort, vec![mixed]).unwrap(), sorted);
+    let e = call(sort, vec![Value::from(vec![int(1), Value::from("a")])]);
+    assert_eq!(e.unwrap_err().code(), Some(codes::ARGUMENT_TYPE));
+
+    let reversed = call(reverse, vec![numbers(3)]).unwrap();
+    assert_eq!(reversed, Value::from(vec![int(2), int(1), int(0)]));
+    assert_eq!(call(reverse, vec![Value::from("ab")]).unwrap(), "ba".into());
+
+    let sliced = call(slice, vec![numbers(5), int(-2)]).unwrap();
+    assert_eq!(sliced, Value::from(vec![int(3), int(4)]));
+    let sliced = call(slice, vec![Value::from("héllo"), int(1), int(3)]);
+    assert_eq!(sliced.unwrap(), Value::from("él"));
+    let sliced = call(slice, vec![numbers(5), int(4), int(1)]).unwrap();
+    assert_eq!(sliced, numbers(0));
+
+    assert_eq!(call(index_of, vec![numbers(5), int(3)]).unwrap(), int(3));
+    assert_eq!(call(index_of, vec![numbers(5), int(9)]).unwrap(), int(-1));
+
+    let joined = call(concat, vec![numbers(2), Value::from(vec![int(2)])]);
+    assert_eq!(joined.unwrap(), numbers(3));
+    let nested =
+      Value::from(vec![numbers(2), int(2), Value::from(vec![numbers(1)])]);
+    let flat = Value::from(vec![int(0), int(1), int(2), numbers(1)]);
+    assert_eq!(call(flatten, vec![nested]).unwrap(), flat);
+  }
+
+  #[test]
+  fn test_seeded_random() {
+    let mut ctx = Context::new();
+    let draw = |ctx: &mut Context| {
+      seed(vec![Value::from(42i64)], ctx, caller()).unwrap();
+      let args = vec![Value::from(1i64), Value::from(6i64)];
+      let die = rand_int(args, ctx, caller()).unwrap();
+      let cards = shuffle(vec![numbers(10)], ctx, caller()).unwrap();
+      return (rand(vec![], ctx, caller()).unwrap(), die, cards);
+    };
+
+    assert_eq!(draw(&mut ctx), draw(&mut ctx));
+
+    let args = vec![Value::from(2i64), Value::from(1i64)];
+    let e = rand_int(args, &mut ctx, caller()).unwrap_err();
+    assert_eq!(e.code(), Some(codes::EMPTY_RANGE));
+  }
+
+  #[test]
+  fn test_assert() {
+    let mut ctx = Context::new();
+    let (yes, no) = (Value::from(1i64), Value::from(0i64));
+
+    assert!(assert(vec![yes.clone()], &mut ctx, caller()).is_ok());
+
+    let e = assert(vec![no, Value::from("empty")], &mut ctx, caller());
+    assert_eq!(e.unwrap_err().message(), "Assertion failed: empty");
+
+    assert!(
+      assert_eq(vec![yes.clone(), yes.clone()], &mut ctx, caller()).is_ok()
+    );
+    let e = assert_eq(vec![yes, Value::from("1")], &mut ctx, caller());
+    assert_eq!(e.unwrap_err().code(), Some(codes::ASSERTION_FAILED));
+  }
+
+  fn object(fields: &[(&str, Value)]) -> Value {
+    let fields: HashMap<String, Value> = fields
+      .iter()
+      .map(|(k, v)| (k.to_string(), v.clone()))
+      .collect();
+    return Value::from(fields);
+  }
+
+  // objects are never equal as values, so their fields are compared
+  fn fields(value: Value) -> HashMap<String, Value> {
+    let Value::SamObject(fields) = value else {
+      panic!("not an object: {:?}", value);
+    };
+    return (*fields).clone();
+  }
+
+  #[test]
+  fn test_objects() {
+    let mut ctx = Context::new();
+    let mut call = |f: Builtin, args: Vec<Value>| {
+      return f(args, &mut ctx, caller()).unwrap();
+    };
+    let int = |i: i64| Value::from(i);
+
+    let config = object(&[
+      ("b", int(2)),
+      ("a", int(1)),
+      ("nested", object(&[("x", int(1)), ("y", numbers(2))])),
+    ]);
+
+    let names = call(keys, vec![config.clone()]);
+    let expected = vec![Value::from("a"), "b".into(), "nested".into()];
+    assert_eq!(names, Value::from(expected));
+    let listed = call(values, vec![object(&[("b", int(2)), ("a", int(1))])]);
+    assert_eq!(listed, Value::from(vec![int(1), int(2)]));
+    assert_eq!(call(has, vec![config.clone(), "a".into()]), int(1));
+
+    let removed = call(remove, vec![config.clone(), "nested".into()]);
+    let expected = object(&[("b", int(2)), ("a", int(1))]);
+    assert_eq!(fields(removed), fields(expected));
+
+    // paths through objects and arrays
+    let path = |path: &str| vec![config.clone(), Value::from(path)];
+    assert_eq!(call(get, path("nested.y.1")), int(1));
+    assert_eq!(call(get, path("nested.z")), Value::Undefined);
+    let args = vec![config.clone(), "a.b".into(), "none".into()];
+    assert_eq!(call(get, args), Value::from("none"));
+
+    let over = object(&[("a", int(9)), ("nested", object(&[("x", int(5))]))]);
+    let mut merged = fields(call(merge, vec![config.clone(), over]));
+    let nested = merged.remove("nested").unwrap();
+    let expected = object(&[("b", int(2)), ("a", int(9))]);
+    assert_eq!(merged, fields(expected));
+    let expected = object(&[("x", int(5)), ("y", numbers(2))]);
+    assert_eq!(fields(nested), fields(expected));
+  }
+
+  #[test]
+  fn test_json() {
+    let mut ctx = Context::new();
+    let mut call = |f: Builtin, args: Vec<Value>| {
+      return f(args, &mut ctx, caller());
+    };
+
+    let text = Value::from(r#"{"a": [1, 2.5, "x"], "b": true, "c": null}"#);
+    let parsed = fields(call(json_parse, vec![text]).unwrap());
+    let a = vec![Value::from(1i64), Value::from(2.5), Value::from("x")];
+    assert_eq!(parsed["a"], Value::from(a));
+    assert_eq!(parsed["b"], Value::from(1i64));
+    assert_eq!(parsed["c"], Value::Undefined);
+
+    let e = call(json_parse, vec!["{a: 1}".into()]).unwrap_err();
+    assert_eq!(e.code(), Some(codes::INVALID_JSON));
+
+    // fields in the order of their names
+    let value = object(&[("b", numbers(2)), ("a", "x".into())]);
+    let compact = call(json_stringify, vec![value.clone()]).unwrap();
+    assert_eq!(compact, Value::from(r#"{"a":"x","b":[0,1]}"#));
+    let pretty = call(json_stringify, vec![value, 1i64.into()]).unwrap();
+    assert_eq!(pretty.to_string().lines().count(), 7);
+
+    let f = Value::from(NativeFunction::new("f", |_| Ok(Value::Undefined)));
+    let e = call(json_stringify, vec![f]).unwrap_err();
+    assert_eq!(e.code(), Some(codes::NOT_SERIALIZABLE));
+  }
+
+  #[test]
+  fn test_time() {
+    let mut ctx = Context::new();
+
+    let start = clock(vec![], &mut ctx, caller()).unwrap();
+    sleep(vec![Value::from(0.01)], &mut ctx, caller()).unwrap();
+    let elapsed = clock(vec![], &mut ctx, caller()).unwrap() - start;
+    assert!(elapsed >= Value::from(0.01));
+
+    let leap_day = Value::from(951_827_696.5);
+    let args = vec![leap_day.clone()];
+    let formatted = format_time(args, &mut ctx, caller()).unwrap();
+    assert_eq!(formatted, Value::from("2000-02-29T12:34:56Z"));
+    let args = vec![leap_day.clone(), "%H:%M:%S.%f".into()];
+    let formatted = format_time(args, &mut ctx, caller()).unwrap();
+    assert_eq!(formatted, Value::from("12:34:56.500000"));
+
+    let args = vec![leap_day, "%A".into()];
+    let e = format_time(args, &mut ctx, caller()).unwrap_err();
+    assert_eq!(e.code(), Some(codes::INVALID_FORMAT));
+
+    // an interrupt ends a sleep early
+    ctx.config.interrupt.interrupt();
+    let e = sleep(vec![Value::from(60i64)], &mut ctx, caller()).unwrap_err();
+    assert_eq!(e.code(), Some(codes::CANCELLED));
+  }
+
+  #[test]
+  fn test_exit() {
+    let mut ctx = Context::new();
+
+    let e = exit(vec![], &mut ctx, caller()).unwrap_err();
+    assert_eq!(e.exit_status(), Some(0));
+    let e = exit(vec![Value::from(3i64)], &mut ctx, caller()).unwrap_err();
+    assert_eq!(e.code(), Some(codes::SCRIPT_EXIT));
+    assert_eq!(e.exit_status(), Some(3));
+
+    let e = exit(vec![Value::from(256i64)], &mut ctx, caller()).unwrap_err();
+    assert_eq!(e.code(), Some(codes::EXIT_STATUS));
+    assert_eq!(e.exit_status(), None);
+  }
+
+  #[test]
+  fn test_format() {
+    let mut ctx = Context::new();
+    let args = vec!["{:<5}|{:>6.1}%".into(), "cpu".into(), Value::from(12.34)];
+    let row = format(args, &mut ctx, caller()).unwrap();
+    assert_eq!(row, Value::from("cpu  |  12.3%"));
+
+    // print formats only when there is something to fill in
+    let out = Captured::default();
+    ctx.config.output.stdout = Writer::new(out.clone());
+    let printed = [
+      vec!["{:03}".into(), Value::from(7i64)],
+      vec!["{:03}".into()],
+      vec!["{ total:".into(), Value::from(2i64)],
+    ];
+    for args in printed {
+      println(args, &mut ctx, caller()).unwrap();
+    }
+    assert_eq!(out.contents(), "007\n{:03}\n{ total: 2\n");
+
+    let args = vec!["{} {}".into(), Value::from(1i64)];
+    let e = println(args, &mut ctx, caller()).unwrap_err();
+    assert_eq!(e.code(), Some(codes::ARGUMENT_COUNT));
+  }
+
+  #[test]
+  fn test_csv() {
+    let mut ctx = Context::new();
+    let text = Value::from("name,size\nsam,12\n\"a, b\",3\n");
+
+    let args = vec![text.clone(), object(&[("headers", 1i64.into())])];
+    let Value::SamArray(rows) = csv_parse(args, &mut ctx, caller()).unwrap()
+    else {
+      panic!("expected rows");
+    };
+    assert_eq!(rows.len(), 2);
+    let row = fields(rows[1].clone());
+    assert_eq!(row["name"], Value::from("a, b"));
+    assert_eq!(row["size"], Value::from("3"));
+
+    // objects are written back under the header they were read with
+    let args = vec![Value::SamArray(rows)];
+    let written = csv_write(args, &mut ctx, caller()).unwrap();
+    assert_eq!(written, text);
+
+    let arrays = csv_parse(vec![text], &mut ctx, caller()).unwrap();
+    let written = csv_write(vec![arrays], &mut ctx, caller()).unwrap();
+    assert_eq!(written.to_string().lines().count(), 3);
+
+    let args = vec!["a,b\n1".into(), object(&[("headers", 1i64.into())])];
+    let e = csv_parse(args, &mut ctx, caller()).unwrap_err();
+    assert_eq!(e.code(), Some(codes::INVALID_CSV));
+
+    let args = vec!["a".into(), object(&[("delimiter", ";;".into())])];
+    let e = csv_parse(args, &mut ctx, caller()).unwrap_err();
+    assert_eq!(e.code(), Some(codes::ARGUMENT_TYPE));
+  }
+
+  #[test]
+  fn test_hashing_and_encoding() {
+    let mut ctx = Context::new();
+    let call =
+      |f: Builtin, arg: Value| f(vec![arg], &mut Context::new(), caller());
+
+    let digest = call(sha256, "abc".into()).unwrap();
+    assert_eq!(
+      digest,
+      Value::from(
+        "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+      )
+    );
+    let binary = Value::SamBytes(Arc::new(b"abc".to_vec()));
+    assert_eq!(call(md5, binary).unwrap(), call(md5, "abc".into()).unwrap());
+
+    let encoded = call(base64_encode, "user:pass".into()).unwrap();
+    assert_eq!(encoded, Value::from("dXNlcjpwYXNz"));
+    assert_eq!(call(base64_decode, encoded).unwrap(), "user:pass".into());
+    assert_eq!(
+      call(url_encode, "a b/c".into()).unwrap(),
+      "a%20b%2Fc".into()
+    );
+    assert_eq!(call(url_decode, "a%20b".into()).unwrap(), "a b".into());
+
+    // bytes that are not UTF-8 come back as bytes
+    let decoded = call(hex_decode, "ff00".into()).unwrap();
+    assert_eq!(decoded, Value::SamBytes(Arc::new(vec![255, 0])));
+    assert_eq!(call(hex_encode, decoded).unwrap(), "ff00".into());
+
+    let e = hex_decode(vec!["f00".into()], &mut ctx, caller()).unwrap_err();
+    assert_eq!(e.code(), Some(codes::INVALID_ENCODING));
+    let e = sha256(vec![Value::from(1i64)], &mut ctx, caller()).unwrap_err();
+    assert_eq!(e.code(), Some(codes::ARGUMENT_TYPE));
+  }
+
+  #[test]
+  fn test_map_filter_reduce() {
+    let mut ctx = Context::new();
+    let double = Value::from(NativeFunction::new("double", |args| {
+      return Ok(args[0].clone() + args[0].clone());
+    }));
+    let odd = Value::from(NativeFunction::new("odd", |args| {
+      let Value::SamNumber(Number::SamInt(n)) = args[0] else {
+        unreachable!();
+      };
+      return Ok(Value::from(n % 2));
+    }));
+    let add = Value::from(NativeFunction::new("add", |args| {
+      return Ok(args[0].clone() + args[1].clone());
+    }));
+
+    let v = map(vec![numbers(3), double], &mut ctx, caller()).unwrap();
+    let expected: Vec<Value> = (0..3).map(|n| Value::from(n * 2)).collect();
+    assert_eq!(v, Value::from(expected));
+
+    let v = filter(vec![numbers(5), odd.clone()], &mut ctx, caller()).unwrap();
+    assert_eq!(v, Value::from(vec![Value::from(1i64), Value::from(3i64)]));
+
+    let args = vec![numbers(5), add.clone(), Value::from(10i64)];
+    let v = reduce(args, &mut ctx, caller()).unwrap();
+    assert_eq!(v, Value::from(20i64));
+    let v = reduce(vec![numbers(5), add.clone()], &mut ctx, caller()).unwrap();
+    assert_eq!(v, Value::from(10i64));
+    let e = reduce(vec![numbers(0), add], &mut ctx, caller()).unwrap_err();
+    assert_eq!(e.code(), Some(codes::EMPTY_RANGE));
+
+    let v = each(vec![numbers(5), odd], &mut ctx, caller()).unwrap();
+    assert_eq!(v, Value::Undefined);
+  }
+
+  #[test]
+  fn test_lazy() {
+    let mut ctx = Context::new();
+    let body = Block {
+      statements: vec![],
+      span: caller().span,
+    };
+    let f = |params: Vec<Symbol>| {
+      return Value::SamFunction(Arc::new(Function {
+        params,
+        body: Arc::new(body.clone()),
+      }));
+    };
+
+    let v = lazy(vec![f(vec![])], &mut ctx, caller()).unwrap();
+    assert!(matches!(v, Value::SamLazy(_)));
+
+    // the function is called with nothing, so it cannot take parameters
+    let e = lazy(vec![f(vec![Symbol::from("x")])], &mut ctx, caller());
+    assert_eq!(e.unwrap_err().code(), Some(codes::ARGUMENT_TYPE));
+    let e = lazy(vec![Value::from(1i64)], &mut ctx, caller());
+    assert_eq!(e.unwrap_err().code(), Some(codes::ARGUMENT_TYPE));
+  }
+
+  #[test]
+  fn test_par_map() {
+    let double = Value::from(NativeFunction::new("double", |args| {
+      return Ok(args[0].clone() + args[0].clone());
+    }));
+
+    let mut ctx = Context::new();
+    let v = par_map(vec![numbers(100), double], &mut ctx, caller()).unwrap();
+
+    let expected: Vec<Value> = (0..100).map(|n| Value::from(n * 2)).collect();
+    assert_eq!(v, Value::from(expected));
+
+    // not an array
+    let e = par_map(
+      vec![Value::from(1i64), Value::Undefined],
+      &mut ctx,
+      caller(),
+    );
+    assert!(e.is_err());
+  }
+
+  #[test]
+  fn test_par_filter() {
+    let even = Value::from(NativeFunction::new("even", |args| {
+      let Value::SamNumber(Number::SamInt(n)) = args[0] else {
+        unreachable!();
+      };
+      return Ok(Value::from((n % 2 == 0) as i64));
+    }));
+
+    let mut ctx = Context::new();
+    let v = par_filter(vec![numbers(10), even], &mut ctx, caller()).unwrap();
+
+    let expected: Vec<Value> = (0..10).step_by(2).map(Value::from).collect();
+    assert_eq!(v, Value::from(expected));
+  }
+}
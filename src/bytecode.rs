@@ -0,0 +1,853 @@
+#![allow(dead_code)]
+
+// The bytecode the vm backend runs: the lowered program compiled to flat
+// instructions for a stack machine, see src/vm.rs. Every instruction keeps
+// the span of the code it came from, so that errors point at the same
+// places as with the tree-walker.
+
+use crate::ast::{
+  BinaryOp, Block, Call, Else, Expr, ExprKind, If, Import, Interface, Literal,
+  Stmt, StmtKind,
+};
+use crate::error::Span;
+use crate::evaluate::callee_name;
+use crate::symbol::Symbol;
+use crate::value::Value;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::HashSet;
+use std::fmt;
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Op {
+  // push constant n
+  Constant(usize),
+  // push undefined
+  Undefined,
+  // drop the top of the stack
+  Pop,
+  // pop the value of a top level statement, the run's result if it is the
+  // last
+  Result,
+
+  // push a copy of the variable with name n
+  Load(usize),
+  // pop into the variable n of the current scope
+  Declare(usize),
+  // declare the variable n as undefined unless the scope already has it
+  DeclareUndefined(usize),
+  // assign the top of the stack to the variable n, leaving it there
+  Store(usize),
+
+  // pop the right and the left operand, push the result
+  Binary(BinaryOp),
+  // pop the left operand, push the result with constant `constant` as the
+  // right one, fused by the peephole pass
+  BinaryConstant {
+    op: BinaryOp,
+    constant: usize,
+  },
+  // replace an object with its property n
+  Member(usize),
+  // replace an index with that element of the array variable `array`;
+  // `index_at` is the location of the index
+  Index {
+    array: usize,
+    index_at: usize,
+  },
+  // pop n values into an array
+  Array(usize),
+  // push a function of lambda n
+  Lambda(usize),
+
+  // pop a callee and its n arguments below it and call it; `name` is how
+  // call traces show the callee
+  Call {
+    argc: usize,
+    name: usize,
+  },
+  // call the variable, builtin, or program with name n with the top argc
+  // values
+  CallName {
+    name: usize,
+    argc: usize,
+  },
+  // like the calls, pushing a handle instead of waiting for the result;
+  // `callee_at` is the location of the callee
+  Spawn {
+    argc: usize,
+    name: usize,
+    callee_at: usize,
+  },
+  SpawnName {
+    name: usize,
+    argc: usize,
+    callee_at: usize,
+  },
+  // pop a handle and push its result; `handle_at` is the location of the
+  // handle
+  Await {
+    handle_at: usize,
+  },
+  // like the calls, setting the call aside as a stage of the next pipe
+  Stage {
+    argc: usize,
+  },
+  StageName {
+    name: usize,
+    argc: usize,
+  },
+  // run the last n stages connected, pushing the result
+  Pipe(usize),
+
+  // load interface n into the current scope
+  Interface(usize),
+  // define the names of module n in the current scope
+  Import(usize),
+
+  // open a block scope
+  EnterScope,
+  // close n block scopes
+  ExitScope(usize),
+
+  // continue at instruction n
+  Jump(usize),
+  // pop a condition and continue at instruction n if it is false
+  Branch(usize),
+  // continue at instruction `target` if the variable `name` exists
+  JumpIfDefined {
+    name: usize,
+    target: usize,
+  },
+
+  // pop an iterable and start a loop over it
+  Iterate,
+  // push the next item of the innermost loop, or end the loop and continue
+  // at instruction n if there is none
+  Next(usize),
+  // end the innermost loop, left by a break
+  EndIterate,
+
+  // pop the return value and leave the current function
+  Return,
+
+  // raise an error the compiler found, once execution gets here
+  Fail(Failure),
+}
+
+impl Op {
+  // the instruction a jump continues at
+  pub fn target(&self) -> Option<usize> {
+    match *self {
+      Op::Jump(t) | Op::Branch(t) | Op::Next(t) => Some(t),
+      Op::JumpIfDefined { target, .. } => Some(target),
+      _ => None,
+    }
+  }
+
+  pub fn target_mut(&mut self) -> Option<&mut usize> {
+    match self {
+      Op::Jump(t) | Op::Branch(t) | Op::Next(t) => Some(t),
+      Op::JumpIfDefined { target, .. } => Some(target),
+      _ => None,
+    }
+  }
+}
+
+// errors of code that compiles, raised only if it runs
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Failure {
+  // a property of something that is not a variable
+  NotAnObject,
+  // a variable where an array literal needs a computed value
+  ValueExpected,
+  // a pipe argument that is not a call
+  NotPipeable,
+  // break or continue outside a loop
+  LoopControl,
+}
+
+// the compiled code of a program or of a function body
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Chunk {
+  pub code: Vec<Op>,
+  // where each instruction came from
+  pub spans: Vec<Span>,
+  // further locations instructions refer to, e.g. of an index
+  pub locations: Vec<Span>,
+  #[serde(with = "constants")]
+  pub constants: Vec<Value>,
+  // variable and property names
+  pub names: Vec<Symbol>,
+  pub lambdas: Vec<(Vec<Symbol>, Arc<Block>)>,
+  pub interfaces: Vec<Interface>,
+  pub imports: Vec<Import>,
+}
+
+impl Chunk {
+  pub fn name(&self, n: usize) -> Symbol {
+    return self.names[n];
+  }
+}
+
+// constants are written out as the literals they were compiled from, see
+// src/cache.rs
+mod constants {
+  use super::*;
+  use serde::ser::Error as _;
+
+  pub fn serialize<S: Serializer>(
+    constants: &[Value],
+    s: S,
+  ) -> Result<S::Ok, S::Error> {
+    let literals = constants
+      .iter()
+      .map(|v| {
+        Literal::from_value(v).ok_or_else(|| {
+          S::Error::custom(format!("{} constant", v.type_name()))
+        })
+      })
+      .collect::<Result<Vec<Literal>, S::Error>>()?;
+
+    return literals.serialize(s);
+  }
+
+  pub fn deserialize<'de, D: Deserializer<'de>>(
+    d: D,
+  ) -> Result<Vec<Value>, D::Error> {
+    let literals = Vec::<Literal>::deserialize(d)?;
+    return Ok(literals.iter().map(Literal::to_value).collect());
+  }
+}
+
+/* =========================
+Compiler
+========================= */
+
+// compile top level statements, the value of each is kept as the result
+pub fn compile_program(statements: &[Stmt]) -> Chunk {
+  let mut compiler = Compiler::default();
+
+  for stmt in statements {
+    compiler.statement(stmt, true);
+  }
+
+  return compiler.chunk;
+}
+
+// compile the body of a function, which returns undefined unless it returns
+// something else
+pub fn compile_function(body: &Block) -> Chunk {
+  let mut compiler = Compiler::default();
+
+  for stmt in &body.statements {
+    compiler.statement(stmt, false);
+  }
+
+  compiler.emit(Op::Undefined, body.span);
+  compiler.emit(Op::Return, body.span);
+
+  return compiler.chunk;
+}
+
+// an enclosing loop, for break and continue
+struct Loop {
+  // scopes open outside of the loop body
+  scopes: usize,
+  // instruction fetching the next item
+  next: usize,
+  // jumps of the breaks, patched to after the loop
+  breaks: Vec<usize>,
+}
+
+#[derive(Default)]
+struct Compiler {
+  chunk: Chunk,
+  // block scopes open at the current instruction
+  scopes: usize,
+  loops: Vec<Loop>,
+}
+
+impl Compiler {
+  fn emit(&mut self, op: Op, span: Span) -> usize {
+    self.chunk.code.push(op);
+    self.chunk.spans.push(span);
+
+    return self.chunk.code.len() - 1;
+  }
+
+  // point the jump at `at` to the next instruction
+  fn patch(&mut self, at: usize) {
+    let target = self.chunk.code.len();
+
+    match self.chunk.code[at].target_mut() {
+      Some(t) => *t = target,
+      None => unreachable!("{:?} is not a jump", self.chunk.code[at]),
+    }
+  }
+
+  fn name(&mut self, name: Symbol) -> usize {
+    let names = &mut self.chunk.names;
+
+    match names.iter().position(|n| *n == name) {
+      Some(n) => n,
+      None => {
+        names.push(name);
+        names.len() - 1
+      }
+    }
+  }
+
+  fn constant(&mut self, v: Value) -> usize {
+    self.chunk.constants.push(v);
+    return self.chunk.constants.len() - 1;
+  }
+
+  fn location(&mut self, span: Span) -> usize {
+    self.chunk.locations.push(span);
+    return self.chunk.locations.len() - 1;
+  }
+
+  /* =========================
+  Statements
+  ========================= */
+
+  // compile a statement, leaving the stack as it was; top level ones keep
+  // their value as the result
+  fn statement(&mut self, stmt: &Stmt, top_level: bool) {
+    let span = stmt.span;
+
+    match &stmt.kind {
+      StmtKind::Expression(expr) => self.expression(expr),
+
+      StmtKind::Declaration { declarators, .. } => {
+        for declarator in declarators {
+          let name = self.name(declarator.name.name);
+
+          match &declarator.value {
+            Some(value) => {
+              self.expression(value);
+              self.emit(Op::Declare(name), declarator.span);
+            }
+            None => {
+              self.emit(Op::DeclareUndefined(name), declarator.span);
+            }
+          }
+        }
+        self.emit(Op::Undefined, span);
+      }
+
+      StmtKind::Assignment { target, value } => {
+        self.expression(value);
+        let name = self.name(target.name);
+        self.emit(Op::Store(name), span);
+      }
+
+      StmtKind::Return(value) => {
+        match value {
+          Some(value) => self.expression(value),
+          None => {
+            self.emit(Op::Undefined, span);
+          }
+        }
+        self.emit(Op::Return, span);
+        return;
+      }
+
+      StmtKind::Break | StmtKind::Continue => {
+        let Some(inner) = self.loops.last() else {
+          self.emit(Op::Fail(Failure::LoopControl), span);
+          return;
+        };
+
+        let (scopes, next) = (inner.scopes, inner.next);
+        if self.scopes > scopes {
+          self.emit(Op::ExitScope(self.scopes - scopes), span);
+        }
+
+        if matches!(stmt.kind, StmtKind::Continue) {
+          self.emit(Op::Jump(next), span);
+        } else {
+          let jump = self.emit(Op::Jump(0), span);
+          self.loops.last_mut().unwrap().breaks.push(jump);
+        }
+        return;
+      }
+
+      StmtKind::Interfaces(interfaces) => {
+        for interface in interfaces {
+          self.chunk.interfaces.push(interface.clone());
+          let n = self.chunk.interfaces.len() - 1;
+          self.emit(Op::Interface(n), interface.span);
+        }
+        self.emit(Op::Undefined, span);
+      }
+
+      StmtKind::Import(import) => {
+        self.chunk.imports.push(import.clone());
+        let n = self.chunk.imports.len() - 1;
+        self.emit(Op::Import(n), import.span);
+        self.emit(Op::Undefined, span);
+      }
+    }
+
+    let keep = if top_level { Op::Result } else { Op::Pop };
+    self.emit(keep, span);
+  }
+
+  fn block(&mut self, block: &Block) {
+    self.emit(Op::EnterScope, block.span);
+    self.scopes += 1;
+
+    for stmt in &block.statements {
+      self.statement(stmt, false);
+    }
+
+    self.scopes -= 1;
+    self.emit(Op::ExitScope(1), block.span);
+  }
+
+  /* =========================
+  Expressions
+  ========================= */
+
+  // compile an expression, pushing its value
+  fn expression(&mut self, expr: &Expr) {
+    let span = expr.span;
+
+    match &expr.kind {
+      ExprKind::Literal(literal) => {
+        let n = self.constant(literal.to_value());
+        self.emit(Op::Constant(n), span);
+      }
+
+      ExprKind::Identifier(name) => {
+        let name = self.name(*name);
+        self.emit(Op::Load(name), span);
+      }
+
+      ExprKind::Binary { op, left, right } => {
+        self.expression(left);
+        self.expression(right);
+        self.emit(Op::Binary(*op), span);
+      }
+
+      ExprKind::If(branch) => {
+        self.branch(branch, span);
+        self.emit(Op::Undefined, span);
+      }
+
+      ExprKind::For {
+        variable,
+        iterable,
+        body,
+      } => {
+        self.expression(iterable);
+        self.emit(Op::Iterate, iterable.span);
+
+        let next = self.emit(Op::Next(0), span);
+        self.loops.push(Loop {
+          scopes: self.scopes,
+          next,
+          breaks: Vec::new(),
+        });
+
+        // the variable is bound in the scope of the body
+        self.emit(Op::EnterScope, body.span);
+        self.scopes += 1;
+        let name = self.name(variable.name);
+        self.emit(Op::Declare(name), variable.span);
+
+        for stmt in &body.statements {
+          self.statement(stmt, false);
+        }
+
+        self.scopes -= 1;
+        self.emit(Op::ExitScope(1), body.span);
+        self.emit(Op::Jump(next), span);
+
+        let inner = self.loops.pop().unwrap();
+        if !inner.breaks.is_empty() {
+          for jump in inner.breaks {
+            self.patch(jump);
+          }
+          self.emit(Op::EndIterate, span);
+        }
+
+        self.patch(next);
+        self.emit(Op::Undefined, span);
+      }
+
+      ExprKind::Lambda { params, body } => {
+        let params = params.iter().map(|p| p.name).collect();
+        self.chunk.lambdas.push((params, body.clone()));
+        let n = self.chunk.lambdas.len() - 1;
+        self.emit(Op::Lambda(n), span);
+      }
+
+      ExprKind::Call(call) => self.call(call, span),
+
+      ExprKind::Member { object, property } => {
+        self.expression(object);
+
+        // only variables have properties
+        if !is_reference(object) {
+          self.emit(Op::Pop, span);
+          self.emit(Op::Fail(Failure::NotAnObject), span);
+          return;
+        }
+
+        let name = self.name(property.name);
+        self.emit(Op::Member(name), span);
+      }
+
+      ExprKind::Array(items) => {
+        for item in items {
+          self.expression(item);
+
+          if is_reference(item) {
+            self.emit(Op::Fail(Failure::ValueExpected), item.span);
+          }
+        }
+        self.emit(Op::Array(items.len()), span);
+      }
+
+      ExprKind::Index { array, index } => {
+        self.expression(index);
+        let array_name = self.name(array.name);
+        let index_at = self.location(index.span);
+        self.emit(
+          Op::Index {
+            array: array_name,
+            index_at,
+          },
+          array.span,
+        );
+      }
+
+      ExprKind::Spawn(call) => {
+        self.args(&call.args);
+        let func = call.function.as_ref();
+        let callee_at = self.location(func.span);
+
+        match &func.kind {
+          ExprKind::Identifier(name) => {
+            let name = self.name(*name);
+            self.emit(
+              Op::SpawnName {
+                name,
+                argc: call.args.len(),
+                callee_at,
+              },
+              span,
+            );
+          }
+          _ => {
+            self.expression(func);
+            let name = self.name(callee_name(func).into());
+            self.emit(
+              Op::Spawn {
+                argc: call.args.len(),
+                name,
+                callee_at,
+              },
+              span,
+            );
+          }
+        }
+      }
+
+      ExprKind::Await(handle) => {
+        self.expression(handle);
+        let handle_at = self.location(handle.span);
+        self.emit(Op::Await { handle_at }, span);
+      }
+    }
+  }
+
+  // an if without its value, `span` is where a bad condition is reported
+  fn branch(&mut self, branch: &If, span: Span) {
+    self.expression(&branch.condition);
+    let to_alternative = self.emit(Op::Branch(0), span);
+
+    self.block(&branch.consequence);
+
+    let Some(alternative) = &branch.alternative else {
+      self.patch(to_alternative);
+      return;
+    };
+
+    let to_end = self.emit(Op::Jump(0), span);
+    self.patch(to_alternative);
+
+    match alternative {
+      Else::Block(block) => self.block(block),
+      Else::If(branch, span) => self.branch(branch, *span),
+    }
+
+    self.patch(to_end);
+  }
+
+  fn args(&mut self, args: &[Expr]) {
+    for arg in args {
+      self.expression(arg);
+    }
+  }
+
+  fn call(&mut self, call: &Call, span: Span) {
+    let func = call.function.as_ref();
+    let argc = call.args.len();
+
+    let ExprKind::Identifier(name) = &func.kind else {
+      self.args(&call.args);
+      self.expression(func);
+      let name = self.name(callee_name(func).into());
+      self.emit(Op::Call { argc, name }, func.span);
+      return;
+    };
+
+    let name = self.name(*name);
+
+    // `pipe(...)` connects its calls, unless a variable named pipe hides it
+    let mut to_end = None;
+    if self.chunk.names[name] == "pipe" {
+      let to_call = self.emit(Op::JumpIfDefined { name, target: 0 }, span);
+      self.pipe(call, span);
+      to_end = Some(self.emit(Op::Jump(0), span));
+      self.patch(to_call);
+    }
+
+    self.args(&call.args);
+    self.emit(Op::CallName { name, argc }, func.span);
+
+    if let Some(to_end) = to_end {
+      self.patch(to_end);
+    }
+  }
+
+  fn pipe(&mut self, call: &Call, span: Span) {
+    for arg in &call.args {
+      let ExprKind::Call(stage) = &arg.kind else {
+        self.emit(Op::Fail(Failure::NotPipeable), arg.span);
+        return;
+      };
+
+      let func = stage.function.as_ref();
+      let argc = stage.args.len();
+      self.args(&stage.args);
+
+      match &func.kind {
+        ExprKind::Identifier(name) => {
+          let name = self.name(*name);
+          self.emit(Op::StageName { name, argc }, func.span);
+        }
+        _ => {
+          self.expression(func);
+          self.emit(Op::Stage { argc }, func.span);
+        }
+      }
+    }
+
+    self.emit(Op::Pipe(call.args.len()), span);
+  }
+}
+
+// expressions the tree-walker evaluates to a variable rather than a value
+fn is_reference(expr: &Expr) -> bool {
+  return matches!(
+    expr.kind,
+    ExprKind::Identifier(_) | ExprKind::Member { .. } | ExprKind::Index { .. }
+  );
+}
+
+/* =========================
+Disassembly
+========================= */
+
+impl fmt::Display for Chunk {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    for (at, op) in self.code.iter().enumerate() {
+      writeln!(f, "{:04}  {}", at, self.instruction(op))?;
+    }
+
+    return Ok(());
+  }
+}
+
+impl Chunk {
+  fn instruction(&self, op: &Op) -> String {
+    return match *op {
+      Op::Constant(n) => format!("constant {}", literal(&self.constants[n])),
+      Op::Load(n) => format!("load {}", self.name(n)),
+      Op::Declare(n) => format!("declare {}", self.name(n)),
+      Op::DeclareUndefined(n) => {
+        format!("declare_undefined {}", self.name(n))
+      }
+      Op::Store(n) => format!("store {}", self.name(n)),
+      Op::Binary(op) => format!("binary {}", op.as_str()),
+      Op::BinaryConstant { op, constant } => format!(
+        "binary_constant {} {}",
+        op.as_str(),
+        literal(&self.constants[constant])
+      ),
+      Op::Member(n) => format!("member {}", self.name(n)),
+      Op::Index { array, .. } => format!("index {}", self.name(array)),
+      Op::Array(n) => format!("array {}", n),
+      Op::Lambda(n) => {
+        let params: Vec<&str> =
+          self.lambdas[n].0.iter().map(|p| p.as_str()).collect();
+        format!("lambda ({})", params.join(", "))
+      }
+      Op::Call { argc, name } => {
+        format!("call {} {}", self.name(name), argc)
+      }
+      Op::CallName { name, argc } => {
+        format!("call_name {} {}", self.name(name), argc)
+      }
+      Op::Spawn { argc, name, .. } => {
+        format!("spawn {} {}", self.name(name), argc)
+      }
+      Op::SpawnName { name, argc, .. } => {
+        format!("spawn_name {} {}", self.name(name), argc)
+      }
+      Op::Stage { argc } => format!("stage {}", argc),
+      Op::StageName { name, argc } => {
+        format!("stage_name {} {}", self.name(name), argc)
+      }
+      Op::Pipe(n) => format!("pipe {}", n),
+      Op::Interface(n) => {
+        let interface = &self.interfaces[n];
+        format!("interface {} {}", interface.path, interface.module.name)
+      }
+      Op::Import(n) => format!("import {}", self.imports[n].path),
+      Op::ExitScope(n) => format!("exit_scope {}", n),
+      Op::Jump(n) => format!("jump {:04}", n),
+      Op::Branch(n) => format!("branch {:04}", n),
+      Op::JumpIfDefined { name, target } => {
+        format!("jump_if_defined {} {:04}", self.name(name), target)
+      }
+      Op::Next(n) => format!("next {:04}", n),
+      Op::Fail(failure) => format!("fail {:?}", failure),
+      Op::Undefined => "undefined".to_owned(),
+      Op::Pop => "pop".to_owned(),
+      Op::Result => "result".to_owned(),
+      Op::Await { .. } => "await".to_owned(),
+      Op::EnterScope => "enter_scope".to_owned(),
+      Op::Iterate => "iterate".to_owned(),
+      Op::EndIterate => "end_iterate".to_owned(),
+      Op::Return => "return".to_owned(),
+    };
+  }
+
+  // the constants, then the instructions under the lines of `source` they
+  // were compiled from, those jumped to marked with `>>`, e.g.
+  //
+  //   constants
+  //        0  "-1"
+  //
+  //        1  let files = ls('-1');
+  //          0000  constant "-1"
+  //          0001  call_name ls 1
+  //   ...
+  //       >> 0007  next 0012
+  pub fn listing(&self, source: &str) -> String {
+    let lines: Vec<&str> = source.lines().collect();
+    let targets: HashSet<usize> =
+      self.code.iter().filter_map(|op| op.target()).collect();
+
+    let mut out = String::new();
+    if !self.constants.is_empty() {
+      out.push_str("constants\n");
+      for (n, constant) in self.constants.iter().enumerate() {
+        out.push_str(&format!("{:>6}  {}\n", n, literal(constant)));
+      }
+      out.push('\n');
+    }
+
+    let mut row = None;
+    for (at, op) in self.code.iter().enumerate() {
+      let span_row = self.spans.get(at).map(|s| s.start_point.row);
+      if span_row.is_some() && span_row != row {
+        row = span_row;
+        let n = row.unwrap();
+        let text = lines.get(n).map_or("", |l| l.trim());
+        out.push_str(&format!("{:>6}  {}\n", n + 1, text));
+      }
+
+      let marker = if targets.contains(&at) { ">>" } else { "" };
+      out.push_str(&format!(
+        "{:>6} {:04}  {}\n",
+        marker,
+        at,
+        self.instruction(op)
+      ));
+    }
+
+    return out;
+  }
+}
+
+// a constant as written in the script, strings quoted
+fn literal(v: &Value) -> String {
+  return match v {
+    Value::SamString(s) => format!("{:?}", s.as_str()),
+    v => v.to_string(),
+  };
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::value::Number;
+  use tree_sitter::Point;
+
+  fn on_row(row: usize) -> Span {
+    let point = Point { row, column: 0 };
+    return Span {
+      start: 0,
+      end: 0,
+      start_point: point,
+      end_point: point,
+    };
+  }
+
+  #[test]
+  fn test_listing() {
+    // let a = 'x';
+    // if (a) { a = 1; }
+    let chunk = Chunk {
+      code: vec![
+        Op::Constant(0),
+        Op::Declare(0),
+        Op::Load(0),
+        Op::Branch(6),
+        Op::Constant(1),
+        Op::Store(0),
+        Op::Undefined,
+      ],
+      spans: [0, 0, 1, 1, 1, 1, 1].into_iter().map(on_row).collect(),
+      constants: vec![Value::from("x"), Value::SamNumber(Number::SamInt(1))],
+      names: vec!["a".into()],
+      ..Chunk::default()
+    };
+
+    assert_eq!(
+      chunk.listing("let a = 'x';\nif (a) { a = 1; }"),
+      "\
+constants
+     0  \"x\"
+     1  1
+
+     1  let a = 'x';
+       0000  constant \"x\"
+       0001  declare a
+     2  if (a) { a = 1; }
+       0002  load a
+       0003  branch 0006
+       0004  constant 1
+       0005  store a
+    >> 0006  undefined
+"
+    );
+  }
+}
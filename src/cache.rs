@@ -0,0 +1,231 @@
+#![allow(dead_code)]
+
+// Compiled scripts kept on disk. With `cache = true` in sam.toml, or
+// --cache, running `build.sam` on the vm backend writes the bytecode of its
+// program to `build.samc` next to it, or into `cache_dir`, and the next run
+// of the unchanged script loads that instead of parsing, checking, and
+// compiling it again. The file is replaced when the script, the settings
+// its compilation depends on, or the interpreter change.
+//
+// Scripts with warnings are not kept, so that their warnings are reported
+// on every run.
+
+use crate::ast;
+use crate::bytecode::Chunk;
+use crate::config::Config;
+use crate::diagnostic::Diagnostic;
+use crate::error::SamError;
+use crate::fold;
+use crate::prune;
+use crate::vm;
+use bincode::Options;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tree_sitter::Node;
+
+pub const EXTENSION: &str = "samc";
+
+// changed whenever the layout of the bytecode or of the syntax tree does
+const FORMAT: u32 = 2;
+
+// what the program of a cache file was compiled from, read before the
+// program itself so that files of other interpreters are never decoded
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Header {
+  format: u32,
+  version: String,
+  key: u64,
+}
+
+impl Header {
+  fn new(key: u64) -> Header {
+    return Header {
+      format: FORMAT,
+      version: env!("CARGO_PKG_VERSION").to_owned(),
+      key,
+    };
+  }
+}
+
+// where the compiled `script` is kept. In a shared directory the name
+// includes the script's path, so that scripts of the same name in
+// different directories do not replace each other's.
+pub fn path(script: &Path, config: &Config) -> PathBuf {
+  let Some(dir) = &config.cache_dir else {
+    return script.with_extension(EXTENSION);
+  };
+
+  let full = fs::canonicalize(script).unwrap_or_else(|_| script.to_owned());
+  let stem = script.file_stem().unwrap_or_default().to_string_lossy();
+
+  return dir.join(format!(
+    "{}-{:016x}.{}",
+    stem,
+    hash(full.to_string_lossy().as_bytes()),
+    EXTENSION
+  ));
+}
+
+// identifies the bytecode of `source`: the source, and the settings that
+// constant folding depends on
+pub fn key(source: &str, config: &Config) -> u64 {
+  let settings = format!("{:?} {:?}", config.strict, config.division_by_zero);
+
+  return hash(&[source.as_bytes(), b"\0", settings.as_bytes()].concat());
+}
+
+// the program kept at `path`, if it is there, compiled for `key`, and by
+// this interpreter
+pub fn load(path: &Path, key: u64) -> Option<Chunk> {
+  let bytes = fs::read(path).ok()?;
+  let mut reader = bytes.as_slice();
+
+  // a damaged file cannot claim more than it holds
+  let options = bincode::options().with_limit(bytes.len() as u64);
+
+  let header: Header = options.deserialize_from(&mut reader).ok()?;
+  if header != Header::new(key) {
+    return None;
+  }
+
+  return options.deserialize_from(&mut reader).ok();
+}
+
+// keep `chunk` at `path` for the next run. Failing to is not an error, the
+// script is only compiled again.
+pub fn store(path: &Path, key: u64, chunk: &Chunk) {
+  let mut bytes = match bincode::options().serialize(&Header::new(key)) {
+    Ok(bytes) => bytes,
+    Err(_) => return,
+  };
+  match bincode::options().serialize(chunk) {
+    Ok(program) => bytes.extend(program),
+    Err(_) => return,
+  }
+
+  if let Some(dir) = path.parent() {
+    let _ = fs::create_dir_all(dir);
+  }
+
+  // written aside and moved into place, so that a run at the same time
+  // never reads half a file
+  let partial =
+    path.with_extension(format!("{}.{}", EXTENSION, std::process::id()));
+  if fs::write(&partial, bytes).is_err() || fs::rename(&partial, path).is_err()
+  {
+    let _ = fs::remove_file(&partial);
+  }
+}
+
+// the bytecode the vm runs for the script of `root`, and the warnings
+// about the dead code left out of it
+pub fn compile(
+  root: Node,
+  source: &str,
+  config: &Config,
+) -> Result<(Chunk, Vec<Diagnostic>), SamError> {
+  let mut program = ast::lower(root, source.as_bytes())?;
+  fold::fold_program(&mut program.statements, config);
+  let warnings = prune::prune_program(&mut program.statements);
+
+  return Ok((vm::compile(&program.statements), warnings));
+}
+
+// FNV-1a, which unlike the hasher of std is the same in every build
+fn hash(bytes: &[u8]) -> u64 {
+  let mut h: u64 = 0xcbf29ce484222325;
+  for &b in bytes {
+    h ^= b as u64;
+    h = h.wrapping_mul(0x100000001b3);
+  }
+
+  return h;
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::ast::BinaryOp;
+  use crate::bytecode::Op;
+  use crate::config::DivisionPolicy;
+  use crate::error::Span;
+  use crate::value::Value;
+  use tree_sitter::Point;
+
+  fn chunk() -> Chunk {
+    let span = Span {
+      start: 3,
+      end: 8,
+      start_point: Point::new(0, 3),
+      end_point: Point::new(1, 2),
+    };
+
+    return Chunk {
+      code: vec![
+        Op::Constant(0),
+        Op::Constant(1),
+        Op::Binary(BinaryOp::Add),
+        Op::Result,
+      ],
+      spans: vec![span; 4],
+      constants: vec![Value::from(1.5), Value::from("text")],
+      names: vec!["total".into()],
+      ..Chunk::default()
+    };
+  }
+
+  #[test]
+  fn test_store_and_load() {
+    let dir =
+      std::env::temp_dir().join(format!("sam-cache-{}", std::process::id()));
+    let path = dir.join("script.samc");
+
+    let original = chunk();
+    store(&path, 7, &original);
+
+    let loaded = load(&path, 7).unwrap();
+    assert_eq!(loaded.code, original.code);
+    assert_eq!(loaded.spans, original.spans);
+    assert_eq!(loaded.constants, original.constants);
+    assert_eq!(loaded.name(0), "total");
+
+    // another source or a damaged file is not used
+    assert!(load(&path, 8).is_none());
+    fs::write(&path, b"not bytecode").unwrap();
+    assert!(load(&path, 7).is_none());
+
+    fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn test_key() {
+    let config = Config::default();
+    assert_eq!(key("let a = 1;", &config), key("let a = 1;", &config));
+    assert_ne!(key("let a = 1;", &config), key("let a = 2;", &config));
+
+    // folding `1 / 0` depends on the division policy
+    let other = Config {
+      division_by_zero: DivisionPolicy::Error,
+      ..Config::default()
+    };
+    assert_ne!(key("1 / 0;", &config), key("1 / 0;", &other));
+  }
+
+  #[test]
+  fn test_path() {
+    let script = Path::new("scripts/build.sam");
+    assert_eq!(
+      path(script, &Config::default()),
+      Path::new("scripts/build.samc")
+    );
+
+    let config = Config {
+      cache_dir: Some(PathBuf::from("cache")),
+      ..Config::default()
+    };
+    let other = Path::new("tools/build.sam");
+    assert_ne!(path(script, &config), path(other, &config));
+    assert!(path(script, &config).starts_with("cache"));
+  }
+}
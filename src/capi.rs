@@ -0,0 +1,213 @@
+#![allow(dead_code)]
+
+// C interface for embedding sam in programs not written in Rust. The header
+// `include/sam.h` is generated from this file with
+// `cbindgen --config cbindgen.toml --output include/sam.h`.
+//
+// Values cross the boundary as JSON text, the same representation used for
+// foreign functions, so hosts only need a JSON parser to read them.
+
+use crate::codes;
+use crate::error::SamError;
+use crate::ffi::FFI;
+use crate::interpreter::Interpreter;
+use crate::value::Value;
+use std::ffi::{CStr, CString, c_char};
+use std::ptr;
+
+/// An interpreter session, created by `sam_new` and freed by `sam_free`.
+pub struct SamInterpreter {
+  interpreter: Interpreter,
+  // message of the last call that failed, none after one that succeeded
+  error: Option<CString>,
+}
+
+impl SamInterpreter {
+  // the outcome of a call as the string handed to C, recording the error
+  fn finish(&mut self, result: Result<Value, SamError>) -> *mut c_char {
+    let json = result.and_then(|v| FFI::value_to_json(&v));
+
+    match json {
+      Ok(json) => {
+        self.error = None;
+        // JSON escapes NUL characters, so there are none in the text
+        return CString::new(json.to_string()).unwrap().into_raw();
+      }
+      Err(e) => {
+        self.error = Some(message(&e));
+        return ptr::null_mut();
+      }
+    }
+  }
+}
+
+// e.g. `error[E0001]: Variable x not defined (line 3)`
+fn message(err: &SamError) -> CString {
+  let mut message = match err.code() {
+    Some(code) => format!("error[{}]: {}", code, err),
+    None => format!("error: {}", err),
+  };
+
+  if let Some(span) = err.span() {
+    message.push_str(&format!(" (line {})", span.start_point.row + 1));
+  }
+
+  return CString::new(message.replace('\0', "\\0")).unwrap();
+}
+
+// borrow a C string, None if it is null or not UTF-8
+unsafe fn text<'a>(s: *const c_char) -> Option<&'a str> {
+  if s.is_null() {
+    return None;
+  }
+
+  return unsafe { CStr::from_ptr(s) }.to_str().ok();
+}
+
+/// Create an interpreter with the default settings.
+#[unsafe(no_mangle)]
+pub extern "C" fn sam_new() -> *mut SamInterpreter {
+  let sam = SamInterpreter {
+    interpreter: Interpreter::new(),
+    error: None,
+  };
+
+  return Box::into_raw(Box::new(sam));
+}
+
+/// Run `source` after everything evaluated before and return the value of
+/// its last statement as JSON, to be freed with `sam_string_free`. Returns
+/// NULL if it failed, `sam_error` tells why.
+///
+/// # Safety
+///
+/// `sam` must come from `sam_new` and `source` must be a NUL-terminated
+/// string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sam_eval(
+  sam: *mut SamInterpreter,
+  source: *const c_char,
+) -> *mut c_char {
+  let Some(sam) = (unsafe { sam.as_mut() }) else {
+    return ptr::null_mut();
+  };
+
+  let Some(source) = (unsafe { text(source) }) else {
+    sam.error = Some(c"error: source is NULL or not UTF-8".to_owned());
+    return ptr::null_mut();
+  };
+
+  let result = sam.interpreter.eval(source);
+  return sam.finish(result);
+}
+
+/// The value of the global variable `name` as JSON, to be freed with
+/// `sam_string_free`. Returns NULL if there is no such variable or its
+/// value has no JSON form, e.g. a function; `sam_error` tells which.
+///
+/// # Safety
+///
+/// `sam` must come from `sam_new` and `name` must be a NUL-terminated
+/// string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sam_get_var(
+  sam: *mut SamInterpreter,
+  name: *const c_char,
+) -> *mut c_char {
+  let Some(sam) = (unsafe { sam.as_mut() }) else {
+    return ptr::null_mut();
+  };
+
+  let Some(name) = (unsafe { text(name) }) else {
+    sam.error = Some(c"error: name is NULL or not UTF-8".to_owned());
+    return ptr::null_mut();
+  };
+
+  let result = match sam.interpreter.get_global(name) {
+    Some(value) => Ok(value.clone()),
+    None => Err(SamError::name(
+      codes::UNDEFINED_VARIABLE,
+      format!("Variable {} not defined", name),
+    )),
+  };
+
+  return sam.finish(result);
+}
+
+/// Why the last call on `sam` returned NULL, or NULL if it succeeded. The
+/// message belongs to `sam` and is valid until its next call.
+///
+/// # Safety
+///
+/// `sam` must come from `sam_new`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sam_error(
+  sam: *const SamInterpreter,
+) -> *const c_char {
+  let Some(sam) = (unsafe { sam.as_ref() }) else {
+    return ptr::null();
+  };
+
+  return sam.error.as_ref().map_or(ptr::null(), |e| e.as_ptr());
+}
+
+/// Free a string returned by `sam_eval` or `sam_get_var`. NULL is ignored.
+///
+/// # Safety
+///
+/// `s` must come from one of those functions and not be freed twice.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sam_string_free(s: *mut c_char) {
+  if !s.is_null() {
+    drop(unsafe { CString::from_raw(s) });
+  }
+}
+
+/// Free an interpreter and everything it holds. NULL is ignored.
+///
+/// # Safety
+///
+/// `sam` must come from `sam_new` and not be freed twice.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sam_free(sam: *mut SamInterpreter) {
+  if !sam.is_null() {
+    drop(unsafe { Box::from_raw(sam) });
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  // a string returned by the C interface, freed after reading it
+  unsafe fn take(s: *mut c_char) -> Option<String> {
+    if s.is_null() {
+      return None;
+    }
+
+    let text = unsafe { CStr::from_ptr(s) }.to_str().unwrap().to_owned();
+    unsafe { sam_string_free(s) };
+
+    return Some(text);
+  }
+
+  #[test]
+  fn test_eval_and_get_var() {
+    unsafe {
+      let sam = sam_new();
+
+      let result = sam_eval(sam, c"let a = [1, 'two']; a[0] + 1;".as_ptr());
+      assert_eq!(take(result).as_deref(), Some("2"));
+      assert!(sam_error(sam).is_null());
+
+      let a = sam_get_var(sam, c"a".as_ptr());
+      assert_eq!(take(a).as_deref(), Some("[1,\"two\"]"));
+
+      assert!(sam_get_var(sam, c"b".as_ptr()).is_null());
+      let error = CStr::from_ptr(sam_error(sam)).to_str().unwrap();
+      assert!(error.starts_with("error[E0001]"));
+
+      sam_free(sam);
+    }
+  }
+}
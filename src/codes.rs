@@ -0,0 +1,1121 @@
+// Stable diagnostic codes. Codes are never reused once published, so
+// tooling can match on them across releases.
+//
+//   E00xx  names
+//   E01xx  operators and types
+//   E02xx  values
+//   E03xx  calls and control flow
+//   E04xx  interfaces and external commands
+//   E05xx  syntax
+//   E06xx  configuration
+//   E07xx  modules and packages
+//   E08xx  compiling to other languages and minifying
+//   E09xx  internal errors
+//   W00xx  warnings
+
+pub const UNDEFINED_VARIABLE: &str = "E0001";
+pub const ASSIGN_UNDECLARED: &str = "E0002";
+pub const UNKNOWN_COMMAND: &str = "E0003";
+pub const USE_BEFORE_DECLARATION: &str = "E0004";
+pub const ASSIGN_TO_CONSTANT: &str = "E0005";
+
+pub const UNKNOWN_OPERATOR: &str = "E0101";
+pub const NOT_AN_OBJECT: &str = "E0102";
+pub const NON_INTEGER_CONDITION: &str = "E0103";
+pub const NOT_ITERABLE: &str = "E0104";
+pub const NOT_CALLABLE: &str = "E0105";
+pub const NON_INTEGER_INDEX: &str = "E0106";
+pub const NOT_INDEXABLE: &str = "E0107";
+pub const UNDEFINED_OPERAND: &str = "E0108";
+pub const OPERAND_MISMATCH: &str = "E0109";
+pub const NOT_SERIALIZABLE: &str = "E0110";
+pub const NOT_AWAITABLE: &str = "E0111";
+pub const ARGUMENT_TYPE: &str = "E0112";
+
+pub const INDEX_OUT_OF_BOUNDS: &str = "E0201";
+pub const INVALID_ESCAPE: &str = "E0202";
+pub const INVALID_NUMBER: &str = "E0203";
+pub const DIVISION_BY_ZERO: &str = "E0204";
+pub const EMPTY_RANGE: &str = "E0205";
+pub const INVALID_JSON: &str = "E0206";
+pub const INVALID_FORMAT: &str = "E0207";
+pub const EXIT_STATUS: &str = "E0208";
+pub const INVALID_CSV: &str = "E0209";
+pub const INVALID_ENCODING: &str = "E0210";
+
+pub const ARGUMENT_COUNT: &str = "E0301";
+pub const RETURN_OUTSIDE_FUNCTION: &str = "E0302";
+pub const RETURN_IN_EXPRESSION: &str = "E0303";
+pub const DUPLICATE_PARAMETER: &str = "E0304";
+pub const BREAK_OUTSIDE_LOOP: &str = "E0305";
+pub const AWAIT_FAILED: &str = "E0306";
+pub const LIMIT_EXCEEDED: &str = "E0307";
+pub const CANCELLED: &str = "E0308";
+pub const ASSERTION_FAILED: &str = "E0309";
+pub const SCRIPT_EXIT: &str = "E0310";
+
+pub const INTERFACE_READ: &str = "E0401";
+pub const INTERFACE_PARSE: &str = "E0402";
+pub const INTERFACE_ENTRY: &str = "E0403";
+pub const COMMAND_FAILED: &str = "E0404";
+pub const FOREIGN_OUTPUT: &str = "E0405";
+pub const TIMEOUT: &str = "E0406";
+pub const COMMAND_EXIT: &str = "E0407";
+pub const LIBRARY_LOAD: &str = "E0408";
+pub const REQUEST_FAILED: &str = "E0409";
+pub const PERMISSION_DENIED: &str = "E0410";
+pub const NOT_A_DIRECTORY: &str = "E0411";
+pub const HOST_FUNCTION_FAILED: &str = "E0412";
+pub const INPUT_FAILED: &str = "E0413";
+
+pub const UNEXPECTED_SYNTAX: &str = "E0501";
+pub const MISSING_SYNTAX: &str = "E0502";
+pub const MALFORMED_TREE: &str = "E0503";
+pub const NOT_AN_EXPRESSION: &str = "E0504";
+pub const INVALID_PATTERN: &str = "E0505";
+
+pub const CONFIG_INVALID: &str = "E0601";
+
+pub const MODULE_NOT_FOUND: &str = "E0701";
+pub const MODULE_FAILED: &str = "E0702";
+pub const IMPORT_CYCLE: &str = "E0703";
+pub const DEPENDENCY_FETCH: &str = "E0704";
+
+pub const NOT_COMPILABLE: &str = "E0801";
+pub const NOT_SHORTENABLE: &str = "E0802";
+
+pub const INTERNAL_ERROR: &str = "E0901";
+
+pub const UNUSED_VARIABLE: &str = "W0001";
+pub const UNUSED_PARAMETER: &str = "W0002";
+pub const UNUSED_INTERFACE: &str = "W0003";
+pub const SHADOWED_NAME: &str = "W0004";
+pub const UNREACHABLE: &str = "W0005";
+pub const IMPLICIT_DECLARATION: &str = "W0006";
+pub const DEAD_BRANCH: &str = "W0007";
+pub const CONSTANT_CONDITION: &str = "W0008";
+pub const MISMATCHED_COMPARISON: &str = "W0009";
+pub const UNQUOTED_ARGUMENT: &str = "W0010";
+
+pub struct Explanation {
+  pub code: &'static str,
+  pub title: &'static str,
+  pub text: &'static str,
+}
+
+// extended descriptions printed by `sam explain`
+pub const CATALOG: &[Explanation] = &[
+  Explanation {
+    code: UNDEFINED_VARIABLE,
+    title: "undefined variable",
+    text: "\
+A variable was read before it was declared in any visible scope.
+
+    let a = b + 1;   // error: b is not declared
+
+Declare the variable with `let` first:
+
+    let b = 1;
+    let a = b + 1;
+",
+  },
+  Explanation {
+    code: ASSIGN_UNDECLARED,
+    title: "assignment to an undeclared variable",
+    text: "\
+Assignment only updates existing variables, it never creates them.
+
+    total = 0;       // error: total is not declared
+
+Declare the variable instead:
+
+    let total = 0;
+
+With `--auto-declare`, `auto_declare = true` in sam.toml, or in the REPL,
+such assignments declare the variable instead (see W0006).
+",
+  },
+  Explanation {
+    code: UNKNOWN_COMMAND,
+    title: "unknown function or command",
+    text: "\
+A call named something that is neither a sam variable, an interface entry,
+nor a program on the PATH.
+
+    let out = lss();  // error: no variable or program named lss
+
+Check the spelling, declare the function, or install the program.
+",
+  },
+  Explanation {
+    code: USE_BEFORE_DECLARATION,
+    title: "variable used before its declaration",
+    text: "\
+A variable is read before the statement that declares it. This is reported
+before the script runs.
+
+    let a = b + 1;  // error: b is declared on the next line
+    let b = 1;
+
+Function bodies are exempt, because they run when called:
+
+    let f = () => { return b; };  // ok
+    let b = 1;
+",
+  },
+  Explanation {
+    code: ASSIGN_TO_CONSTANT,
+    title: "assignment to a constant",
+    text: "\
+A variable declared with `const` cannot be assigned to.
+
+    const limit = 10;
+    limit = 20;  // error
+
+Declare it with `let` if it needs to change.
+",
+  },
+  Explanation {
+    code: UNKNOWN_OPERATOR,
+    title: "unknown operator",
+    text: "\
+A binary expression used an operator sam does not support. The supported
+operators are + - * / // % < > <= >= == != && ||.
+
+    let a = 1 <> 2;  // error
+
+Use one of the supported operators:
+
+    let a = 1 != 2;
+",
+  },
+  Explanation {
+    code: NOT_AN_OBJECT,
+    title: "property access on a non-object",
+    text: "\
+Only objects have properties, such as the result of a shell command.
+
+    let n = 5;
+    let s = n.stdout;  // error: n is a number
+
+    let r = ls();
+    let s = r.stdout;  // ok
+",
+  },
+  Explanation {
+    code: NON_INTEGER_CONDITION,
+    title: "condition is not an integer",
+    text: "\
+Conditions of `if` must evaluate to an integer, where 0 is false and any
+other integer is true.
+
+    if ('yes') { ... }  // error
+
+    if (1 == 1) { ... } // ok
+",
+  },
+  Explanation {
+    code: NOT_ITERABLE,
+    title: "for loop over a non-array",
+    text: "\
+`for` loops iterate over arrays, and over the lines of foreign functions
+declared with \"stream\": true.
+
+    for x in 5 { ... }          // error
+
+    for x in [1, 2, 3] { ... }  // ok
+
+    // { \"logs\": { \"command\": \"tail -f app.log\", \"stream\": true } }
+    for line in logs() { ... }  // ok, one line at a time
+",
+  },
+  Explanation {
+    code: NOT_CALLABLE,
+    title: "call of a non-function",
+    text: "\
+A variable that does not hold a function was called.
+
+    let a = 1;
+    let b = a();  // error: a is a number
+
+The arguments of `pipe` must be calls of programs or foreign functions,
+whose output is passed along instead of being returned:
+
+    let n = pipe(ls(), grep('foo'), count());
+",
+  },
+  Explanation {
+    code: NON_INTEGER_INDEX,
+    title: "array index is not an integer",
+    text: "\
+Arrays are indexed by integers.
+
+    let a = [1, 2];
+    let b = a['0'];  // error
+    let c = a[0];    // ok
+",
+  },
+  Explanation {
+    code: NOT_INDEXABLE,
+    title: "indexing a non-array",
+    text: "\
+Only arrays can be indexed with `[...]`.
+
+    let a = 5;
+    let b = a[0];  // error: a is a number
+",
+  },
+  Explanation {
+    code: UNDEFINED_OPERAND,
+    title: "operation on undefined (strict mode)",
+    text: "\
+In strict mode an operator was applied to `undefined`, for example the
+result of reading a missing property.
+
+    let r = ls();
+    let n = r.lines + 1;  // error: r has no property lines
+
+Outside strict mode the result would silently be undefined as well. Strict
+mode is enabled with `--strict` or `strict = true` in sam.toml.
+",
+  },
+  Explanation {
+    code: OPERAND_MISMATCH,
+    title: "operand types do not match (strict mode)",
+    text: "\
+In strict mode an operator was applied to values of types it does not
+support together.
+
+    let a = 'count: ' + 1;  // error: string and number
+    let b = 'count: ' + '1'; // ok
+
+`+` accepts two numbers or two strings, comparisons accept two numbers or
+two strings, and the other arithmetic and logical operators accept numbers
+only. `==` and `!=` accept any two values.
+",
+  },
+  Explanation {
+    code: NOT_SERIALIZABLE,
+    title: "value cannot be converted to JSON",
+    text: "\
+Functions have no JSON representation, so they cannot be passed to a
+foreign function that takes its arguments as JSON on stdin, nor to
+`json_stringify`.
+
+    let f = (x) => { return x; };
+    let r = tool(f);  // error if tool uses \"input\": \"json\"
+    json_stringify(f)  // error
+",
+  },
+  Explanation {
+    code: NOT_AWAITABLE,
+    title: "await of a non-handle",
+    text: "\
+`await` waits for a call started with `spawn`, any other value is an error.
+
+    let h = spawn(ls());
+    let r = await(h);  // ok
+    let s = await(r);  // error: r is the result object, not a handle
+",
+  },
+  Explanation {
+    code: ARGUMENT_TYPE,
+    title: "argument of the wrong type",
+    text: "\
+A function declaring the types of its parameters was called with a value
+of another type. Library functions take integers for `i64`, any number for
+`f64`, and strings without NUL bytes for `str`.
+
+    // { \"cos\": { \"library\": \"libm.so.6\", \"params\": [\"f64\"],
+    //             \"returns\": \"f64\" } }
+    cos(\"0\");   // error
+    cos(0);     // ok
+
+Command entries declare `any`, `number`, `int`, `string`, `array`, or
+`object`, optionally naming each parameter, and may declare the type of
+the result they print:
+
+    { \"resize\": { \"command\": \"./resize.py\",
+                  \"params\": [\"string\", { \"name\": \"width\", \"type\": \"int\" }],
+                  \"returns\": \"object\" } }
+",
+  },
+  Explanation {
+    code: INDEX_OUT_OF_BOUNDS,
+    title: "array index out of bounds",
+    text: "\
+An index was negative or not smaller than the length of the array.
+
+    let a = [1, 2];
+    let b = a[2];  // error: valid indices are 0 and 1
+",
+  },
+  Explanation {
+    code: INVALID_ESCAPE,
+    title: "invalid escape sequence",
+    text: "\
+A string contains an escape sequence sam does not understand, or one that
+does not encode a valid character.
+
+    let a = 'bad \\q';     // error: unknown escape
+    let b = '\\u{110000}'; // error: not a unicode scalar value
+
+Supported escapes are \\n \\r \\t \\\\ \\' \\\", octal \\123, hex \\x41, and
+unicode \\u{1F600}.
+",
+  },
+  Explanation {
+    code: INVALID_NUMBER,
+    title: "invalid number literal",
+    text: "\
+A number literal could not be represented, usually because an integer does
+not fit in 64 bits.
+
+    let a = 99999999999999999999;  // error
+",
+  },
+  Explanation {
+    code: DIVISION_BY_ZERO,
+    title: "division by zero",
+    text: "\
+The divisor of `/`, `%`, or `floor_div` was zero while the division policy
+is set to `error` in sam.toml:
+
+    division_by_zero = 'error'
+
+    let a = 1 / 0;  // error
+
+The other policies are `undefined`, the default, which makes the result
+undefined, and `ieee`, which follows floating point rules and gives
+infinity, or NaN for `0 / 0` and `%`.
+",
+  },
+  Explanation {
+    code: EMPTY_RANGE,
+    title: "nothing to draw from",
+    text: "\
+A builtin was given nothing to take its result from: `rand_int` bounds
+with no integer between them, the first being above the second, or an
+empty array for `reduce` without a value to start from.
+
+    rand_int(1, 6)                  // ok, 1 to 6
+    rand_int(6, 1)                  // error
+    reduce([], add)                 // error
+    reduce([], add, 0)              // ok, 0
+",
+  },
+  Explanation {
+    code: INVALID_JSON,
+    title: "invalid JSON",
+    text: "\
+The string given to `json_parse` is not a single JSON value. The message
+tells the line and column where parsing stopped.
+
+    json_parse('{\"a\": 1}')          // ok, an object
+    json_parse('{a: 1}')              // error, keys are quoted in JSON
+",
+  },
+  Explanation {
+    code: INVALID_FORMAT,
+    title: "invalid format string",
+    text: "\
+A format string could not be read. `format` takes placeholders in braces,
+`{}` or `{:>8.2}` with a fill, alignment, sign, width, precision, and
+base, and `{{` and `}}` for literal braces. `format_time` knows %Y, %m,
+%d, %H, %M, %S, %f for microseconds, and %% for a percent sign.
+
+    format('{:>8}', name)             // ok
+    format('{:q}', name)              // error
+    format_time(now(), '%d/%m/%Y')    // ok
+    format_time(now(), '%A')          // error
+",
+  },
+  Explanation {
+    code: EXIT_STATUS,
+    title: "exit status out of range",
+    text: "\
+`exit` was given a status that is not an integer from 0 to 255, the ones
+a process can exit with.
+
+    exit(2)    // ok
+    exit(256)  // error
+",
+  },
+  Explanation {
+    code: INVALID_CSV,
+    title: "invalid CSV",
+    text: "\
+The text given to `csv_parse` has a quoted field that is never closed, or
+text after the closing quote of a field, or, with `headers`, a row with
+more or fewer fields than the header. Quotes inside a quoted field are
+doubled.
+
+    csv_parse('a,\"b\"\"c\"')              // ok, a and b\"c
+    csv_parse('a,\"b')                   // error
+    csv_parse('x,y\\n1', {headers: 1})  // error, 1 field for 2
+",
+  },
+  Explanation {
+    code: INVALID_ENCODING,
+    title: "invalid encoding",
+    text: "\
+The text given to `base64_decode`, `hex_decode`, or `url_decode` is not
+in that encoding: a character outside its alphabet, an odd number of
+hexadecimal digits, a `%` without two hexadecimal digits after it, or
+base64 that ends part way through a byte.
+
+    hex_decode('ff00')      // ok
+    hex_decode('f00')       // error, 3 digits
+    url_decode('100%25')    // ok, 100%
+    url_decode('100%')      // error
+",
+  },
+  Explanation {
+    code: ARGUMENT_COUNT,
+    title: "wrong number of arguments",
+    text: "\
+A function was called with a different number of arguments than it has
+parameters.
+
+    let f = (x, y) => { return x + y; };
+    let a = f(1);     // error: expected 2 arguments
+    let b = f(1, 2);  // ok
+",
+  },
+  Explanation {
+    code: RETURN_OUTSIDE_FUNCTION,
+    title: "return outside of a function",
+    text: "\
+`return` can only be used inside a function body.
+
+    return 5;  // error at the top level
+",
+  },
+  Explanation {
+    code: RETURN_IN_EXPRESSION,
+    title: "return used as a value",
+    text: "\
+A `return` was reached while evaluating an argument or array element, where
+a value was expected instead.
+",
+  },
+  Explanation {
+    code: DUPLICATE_PARAMETER,
+    title: "duplicate parameter name",
+    text: "\
+A function declares the same parameter name twice, so the first one could
+never be read.
+
+    let f = (x, x) => { return x; };  // error
+",
+  },
+  Explanation {
+    code: BREAK_OUTSIDE_LOOP,
+    title: "break or continue outside of a loop",
+    text: "\
+`break` and `continue` can only be used inside the body of a `for` loop. A
+function body starts outside of any loop, even if the function is defined
+in one.
+
+    break;  // error
+
+    for x in [1, 2, 3] {
+      if (x == 2) { break; }  // ok
+    }
+",
+  },
+  Explanation {
+    code: AWAIT_FAILED,
+    title: "await of a failed call",
+    text: "\
+A handle whose call failed was awaited again. The error of the call is
+reported by the first `await` only.
+
+    let h = spawn(missing_tool());
+    let a = await(h);  // error: missing_tool is not a command
+    let b = await(h);  // error E0306
+",
+  },
+  Explanation {
+    code: LIMIT_EXCEEDED,
+    title: "resource limit exceeded",
+    text: "\
+The script did more work than whoever runs it allows, and was stopped.
+Limits are off unless set with `--max-steps` on the command line or by the
+program embedding sam, which can also bound the iterations of a single
+`for` loop, how deep function calls may nest, and the size of strings,
+arrays, and objects. Builtins such as `pad` and `join` never make a string
+or array of more than 2^30 bytes or elements unless a larger size is
+allowed.
+
+    // sam --max-steps=1000 script.sam
+    for line in logs() { work(line); }  // error after 1000 steps
+",
+  },
+  Explanation {
+    code: CANCELLED,
+    title: "evaluation was interrupted",
+    text: "\
+The program running the script asked it to stop, e.g. because its user
+pressed a cancel button. The script stops before its next statement or
+expression, and a command it was waiting for is killed.
+
+    let report = long_running_job();  // error E0308 once interrupted
+",
+  },
+  Explanation {
+    code: ASSERTION_FAILED,
+    title: "assertion failed",
+    text: "\
+A condition given to `assert` did not hold, or the values given to
+`assert_eq` differ. Assertions state what a script expects, and are how
+the tests run by `sam test` fail.
+
+    assert(count > 0, 'no files found');  // error E0309 if count is 0
+    assert_eq(add(2, 2), 5);              // error E0309: 4 != 5
+",
+  },
+  Explanation {
+    code: SCRIPT_EXIT,
+    title: "script exited",
+    text: "\
+The script called `exit`. `sam run` and the REPL end with its status
+without reporting an error; the error is seen where a script is not the
+whole process, e.g. a test run by `sam test` or a script run by a host
+program, whose caller decides what exiting means.
+
+    if (len(args) == 0) {
+      println('nothing to do');
+      exit(0);
+    }
+",
+  },
+  Explanation {
+    code: INTERFACE_READ,
+    title: "interface file could not be read",
+    text: "\
+The file named in an `interface` declaration does not exist or is not
+readable.
+
+    interface 'missing.json' load tool;  // error
+
+Relative paths are tried as written first, then in each directory of the
+search path: those given with `--interface DIR`, then `interface_path` in
+sam.toml, then SAM_PATH. The first existing file wins.
+
+    # sam.toml
+    interface_path = [\"interfaces\", \"/usr/local/share/sam\"]
+",
+  },
+  Explanation {
+    code: INTERFACE_PARSE,
+    title: "interface file is not valid",
+    text: "\
+The interface file could not be parsed. Interface files map entry names to
+commands, in JSON, or in TOML or YAML for files ending in .toml, .yaml, or
+.yml:
+
+    { \"tool\": \"python3 tool.py\" }    // tools.json
+    tool = \"python3 tool.py\"           # tools.toml
+    tool: python3 tool.py              # tools.yaml
+",
+  },
+  Explanation {
+    code: INTERFACE_ENTRY,
+    title: "invalid interface entry",
+    text: "\
+The entry loaded from an interface file is missing, is not a command, or
+its command cannot be split into words because of an unterminated quote.
+
+    interface 'tools.json' load tool;  // tools.json has no \"tool\" key
+
+Every entry of the file is checked when it is loaded, and every problem is
+listed with its line: unknown keys, values of the wrong type, and names
+defined twice.
+
+    tools.json:3: unknown key `comand` in `tool`, did you mean `command`?
+
+Entries are a command string, run directly with the call arguments appended
+as separate words, or an object opting in to the shell:
+
+    { \"tool\": \"python3 'my tool.py'\" }
+    { \"tool\": { \"command\": \"jq . | head\", \"shell\": true } }
+
+The shell is `sh` on unix and `cmd` on Windows. Commands without the shell
+are split the same way on every platform, so they are the portable choice.
+
+With \"input\": \"json\" the arguments are written to stdin as one JSON
+array instead of being passed on the command line; with \"input\": \"bytes\"
+the first argument is written to stdin as is. \"output\": \"bytes\" returns
+stdout unparsed, as bytes, for commands like `tar` or image tools.
+
+\"env\" maps variable names to string values set for the command, and
+\"clear_env\": true drops every other inherited variable:
+
+    { \"deploy\": { \"command\": \"./deploy.sh\", \"env\": { \"STAGE\": \"prod\" } } }
+
+\"stderr\" is \"capture\" to keep the command's stderr for error messages,
+the default, \"inherit\" to show it as it is written, e.g. progress output,
+or \"merge\" to mix it into stdout. `stderr = \"inherit\"` in sam.toml sets
+the default for every call.
+
+With \"cache\": true a call with the same arguments as an earlier one
+returns the earlier result without running the command again; with
+\"cache\": { \"ttl\": 60 } results are reused for 60 seconds only.
+
+\"callbacks\" lists the sam functions the command may call while it runs.
+It writes JSON-RPC 2.0 requests to stdout, one per line, and reads each
+response from a line of stdin; every other line is its output:
+
+    { \"plugin\": { \"command\": \"./plugin\", \"callbacks\": [\"log\"] } }
+    // the plugin writes
+    // {\"jsonrpc\": \"2.0\", \"id\": 1, \"method\": \"log\", \"params\": [\"hi\"]}
+
+With \"persistent\": true the command is started once and kept running.
+Each call writes its arguments to stdin as a line with a JSON array, and
+the next line the command prints is the result:
+
+    { \"tag\": { \"command\": \"python3 -u tagger.py\", \"persistent\": true } }
+",
+  },
+  Explanation {
+    code: COMMAND_FAILED,
+    title: "external command could not be run",
+    text: "\
+The operating system refused to start the command of a shell call or
+foreign function, for example because of missing permissions.
+",
+  },
+  Explanation {
+    code: FOREIGN_OUTPUT,
+    title: "foreign function returned invalid output",
+    text: "\
+Foreign functions must print a single JSON value on stdout, which becomes
+the result of the call.
+
+    { \"tool\": \"echo not json\" }   // error when tool() is called
+    { \"tool\": \"echo 42\" }         // ok, returns 42
+
+Entries declaring a result type with \"returns\" must print a value of
+that type.
+",
+  },
+  Explanation {
+    code: TIMEOUT,
+    title: "external command timed out",
+    text: "\
+A shell command or foreign function ran longer than its time limit and was
+killed. The default limit for every call is set with `--timeout 30` or
+`timeout = 30` in sam.toml, in seconds; interface entries can override it:
+
+    { \"tool\": { \"command\": \"python3 tool.py\", \"timeout\": 2.5 } }
+
+Without a limit calls may run forever.
+",
+  },
+  Explanation {
+    code: COMMAND_EXIT,
+    title: "foreign function failed",
+    text: "\
+The command of a foreign function exited with a non-zero status. Its stderr,
+if any, is shown as the cause; its stdout is not parsed.
+
+    { \"tool\": \"python3 missing.py\" }   // error when tool() is called
+
+To handle failures in the script instead, have calls return an object with
+`ok`, `status`, `stdout` and `stderr`, either for one entry or for all of
+them with `exit_status = \"result\"` in sam.toml:
+
+    { \"tool\": { \"command\": \"python3 tool.py\", \"exit_status\": \"result\" } }
+    let r = tool();
+    if r.ok { r.stdout } else { r.stderr }
+",
+  },
+  Explanation {
+    code: LIBRARY_LOAD,
+    title: "shared library could not be loaded",
+    text: "\
+An interface entry names a shared library that could not be opened, or a
+symbol the library does not export. Library paths containing a slash are
+relative to the interface file, bare names are searched like the system
+loader does.
+
+    { \"add\": { \"library\": \"./libmath.so\", \"symbol\": \"add\",
+               \"params\": [\"i64\", \"i64\"], \"returns\": \"i64\" } }
+",
+  },
+  Explanation {
+    code: REQUEST_FAILED,
+    title: "HTTP request failed",
+    text: "\
+A foreign function backed by an HTTP endpoint could not reach the server,
+or the server answered with an error status. The response body, if any, is
+shown as the cause.
+
+    { \"predict\": { \"url\": \"http://localhost:8080/predict\" } }
+
+Calls POST their arguments as one JSON array and expect a JSON response.
+
+`http_get` and `http_post` fail only when there is no response: an error
+status is theirs to return, in the `status` of the response.
+",
+  },
+  Explanation {
+    code: PERMISSION_DENIED,
+    title: "not allowed by the sandbox",
+    text: "\
+The script was run with `--sandbox` or an `--allow-*` flag and tried to do
+something it was not granted. Each kind of access is granted separately,
+either entirely or for a list of programs, paths, or hosts:
+
+    sam --allow-run=git,jq script.sam          // only git and jq
+    sam --allow-read=./interfaces script.sam   // only files below it
+    sam --allow-net=localhost script.sam       // only localhost
+
+Foreign functions with \"shell\": true run `sh`, which can run anything,
+so they need `sh` to be allowed. Loading a shared library counts as
+running it.
+
+Unless every program may run, sam.toml and interface files cannot set
+environment variables for external calls, as PATH or LD_PRELOAD could make
+an allowed program run another. External calls only run in directories the
+script may read, whether set with `chdir` or an interface's \"cwd\".
+",
+  },
+  Explanation {
+    code: NOT_A_DIRECTORY,
+    title: "working directory does not exist",
+    text: "\
+`chdir` was given a path that is not an existing directory. Relative paths
+start from the current working directory, which `cwd()` returns.
+
+    chdir('biuld');  // error: no such directory
+    chdir('build');  // ok, external calls now run in build
+
+Interface entries can set their own directory with \"cwd\", relative to
+the interface file.
+",
+  },
+  Explanation {
+    code: HOST_FUNCTION_FAILED,
+    title: "function of the embedding program failed",
+    text: "\
+A function provided by the program running sam, rather than by the script
+or an interface file, failed. From Python this is a registered callable
+that raised an exception; the message includes the exception.
+
+    # s.register(\"parse\", int)
+    parse('12');   // ok, 12
+    parse('abc');  // error: Python function `parse` raised ValueError
+",
+  },
+  Explanation {
+    code: INPUT_FAILED,
+    title: "input could not be read",
+    text: "\
+`input` could not read a line of the script's input, e.g. because it is not
+valid UTF-8 or the terminal went away. The end of the input is not an
+error: `input` is then undefined.
+
+    let name = input('name? ');   // ok, 'sam' once the user typed sam
+",
+  },
+  Explanation {
+    code: UNEXPECTED_SYNTAX,
+    title: "unexpected syntax",
+    text: "\
+The parser found text it could not make sense of. Every error of this kind
+is reported before the script runs.
+
+    let a = 1 +;  // error: the expression is incomplete
+",
+  },
+  Explanation {
+    code: MISSING_SYNTAX,
+    title: "missing syntax",
+    text: "\
+The parser expected a token, usually a closing bracket or a semicolon, and
+inserted it to keep going.
+
+    let a = [1, 2;  // error: missing `]`
+",
+  },
+  Explanation {
+    code: MALFORMED_TREE,
+    title: "malformed syntax tree",
+    text: "\
+The syntax tree had a shape the interpreter does not expect. This usually
+means the grammar and the interpreter are out of sync; please report it.
+",
+  },
+  Explanation {
+    code: NOT_AN_EXPRESSION,
+    title: "not an expression",
+    text: "\
+A program embedding sam asked for the value of a single expression, but the
+text was a statement, several statements, or empty.
+
+    a + b * 2       // ok
+    let c = a + b   // error: a declaration is not an expression
+    a; b            // error: two expressions
+",
+  },
+  Explanation {
+    code: INVALID_PATTERN,
+    title: "invalid search pattern",
+    text: "\
+The pattern given to `sam query` could not be used. A pattern is a single
+statement or expression of sam, with `$name` standing for any expression,
+or with `--tree-sitter` a query over the node kinds of the grammar that
+captures the nodes to report.
+
+    sam query 'chdir($dir)'                         // ok
+    sam query 'let x ='                             // error: not valid sam
+    sam query --tree-sitter '(identifier)'          // error: no capture
+    sam query --tree-sitter '(identifier) @name'    // ok
+",
+  },
+  Explanation {
+    code: CONFIG_INVALID,
+    title: "invalid configuration",
+    text: "\
+The sam.toml of the script, next to it or in a directory above it, could
+not be read or contains an unknown setting or a value of the wrong type.
+
+    strict = \"yes\"  # error: expected a boolean
+    strict = true   # ok
+",
+  },
+  Explanation {
+    code: MODULE_NOT_FOUND,
+    title: "module not found",
+    text: "\
+An imported module does not exist. Paths starting with `./` or `../` are
+relative to the importing script, others to the source root of the project,
+`root` in the `[package]` table of sam.toml, and then to the importing
+script. A path starting with the name of a dependency is looked up in that
+dependency:
+
+    import('util/paths');   // src/util/paths.sam with root = \"src\"
+    import('./helpers');    // helpers.sam next to the script
+    import('http/client');  // client.sam of the dependency `http`
+
+Git dependencies must be fetched with `sam vendor` before they are
+imported.
+",
+  },
+  Explanation {
+    code: MODULE_FAILED,
+    title: "module failed to load",
+    text: "\
+An imported module could not be run to the end. The message gives the
+error of the module and where in it the error happened; run the module on
+its own to see the full report.
+",
+  },
+  Explanation {
+    code: IMPORT_CYCLE,
+    title: "import cycle",
+    text: "\
+A module imports itself, directly or through other modules. Each module
+runs once, before anything that imports it, so modules cannot import each
+other. Move what both need into a third module they both import.
+",
+  },
+  Explanation {
+    code: DEPENDENCY_FETCH,
+    title: "dependency could not be fetched",
+    text: "\
+`sam vendor` could not copy a path dependency or clone a git dependency
+into the vendor directory. Check that the path exists, that the repository
+can be cloned with `git clone`, and that `rev` names a branch, tag, or
+commit of it:
+
+    [dependencies]
+    http = { git = \"https://example.com/sam-http.git\", rev = \"v1.2.0\" }
+",
+  },
+  Explanation {
+    code: NOT_COMPILABLE,
+    title: "cannot be compiled",
+    text: "\
+`sam compile` met something the target language has no equivalent for:
+
+    import('util/paths');              // imports
+    let kind = if (a) { return 1; };   // `if` and `for` used as values
+    let c = lazy(() => { return 1; }); // lazy values
+
+Interface entries must be plain commands, without options other than
+`shell`, `params`, and `returns`. Rewrite the script without them, or run
+it with sam.
+",
+  },
+  Explanation {
+    code: NOT_SHORTENABLE,
+    title: "names cannot be shortened",
+    text: "\
+`sam minify --shorten` renames every variable the script declares, the
+same way everywhere, since functions see the names of whoever calls them.
+The names an interface or an imported module defines are not in the
+script, so a short name could end up hiding one of them:
+
+    interface 'tools.json' load tool;
+    import('util/paths');
+
+Minify such a script without `--shorten`.
+",
+  },
+  Explanation {
+    code: INTERNAL_ERROR,
+    title: "internal interpreter error",
+    text: "\
+The interpreter crashed while evaluating the script. This is a bug in sam,
+not in the script. The report points at the code that was being evaluated
+and includes a backtrace; please attach both when reporting the issue.
+",
+  },
+  Explanation {
+    code: UNUSED_VARIABLE,
+    title: "unused variable",
+    text: "\
+A variable is declared but never read.
+
+    let a = 1;  // warning: a is never used
+
+Prefix the name with an underscore to keep it without a warning:
+
+    let _a = ls();
+",
+  },
+  Explanation {
+    code: UNUSED_PARAMETER,
+    title: "unused parameter",
+    text: "\
+A function parameter is never read in the function body.
+
+    let f = (x, y) => { return x; };  // warning: y is never used
+",
+  },
+  Explanation {
+    code: UNUSED_INTERFACE,
+    title: "unused interface entry",
+    text: "\
+An entry is loaded from an interface file but never called.
+
+    interface 'tools.json' load tool;  // warning if tool() is never called
+",
+  },
+  Explanation {
+    code: SHADOWED_NAME,
+    title: "shadowed name",
+    text: "\
+A declaration hides a variable of the same name from an enclosing scope,
+which makes the outer variable unreachable inside the block.
+
+    let x = 1;
+    let f = () => { let x = 2; return x; };  // warning
+",
+  },
+  Explanation {
+    code: UNREACHABLE,
+    title: "unreachable statement",
+    text: "\
+A statement follows a `return`, `break`, or `continue` in the same block
+and can never run. It is left out of the program.
+
+    let f = () => { return 1; ls(); };  // warning: ls() never runs
+",
+  },
+  Explanation {
+    code: IMPLICIT_DECLARATION,
+    title: "implicit declaration",
+    text: "\
+An assignment declared a new variable because auto-declaration is enabled,
+which is the default in the REPL.
+
+    total = 0;  // warning: total is declared implicitly
+
+Declare variables with `let` to make the intent explicit:
+
+    let total = 0;
+",
+  },
+  Explanation {
+    code: DEAD_BRANCH,
+    title: "dead branch",
+    text: "\
+The condition of an `if` is the same on every run, after constant folding,
+so one of its branches can never run. The branch is left out of the
+program.
+
+    if (0) { ls(); }             // warning: ls() never runs
+    if (2 > 1) { a(); } else { b(); }  // warning: b() never runs
+
+Remove the branch, or the `if` around the code that does run.
+",
+  },
+  Explanation {
+    code: CONSTANT_CONDITION,
+    title: "constant condition",
+    text: "\
+Reported by `sam lint`: the condition of an `if` is the same on every run,
+after constant folding, so testing it is pointless and often a leftover of
+debugging.
+
+    if (1) { deploy(); }       // warning
+    if (2 * 0) { cleanup(); }  // warning
+
+Remove the `if`, or test what was meant to be tested.
+",
+  },
+  Explanation {
+    code: MISMATCHED_COMPARISON,
+    title: "comparison of mismatched types",
+    text: "\
+Reported by `sam lint`: `==` or `!=` compares values that are of different
+types, e.g. a number and a string. Values of different types are never
+equal, so the comparison always has the same result.
+
+    if (count == '3') { ... }  // warning: always false
+
+Compare values of the same type, e.g. `count == 3`.
+",
+  },
+  Explanation {
+    code: UNQUOTED_ARGUMENT,
+    title: "unquoted argument",
+    text: "\
+Reported by `sam lint`: an argument of an external command is a name that
+is not declared anywhere in the script. Arguments are values, not words
+of a shell command line, so the name fails as an undefined variable when
+the command runs.
+
+    grep(needle, 'notes.txt');    // warning: needle is not a variable
+    grep('needle', 'notes.txt');  // the word needle
+
+Quote the argument, or declare the variable.
+",
+  },
+];
+
+pub fn explain(code: &str) -> Option<&'static Explanation> {
+  return CATALOG.iter().find(|e| e.code.eq_ignore_ascii_case(code));
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::collections::HashSet;
+
+  #[test]
+  fn test_codes_are_unique() {
+    let codes: HashSet<_> = CATALOG.iter().map(|e| e.code).collect();
+    assert_eq!(codes.len(), CATALOG.len());
+  }
+
+  #[test]
+  fn test_explain_lookup() {
+    assert_eq!(explain("e0001").unwrap().title, "undefined variable");
+    assert!(explain("E9999").is_none());
+  }
+}
@@ -0,0 +1,131 @@
+#![allow(dead_code)]
+
+// Comments of sam source: from `//` to the end of the line, outside
+// strings. `///` documents the declaration below it and `//!` the script,
+// see `sam doc`.
+//
+// `//` is no operator: the grammar has no token for it, and `sam doc`,
+// `sam fmt`, and the examples of `sam explain` all read it as a comment.
+// Division rounding down is the builtin `floor_div(a, b)` instead, which
+// follows the division policy like `/` and `%`.
+//
+// The grammar has no rule for comments either, so they are blanked out with
+// spaces before tree-sitter parses the source. Every other byte stays where
+// it was, so spans, rows, and the edits of `reparse` still line up with the
+// text as written.
+
+use std::borrow::Cow;
+use std::ops::Range;
+
+// the byte ranges of the comments of `source`, each from its `//` to the
+// end of its line, line break left out
+pub fn comments(source: &[u8]) -> Vec<Range<usize>> {
+  let mut ranges = Vec::new();
+  let mut quote: Option<u8> = None;
+  let mut i = 0;
+
+  while i < source.len() {
+    match (quote, source[i]) {
+      // the escaped character cannot end the string
+      (Some(_), b'\\') => i += 1,
+      (Some(q), b) if b == q => quote = None,
+      (Some(_), _) => {}
+      (None, b @ (b'\'' | b'"')) => quote = Some(b),
+      (None, b'/') if source.get(i + 1) == Some(&b'/') => {
+        let end = source[i..]
+          .iter()
+          .position(|b| *b == b'\n')
+          .map_or(source.len(), |n| i + n);
+        let end = match source[..end].ends_with(b"\r") {
+          true => end - 1,
+          false => end,
+        };
+        ranges.push(i..end);
+        i = end;
+        continue;
+      }
+      (None, _) => {}
+    }
+    i += 1;
+  }
+
+  return ranges;
+}
+
+// `source` with the bytes of its comments replaced by spaces
+pub fn blank(source: &[u8]) -> Cow<'_, [u8]> {
+  let ranges = comments(source);
+  if ranges.is_empty() {
+    return Cow::Borrowed(source);
+  }
+
+  let mut blanked = source.to_vec();
+  for range in ranges {
+    blanked[range].fill(b' ');
+  }
+
+  return Cow::Owned(blanked);
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_comments() {
+    let source = "//! Module.\nlet a = 1; // one\nlet b = 'x // y';";
+    let ranges = comments(source.as_bytes());
+
+    let texts: Vec<&str> = ranges.iter().map(|r| &source[r.clone()]).collect();
+    assert_eq!(texts, ["//! Module.", "// one"]);
+
+    // escaped quotes do not end a string
+    assert!(comments(br#"let s = "a \" // b";"#).is_empty());
+    let ranges = comments(b"let s = 'it\\'s'; // c");
+    assert_eq!(ranges.len(), 1);
+    assert_eq!(ranges[0], 17..21);
+    assert!(comments(b"let a = 4 / 2;").is_empty());
+  }
+
+  #[test]
+  fn test_strings_with_slashes() {
+    // `//` in a string, of either quote, is part of the string
+    for source in [
+      "let url = 'https://example.com';",
+      "let url = \"https://example.com\";",
+      "let s = \"it's // not a comment\";",
+      "let s = 'say \"//\"';",
+      "print('a//b', \"c//d\");",
+    ] {
+      assert!(comments(source.as_bytes()).is_empty(), "{}", source);
+    }
+
+    // the string ends at its quote, even after an escaped backslash
+    let source = r"let s = 'a\\'; // b";
+    let texts: Vec<&str> = comments(source.as_bytes())
+      .into_iter()
+      .map(|r| &source[r])
+      .collect();
+    assert_eq!(texts, ["// b"]);
+
+    let source = "let url = 'http://x'; // 'y";
+    let blanked = blank(source.as_bytes());
+    assert_eq!(
+      std::str::from_utf8(&blanked).unwrap(),
+      "let url = 'http://x';      "
+    );
+  }
+
+  #[test]
+  fn test_blank() {
+    let source = "let a = 1; // one\r\nlet é = 2; //é\n";
+    let blanked = blank(source.as_bytes());
+
+    assert_eq!(blanked.len(), source.len());
+    assert_eq!(
+      std::str::from_utf8(&blanked).unwrap(),
+      "let a = 1;       \r\nlet é = 2;     \n"
+    );
+    assert!(matches!(blank(b"let a = 1;"), Cow::Borrowed(_)));
+  }
+}
@@ -0,0 +1,402 @@
+#![allow(dead_code)]
+
+use crate::codes;
+use crate::coverage::Coverage;
+use crate::debugger::Debugger;
+use crate::error::SamError;
+use crate::interrupt::InterruptHandle;
+use crate::lint::LintConfig;
+use crate::manifest::{Dependency, Package};
+use crate::modules::Modules;
+use crate::output::Streams;
+use crate::permissions::Permissions;
+use crate::process::{CallOptions, ExitPolicy, StderrMode};
+use crate::profile::Profiler;
+use crate::trace::{Trace, Tracer};
+use clap::ValueEnum;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+// name of the settings file looked up next to the script and above it
+pub const CONFIG_FILE: &str = "sam.toml";
+
+// interpreter settings, read from `sam.toml` and overridden by command line
+// flags
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+  // operations on undefined or mismatched values are errors instead of
+  // silently producing undefined
+  pub strict: bool,
+
+  // result of `/`, `%`, and `floor_div` with a zero divisor
+  pub division_by_zero: DivisionPolicy,
+
+  // what runs the script: "tree" or "vm"
+  pub backend: Backend,
+
+  // assigning to an undeclared name declares it in the current scope, with
+  // a warning, instead of failing; unset means on in the REPL only
+  pub auto_declare: Option<bool>,
+
+  // default time limit of external calls in seconds, none if unset
+  pub timeout: Option<f64>,
+
+  // environment variables set for every external call, as an `[env]` table;
+  // refused when `--allow-run` limits what runs, see Permissions::check_env
+  pub env: HashMap<String, String>,
+
+  // what foreign functions exiting with a non-zero status evaluate to
+  pub exit_status: ExitPolicy,
+
+  // where the stderr of external calls goes: "capture", "inherit", "merge"
+  pub stderr: StderrMode,
+
+  // directories searched for interface files not found where the script
+  // says, relative ones from the directory of sam.toml
+  pub interface_path: Vec<PathBuf>,
+
+  // keep the bytecode of scripts run with the vm backend on disk, so that
+  // running them again skips parsing and compiling, see src/cache.rs
+  pub cache: bool,
+
+  // directory the compiled scripts are kept in, next to each script if
+  // unset; a relative one is from the directory of sam.toml
+  pub cache_dir: Option<PathBuf>,
+
+  // rules of `sam lint` turned off or made errors, as a `[lint]` table
+  pub lint: LintConfig,
+
+  // the package the scripts next to sam.toml make up, as a `[package]`
+  // table, see src/manifest.rs
+  pub package: Package,
+
+  // packages modules are imported from, by name, as a `[dependencies]`
+  // table
+  pub dependencies: BTreeMap<String, Dependency>,
+
+  // directory of the sam.toml read, that of the project, none without one
+  #[serde(skip)]
+  pub project: Option<PathBuf>,
+
+  // directory of the script or module running, which imports of `./` and
+  // `../` paths start from; the working directory if unset
+  #[serde(skip)]
+  pub module_dir: Option<PathBuf>,
+
+  // modules imported so far, each run once however often it is imported
+  #[serde(skip)]
+  pub modules: Modules,
+
+  // sandbox of the script, only ever set from the command line so that a
+  // project cannot grant itself access
+  #[serde(skip)]
+  pub permissions: Permissions,
+
+  // bounds on the work a script may do, likewise only set by whoever runs
+  // it
+  #[serde(skip)]
+  pub limits: Limits,
+
+  // directory external calls run in, changed by `chdir`; none is the
+  // interpreter's own
+  #[serde(skip)]
+  pub cwd: Option<PathBuf>,
+
+  // told about every external command run, set by `--trace-ffi` or by an
+  // embedder auditing what a script does
+  #[serde(skip)]
+  pub trace: Option<Trace>,
+
+  // told about every statement and expression the tree walker evaluates,
+  // set by `--trace`
+  #[serde(skip)]
+  pub tracer: Option<Tracer>,
+
+  // times every call, set by `--profile`
+  #[serde(skip)]
+  pub profile: Option<Profiler>,
+
+  // counts every statement the tree walker runs, set by `--coverage`
+  #[serde(skip)]
+  pub coverage: Option<Coverage>,
+
+  // stops the tree walker at breakpoints and steps, set by `sam dap`
+  #[serde(skip)]
+  pub debugger: Option<Debugger>,
+
+  // stops the script from another thread, see
+  // `Interpreter::interrupt_handle`
+  #[serde(skip)]
+  pub interrupt: InterruptHandle,
+
+  // where output meant for the user is written, replaced by embedders that
+  // capture or redirect it
+  #[serde(skip)]
+  pub output: Streams,
+}
+
+// resources a script may use, unbounded if unset, so that untrusted
+// scripts can be run without hanging or exhausting the host
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Limits {
+  // statements and expressions evaluated per run
+  pub steps: Option<u64>,
+  // iterations of a single for loop
+  pub loop_iterations: Option<u64>,
+  // sam functions running at once, i.e. how deep recursion may go
+  pub call_depth: Option<usize>,
+  // bytes of a string or bytes value, or elements of an array or object,
+  // created by the script
+  pub value_size: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DivisionPolicy {
+  // raise a runtime error
+  Error,
+  // evaluate to undefined
+  #[default]
+  Undefined,
+  // follow floating point rules: infinity, or NaN for `0 / 0` and `%`
+  Ieee,
+}
+
+// how scripts are run, both give the same results
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+  // walk the lowered tree, the reference implementation
+  #[default]
+  Tree,
+  // compile to bytecode for a stack machine, faster on loops and calls
+  Vm,
+}
+
+impl Config {
+  pub fn call_options(&self) -> CallOptions {
+    return CallOptions {
+      timeout: self
+        .timeout
+        .and_then(|t| Duration::try_from_secs_f64(t).ok()),
+      env: self.env.clone(),
+      clear_env: false,
+      exit_status: self.exit_status,
+      permissions: self.permissions.clone(),
+      cwd: self.cwd.clone(),
+      stderr: self.stderr,
+      trace: self.trace.clone(),
+      interrupt: self.interrupt.clone(),
+    };
+  }
+
+  pub fn working_dir(&self) -> PathBuf {
+    return self
+      .cwd
+      .clone()
+      .or_else(|| env::current_dir().ok())
+      .unwrap_or_default();
+  }
+
+  // run external calls in `path` from now on, relative paths starting from
+  // the current working directory. The directory must be readable, as
+  // allowed programs may read what they find there, e.g. git its hooks.
+  pub fn chdir(&mut self, path: &str) -> Result<(), SamError> {
+    let dir = self.working_dir().join(path);
+
+    let dir =
+      dir
+        .canonicalize()
+        .ok()
+        .filter(|d| d.is_dir())
+        .ok_or_else(|| {
+          SamError::value(
+            codes::NOT_A_DIRECTORY,
+            format!("{} is not a directory", dir.display()),
+          )
+        })?;
+    self.permissions.check_read(&dir)?;
+
+    self.cwd = Some(dir);
+
+    return Ok(());
+  }
+
+  pub fn parse(text: &str) -> Result<Config, SamError> {
+    return toml::from_str(text).map_err(|e| {
+      SamError::value(
+        codes::CONFIG_INVALID,
+        format!("Invalid {}: {}", CONFIG_FILE, e.message()),
+      )
+      .caused_by(e)
+    });
+  }
+
+  // settings of the `sam.toml` in `dir`, or the defaults if there is none
+  pub fn load(dir: &Path) -> Result<Config, SamError> {
+    let path = dir.join(CONFIG_FILE);
+
+    if !path.is_file() {
+      return Ok(Config::default());
+    }
+
+    let text = fs::read_to_string(&path).map_err(|e| {
+      SamError::value(
+        codes::CONFIG_INVALID,
+        format!("Could not read {}", path.display()),
+      )
+      .caused_by(e)
+    })?;
+
+    let mut config = Config::parse(&text)?;
+    for entry in &mut config.interface_path {
+      *entry = dir.join(&*entry);
+    }
+    if let Some(cache_dir) = &mut config.cache_dir {
+      *cache_dir = dir.join(&*cache_dir);
+    }
+    config.package.resolve(dir);
+    for dependency in config.dependencies.values_mut() {
+      if let Dependency::Path { path } = dependency {
+        *path = dir.join(&*path);
+      }
+    }
+    config.project = Some(dir.to_path_buf());
+
+    return Ok(config);
+  }
+
+  // settings of the nearest sam.toml, in `dir` or a directory above it,
+  // i.e. of the project `dir` is in
+  pub fn find(dir: &Path) -> Result<Config, SamError> {
+    let dir = match dir.as_os_str().is_empty() {
+      true => Path::new("."),
+      false => dir,
+    };
+    let absolute = dir.canonicalize().unwrap_or(dir.to_path_buf());
+
+    return match absolute.ancestors().find(|d| d.join(CONFIG_FILE).is_file()) {
+      Some(project) => Config::load(project),
+      None => Ok(Config::default()),
+    };
+  }
+
+  // where interface files are looked up, in order: the path as written,
+  // then the configured directories, then those in SAM_PATH
+  pub fn interface_candidates(&self, path: &str) -> Vec<PathBuf> {
+    let mut candidates = vec![PathBuf::from(path)];
+
+    if Path::new(path).is_absolute() {
+      return candidates;
+    }
+
+    let sam_path = env::var_os("SAM_PATH").unwrap_or_default();
+    let dirs =
+      self.interface_path.iter().cloned().chain(
+        env::split_paths(&sam_path).filter(|d| !d.as_os_str().is_empty()),
+      );
+
+    candidates.extend(dirs.map(|dir| dir.join(path)));
+
+    return candidates;
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::permissions::Allow;
+
+  #[test]
+  fn test_parse_config() {
+    assert!(!Config::parse("").unwrap().strict);
+    assert!(Config::parse("strict = true").unwrap().strict);
+  }
+
+  #[test]
+  fn test_parse_division_policy() {
+    let config = Config::parse("division_by_zero = 'ieee'").unwrap();
+    assert_eq!(config.division_by_zero, DivisionPolicy::Ieee);
+    assert!(Config::parse("division_by_zero = 'nan'").is_err());
+  }
+
+  #[test]
+  fn test_parse_backend() {
+    assert_eq!(Config::parse("").unwrap().backend, Backend::Tree);
+    assert_eq!(
+      Config::parse("backend = 'vm'").unwrap().backend,
+      Backend::Vm
+    );
+  }
+
+  #[test]
+  fn test_parse_lint() {
+    let config =
+      Config::parse("[lint]\nallow = ['shadowing']\ndeny = ['unreachable']")
+        .unwrap();
+    assert_eq!(config.lint.allow, vec!["shadowing"]);
+    assert_eq!(config.lint.deny, vec!["unreachable"]);
+    assert!(Config::parse("[lint]\nignore = []").is_err());
+  }
+
+  #[test]
+  fn test_parse_exit_status() {
+    let config = Config::parse("exit_status = 'result'").unwrap();
+    assert_eq!(config.call_options().exit_status, ExitPolicy::Result);
+  }
+
+  #[test]
+  fn test_interface_candidates() {
+    let config = Config::parse("interface_path = ['lib', '/opt/sam']").unwrap();
+
+    assert_eq!(
+      config.interface_candidates("tools.json")[..3],
+      [
+        PathBuf::from("tools.json"),
+        PathBuf::from("lib/tools.json"),
+        PathBuf::from("/opt/sam/tools.json"),
+      ]
+    );
+    assert_eq!(config.interface_candidates("/etc/tools.json").len(), 1);
+  }
+
+  #[test]
+  fn test_parse_stderr() {
+    let config = Config::parse("stderr = 'inherit'").unwrap();
+    assert_eq!(config.call_options().stderr, StderrMode::Inherit);
+    assert!(Config::parse("stderr = 'discard'").is_err());
+  }
+
+  #[test]
+  fn test_chdir() {
+    let mut config = Config::default();
+    let tmp = env::temp_dir().canonicalize().unwrap();
+
+    config.chdir(&tmp.display().to_string()).unwrap();
+    assert_eq!(config.call_options().cwd, Some(tmp.clone()));
+
+    config.chdir("..").unwrap();
+    assert_eq!(config.working_dir(), tmp.parent().unwrap());
+
+    let err = config.chdir("no-such-dir").unwrap_err();
+    assert_eq!(err.code(), Some(codes::NOT_A_DIRECTORY));
+    assert_eq!(config.working_dir(), tmp.parent().unwrap());
+
+    config.permissions.read = Allow::Only(vec![tmp.display().to_string()]);
+    config.chdir(&tmp.display().to_string()).unwrap();
+    let err = config.chdir("..").unwrap_err();
+    assert_eq!(err.code(), Some(codes::PERMISSION_DENIED));
+    assert_eq!(config.working_dir(), tmp);
+  }
+
+  #[test]
+  fn test_parse_config_unknown_key() {
+    let err = Config::parse("stirct = true").unwrap_err();
+    assert_eq!(err.code(), Some(codes::CONFIG_INVALID));
+  }
+}
@@ -1,41 +1,140 @@
 #![allow(dead_code)]
 
-use tree_sitter::Tree;
-
+use crate::config::Config;
+use crate::diagnostic::Diagnostic;
+use crate::error::SamError;
+use crate::random::Random;
+use crate::symbol::{Symbol, SymbolMap};
 use crate::value::Value;
-use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::fmt;
+use std::ops::Index;
 
 // Variant for statements, allows for early return
 pub enum EvalControl<'a> {
   Value(Value),
   Return(Value),
+  Break,
+  Continue,
   Reference(&'a Value),
 }
 
-pub type EvalResult<'a> = Result<EvalControl<'a>, String>;
+pub type EvalResult<'a> = Result<EvalControl<'a>, SamError>;
 
 impl EvalControl<'_> {
   pub fn to_value(&self) -> Value {
     match self {
       EvalControl::Value(v) | EvalControl::Return(v) => v.clone(),
       EvalControl::Reference(v) => (*v).clone(),
+      EvalControl::Break | EvalControl::Continue => Value::Undefined,
     }
   }
 }
 
-type SymbolTable = HashMap<String, Value>;
+// the variables of a scope, by interned name
+#[derive(Clone, Default, PartialEq)]
+pub struct SymbolTable(SymbolMap<Value>);
+
+impl SymbolTable {
+  pub fn get(&self, name: impl Into<Symbol>) -> Option<&Value> {
+    return self.0.get(&name.into());
+  }
+
+  pub fn get_mut(&mut self, name: impl Into<Symbol>) -> Option<&mut Value> {
+    return self.0.get_mut(&name.into());
+  }
+
+  pub fn contains_key(&self, name: impl Into<Symbol>) -> bool {
+    return self.0.contains_key(&name.into());
+  }
+
+  pub fn insert(
+    &mut self,
+    name: impl Into<Symbol>,
+    value: Value,
+  ) -> Option<Value> {
+    return self.0.insert(name.into(), value);
+  }
+
+  pub fn remove(&mut self, name: impl Into<Symbol>) -> Option<Value> {
+    return self.0.remove(&name.into());
+  }
+
+  pub fn entry(&mut self, name: impl Into<Symbol>) -> Entry<'_, Symbol, Value> {
+    return self.0.entry(name.into());
+  }
+
+  pub fn keys(&self) -> impl Iterator<Item = &'static str> {
+    return self.0.keys().map(|k| k.as_str());
+  }
+
+  pub fn iter(&self) -> impl Iterator<Item = (&'static str, &Value)> {
+    return self.0.iter().map(|(k, v)| (k.as_str(), v));
+  }
+
+  pub fn len(&self) -> usize {
+    return self.0.len();
+  }
+
+  pub fn is_empty(&self) -> bool {
+    return self.0.is_empty();
+  }
+}
+
+impl<K: Into<Symbol>> Extend<(K, Value)> for SymbolTable {
+  fn extend<I: IntoIterator<Item = (K, Value)>>(&mut self, iter: I) {
+    self.0.extend(iter.into_iter().map(|(k, v)| (k.into(), v)));
+  }
+}
+
+impl Index<&str> for SymbolTable {
+  type Output = Value;
+
+  fn index(&self, name: &str) -> &Value {
+    return self.get(name).expect("variable not in scope");
+  }
+}
+
+impl fmt::Debug for SymbolTable {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_map().entries(self.iter()).finish()
+  }
+}
 
 #[derive(Debug)]
-pub struct Context<'a> {
+pub struct Context {
   pub call_stack: Vec<SymbolTable>,
-  pub tree: &'a tree_sitter::Tree,
+  pub config: Config,
+  // non-fatal problems found while evaluating
+  pub warnings: Vec<Diagnostic>,
+  // statements and expressions evaluated in the current run, counted
+  // against the step limit
+  pub steps: u64,
+  // sam function calls in progress, counted against the depth limit
+  pub calls: usize,
+  // what `rand` and friends draw from, reseeded by `seed`
+  pub random: Random,
+}
+
+impl Default for Context {
+  fn default() -> Context {
+    return Context::new();
+  }
 }
 
-impl<'a> Context<'a> {
-  pub fn new(tree: &'a Tree) -> Context<'a> {
+impl Context {
+  pub fn new() -> Context {
+    return Context::with_config(Config::default());
+  }
+
+  pub fn with_config(config: Config) -> Context {
     let mut ctx = Context {
       call_stack: Vec::new(),
-      tree,
+      config,
+      warnings: Vec::new(),
+      steps: 0,
+      calls: 0,
+      random: Random::from_entropy(),
     };
 
     // create global scope
@@ -49,9 +148,13 @@ impl<'a> Context<'a> {
     return self.call_stack.len();
   }
 
-  pub fn search_in_stack(&mut self, varname: &String) -> Option<&mut Value> {
+  pub fn search_in_stack(
+    &mut self,
+    varname: impl Into<Symbol>,
+  ) -> Option<&mut Value> {
     // find the first entry from the top of the stack that matches the variable
     // name (lexical scoping)
+    let varname = varname.into();
 
     let reverse_iter = self.call_stack.iter_mut().rev();
 
@@ -67,9 +170,14 @@ impl<'a> Context<'a> {
     return None;
   }
 
+  // every variable name visible from the current scope
+  pub fn visible_names(&self) -> impl Iterator<Item = &'static str> + '_ {
+    return self.call_stack.iter().flat_map(|t| t.keys());
+  }
+
   // create a new scope for the call stack
   pub fn init_scope(&mut self) {
-    let new_scope: SymbolTable = HashMap::new();
+    let new_scope = SymbolTable::default();
 
     self.call_stack.push(new_scope);
   }
@@ -79,6 +187,28 @@ impl<'a> Context<'a> {
     self.call_stack.pop();
   }
 
+  // a context for running part of the script on another thread, e.g. for
+  // `par_map`: it sees the same variables, but what it declares, assigns,
+  // or warns about stays with it. It draws random numbers of its own, which
+  // no seed makes reproducible, as which thread gets which work is not.
+  pub fn child(&self) -> Context {
+    return Context {
+      call_stack: self.call_stack.clone(),
+      config: self.config.clone(),
+      warnings: Vec::new(),
+      steps: self.steps,
+      calls: self.calls,
+      random: Random::from_entropy(),
+    };
+  }
+
+  // drop every scope but the global one, e.g. the scopes of the functions
+  // an error unwound through
+  pub fn unwind_to_global(&mut self) {
+    self.call_stack.truncate(1);
+    self.calls = 0;
+  }
+
   pub fn current_scope(&mut self) -> &mut SymbolTable {
     return self.call_stack.last_mut().unwrap();
   }
@@ -86,4 +216,15 @@ impl<'a> Context<'a> {
   pub fn global_scope(&mut self) -> &mut SymbolTable {
     return self.call_stack.first_mut().unwrap();
   }
+
+  // declare or replace a global variable, whatever scope is current, e.g.
+  // for a host seeding the script's inputs
+  pub fn set_global(&mut self, name: &str, value: impl Into<Value>) {
+    self.global_scope().insert(name, value.into());
+  }
+
+  // a global variable, even one hidden by a local of the same name
+  pub fn get_global(&self, name: &str) -> Option<&Value> {
+    return self.call_stack.first().and_then(|scope| scope.get(name));
+  }
 }
@@ -0,0 +1,253 @@
+#![allow(dead_code)]
+
+// Statement coverage, set by `--coverage` and `sam test --coverage`. Every
+// statement the tree walker evaluates is counted by where it starts in the
+// source. Once the script ran, the counts are matched against the
+// statements of its syntax tree, anywhere in it, so that statements never
+// run are reported too.
+//
+// The report is a table of the statements run per script, with the lines
+// of those that were not, and optionally a file in the lcov format coverage
+// tools read, one record per script:
+//
+//   SF:scripts/build.sam
+//   DA:1,1
+//   DA:4,0
+//   LF:2
+//   LH:1
+//   end_of_record
+
+use crate::ast::{self, Program, Stmt, Visitor};
+use crate::error::{SamError, Span};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use tree_sitter::Node;
+
+// shared by every copy of the config, and so by the threads of a run
+#[derive(Clone, Default)]
+pub struct Coverage(Arc<Mutex<HashMap<usize, u64>>>);
+
+impl Coverage {
+  pub fn new() -> Self {
+    return Coverage::default();
+  }
+
+  // count a run of the statement at `span`
+  pub fn hit(&self, span: Span) {
+    *self.0.lock().unwrap().entry(span.start).or_default() += 1;
+  }
+
+  // the coverage of the script `file`, of source `source` and tree `root`
+  pub fn report(
+    &self,
+    root: Node,
+    source: &str,
+    file: &str,
+  ) -> Result<FileCoverage, SamError> {
+    let program = ast::lower(root, source.as_bytes())?;
+    return Ok(self.of_program(&program, file));
+  }
+
+  fn of_program(&self, program: &Program, file: &str) -> FileCoverage {
+    let mut statements = Statements(Vec::new());
+    statements.visit_program(program);
+
+    let hits = self.0.lock().unwrap();
+
+    // a line is run as often as the statement on it run the most
+    let mut lines = BTreeMap::new();
+    for span in statements.0 {
+      let count = hits.get(&span.start).copied().unwrap_or(0);
+      let line = lines.entry(span.start_point.row + 1).or_insert(0);
+      *line = count.max(*line);
+    }
+
+    return FileCoverage {
+      file: file.to_owned(),
+      lines,
+    };
+  }
+}
+
+impl std::fmt::Debug for Coverage {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "Coverage")
+  }
+}
+
+// count a run of the statement at `span` if coverage is recorded
+pub fn hit(coverage: &Option<Coverage>, span: Span) {
+  if let Some(coverage) = coverage {
+    coverage.hit(span);
+  }
+}
+
+// the spans of every statement of a program, those of function bodies and
+// blocks included
+struct Statements(Vec<Span>);
+
+impl Visitor for Statements {
+  fn visit_stmt(&mut self, stmt: &Stmt) {
+    self.0.push(stmt.span);
+    ast::walk_stmt(self, stmt);
+  }
+}
+
+// the lines of a script that have statements on them, numbered from 1, and
+// how often they were run
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileCoverage {
+  pub file: String,
+  pub lines: BTreeMap<usize, u64>,
+}
+
+impl FileCoverage {
+  pub fn covered(&self) -> usize {
+    return self.lines.values().filter(|&&count| count > 0).count();
+  }
+
+  // the lines never run, consecutive ones as ranges, e.g. `4, 9-11`
+  pub fn missing(&self) -> String {
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    let mut run = true;
+
+    for (&line, &count) in &self.lines {
+      if count > 0 {
+        run = true;
+        continue;
+      }
+
+      // lines without statements between two missed ones do not split them
+      match ranges.last_mut() {
+        Some(range) if !run => range.1 = line,
+        _ => ranges.push((line, line)),
+      }
+      run = false;
+    }
+
+    return ranges
+      .into_iter()
+      .map(|(start, end)| match start == end {
+        true => start.to_string(),
+        false => format!("{}-{}", start, end),
+      })
+      .collect::<Vec<_>>()
+      .join(", ");
+  }
+
+  pub fn lcov(&self) -> String {
+    let mut out = format!("SF:{}\n", self.file);
+    for (line, count) in &self.lines {
+      out.push_str(&format!("DA:{},{}\n", line, count));
+    }
+    out.push_str(&format!("LF:{}\n", self.lines.len()));
+    out.push_str(&format!("LH:{}\n", self.covered()));
+    out.push_str("end_of_record\n");
+
+    return out;
+  }
+}
+
+// the table printed after the run
+pub fn summary(files: &[FileCoverage]) -> String {
+  let width = files
+    .iter()
+    .map(|f| f.file.chars().count())
+    .chain(["file".len()])
+    .max()
+    .unwrap();
+
+  let row = |file: &str, lines: usize, covered: usize, missing: &str| {
+    let percent = match lines {
+      0 => 100.0,
+      _ => 100.0 * covered as f64 / lines as f64,
+    };
+    let row = format!(
+      "{:<width$}  {:>6}  {:>6}  {:>6.1}%  {}",
+      file, lines, covered, percent, missing
+    );
+    return format!("{}\n", row.trim_end());
+  };
+
+  let mut out = format!(
+    "{:<width$}  {:>6}  {:>6}  {:>7}  {}\n",
+    "file", "lines", "run", "cover", "missing"
+  );
+  for f in files {
+    out.push_str(&row(&f.file, f.lines.len(), f.covered(), &f.missing()));
+  }
+
+  if files.len() > 1 {
+    let lines = files.iter().map(|f| f.lines.len()).sum();
+    let covered = files.iter().map(|f| f.covered()).sum();
+    out.push_str(&row("total", lines, covered, ""));
+  }
+
+  return out;
+}
+
+// the records of every script, in the lcov format
+pub fn lcov(files: &[FileCoverage]) -> String {
+  return files.iter().map(|f| f.lcov()).collect();
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn lines(counts: &[(usize, u64)]) -> FileCoverage {
+    return FileCoverage {
+      file: "a.sam".to_owned(),
+      lines: counts.iter().copied().collect(),
+    };
+  }
+
+  #[test]
+  fn test_missing() {
+    let file = lines(&[(1, 1), (2, 0), (4, 0), (5, 3), (7, 0), (9, 0)]);
+    assert_eq!(file.covered(), 2);
+    assert_eq!(file.missing(), "2-4, 7-9");
+
+    assert_eq!(lines(&[(1, 1)]).missing(), "");
+  }
+
+  #[test]
+  fn test_reports() {
+    let file = lines(&[(1, 2), (3, 0)]);
+    assert_eq!(
+      file.lcov(),
+      "SF:a.sam\nDA:1,2\nDA:3,0\nLF:2\nLH:1\nend_of_record\n"
+    );
+
+    let summary = summary(&[file]);
+    let row = summary.lines().nth(1).unwrap();
+    assert!(row.starts_with("a.sam"));
+    assert!(row.ends_with("50.0%  3"));
+  }
+
+  #[test]
+  fn test_hits() {
+    let source = "let a = 1;\nif (a > 2) {\n  a = 3;\n};\nlet f = () => {\n  return a;\n};\nf(); f();";
+    let tree = crate::parser().parse(source, None).unwrap();
+    let program = ast::lower(tree.root_node(), source.as_bytes()).unwrap();
+
+    let coverage = Coverage::new();
+    let mut ctx = crate::context::Context::with_config(crate::config::Config {
+      coverage: Some(coverage.clone()),
+      ..Default::default()
+    });
+    crate::evaluate::evaluate_source(
+      &tree.root_node(),
+      source.as_bytes(),
+      &mut ctx,
+      0,
+    )
+    .unwrap();
+
+    let file = coverage.of_program(&program, "a.sam");
+    assert_eq!(
+      file.lines.into_iter().collect::<Vec<_>>(),
+      vec![(1, 1), (2, 1), (3, 0), (5, 1), (6, 2), (8, 1)]
+    );
+  }
+}
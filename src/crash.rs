@@ -0,0 +1,129 @@
+#![allow(dead_code)]
+
+use crate::codes;
+use crate::error::{SamError, Span};
+use std::backtrace::Backtrace;
+use std::cell::{Cell, RefCell};
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+
+const REPORT_URL: &str = "https://github.com/44mira/sam/issues";
+
+thread_local! {
+  // the node being evaluated, the best guess of what triggered a crash
+  static CURRENT_NODE: Cell<Option<Span>> = const { Cell::new(None) };
+  // set while a crash would be turned into an error by `catch`
+  static CATCHING: Cell<bool> = const { Cell::new(false) };
+  static LAST_CRASH: RefCell<Option<Crash>> = const { RefCell::new(None) };
+}
+
+// what the panic hook learned about a panic
+#[derive(Debug)]
+pub struct Crash {
+  message: String,
+  location: String,
+  backtrace: Backtrace,
+}
+
+impl fmt::Display for Crash {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "panicked at {}: {}", self.location, self.message)?;
+    write!(f, "\nbacktrace:\n{}", self.backtrace)
+  }
+}
+
+impl std::error::Error for Crash {}
+
+// record the node about to be evaluated
+pub fn enter(span: Span) {
+  CURRENT_NODE.with(|current| current.set(Some(span)));
+}
+
+// replace the default panic message with a request to report the bug. Inside
+// `catch` nothing is printed, the crash becomes an ordinary error instead.
+pub fn install_hook() {
+  panic::set_hook(Box::new(|info| {
+    let message = match info.payload().downcast_ref::<&str>() {
+      Some(s) => s.to_string(),
+      None => match info.payload().downcast_ref::<String>() {
+        Some(s) => s.clone(),
+        None => "unknown panic".to_owned(),
+      },
+    };
+
+    let crash = Crash {
+      message,
+      location: info
+        .location()
+        .map(|l| l.to_string())
+        .unwrap_or_else(|| "<unknown>".to_owned()),
+      backtrace: Backtrace::force_capture(),
+    };
+
+    if CATCHING.with(|c| c.get()) {
+      LAST_CRASH.with(|last| *last.borrow_mut() = Some(crash));
+      return;
+    }
+
+    eprintln!(
+      "error: internal interpreter error, please report it at {}",
+      REPORT_URL
+    );
+    eprintln!("{}", crash);
+  }));
+}
+
+// run `f`, turning a panic inside it into an internal error pointing at the
+// node that was being evaluated
+pub fn catch<T>(
+  f: impl FnOnce() -> Result<T, SamError>,
+) -> Result<T, SamError> {
+  let was_catching = CATCHING.with(|c| c.replace(true));
+  let result = panic::catch_unwind(AssertUnwindSafe(f));
+  CATCHING.with(|c| c.set(was_catching));
+
+  let Err(payload) = result else {
+    return result.unwrap();
+  };
+
+  let mut err = SamError::runtime(
+    codes::INTERNAL_ERROR,
+    format!(
+      "internal interpreter error, please report it at {}",
+      REPORT_URL
+    ),
+  );
+
+  if let Some(span) = CURRENT_NODE.with(|current| current.get()) {
+    err = err.at(span);
+  }
+
+  // without the hook installed only the payload is known
+  let crash = LAST_CRASH.with(|last| last.borrow_mut().take());
+  return Err(match crash {
+    Some(crash) => err.caused_by(crash),
+    None => match payload.downcast::<&str>() {
+      Ok(s) => err.caused_by(*s),
+      Err(_) => err,
+    },
+  });
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_catch_panic() {
+    let result: Result<(), SamError> = catch(|| panic!("boom"));
+    let err = result.unwrap_err();
+
+    assert_eq!(err.code(), Some(codes::INTERNAL_ERROR));
+  }
+
+  #[test]
+  fn test_catch_passes_errors() {
+    let result: Result<i32, SamError> = catch(|| Ok(1));
+    assert_eq!(result.unwrap(), 1);
+  }
+}
@@ -0,0 +1,671 @@
+#![allow(dead_code)]
+
+// `sam dap`: a Debug Adapter Protocol server on stdin and stdout
+// (https://microsoft.github.io/debug-adapter-protocol/), so editors such as
+// VS Code can run a script with breakpoints, step through it, and look at
+// its variables. The script runs on a thread of its own with the tree
+// walker, stopped and resumed through a `Debugger`; requests are answered
+// on the main thread.
+//
+// A single script is debugged per session, and breakpoints are lines of it.
+// Inspections wait for the script to stop, and fail if it keeps running.
+
+use crate::config::{Backend, Config};
+use crate::debugger::{Debugger, Paused, Resume, StopReason};
+use crate::diagnostic::Diagnostic;
+use crate::interpreter::Interpreter;
+use crate::output::{Reader, Writer};
+use crate::value::Value;
+use serde_json::{Value as Json, json};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+// the only thread there is to the client
+const THREAD: i64 = 1;
+// variable references: scopes of frames count from 1, values from VALUES
+const GLOBALS: i64 = 999;
+const VALUES: i64 = 1000;
+// how long an inspection waits for a running script to stop
+const WAIT: Duration = Duration::from_secs(2);
+
+pub fn run(config: Config) -> ExitCode {
+  let stdin = io::stdin();
+  return serve(stdin.lock(), io::stdout(), config);
+}
+
+/* =========================
+Messages
+========================= */
+
+// the next message, none at the end of input
+fn read_message(input: &mut impl BufRead) -> io::Result<Option<Json>> {
+  let mut length = None;
+
+  loop {
+    let mut line = String::new();
+    if input.read_line(&mut line)? == 0 {
+      return Ok(None);
+    }
+
+    let line = line.trim_end();
+    if line.is_empty() {
+      break;
+    }
+    if let Some(value) = line.strip_prefix("Content-Length:") {
+      length = value.trim().parse::<usize>().ok();
+    }
+  }
+
+  let Some(length) = length else {
+    return Err(io::Error::new(
+      io::ErrorKind::InvalidData,
+      "message without a Content-Length",
+    ));
+  };
+
+  let mut body = vec![0; length];
+  input.read_exact(&mut body)?;
+  return Ok(Some(serde_json::from_slice(&body)?));
+}
+
+// writes responses and events, from any thread
+#[derive(Clone)]
+struct Client {
+  out: Arc<Mutex<dyn Write + Send>>,
+  seq: Arc<AtomicI64>,
+}
+
+impl Client {
+  fn send(&self, mut message: Json) {
+    message["seq"] = json!(self.seq.fetch_add(1, Ordering::Relaxed) + 1);
+    let body = message.to_string();
+
+    let mut out = self.out.lock().unwrap_or_else(|e| e.into_inner());
+    let _ = write!(out, "Content-Length: {}\r\n\r\n{}", body.len(), body);
+    let _ = out.flush();
+  }
+
+  fn event(&self, event: &str, body: Json) {
+    self.send(json!({ "type": "event", "event": event, "body": body }));
+  }
+
+  fn respond(&self, request: &Json, body: Json) {
+    self.send(json!({
+      "type": "response",
+      "request_seq": request["seq"],
+      "command": request["command"],
+      "success": true,
+      "body": body,
+    }));
+  }
+
+  fn fail(&self, request: &Json, message: &str) {
+    self.send(json!({
+      "type": "response",
+      "request_seq": request["seq"],
+      "command": request["command"],
+      "success": false,
+      "message": message,
+    }));
+  }
+}
+
+// output of the script, sent to the client's console
+struct Output {
+  client: Client,
+  category: &'static str,
+}
+
+impl Write for Output {
+  fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    self.client.event(
+      "output",
+      json!({
+        "category": self.category,
+        "output": String::from_utf8_lossy(buf),
+      }),
+    );
+    return Ok(buf.len());
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    return Ok(());
+  }
+}
+
+/* =========================
+Session
+========================= */
+
+struct Session {
+  client: Client,
+  config: Config,
+  // the launched script
+  program: Option<PathBuf>,
+  text: String,
+  debugger: Option<Debugger>,
+  // values whose elements the client may ask for, by reference minus
+  // VALUES; they are only valid until the script is resumed
+  values: Vec<Value>,
+  script: Option<thread::JoinHandle<()>>,
+}
+
+pub fn serve(
+  mut input: impl BufRead,
+  output: impl Write + Send + 'static,
+  config: Config,
+) -> ExitCode {
+  let mut session = Session {
+    client: Client {
+      out: Arc::new(Mutex::new(output)),
+      seq: Arc::default(),
+    },
+    config,
+    program: None,
+    text: String::new(),
+    debugger: None,
+    values: Vec::new(),
+    script: None,
+  };
+
+  loop {
+    let request = match read_message(&mut input) {
+      Ok(Some(request)) => request,
+      Ok(None) => break,
+      Err(e) => {
+        eprintln!("error: could not read a request: {}", e);
+        break;
+      }
+    };
+
+    if request["type"] != "request" {
+      continue;
+    }
+
+    let command = request["command"].as_str().unwrap_or_default();
+    let args = &request["arguments"];
+    let result = session.handle(command, args, &request);
+
+    match result {
+      Ok(Some(body)) => session.client.respond(&request, body),
+      Ok(None) => {}
+      Err(message) => session.client.fail(&request, &message),
+    }
+
+    if command == "disconnect" {
+      break;
+    }
+  }
+
+  // a script still stopped would keep the process alive
+  if let Some(debugger) = &session.debugger {
+    session.config.interrupt.interrupt();
+    debugger.detach();
+  }
+  if let Some(script) = session.script.take() {
+    let _ = script.join();
+  }
+
+  return ExitCode::SUCCESS;
+}
+
+impl Session {
+  // the body of the response to `command`, none if it was sent already
+  fn handle(
+    &mut self,
+    command: &str,
+    args: &Json,
+    request: &Json,
+  ) -> Result<Option<Json>, String> {
+    let body = match command {
+      "initialize" => json!({
+        "supportsConfigurationDoneRequest": true,
+        "supportsTerminateRequest": true,
+        "supportsEvaluateForHovers": true,
+      }),
+
+      "launch" => {
+        self.launch(args)?;
+        // breakpoints are asked for once there is a script to put them in
+        self.client.respond(request, json!({}));
+        self.client.event("initialized", json!({}));
+        return Ok(None);
+      }
+
+      "setBreakpoints" => self.set_breakpoints(args),
+
+      "setExceptionBreakpoints" => json!({ "breakpoints": [] }),
+
+      "configurationDone" => {
+        self.client.respond(request, json!({}));
+        self.start();
+        return Ok(None);
+      }
+
+      "threads" => json!({ "threads": [{ "id": THREAD, "name": "main" }] }),
+
+      "stackTrace" => self.stack_trace()?,
+
+      "scopes" => {
+        let frame = args["frameId"].as_i64().unwrap_or(0);
+        json!({ "scopes": [
+          { "name": "Locals", "variablesReference": frame + 1,
+            "expensive": false },
+          { "name": "Globals", "variablesReference": GLOBALS,
+            "expensive": false },
+        ]})
+      }
+
+      "variables" => {
+        let reference = args["variablesReference"].as_i64().unwrap_or(0);
+        self.variables(reference)?
+      }
+
+      "evaluate" => {
+        let expression = args["expression"].as_str().unwrap_or_default();
+        self.evaluate(expression.trim())?
+      }
+
+      "continue" | "next" | "stepIn" | "stepOut" => {
+        let how = match command {
+          "continue" => Resume::Continue,
+          "next" => Resume::StepOver,
+          "stepIn" => Resume::StepIn,
+          _ => Resume::StepOut,
+        };
+        self.values.clear();
+        self.debugger()?.resume(how);
+        json!({ "allThreadsContinued": true })
+      }
+
+      "pause" => {
+        self.debugger()?.pause();
+        json!({})
+      }
+
+      "disconnect" | "terminate" => {
+        self.config.interrupt.interrupt();
+        if let Some(debugger) = &self.debugger {
+          debugger.detach();
+        }
+        json!({})
+      }
+
+      _ => return Err(format!("`{}` is not supported", command)),
+    };
+
+    return Ok(Some(body));
+  }
+
+  fn debugger(&self) -> Result<&Debugger, String> {
+    return self
+      .debugger
+      .as_ref()
+      .ok_or_else(|| "no script was launched".to_owned());
+  }
+
+  fn launch(&mut self, args: &Json) -> Result<(), String> {
+    let Some(program) = args["program"].as_str() else {
+      return Err("launch needs the `program` to debug".to_owned());
+    };
+    let program = PathBuf::from(program);
+
+    self.text = fs::read_to_string(&program)
+      .map_err(|e| format!("could not read {}: {}", program.display(), e))?;
+
+    let client = self.client.clone();
+    let stop_on_entry = args["stopOnEntry"].as_bool().unwrap_or(false);
+    self.debugger = Some(Debugger::new(stop_on_entry, move |reason| {
+      let reason = match reason {
+        StopReason::Entry => "entry",
+        StopReason::Breakpoint => "breakpoint",
+        StopReason::Step => "step",
+        StopReason::Pause => "pause",
+      };
+      client.event(
+        "stopped",
+        json!({
+          "reason": reason,
+          "threadId": THREAD,
+          "allThreadsStopped": true,
+        }),
+      );
+    }));
+    self.program = Some(program);
+
+    return Ok(());
+  }
+
+  fn set_breakpoints(&mut self, args: &Json) -> Json {
+    let lines: Vec<i64> = args["breakpoints"]
+      .as_array()
+      .map(|b| b.iter().filter_map(|b| b["line"].as_i64()).collect())
+      .unwrap_or_default();
+
+    let path = args["source"]["path"].as_str().map(Path::new);
+    let ours = match (&self.program, path, &self.debugger) {
+      (Some(program), Some(path), Some(debugger))
+        if same_file(program, path) =>
+      {
+        debugger.set_breakpoints(
+          lines.iter().filter(|l| **l > 0).map(|l| *l as usize - 1),
+        );
+        true
+      }
+      _ => false,
+    };
+
+    let breakpoints: Vec<Json> = lines
+      .iter()
+      .map(|line| match ours {
+        true => json!({ "verified": true, "line": line }),
+        false => json!({
+          "verified": false,
+          "line": line,
+          "message": "only the launched script can have breakpoints",
+        }),
+      })
+      .collect();
+
+    return json!({ "breakpoints": breakpoints });
+  }
+
+  // run the launched script on a thread of its own
+  fn start(&mut self) {
+    let (Some(program), Some(debugger)) = (&self.program, &self.debugger)
+    else {
+      return;
+    };
+
+    let mut config = self.config.clone();
+    config.backend = Backend::Tree;
+    config.debugger = Some(debugger.clone());
+    config.module_dir = program.parent().map(Path::to_path_buf);
+    // stdin carries the requests of the client
+    config.output.stdin = Reader::empty();
+    config.output.stdout = Writer::new(Output {
+      client: self.client.clone(),
+      category: "stdout",
+    });
+    config.output.stderr = Writer::new(Output {
+      client: self.client.clone(),
+      category: "stderr",
+    });
+
+    let client = self.client.clone();
+    let file = program.display().to_string();
+    let text = self.text.clone();
+
+    self.script = Some(thread::spawn(move || {
+      let mut interpreter = Interpreter::with_config(config);
+      let result = interpreter.eval(&text);
+
+      let code = match result {
+        Ok(_) => 0,
+        Err(e) => {
+          let rendered =
+            Diagnostic::from(&e).render(&file, interpreter.source());
+          client.event(
+            "output",
+            json!({ "category": "stderr", "output": rendered }),
+          );
+          1
+        }
+      };
+
+      client.event("exited", json!({ "exitCode": code }));
+      client.event("terminated", json!({}));
+    }));
+  }
+
+  // `inspect` run against the stopped script, whose answer is waited for
+  fn inspect<T: Send + 'static>(
+    &self,
+    inspect: impl FnOnce(&Paused) -> T + Send + 'static,
+  ) -> Result<T, String> {
+    let (sender, receiver) = mpsc::channel();
+    self.debugger()?.inspect(move |paused| {
+      let _ = sender.send(inspect(paused));
+    });
+
+    return receiver
+      .recv_timeout(WAIT)
+      .map_err(|_| "the script is not stopped".to_owned());
+  }
+
+  fn stack_trace(&self) -> Result<Json, String> {
+    let program = self.program.clone().unwrap_or_default();
+
+    let frames = self.inspect(|paused| {
+      // innermost first, each where it is at: the statement stopped at,
+      // or the call of the next frame
+      let mut frames = Vec::new();
+      for (i, frame) in paused.frames.iter().enumerate().rev() {
+        let at = match paused.frames.get(i + 1) {
+          Some(inner) => inner.call_site.unwrap_or(paused.span),
+          None => paused.span,
+        };
+        frames.push((frame.name.clone(), at));
+      }
+      frames
+    })?;
+
+    let frames: Vec<Json> = frames
+      .iter()
+      .enumerate()
+      .map(|(id, (name, at))| {
+        json!({
+          "id": id,
+          "name": name,
+          "line": at.start_point.row + 1,
+          "column": at.start_point.column + 1,
+          "source": {
+            "name": program.file_name().map(|n| n.to_string_lossy()),
+            "path": program.display().to_string(),
+          },
+        })
+      })
+      .collect();
+
+    return Ok(json!({ "stackFrames": frames, "totalFrames": frames.len() }));
+  }
+
+  fn variables(&mut self, reference: i64) -> Result<Json, String> {
+    let children: Vec<(String, Value)> = match reference {
+      VALUES.. => {
+        let index = (reference - VALUES) as usize;
+        match self.values.get(index) {
+          Some(Value::SamArray(items)) => items
+            .iter()
+            .enumerate()
+            .map(|(i, v)| (i.to_string(), v.clone()))
+            .collect(),
+          Some(Value::SamObject(fields)) => fields
+            .iter()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect::<BTreeMap<_, _>>()
+            .into_iter()
+            .collect(),
+          _ => Vec::new(),
+        }
+      }
+      GLOBALS => self.inspect(|paused| scope_variables(paused, None))?,
+      frame => {
+        let frame = (frame - 1).max(0) as usize;
+        self.inspect(move |paused| scope_variables(paused, Some(frame)))?
+      }
+    };
+
+    let variables: Vec<Json> = children
+      .into_iter()
+      .map(|(name, value)| self.variable(&name, value))
+      .collect();
+
+    return Ok(json!({ "variables": variables }));
+  }
+
+  // a variable for the client, with a reference to its elements if it has
+  // any
+  fn variable(&mut self, name: &str, value: Value) -> Json {
+    let reference = match &value {
+      Value::SamArray(items) if !items.is_empty() => self.keep(value.clone()),
+      Value::SamObject(fields) if !fields.is_empty() => {
+        self.keep(value.clone())
+      }
+      _ => 0,
+    };
+
+    return json!({
+      "name": name,
+      "value": one_line(&value),
+      "type": value.type_name(),
+      "variablesReference": reference,
+    });
+  }
+
+  fn keep(&mut self, value: Value) -> i64 {
+    self.values.push(value);
+    return VALUES + self.values.len() as i64 - 1;
+  }
+
+  // the value of a variable visible where the script stopped, for hovers
+  // and watches
+  fn evaluate(&mut self, name: &str) -> Result<Json, String> {
+    let lookup = name.to_owned();
+    let value = self.inspect(move |paused| {
+      paused
+        .ctx
+        .call_stack
+        .iter()
+        .rev()
+        .find_map(|scope| scope.get(lookup.as_str()).cloned())
+    })?;
+
+    let Some(value) = value else {
+      return Err(format!("{} is not a variable here", name));
+    };
+
+    let variable = self.variable(name, value);
+    return Ok(json!({
+      "result": variable["value"],
+      "type": variable["type"],
+      "variablesReference": variable["variablesReference"],
+    }));
+  }
+}
+
+// the variables of the scopes of frame `frame`, counted from the innermost,
+// or the global ones; inner scopes hide outer ones
+fn scope_variables(
+  paused: &Paused,
+  frame: Option<usize>,
+) -> Vec<(String, Value)> {
+  let stack = &paused.ctx.call_stack;
+
+  let scopes = match frame {
+    None => 0..1.min(stack.len()),
+    Some(frame) => {
+      let Some(index) = paused.frames.len().checked_sub(frame + 1) else {
+        return Vec::new();
+      };
+      // the globals are a scope of their own
+      let start = paused.frames[index].scope_start.max(1);
+      let end = match paused.frames.get(index + 1) {
+        Some(inner) => inner.scope_start,
+        None => stack.len(),
+      };
+      start.min(end)..end.min(stack.len())
+    }
+  };
+
+  let mut variables = BTreeMap::new();
+  for scope in &stack[scopes] {
+    for (name, value) in scope.iter() {
+      variables.insert(name.to_owned(), value.clone());
+    }
+  }
+
+  return variables.into_iter().collect();
+}
+
+fn one_line(value: &Value) -> String {
+  return value
+    .to_string()
+    .split_whitespace()
+    .collect::<Vec<_>>()
+    .join(" ");
+}
+
+fn same_file(a: &Path, b: &Path) -> bool {
+  return match (a.canonicalize(), b.canonicalize()) {
+    (Ok(a), Ok(b)) => a == b,
+    _ => a == b,
+  };
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::output::Captured;
+
+  fn framed(message: Json) -> String {
+    let body = message.to_string();
+    return format!("Content-Length: {}\r\n\r\n{}", body.len(), body);
+  }
+
+  // the messages the server wrote
+  fn messages(text: &str) -> Vec<Json> {
+    let mut input = io::Cursor::new(text.as_bytes());
+    let mut messages = Vec::new();
+    while let Some(message) = read_message(&mut input).unwrap() {
+      messages.push(message);
+    }
+    return messages;
+  }
+
+  #[test]
+  fn test_read_message() {
+    let text = framed(json!({ "seq": 1, "command": "threads" }))
+      + &framed(json!({ "seq": 2 }));
+    let messages = messages(&text);
+
+    assert_eq!(messages.len(), 2);
+    assert_eq!(messages[0]["command"], "threads");
+  }
+
+  #[test]
+  fn test_requests_without_a_script() {
+    let requests = [
+      json!({ "seq": 1, "type": "request", "command": "initialize" }),
+      json!({ "seq": 2, "type": "request", "command": "threads" }),
+      json!({ "seq": 3, "type": "request", "command": "next" }),
+      json!({ "seq": 4, "type": "request", "command": "launch",
+        "arguments": { "program": "/no/such/script.sam" } }),
+      json!({ "seq": 5, "type": "request", "command": "disconnect" }),
+    ];
+    let input: String = requests.into_iter().map(framed).collect();
+
+    let out = Captured::default();
+    serve(io::Cursor::new(input), out.clone(), Config::default());
+    let responses = messages(&out.contents());
+
+    let outcome: Vec<(i64, bool)> = responses
+      .iter()
+      .map(|r| (r["request_seq"].as_i64().unwrap(), r["success"] == true))
+      .collect();
+    assert_eq!(
+      outcome,
+      vec![(1, true), (2, true), (3, false), (4, false), (5, true)]
+    );
+    assert_eq!(responses[1]["body"]["threads"][0]["id"], THREAD);
+    assert_eq!(responses[2]["message"], "no script was launched");
+  }
+}
@@ -0,0 +1,340 @@
+#![allow(dead_code)]
+
+// Breakpoints and stepping for the tree walker, driven from another thread,
+// e.g. by the Debug Adapter Protocol server of `sam dap`. The evaluator
+// reports every statement it is about to run; when one should stop, the
+// script's thread waits until it is resumed, meanwhile running the
+// inspections it is sent against the paused `Context`.
+
+use crate::context::Context;
+use crate::error::Span;
+use std::collections::HashSet;
+use std::fmt;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+// why the script stopped
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StopReason {
+  Entry,
+  Breakpoint,
+  Step,
+  Pause,
+}
+
+// how a stopped script carries on
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Resume {
+  Continue,
+  // to the next line, wherever it is
+  StepIn,
+  // to the next line of the same function or of a caller
+  StepOver,
+  // to the next line of a caller
+  StepOut,
+}
+
+// a sam function call in progress, the script itself being the first
+#[derive(Debug, Clone)]
+pub struct Frame {
+  pub name: String,
+  // where it was called from, none for the script
+  pub call_site: Option<Span>,
+  // the first scope of `Context::call_stack` that is its own
+  pub scope_start: usize,
+}
+
+// the script while it is stopped, for inspections
+pub struct Paused<'a> {
+  pub ctx: &'a Context,
+  // outermost first
+  pub frames: &'a [Frame],
+  // the statement it stopped before
+  pub span: Span,
+}
+
+type Inspection = Box<dyn FnOnce(&Paused) + Send>;
+
+enum Command {
+  Resume(Resume),
+  Inspect(Inspection),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Mode {
+  Run,
+  Pause,
+  Step,
+  // stop at a depth of calls no deeper than this
+  StepTo(usize),
+}
+
+struct State {
+  // rows of the source, counted from 0
+  breakpoints: HashSet<usize>,
+  mode: Mode,
+  frames: Vec<Frame>,
+  // row and depth of the statement run last, so that a breakpoint stops
+  // once when its line is reached, not once for every statement on it
+  last: Option<(usize, usize)>,
+  reason: Option<StopReason>,
+}
+
+#[derive(Clone)]
+pub struct Debugger {
+  state: Arc<Mutex<State>>,
+  sender: Sender<Command>,
+  commands: Arc<Mutex<Receiver<Command>>>,
+  // told when the script stopped, before it waits to be resumed
+  on_stop: Arc<dyn Fn(StopReason) + Send + Sync>,
+}
+
+impl Debugger {
+  // `stop_on_entry` stops before the first statement
+  pub fn new(
+    stop_on_entry: bool,
+    on_stop: impl Fn(StopReason) + Send + Sync + 'static,
+  ) -> Debugger {
+    let (sender, receiver) = mpsc::channel();
+    let state = State {
+      breakpoints: HashSet::new(),
+      mode: if stop_on_entry {
+        Mode::Pause
+      } else {
+        Mode::Run
+      },
+      frames: vec![Frame {
+        name: "<script>".to_owned(),
+        call_site: None,
+        scope_start: 0,
+      }],
+      last: None,
+      reason: stop_on_entry.then_some(StopReason::Entry),
+    };
+
+    return Debugger {
+      state: Arc::new(Mutex::new(state)),
+      sender,
+      commands: Arc::new(Mutex::new(receiver)),
+      on_stop: Arc::new(on_stop),
+    };
+  }
+
+  fn state(&self) -> MutexGuard<'_, State> {
+    return self.state.lock().unwrap_or_else(|e| e.into_inner());
+  }
+
+  /* =========================
+  Driving
+  ========================= */
+
+  // replaces every breakpoint, given by the rows of their lines
+  pub fn set_breakpoints(&self, rows: impl IntoIterator<Item = usize>) {
+    self.state().breakpoints = rows.into_iter().collect();
+  }
+
+  // stop before the next statement
+  pub fn pause(&self) {
+    let mut state = self.state();
+    state.mode = Mode::Pause;
+    state.reason = Some(StopReason::Pause);
+  }
+
+  pub fn resume(&self, how: Resume) {
+    {
+      let mut state = self.state();
+      let depth = state.frames.len();
+      state.mode = match how {
+        Resume::Continue => Mode::Run,
+        Resume::StepIn => Mode::Step,
+        Resume::StepOver => Mode::StepTo(depth),
+        Resume::StepOut => Mode::StepTo(depth.saturating_sub(1)),
+      };
+      state.reason = None;
+    }
+    let _ = self.sender.send(Command::Resume(how));
+  }
+
+  // run `inspect` on the script's thread once it is stopped, or when it
+  // stops next
+  pub fn inspect(&self, inspect: impl FnOnce(&Paused) + Send + 'static) {
+    let _ = self.sender.send(Command::Inspect(Box::new(inspect)));
+  }
+
+  // let a script stopped for good run to its end, e.g. once the client
+  // went away
+  pub fn detach(&self) {
+    self.set_breakpoints([]);
+    self.resume(Resume::Continue);
+  }
+
+  /* =========================
+  Hooks of the evaluator
+  ========================= */
+
+  // before a statement, waiting there if it should stop
+  pub fn statement(&self, span: Span, ctx: &Context) {
+    let stop = {
+      let mut state = self.state();
+      let reason = state.stop_at(span.start_point.row);
+      reason.map(|reason| (reason, state.frames.clone()))
+    };
+
+    let Some((reason, frames)) = stop else {
+      return;
+    };
+
+    (self.on_stop)(reason);
+
+    let paused = Paused {
+      ctx,
+      frames: &frames,
+      span,
+    };
+
+    // one thread at a time waits, e.g. of `par_map`
+    let commands = self.commands.lock().unwrap_or_else(|e| e.into_inner());
+    loop {
+      match commands.recv() {
+        Ok(Command::Inspect(inspect)) => inspect(&paused),
+        Ok(Command::Resume(_)) | Err(_) => return,
+      }
+    }
+  }
+
+  // a sam function is called from `call_site`, its scopes starting at
+  // `scope_start`
+  pub fn enter(&self, name: &str, call_site: Span, scope_start: usize) {
+    self.state().frames.push(Frame {
+      name: name.to_owned(),
+      call_site: Some(call_site),
+      scope_start,
+    });
+  }
+
+  // the function entered last returned or failed
+  pub fn exit(&self) {
+    let mut state = self.state();
+    if state.frames.len() > 1 {
+      state.frames.pop();
+    }
+  }
+}
+
+impl State {
+  fn stop_at(&mut self, row: usize) -> Option<StopReason> {
+    let depth = self.frames.len();
+    let arrived = self.last != Some((row, depth));
+    self.last = Some((row, depth));
+
+    let reason = match self.mode {
+      Mode::Pause => self.reason.or(Some(StopReason::Pause)),
+      Mode::Step if arrived => Some(StopReason::Step),
+      Mode::StepTo(max) if arrived && depth <= max => Some(StopReason::Step),
+      _ if arrived && self.breakpoints.contains(&row) => {
+        Some(StopReason::Breakpoint)
+      }
+      _ => None,
+    };
+
+    if reason.is_some() {
+      self.mode = Mode::Run;
+      self.reason = None;
+    }
+
+    return reason;
+  }
+}
+
+impl fmt::Debug for Debugger {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "Debugger")
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use tree_sitter::Point;
+
+  fn at(row: usize) -> Span {
+    return Span {
+      start: 0,
+      end: 0,
+      start_point: Point::new(row, 0),
+      end_point: Point::new(row, 0),
+    };
+  }
+
+  fn state(mode: Mode, breakpoints: &[usize]) -> State {
+    return State {
+      breakpoints: breakpoints.iter().copied().collect(),
+      mode,
+      frames: vec![Frame {
+        name: "<script>".to_owned(),
+        call_site: None,
+        scope_start: 0,
+      }],
+      last: None,
+      reason: None,
+    };
+  }
+
+  #[test]
+  fn test_breakpoints_stop_once_per_line() {
+    let mut state = state(Mode::Run, &[2]);
+
+    assert_eq!(state.stop_at(1), None);
+    assert_eq!(state.stop_at(2), Some(StopReason::Breakpoint));
+    // another statement of the same line
+    assert_eq!(state.stop_at(2), None);
+    assert_eq!(state.stop_at(3), None);
+    // the line again, e.g. in the next iteration of a loop
+    assert_eq!(state.stop_at(2), Some(StopReason::Breakpoint));
+  }
+
+  #[test]
+  fn test_step_over_calls() {
+    let mut state = state(Mode::StepTo(1), &[]);
+
+    // inside a function called from the current line
+    state.frames.push(Frame {
+      name: "f".to_owned(),
+      call_site: Some(at(0)),
+      scope_start: 1,
+    });
+    assert_eq!(state.stop_at(5), None);
+    state.frames.pop();
+
+    assert_eq!(state.stop_at(1), Some(StopReason::Step));
+    assert_eq!(state.mode, Mode::Run);
+  }
+
+  #[test]
+  fn test_inspect_while_stopped() {
+    let (sender, receiver) = mpsc::channel();
+    let debugger = Debugger::new(true, move |reason| {
+      sender.send(reason).unwrap();
+    });
+
+    let driver = debugger.clone();
+    let thread = std::thread::spawn(move || {
+      assert_eq!(receiver.recv().unwrap(), StopReason::Entry);
+
+      let (rows, seen) = mpsc::channel();
+      driver.inspect(move |paused| {
+        rows.send(paused.span.start_point.row).unwrap();
+      });
+      assert_eq!(seen.recv().unwrap(), 4);
+
+      driver.resume(Resume::Continue);
+    });
+
+    let ctx = Context::new();
+    debugger.statement(at(4), &ctx);
+    // not stopped again
+    debugger.statement(at(5), &ctx);
+
+    thread.join().unwrap();
+  }
+}
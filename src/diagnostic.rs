@@ -0,0 +1,186 @@
+#![allow(dead_code)]
+
+use crate::error::{Frame, SamError, Span};
+use serde_json::json;
+use std::error::Error;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+  Error,
+  Warning,
+}
+
+impl Severity {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      Severity::Error => "error",
+      Severity::Warning => "warning",
+    }
+  }
+}
+
+// a renderable report: a headline, an optional labeled span, and notes
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+  pub severity: Severity,
+  pub code: Option<&'static str>,
+  pub message: String,
+  pub span: Option<Span>,
+  pub label: Option<String>,
+  pub notes: Vec<String>,
+  pub help: Option<String>,
+  pub trace: Vec<Frame>,
+}
+
+impl Diagnostic {
+  pub fn error(message: impl Into<String>) -> Self {
+    return Diagnostic {
+      severity: Severity::Error,
+      code: None,
+      message: message.into(),
+      span: None,
+      label: None,
+      notes: Vec::new(),
+      help: None,
+      trace: Vec::new(),
+    };
+  }
+
+  pub fn warning(message: impl Into<String>) -> Self {
+    return Diagnostic {
+      severity: Severity::Warning,
+      ..Diagnostic::error(message)
+    };
+  }
+
+  pub fn with_code(mut self, code: &'static str) -> Self {
+    self.code = Some(code);
+    return self;
+  }
+
+  pub fn with_span(mut self, span: Option<Span>) -> Self {
+    self.span = span;
+    return self;
+  }
+
+  pub fn with_label(mut self, label: impl Into<String>) -> Self {
+    self.label = Some(label.into());
+    return self;
+  }
+
+  pub fn with_note(mut self, note: impl Into<String>) -> Self {
+    self.notes.push(note.into());
+    return self;
+  }
+}
+
+/* =========================
+JSON output
+========================= */
+
+impl Diagnostic {
+  // structured form for editors and CI wrappers; lines and columns are
+  // one-based like in the human readable output
+  pub fn to_json(&self, file: &str) -> serde_json::Value {
+    let span_json = |span: &Span| {
+      json!({
+        "file": file,
+        "byte_start": span.start,
+        "byte_end": span.end,
+        "line_start": span.start_point.row + 1,
+        "column_start": span.start_point.column + 1,
+        "line_end": span.end_point.row + 1,
+        "column_end": span.end_point.column + 1,
+      })
+    };
+
+    let trace: Vec<_> = self
+      .trace
+      .iter()
+      .map(|frame| {
+        json!({
+          "name": frame.name,
+          "call_site": span_json(&frame.call_site),
+        })
+      })
+      .collect();
+
+    return json!({
+      "code": self.code,
+      "severity": self.severity.as_str(),
+      "message": self.message,
+      "span": self.span.as_ref().map(span_json),
+      "label": self.label,
+      "notes": self.notes,
+      "help": self.help,
+      "trace": trace,
+    });
+  }
+}
+
+/* =========================
+Conversion from errors
+========================= */
+
+impl From<&SamError> for Diagnostic {
+  fn from(err: &SamError) -> Self {
+    let label = match err {
+      SamError::ParseError { .. } => "invalid syntax",
+      SamError::NameError { .. } => "undefined name",
+      SamError::TypeError { .. } => "wrong type",
+      SamError::ValueError { .. } => "invalid value",
+      SamError::RuntimeError { .. } => "raised here",
+      SamError::FfiError { .. } => "external call failed",
+    };
+
+    let mut diagnostic = Diagnostic::error(err.message())
+      .with_span(err.span())
+      .with_label(label);
+
+    diagnostic.code = err.code();
+
+    // surface the chain of underlying causes as notes
+    let mut cause = err.source();
+    while let Some(c) = cause {
+      diagnostic = diagnostic.with_note(format!("caused by: {}", c));
+      cause = c.source();
+    }
+
+    diagnostic.help = err.help().map(|h| h.to_owned());
+    diagnostic.trace = err.trace().to_vec();
+
+    return diagnostic;
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::codes;
+  use tree_sitter::Point;
+
+  fn span(row: usize, start: usize, end: usize) -> Span {
+    Span {
+      start: 0,
+      end: 0,
+      start_point: Point { row, column: start },
+      end_point: Point { row, column: end },
+    }
+  }
+
+  #[test]
+  fn test_to_json() {
+    let err =
+      SamError::name(codes::UNDEFINED_VARIABLE, "Variable foo not defined")
+        .at(span(1, 8, 11))
+        .with_help("did you mean `for`?");
+    let json = Diagnostic::from(&err).to_json("main.sam");
+
+    assert_eq!(json["code"], "E0001");
+    assert_eq!(json["severity"], "error");
+    assert_eq!(json["message"], "Variable foo not defined");
+    assert_eq!(json["span"]["line_start"], 2);
+    assert_eq!(json["span"]["column_start"], 9);
+    assert_eq!(json["help"], "did you mean `for`?");
+  }
+}
@@ -0,0 +1,83 @@
+#![allow(dead_code)]
+
+// SHA-256 and HMAC-SHA256, e.g. for signing the messages of the Jupyter
+// kernel, and MD5 for the checksums of `md5`, with the hashes of the
+// RustCrypto crates.
+
+use hmac::{Hmac, Mac};
+use md5::Md5;
+use sha2::{Digest, Sha256};
+
+pub fn sha256(data: &[u8]) -> [u8; 32] {
+  return Sha256::digest(data).into();
+}
+
+pub fn hmac_sha256(key: &[u8], data: &[u8]) -> [u8; 32] {
+  // longer keys are hashed first, shorter ones padded, so any will do
+  let mut mac = Hmac::<Sha256>::new_from_slice(key).unwrap();
+  mac.update(data);
+
+  return mac.finalize().into_bytes().into();
+}
+
+// broken for signatures and passwords, still what many checksums are given
+// in
+pub fn md5(data: &[u8]) -> [u8; 16] {
+  return Md5::digest(data).into();
+}
+
+// lowercase hexadecimal, two digits per byte
+pub fn hex(bytes: &[u8]) -> String {
+  return bytes.iter().map(|b| format!("{:02x}", b)).collect();
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_sha256() {
+    assert_eq!(
+      hex(&sha256(b"")),
+      "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+    );
+    assert_eq!(
+      hex(&sha256(b"abc")),
+      "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+    );
+    // two blocks once padded
+    assert_eq!(
+      hex(&sha256(
+        b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"
+      )),
+      "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+    );
+  }
+
+  #[test]
+  fn test_md5() {
+    // RFC 1321, appendix A.5
+    assert_eq!(hex(&md5(b"")), "d41d8cd98f00b204e9800998ecf8427e");
+    assert_eq!(hex(&md5(b"abc")), "900150983cd24fb0d6963f7d28e17f72");
+    assert_eq!(
+      hex(&md5("1234567890".repeat(8).as_bytes())),
+      "57edf4a22be3c955ac49da2e2107b67a"
+    );
+  }
+
+  #[test]
+  fn test_hmac_sha256() {
+    // RFC 4231, test cases 2 and 6
+    assert_eq!(
+      hex(&hmac_sha256(b"Jefe", b"what do ya want for nothing?")),
+      "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+    );
+    assert_eq!(
+      hex(&hmac_sha256(
+        &[0xaa; 131],
+        b"Test Using Larger Than Block-Size Key - Hash Key First"
+      )),
+      "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54"
+    );
+  }
+}
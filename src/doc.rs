@@ -0,0 +1,513 @@
+#![allow(dead_code)]
+
+// `sam doc`: the API of a script, i.e. its top level functions, constants,
+// and interfaces, with the doc comments written above them:
+//
+//   //! Helpers for releases.
+//
+//   /// The tag of the release, e.g. `v1.2.0`.
+//   const TAG = 'v' + VERSION;
+//
+//   /// Push `tag` to every remote.
+//   let publish = (tag) => { ... };
+//
+// Every script is a module of its own, documented in Markdown or HTML.
+// Interfaces list the entry they load from their interface file, with the
+// parameters and result it declares. Names starting with `_` and tests are
+// left out.
+
+use crate::ast::{self, Expr, ExprKind, StmtKind};
+use crate::config::Config;
+use crate::error::SamError;
+use crate::ffi;
+use crate::testing;
+use clap::ValueEnum;
+use std::fs;
+use tree_sitter::Node;
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum Format {
+  Markdown,
+  Html,
+}
+
+impl Format {
+  pub fn extension(self) -> &'static str {
+    match self {
+      Format::Markdown => "md",
+      Format::Html => "html",
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Module {
+  pub name: String,
+  // the `//!` comments at the top of the script
+  pub doc: String,
+  pub items: Vec<Item>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Item {
+  pub name: String,
+  pub kind: ItemKind,
+  // the `///` comments right above the declaration
+  pub doc: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ItemKind {
+  Function { params: Vec<String> },
+  // with the source of its value if it fits on a line
+  Constant { value: Option<String> },
+  // `interface 'git.json' load git;`, with the entry if the file was found
+  Interface { path: String, entry: Option<Entry> },
+}
+
+// what an interface entry runs, and the types it declares
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+  // e.g. command `git`, or symbol `cos` of libm.so.6
+  pub target: String,
+  pub params: Option<Vec<String>>,
+  pub returns: Option<String>,
+}
+
+// longest constant value shown
+const MAX_VALUE: usize = 60;
+
+// the module documented by the script of source `source` and tree `root`;
+// interface files are looked up as configured by `config`
+pub fn extract(
+  root: Node,
+  source: &str,
+  name: &str,
+  config: &Config,
+) -> Result<Module, SamError> {
+  let program = ast::lower(root, source.as_bytes())?;
+  let lines: Vec<&str> = source.lines().collect();
+
+  let mut items = Vec::new();
+  for stmt in &program.statements {
+    let doc = comments_above(&lines, stmt.span.start_point.row);
+
+    match &stmt.kind {
+      StmtKind::Declaration {
+        constant,
+        declarators,
+        ..
+      } => {
+        for declarator in declarators {
+          let name = declarator.name.name.as_str();
+          if name.starts_with('_') || is_test(name, declarator.value.as_ref()) {
+            continue;
+          }
+
+          let kind = match declarator.value.as_ref().map(|v| &v.kind) {
+            Some(ExprKind::Lambda { params, .. }) => ItemKind::Function {
+              params: params.iter().map(|p| p.name.to_string()).collect(),
+            },
+            _ if *constant => ItemKind::Constant {
+              value: declarator.value.as_ref().and_then(|v| {
+                let text = source.get(v.span.start..v.span.end)?;
+                let short = !text.contains('\n') && text.len() <= MAX_VALUE;
+                short.then(|| text.to_owned())
+              }),
+            },
+            _ => continue,
+          };
+
+          items.push(Item {
+            name: name.to_owned(),
+            kind,
+            doc: doc.clone(),
+          });
+        }
+      }
+
+      StmtKind::Interfaces(interfaces) => {
+        for interface in interfaces {
+          let name = interface.module.name.as_str();
+          items.push(Item {
+            name: name.to_owned(),
+            kind: ItemKind::Interface {
+              path: interface.path.clone(),
+              entry: entry(&interface.path, name, config),
+            },
+            doc: doc.clone(),
+          });
+        }
+      }
+
+      _ => {}
+    }
+  }
+
+  let module_doc = lines
+    .iter()
+    .map(|l| l.trim())
+    .take_while(|l| l.starts_with("//!"))
+    .map(|l| comment_text(l, "//!"))
+    .collect::<Vec<_>>()
+    .join("\n");
+
+  return Ok(Module {
+    name: name.to_owned(),
+    doc: module_doc.trim().to_owned(),
+    items,
+  });
+}
+
+fn is_test(name: &str, value: Option<&Expr>) -> bool {
+  return name.starts_with(testing::PREFIX)
+    && matches!(value.map(|v| &v.kind), Some(ExprKind::Lambda { .. }));
+}
+
+// the `///` lines right above line `row`, without their slashes
+fn comments_above(lines: &[&str], row: usize) -> String {
+  let mut doc: Vec<&str> = lines[..row.min(lines.len())]
+    .iter()
+    .rev()
+    .map(|l| l.trim())
+    .take_while(|l| l.starts_with("///"))
+    .map(|l| comment_text(l, "///"))
+    .collect();
+  doc.reverse();
+
+  return doc.join("\n").trim().to_owned();
+}
+
+fn comment_text<'a>(line: &'a str, marker: &str) -> &'a str {
+  let text = &line[marker.len()..];
+  return text.strip_prefix(' ').unwrap_or(text);
+}
+
+// the entry `name` of the interface file `path`, none if the file cannot
+// be found or read; `sam doc` documents what it can rather than fail
+fn entry(path: &str, name: &str, config: &Config) -> Option<Entry> {
+  let candidates = config.interface_candidates(path);
+  let found = candidates.iter().find(|c| c.is_file())?;
+  let contents = fs::read_to_string(found).ok()?;
+  let json = ffi::parse_interface(path, &contents).ok()?;
+
+  let entry = match json.get(name)? {
+    serde_json::Value::String(cmd) => {
+      return Some(Entry {
+        target: format!("command `{}`", cmd),
+        params: None,
+        returns: None,
+      });
+    }
+    serde_json::Value::Object(entry) => entry,
+    _ => return None,
+  };
+
+  let text = |key: &str| entry.get(key).and_then(|v| v.as_str());
+  let target = match (text("command"), text("library"), text("url")) {
+    (Some(cmd), _, _) => format!("command `{}`", cmd),
+    (_, Some(library), _) => {
+      format!("symbol `{}` of {}", text("symbol").unwrap_or(name), library)
+    }
+    (_, _, Some(url)) => format!("endpoint {}", url),
+    _ => return None,
+  };
+
+  // a type, or `{ "name": "count", "type": "int" }`
+  let params = entry
+    .get("params")
+    .and_then(|p| p.as_array())
+    .map(|params| {
+      params
+        .iter()
+        .map(|p| match (p.get("name"), p.get("type")) {
+          (Some(n), Some(t)) => {
+            format!(
+              "{}: {}",
+              n.as_str().unwrap_or(""),
+              t.as_str().unwrap_or("")
+            )
+          }
+          _ => p.as_str().unwrap_or("").to_owned(),
+        })
+        .collect()
+    });
+
+  return Some(Entry {
+    target,
+    params,
+    returns: text("returns").map(|r| r.to_owned()),
+  });
+}
+
+/* =========================
+Rendering
+========================= */
+
+pub fn render(module: &Module, format: Format) -> String {
+  return match format {
+    Format::Markdown => markdown(module),
+    Format::Html => html(module),
+  };
+}
+
+// the page listing every module, `modules` being their names
+pub fn index(modules: &[String], format: Format) -> String {
+  let link = |name: &String| format!("{}.{}", name, format.extension());
+
+  return match format {
+    Format::Markdown => {
+      let mut out = "# Modules\n\n".to_owned();
+      for name in modules {
+        out.push_str(&format!("- [{}]({})\n", name, link(name)));
+      }
+      out
+    }
+    Format::Html => {
+      let mut body = "<h1>Modules</h1>\n<ul>\n".to_owned();
+      for name in modules {
+        body.push_str(&format!(
+          "<li><a href=\"{}\">{}</a></li>\n",
+          escape(&link(name)),
+          escape(name)
+        ));
+      }
+      body.push_str("</ul>\n");
+      page("Modules", &body)
+    }
+  };
+}
+
+// how an item is declared, e.g. `publish(tag)`
+fn signature(item: &Item) -> String {
+  match &item.kind {
+    ItemKind::Function { params } => {
+      format!("{}({})", item.name, params.join(", "))
+    }
+    ItemKind::Constant { value: Some(value) } => {
+      format!("const {} = {}", item.name, value)
+    }
+    ItemKind::Constant { value: None } => format!("const {}", item.name),
+    ItemKind::Interface { path, entry } => {
+      let declared = match entry {
+        Some(Entry {
+          params: Some(params),
+          returns,
+          ..
+        }) => {
+          let returns = returns.as_deref().unwrap_or("any");
+          format!("{}({}) -> {}", item.name, params.join(", "), returns)
+        }
+        Some(Entry {
+          returns: Some(returns),
+          ..
+        }) => format!("{}(...) -> {}", item.name, returns),
+        _ => item.name.clone(),
+      };
+      format!("interface '{}' load {}; // {}", path, item.name, declared)
+    }
+  }
+}
+
+// what an interface entry runs, a line of its own under the signature
+fn target(item: &Item) -> Option<String> {
+  return match &item.kind {
+    ItemKind::Interface {
+      entry: Some(entry), ..
+    } => Some(format!("Runs {}.", entry.target)),
+    ItemKind::Interface { entry: None, .. } => {
+      Some("The interface file was not found.".to_owned())
+    }
+    _ => None,
+  };
+}
+
+fn section(kind: &ItemKind) -> &'static str {
+  return match kind {
+    ItemKind::Function { .. } => "Functions",
+    ItemKind::Constant { .. } => "Constants",
+    ItemKind::Interface { .. } => "Interfaces",
+  };
+}
+
+const SECTIONS: [&str; 3] = ["Functions", "Constants", "Interfaces"];
+
+fn markdown(module: &Module) -> String {
+  let mut out = format!("# {}\n", module.name);
+  if !module.doc.is_empty() {
+    out.push_str(&format!("\n{}\n", module.doc));
+  }
+
+  for title in SECTIONS {
+    let items: Vec<_> = module
+      .items
+      .iter()
+      .filter(|i| section(&i.kind) == title)
+      .collect();
+    if items.is_empty() {
+      continue;
+    }
+
+    out.push_str(&format!("\n## {}\n", title));
+    for item in items {
+      out.push_str(&format!("\n### `{}`\n\n", item.name));
+      out.push_str(&format!("```sam\n{}\n```\n", signature(item)));
+      if let Some(target) = target(item) {
+        out.push_str(&format!("\n{}\n", target));
+      }
+      if !item.doc.is_empty() {
+        out.push_str(&format!("\n{}\n", item.doc));
+      }
+    }
+  }
+
+  return out;
+}
+
+fn html(module: &Module) -> String {
+  let mut body = format!("<h1>{}</h1>\n", escape(&module.name));
+  body.push_str(&paragraphs(&module.doc));
+
+  for title in SECTIONS {
+    let items: Vec<_> = module
+      .items
+      .iter()
+      .filter(|i| section(&i.kind) == title)
+      .collect();
+    if items.is_empty() {
+      continue;
+    }
+
+    body.push_str(&format!("<h2>{}</h2>\n", title));
+    for item in items {
+      body.push_str(&format!(
+        "<h3 id=\"{0}\"><code>{0}</code></h3>\n<pre><code>{1}</code></pre>\n",
+        escape(&item.name),
+        escape(&signature(item))
+      ));
+      if let Some(target) = target(item) {
+        body.push_str(&paragraphs(&target));
+      }
+      body.push_str(&paragraphs(&item.doc));
+    }
+  }
+
+  return page(&module.name, &body);
+}
+
+fn page(title: &str, body: &str) -> String {
+  return format!(
+    "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+     <title>{}</title>\n</head>\n<body>\n{}</body>\n</html>\n",
+    escape(title),
+    body
+  );
+}
+
+// paragraphs are separated by blank lines
+fn paragraphs(text: &str) -> String {
+  return text
+    .split("\n\n")
+    .map(|p| p.trim())
+    .filter(|p| !p.is_empty())
+    .map(|p| format!("<p>{}</p>\n", escape(p)))
+    .collect();
+}
+
+fn escape(text: &str) -> String {
+  return text
+    .replace('&', "&amp;")
+    .replace('<', "&lt;")
+    .replace('>', "&gt;")
+    .replace('"', "&quot;");
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn module() -> Module {
+    return Module {
+      name: "release".to_owned(),
+      doc: "Helpers for releases.".to_owned(),
+      items: vec![
+        Item {
+          name: "publish".to_owned(),
+          kind: ItemKind::Function {
+            params: vec!["tag".to_owned()],
+          },
+          doc: "Push `tag` to every remote.".to_owned(),
+        },
+        Item {
+          name: "git".to_owned(),
+          kind: ItemKind::Interface {
+            path: "git.json".to_owned(),
+            entry: Some(Entry {
+              target: "command `git`".to_owned(),
+              params: Some(vec!["args: array".to_owned()]),
+              returns: Some("string".to_owned()),
+            }),
+          },
+          doc: String::new(),
+        },
+      ],
+    };
+  }
+
+  #[test]
+  fn test_comments_above() {
+    let lines = vec!["let a = 1;", "/// First", "///", "/// second", "let b;"];
+    assert_eq!(comments_above(&lines, 4), "First\n\nsecond");
+    assert_eq!(comments_above(&lines, 1), "");
+  }
+
+  #[test]
+  fn test_markdown() {
+    let out = render(&module(), Format::Markdown);
+    assert!(out.starts_with("# release\n\nHelpers for releases.\n"));
+    assert!(out.contains("## Functions\n\n### `publish`\n"));
+    assert!(out.contains("publish(tag)\n"));
+    assert!(out.contains(
+      "interface 'git.json' load git; // git(args: array) -> string"
+    ));
+    assert!(out.contains("Runs command `git`."));
+    assert!(!out.contains("## Constants"));
+  }
+
+  #[test]
+  fn test_html() {
+    let out = render(&module(), Format::Html);
+    assert!(out.contains("<title>release</title>"));
+    assert!(out.contains("<p>Push `tag` to every remote.</p>"));
+    assert!(out.contains("git(args: array) -&gt; string"));
+  }
+
+  #[test]
+  fn test_extract() {
+    let source = "//! Helpers.\n\n/// The tag.\nconst TAG = 'v1';\nlet _hidden = () => {};\n/// Push it.\nlet publish = (tag, remote) => {};\nlet test_publish = () => {};\nlet count = 0;";
+    let tree = crate::parser().parse(source, None).unwrap();
+    let module =
+      extract(tree.root_node(), source, "release", &Config::default()).unwrap();
+
+    assert_eq!(module.doc, "Helpers.");
+    assert_eq!(
+      module.items,
+      vec![
+        Item {
+          name: "TAG".to_owned(),
+          kind: ItemKind::Constant {
+            value: Some("'v1'".to_owned()),
+          },
+          doc: "The tag.".to_owned(),
+        },
+        Item {
+          name: "publish".to_owned(),
+          kind: ItemKind::Function {
+            params: vec!["tag".to_owned(), "remote".to_owned()],
+          },
+          doc: "Push it.".to_owned(),
+        },
+      ]
+    );
+  }
+}
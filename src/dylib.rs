@@ -0,0 +1,322 @@
+#![allow(dead_code)]
+
+use crate::codes;
+use crate::error::SamError;
+use crate::value::{NativeFunction, Number, Value};
+#[cfg(not(target_arch = "wasm32"))]
+use libloading::Library;
+use std::ffi::{CStr, CString, c_char};
+use std::path::Path;
+use std::sync::Arc;
+
+// C types a library function can take and return
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CType {
+  // int64_t
+  I64,
+  // double
+  F64,
+  // const char *, NUL terminated UTF-8
+  Str,
+  // return type only
+  Void,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Signature {
+  pub params: Vec<CType>,
+  pub returns: CType,
+}
+
+// most parameters a library function can declare
+pub const MAX_PARAMS: usize = 4;
+
+impl CType {
+  pub fn parse(name: &str) -> Option<CType> {
+    match name {
+      "i64" => Some(CType::I64),
+      "f64" => Some(CType::F64),
+      "str" => Some(CType::Str),
+      "void" => Some(CType::Void),
+      _ => None,
+    }
+  }
+}
+
+// an argument as the C calling convention sees it: integers and pointers
+// travel in the same registers, doubles in others
+enum Word {
+  I(i64),
+  F(f64),
+}
+
+// load `symbol` from the shared library at `path` as a sam function. The
+// library stays loaded as long as the function value lives.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn load(
+  path: &Path,
+  symbol: &str,
+  signature: Signature,
+) -> Result<NativeFunction, SamError> {
+  if signature.params.len() > MAX_PARAMS
+    || signature.params.contains(&CType::Void)
+  {
+    return Err(SamError::ffi(
+      codes::INTERFACE_ENTRY,
+      format!(
+        "Library function `{}` must take at most {} parameters of type i64, \
+         f64, or str",
+        symbol, MAX_PARAMS
+      ),
+    ));
+  }
+
+  let load_error = |e: libloading::Error| {
+    SamError::ffi(
+      codes::LIBRARY_LOAD,
+      format!("Could not load `{}` from {}", symbol, path.display()),
+    )
+    .caused_by(e)
+  };
+
+  // SAFETY: loading runs the library's initializers, which is what asking
+  // for the library in an interface file means
+  let library = unsafe { Library::new(path) }.map_err(load_error)?;
+
+  // SAFETY: only the address is taken here; it is called through the
+  // declared signature, which the interface file vouches for
+  let address = unsafe {
+    *library
+      .get::<*const ()>(symbol.as_bytes())
+      .map_err(load_error)?
+  } as usize;
+
+  let library = Arc::new(library);
+  let name = symbol.to_owned();
+
+  return Ok(NativeFunction::new(symbol, move |args| {
+    // keep the library mapped while the function can still be called
+    let _ = &library;
+    call(&name, address, &signature, args)
+  }));
+}
+
+// WebAssembly has no shared libraries to load
+#[cfg(target_arch = "wasm32")]
+pub fn load(
+  path: &Path,
+  symbol: &str,
+  _signature: Signature,
+) -> Result<NativeFunction, SamError> {
+  return Err(SamError::ffi(
+    codes::LIBRARY_LOAD,
+    format!(
+      "Could not load `{}` from {}: libraries cannot be loaded in \
+       WebAssembly",
+      symbol,
+      path.display()
+    ),
+  ));
+}
+
+// call the function at `$address` with the argument words, matching each
+// word's kind at runtime to pick the function pointer type, e.g.
+// `unsafe extern "C" fn(i64, f64) -> $ret`
+macro_rules! call_as {
+  ($address:expr, $words:expr, $ret:ty) => {
+    match $words {
+      [] => dispatch!($address, $ret; []; ),
+      [a] => dispatch!($address, $ret; [a]; ),
+      [a, b] => dispatch!($address, $ret; [a, b]; ),
+      [a, b, c] => dispatch!($address, $ret; [a, b, c]; ),
+      [a, b, c, d] => dispatch!($address, $ret; [a, b, c, d]; ),
+      _ => unreachable!("signatures are limited to MAX_PARAMS"),
+    }
+  };
+}
+
+// peel one word at a time, collecting the parameter types
+macro_rules! dispatch {
+  ($address:expr, $ret:ty; []; $($arg:ident: $ty:ty,)*) => {{
+    let f: unsafe extern "C" fn($($ty),*) -> $ret =
+      std::mem::transmute($address);
+    f($(*$arg),*)
+  }};
+
+  (
+    $address:expr, $ret:ty;
+    [$word:ident $(, $rest:ident)*];
+    $($arg:ident: $ty:ty,)*
+  ) => {
+    match $word {
+      Word::I($word) => {
+        dispatch!($address, $ret; [$($rest),*]; $($arg: $ty,)* $word: i64,)
+      }
+      Word::F($word) => {
+        dispatch!($address, $ret; [$($rest),*]; $($arg: $ty,)* $word: f64,)
+      }
+    }
+  };
+}
+
+fn call(
+  name: &str,
+  address: usize,
+  signature: &Signature,
+  args: &[Value],
+) -> Result<Value, SamError> {
+  if args.len() != signature.params.len() {
+    return Err(SamError::runtime(
+      codes::ARGUMENT_COUNT,
+      format!(
+        "`{}` takes {} arguments but {} were given",
+        name,
+        signature.params.len(),
+        args.len()
+      ),
+    ));
+  }
+
+  // strings passed by pointer must outlive the call
+  let mut strings = Vec::new();
+  let mut words = Vec::with_capacity(args.len());
+
+  for (i, (arg, ty)) in args.iter().zip(&signature.params).enumerate() {
+    let word = match (ty, arg) {
+      (CType::I64, Value::SamNumber(Number::SamInt(n))) => Word::I(*n),
+      (CType::F64, Value::SamNumber(n)) => Word::F(n.as_f64()),
+      (CType::Str, Value::SamString(s)) => {
+        let s = CString::new(s.as_str()).map_err(|e| {
+          SamError::value(
+            codes::ARGUMENT_TYPE,
+            format!("Argument {} of `{}` contains a NUL byte", i + 1, name),
+          )
+          .caused_by(e)
+        })?;
+        let word = Word::I(s.as_ptr() as i64);
+        strings.push(s);
+        word
+      }
+      _ => {
+        let expected = match ty {
+          CType::I64 => "an integer",
+          CType::F64 => "a number",
+          _ => "a string",
+        };
+        return Err(SamError::type_error(
+          codes::ARGUMENT_TYPE,
+          format!(
+            "Argument {} of `{}` must be {}, found {}",
+            i + 1,
+            name,
+            expected,
+            arg.type_name()
+          ),
+        ));
+      }
+    };
+
+    words.push(word);
+  }
+
+  let words = words.as_slice();
+
+  // SAFETY: the interface file declares the signature of the symbol; the
+  // argument words match it one to one
+  let result = unsafe {
+    match signature.returns {
+      CType::Void => {
+        call_as!(address, words, ());
+        Value::Undefined
+      }
+      CType::I64 => {
+        Value::SamNumber(Number::SamInt(call_as!(address, words, i64)))
+      }
+      CType::F64 => {
+        Value::SamNumber(Number::SamFloat(call_as!(address, words, f64)))
+      }
+      CType::Str => {
+        let ptr = call_as!(address, words, i64) as *const c_char;
+
+        // the library keeps ownership of the returned string
+        if ptr.is_null() {
+          Value::Undefined
+        } else {
+          Value::from(CStr::from_ptr(ptr).to_string_lossy().into_owned())
+        }
+      }
+    }
+  };
+
+  drop(strings);
+
+  return Ok(result);
+}
+
+// the tests load the C and math libraries of glibc
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+  use super::*;
+
+  fn libm() -> &'static Path {
+    Path::new("libm.so.6")
+  }
+
+  #[test]
+  fn test_call_double() {
+    let signature = Signature {
+      params: vec![CType::F64, CType::F64],
+      returns: CType::F64,
+    };
+    let pow = load(libm(), "pow", signature).unwrap();
+
+    let args = [
+      Value::SamNumber(Number::SamInt(2)),
+      Value::SamNumber(Number::SamFloat(10.0)),
+    ];
+    assert_eq!(
+      pow.call(&args).unwrap(),
+      Value::SamNumber(Number::SamFloat(1024.0))
+    );
+  }
+
+  #[test]
+  fn test_call_string() {
+    let signature = Signature {
+      params: vec![CType::Str],
+      returns: CType::I64,
+    };
+    let strlen = load(Path::new("libc.so.6"), "strlen", signature).unwrap();
+
+    let args = [Value::from("héllo")];
+    assert_eq!(
+      strlen.call(&args).unwrap(),
+      Value::SamNumber(Number::SamInt(6))
+    );
+  }
+
+  #[test]
+  fn test_argument_checks() {
+    let signature = Signature {
+      params: vec![CType::F64],
+      returns: CType::F64,
+    };
+    let cos = load(libm(), "cos", signature).unwrap();
+
+    let err = cos.call(&[]).unwrap_err();
+    assert_eq!(err.code(), Some(codes::ARGUMENT_COUNT));
+
+    let err = cos.call(&[Value::from("0")]).unwrap_err();
+    assert_eq!(err.code(), Some(codes::ARGUMENT_TYPE));
+  }
+
+  #[test]
+  fn test_missing_symbol() {
+    let signature = Signature {
+      params: vec![],
+      returns: CType::Void,
+    };
+    let err = load(libm(), "no_such_symbol", signature).unwrap_err();
+    assert_eq!(err.code(), Some(codes::LIBRARY_LOAD));
+  }
+}
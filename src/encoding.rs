@@ -0,0 +1,193 @@
+#![allow(dead_code)]
+
+// Text encodings of bytes for `base64_encode`, `hex_encode`, and
+// `url_encode`, and back. Base64 uses the standard alphabet of RFC 4648
+// and pads with `=`, though padding may be left out when decoding. URLs
+// keep the unreserved characters of RFC 3986 and percent-encode every
+// other byte, spaces included.
+
+use crate::codes;
+use crate::digest::hex;
+use crate::error::SamError;
+use base64::DecodeError;
+use base64::alphabet;
+use base64::engine::{
+  DecodePaddingMode, Engine, GeneralPurpose, GeneralPurposeConfig,
+};
+use percent_encoding::{
+  AsciiSet, NON_ALPHANUMERIC, percent_decode, percent_encode,
+};
+
+const BASE64: GeneralPurpose = GeneralPurpose::new(
+  &alphabet::STANDARD,
+  GeneralPurposeConfig::new()
+    .with_decode_padding_mode(DecodePaddingMode::Indifferent),
+);
+
+// every byte but the unreserved characters
+const URL: &AsciiSet = &NON_ALPHANUMERIC
+  .remove(b'-')
+  .remove(b'_')
+  .remove(b'.')
+  .remove(b'~');
+
+/* =========================
+Base64
+========================= */
+
+pub fn base64_encode(bytes: &[u8]) -> String {
+  return BASE64.encode(bytes);
+}
+
+pub fn base64_decode(text: &str) -> Result<Vec<u8>, SamError> {
+  return BASE64.decode(text).map_err(|e| {
+    invalid(match e {
+      DecodeError::InvalidByte(i, _) | DecodeError::InvalidLastSymbol(i, _) => {
+        format!(
+          "Invalid base64: `{}` at position {}",
+          text[i..].chars().next().unwrap_or_default(),
+          i + 1
+        )
+      }
+      // a last character can only hold 2 or 4 bits of a byte, not 6, and
+      // padding fills out a group of 4 characters
+      DecodeError::InvalidLength(_) | DecodeError::InvalidPadding => {
+        "Invalid base64: the text ends part way through a byte".to_owned()
+      }
+    })
+  });
+}
+
+/* =========================
+Hexadecimal
+========================= */
+
+pub fn hex_encode(bytes: &[u8]) -> String {
+  return hex(bytes);
+}
+
+// two digits per byte, in either case
+pub fn hex_decode(text: &str) -> Result<Vec<u8>, SamError> {
+  if !text.len().is_multiple_of(2) {
+    return Err(invalid(format!(
+      "Invalid hexadecimal: {} digits, not two for each byte",
+      text.len()
+    )));
+  }
+
+  return text
+    .as_bytes()
+    .chunks(2)
+    .enumerate()
+    .map(|(i, pair)| {
+      return digit_pair(pair).ok_or_else(|| {
+        invalid(format!(
+          "Invalid hexadecimal: `{}` at position {}",
+          String::from_utf8_lossy(pair),
+          i * 2 + 1
+        ))
+      });
+    })
+    .collect();
+}
+
+/* =========================
+URLs
+========================= */
+
+pub fn url_encode(bytes: &[u8]) -> String {
+  return percent_encode(bytes, URL).to_string();
+}
+
+// `%XX` escapes back to their bytes; a `+` stays a plus, as it only means
+// a space in forms
+pub fn url_decode(text: &str) -> Result<Vec<u8>, SamError> {
+  // `percent_decode` would keep a `%` without digits as it is
+  for (i, _) in text.match_indices('%') {
+    if text
+      .as_bytes()
+      .get(i + 1..i + 3)
+      .and_then(digit_pair)
+      .is_none()
+    {
+      return Err(invalid(format!(
+        "Invalid URL encoding: a `%` at position {} without two hexadecimal \
+         digits after it",
+        i + 1
+      )));
+    }
+  }
+
+  return Ok(percent_decode(text.as_bytes()).collect());
+}
+
+// the byte two hexadecimal digits stand for; `from_str_radix` alone would
+// take a sign too
+fn digit_pair(pair: &[u8]) -> Option<u8> {
+  if !pair.iter().all(u8::is_ascii_hexdigit) {
+    return None;
+  }
+
+  let digits = std::str::from_utf8(pair).ok()?;
+  return u8::from_str_radix(digits, 16).ok();
+}
+
+fn invalid(message: impl Into<String>) -> SamError {
+  return SamError::value(codes::INVALID_ENCODING, message.into());
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_base64() {
+    // RFC 4648, section 10
+    let vectors = [
+      ("", ""),
+      ("f", "Zg=="),
+      ("fo", "Zm8="),
+      ("foo", "Zm9v"),
+      ("foob", "Zm9vYg=="),
+      ("fooba", "Zm9vYmE="),
+      ("foobar", "Zm9vYmFy"),
+    ];
+    for (plain, encoded) in vectors {
+      assert_eq!(base64_encode(plain.as_bytes()), encoded);
+      assert_eq!(base64_decode(encoded).unwrap(), plain.as_bytes());
+    }
+
+    assert_eq!(base64_decode("Zm9vYg").unwrap(), b"foob");
+    assert_eq!(base64_encode(&[0xfb, 0xff]), "+/8=");
+
+    for text in ["Zm9v!", "Z", "Zm9vY===", "Zg=x", "Zm9v="] {
+      let e = base64_decode(text).unwrap_err();
+      assert_eq!(e.code(), Some(codes::INVALID_ENCODING), "{}", text);
+    }
+  }
+
+  #[test]
+  fn test_hex_and_url() {
+    assert_eq!(hex_encode(&[0, 171, 255]), "00abff");
+    assert_eq!(hex_decode("00ABff").unwrap(), [0, 171, 255]);
+    assert!(
+      hex_decode("abc")
+        .unwrap_err()
+        .message()
+        .contains("3 digits")
+    );
+    assert!(hex_decode("zz").unwrap_err().message().contains("`zz`"));
+    assert!(hex_decode("+f").is_err());
+
+    let query = "a b&c=d/é~";
+    assert_eq!(url_encode(query.as_bytes()), "a%20b%26c%3Dd%2F%C3%A9~");
+    assert_eq!(
+      url_decode("a%20b%26c+d%c3%a9").unwrap(),
+      "a b&c+dé".as_bytes()
+    );
+
+    let e = url_decode("100%").unwrap_err();
+    assert_eq!(e.code(), Some(codes::INVALID_ENCODING));
+    assert!(e.message().contains("position 4"));
+  }
+}
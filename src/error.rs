@@ -0,0 +1,378 @@
+#![allow(dead_code)]
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use thiserror::Error;
+use tree_sitter::{Node, Point, Range};
+
+// boxed underlying error (io, json, ...) that caused a SamError
+pub type Cause = Box<dyn std::error::Error + Send + Sync>;
+
+// location of an error in the source, in bytes and in rows/columns
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Span {
+  pub start: usize,
+  pub end: usize,
+  #[serde(with = "point")]
+  pub start_point: Point,
+  #[serde(with = "point")]
+  pub end_point: Point,
+}
+
+// tree-sitter points as (row, column), for compiled scripts kept on disk
+mod point {
+  use serde::{Deserialize, Deserializer, Serialize, Serializer};
+  use tree_sitter::Point;
+
+  pub fn serialize<S: Serializer>(p: &Point, s: S) -> Result<S::Ok, S::Error> {
+    return (p.row, p.column).serialize(s);
+  }
+
+  pub fn deserialize<'de, D: Deserializer<'de>>(
+    d: D,
+  ) -> Result<Point, D::Error> {
+    let (row, column) = <(usize, usize)>::deserialize(d)?;
+    return Ok(Point::new(row, column));
+  }
+}
+
+// a function call the error propagated through
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+  pub name: String,
+  pub call_site: Span,
+}
+
+#[derive(Debug, Error)]
+pub enum SamError {
+  // malformed or unexpected syntax tree
+  #[error("{message}")]
+  ParseError {
+    message: String,
+    #[source]
+    cause: Option<Cause>,
+    details: Box<Details>,
+  },
+
+  // lookup of an undefined variable or function
+  #[error("{message}")]
+  NameError {
+    message: String,
+    #[source]
+    cause: Option<Cause>,
+    details: Box<Details>,
+  },
+
+  // operation applied to a value of the wrong type
+  #[error("{message}")]
+  TypeError {
+    message: String,
+    #[source]
+    cause: Option<Cause>,
+    details: Box<Details>,
+  },
+
+  // right type but invalid value (out of bounds index, bad escape, ...)
+  #[error("{message}")]
+  ValueError {
+    message: String,
+    #[source]
+    cause: Option<Cause>,
+    details: Box<Details>,
+  },
+
+  // misuse of control flow or calls (return outside function, arity, ...)
+  #[error("{message}")]
+  RuntimeError {
+    message: String,
+    #[source]
+    cause: Option<Cause>,
+    details: Box<Details>,
+  },
+
+  // failures while loading interfaces or running external commands
+  #[error("{message}")]
+  FfiError {
+    message: String,
+    #[source]
+    cause: Option<Cause>,
+    details: Box<Details>,
+  },
+}
+
+// location and context of an error, boxed to keep SamError small since it
+// travels through every evaluation result
+#[derive(Debug, Default)]
+pub struct Details {
+  pub code: Option<&'static str>,
+  pub span: Option<Span>,
+  pub trace: Vec<Frame>,
+  pub help: Option<String>,
+  // the status the script asked to exit with, by calling `exit`
+  pub exit_status: Option<u8>,
+}
+
+/* =========================
+Span conversions
+========================= */
+
+impl From<Range> for Span {
+  fn from(range: Range) -> Self {
+    Span {
+      start: range.start_byte,
+      end: range.end_byte,
+      start_point: range.start_point,
+      end_point: range.end_point,
+    }
+  }
+}
+
+impl From<&Node<'_>> for Span {
+  fn from(node: &Node<'_>) -> Self {
+    node.range().into()
+  }
+}
+
+impl From<Node<'_>> for Span {
+  fn from(node: Node<'_>) -> Self {
+    node.range().into()
+  }
+}
+
+impl fmt::Display for Span {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    // rows and columns are zero-based internally, but humans count from 1
+    write!(
+      f,
+      "{}:{}",
+      self.start_point.row + 1,
+      self.start_point.column + 1
+    )
+  }
+}
+
+/* =========================
+Constructors
+========================= */
+
+impl SamError {
+  pub fn parse(code: &'static str, message: impl Into<String>) -> Self {
+    SamError::ParseError {
+      message: message.into(),
+      cause: None,
+      details: Box::new(Details {
+        code: Some(code),
+        ..Details::default()
+      }),
+    }
+  }
+
+  pub fn name(code: &'static str, message: impl Into<String>) -> Self {
+    SamError::NameError {
+      message: message.into(),
+      cause: None,
+      details: Box::new(Details {
+        code: Some(code),
+        ..Details::default()
+      }),
+    }
+  }
+
+  pub fn type_error(code: &'static str, message: impl Into<String>) -> Self {
+    SamError::TypeError {
+      message: message.into(),
+      cause: None,
+      details: Box::new(Details {
+        code: Some(code),
+        ..Details::default()
+      }),
+    }
+  }
+
+  pub fn value(code: &'static str, message: impl Into<String>) -> Self {
+    SamError::ValueError {
+      message: message.into(),
+      cause: None,
+      details: Box::new(Details {
+        code: Some(code),
+        ..Details::default()
+      }),
+    }
+  }
+
+  pub fn runtime(code: &'static str, message: impl Into<String>) -> Self {
+    SamError::RuntimeError {
+      message: message.into(),
+      cause: None,
+      details: Box::new(Details {
+        code: Some(code),
+        ..Details::default()
+      }),
+    }
+  }
+
+  pub fn ffi(code: &'static str, message: impl Into<String>) -> Self {
+    SamError::FfiError {
+      message: message.into(),
+      cause: None,
+      details: Box::new(Details {
+        code: Some(code),
+        ..Details::default()
+      }),
+    }
+  }
+
+  // attach the location of the offending node, keeping an existing one
+  pub fn at(mut self, location: impl Into<Span>) -> Self {
+    let details = self.details_mut();
+
+    if details.span.is_none() {
+      details.span = Some(location.into());
+    }
+
+    return self;
+  }
+
+  // attach the underlying error that caused this one
+  pub fn caused_by(mut self, error: impl Into<Cause>) -> Self {
+    *self.cause_mut() = Some(error.into());
+    return self;
+  }
+
+  // record a call the error unwound through; innermost calls come first
+  pub fn with_frame(
+    mut self,
+    name: impl Into<String>,
+    call_site: Span,
+  ) -> Self {
+    self.details_mut().trace.push(Frame {
+      name: name.into(),
+      call_site,
+    });
+    return self;
+  }
+
+  // attach a hint on how to fix the error, e.g. a likely intended name
+  pub fn with_help(mut self, help: impl Into<String>) -> Self {
+    self.details_mut().help = Some(help.into());
+    return self;
+  }
+
+  // mark the error as the script exiting on purpose, with `status`
+  pub fn with_exit_status(mut self, status: u8) -> Self {
+    self.details_mut().exit_status = Some(status);
+    return self;
+  }
+
+  /* =========================
+  Accessors
+  ========================= */
+
+  pub fn message(&self) -> &str {
+    match self {
+      SamError::ParseError { message, .. }
+      | SamError::NameError { message, .. }
+      | SamError::TypeError { message, .. }
+      | SamError::ValueError { message, .. }
+      | SamError::RuntimeError { message, .. }
+      | SamError::FfiError { message, .. } => message,
+    }
+  }
+
+  pub fn code(&self) -> Option<&'static str> {
+    return self.details().code;
+  }
+
+  pub fn span(&self) -> Option<Span> {
+    return self.details().span;
+  }
+
+  pub fn trace(&self) -> &[Frame] {
+    return &self.details().trace;
+  }
+
+  pub fn help(&self) -> Option<&str> {
+    return self.details().help.as_deref();
+  }
+
+  // the status of a script that called `exit`, which is not a failure to
+  // report
+  pub fn exit_status(&self) -> Option<u8> {
+    return self.details().exit_status;
+  }
+
+  fn details(&self) -> &Details {
+    match self {
+      SamError::ParseError { details, .. }
+      | SamError::NameError { details, .. }
+      | SamError::TypeError { details, .. }
+      | SamError::ValueError { details, .. }
+      | SamError::RuntimeError { details, .. }
+      | SamError::FfiError { details, .. } => details,
+    }
+  }
+
+  fn details_mut(&mut self) -> &mut Details {
+    match self {
+      SamError::ParseError { details, .. }
+      | SamError::NameError { details, .. }
+      | SamError::TypeError { details, .. }
+      | SamError::ValueError { details, .. }
+      | SamError::RuntimeError { details, .. }
+      | SamError::FfiError { details, .. } => details,
+    }
+  }
+
+  fn cause_mut(&mut self) -> &mut Option<Cause> {
+    match self {
+      SamError::ParseError { cause, .. }
+      | SamError::NameError { cause, .. }
+      | SamError::TypeError { cause, .. }
+      | SamError::ValueError { cause, .. }
+      | SamError::RuntimeError { cause, .. }
+      | SamError::FfiError { cause, .. } => cause,
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::codes;
+  use std::error::Error;
+
+  fn span_at(row: usize, column: usize) -> Span {
+    Span {
+      start: 0,
+      end: 1,
+      start_point: Point { row, column },
+      end_point: Point {
+        row,
+        column: column + 1,
+      },
+    }
+  }
+
+  #[test]
+  fn test_at_keeps_innermost_span() {
+    let err = SamError::name(codes::UNDEFINED_VARIABLE, "x")
+      .at(span_at(1, 2))
+      .at(span_at(5, 6));
+    assert_eq!(err.span(), Some(span_at(1, 2)));
+  }
+
+  #[test]
+  fn test_caused_by_sets_source() {
+    let io = std::io::Error::new(std::io::ErrorKind::NotFound, "missing");
+    let err =
+      SamError::ffi(codes::COMMAND_FAILED, "could not run").caused_by(io);
+
+    assert_eq!(err.to_string(), "could not run");
+    assert_eq!(err.source().unwrap().to_string(), "missing");
+  }
+
+  #[test]
+  fn test_span_display_is_one_based() {
+    assert_eq!(span_at(0, 0).to_string(), "1:1");
+  }
+}
@@ -1,53 +1,172 @@
 #![allow(dead_code, unused_imports)]
 
+use crate::analysis;
+use crate::ast::{
+  self, BinaryOp, Block, Call, Declarator, Else, Expr, ExprKind, Ident, If,
+  Interface, Stmt, StmtKind,
+};
+use crate::builtins;
+use crate::codes;
+use crate::config::{Backend, Config};
 use crate::context::{Context, EvalControl, EvalResult};
-use crate::ffi::{FFI, Shell};
-use crate::value::{ForeignFunction, Function, Number, Value};
-use tree_sitter::{Node, Tree};
+use crate::coverage;
+use crate::crash;
+use crate::diagnostic::Diagnostic;
+use crate::error::{SamError, Span};
+use crate::ffi::{FFI, Shell, Stage};
+use crate::fold;
+use crate::modules;
+use crate::profile::{self, Kind};
+use crate::prune;
+use crate::suggest::did_you_mean;
+use crate::symbol::Symbol;
+use crate::syntax::syntax_errors;
+use crate::trace::{Step, Tracer};
+use crate::value::{
+  Division, ForeignFunction, Function, Handle, Number, Value,
+};
+use crate::vm;
+use std::sync::Arc;
+use tree_sitter::Node;
+
+// attach a "did you mean" hint for a name that failed to resolve
+pub(crate) fn suggest_name(
+  err: SamError,
+  name: &str,
+  ctx: &Context,
+) -> SamError {
+  match did_you_mean(name, ctx.visible_names()) {
+    Some(help) => err.with_help(help),
+    None => err,
+  }
+}
 
 fn expect_node(
   node: &Node,
   node_name: &str,
   message: &str,
-) -> Result<(), String> {
+) -> Result<(), SamError> {
   if node.kind() != node_name {
-    return Err(format!("{} {:#?}", message, node.range()));
+    return Err(SamError::parse(codes::MALFORMED_TREE, message).at(node));
   }
   Ok(())
 }
 
-pub fn evaluate<'a>(
-  root: &'a Node,
+// name of a callee that is a plain identifier, e.g. a command to run
+fn identifier(expr: &Expr) -> Result<Symbol, SamError> {
+  match &expr.kind {
+    ExprKind::Identifier(name) => Ok(*name),
+    _ => Err(
+      SamError::parse(codes::MALFORMED_TREE, "Expected identifier")
+        .at(expr.span),
+    ),
+  }
+}
+
+// how a call trace names a callee, e.g. `f` or `obj.f`
+pub(crate) fn callee_name(expr: &Expr) -> String {
+  match &expr.kind {
+    ExprKind::Identifier(name) => name.to_string(),
+    ExprKind::Member { object, property } => {
+      format!("{}.{}", callee_name(object), property.name)
+    }
+    _ => "<anonymous>".to_owned(),
+  }
+}
+
+pub fn evaluate(root: &Node, source: &[u8]) -> Result<Context, SamError> {
+  return evaluate_with_config(root, source, Config::default());
+}
+
+pub fn evaluate_with_config(
+  root: &Node,
   source: &[u8],
-  tree: &'a Tree,
-) -> Result<Context<'a>, String> {
+  config: Config,
+) -> Result<Context, SamError> {
+  let mut ctx = Context::with_config(config);
+
+  evaluate_source(root, source, &mut ctx, 0)?;
+
+  Ok(ctx)
+}
+
+// evaluate the top level statements starting at or after byte `start` in
+// an existing context, returning the value of the last one
+pub fn evaluate_source(
+  root: &Node,
+  source: &[u8],
+  ctx: &mut Context,
+  start: usize,
+) -> Result<Value, SamError> {
   expect_node(root, "source_file", "Expected source file")?;
 
-  let mut ctx = Context::new(tree);
+  // refuse to run a tree the parser had to recover from
+  if let Some(err) = syntax_errors(*root, source).into_iter().next() {
+    return Err(err);
+  }
+
+  // or one with errors that are certain to surface later; statements
+  // before `start` have already been checked and run
+  let semantic = analysis::check(*root, source)
+    .into_iter()
+    .find(|e| e.span().is_none_or(|s| s.start >= start));
+  if let Some(err) = semantic {
+    return Err(err);
+  }
 
+  // the tree is only read once, what runs is the lowered program
   let mut walker = root.walk();
-  let mut children = root.named_children(&mut walker);
-
-  // optionally check if the first is interfaces
-  if let Some(first) = children.next() {
-    if first.kind() == "interfaces" {
-      evaluate_interfaces(first, &mut ctx, source)?;
-    } else {
-      evaluate_statement(first, &mut ctx, source)?;
-    }
+  let mut statements = root
+    .named_children(&mut walker)
+    .filter(|child| child.start_byte() >= start)
+    .map(|child| ast::lower_statement(child, source))
+    .collect::<Result<Vec<Stmt>, SamError>>()?;
+
+  fold::fold_program(&mut statements, &ctx.config);
+  ctx.warnings.extend(prune::prune_program(&mut statements));
+
+  match ctx.config.backend {
+    Backend::Tree => evaluate_program(&statements, ctx),
+    Backend::Vm => vm::run(&statements, ctx),
   }
+}
 
-  // run the rest as regular
-  for child in children {
-    match evaluate_statement(child, &mut ctx, source)? {
-      EvalControl::Value(_) | EvalControl::Reference(_) => {}
+// evaluate lowered top level statements, returning the value of the last
+pub fn evaluate_program(
+  statements: &[Stmt],
+  ctx: &mut Context,
+) -> Result<Value, SamError> {
+  // every run gets the whole step budget
+  ctx.steps = 0;
+
+  let mut last = Value::Undefined;
+
+  for stmt in statements {
+    match evaluate_statement(stmt, ctx)? {
+      EvalControl::Value(v) => last = v,
+      EvalControl::Reference(v) => last = v.clone(),
       EvalControl::Return(_) => {
-        return Err("Return outside function".to_owned());
+        return Err(
+          SamError::runtime(
+            codes::RETURN_OUTSIDE_FUNCTION,
+            "Return outside function",
+          )
+          .at(stmt.span),
+        );
+      }
+      EvalControl::Break | EvalControl::Continue => {
+        return Err(
+          SamError::parse(
+            codes::BREAK_OUTSIDE_LOOP,
+            "Loop control outside loop",
+          )
+          .at(stmt.span),
+        );
       }
     }
   }
 
-  Ok(ctx)
+  Ok(last)
 }
 
 /* =========================
@@ -55,35 +174,105 @@ Interfaces
 ========================= */
 
 fn evaluate_interfaces(
-  node: Node,
+  interfaces: &[Interface],
   ctx: &mut Context,
-  source: &[u8],
-) -> Result<(), String> {
-  expect_node(&node, "interfaces", "Expected interfaces")?;
-
-  let mut walker = node.walk();
-  let interfaces = node.named_children(&mut walker);
-
+) -> Result<(), SamError> {
   for interface in interfaces {
-    evaluate_interface(interface, ctx, source)?;
+    evaluate_interface(interface, ctx)?;
   }
 
   return Ok(());
 }
 
-fn evaluate_interface(
-  node: Node,
+pub(crate) fn evaluate_interface(
+  interface: &Interface,
   ctx: &mut Context,
-  source: &[u8],
-) -> Result<(), String> {
-  expect_node(&node, "interface", "Expected interface")?;
+) -> Result<(), SamError> {
+  let path = &interface.path;
+
+  let candidates = ctx.config.interface_candidates(path);
+  let Some(found) = candidates.iter().find(|c| c.is_file()) else {
+    let tried: Vec<String> =
+      candidates.iter().map(|c| c.display().to_string()).collect();
+
+    return Err(
+      SamError::ffi(
+        codes::INTERFACE_READ,
+        format!("Interface file {} not found", path),
+      )
+      .at(interface.path_span)
+      .with_help(format!(
+        "looked in {}; add directories with --interface, `interface_path` \
+         in sam.toml, or SAM_PATH",
+        tried.join(", ")
+      )),
+    );
+  };
 
-  let path =
-    evaluate_string(node.child_by_field_name("path").unwrap(), source)?;
-  let module =
-    evaluate_identifier(node.child_by_field_name("module").unwrap(), source)?;
+  FFI::register_ffi(
+    &found.to_string_lossy(),
+    interface.module.name.as_str(),
+    ctx,
+  )?;
+
+  return Ok(());
+}
+
+/* =========================
+Limits
+========================= */
+
+// count a statement or expression against the step limit
+pub(crate) fn step(span: Span, ctx: &mut Context) -> Result<(), SamError> {
+  ctx.steps += 1;
+
+  if ctx.config.interrupt.is_interrupted() {
+    return Err(
+      SamError::runtime(codes::CANCELLED, "Evaluation was interrupted")
+        .at(span),
+    );
+  }
+
+  match ctx.config.limits.steps {
+    Some(max) if ctx.steps > max => Err(
+      SamError::runtime(
+        codes::LIMIT_EXCEEDED,
+        format!("Script exceeded its limit of {} steps", max),
+      )
+      .at(span),
+    ),
+    _ => Ok(()),
+  }
+}
+
+// a value computed by the script must fit the value size limit
+pub(crate) fn check_size(
+  v: &Value,
+  max: usize,
+  span: Span,
+) -> Result<(), SamError> {
+  let size = match v {
+    Value::SamString(s) => s.len(),
+    Value::SamBytes(b) => b.len(),
+    Value::SamArray(items) => items.len(),
+    Value::SamObject(fields) => fields.len(),
+    _ => 0,
+  };
 
-  FFI::register_ffi(&path, &module, ctx)?;
+  if size > max {
+    return Err(
+      SamError::runtime(
+        codes::LIMIT_EXCEEDED,
+        format!(
+          "A {} of size {} exceeds the limit of {}",
+          v.type_name(),
+          size,
+          max
+        ),
+      )
+      .at(span),
+    );
+  }
 
   return Ok(());
 }
@@ -92,31 +281,79 @@ fn evaluate_interface(
 Statements
 ========================= */
 
-fn evaluate_statement<'a>(
-  node: Node,
-  ctx: &'a mut Context,
-  source: &[u8],
-) -> EvalResult<'a> {
-  match node.kind() {
-    "expression_statement" => {
-      let v = evaluate_expression(node.child(0).unwrap(), ctx, source)?;
-      Ok(v)
-    }
+fn evaluate_statement<'a>(stmt: &Stmt, ctx: &'a mut Context) -> EvalResult<'a> {
+  crash::enter(stmt.span);
+  step(stmt.span, ctx)?;
+  coverage::hit(&ctx.config.coverage, stmt.span);
+  if let Some(debugger) = &ctx.config.debugger {
+    debugger.statement(stmt.span, ctx);
+  }
+
+  // an expression statement is traced as its expression
+  let tracer = match &stmt.kind {
+    StmtKind::Expression(_) => None,
+    _ => ctx.config.tracer.clone(),
+  };
+  let depth = ctx.calls;
 
-    "variable_declaration" => {
-      evaluate_variable_declaration(node, ctx, source)?;
+  let result = match &stmt.kind {
+    StmtKind::Expression(expr) => evaluate_expression(expr, ctx),
+
+    StmtKind::Declaration { declarators, .. } => {
+      for declarator in declarators {
+        evaluate_variable_declarator(declarator, ctx)?;
+      }
       Ok(EvalControl::Value(Value::Undefined))
     }
 
-    "assignment" => {
-      let v = evaluate_assignment(node, ctx, source)?;
+    StmtKind::Assignment { target, value } => {
+      let v = evaluate_assignment(target, value, stmt.span, ctx)?;
       Ok(EvalControl::Value(v))
     }
 
-    "return_statement" => evaluate_return_statement(node, ctx, source),
+    StmtKind::Return(value) => evaluate_return_statement(value.as_ref(), ctx),
+
+    StmtKind::Break => Ok(EvalControl::Break),
+
+    StmtKind::Continue => Ok(EvalControl::Continue),
+
+    // entries are loaded into the current scope, so an interface inside a
+    // block is only visible there
+    StmtKind::Interfaces(interfaces) => {
+      evaluate_interfaces(interfaces, ctx)?;
+      Ok(EvalControl::Value(Value::Undefined))
+    }
+
+    // likewise for the names of a module
+    StmtKind::Import(import) => {
+      modules::import(import, ctx)?;
+      Ok(EvalControl::Value(Value::Undefined))
+    }
+  };
 
-    _ => Err(format!("Unknown statement {:?}", node.range())),
+  if let Some(tracer) = &tracer {
+    trace_step(tracer, stmt.span, depth, &result);
   }
+
+  return result;
+}
+
+// report a finished statement or expression to `--trace`
+fn trace_step(tracer: &Tracer, span: Span, depth: usize, result: &EvalResult) {
+  let outcome = match result {
+    Ok(EvalControl::Value(v)) => v.to_string(),
+    Ok(EvalControl::Reference(v)) => v.to_string(),
+    Ok(EvalControl::Return(v)) => format!("return {}", v),
+    Ok(EvalControl::Break) => "break".to_owned(),
+    Ok(EvalControl::Continue) => "continue".to_owned(),
+    Err(e) => format!("error: {}", e.message()),
+  };
+
+  tracer.step(&Step {
+    span,
+    depth,
+    outcome,
+  });
 }
 
 /* =========================
@@ -124,139 +361,200 @@ Expressions
 ========================= */
 
 pub fn evaluate_expression<'a>(
-  node: Node,
+  expr: &Expr,
   ctx: &'a mut Context,
-  source: &[u8],
 ) -> EvalResult<'a> {
-  match node.kind() {
-    "literal" => Ok(EvalControl::Value(evaluate_literal(node, source)?)),
+  crash::enter(expr.span);
+  step(expr.span, ctx)?;
 
-    "binary_expression" => {
-      let v = evaluate_binary_expression(node, ctx, source)?;
+  let max_size = ctx.config.limits.value_size;
+  let (tracer, depth) = (ctx.config.tracer.clone(), ctx.calls);
+
+  let result = match &expr.kind {
+    ExprKind::Literal(literal) => Ok(EvalControl::Value(literal.to_value())),
+
+    ExprKind::Binary { op, left, right } => {
+      let v = evaluate_binary_expression(*op, left, right, expr.span, ctx)?;
       Ok(EvalControl::Value(v))
     }
 
-    "if_expression" => evaluate_if_expression(node, ctx, source),
+    ExprKind::If(branch) => evaluate_if_expression(branch, expr.span, ctx),
 
-    "lambda_expression" => {
-      let v = evaluate_lambda_expression(node, ctx, source)?;
-      Ok(EvalControl::Value(v))
+    ExprKind::Lambda { params, body } => {
+      let params = params.iter().map(|p| p.name).collect();
+      Ok(EvalControl::Value(Value::from(Function::new(
+        params,
+        body.clone(),
+      ))))
     }
 
-    "call_expression" => evaluate_call_expression(node, ctx, source),
+    ExprKind::Call(call) => evaluate_call_expression(call, expr.span, ctx),
 
-    "identifier" => {
-      let name = evaluate_identifier(node, source)?;
-      let Some(var) = ctx.search_in_stack(&name) else {
-        return Err(format!(
-          "Variable {} not defined {:?}",
-          name,
-          node.range()
-        ));
-      };
-      Ok(EvalControl::Reference(var))
+    ExprKind::Identifier(name) => {
+      force(*name, expr.span, ctx, call_value)?;
+      Ok(EvalControl::Reference(lookup(*name, expr.span, ctx)?))
     }
 
-    "nested_identifier" => evaluate_nested_identifier(node, ctx, source),
+    ExprKind::Member { object, property } => {
+      evaluate_nested_identifier(object, property, expr.span, ctx)
+    }
 
-    "array_expression" => {
-      let v = evaluate_array_expression(node, ctx, source)?;
+    ExprKind::Array(items) => {
+      let v = evaluate_array_expression(items, ctx)?;
       Ok(EvalControl::Value(v))
     }
 
-    "array_access_expression" => {
-      let v = evaluate_array_access_expression(node, ctx, source)?;
+    ExprKind::Index { array, index } => {
+      let v = evaluate_array_access_expression(array, index, ctx)?;
       Ok(EvalControl::Reference(v))
     }
 
-    "for_expression" => evaluate_for_expression(node, ctx, source),
+    ExprKind::For {
+      variable,
+      iterable,
+      body,
+    } => evaluate_for_expression(variable, iterable, body, expr.span, ctx),
 
-    _ => Err(format!("Unknown expression {:?}", node.range())),
+    ExprKind::Spawn(call) => {
+      let v = evaluate_spawn_expression(call, expr.span, ctx)?;
+      Ok(EvalControl::Value(v))
+    }
+
+    ExprKind::Await(handle) => {
+      let v = evaluate_await_expression(handle, expr.span, ctx)?;
+      Ok(EvalControl::Value(v))
+    }
+  };
+
+  // values only grow by being computed, references were checked before
+  if let (Some(max), Ok(EvalControl::Value(v))) = (max_size, &result) {
+    check_size(v, max, expr.span)?;
+  }
+
+  if let Some(tracer) = &tracer {
+    trace_step(tracer, expr.span, depth, &result);
   }
+
+  return result;
 }
 
-/* =========================
-Binary expression
-========================= */
+// the variable `name`, wherever in the stack it is
+pub(crate) fn lookup(
+  name: Symbol,
+  span: Span,
+  ctx: &mut Context,
+) -> Result<&Value, SamError> {
+  if ctx.search_in_stack(name).is_none() {
+    let err = SamError::name(
+      codes::UNDEFINED_VARIABLE,
+      format!("Variable {} not defined", name),
+    );
+    return Err(suggest_name(err, name.as_str(), ctx).at(span));
+  }
 
-fn evaluate_binary_expression(
-  node: Node,
+  return Ok(ctx.search_in_stack(name).unwrap());
+}
+
+// run the function of the lazy value in `name` if this is its first read,
+// keeping the result in the variable; `call` is how the backend calls it
+pub(crate) fn force(
+  name: Symbol,
+  span: Span,
   ctx: &mut Context,
-  source: &[u8],
-) -> Result<Value, String> {
-  expect_node(&node, "binary_expression", "Expected binary expression")?;
+  call: builtins::Call,
+) -> Result<(), SamError> {
+  let Some(Value::SamLazy(init)) = ctx.search_in_stack(name) else {
+    return Ok(());
+  };
+  let init = init.clone();
 
-  let left = evaluate_expression(
-    node.child_by_field_name("left").unwrap(),
-    ctx,
-    source,
-  )?
-  .to_value();
+  // a function reading its own variable sees it undefined rather than
+  // running itself again
+  *ctx.search_in_stack(name).unwrap() = Value::Undefined;
 
-  let right = evaluate_expression(
-    node.child_by_field_name("right").unwrap(),
-    ctx,
-    source,
-  )?
-  .to_value();
+  let result = call(Value::SamFunction(init.clone()), vec![], span, ctx);
 
-  let op = node.child(1).unwrap().utf8_text(source).unwrap().trim();
+  // a failed function runs again on the next read
+  let v = match &result {
+    Ok(v) => v.clone(),
+    Err(_) => Value::SamLazy(init),
+  };
+  if let Some(slot) = ctx.search_in_stack(name) {
+    *slot = v;
+  }
 
-  Ok(match op {
-    "+" => left + right,
-    "-" => left - right,
-    "*" => left * right,
-    "/" => left / right,
-    "%" => left % right,
-    "<" => (left < right).into(),
-    ">" => (left > right).into(),
-    "==" => (left == right).into(),
-    "<=" => (left <= right).into(),
-    ">=" => (left >= right).into(),
-    "!=" => (left != right).into(),
-    "&&" => (left.into() && right.into()).into(),
-    "||" => (left.into() || right.into()).into(),
-    _ => return Err(format!("Unknown operator {:?}", node.range())),
-  })
+  return result.map(|_| ());
 }
 
 /* =========================
-Variable declaration
+Binary expression
 ========================= */
 
-fn evaluate_variable_declaration(
-  node: Node,
+fn evaluate_binary_expression(
+  op: BinaryOp,
+  left: &Expr,
+  right: &Expr,
+  span: Span,
   ctx: &mut Context,
-  source: &[u8],
-) -> Result<(), String> {
-  expect_node(&node, "variable_declaration", "Expected declaration")?;
+) -> Result<Value, SamError> {
+  let left = evaluate_expression(left, ctx)?.to_value();
+  let right = evaluate_expression(right, ctx)?.to_value();
+
+  return binary(op, left, right, span, &ctx.config);
+}
 
-  let mut walker = node.walk();
-  for declarator in node.named_children(&mut walker) {
-    evaluate_variable_declarator(declarator, ctx, source)?;
+// apply an operator to evaluated operands
+pub(crate) fn binary(
+  op: BinaryOp,
+  left: Value,
+  right: Value,
+  span: Span,
+  config: &Config,
+) -> Result<Value, SamError> {
+  if config.strict {
+    Value::check_operands(op.as_str(), &left, &right)
+      .map_err(|e| e.at(span))?;
   }
 
-  Ok(())
+  Ok(match op {
+    BinaryOp::Add => left + right,
+    BinaryOp::Sub => left - right,
+    BinaryOp::Mul => left * right,
+    BinaryOp::Div => left
+      .divide(Division::Div, right, config.division_by_zero)
+      .map_err(|e| e.at(span))?,
+    BinaryOp::Rem => left
+      .divide(Division::Rem, right, config.division_by_zero)
+      .map_err(|e| e.at(span))?,
+    BinaryOp::Lt => (left < right).into(),
+    BinaryOp::Gt => (left > right).into(),
+    BinaryOp::Eq => (left == right).into(),
+    BinaryOp::Le => (left <= right).into(),
+    BinaryOp::Ge => (left >= right).into(),
+    BinaryOp::Ne => (left != right).into(),
+    BinaryOp::And => (left.into() && right.into()).into(),
+    BinaryOp::Or => (left.into() || right.into()).into(),
+  })
 }
 
+/* =========================
+Variable declaration
+========================= */
+
 fn evaluate_variable_declarator(
-  node: Node,
+  declarator: &Declarator,
   ctx: &mut Context,
-  source: &[u8],
-) -> Result<(), String> {
-  expect_node(&node, "variable_declarator", "Expected declarator")?;
-
-  let ident =
-    evaluate_identifier(node.child_by_field_name("variable").unwrap(), source)?;
-
-  let value = node
-    .child_by_field_name("value")
-    .map(|n| evaluate_expression(n, ctx, source))
-    .transpose()?
-    .map(|v| v.to_value());
+) -> Result<(), SamError> {
+  let value = declarator
+    .value
+    .as_ref()
+    .map(|v| evaluate_expression(v, ctx).map(|v| v.to_value()))
+    .transpose()?;
 
   let scope = ctx.current_scope();
-  let entry = scope.entry(ident).or_insert(Value::Undefined);
+  let entry = scope
+    .entry(declarator.name.name)
+    .or_insert(Value::Undefined);
 
   if let Some(v) = value {
     *entry = v;
@@ -270,25 +568,46 @@ Assignment
 ========================= */
 
 fn evaluate_assignment(
-  node: Node,
+  target: &Ident,
+  value: &Expr,
+  span: Span,
   ctx: &mut Context,
-  source: &[u8],
-) -> Result<Value, String> {
-  expect_node(&node, "assignment", "Expected assignment")?;
+) -> Result<Value, SamError> {
+  let rhs = evaluate_expression(value, ctx)?.to_value();
 
-  let lhs =
-    evaluate_identifier(node.child_by_field_name("lhs").unwrap(), source)?;
+  return assign(target.name, rhs, span, ctx);
+}
 
-  let rhs =
-    evaluate_expression(node.child_by_field_name("rhs").unwrap(), ctx, source)?
-      .to_value();
+// store an evaluated value in the variable `lhs`, returning it
+pub(crate) fn assign(
+  lhs: Symbol,
+  rhs: Value,
+  span: Span,
+  ctx: &mut Context,
+) -> Result<Value, SamError> {
+  if ctx.search_in_stack(lhs).is_none() {
+    let err = SamError::name(
+      codes::ASSIGN_UNDECLARED,
+      format!("Assigning to undefined variable {}", lhs),
+    );
 
-  let Some(var) = ctx.search_in_stack(&lhs) else {
-    return Err(format!(
-      "Assigning to undefined variable {:?}",
-      node.range()
-    ));
-  };
+    if ctx.config.auto_declare != Some(true) {
+      return Err(suggest_name(err, lhs.as_str(), ctx).at(span));
+    }
+
+    ctx.warnings.push(
+      Diagnostic::warning(format!("assignment implicitly declares `{}`", lhs))
+        .with_code(codes::IMPLICIT_DECLARATION)
+        .with_span(Some(span))
+        .with_label("declared here")
+        .with_note(format!("declare it with `let {} = ...;` instead", lhs)),
+    );
+
+    ctx.current_scope().insert(lhs, rhs.clone());
+    return Ok(rhs);
+  }
+
+  let var = ctx.search_in_stack(lhs).unwrap();
 
   *var = rhs.clone();
   Ok(rhs)
@@ -299,27 +618,19 @@ Attribute access
 ========================= */
 
 fn evaluate_nested_identifier<'a>(
-  node: Node,
+  object: &Expr,
+  property: &Ident,
+  span: Span,
   ctx: &'a mut Context,
-  source: &[u8],
 ) -> EvalResult<'a> {
-  let parent_node = node
-    .child_by_field_name("parent")
-    .ok_or("Missing parent in nested_identifier")?;
-
-  let name_node = node
-    .child_by_field_name("name")
-    .ok_or("Missing name in nested_identifier")?;
-
-  let EvalControl::Reference(r) =
-    evaluate_expression(parent_node, ctx, source)?
-  else {
-    return Err(format!("Expected identifier {:?}", node.range()));
+  let EvalControl::Reference(r) = evaluate_expression(object, ctx)? else {
+    return Err(
+      SamError::type_error(codes::NOT_AN_OBJECT, "Expected identifier")
+        .at(span),
+    );
   };
 
-  let key = name_node.utf8_text(source).map_err(|e| e.to_string())?;
-
-  let val = r.get_attr(&node, key)?;
+  let val = r.get_attr(span, property.name.as_str())?;
   return Ok(EvalControl::Reference(val));
 }
 
@@ -328,45 +639,40 @@ If expression
 ========================= */
 
 fn evaluate_if_expression<'a>(
-  node: Node,
+  branch: &If,
+  span: Span,
   ctx: &'a mut Context,
-  source: &[u8],
 ) -> EvalResult<'a> {
   use {Number::SamInt, Value::SamNumber};
 
-  expect_node(&node, "if_expression", "Expected if expression")?;
-
-  let cond = evaluate_expression(
-    node.child_by_field_name("condition").unwrap(),
-    ctx,
-    source,
-  )?
-  .to_value();
-
-  let SamNumber(SamInt(c)) = cond else {
-    return Err(format!("Condition must be integer {:?}", node.range()));
-  };
+  let cond = evaluate_expression(&branch.condition, ctx)?.to_value();
 
-  if c != 0 {
-    return evaluate_statement_block(
-      node.child_by_field_name("consequence").unwrap(),
-      ctx,
-      source,
-      None,
-    );
+  if condition(cond, span)? {
+    return evaluate_statement_block(&branch.consequence, ctx, None);
   }
 
-  if let Some(else_arm) = node.child_by_field_name("else") {
-    return match else_arm.kind() {
-      "statement_block" => {
-        evaluate_statement_block(else_arm, ctx, source, None)
-      }
-      "if_expression" => evaluate_if_expression(else_arm, ctx, source),
-      _ => Err(format!("Invalid else {:?}", else_arm.range())),
-    };
+  match &branch.alternative {
+    Some(Else::Block(block)) => evaluate_statement_block(block, ctx, None),
+    Some(Else::If(branch, span)) => evaluate_if_expression(branch, *span, ctx),
+    None => Ok(EvalControl::Value(Value::Undefined)),
   }
+}
 
-  Ok(EvalControl::Value(Value::Undefined))
+// whether a condition holds, conditions are integers with 0 for false
+pub(crate) fn condition(cond: Value, span: Span) -> Result<bool, SamError> {
+  use {Number::SamInt, Value::SamNumber};
+
+  let SamNumber(SamInt(c)) = cond else {
+    return Err(
+      SamError::type_error(
+        codes::NON_INTEGER_CONDITION,
+        "Condition must be integer",
+      )
+      .at(span),
+    );
+  };
+
+  return Ok(c != 0);
 }
 
 /* =========================
@@ -374,46 +680,34 @@ For expression
 ========================= */
 
 fn evaluate_for_expression<'a>(
-  node: Node,
+  variable: &Ident,
+  iterable: &Expr,
+  body: &Block,
+  span: Span,
   ctx: &'a mut Context,
-  source: &[u8],
 ) -> EvalResult<'a> {
-  expect_node(&node, "for_expression", "Expected for expression")?;
-
-  // extract iterable
-  let arr_node = node.child_by_field_name("iterable").unwrap();
-
-  // clone the iterable to loop
-  // (might be a performance bottleneck but it guarantees idempotence)
-  let Value::SamArray(arr) =
-    evaluate_expression(arr_node, ctx, source)?.to_value()
-  else {
-    return Err(format!(
-      "Expected array type in for loop {:?}",
-      arr_node.range()
-    ));
-  };
+  // clone the iterable to loop, which only shares its items but still
+  // guarantees idempotence
+  let v = evaluate_expression(iterable, ctx)?.to_value();
+  let items = iterate(v, iterable.span)?;
 
-  // get variable name
-  let var_node = node.child_by_field_name("variable").unwrap();
-  let name = evaluate_identifier(var_node, source)?;
+  let mut iterations = 0;
 
-  let body_node = node.child_by_field_name("body").unwrap();
+  // loop over the iterable, binding the current value to the variable
+  for item in items {
+    iterations += 1;
+    count_iteration(iterations, span, ctx)?;
 
-  // loop over the iterable, binding the current value to 'name'
-  for v in arr {
-    let iteration = evaluate_statement_block(
-      body_node,
-      ctx,
-      source,
-      Some(vec![(name.to_owned(), v)]),
-    )?;
+    let v = item.map_err(|e| e.at(iterable.span))?;
+    let iteration =
+      evaluate_statement_block(body, ctx, Some(vec![(variable.name, v)]))?;
 
-    // check for return
+    // check for return and break, continue already left the body
     match iteration {
       EvalControl::Return(r) => {
         return Ok(EvalControl::Return(r));
       }
+      EvalControl::Break => break,
       _ => {}
     }
   }
@@ -421,109 +715,428 @@ fn evaluate_for_expression<'a>(
   return Ok(EvalControl::Value(Value::Undefined));
 }
 
-/* =========================
-Lambda & Call
-========================= */
+pub(crate) type Items = Box<dyn Iterator<Item = Result<Value, SamError>>>;
 
-fn evaluate_lambda_expression(
-  node: Node,
-  _ctx: &mut Context,
-  source: &[u8],
-) -> Result<Value, String> {
-  expect_node(&node, "lambda_expression", "Expected lambda")?;
+// the items a for loop goes through
+pub(crate) fn iterate(v: Value, span: Span) -> Result<Items, SamError> {
+  match v {
+    // the items are shared with the array, which the loop may reassign
+    Value::SamArray(arr) => {
+      Ok(Box::new((0..arr.len()).map(move |i| Ok(arr[i].clone()))))
+    }
 
-  // retrieve byte representation for lazy evaluation
-  let range = node.child_by_field_name("body").unwrap().byte_range();
+    // lines are read as the loop asks for them
+    Value::SamStream(stream) => Ok(Box::new(std::iter::from_fn(move || {
+      stream.next().transpose()
+    }))),
+
+    _ => Err(
+      SamError::type_error(
+        codes::NOT_ITERABLE,
+        "Expected array or stream in for loop",
+      )
+      .at(span),
+    ),
+  }
+}
+
+// a loop starting its iteration number `iterations`, counted from 1
+pub(crate) fn count_iteration(
+  iterations: u64,
+  span: Span,
+  ctx: &Context,
+) -> Result<(), SamError> {
+  match ctx.config.limits.loop_iterations {
+    Some(max) if iterations > max => Err(
+      SamError::runtime(
+        codes::LIMIT_EXCEEDED,
+        format!("Loop exceeded its limit of {} iterations", max),
+      )
+      .at(span),
+    ),
+    _ => Ok(()),
+  }
+}
 
-  // temporarily represent as empty small Vec
-  let mut params = Vec::with_capacity(1);
+/* =========================
+Lambda & Call
+========================= */
 
-  // if parameters exist, replace the Vec
-  if let Some(params_node) = node.child_by_field_name("parameters") {
-    params = Function::extract_params(params_node, source)?;
+// arguments of a call, evaluated left to right
+fn evaluate_args(
+  args: &[Expr],
+  ctx: &mut Context,
+) -> Result<Vec<Value>, SamError> {
+  let mut values = Vec::with_capacity(args.len());
+
+  for arg in args {
+    match evaluate_expression(arg, ctx)? {
+      EvalControl::Value(a) => values.push(a),
+      EvalControl::Reference(a) => values.push(a.clone()),
+      _ => {
+        return Err(
+          SamError::runtime(
+            codes::RETURN_IN_EXPRESSION,
+            "Unexpected return expression.",
+          )
+          .at(arg.span),
+        );
+      }
+    }
   }
 
-  return Ok(Value::SamFunction(Function::new(range, params)));
+  Ok(values)
 }
 
 fn evaluate_call_expression<'a>(
-  node: Node,
+  call: &Call,
+  span: Span,
   ctx: &'a mut Context,
-  source: &[u8],
 ) -> EvalResult<'a> {
-  expect_node(&node, "call_expression", "Expected call")?;
-
-  let func_node = node.child_by_field_name("function").unwrap();
+  let func = call.function.as_ref();
 
-  // temporarily represent as empty small Vec
-  let mut args = Vec::with_capacity(1);
-
-  if let Some(args_node) = node.child_by_field_name("arguments") {
-    args = Function::extract_args(args_node, ctx, source)?;
+  // `pipe(ls(), grep('foo'))` connects the calls instead of running them one
+  // after the other, unless a variable named pipe hides it
+  if matches!(&func.kind, ExprKind::Identifier(name) if name == "pipe")
+    && ctx.search_in_stack("pipe".to_owned()).is_none()
+  {
+    return Ok(EvalControl::Value(evaluate_pipe(call, span, ctx)?));
   }
 
+  let args = evaluate_args(&call.args, ctx)?;
+
   // determine whether foreign or local function based on variable existence
-  match evaluate_expression(func_node, ctx, source) {
+  match evaluate_expression(func, ctx) {
     // if var found
-    Ok(f) => {
-      evaluate_local_function(f.to_value(), args, func_node, ctx, source)
-    }
+    Ok(f) => match f.to_value() {
+      // interface entries run as external commands, whichever scope they
+      // were loaded into
+      Value::SamForeignFunction(ff) => {
+        let v = call_foreign(&ff, &args, func.span, ctx, |f, args, ctx| {
+          evaluate_local_function(f, args, func, ctx).map(|v| v.to_value())
+        })?;
+        Ok(EvalControl::Value(v))
+      }
+      Value::SamNativeFunction(f) => Ok(EvalControl::Value(
+        f.call(&args).map_err(|e| e.at(func.span))?,
+      )),
+      f => evaluate_local_function(f, args, func, ctx),
+    },
 
     // if var not found
-    Err(_) => evaluate_foreign_function(args, func_node, ctx, source),
+    Err(SamError::NameError { .. }) => evaluate_shell_command(args, func, ctx),
+
+    Err(e) => Err(e),
+  }
+}
+
+// run a foreign function, which may call back the sam functions it lists by
+// name; `call_local` runs those that are not native
+pub(crate) fn call_foreign(
+  ff: &ForeignFunction,
+  args: &Vec<Value>,
+  span: Span,
+  ctx: &mut Context,
+  mut call_local: impl FnMut(
+    Value,
+    Vec<Value>,
+    &mut Context,
+  ) -> Result<Value, SamError>,
+) -> Result<Value, SamError> {
+  let options = ctx.config.call_options();
+  let _timed = profile::enter(&ctx.config.profile, Kind::Foreign, &ff.cmd);
+
+  let mut callback = |name: &str, args: Vec<Value>| {
+    let Some(f) = ctx.search_in_stack(name.to_owned()).cloned() else {
+      return Err(SamError::name(
+        codes::UNDEFINED_VARIABLE,
+        format!("Variable {} not defined", name),
+      ));
+    };
+
+    match f {
+      Value::SamNativeFunction(f) => f.call(&args),
+      f => call_local(f, args, ctx),
+    }
+  };
+
+  return FFI::call_with(ff, args, &options, &mut callback)
+    .map_err(|e| e.at(span));
+}
+
+fn evaluate_pipe(
+  call: &Call,
+  span: Span,
+  ctx: &mut Context,
+) -> Result<Value, SamError> {
+  let mut stages = Vec::new();
+
+  for arg in &call.args {
+    let ExprKind::Call(stage_call) = &arg.kind else {
+      return Err(
+        SamError::type_error(
+          codes::NOT_CALLABLE,
+          "Every argument of pipe must be a call",
+        )
+        .at(arg.span),
+      );
+    };
+
+    let func = stage_call.function.as_ref();
+    let args = evaluate_args(&stage_call.args, ctx)?;
+
+    let stage = match evaluate_expression(func, ctx) {
+      Ok(f) => stage(f.to_value(), args, func.span)?,
+
+      Err(SamError::NameError { .. }) => {
+        Stage::Shell(identifier(func)?.to_string(), args)
+      }
+
+      Err(e) => return Err(e),
+    };
+
+    stages.push(stage);
+  }
+
+  let options = ctx.config.call_options();
+
+  return FFI::pipe(stages, &options).map_err(|e| e.at(span));
+}
+
+// a stage of a pipe that calls the value `f`
+pub(crate) fn stage(
+  f: Value,
+  args: Vec<Value>,
+  span: Span,
+) -> Result<Stage, SamError> {
+  match f {
+    Value::SamForeignFunction(ff) => Ok(Stage::Foreign(ff, args)),
+    f => Err(
+      SamError::type_error(
+        codes::NOT_CALLABLE,
+        format!(
+          "Only commands and foreign functions can be piped, found {}",
+          f.type_name()
+        ),
+      )
+      .at(span),
+    ),
   }
 }
 
 fn evaluate_local_function<'a>(
   f: Value,
   args: Vec<Value>,
-  node: Node,
+  callee: &Expr,
   ctx: &'a mut Context,
-  source: &[u8],
 ) -> EvalResult<'a> {
-  if let Value::SamFunction(func) = f {
-    if args.len() != func.params.len() {
-      return Err(format!("Argument count mismatch {:?}", node.range()));
+  let Value::SamFunction(func) = f else {
+    return Err(not_callable(callee.span));
+  };
+
+  let v = run_function(&func, args, callee_name(callee), callee.span, ctx)?;
+  return Ok(EvalControl::Value(v));
+}
+
+fn run_function(
+  func: &Function,
+  args: Vec<Value>,
+  name: String,
+  span: Span,
+  ctx: &mut Context,
+) -> Result<Value, SamError> {
+  let bindings = bind_args(func, args, span, ctx)?;
+  let _timed = profile::enter(&ctx.config.profile, Kind::Function, &name);
+
+  let debugger = ctx.config.debugger.clone();
+  if let Some(debugger) = &debugger {
+    debugger.enter(&name, span, ctx.call_stack.len());
+  }
+
+  ctx.calls += 1;
+  // a return ends the call, it does not carry on into the caller
+  let result = evaluate_statement_block(&func.body, ctx, Some(bindings))
+    .map(|v| v.to_value());
+  ctx.calls -= 1;
+
+  if let Some(debugger) = &debugger {
+    debugger.exit();
+  }
+
+  return result.map_err(|e| e.with_frame(name, span));
+}
+
+// call a function value without a call expression, for builtins that are
+// given functions
+pub(crate) fn call_value(
+  f: Value,
+  args: Vec<Value>,
+  span: Span,
+  ctx: &mut Context,
+) -> Result<Value, SamError> {
+  match f {
+    Value::SamForeignFunction(ff) => {
+      call_foreign(&ff, &args, span, ctx, |f, args, ctx| {
+        call_value(f, args, span, ctx)
+      })
     }
+    Value::SamNativeFunction(f) => f.call(&args).map_err(|e| e.at(span)),
+    Value::SamFunction(func) => {
+      run_function(&func, args, "<anonymous>".to_owned(), span, ctx)
+    }
+    _ => Err(not_callable(span)),
+  }
+}
+
+// how `backend` calls function values, e.g. for `sam test` calling the
+// functions of a script that already ran
+pub(crate) fn backend_call(backend: Backend) -> builtins::Call {
+  return match backend {
+    Backend::Tree => call_value,
+    Backend::Vm => vm::call_value,
+  };
+}
 
-    let bindings = func.params.iter().cloned().zip(args).collect();
+pub(crate) fn not_callable(span: Span) -> SamError {
+  return SamError::type_error(codes::NOT_CALLABLE, "Expected function type")
+    .at(span);
+}
 
-    let body = ctx
-      .tree
-      .root_node()
-      .descendant_for_byte_range(func.body.start, func.body.end)
-      .ok_or("Function body not found")?;
+// the parameters of a sam function about to be called paired with its
+// arguments, if the call may go ahead
+pub(crate) fn bind_args(
+  func: &Function,
+  args: Vec<Value>,
+  span: Span,
+  ctx: &Context,
+) -> Result<Vec<(Symbol, Value)>, SamError> {
+  if args.len() != func.params.len() {
+    return Err(
+      SamError::runtime(codes::ARGUMENT_COUNT, "Argument count mismatch")
+        .at(span),
+    );
+  }
 
-    return evaluate_statement_block(body, ctx, source, Some(bindings));
+  match ctx.config.limits.call_depth {
+    Some(max) if ctx.calls >= max => {
+      return Err(
+        SamError::runtime(
+          codes::LIMIT_EXCEEDED,
+          format!("Script exceeded its limit of {} nested calls", max),
+        )
+        .at(span),
+      );
+    }
+    _ => {}
   }
 
-  return Err(format!("Expected function type {:?}", node.range()));
+  return Ok(func.params.iter().cloned().zip(args).collect());
 }
 
-fn evaluate_foreign_function<'a>(
+fn evaluate_shell_command<'a>(
   args: Vec<Value>,
-  func_node: Node,
+  func: &Expr,
   ctx: &'a mut Context,
-  source: &[u8],
 ) -> EvalResult<'a> {
-  // Otherwise: shell fallback
-  let command_name = match func_node.kind() {
-    "identifier" => evaluate_identifier(func_node, source)?,
-    _ => return Err(format!("Invalid shell command {:?}", func_node.range())),
+  // names that are not variables run as programs of the same name
+  let ExprKind::Identifier(command_name) = &func.kind else {
+    return Err(
+      SamError::name(codes::NOT_CALLABLE, "Invalid shell command")
+        .at(func.span),
+    );
   };
 
-  let result;
+  let v = run_command(command_name.as_str(), args, func.span, ctx, call_value)?;
+  return Ok(EvalControl::Value(v));
+}
 
-  // check for FFI or Shell command
-  if let Some(Value::SamForeignFunction(ff)) =
-    ctx.global_scope().get(&command_name)
-  {
-    result = FFI::call(ff, &args)?;
-  } else {
-    result = Shell::call(&command_name, args)?;
+// call a builtin or, failing that, run the program `name`; `call` is how
+// the backend calls the functions builtins are given
+pub(crate) fn run_command(
+  name: &str,
+  args: Vec<Value>,
+  span: Span,
+  ctx: &mut Context,
+  call: builtins::Call,
+) -> Result<Value, SamError> {
+  // builtins before programs, e.g. `cwd()`
+  if let Some(builtin) = builtins::lookup(name) {
+    let _timed = profile::enter(&ctx.config.profile, Kind::Builtin, name);
+    return builtin(args, ctx, builtins::Caller { call, span })
+      .map_err(|e| e.at(span));
   }
 
-  return Ok(EvalControl::Value(result));
+  let options = ctx.config.call_options();
+  let _timed = profile::enter(&ctx.config.profile, Kind::Program, name);
+
+  return Shell::call(name, args, &options).map_err(|e| match e {
+    SamError::NameError { .. } => suggest_name(e, name, ctx).at(span),
+    _ => e.at(span),
+  });
+}
+
+/* =========================
+Spawn and await
+========================= */
+
+fn evaluate_spawn_expression(
+  call: &Call,
+  span: Span,
+  ctx: &mut Context,
+) -> Result<Value, SamError> {
+  let func = call.function.as_ref();
+  let args = evaluate_args(&call.args, ctx)?;
+
+  let options = ctx.config.call_options();
+
+  let foreign = match evaluate_expression(func, ctx) {
+    Ok(f) => match f.to_value() {
+      Value::SamForeignFunction(ff) => ff,
+
+      Value::SamNativeFunction(f) => {
+        return Ok(Value::from(Handle::spawn(span, move || f.call(&args))));
+      }
+
+      // sam functions share the interpreter state, so they run right away
+      // and the handle is finished from the start
+      f => {
+        let v = evaluate_local_function(f, args, func, ctx)?;
+        return Ok(Value::from(Handle::finished(span, v.to_value())));
+      }
+    },
+
+    Err(SamError::NameError { .. }) => {
+      let command_name = identifier(func)?.as_str();
+
+      return Ok(Value::from(Handle::spawn(span, move || {
+        Shell::call(command_name, args, &options)
+      })));
+    }
+
+    Err(e) => return Err(e),
+  };
+
+  return Ok(Value::from(Handle::spawn(span, move || {
+    FFI::call(&foreign, &args, &options)
+  })));
+}
+
+fn evaluate_await_expression(
+  handle: &Expr,
+  span: Span,
+  ctx: &mut Context,
+) -> Result<Value, SamError> {
+  let Value::SamHandle(h) = evaluate_expression(handle, ctx)?.to_value() else {
+    return Err(
+      SamError::type_error(
+        codes::NOT_AWAITABLE,
+        "Only handles returned by spawn can be awaited",
+      )
+      .at(handle.span),
+    );
+  };
+
+  return h.join().map_err(|e| e.at(span));
 }
 
 /* =========================
@@ -531,13 +1144,10 @@ Statement block
 ========================= */
 
 fn evaluate_statement_block<'a>(
-  node: Node,
+  block: &Block,
   ctx: &'a mut Context,
-  source: &[u8],
-  bindings: Option<Vec<(String, Value)>>,
+  bindings: Option<Vec<(Symbol, Value)>>,
 ) -> EvalResult<'a> {
-  expect_node(&node, "statement_block", "Expected block")?;
-
   ctx.init_scope();
 
   if let Some(bindings) = bindings {
@@ -547,14 +1157,21 @@ fn evaluate_statement_block<'a>(
     }
   }
 
-  let mut walker = node.walk();
-  for stmt in node.named_children(&mut walker) {
-    match evaluate_statement(stmt, ctx, source)? {
+  for stmt in &block.statements {
+    match evaluate_statement(stmt, ctx)? {
       EvalControl::Value(_) | EvalControl::Reference(_) => {}
       EvalControl::Return(v) => {
         ctx.destroy_scope();
         return Ok(EvalControl::Return(v));
       }
+      EvalControl::Break => {
+        ctx.destroy_scope();
+        return Ok(EvalControl::Break);
+      }
+      EvalControl::Continue => {
+        ctx.destroy_scope();
+        return Ok(EvalControl::Continue);
+      }
     }
   }
 
@@ -567,157 +1184,119 @@ Return
 ========================= */
 
 fn evaluate_return_statement<'a>(
-  node: Node,
+  value: Option<&Expr>,
   ctx: &'a mut Context,
-  source: &[u8],
 ) -> EvalResult<'a> {
-  expect_node(&node, "return_statement", "Expected return")?;
-
-  let value = match node.child_by_field_name("value") {
-    Some(v) => evaluate_expression(v, ctx, source)?.to_value(),
+  let value = match value {
+    Some(v) => evaluate_expression(v, ctx)?.to_value(),
     None => Value::Undefined,
   };
 
   Ok(EvalControl::Return(value))
 }
 
-/* =========================
-Literals & identifiers
-========================= */
-
-fn evaluate_identifier(node: Node, source: &[u8]) -> Result<String, String> {
-  expect_node(&node, "identifier", "Expected identifier")?;
-  Ok(node.utf8_text(source).unwrap().to_owned())
-}
-
-fn evaluate_literal(node: Node, source: &[u8]) -> Result<Value, String> {
-  expect_node(&node, "literal", "Expected literal")?;
-  let child = node.child(0).unwrap();
-
-  match child.kind() {
-    "number" => Ok(Value::SamNumber(evaluate_number(child, source)?)),
-    "string" => Ok(Value::SamString(evaluate_string(child, source)?)),
-    _ => Err(format!("Unknown literal {:?}", node.range())),
-  }
-}
-
-fn evaluate_string(node: Node, source: &[u8]) -> Result<String, String> {
-  expect_node(&node, "string", "Expected string")?;
-
-  let mut result = String::new();
-  let mut walker = node.walk();
-
-  for child in node.named_children(&mut walker) {
-    match child.kind() {
-      "string_fragment" => {
-        result.push_str(child.utf8_text(source).unwrap());
-      }
-      "escape_sequence" => {
-        let esc = child.utf8_text(source).unwrap();
-        result.push(Value::decode_escape(esc)?);
-      }
-      _ => {}
-    }
-  }
-
-  return Ok(result);
-}
-
-fn evaluate_number(node: Node, source: &[u8]) -> Result<Number, String> {
-  expect_node(&node, "number", "Expected number")?;
-
-  let text = node.utf8_text(source).unwrap();
-  if text.contains('.') {
-    Ok(Number::SamFloat(text.parse().unwrap()))
-  } else {
-    Ok(Number::SamInt(text.parse().unwrap()))
-  }
-}
-
 /* =========================
 Arrays
 ========================= */
 
 fn evaluate_array_expression(
-  node: Node,
+  items: &[Expr],
   ctx: &mut Context,
-  source: &[u8],
-) -> Result<Value, String> {
-  expect_node(&node, "array_expression", "Expected array expression")?;
-
-  let mut walker = node.walk();
-
-  let mut arr = Vec::new();
+) -> Result<Value, SamError> {
+  let mut arr = Vec::with_capacity(items.len());
 
   // iterate over items in list
-  for item in node.named_children(&mut walker) {
-    let EvalControl::Value(val) = evaluate_expression(item, ctx, source)?
-    else {
-      return Err(format!("Unexpected return statement. {:#?}", item.range()));
+  for item in items {
+    let EvalControl::Value(val) = evaluate_expression(item, ctx)? else {
+      return Err(
+        SamError::runtime(
+          codes::RETURN_IN_EXPRESSION,
+          "Unexpected return statement.",
+        )
+        .at(item.span),
+      );
     };
 
     arr.push(val);
   }
 
-  return Ok(Value::SamArray(arr));
+  return Ok(Value::SamArray(Arc::new(arr)));
 }
 
 fn evaluate_array_access_expression<'a>(
-  node: Node,
+  array: &Ident,
+  index_expr: &Expr,
   ctx: &'a mut Context,
-  source: &[u8],
-) -> Result<&'a Value, String> {
-  expect_node(
-    &node,
-    "array_access_expression",
-    "Expected array access expression",
-  )?;
+) -> Result<&'a Value, SamError> {
+  let index = evaluate_expression(index_expr, ctx)?.to_value();
+  force(array.name, array.span, ctx, call_value)?;
 
-  // extract index expression
-  let index_expr = node.child_by_field_name("index").unwrap();
-
-  // evaluate index expression and check that it is of type SamInt
-  let Value::SamNumber(Number::SamInt(index)) =
-    evaluate_expression(index_expr, ctx, source)?.to_value()
-  else {
-    return Err(format!(
-      "Expected index to be of type Int {:?}",
-      node.range()
-    ));
-  };
+  return element(array.name, array.span, index, index_expr.span, ctx);
+}
 
-  // extract array variable to access
-  let var_node = node.child_by_field_name("array").unwrap();
-  let var_name = evaluate_identifier(var_node, source)?; // get string name
+// the element at an evaluated index of the array variable `var_name`
+pub(crate) fn element(
+  var_name: Symbol,
+  array_span: Span,
+  index: Value,
+  index_span: Span,
+  ctx: &mut Context,
+) -> Result<&Value, SamError> {
+  // check that the index is of type SamInt
+  let Value::SamNumber(Number::SamInt(index)) = index else {
+    return Err(
+      SamError::type_error(
+        codes::NON_INTEGER_INDEX,
+        "Expected index to be of type Int",
+      )
+      .at(index_span),
+    );
+  };
 
   // check if it exists in the stack
-  let Some(var) = ctx.search_in_stack(&var_name) else {
-    return Err(format!("Accessing undefined variable {:?}", node.range()));
-  };
+  if ctx.search_in_stack(var_name).is_none() {
+    let err = SamError::name(
+      codes::UNDEFINED_VARIABLE,
+      format!("Accessing undefined variable {}", var_name),
+    );
+    return Err(suggest_name(err, var_name.as_str(), ctx).at(array_span));
+  }
+
+  let var = ctx.search_in_stack(var_name).unwrap();
 
   // check that the variable is of type SamArray
   let arr = match var {
     Value::SamArray(arr) => arr,
     _ => {
-      return Err(format!("Expected array for accessing {:?}", node.range()));
+      return Err(
+        SamError::type_error(
+          codes::NOT_INDEXABLE,
+          "Expected array for accessing",
+        )
+        .at(array_span),
+      );
     }
   };
 
   // check valid bounds
   let index = match index {
     x if x < 0 => {
-      return Err(format!(
-        "Index cannot be negative ({}) {:?}",
-        x,
-        node.range()
-      ));
+      return Err(
+        SamError::value(
+          codes::INDEX_OUT_OF_BOUNDS,
+          format!("Index cannot be negative ({})", x),
+        )
+        .at(index_span),
+      );
     }
     x if x as usize >= arr.len() => {
-      return Err(format!(
-        "Index cannot be larger than the array length ({}) {:?}",
-        x,
-        node.range()
-      ));
+      return Err(
+        SamError::value(
+          codes::INDEX_OUT_OF_BOUNDS,
+          format!("Index cannot be larger than the array length ({})", x),
+        )
+        .at(index_span),
+      );
     }
     _ => index as usize,
   };
@@ -731,6 +1310,7 @@ fn evaluate_array_access_expression<'a>(
 #[cfg(test)]
 mod tests {
   use super::*;
+  use crate::config::Limits;
   use std::fs;
   use tree_sitter::{Language, Parser};
 
@@ -756,10 +1336,28 @@ mod tests {
 
     let root = tree.root_node();
 
-    let result = evaluate(&root, source, &tree);
+    let result = evaluate(&root, source);
     assert!(result.is_ok());
   }
 
+  #[test]
+  fn test_syntax_errors() {
+    let source = b"
+        let a = ;
+        let b = 1 +;
+    ";
+
+    let mut parser = get_parser();
+    let tree = parser.parse(source, None).unwrap();
+
+    let root = tree.root_node();
+
+    assert!(!syntax_errors(root, source).is_empty());
+
+    let result = evaluate(&root, source);
+    assert!(matches!(result, Err(SamError::ParseError { .. })));
+  }
+
   #[test]
   fn test_variable_assignment() {
     let source = b"
@@ -772,7 +1370,7 @@ mod tests {
 
     let root = tree.root_node();
 
-    let result = evaluate(&root, source, &tree);
+    let result = evaluate(&root, source);
     assert!(result.is_ok());
   }
 
@@ -788,7 +1386,7 @@ mod tests {
 
     let root = tree.root_node();
 
-    let result = evaluate(&root, source, &tree);
+    let result = evaluate(&root, source);
     assert!(result.is_ok());
     assert_eq!(
       result.unwrap().call_stack[0]["b"],
@@ -808,7 +1406,7 @@ mod tests {
 
     let root = tree.root_node();
 
-    let result = evaluate(&root, source, &tree);
+    let result = evaluate(&root, source);
     assert!(result.is_ok());
 
     assert_eq!(
@@ -828,10 +1426,151 @@ mod tests {
 
     let root = tree.root_node();
 
-    let result = evaluate(&root, source, &tree);
+    let result = evaluate(&root, source);
     assert!(!result.is_ok());
   }
 
+  #[test]
+  fn test_did_you_mean() {
+    let source = b"
+      let count = 1;
+      let b = coutn + 1;
+    ";
+
+    let mut parser = get_parser();
+    let tree = parser.parse(source, None).unwrap();
+
+    let root = tree.root_node();
+
+    let Err(err) = evaluate(&root, source) else {
+      panic!("expected an undefined variable error");
+    };
+
+    assert_eq!(err.help(), Some("did you mean `count`?"));
+  }
+
+  #[test]
+  fn test_strict_mode() {
+    let source = b"
+      let a = 'count: ' + 1;
+    ";
+
+    let mut parser = get_parser();
+    let tree = parser.parse(source, None).unwrap();
+
+    let root = tree.root_node();
+
+    // lenient by default
+    assert!(evaluate(&root, source).is_ok());
+
+    let config = Config {
+      strict: true,
+      ..Config::default()
+    };
+    let Err(err) = evaluate_with_config(&root, source, config) else {
+      panic!("expected an operand mismatch error");
+    };
+
+    assert_eq!(err.code(), Some(codes::OPERAND_MISMATCH));
+    assert_eq!(err.message(), "Cannot apply `+` to string and number");
+  }
+
+  #[test]
+  fn test_break_continue() {
+    let source = b"
+      let total = 0;
+      for x in [1, 2, 3, 4, 5] {
+        if (x == 2) { continue; }
+        if (x == 4) { break; }
+        total = total + x;
+      }
+    ";
+
+    let mut parser = get_parser();
+    let tree = parser.parse(source, None).unwrap();
+
+    let root = tree.root_node();
+
+    let mut ctx = evaluate(&root, source).unwrap();
+    let total = ctx.search_in_stack("total".to_owned()).unwrap();
+
+    assert_eq!(*total, Value::SamNumber(Number::SamInt(4)));
+  }
+
+  #[test]
+  fn test_spawn_await() {
+    let source = b"
+      let a = spawn(echo('one'));
+      let b = spawn(echo('two'));
+      let r = await(b);
+      let out = r.stdout;
+    ";
+
+    let mut parser = get_parser();
+    let tree = parser.parse(source, None).unwrap();
+
+    let root = tree.root_node();
+
+    let mut ctx = evaluate(&root, source).unwrap();
+    let out = ctx.search_in_stack("out".to_owned()).unwrap();
+
+    assert_eq!(*out, Value::from("two\n"));
+  }
+
+  #[test]
+  fn test_for_over_stream() {
+    let source = b"
+      let count = 0;
+      for line in lines {
+        count = count + 1;
+      }
+    ";
+
+    let mut parser = get_parser();
+    let tree = parser.parse(source, None).unwrap();
+
+    let root = tree.root_node();
+
+    let f = ForeignFunction {
+      stream: true,
+      ..ForeignFunction::new("seq 3".to_owned())
+    };
+    let lines = FFI::call(&f, &vec![], &Default::default()).unwrap();
+
+    let mut ctx = Context::new();
+    ctx.global_scope().insert("lines".to_owned(), lines);
+
+    evaluate_source(&root, source, &mut ctx, 0).unwrap();
+    let count = ctx.search_in_stack("count".to_owned()).unwrap();
+
+    assert_eq!(*count, Value::SamNumber(Number::SamInt(3)));
+  }
+
+  #[test]
+  fn test_auto_declare() {
+    let source = b"
+      total = 2;
+      let b = total + 1;
+    ";
+
+    let mut parser = get_parser();
+    let tree = parser.parse(source, None).unwrap();
+
+    let root = tree.root_node();
+
+    // an error unless enabled
+    assert!(evaluate(&root, source).is_err());
+
+    let config = Config {
+      auto_declare: Some(true),
+      ..Config::default()
+    };
+    let ctx = evaluate_with_config(&root, source, config).unwrap();
+
+    assert_eq!(ctx.warnings.len(), 1);
+    assert_eq!(ctx.warnings[0].code, Some(codes::IMPLICIT_DECLARATION));
+  }
+
   #[test]
   fn test_shell_fn() {
     let source = b"
@@ -843,7 +1582,7 @@ mod tests {
 
     let root = tree.root_node();
 
-    let result = evaluate(&root, source, &tree);
+    let result = evaluate(&root, source);
     assert!(result.is_ok());
   }
 
@@ -859,7 +1598,7 @@ mod tests {
 
     let root = tree.root_node();
 
-    let result = evaluate(&root, source, &tree);
+    let result = evaluate(&root, source);
     assert!(result.is_ok());
     assert_eq!(
       result.unwrap().call_stack[0]["b"],
@@ -879,10 +1618,31 @@ mod tests {
 
     let root = tree.root_node();
 
-    let result = evaluate(&root, source, &tree);
+    let result = evaluate(&root, source);
     assert!(!result.is_ok());
   }
 
+  #[test]
+  fn test_call_trace() {
+    let source = b"
+      let inner = (x) => { return x; };
+      let outer = () => { return inner(); };
+      let b = outer();
+    ";
+
+    let mut parser = get_parser();
+    let tree = parser.parse(source, None).unwrap();
+
+    let root = tree.root_node();
+
+    let Err(err) = evaluate(&root, source) else {
+      panic!("expected an argument count error");
+    };
+
+    let names: Vec<_> = err.trace().iter().map(|f| f.name.as_str()).collect();
+    assert_eq!(names, vec!["inner", "outer"]);
+  }
+
   #[test]
   fn test_strings() {
     let source = b"
@@ -895,20 +1655,14 @@ mod tests {
 
     let root = tree.root_node();
 
-    let result = evaluate(&root, source, &tree);
+    let result = evaluate(&root, source);
     assert!(result.is_ok());
 
     let result = result.unwrap();
 
-    assert_eq!(
-      result.call_stack[0]["a"],
-      Value::SamString("hello".to_owned()),
-    );
+    assert_eq!(result.call_stack[0]["a"], Value::from("hello"),);
 
-    assert_eq!(
-      result.call_stack[0]["b"],
-      Value::SamString("hello\nworld".to_owned())
-    );
+    assert_eq!(result.call_stack[0]["b"], Value::from("hello\nworld"));
   }
 
   #[test]
@@ -923,15 +1677,12 @@ mod tests {
 
     let root = tree.root_node();
 
-    let result = evaluate(&root, source, &tree);
+    let result = evaluate(&root, source);
     assert!(result.is_ok());
 
     let result = result.unwrap();
 
-    assert_eq!(
-      result.call_stack[0]["a"],
-      Value::SamString("hello world".to_owned()),
-    );
+    assert_eq!(result.call_stack[0]["a"], Value::from("hello world"),);
     assert_eq!(
       result.call_stack[0]["b"],
       Value::SamNumber(Number::SamInt(1)),
@@ -950,14 +1701,14 @@ mod tests {
 
     let root = tree.root_node();
 
-    let result = evaluate(&root, source, &tree);
+    let result = evaluate(&root, source);
     assert!(result.is_ok());
 
     let result = result.unwrap();
 
     assert_eq!(
       result.call_stack[0]["a"],
-      Value::SamArray(vec![
+      Value::from(vec![
         Value::SamNumber(Number::SamInt(1)),
         Value::SamNumber(Number::SamInt(2)),
         Value::SamNumber(Number::SamInt(3))
@@ -985,7 +1736,7 @@ mod tests {
 
     let root = tree.root_node();
 
-    let result = evaluate(&root, source, &tree);
+    let result = evaluate(&root, source);
     println!("{:#?}", result);
     assert!(result.is_ok());
 
@@ -993,10 +1744,121 @@ mod tests {
 
     assert_eq!(
       result.global_scope()["bar"],
-      Value::SamForeignFunction(ForeignFunction::new("echo 42".to_owned()))
+      Value::from(ForeignFunction::new("echo 42".to_owned()))
+    );
+  }
+
+  #[test]
+  fn test_ffi_call() {
+    let path = std::env::temp_dir().join("sam-call.json");
+    fs::write(&path, r#"{"answer": "echo 42"}"#).unwrap();
+
+    let source = b"
+    interface '/tmp/sam-call.json' load answer;
+    let x = answer();
+    ";
+
+    let mut parser = get_parser();
+    let tree = parser.parse(source, None).unwrap();
+
+    let root = tree.root_node();
+
+    let mut ctx = evaluate(&root, source).unwrap();
+    assert_eq!(
+      ctx.global_scope()["x"],
+      Value::SamNumber(Number::SamInt(42))
+    );
+  }
+
+  #[test]
+  fn test_ffi_callback() {
+    let source = b"
+      let double = (n) => { return n * 2; };
+      let x = plugin();
+    ";
+
+    let mut parser = get_parser();
+    let tree = parser.parse(source, None).unwrap();
+
+    let root = tree.root_node();
+
+    // calls back `double(21)` and prints the result it got
+    let plugin = ForeignFunction {
+      callbacks: vec!["double".to_owned()],
+      ..ForeignFunction::shell(
+        "echo '{\"jsonrpc\": \"2.0\", \"id\": 1, \"method\": \"double\", \
+         \"params\": [21]}'; read r; echo \"$r\" | sed 's/.*\"result\":\\([0-9]*\\).*/\\1/' #"
+          .to_owned(),
+      )
+    };
+
+    let mut ctx = Context::new();
+    ctx
+      .global_scope()
+      .insert("plugin".to_owned(), Value::from(plugin));
+
+    evaluate_source(&root, source, &mut ctx, 0).unwrap();
+    assert_eq!(
+      ctx.global_scope()["x"],
+      Value::SamNumber(Number::SamInt(42))
     );
   }
 
+  #[test]
+  fn test_pipe() {
+    let source = b"
+      let r = pipe(printf('a\\nb\\nab\\n'), grep('b'));
+      let out = r.stdout;
+    ";
+
+    let mut parser = get_parser();
+    let tree = parser.parse(source, None).unwrap();
+
+    let root = tree.root_node();
+
+    let mut ctx = evaluate(&root, source).unwrap();
+    let out = ctx.search_in_stack("out".to_owned()).unwrap();
+
+    assert_eq!(*out, Value::from("b\nab\n"));
+  }
+
+  #[test]
+  fn test_empty_pipe() {
+    let source = b"pipe();";
+    let tree = get_parser().parse(source, None).unwrap();
+
+    for backend in [Backend::Tree, Backend::Vm] {
+      let mut ctx = Context::with_config(Config {
+        backend,
+        ..Config::default()
+      });
+
+      let err =
+        evaluate_source(&tree.root_node(), source, &mut ctx, 0).unwrap_err();
+      assert_eq!(err.code(), Some(codes::ARGUMENT_COUNT));
+    }
+  }
+
+  #[test]
+  fn test_chdir() {
+    let source = b"
+      chdir('/');
+      let dir = cwd();
+      let r = pwd();
+      let out = r.stdout;
+    ";
+
+    let mut parser = get_parser();
+    let tree = parser.parse(source, None).unwrap();
+
+    let root = tree.root_node();
+
+    let mut ctx = evaluate(&root, source).unwrap();
+    let globals = ctx.global_scope();
+    assert_eq!(globals["dir"], Value::from("/"));
+    assert_eq!(globals["out"], Value::from("/\n"));
+  }
+
   #[test]
   fn test_for_loop() {
     let source = b"
@@ -1013,7 +1875,7 @@ mod tests {
 
     let root = tree.root_node();
 
-    let result = evaluate(&root, source, &tree);
+    let result = evaluate(&root, source);
     assert!(result.is_ok());
 
     let result = result.unwrap();
@@ -1023,4 +1885,157 @@ mod tests {
       Value::SamNumber(Number::SamInt(6))
     );
   }
+
+  #[test]
+  fn test_limits() {
+    let source = b"
+      let a = [1, 2, 3, 4];
+      let b = 0;
+      for c in a { b = b + c; };
+      let s = 'ab' + 'cd';
+    ";
+
+    let mut parser = get_parser();
+    let tree = parser.parse(source, None).unwrap();
+    let root = tree.root_node();
+
+    let run = |limits: Limits| {
+      let config = Config {
+        limits,
+        ..Config::default()
+      };
+      evaluate_with_config(&root, source, config)
+    };
+
+    assert!(run(Limits::default()).is_ok());
+
+    for limits in [
+      Limits {
+        steps: Some(10),
+        ..Limits::default()
+      },
+      Limits {
+        loop_iterations: Some(3),
+        ..Limits::default()
+      },
+      Limits {
+        value_size: Some(3),
+        ..Limits::default()
+      },
+    ] {
+      let Err(err) = run(limits) else {
+        panic!("expected {:?} to stop the script", limits);
+      };
+      assert_eq!(err.code(), Some(codes::LIMIT_EXCEEDED));
+    }
+  }
+
+  #[test]
+  fn test_profile() {
+    use crate::profile::Profiler;
+
+    let source = b"
+      let inner = (x) => { return x; };
+      let outer = () => { return inner(1) + inner(2); };
+      let a = outer();
+      let here = cwd();
+    ";
+
+    let tree = get_parser().parse(source, None).unwrap();
+
+    for backend in [Backend::Tree, Backend::Vm] {
+      let profiler = Profiler::new();
+      let mut ctx = Context::with_config(Config {
+        backend,
+        profile: Some(profiler.clone()),
+        ..Config::default()
+      });
+
+      evaluate_source(&tree.root_node(), source, &mut ctx, 0).unwrap();
+
+      let calls: Vec<_> = profiler
+        .entries()
+        .into_iter()
+        .map(|(kind, name, entry)| (kind.to_string(), name, entry.calls))
+        .collect();
+      assert!(calls.contains(&("function".to_owned(), "inner".to_owned(), 2)));
+      assert!(calls.contains(&("function".to_owned(), "outer".to_owned(), 1)));
+      assert!(calls.contains(&("builtin".to_owned(), "cwd".to_owned(), 1)));
+      assert!(profiler.folded().contains("outer;inner "));
+    }
+  }
+
+  #[test]
+  fn test_trace() {
+    use crate::trace::{Step, Tracer};
+    use std::sync::Mutex;
+
+    let source = b"let a = 3;\nlet f = (x) => { return x * 2; };\nf(a);";
+    let tree = get_parser().parse(source, None).unwrap();
+
+    let steps = Arc::new(Mutex::new(Vec::<Step>::new()));
+    let seen = steps.clone();
+    let mut ctx = Context::with_config(Config {
+      tracer: Some(Tracer::new(move |step| {
+        seen.lock().unwrap().push(step.clone())
+      })),
+      ..Config::default()
+    });
+
+    evaluate_source(&tree.root_node(), source, &mut ctx, 0).unwrap();
+
+    let steps = steps.lock().unwrap();
+    let outcomes: Vec<_> = steps
+      .iter()
+      .map(|s| (s.depth, s.outcome.as_str()))
+      .collect();
+
+    // the body of `f` runs one call deep, the call itself at the top
+    assert!(outcomes.contains(&(1, "6")));
+    assert!(outcomes.contains(&(1, "return 6")));
+    assert_eq!(outcomes.last(), Some(&(0, "6")));
+    assert_eq!(steps.last().unwrap().span.start_point.row, 2);
+  }
+
+  #[test]
+  fn test_lazy_value() {
+    use crate::value::NativeFunction;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let source = b"
+        let config = lazy(() => { return load(); });
+        let a = 1;
+        let b = config + 1;
+        let c = config + 2;
+        let unused = lazy(() => { return load(); });
+    ";
+
+    let tree = get_parser().parse(source, None).unwrap();
+
+    for backend in [Backend::Tree, Backend::Vm] {
+      // the function runs on the first read only, and never if unread
+      let runs = Arc::new(AtomicUsize::new(0));
+      let counter = runs.clone();
+
+      let mut ctx = Context::with_config(Config {
+        backend,
+        ..Config::default()
+      });
+      ctx.set_global(
+        "load",
+        NativeFunction::new("load", move |_| {
+          counter.fetch_add(1, Ordering::SeqCst);
+          return Ok(Value::from(10i64));
+        }),
+      );
+
+      evaluate_source(&tree.root_node(), source, &mut ctx, 0).unwrap();
+
+      assert_eq!(runs.load(Ordering::SeqCst), 1);
+      assert_eq!(ctx.call_stack[0]["b"], Value::from(11i64));
+      assert_eq!(ctx.call_stack[0]["c"], Value::from(12i64));
+      assert_eq!(ctx.call_stack[0]["config"], Value::from(10i64));
+      assert!(matches!(ctx.call_stack[0]["unused"], Value::SamLazy(_)));
+    }
+  }
 }
@@ -1,47 +1,365 @@
 #![allow(dead_code)]
 
+use crate::codes;
 use crate::context::Context;
-use crate::value::{ForeignFunction, Number, Value};
+use crate::dylib::{self, CType, Signature};
+use crate::error::{Cause, SamError};
+use crate::http;
+use crate::process::{
+  self, CallOptions, ExitPolicy, LineReader, Output, RunError, StderrMode,
+};
+use crate::schema;
+use crate::value::{
+  ArgPassing, ForeignFunction, ForeignSignature, Number, OutputFormat, Param,
+  Type, Value,
+};
 use serde_json;
 use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fmt;
 use std::fs;
+use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 pub struct Shell;
 pub struct FFI;
 
+// error for a command that did not run to completion
+fn run_error(e: RunError, what: &str, name: &str) -> SamError {
+  match e {
+    RunError::Timeout(limit) => SamError::ffi(
+      codes::TIMEOUT,
+      format!(
+        "{} `{}` timed out after {}",
+        what,
+        name,
+        process::format_duration(limit)
+      ),
+    ),
+    RunError::Interrupted => SamError::runtime(
+      codes::CANCELLED,
+      format!("{} `{}` was interrupted", what, name),
+    ),
+    RunError::Spawn(e) | RunError::Io(e) => SamError::ffi(
+      codes::COMMAND_FAILED,
+      format!("Could not run {} `{}`.", what, name),
+    )
+    .caused_by(e),
+  }
+}
+
+// what the sandbox asks of every external call before it starts: the
+// program may run, the variables set for it do not change what it runs, and
+// the directory it runs in, from `chdir` or an interface entry, may be read
+fn check_start(cmd: &Command, options: &CallOptions) -> Result<(), SamError> {
+  let permissions = &options.permissions;
+  permissions.check_run(&cmd.get_program().to_string_lossy())?;
+  permissions.check_env(&options.env)?;
+  if let Some(dir) = &options.cwd {
+    permissions.check_read(dir)?;
+  }
+
+  return Ok(());
+}
+
 impl Shell {
-  pub fn call(name: &str, args: Vec<Value>) -> Result<Value, String> {
+  pub fn call(
+    name: &str,
+    args: Vec<Value>,
+    options: &CallOptions,
+  ) -> Result<Value, SamError> {
     // fallback shell call
     let mut cmd = Command::new(name);
+    check_start(&cmd, options)?;
 
     for arg in args {
-      cmd.arg(arg.to_string());
+      cmd.arg(argument(&arg));
     }
 
-    let output = cmd.output().map_err(|e| e.to_string())?;
+    let output = process::run(cmd, None, options).map_err(|e| match e {
+      // a missing program means the name is simply not defined anywhere
+      RunError::Spawn(e) if e.kind() == std::io::ErrorKind::NotFound => {
+        SamError::name(
+          codes::UNKNOWN_COMMAND,
+          format!("`{}` is neither a variable nor a command", name),
+        )
+      }
+      e => run_error(e, "shell command", name),
+    })?;
 
-    // return obj
-    let mut obj = HashMap::new();
+    let stdout =
+      Value::from(String::from_utf8_lossy(&output.stdout).to_string());
 
-    obj.insert(
-      "stdout".to_string(),
-      Value::SamString(String::from_utf8_lossy(&output.stdout).to_string()),
-    );
+    return Ok(result_object(&output, stdout));
+  }
+}
 
-    obj.insert(
-      "stderr".to_string(),
-      Value::SamString(String::from_utf8_lossy(&output.stderr).to_string()),
-    );
+fn check_args(
+  cmd: &str,
+  signature: &ForeignSignature,
+  args: &[Value],
+) -> Result<(), SamError> {
+  if args.len() != signature.params.len() {
+    return Err(SamError::runtime(
+      codes::ARGUMENT_COUNT,
+      format!(
+        "`{}` takes {} arguments but {} were given",
+        cmd,
+        signature.params.len(),
+        args.len()
+      ),
+    ));
+  }
 
-    obj.insert(
-      "status".to_string(),
-      Value::SamNumber(Number::SamInt(
-        output.status.code().unwrap_or(-1) as i64
-      )),
-    );
+  for (i, (arg, param)) in args.iter().zip(&signature.params).enumerate() {
+    if param.ty.accepts(arg) {
+      continue;
+    }
+
+    let which = match &param.name {
+      Some(name) => format!("Argument {} (`{}`)", i + 1, name),
+      None => format!("Argument {}", i + 1),
+    };
+
+    return Err(SamError::type_error(
+      codes::ARGUMENT_TYPE,
+      format!(
+        "{} of `{}` must be of type {}, found {}",
+        which,
+        cmd,
+        param.ty.name(),
+        arg.type_name()
+      ),
+    ));
+  }
+
+  return Ok(());
+}
+
+fn check_result(
+  cmd: &str,
+  signature: &ForeignSignature,
+  result: &Value,
+) -> Result<(), SamError> {
+  if signature.returns.accepts(result) {
+    return Ok(());
+  }
+
+  return Err(SamError::ffi(
+    codes::FOREIGN_OUTPUT,
+    format!(
+      "`{}` returned {} but is declared to return {}",
+      cmd,
+      result.type_name(),
+      signature.returns.name()
+    ),
+  ));
+}
+
+// interface files are JSON unless their extension says TOML or YAML; the
+// entries look the same in every format
+pub(crate) fn parse_interface(
+  path: &str,
+  contents: &str,
+) -> Result<serde_json::Value, Cause> {
+  let extension = Path::new(path).extension().and_then(|e| e.to_str());
+
+  return match extension {
+    Some("toml") => Ok(toml::from_str(contents)?),
+    Some("yaml" | "yml") => Ok(serde_yaml_ng::from_str(contents)?),
+    _ => Ok(serde_json::from_str(contents)?),
+  };
+}
+
+// a time limit in seconds, fractions allowed
+fn seconds(v: &serde_json::Value) -> Option<Duration> {
+  return v
+    .as_f64()
+    .and_then(|secs| Duration::try_from_secs_f64(secs).ok());
+}
+
+// error for a foreign function that exited unsuccessfully
+fn exit_error(cmd: &str, output: &Output) -> SamError {
+  let err = SamError::ffi(
+    codes::COMMAND_EXIT,
+    format!(
+      "Foreign function `{}` exited with status {}",
+      cmd,
+      process::status_code(output.status)
+    ),
+  )
+  .with_help(
+    "set `\"exit_status\": \"result\"` on the interface entry to handle \
+     failures in the script",
+  );
+
+  let stderr = String::from_utf8_lossy(&output.stderr);
+  if stderr.trim().is_empty() {
+    return err;
+  }
+
+  return err.caused_by(stderr.trim().to_owned());
+}
+
+// `{ok, status, stdout, stderr}` describing a finished command, shared by
+// shell calls and foreign functions returning their result
+fn result_object(output: &Output, stdout: Value) -> Value {
+  let mut obj = HashMap::new();
+  let status = process::status_code(output.status);
+
+  obj.insert(
+    "ok".to_string(),
+    Value::SamNumber(Number::SamInt(output.status.success() as i64)),
+  );
+
+  obj.insert(
+    "status".to_string(),
+    Value::SamNumber(Number::SamInt(status)),
+  );
+
+  obj.insert("stdout".to_string(), stdout);
+
+  obj.insert(
+    "stderr".to_string(),
+    Value::from(String::from_utf8_lossy(&output.stderr).to_string()),
+  );
+
+  return Value::SamObject(Arc::new(obj));
+}
+
+/* =========================
+Streams
+========================= */
+
+// output of a foreign function with `"stream": true`, read line by line
+// while the command runs, e.g. by a `for` loop
+#[derive(Clone)]
+pub struct Stream {
+  pub cmd: String,
+  exit_status: ExitPolicy,
+  // none once the command exited
+  reader: Arc<Mutex<Option<LineReader>>>,
+}
+
+impl Stream {
+  // the next line as a string, None once the output ended
+  pub fn next(&self) -> Result<Option<Value>, SamError> {
+    let mut reader = self.reader.lock().unwrap_or_else(|e| e.into_inner());
+
+    let Some(lines) = reader.as_mut() else {
+      return Ok(None);
+    };
+
+    match lines.next_line() {
+      Some(Ok(line)) => return Ok(Some(Value::from(line))),
+      Some(Err(e)) => {
+        *reader = None;
+        return Err(run_error(e, "foreign function", &self.cmd));
+      }
+      None => {}
+    }
+
+    let output = lines.finish();
+    *reader = None;
 
-    return Ok(Value::SamObject(obj));
+    let output =
+      output.map_err(|e| run_error(e, "foreign function", &self.cmd))?;
+
+    if !output.status.success() && self.exit_status == ExitPolicy::Error {
+      return Err(exit_error(&self.cmd, &output));
+    }
+
+    return Ok(None);
+  }
+
+  pub fn same(&self, other: &Stream) -> bool {
+    return Arc::ptr_eq(&self.reader, &other.reader);
+  }
+}
+
+impl fmt::Debug for Stream {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "Stream({})", self.cmd)
+  }
+}
+
+/* =========================
+Result cache
+========================= */
+
+// results of earlier calls by their JSON encoded arguments, shared by every
+// copy of the function value
+#[derive(Debug)]
+pub struct CallCache {
+  // none keeps results forever
+  ttl: Option<Duration>,
+  results: Mutex<HashMap<String, (Instant, Value)>>,
+}
+
+impl CallCache {
+  pub fn new(ttl: Option<Duration>) -> Self {
+    return CallCache {
+      ttl,
+      results: Mutex::new(HashMap::new()),
+    };
+  }
+
+  fn get(&self, key: &str) -> Option<Value> {
+    let results = self.results.lock().unwrap_or_else(|e| e.into_inner());
+    let (stored, value) = results.get(key)?;
+
+    if self.ttl.is_some_and(|ttl| stored.elapsed() > ttl) {
+      return None;
+    }
+
+    return Some(value.clone());
+  }
+
+  fn insert(&self, key: String, value: Value) {
+    let mut results = self.results.lock().unwrap_or_else(|e| e.into_inner());
+    results.insert(key, (Instant::now(), value));
+  }
+}
+
+/* =========================
+Pipelines
+========================= */
+
+// one command of a pipeline with its arguments
+pub enum Stage {
+  Foreign(Arc<ForeignFunction>, Vec<Value>),
+  // a program called by name, like a shell call
+  Shell(String, Vec<Value>),
+}
+
+impl Stage {
+  fn name(&self) -> &str {
+    match self {
+      Stage::Foreign(f, _) => &f.cmd,
+      Stage::Shell(name, _) => name,
+    }
+  }
+}
+
+/* =========================
+Coprocesses
+========================= */
+
+// the process of a foreign function with `"persistent": true`, started by
+// the first call and kept running for the next ones. Every call writes the
+// arguments as a line with a JSON array and reads the result from the next
+// line of output.
+#[derive(Default)]
+pub struct Coprocess {
+  // none until the first call, and again after the process exited
+  reader: Mutex<Option<LineReader>>,
+}
+
+impl fmt::Debug for Coprocess {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "Coprocess")
   }
 }
 
@@ -50,79 +368,835 @@ impl FFI {
     path: &str,
     name: &str,
     ctx: &mut Context,
-  ) -> Result<(), String> {
-    let Ok(contents) = fs::read_to_string(&path) else {
-      return Err(format!("There was an error in reading from {}.", path));
-    };
+  ) -> Result<(), SamError> {
+    ctx.config.permissions.check_read(Path::new(path))?;
 
-    let Ok(json): Result<serde_json::Value, _> =
-      serde_json::from_str(&contents)
-    else {
-      return Err(format!(
-        "There was an error in parsing {} from {}.",
-        name, path
-      ));
+    let contents = fs::read_to_string(path).map_err(|e| {
+      SamError::ffi(
+        codes::INTERFACE_READ,
+        format!("There was an error in reading from {}.", path),
+      )
+      .caused_by(e)
+    })?;
+
+    let json = parse_interface(path, &contents).map_err(|e| {
+      SamError::ffi(
+        codes::INTERFACE_PARSE,
+        format!("There was an error in parsing {} from {}.", name, path),
+      )
+      .caused_by(e)
+    })?;
+
+    schema::validate(path, &contents, &json)?;
+
+    let invalid = || {
+      SamError::ffi(
+        codes::INTERFACE_ENTRY,
+        format!(
+          "Interface entry {} must be a command string or an object with a \
+           `command`, `library`, or `url`",
+          name
+        ),
+      )
     };
 
-    let cmd = json
-      .get(&name)
-      .and_then(|v| v.as_str())
-      .ok_or("Interface entry must be a string")?;
+    // either "cmd", or an object with options:
+    //   { "command": "cmd", "shell": true, "input": "json", "timeout": 5,
+    //     "env": { "STAGE": "prod" }, "clear_env": true, "stream": true,
+    //     "callbacks": ["log"], "persistent": true, "cwd": "build",
+    //     "stderr": "inherit" }
+    let function = match json.get(name) {
+      Some(serde_json::Value::String(cmd)) => ForeignFunction::new(cmd.clone()),
 
-    ctx.current_scope().insert(
-      name.to_owned(),
-      Value::SamForeignFunction(ForeignFunction::new(cmd.to_owned())),
-    );
+      // a symbol of a shared library, see `library_entry`
+      Some(serde_json::Value::Object(entry))
+        if entry.contains_key("library") =>
+      {
+        let (library, symbol, signature) =
+          Self::library_entry(path, name, entry).ok_or_else(invalid)?;
+        let permissions = &ctx.config.permissions;
+        permissions.check_run(&library.to_string_lossy())?;
+
+        let native = dylib::load(&library, &symbol, signature)?;
+
+        ctx
+          .current_scope()
+          .insert(name.to_owned(), Value::from(native));
+
+        return Ok(());
+      }
+
+      // an HTTP endpoint, `{ "url": "http://localhost:8080/predict" }`,
+      // called with a POST of the JSON encoded arguments
+      Some(serde_json::Value::Object(entry)) if entry.contains_key("url") => {
+        let url = entry
+          .get("url")
+          .and_then(|u| u.as_str())
+          .ok_or_else(invalid)?;
+
+        let timeout = match entry.get("timeout") {
+          None => ctx.config.call_options().timeout,
+          Some(t) => Some(seconds(t).ok_or_else(invalid)?),
+        };
+
+        let permissions = ctx.config.permissions.clone();
+        let endpoint =
+          http::endpoint(name, url.to_owned(), timeout, permissions);
+
+        ctx
+          .current_scope()
+          .insert(name.to_owned(), Value::from(endpoint));
+
+        return Ok(());
+      }
+
+      Some(serde_json::Value::Object(entry)) => {
+        let cmd = entry
+          .get("command")
+          .and_then(|c| c.as_str())
+          .ok_or_else(invalid)?
+          .to_owned();
+
+        let input = match entry.get("input").and_then(|i| i.as_str()) {
+          None | Some("argv") => ArgPassing::Argv,
+          Some("json") => ArgPassing::JsonStdin,
+          Some("bytes") => ArgPassing::RawStdin,
+          Some(_) => return Err(invalid()),
+        };
+
+        let output = match entry.get("output").and_then(|o| o.as_str()) {
+          None | Some("json") => OutputFormat::Json,
+          Some("bytes") => OutputFormat::Bytes,
+          Some(_) => return Err(invalid()),
+        };
+
+        let timeout = match entry.get("timeout") {
+          None => None,
+          Some(t) => Some(seconds(t).ok_or_else(invalid)?),
+        };
+
+        // variable values must be strings
+        let env = match entry.get("env") {
+          None => HashMap::new(),
+          Some(serde_json::Value::Object(vars)) => vars
+            .iter()
+            .map(|(k, v)| Some((k.clone(), v.as_str()?.to_owned())))
+            .collect::<Option<_>>()
+            .ok_or_else(invalid)?,
+          Some(_) => return Err(invalid()),
+        };
+
+        let exit_status =
+          match entry.get("exit_status").and_then(|e| e.as_str()) {
+            None => None,
+            Some("error") => Some(ExitPolicy::Error),
+            Some("result") => Some(ExitPolicy::Result),
+            Some(_) => return Err(invalid()),
+          };
+
+        // names of the sam functions the command may call, see `converse`
+        let callbacks = match entry.get("callbacks") {
+          None => Vec::new(),
+          Some(serde_json::Value::Array(names)) => names
+            .iter()
+            .map(|n| Some(n.as_str()?.to_owned()))
+            .collect::<Option<_>>()
+            .ok_or_else(invalid)?,
+          Some(_) => return Err(invalid()),
+        };
+
+        // relative to the interface file, like library paths
+        let cwd = match entry.get("cwd") {
+          None => None,
+          Some(serde_json::Value::String(dir)) => Some(
+            Path::new(path)
+              .parent()
+              .map_or_else(|| PathBuf::from(dir), |parent| parent.join(dir)),
+          ),
+          Some(_) => return Err(invalid()),
+        };
+
+        let stderr = match entry.get("stderr").and_then(|s| s.as_str()) {
+          None => None,
+          Some("capture") => Some(StderrMode::Capture),
+          Some("inherit") => Some(StderrMode::Inherit),
+          Some("merge") => Some(StderrMode::Merge),
+          Some(_) => return Err(invalid()),
+        };
+
+        let flag = |key| entry.get(key).and_then(|v| v.as_bool()) == Some(true);
+
+        ForeignFunction {
+          cmd,
+          shell: flag("shell"),
+          input,
+          output,
+          timeout,
+          env,
+          clear_env: flag("clear_env"),
+          exit_status,
+          stderr,
+          stream: flag("stream"),
+          signature: Self::signature(entry).ok_or_else(invalid)?,
+          cache: Self::cache(entry).ok_or_else(invalid)?,
+          callbacks,
+          cwd,
+          coprocess: flag("persistent").then(|| Arc::new(Coprocess::default())),
+        }
+      }
+      _ => return Err(invalid()),
+    };
+
+    ctx
+      .current_scope()
+      .insert(name.to_owned(), Value::from(function));
 
     return Ok(());
   }
 
-  pub fn call(f: &ForeignFunction, args: &Vec<Value>) -> Result<Value, String> {
-    let mut cmd = Command::new("sh");
-    cmd.arg("-c");
+  // `"cache": true` keeps results for the whole run, `"cache": { "ttl": 60 }`
+  // for that many seconds
+  fn cache(
+    entry: &serde_json::Map<String, serde_json::Value>,
+  ) -> Option<Option<Arc<CallCache>>> {
+    let ttl = match entry.get("cache") {
+      None | Some(serde_json::Value::Bool(false)) => return Some(None),
+      Some(serde_json::Value::Bool(true)) => None,
+      Some(serde_json::Value::Object(cache)) => {
+        Some(seconds(cache.get("ttl")?)?)
+      }
+      Some(_) => return None,
+    };
+
+    return Some(Some(Arc::new(CallCache::new(ttl))));
+  }
+
+  // declared types of a command entry, none if it declares neither
+  // parameters nor a result:
+  //   "params": ["string", { "name": "count", "type": "int" }],
+  //   "returns": "object"
+  fn signature(
+    entry: &serde_json::Map<String, serde_json::Value>,
+  ) -> Option<Option<ForeignSignature>> {
+    if !entry.contains_key("params") && !entry.contains_key("returns") {
+      return Some(None);
+    }
+
+    let mut params = Vec::new();
+    for param in entry
+      .get("params")
+      .map_or(Some(&vec![]), |p| p.as_array())?
+    {
+      params.push(match param {
+        serde_json::Value::String(ty) => Param {
+          name: None,
+          ty: Type::parse(ty)?,
+        },
+        serde_json::Value::Object(param) => Param {
+          name: Some(param.get("name")?.as_str()?.to_owned()),
+          ty: Type::parse(param.get("type")?.as_str()?)?,
+        },
+        _ => return None,
+      });
+    }
 
-    let full_cmd = format!(
-      "{} {}",
-      f.cmd,
-      args
+    let returns = match entry.get("returns") {
+      None => Type::Any,
+      Some(ty) => Type::parse(ty.as_str()?)?,
+    };
+
+    return Some(Some(ForeignSignature { params, returns }));
+  }
+
+  // library, symbol, and signature of an entry like
+  //   { "library": "libm.so.6", "symbol": "cos", "params": ["f64"],
+  //     "returns": "f64" }
+  // where the symbol defaults to the entry name and a missing return type
+  // is void. Paths with a slash are relative to the interface file.
+  fn library_entry(
+    path: &str,
+    name: &str,
+    entry: &serde_json::Map<String, serde_json::Value>,
+  ) -> Option<(PathBuf, String, Signature)> {
+    let library = Path::new(entry.get("library")?.as_str()?);
+    let library = match Path::new(path).parent() {
+      Some(dir) if library.components().count() > 1 => dir.join(library),
+      _ => library.to_path_buf(),
+    };
+
+    let symbol = match entry.get("symbol") {
+      None => name,
+      Some(s) => s.as_str()?,
+    };
+
+    let params = match entry.get("params") {
+      None => Vec::new(),
+      Some(p) => p
+        .as_array()?
         .iter()
-        .map(|v| v.to_string())
-        .collect::<Vec<_>>()
-        .join(" ")
-    );
+        .map(|ty| CType::parse(ty.as_str()?))
+        .collect::<Option<_>>()?,
+    };
 
-    cmd.arg(full_cmd);
+    let returns = match entry.get("returns") {
+      None => CType::Void,
+      Some(ty) => CType::parse(ty.as_str()?)?,
+    };
 
-    let output = cmd.output().map_err(|e| e.to_string())?;
+    return Some((library, symbol.to_owned(), Signature { params, returns }));
+  }
 
-    let stdout = String::from_utf8_lossy(&output.stdout);
-    let Ok(parsed): Result<serde_json::Value, _> =
-      serde_json::from_str(&stdout)
+  pub fn call(
+    f: &ForeignFunction,
+    args: &Vec<Value>,
+    options: &CallOptions,
+  ) -> Result<Value, SamError> {
+    return Self::call_with(f, args, options, &mut no_callbacks);
+  }
+
+  // call `f`, answering the sam functions it calls back with `callback`
+  pub fn call_with(
+    f: &ForeignFunction,
+    args: &Vec<Value>,
+    options: &CallOptions,
+    callback: &mut Callback,
+  ) -> Result<Value, SamError> {
+    if let Some(signature) = &f.signature {
+      check_args(&f.cmd, signature, args)?;
+    }
+
+    // streams are consumed as they are read, there is nothing to reuse
+    let cache = f.cache.as_ref().filter(|_| !f.stream);
+    let Some(cache) = cache else {
+      return Self::run(f, args, options, callback);
+    };
+
+    // arguments that cannot be encoded are never cached
+    let Ok(key) = Self::value_to_json(&Value::SamArray(Arc::new(args.clone())))
     else {
-      return Err(format!(
-        "There was an error in parsing the output of `{}`.",
-        f.cmd
+      return Self::run(f, args, options, callback);
+    };
+    let key = key.to_string();
+
+    if let Some(hit) = cache.get(&key) {
+      return Ok(hit);
+    }
+
+    let result = Self::run(f, args, options, callback)?;
+    cache.insert(key, result.clone());
+
+    return Ok(result);
+  }
+
+  fn run(
+    f: &ForeignFunction,
+    args: &[Value],
+    options: &CallOptions,
+    callback: &mut Callback,
+  ) -> Result<Value, SamError> {
+    let (cmd, input) = Self::command(f, args)?;
+    let options = Self::options(f, options);
+    check_start(&cmd, &options)?;
+
+    if let Some(coprocess) = &f.coprocess {
+      return Self::exchange(f, coprocess, cmd, args, &options, callback);
+    }
+
+    if f.stream {
+      let reader = process::stream(cmd, input, &options)
+        .map_err(|e| run_error(e, "foreign function", &f.cmd))?;
+
+      return Ok(Value::from(Stream {
+        cmd: f.cmd.clone(),
+        exit_status: options.exit_status,
+        reader: Arc::new(Mutex::new(Some(reader))),
+      }));
+    }
+
+    let output = if f.callbacks.is_empty() {
+      process::run(cmd, input, &options)
+        .map_err(|e| run_error(e, "foreign function", &f.cmd))?
+    } else {
+      Self::converse(f, cmd, input, &options, callback)?
+    };
+
+    return Self::output_value(f, &options, &output);
+  }
+
+  // the value a finished call evaluates to, following the exit policy
+  fn output_value(
+    f: &ForeignFunction,
+    options: &CallOptions,
+    output: &Output,
+  ) -> Result<Value, SamError> {
+    let failed = !output.status.success();
+
+    if f.output == OutputFormat::Bytes {
+      if failed && options.exit_status == ExitPolicy::Error {
+        return Err(exit_error(&f.cmd, output));
+      }
+
+      let value = Value::SamBytes(Arc::new(output.stdout.clone()));
+      if let Some(signature) = &f.signature {
+        check_result(&f.cmd, signature, &value)?;
+      }
+
+      return Ok(match options.exit_status {
+        ExitPolicy::Error => value,
+        ExitPolicy::Result => result_object(output, value),
+      });
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let parsed = serde_json::from_str::<serde_json::Value>(&stdout);
+
+    if failed && options.exit_status == ExitPolicy::Error {
+      return Err(exit_error(&f.cmd, output));
+    }
+
+    let value = match parsed {
+      Ok(json) if failed => Self::json_to_value(json)?,
+      Ok(json) => {
+        let value = Self::json_to_value(json)?;
+        if let Some(signature) = &f.signature {
+          check_result(&f.cmd, signature, &value)?;
+        }
+        value
+      }
+      // a failed command owes no JSON, its output is passed on as text
+      Err(_) if failed => Value::from(stdout.into_owned()),
+      Err(e) => {
+        return Err(
+          SamError::ffi(
+            codes::FOREIGN_OUTPUT,
+            format!("There was an error in parsing the output of `{}`.", f.cmd),
+          )
+          .caused_by(e),
+        );
+      }
+    };
+
+    return Ok(match options.exit_status {
+      ExitPolicy::Error => value,
+      ExitPolicy::Result => result_object(output, value),
+    });
+  }
+
+  // run the stages with the output of each piped into the next. The last
+  // stage decides the result, like the last command of a shell pipeline.
+  pub fn pipe(
+    stages: Vec<Stage>,
+    options: &CallOptions,
+  ) -> Result<Value, SamError> {
+    // `pipe();` has no last stage to give the result
+    if stages.is_empty() {
+      return Err(SamError::runtime(
+        codes::ARGUMENT_COUNT,
+        "`pipe` needs at least one stage",
       ));
+    }
+
+    let mut commands = Vec::with_capacity(stages.len());
+    let mut input = None;
+
+    for (i, stage) in stages.iter().enumerate() {
+      let (cmd, stage_input, stage_options) = match stage {
+        Stage::Foreign(f, args) => {
+          if let Some(signature) = &f.signature {
+            check_args(&f.cmd, signature, args)?;
+          }
+          let (cmd, input) = Self::command(f, args)?;
+          (cmd, input, Self::options(f, options))
+        }
+        Stage::Shell(name, args) => {
+          let mut cmd = Command::new(name);
+          cmd.args(args.iter().map(argument));
+          (cmd, None, options.clone())
+        }
+      };
+
+      check_start(&cmd, &stage_options)?;
+
+      match stage_input {
+        Some(_) if i > 0 => {
+          return Err(SamError::ffi(
+            codes::INTERFACE_ENTRY,
+            format!(
+              "`{}` takes JSON input and can only start a pipeline",
+              stage.name()
+            ),
+          ));
+        }
+        Some(bytes) => input = Some(bytes),
+        None => {}
+      }
+
+      commands.push((cmd, stage_options));
+    }
+
+    let names = stages
+      .iter()
+      .map(Stage::name)
+      .collect::<Vec<_>>()
+      .join(" | ");
+    let outputs = process::pipeline(commands, input)
+      .map_err(|e| run_error(e, "pipeline", &names))?;
+
+    let (Some(stage), Some(output)) = (stages.last(), outputs.last()) else {
+      return Ok(Value::Undefined);
     };
 
-    return Self::json_to_value(parsed);
+    return match stage {
+      Stage::Foreign(f, _) => {
+        Self::output_value(f, &Self::options(f, options), output)
+      }
+      Stage::Shell(..) => {
+        let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+        Ok(result_object(output, Value::from(stdout)))
+      }
+    };
   }
 
-  pub fn json_to_value(v: serde_json::Value) -> Result<Value, String> {
+  // run a command that may call back into sam. Lines it writes that are
+  // JSON-RPC requests are answered on its stdin, every other line is its
+  // output. No time limit applies, the callbacks may take any time.
+  fn converse(
+    f: &ForeignFunction,
+    cmd: Command,
+    input: Option<Vec<u8>>,
+    options: &CallOptions,
+    callback: &mut Callback,
+  ) -> Result<Output, SamError> {
+    let fail = |e| run_error(e, "foreign function", &f.cmd);
+
+    // JSON arguments take the first line, before any response
+    let input = input.map(|mut bytes| {
+      bytes.push(b'\n');
+      bytes
+    });
+
+    let mut reader = process::channel(cmd, input, options).map_err(fail)?;
+    let mut stdout = Vec::new();
+
+    while let Some(line) = reader.next_line() {
+      let line = line.map_err(fail)?;
+
+      let Some(request) = rpc_request(&line) else {
+        stdout.extend_from_slice(line.as_bytes());
+        stdout.push(b'\n');
+        continue;
+      };
+
+      Self::respond(f, &mut reader, &request, callback);
+    }
+
+    let mut output = reader.finish().map_err(fail)?;
+    output.stdout = stdout;
+
+    return Ok(output);
+  }
+
+  // one call of a persistent foreign function, starting its process if it
+  // is not running. Calls are answered one at a time, in order.
+  fn exchange(
+    f: &ForeignFunction,
+    coprocess: &Coprocess,
+    cmd: Command,
+    args: &[Value],
+    options: &CallOptions,
+    callback: &mut Callback,
+  ) -> Result<Value, SamError> {
+    let fail = |e| run_error(e, "foreign function", &f.cmd);
+
+    let request = args
+      .iter()
+      .map(Self::value_to_json)
+      .collect::<Result<Vec<_>, SamError>>()?;
+    let request = serde_json::Value::Array(request).to_string();
+
+    let mut running =
+      coprocess.reader.lock().unwrap_or_else(|e| e.into_inner());
+
+    // a process that exited after its last answer is started again
+    if running.as_mut().is_some_and(|reader| !reader.running()) {
+      *running = None;
+    }
+
+    let reader = match running.as_mut() {
+      Some(reader) => reader,
+      None => {
+        running.insert(process::channel(cmd, None, options).map_err(fail)?)
+      }
+    };
+
+    // a process exiting right now is found out when its answer is read
+    let _ = reader.send_line(&request);
+
+    let line = loop {
+      match reader.next_line() {
+        Some(Ok(line)) => match rpc_request(&line) {
+          Some(request) if !f.callbacks.is_empty() => {
+            Self::respond(f, reader, &request, callback);
+          }
+          _ => break line,
+        },
+        Some(Err(e)) => {
+          *running = None;
+          return Err(fail(e));
+        }
+        None => {
+          let output = reader.finish();
+          *running = None;
+
+          let output = output.map_err(fail)?;
+          if !output.status.success() {
+            return Err(exit_error(&f.cmd, &output));
+          }
+
+          return Err(SamError::ffi(
+            codes::FOREIGN_OUTPUT,
+            format!("Foreign function `{}` exited without answering.", f.cmd),
+          ));
+        }
+      }
+    };
+
+    let json = serde_json::from_str(&line).map_err(|e| {
+      SamError::ffi(
+        codes::FOREIGN_OUTPUT,
+        format!("There was an error in parsing the output of `{}`.", f.cmd),
+      )
+      .caused_by(e)
+    })?;
+
+    let value = Self::json_to_value(json)?;
+    if let Some(signature) = &f.signature {
+      check_result(&f.cmd, signature, &value)?;
+    }
+
+    return Ok(value);
+  }
+
+  // answer a callback request on the command's stdin
+  fn respond(
+    f: &ForeignFunction,
+    reader: &mut LineReader,
+    request: &serde_json::Map<String, serde_json::Value>,
+    callback: &mut Callback,
+  ) {
+    let answer = Self::answer(f, request, callback);
+
+    // notifications, requests without an id, get no response
+    let Some(id) = request.get("id").filter(|id| !id.is_null()) else {
+      return;
+    };
+
+    let response = match answer {
+      Ok(result) => {
+        serde_json::json!({ "jsonrpc": "2.0", "id": id, "result": result })
+      }
+      Err((code, message)) => serde_json::json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": { "code": code, "message": message },
+      }),
+    };
+
+    // a command that stopped reading has exited or is about to, its exit
+    // status tells what went wrong
+    let _ = reader.send_line(&response.to_string());
+  }
+
+  // result of a callback request, or a JSON-RPC error code and message
+  fn answer(
+    f: &ForeignFunction,
+    request: &serde_json::Map<String, serde_json::Value>,
+    callback: &mut Callback,
+  ) -> Result<serde_json::Value, (i64, String)> {
+    let method = request["method"].as_str().unwrap_or_default();
+
+    if !f.callbacks.iter().any(|name| name == method) {
+      return Err((-32601, format!("`{}` is not a callback", method)));
+    }
+
+    let args = match request.get("params") {
+      None => Vec::new(),
+      Some(serde_json::Value::Array(params)) => params
+        .iter()
+        .map(|p| Self::json_to_value(p.clone()))
+        .collect::<Result<_, SamError>>()
+        .map_err(|e| (-32602, e.message().to_owned()))?,
+      Some(_) => {
+        return Err((-32602, "`params` must be an array".to_owned()));
+      }
+    };
+
+    return callback(method, args)
+      .and_then(|result| Self::value_to_json(&result))
+      .map_err(|e| (-32000, e.message().to_owned()));
+  }
+
+  // the process to run for a call, and what to write to its stdin
+  fn command(
+    f: &ForeignFunction,
+    args: &[Value],
+  ) -> Result<(Command, Option<Vec<u8>>), SamError> {
+    let mut cmd = if f.shell {
+      process::shell(&f.cmd)
+    } else {
+      let argv = split_command(&f.cmd).ok_or_else(|| {
+        SamError::ffi(
+          codes::INTERFACE_ENTRY,
+          format!("Unterminated quote in command `{}`.", f.cmd),
+        )
+      })?;
+
+      let Some((program, fixed)) = argv.split_first() else {
+        return Err(SamError::ffi(
+          codes::INTERFACE_ENTRY,
+          "Interface entry has an empty command.",
+        ));
+      };
+
+      let mut cmd = Command::new(program);
+      cmd.args(fixed);
+      cmd
+    };
+
+    let input = match f.input {
+      ArgPassing::Argv => {
+        // every argument is exactly one argv entry, whatever it contains
+        Self::check_shell_args(f, args)?;
+        cmd.args(args.iter().map(argument));
+        None
+      }
+
+      ArgPassing::RawStdin => {
+        let Some((first, rest)) = args.split_first() else {
+          return Err(SamError::runtime(
+            codes::ARGUMENT_COUNT,
+            format!("`{}` needs an argument to write to its stdin", f.cmd),
+          ));
+        };
+
+        Self::check_shell_args(f, rest)?;
+        cmd.args(rest.iter().map(argument));
+        Some(match first {
+          Value::SamBytes(bytes) => bytes.to_vec(),
+          v => v.to_string().into_bytes(),
+        })
+      }
+
+      ArgPassing::JsonStdin => {
+        let input = serde_json::Value::Array(
+          args
+            .iter()
+            .map(Self::value_to_json)
+            .collect::<Result<_, SamError>>()?,
+        );
+        Some(input.to_string().into_bytes())
+      }
+    };
+
+    return Ok((cmd, input));
+  }
+
+  // `cmd /C` reads the arguments after the script itself, expanding
+  // `%PATH%` and letting a `"` end the quoting, so on Windows a shell entry
+  // takes its arguments on stdin only
+  fn check_shell_args(
+    f: &ForeignFunction,
+    args: &[Value],
+  ) -> Result<(), SamError> {
+    if cfg!(windows) && f.shell && !args.is_empty() {
+      return Err(
+        SamError::ffi(
+          codes::INTERFACE_ENTRY,
+          format!("`{}` runs through cmd and cannot take arguments", f.cmd),
+        )
+        .with_help("pass them on stdin with the `json` input instead"),
+      );
+    }
+
+    return Ok(());
+  }
+
+  // the entry's own settings win over the global defaults
+  fn options(f: &ForeignFunction, options: &CallOptions) -> CallOptions {
+    let mut options = options.clone();
+    options.timeout = f.timeout.or(options.timeout);
+    options.clear_env |= f.clear_env;
+    options.exit_status = f.exit_status.unwrap_or(options.exit_status);
+    options.cwd = f.cwd.clone().or(options.cwd);
+    options.stderr = f.stderr.unwrap_or(options.stderr);
+    options
+      .env
+      .extend(f.env.iter().map(|(k, v)| (k.clone(), v.clone())));
+
+    return options;
+  }
+
+  // inverse of json_to_value; sam has no booleans, so they come back as
+  // the integers 0 and 1
+  pub fn value_to_json(v: &Value) -> Result<serde_json::Value, SamError> {
+    match v {
+      Value::Undefined => Ok(serde_json::Value::Null),
+      Value::SamNumber(Number::SamInt(i)) => Ok((*i).into()),
+      // NaN and infinities have no JSON representation
+      Value::SamNumber(Number::SamFloat(f)) => Ok(
+        serde_json::Number::from_f64(*f)
+          .map(serde_json::Value::Number)
+          .unwrap_or(serde_json::Value::Null),
+      ),
+      Value::SamString(s) => Ok(serde_json::Value::String(s.to_string())),
+      // JSON has no bytes, they become an array of numbers
+      Value::SamBytes(b) => Ok(serde_json::Value::Array(
+        b.iter().map(|&byte| byte.into()).collect(),
+      )),
+      Value::SamArray(a) => Ok(serde_json::Value::Array(
+        a.iter()
+          .map(Self::value_to_json)
+          .collect::<Result<_, _>>()?,
+      )),
+      Value::SamObject(o) => Ok(serde_json::Value::Object(
+        o.iter()
+          .map(|(k, v)| Ok((k.clone(), Self::value_to_json(v)?)))
+          .collect::<Result<_, SamError>>()?,
+      )),
+      Value::SamFunction(_)
+      | Value::SamForeignFunction(_)
+      | Value::SamHandle(_)
+      | Value::SamStream(_)
+      | Value::SamNativeFunction(_)
+      | Value::SamLazy(_) => Err(SamError::type_error(
+        codes::NOT_SERIALIZABLE,
+        format!("Cannot convert a {} to JSON", v.type_name()),
+      )),
+    }
+  }
+
+  pub fn json_to_value(v: serde_json::Value) -> Result<Value, SamError> {
     match v {
       serde_json::Value::Null => Ok(Value::Undefined),
       serde_json::Value::Bool(b) => {
         Ok(Value::SamNumber(Number::SamInt((b as i32).into())))
       }
-      serde_json::Value::String(s) => Ok(Value::SamString(s)),
-      serde_json::Value::Array(_a) => todo!(), // TODO: Arrays
+      serde_json::Value::String(s) => Ok(Value::from(s)),
+      serde_json::Value::Array(a) => {
+        let items = a
+          .into_iter()
+          .map(Self::json_to_value)
+          .collect::<Result<_, SamError>>()?;
+
+        Ok(Value::SamArray(Arc::new(items)))
+      }
       serde_json::Value::Object(o) => {
         let map = o
           .into_iter()
           .map(|(k, v)| Ok((k, Self::json_to_value(v)?)))
-          .collect::<Result<_, String>>()?;
+          .collect::<Result<_, SamError>>()?;
 
-        Ok(Value::SamObject(map))
+        Ok(Value::SamObject(Arc::new(map)))
       }
       serde_json::Value::Number(n) => {
         let parsed = if let Some(i) = n.as_i64() {
@@ -130,7 +1204,10 @@ impl FFI {
         } else if let Some(f) = n.as_f64() {
           Ok(Number::SamFloat(f))
         } else {
-          Err(format!("Invalid JSON number encountered."))
+          Err(SamError::ffi(
+            codes::FOREIGN_OUTPUT,
+            "Invalid JSON number encountered.",
+          ))
         };
 
         Ok(Value::SamNumber(parsed?))
@@ -138,3 +1215,615 @@ impl FFI {
     }
   }
 }
+
+// a value as one command line argument. Bytes are passed unchanged where
+// the platform allows it.
+fn argument(v: &Value) -> OsString {
+  match v {
+    #[cfg(unix)]
+    Value::SamBytes(bytes) => {
+      use std::os::unix::ffi::OsStringExt;
+      OsString::from_vec(bytes.to_vec())
+    }
+    v => OsString::from(v.to_string()),
+  }
+}
+
+// answers a foreign function calling back into sam, given the name of the
+// function and its arguments
+pub type Callback<'a> =
+  dyn FnMut(&str, Vec<Value>) -> Result<Value, SamError> + 'a;
+
+// calls made without access to the interpreter, e.g. spawned ones
+fn no_callbacks(name: &str, _args: Vec<Value>) -> Result<Value, SamError> {
+  return Err(SamError::runtime(
+    codes::NOT_CALLABLE,
+    format!("`{}` cannot be called back from here", name),
+  ));
+}
+
+// a line that is a JSON-RPC request, an object with a `method`
+fn rpc_request(
+  line: &str,
+) -> Option<serde_json::Map<String, serde_json::Value>> {
+  match serde_json::from_str(line) {
+    Ok(serde_json::Value::Object(request))
+      if request.get("method").is_some_and(|m| m.is_string()) =>
+    {
+      Some(request)
+    }
+    _ => None,
+  }
+}
+
+// split a command line into words like a POSIX shell would, honoring
+// quotes and backslashes but nothing else; None if a quote is left open
+pub fn split_command(cmd: &str) -> Option<Vec<String>> {
+  let mut words = Vec::new();
+  let mut word: Option<String> = None;
+  let mut chars = cmd.chars();
+
+  while let Some(c) = chars.next() {
+    match c {
+      _ if c.is_whitespace() => {
+        if let Some(w) = word.take() {
+          words.push(w);
+        }
+      }
+
+      '\'' => {
+        let w = word.get_or_insert_with(String::new);
+        loop {
+          match chars.next()? {
+            '\'' => break,
+            c => w.push(c),
+          }
+        }
+      }
+
+      '"' => {
+        let w = word.get_or_insert_with(String::new);
+        loop {
+          match chars.next()? {
+            '"' => break,
+            '\\' => match chars.next()? {
+              c @ ('"' | '\\' | '$' | '`') => w.push(c),
+              c => {
+                w.push('\\');
+                w.push(c);
+              }
+            },
+            c => w.push(c),
+          }
+        }
+      }
+
+      '\\' => {
+        let escaped = chars.next()?;
+        word.get_or_insert_with(String::new).push(escaped);
+      }
+
+      _ => word.get_or_insert_with(String::new).push(c),
+    }
+  }
+
+  if let Some(w) = word {
+    words.push(w);
+  }
+
+  return Some(words);
+}
+
+// the tests run commands found on every unix
+#[cfg(all(test, unix))]
+mod tests {
+  use super::*;
+  use crate::permissions::Allow;
+
+  #[test]
+  fn test_parse_interface_formats() {
+    let json = parse_interface(
+      "tools.json",
+      r#"{ "fmt": { "command": "black -", "timeout": 2 } }"#,
+    )
+    .unwrap();
+
+    let toml = parse_interface(
+      "tools.toml",
+      "[fmt]\ncommand = \"black -\"\ntimeout = 2\n",
+    )
+    .unwrap();
+
+    let yaml =
+      parse_interface("tools.yml", "fmt:\n  command: black -\n  timeout: 2\n")
+        .unwrap();
+
+    assert_eq!(toml, json);
+    assert_eq!(yaml, json);
+    assert!(parse_interface("tools.toml", "fmt = ").is_err());
+  }
+
+  #[test]
+  fn test_split_command() {
+    assert_eq!(
+      split_command("python3 'my tool.py' --name \"a \\\"b\\\"\"").unwrap(),
+      vec!["python3", "my tool.py", "--name", "a \"b\""]
+    );
+    assert_eq!(split_command("  echo   42 ").unwrap(), vec!["echo", "42"]);
+    assert!(split_command("echo 'open").is_none());
+  }
+
+  #[test]
+  fn test_arguments_are_not_interpreted() {
+    // under `sh -c` this would run `echo injected` as a second command
+    let f = ForeignFunction::new("echo".to_owned());
+    let arg = Value::from("\"a b; echo injected\"");
+
+    assert_eq!(
+      FFI::call(&f, &vec![arg], &CallOptions::default()).unwrap(),
+      Value::from("a b; echo injected")
+    );
+  }
+
+  #[test]
+  fn test_value_to_json() {
+    let mut obj = HashMap::new();
+    obj.insert(
+      "items".to_owned(),
+      Value::from(vec![
+        Value::SamNumber(Number::SamInt(1)),
+        Value::SamNumber(Number::SamFloat(0.5)),
+        Value::from("x"),
+        Value::Undefined,
+      ]),
+    );
+
+    assert_eq!(
+      FFI::value_to_json(&Value::from(obj)).unwrap(),
+      serde_json::json!({ "items": [1, 0.5, "x", null] })
+    );
+  }
+
+  #[test]
+  fn test_json_to_value_array() {
+    let json = serde_json::json!([1, ["a", null], true]);
+
+    assert_eq!(
+      FFI::json_to_value(json).unwrap(),
+      Value::from(vec![
+        Value::SamNumber(Number::SamInt(1)),
+        Value::from(vec![Value::from("a"), Value::Undefined]),
+        Value::SamNumber(Number::SamInt(1)),
+      ])
+    );
+  }
+
+  #[test]
+  fn test_value_to_json_special() {
+    let nan = Value::SamNumber(Number::SamFloat(f64::NAN));
+    assert_eq!(FFI::value_to_json(&nan).unwrap(), serde_json::Value::Null);
+
+    let f = Value::from(ForeignFunction::new("ls".to_owned()));
+    let err = FFI::value_to_json(&f).unwrap_err();
+    assert_eq!(err.code(), Some(codes::NOT_SERIALIZABLE));
+  }
+
+  #[test]
+  fn test_json_stdin() {
+    // the child strips the brackets of the argument array, echoing the
+    // first argument back as the result
+    let f = ForeignFunction {
+      input: ArgPassing::JsonStdin,
+      ..ForeignFunction::new("sed -e s/^.// -e s/.$//".to_owned())
+    };
+
+    let mut obj = HashMap::new();
+    obj.insert("name".to_owned(), Value::from("a 'b' c"));
+
+    let Value::SamObject(result) =
+      FFI::call(&f, &vec![Value::from(obj)], &CallOptions::default()).unwrap()
+    else {
+      panic!("expected an object");
+    };
+
+    assert_eq!(result["name"], Value::from("a 'b' c"));
+  }
+
+  #[test]
+  fn test_foreign_function_timeout() {
+    let f = ForeignFunction {
+      timeout: Some(Duration::from_millis(100)),
+      ..ForeignFunction::new("sleep 5".to_owned())
+    };
+
+    let err = FFI::call(&f, &vec![], &CallOptions::default()).unwrap_err();
+    assert_eq!(err.code(), Some(codes::TIMEOUT));
+  }
+
+  #[test]
+  fn test_foreign_function_env() {
+    let mut f = ForeignFunction::shell("echo \"\\\"$STAGE\\\"\" #".to_owned());
+    f.env.insert("STAGE".to_owned(), "prod".to_owned());
+
+    let mut options = CallOptions::default();
+    options.env.insert("STAGE".to_owned(), "dev".to_owned());
+
+    assert_eq!(
+      FFI::call(&f, &vec![], &options).unwrap(),
+      Value::from("prod")
+    );
+  }
+
+  #[test]
+  fn test_exit_status_error() {
+    let f = ForeignFunction::shell("echo oops >&2; exit 3 #".to_owned());
+
+    let err = FFI::call(&f, &vec![], &CallOptions::default()).unwrap_err();
+    assert_eq!(err.code(), Some(codes::COMMAND_EXIT));
+    assert_eq!(
+      err.message(),
+      format!("Foreign function `{}` exited with status 3", f.cmd)
+    );
+  }
+
+  #[test]
+  fn test_exit_status_result() {
+    let f = ForeignFunction {
+      exit_status: Some(ExitPolicy::Result),
+      ..ForeignFunction::shell("echo partial; exit 1 #".to_owned())
+    };
+
+    let Value::SamObject(result) =
+      FFI::call(&f, &vec![], &CallOptions::default()).unwrap()
+    else {
+      panic!("expected an object");
+    };
+
+    assert_eq!(result["ok"], Value::SamNumber(Number::SamInt(0)));
+    assert_eq!(result["status"], Value::SamNumber(Number::SamInt(1)));
+    assert_eq!(result["stdout"], Value::from("partial\n"));
+  }
+
+  #[test]
+  fn test_stream() {
+    let f = ForeignFunction {
+      stream: true,
+      ..ForeignFunction::shell("printf 'a\\nb\\n'; exit 2 #".to_owned())
+    };
+
+    let Value::SamStream(stream) =
+      FFI::call(&f, &vec![], &CallOptions::default()).unwrap()
+    else {
+      panic!("expected a stream");
+    };
+
+    assert_eq!(stream.next().unwrap(), Some(Value::from("a")));
+    assert_eq!(stream.next().unwrap(), Some(Value::from("b")));
+
+    // the exit status is only known at the end of the output
+    let err = stream.next().unwrap_err();
+    assert_eq!(err.code(), Some(codes::COMMAND_EXIT));
+    assert_eq!(stream.next().unwrap(), None);
+  }
+
+  #[test]
+  fn test_typed_signature() {
+    let f = ForeignFunction {
+      signature: Some(ForeignSignature {
+        params: vec![Param {
+          name: Some("count".to_owned()),
+          ty: Type::Int,
+        }],
+        returns: Type::String,
+      }),
+      ..ForeignFunction::new("echo".to_owned())
+    };
+    let options = CallOptions::default();
+
+    let err = FFI::call(&f, &vec![], &options).unwrap_err();
+    assert_eq!(err.code(), Some(codes::ARGUMENT_COUNT));
+
+    let half = Value::SamNumber(Number::SamFloat(0.5));
+    let err = FFI::call(&f, &vec![half], &options).unwrap_err();
+    assert_eq!(
+      err.message(),
+      "Argument 1 (`count`) of `echo` must be of type int, found number"
+    );
+
+    // `echo 2` prints a number, not the declared string
+    let two = Value::SamNumber(Number::SamInt(2));
+    let err = FFI::call(&f, &vec![two], &options).unwrap_err();
+    assert_eq!(err.code(), Some(codes::FOREIGN_OUTPUT));
+  }
+
+  #[test]
+  fn test_cached_calls() {
+    // prints how many times it ran, counting in a file
+    let dir =
+      std::env::temp_dir().join(format!("sam-cache-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let counter = dir.join("count");
+    fs::write(&counter, "").unwrap();
+
+    let f = ForeignFunction {
+      cache: Some(Arc::new(CallCache::new(None))),
+      ..ForeignFunction::shell(format!(
+        "echo x >> {0}; wc -l < {0} #",
+        counter.display()
+      ))
+    };
+    let options = CallOptions::default();
+    let one = || vec![Value::SamNumber(Number::SamInt(1))];
+    let two = || vec![Value::SamNumber(Number::SamInt(2))];
+
+    assert_eq!(
+      FFI::call(&f, &one(), &options).unwrap(),
+      Value::SamNumber(Number::SamInt(1))
+    );
+    assert_eq!(
+      FFI::call(&f, &one(), &options).unwrap(),
+      Value::SamNumber(Number::SamInt(1))
+    );
+    assert_eq!(
+      FFI::call(&f, &two(), &options).unwrap(),
+      Value::SamNumber(Number::SamInt(2))
+    );
+
+    fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn test_cache_ttl() {
+    let cache = CallCache::new(Some(Duration::ZERO));
+    cache.insert("[]".to_owned(), Value::Undefined);
+    std::thread::sleep(Duration::from_millis(5));
+    assert_eq!(cache.get("[]"), None);
+
+    let cache = CallCache::new(None);
+    cache.insert("[]".to_owned(), Value::Undefined);
+    assert_eq!(cache.get("[]"), Some(Value::Undefined));
+  }
+
+  #[test]
+  fn test_sandboxed_call() {
+    let mut options = CallOptions::default();
+    options.permissions.run = Allow::Only(vec!["echo".to_owned()]);
+
+    let echo = ForeignFunction::new("echo 1".to_owned());
+    assert!(FFI::call(&echo, &vec![], &options).is_ok());
+
+    // the shell could run anything, so it needs to be allowed itself
+    let shell = ForeignFunction::shell("echo 1".to_owned());
+    let err = FFI::call(&shell, &vec![], &options).unwrap_err();
+    assert_eq!(err.code(), Some(codes::PERMISSION_DENIED));
+
+    let err = Shell::call("ls", vec![], &options).unwrap_err();
+    assert_eq!(err.code(), Some(codes::PERMISSION_DENIED));
+
+    // variables could make echo run something else, e.g. with LD_PRELOAD
+    let mut f = ForeignFunction::new("echo 1".to_owned());
+    f.env.insert("LD_PRELOAD".to_owned(), "evil.so".to_owned());
+    let err = FFI::call(&f, &vec![], &options).unwrap_err();
+    assert_eq!(err.code(), Some(codes::PERMISSION_DENIED));
+
+    let mut sam_toml = options.clone();
+    sam_toml.env.insert("PATH".to_owned(), ".".to_owned());
+    let err = FFI::call(&echo, &vec![], &sam_toml).unwrap_err();
+    assert_eq!(err.code(), Some(codes::PERMISSION_DENIED));
+
+    // nor may it run in a directory the script cannot read
+    let f = ForeignFunction {
+      cwd: Some(std::env::temp_dir()),
+      ..ForeignFunction::new("echo 1".to_owned())
+    };
+    assert!(FFI::call(&f, &vec![], &options).is_ok());
+    options.permissions.read = Allow::Nothing;
+    let err = FFI::call(&f, &vec![], &options).unwrap_err();
+    assert_eq!(err.code(), Some(codes::PERMISSION_DENIED));
+  }
+
+  #[test]
+  fn test_callbacks() {
+    // asks for `double(21)` and an unlisted function, printing both
+    // responses as its result
+    let f = ForeignFunction {
+      callbacks: vec!["double".to_owned()],
+      ..ForeignFunction::shell(
+        "echo '{\"jsonrpc\": \"2.0\", \"id\": 1, \"method\": \"double\", \
+         \"params\": [21]}'; read a; \
+         echo '{\"jsonrpc\": \"2.0\", \"id\": 2, \"method\": \"secret\"}'; \
+         read b; echo \"[$a, $b]\" #"
+          .to_owned(),
+      )
+    };
+
+    let mut called = Vec::new();
+    let mut double = |name: &str, args: Vec<Value>| {
+      called.push(name.to_owned());
+      match args.as_slice() {
+        [Value::SamNumber(Number::SamInt(n))] => {
+          Ok(Value::SamNumber(Number::SamInt(n * 2)))
+        }
+        _ => Ok(Value::Undefined),
+      }
+    };
+
+    let Value::SamArray(responses) =
+      FFI::call_with(&f, &vec![], &CallOptions::default(), &mut double)
+        .unwrap()
+    else {
+      panic!("expected an array");
+    };
+
+    let Value::SamObject(first) = &responses[0] else {
+      panic!("expected a response");
+    };
+    assert_eq!(first["result"], Value::SamNumber(Number::SamInt(42)));
+
+    let Value::SamObject(second) = &responses[1] else {
+      panic!("expected a response");
+    };
+    let Value::SamObject(error) = &second["error"] else {
+      panic!("expected an error");
+    };
+    assert_eq!(error["code"], Value::SamNumber(Number::SamInt(-32601)));
+
+    assert_eq!(called, ["double"]);
+  }
+
+  #[test]
+  fn test_persistent() {
+    // answers every call with how many calls it got so far
+    let f = ForeignFunction {
+      coprocess: Some(Arc::new(Coprocess::default())),
+      ..ForeignFunction::shell(
+        "n=0; while read args; do n=$((n + 1)); echo $n; done #".to_owned(),
+      )
+    };
+    let options = CallOptions::default();
+
+    for expected in 1..=3 {
+      assert_eq!(
+        FFI::call(&f, &vec![], &options).unwrap(),
+        Value::SamNumber(Number::SamInt(expected))
+      );
+    }
+
+    // copies of the function value share the process
+    let copy = f.clone();
+    assert_eq!(
+      FFI::call(&copy, &vec![], &options).unwrap(),
+      Value::SamNumber(Number::SamInt(4))
+    );
+  }
+
+  #[test]
+  fn test_persistent_restart() {
+    // answers one call and exits
+    let f = ForeignFunction {
+      coprocess: Some(Arc::new(Coprocess::default())),
+      ..ForeignFunction::shell("read args; echo \"$args\" #".to_owned())
+    };
+    let options = CallOptions::default();
+    let one = Value::SamNumber(Number::SamInt(1));
+
+    assert_eq!(
+      FFI::call(&f, &vec![one.clone()], &options).unwrap(),
+      Value::from(vec![one.clone()])
+    );
+    std::thread::sleep(Duration::from_millis(50));
+    assert_eq!(
+      FFI::call(&f, &vec![one.clone()], &options).unwrap(),
+      Value::from(vec![one])
+    );
+  }
+
+  #[test]
+  fn test_pipe() {
+    let count = ForeignFunction::new("wc -l".to_owned());
+    let stages = vec![
+      Stage::Shell("printf".to_owned(), vec![Value::from("a\\nb\\nab\\n")]),
+      Stage::Shell("grep".to_owned(), vec![Value::from("b")]),
+      Stage::Foreign(Arc::new(count), vec![]),
+    ];
+
+    assert_eq!(
+      FFI::pipe(stages, &CallOptions::default()).unwrap(),
+      Value::SamNumber(Number::SamInt(2))
+    );
+  }
+
+  #[test]
+  fn test_pipe_without_stages() {
+    let err = FFI::pipe(vec![], &CallOptions::default()).unwrap_err();
+    assert_eq!(err.code(), Some(codes::ARGUMENT_COUNT));
+  }
+
+  #[test]
+  fn test_pipe_json_input() {
+    let f = ForeignFunction {
+      input: ArgPassing::JsonStdin,
+      ..ForeignFunction::new("cat".to_owned())
+    };
+    let stages = vec![
+      Stage::Shell("true".to_owned(), vec![]),
+      Stage::Foreign(Arc::new(f), vec![]),
+    ];
+
+    let err = FFI::pipe(stages, &CallOptions::default()).unwrap_err();
+    assert_eq!(err.code(), Some(codes::INTERFACE_ENTRY));
+  }
+
+  #[test]
+  fn test_cwd() {
+    let tmp = std::env::temp_dir().canonicalize().unwrap();
+    let pwd = ForeignFunction::shell("echo \\\"$(pwd)\\\" #".to_owned());
+
+    let options = CallOptions {
+      cwd: Some(tmp.clone()),
+      ..CallOptions::default()
+    };
+    assert_eq!(
+      FFI::call(&pwd, &vec![], &options).unwrap(),
+      Value::from(tmp.display().to_string())
+    );
+
+    // the entry's own directory wins
+    let root = ForeignFunction {
+      cwd: Some(PathBuf::from("/")),
+      ..pwd
+    };
+    assert_eq!(
+      FFI::call(&root, &vec![], &options).unwrap(),
+      Value::from("/")
+    );
+  }
+
+  #[test]
+  fn test_bytes() {
+    let f = ForeignFunction {
+      input: ArgPassing::RawStdin,
+      output: OutputFormat::Bytes,
+      ..ForeignFunction::new("cat".to_owned())
+    };
+    let binary = vec![0, 159, 146, 150, 255, b'\n'];
+
+    assert_eq!(
+      FFI::call(
+        &f,
+        &vec![Value::SamBytes(Arc::new(binary.clone()))],
+        &CallOptions::default()
+      )
+      .unwrap(),
+      Value::SamBytes(Arc::new(binary))
+    );
+
+    let err = FFI::call(&f, &vec![], &CallOptions::default()).unwrap_err();
+    assert_eq!(err.code(), Some(codes::ARGUMENT_COUNT));
+
+    assert_eq!(
+      FFI::value_to_json(&Value::SamBytes(Arc::new(vec![1, 255]))).unwrap(),
+      serde_json::json!([1, 255])
+    );
+  }
+
+  // cmd would expand `%PATH%` and let `"` end the quoting
+  #[test]
+  #[cfg(windows)]
+  fn test_shell_arguments_on_windows() {
+    let f = ForeignFunction::shell("echo".to_owned());
+    let args = vec![Value::from("%PATH%\" & calc & \"")];
+
+    let err = FFI::call(&f, &args, &CallOptions::default()).unwrap_err();
+    assert_eq!(err.code(), Some(codes::INTERFACE_ENTRY));
+  }
+
+  #[test]
+  fn test_shell_opt_in() {
+    let f = ForeignFunction::shell("echo 4$((1 + 1)) #".to_owned());
+    assert_eq!(
+      FFI::call(&f, &vec![], &CallOptions::default()).unwrap(),
+      Value::SamNumber(Number::SamInt(42))
+    );
+  }
+}
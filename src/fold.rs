@@ -0,0 +1,202 @@
+#![allow(dead_code)]
+
+// Constant folding: operators applied to literals are computed once before
+// the program runs, so that `2 * 60 * 60` evaluates like `7200`. An
+// operation that would fail, e.g. a division by zero with the error policy,
+// or whose result cannot be written as a literal is left alone, to fail or
+// run only if it is reached.
+
+use crate::ast::{Block, Else, Expr, ExprKind, If, Literal, Stmt, StmtKind};
+use crate::config::Config;
+use crate::evaluate::binary;
+use std::sync::Arc;
+
+pub fn fold_program(statements: &mut [Stmt], config: &Config) {
+  for stmt in statements {
+    fold_statement(stmt, config);
+  }
+}
+
+fn fold_block(block: &mut Block, config: &Config) {
+  fold_program(&mut block.statements, config);
+}
+
+fn fold_statement(stmt: &mut Stmt, config: &Config) {
+  match &mut stmt.kind {
+    StmtKind::Expression(expr) => fold_expression(expr, config),
+    StmtKind::Declaration { declarators, .. } => {
+      for value in declarators.iter_mut().filter_map(|d| d.value.as_mut()) {
+        fold_expression(value, config);
+      }
+    }
+    StmtKind::Assignment { value, .. } => fold_expression(value, config),
+    StmtKind::Return(Some(value)) => fold_expression(value, config),
+    StmtKind::Return(None)
+    | StmtKind::Break
+    | StmtKind::Continue
+    | StmtKind::Interfaces(_)
+    | StmtKind::Import(_) => {}
+  }
+}
+
+pub fn fold_expression(expr: &mut Expr, config: &Config) {
+  match &mut expr.kind {
+    ExprKind::Binary { op, left, right } => {
+      fold_expression(left, config);
+      fold_expression(right, config);
+
+      let (ExprKind::Literal(l), ExprKind::Literal(r)) =
+        (&left.kind, &right.kind)
+      else {
+        return;
+      };
+
+      let folded = binary(*op, l.to_value(), r.to_value(), expr.span, config)
+        .ok()
+        .and_then(|v| Literal::from_value(&v));
+
+      if let Some(literal) = folded {
+        expr.kind = ExprKind::Literal(literal);
+      }
+    }
+
+    ExprKind::If(branch) => fold_if(branch, config),
+
+    ExprKind::For { iterable, body, .. } => {
+      fold_expression(iterable, config);
+      fold_block(body, config);
+    }
+
+    // not shared with anything yet while the program is being prepared
+    ExprKind::Lambda { body, .. } => fold_block(Arc::make_mut(body), config),
+
+    ExprKind::Call(call) | ExprKind::Spawn(call) => {
+      fold_expression(&mut call.function, config);
+      for arg in &mut call.args {
+        fold_expression(arg, config);
+      }
+    }
+
+    ExprKind::Member { object, .. } => fold_expression(object, config),
+
+    ExprKind::Array(items) => {
+      for item in items {
+        fold_expression(item, config);
+      }
+    }
+
+    ExprKind::Index { index, .. } => fold_expression(index, config),
+
+    ExprKind::Await(handle) => fold_expression(handle, config),
+
+    ExprKind::Literal(_) | ExprKind::Identifier(_) => {}
+  }
+}
+
+fn fold_if(branch: &mut If, config: &Config) {
+  fold_expression(&mut branch.condition, config);
+  fold_block(&mut branch.consequence, config);
+
+  match &mut branch.alternative {
+    Some(Else::Block(block)) => fold_block(block, config),
+    Some(Else::If(branch, _)) => fold_if(branch, config),
+    None => {}
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::ast::BinaryOp;
+  use crate::config::DivisionPolicy;
+  use crate::error::Span;
+  use crate::value::Number;
+  use tree_sitter::Point;
+
+  fn span() -> Span {
+    return Span {
+      start: 0,
+      end: 0,
+      start_point: Point::default(),
+      end_point: Point::default(),
+    };
+  }
+
+  fn literal(literal: Literal) -> Expr {
+    return Expr {
+      kind: ExprKind::Literal(literal),
+      span: span(),
+    };
+  }
+
+  fn int(n: i64) -> Expr {
+    return literal(Literal::Number(Number::SamInt(n)));
+  }
+
+  fn binary_expr(op: BinaryOp, left: Expr, right: Expr) -> Expr {
+    return Expr {
+      kind: ExprKind::Binary {
+        op,
+        left: Box::new(left),
+        right: Box::new(right),
+      },
+      span: span(),
+    };
+  }
+
+  #[test]
+  fn test_fold_arithmetic() {
+    // 2 * 60 * 60
+    let mut expr = binary_expr(
+      BinaryOp::Mul,
+      binary_expr(BinaryOp::Mul, int(2), int(60)),
+      int(60),
+    );
+    fold_expression(&mut expr, &Config::default());
+    assert_eq!(expr, int(7200));
+
+    let mut expr = binary_expr(
+      BinaryOp::Add,
+      literal(Literal::String("a".to_owned())),
+      literal(Literal::String("b".to_owned())),
+    );
+    fold_expression(&mut expr, &Config::default());
+    assert_eq!(expr, literal(Literal::String("ab".to_owned())));
+  }
+
+  #[test]
+  fn test_fold_overflow() {
+    // 9223372036854775807 + 1, a float as when it runs
+    let mut expr = binary_expr(BinaryOp::Add, int(i64::MAX), int(1));
+    fold_expression(&mut expr, &Config::default());
+    assert!(matches!(
+      expr.kind,
+      ExprKind::Literal(Literal::Number(Number::SamFloat(f)))
+        if f == 2f64.powi(63)
+    ));
+
+    let mut expr = binary_expr(BinaryOp::Mul, int(i64::MIN), int(-1));
+    fold_expression(&mut expr, &Config::default());
+    assert!(matches!(
+      expr.kind,
+      ExprKind::Literal(Literal::Number(Number::SamFloat(_)))
+    ));
+  }
+
+  #[test]
+  fn test_keep_failing_operations() {
+    let config = Config {
+      division_by_zero: DivisionPolicy::Error,
+      ..Config::default()
+    };
+
+    let mut expr = binary_expr(BinaryOp::Div, int(1), int(0));
+    fold_expression(&mut expr, &config);
+    assert!(matches!(expr.kind, ExprKind::Binary { .. }));
+
+    // undefined has no literal
+    let mut expr = binary_expr(BinaryOp::Div, int(1), int(0));
+    fold_expression(&mut expr, &Config::default());
+    assert!(matches!(expr.kind, ExprKind::Binary { .. }));
+  }
+}
@@ -0,0 +1,267 @@
+#![allow(dead_code)]
+
+// Formatting of sam source for `sam fmt`, from the tokens of its syntax
+// tree: one statement per line, each ending in a semicolon, blocks indented
+// by two spaces, and one space around operators and after commas. Blank
+// lines between statements are kept, at most one in a row, and comments
+// stay at the end of their line or on a line of their own.
+//
+// The tree of the formatted source is compared with the original one, so
+// that formatting never changes what a script does.
+
+use crate::codes;
+use crate::comments::comments;
+use crate::error::SamError;
+use tree_sitter::Node;
+
+const INDENT: &str = "  ";
+
+// a token of the source, the text of its leaf node
+#[derive(Debug, Clone, PartialEq)]
+struct Token {
+  text: String,
+  // identifiers, numbers, and strings, as opposed to keywords and
+  // punctuation
+  named: bool,
+  // where the token starts, and the lines of the source it starts and
+  // ends on
+  start_byte: usize,
+  start_row: usize,
+  end_row: usize,
+}
+
+// `source` formatted, given its syntax tree, which must be free of syntax
+// errors
+pub fn format(root: Node, source: &str) -> Result<String, SamError> {
+  let mut tokens = Vec::new();
+  collect(root, source.as_bytes(), &mut tokens);
+
+  // the tree has no comments, they go back between the tokens around them
+  for range in comments(source.as_bytes()) {
+    let row = source[..range.start].matches('\n').count();
+    tokens.push(Token {
+      text: source[range.start..range.end].trim_end().to_owned(),
+      named: false,
+      start_byte: range.start,
+      start_row: row,
+      end_row: row,
+    });
+  }
+  tokens.sort_by_key(|t| t.start_byte);
+
+  let formatted = layout(&tokens);
+
+  let tree = crate::parser().parse(&formatted, None).unwrap();
+  if tree.root_node().to_sexp() != root.to_sexp() {
+    return Err(SamError::runtime(
+      codes::INTERNAL_ERROR,
+      "Formatting would change the program, it was left as is",
+    ));
+  }
+
+  return Ok(formatted);
+}
+
+// the tokens of `node` in order, with the semicolons statements leave out
+fn collect(node: Node, source: &[u8], tokens: &mut Vec<Token>) {
+  // strings are kept as written, escapes and all
+  if node.child_count() == 0 || matches!(node.kind(), "string" | "number") {
+    tokens.push(Token {
+      text: node.utf8_text(source).unwrap_or_default().to_owned(),
+      named: node.is_named(),
+      start_byte: node.start_byte(),
+      start_row: node.start_position().row,
+      end_row: node.end_position().row,
+    });
+    return;
+  }
+
+  let mut walker = node.walk();
+  for child in node.children(&mut walker) {
+    collect(child, source, tokens);
+  }
+
+  if is_statement(node) && !ends_with_semicolon(node) {
+    let row = node.end_position().row;
+    tokens.push(Token {
+      text: ";".to_owned(),
+      named: false,
+      start_byte: node.end_byte(),
+      start_row: row,
+      end_row: row,
+    });
+  }
+}
+
+pub(crate) fn is_statement(node: Node) -> bool {
+  let Some(parent) = node.parent() else {
+    return false;
+  };
+
+  return node.is_named()
+    && matches!(parent.kind(), "source_file" | "statement_block");
+}
+
+pub(crate) fn ends_with_semicolon(node: Node) -> bool {
+  let last = node.child(node.child_count().saturating_sub(1) as u32);
+  let next = node.next_sibling();
+
+  return [last, next].into_iter().flatten().any(|n| n.kind() == ";");
+}
+
+// what goes between two tokens
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Gap {
+  None,
+  Space,
+  Newline,
+}
+
+fn layout(tokens: &[Token]) -> String {
+  let mut out = String::new();
+  let mut depth: usize = 0;
+
+  for (i, token) in tokens.iter().enumerate() {
+    if token.text == "}" {
+      depth = depth.saturating_sub(1);
+    }
+
+    match i.checked_sub(1).map(|p| &tokens[p]) {
+      None => {}
+      Some(prev) => match gap(prev, token) {
+        Gap::None => {}
+        Gap::Space => out.push(' '),
+        Gap::Newline => {
+          // a blank line between statements stays, closing braces never
+          // follow one
+          if token.start_row > prev.end_row + 1 && token.text != "}" {
+            out.push('\n');
+          }
+          out.push('\n');
+          out.push_str(&INDENT.repeat(depth));
+        }
+      },
+    }
+
+    out.push_str(&token.text);
+
+    if token.text == "{" {
+      depth += 1;
+    }
+  }
+
+  if !out.is_empty() {
+    out.push('\n');
+  }
+
+  return out;
+}
+
+fn gap(prev: &Token, next: &Token) -> Gap {
+  let (p, n) = (prev.text.as_str(), next.text.as_str());
+
+  match (p, n) {
+    // a comment runs to the end of its line
+    _ if p.starts_with("//") => Gap::Newline,
+    _ if n.starts_with("//") && next.start_row == prev.end_row => Gap::Space,
+    _ if n.starts_with("//") => Gap::Newline,
+    ("{", "}") => Gap::None,
+    (";" | "{", _) | (_, "}") => Gap::Newline,
+    ("}", "else") => Gap::Space,
+    ("}", ";" | "," | ")" | "]" | ".") => Gap::None,
+    ("}", _) => Gap::Newline,
+    (_, ";" | "," | ")" | "]" | ".") | ("(" | "[" | ".", _) => Gap::None,
+    // calls and indexing, e.g. `f(x)` and `a[0]`
+    (_, "(" | "[") if prev.named || matches!(p, ")" | "]") => Gap::None,
+    _ => Gap::Space,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  // tokens of `text` split on spaces, each on a line of its own if
+  // `rows` is set
+  fn tokens(text: &str, rows: &[usize]) -> Vec<Token> {
+    return text
+      .split(' ')
+      .enumerate()
+      .map(|(i, t)| Token {
+        text: t.to_owned(),
+        named: t.chars().all(|c| c.is_alphanumeric() || c == '\'')
+          && !matches!(t, "let" | "return" | "if" | "else"),
+        start_byte: 0,
+        start_row: rows.get(i).copied().unwrap_or(0),
+        end_row: rows.get(i).copied().unwrap_or(0),
+      })
+      .collect();
+  }
+
+  #[test]
+  fn test_layout() {
+    assert_eq!(
+      layout(&tokens("let a = f ( 1 , [ 2 ] ) ;", &[])),
+      "let a = f(1, [2]);\n"
+    );
+    assert_eq!(
+      layout(&tokens(
+        "let f = ( x ) => { if ( x ) { return a [ 0 ] ; } else { } ; } ;",
+        &[]
+      )),
+      "let f = (x) => {\n  if (x) {\n    return a[0];\n  } else {};\n};\n"
+    );
+  }
+
+  #[test]
+  fn test_blank_lines() {
+    // at most one blank line, and none before a closing brace
+    let formatted =
+      layout(&tokens("a ; b ; { c ; }", &[0, 0, 3, 3, 4, 4, 4, 7]));
+    assert_eq!(formatted, "a;\n\nb;\n{\n  c;\n}\n");
+  }
+
+  #[test]
+  fn test_comments() {
+    // one at the end of a line, one on a line of its own
+    let formatted = layout(&tokens(
+      "{ a ; //one //two b ; }",
+      &[0, 0, 0, 0, 1, 2, 2, 3],
+    ));
+    assert_eq!(formatted, "{\n  a; //one\n  //two\n  b;\n}\n");
+  }
+
+  #[test]
+  fn test_format() {
+    let sources = [
+      "let a=1;let b = a+2",
+      "let f = (x,y) => {return x*y}; f(2,3);",
+      "if (a == 1) {\n\n\n  b = 2\n} else { b = 3 };",
+      "let xs=[1,2,3];for x in xs { let y = xs[0]; };",
+      "// totals\nlet a = 1 // one\nif (a) {\n// inside\nb = 2;}",
+    ];
+
+    for source in sources {
+      let tree = crate::parser().parse(source, None).unwrap();
+      let once = format(tree.root_node(), source).unwrap();
+
+      // formatting formatted source changes nothing
+      let tree = crate::parser().parse(&once, None).unwrap();
+      assert_eq!(format(tree.root_node(), &once).unwrap(), once, "{}", source);
+    }
+
+    let source = "let a=1;let b = a+2";
+    let tree = crate::parser().parse(source, None).unwrap();
+    assert_eq!(
+      format(tree.root_node(), source).unwrap(),
+      "let a = 1;\nlet b = a + 2;\n"
+    );
+
+    let source = "// totals\nlet a = 1 // one\nif (a) {\n// inside\nb = 2;}";
+    let tree = crate::parser().parse(source, None).unwrap();
+    assert_eq!(
+      format(tree.root_node(), source).unwrap(),
+      "// totals\nlet a = 1; // one\nif (a) {\n  // inside\n  b = 2;\n};\n"
+    );
+  }
+}
@@ -0,0 +1,156 @@
+#![allow(dead_code)]
+
+// Syntax highlighting of sam source for terminals, with the highlight
+// queries of the grammar, tree-sitter-sam/queries/highlights.scm. Used for
+// the input of the REPL, `sam ast`, and the source lines of diagnostics.
+//
+// As with the highlighters of editors, the innermost capture of a range
+// wins, and of the captures of one node the pattern written first.
+// Comments are not in the tree, they are painted over everything else.
+
+use crate::comments::comments;
+use std::sync::LazyLock;
+use tree_sitter::{Query, QueryCursor, StreamingIterator};
+
+const HIGHLIGHTS: &str =
+  include_str!("../tree-sitter-sam/queries/highlights.scm");
+
+// `None` if the queries do not fit the grammar, the source is then left
+// plain rather than failing whatever wanted it colored
+static QUERY: LazyLock<Option<Query>> =
+  LazyLock::new(|| Query::new(&crate::language(), HIGHLIGHTS).ok());
+
+pub const RESET: &str = "\x1b[0m";
+
+// the escape sequence for a capture name, by its first part, e.g. `string`
+// for `string.escape`
+fn style(capture: &str) -> &'static str {
+  match capture.split('.').next().unwrap_or_default() {
+    "keyword" | "conditional" | "repeat" | "include" => "\x1b[35m",
+    "string" | "escape" => "\x1b[32m",
+    "number" | "float" | "boolean" | "constant" => "\x1b[33m",
+    "function" | "method" => "\x1b[34m",
+    "property" | "type" | "module" | "namespace" => "\x1b[36m",
+    "comment" => "\x1b[2m",
+    _ => "",
+  }
+}
+
+// the escape sequence of every byte of `source`, empty for plain text
+pub fn styles(source: &str) -> Vec<&'static str> {
+  let mut styles = vec![""; source.len()];
+
+  let Some(query) = QUERY.as_ref() else {
+    return styles;
+  };
+
+  let Some(tree) = crate::parser().parse(source, None) else {
+    return styles;
+  };
+
+  let names = query.capture_names();
+  let mut ranges = Vec::new();
+  let mut cursor = QueryCursor::new();
+  let mut matches = cursor.matches(query, tree.root_node(), source.as_bytes());
+
+  while let Some(m) = matches.next() {
+    for capture in m.captures {
+      let range = capture.node.byte_range();
+      let style = style(names[capture.index as usize]);
+      ranges.push((range, m.pattern_index, style));
+    }
+  }
+
+  // painted outermost first and, for one node, the last pattern first, so
+  // what wins is painted last
+  ranges.sort_by(|(a, a_pattern, _), (b, b_pattern, _)| {
+    (b.end - b.start)
+      .cmp(&(a.end - a.start))
+      .then(b_pattern.cmp(a_pattern))
+  });
+
+  for (range, _, style) in ranges {
+    styles[range].fill(style);
+  }
+  for range in comments(source.as_bytes()) {
+    styles[range].fill(style("comment"));
+  }
+
+  return styles;
+}
+
+// the lines of `source` highlighted, each one colored on its own, so that
+// any of them can be printed without the others
+pub fn highlight(source: &str) -> Vec<String> {
+  let styles = styles(source);
+
+  let mut lines = Vec::new();
+  let mut start = 0;
+  for line in source.split('\n') {
+    let text = line.strip_suffix('\r').unwrap_or(line);
+    lines.push(paint_range(source, &styles, start, start + text.len()));
+    start += line.len() + 1;
+  }
+
+  return lines;
+}
+
+// the bytes `start..end` of `source` with the escape sequences of `styles`,
+// reset at the end
+pub fn paint_range(
+  source: &str,
+  styles: &[&'static str],
+  start: usize,
+  end: usize,
+) -> String {
+  let mut out = String::new();
+  let mut current = "";
+
+  for (i, c) in source[start..end].char_indices() {
+    let style = styles[start + i];
+    if style != current {
+      if !current.is_empty() {
+        out.push_str(RESET);
+      }
+      out.push_str(style);
+      current = style;
+    }
+    out.push(c);
+  }
+
+  if !current.is_empty() {
+    out.push_str(RESET);
+  }
+
+  return out;
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_paint_range() {
+    let source = "let a = 'x';\nb";
+    let mut styles = vec![""; source.len()];
+    styles[0..3].fill("K");
+    styles[8..11].fill("S");
+
+    assert_eq!(
+      paint_range(source, &styles, 0, 12),
+      format!("Klet{} a = S'x'{};", RESET, RESET)
+    );
+    assert_eq!(paint_range(source, &styles, 13, 14), "b");
+  }
+
+  #[test]
+  fn test_highlight() {
+    let source = "// sum\nlet total = 1 + 'two';";
+    let lines = highlight(source);
+
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].starts_with(style("comment")));
+    assert!(lines[1].contains(&format!("{}let{}", style("keyword"), RESET)));
+    assert!(lines[1].contains(&format!("{}'two'{}", style("string"), RESET)));
+  }
+}
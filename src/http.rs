@@ -0,0 +1,281 @@
+#![allow(dead_code)]
+
+use crate::codes;
+use crate::error::SamError;
+use crate::ffi::FFI;
+use crate::permissions::Permissions;
+use crate::process;
+use crate::value::{NativeFunction, Value};
+use std::collections::HashMap;
+use std::error::Error;
+use std::io;
+use std::time::Duration;
+
+// a foreign function served over HTTP: every call POSTs its arguments as a
+// JSON array to `url` and the JSON response becomes the result
+pub fn endpoint(
+  name: &str,
+  url: String,
+  timeout: Option<Duration>,
+  permissions: Permissions,
+) -> NativeFunction {
+  return NativeFunction::new(name, move |args| {
+    permissions.check_net(&url)?;
+    post(&url, args, timeout)
+  });
+}
+
+fn post(
+  url: &str,
+  args: &[Value],
+  timeout: Option<Duration>,
+) -> Result<Value, SamError> {
+  let body = serde_json::Value::Array(
+    args
+      .iter()
+      .map(FFI::value_to_json)
+      .collect::<Result<_, SamError>>()?,
+  );
+
+  let mut request = ureq::post(url).set("Content-Type", "application/json");
+  if let Some(timeout) = timeout {
+    request = request.timeout(timeout);
+  }
+
+  let response = request
+    .send_string(&body.to_string())
+    .map_err(|e| request_error(e, url, timeout))?;
+
+  let text = response.into_string().map_err(|e| {
+    SamError::ffi(
+      codes::REQUEST_FAILED,
+      format!("Could not read the response of {}", url),
+    )
+    .caused_by(e)
+  })?;
+
+  let parsed: serde_json::Value = serde_json::from_str(&text).map_err(|e| {
+    SamError::ffi(
+      codes::FOREIGN_OUTPUT,
+      format!("There was an error in parsing the response of {}.", url),
+    )
+    .caused_by(e)
+  })?;
+
+  return FFI::json_to_value(parsed);
+}
+
+// a request of `http_get` or `http_post`, answered by an object of the
+// response's status, headers, and body, whatever the status. Header names
+// are in lowercase, repeated ones joined by commas.
+pub fn request(
+  method: &str,
+  url: &str,
+  body: Option<&str>,
+  headers: &[(String, String)],
+  timeout: Option<Duration>,
+  permissions: &Permissions,
+) -> Result<Value, SamError> {
+  permissions.check_net(url)?;
+
+  let mut request = ureq::request(method, url);
+  for (name, value) in headers {
+    request = request.set(name, value);
+  }
+  if let Some(timeout) = timeout {
+    request = request.timeout(timeout);
+  }
+
+  let sent = match body {
+    Some(body) => request.send_string(body),
+    None => request.call(),
+  };
+  let response = match sent {
+    Ok(response) => response,
+    Err(ureq::Error::Status(_, response)) => response,
+    Err(e) => return Err(request_error(e, url, timeout)),
+  };
+
+  let status = Value::from(response.status() as i64);
+  let mut names = response.headers_names();
+  names.dedup();
+  let headers: HashMap<String, Value> = names
+    .iter()
+    .map(|name| {
+      let values = response.all(name).join(", ");
+      (name.to_lowercase(), Value::from(values))
+    })
+    .collect();
+
+  let body = response.into_string().map_err(|e| {
+    SamError::ffi(
+      codes::REQUEST_FAILED,
+      format!("Could not read the response of {}", url),
+    )
+    .caused_by(e)
+  })?;
+
+  let response = HashMap::from([
+    ("status".to_owned(), status),
+    ("headers".to_owned(), Value::from(headers)),
+    ("body".to_owned(), Value::from(body)),
+  ]);
+  return Ok(Value::from(response));
+}
+
+fn request_error(
+  e: ureq::Error,
+  url: &str,
+  timeout: Option<Duration>,
+) -> SamError {
+  match e {
+    ureq::Error::Status(status, response) => {
+      let err = SamError::ffi(
+        codes::REQUEST_FAILED,
+        format!("Request to {} failed with status {}", url, status),
+      );
+
+      // servers usually explain the failure in the body
+      match response.into_string() {
+        Ok(body) if !body.trim().is_empty() => {
+          err.caused_by(body.trim().to_owned())
+        }
+        _ => err,
+      }
+    }
+
+    ureq::Error::Transport(t) => {
+      let timed_out = t
+        .source()
+        .and_then(|s| s.downcast_ref::<io::Error>())
+        .is_some_and(|e| {
+          matches!(
+            e.kind(),
+            io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock
+          )
+        });
+
+      match timeout {
+        Some(limit) if timed_out => SamError::ffi(
+          codes::TIMEOUT,
+          format!(
+            "Request to {} timed out after {}",
+            url,
+            process::format_duration(limit)
+          ),
+        ),
+        _ => SamError::ffi(
+          codes::REQUEST_FAILED,
+          format!("Could not send a request to {}", url),
+        )
+        .caused_by(t),
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::value::Number;
+  use std::io::{BufRead, BufReader, Read, Write};
+  use std::net::TcpListener;
+  use std::thread;
+
+  type Receiver = thread::JoinHandle<String>;
+
+  // answer one request with `status` and `body`, returning the url and the
+  // body the client sent
+  fn serve(status: &'static str, body: &'static str) -> (String, Receiver) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/predict", listener.local_addr().unwrap());
+
+    let received = thread::spawn(move || {
+      let (stream, _) = listener.accept().unwrap();
+      let mut reader = BufReader::new(stream);
+
+      let mut length = 0;
+      loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        if line == "\r\n" {
+          break;
+        }
+        if let Some(value) = line.to_lowercase().strip_prefix("content-length:")
+        {
+          length = value.trim().parse().unwrap();
+        }
+      }
+
+      let mut request = vec![0; length];
+      reader.read_exact(&mut request).unwrap();
+
+      write!(
+        reader.get_mut(),
+        "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+      )
+      .unwrap();
+
+      String::from_utf8(request).unwrap()
+    });
+
+    return (url, received);
+  }
+
+  #[test]
+  fn test_endpoint_call() {
+    let (url, received) = serve("200 OK", "{\"label\": \"cat\"}");
+    let predict = endpoint("predict", url, None, Permissions::default());
+
+    let Value::SamObject(result) = predict
+      .call(&[Value::SamNumber(Number::SamInt(1)), Value::Undefined])
+      .unwrap()
+    else {
+      panic!("expected an object");
+    };
+
+    assert_eq!(result["label"], Value::from("cat"));
+    assert_eq!(received.join().unwrap(), "[1,null]");
+  }
+
+  #[test]
+  fn test_request() {
+    let (url, received) = serve("404 Not Found", "no such user");
+    let headers = [("X-Token".to_owned(), "secret".to_owned())];
+    let permissions = Permissions::default();
+
+    let response =
+      request("POST", &url, Some("{}"), &headers, None, &permissions);
+    let Ok(Value::SamObject(response)) = response else {
+      panic!("expected an object");
+    };
+
+    assert_eq!(response["status"], Value::from(404i64));
+    assert_eq!(response["body"], Value::from("no such user"));
+    let Value::SamObject(headers) = &response["headers"] else {
+      panic!("expected the headers");
+    };
+    assert_eq!(headers["content-length"], Value::from("12"));
+    assert_eq!(received.join().unwrap(), "{}");
+
+    let sandboxed = Permissions {
+      net: crate::permissions::Allow::Nothing,
+      ..Permissions::default()
+    };
+    let err = request("GET", &url, None, &[], None, &sandboxed).unwrap_err();
+    assert_eq!(err.code(), Some(codes::PERMISSION_DENIED));
+  }
+
+  #[test]
+  fn test_endpoint_status() {
+    let (url, _received) = serve("500 Internal Server Error", "model missing");
+    let predict = endpoint("predict", url, None, Permissions::default());
+
+    let err = predict.call(&[]).unwrap_err();
+    assert_eq!(err.code(), Some(codes::REQUEST_FAILED));
+    assert!(err.message().ends_with("failed with status 500"));
+  }
+}
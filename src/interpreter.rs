@@ -0,0 +1,459 @@
+#![allow(dead_code)]
+
+use crate::bytecode::Chunk;
+use crate::codes;
+use crate::config::{Backend, Config, Limits};
+use crate::context::Context;
+use crate::crash;
+use crate::error::SamError;
+use crate::evaluate::evaluate_source;
+use crate::interrupt::InterruptHandle;
+use crate::output::{Reader, Writer};
+use crate::permissions::Permissions;
+use crate::reparse::Reparser;
+use crate::syntax::syntax_errors;
+use crate::value::{NativeFunction, Value};
+use crate::vm;
+use std::path::PathBuf;
+use tree_sitter::Tree;
+
+// a sam session for programs embedding the language. Every evaluated source
+// is appended to one session source, like the lines of the REPL, so
+// variables and functions defined by one `eval` are there for the next.
+pub struct Interpreter {
+  // the session source grows with every eval, so only what was added to it
+  // is parsed again
+  parser: Reparser,
+  // every source accepted so far, followed by the last one if it was
+  // rejected for its syntax errors
+  source: String,
+  // length of the accepted part of `source`
+  accepted: usize,
+  ctx: Context,
+}
+
+impl Interpreter {
+  pub fn new() -> Interpreter {
+    return Interpreter::with_config(Config::default());
+  }
+
+  // settings for a new interpreter, e.g.
+  //
+  //   Interpreter::options().strict(true).max_depth(64).build()
+  pub fn options() -> InterpreterOptions {
+    return InterpreterOptions::default();
+  }
+
+  pub fn with_config(config: Config) -> Interpreter {
+    return Interpreter {
+      parser: Reparser::new(),
+      source: String::new(),
+      accepted: 0,
+      ctx: Context::with_config(config),
+    };
+  }
+
+  // run `source` after everything evaluated before, returning the value of
+  // its last statement. Source with syntax errors is not run at all, and a
+  // failed run keeps what it did up to the error.
+  pub fn eval(&mut self, source: &str) -> Result<Value, SamError> {
+    let (tree, start) = self.parse(source)?;
+    return self.run(tree, start);
+  }
+
+  // evaluate a single expression, e.g. `a + b * 2`, against the variables
+  // of the session and return its value. Anything else, a declaration or
+  // two statements, is an error and nothing is run.
+  pub fn eval_expr(&mut self, expression: &str) -> Result<Value, SamError> {
+    let expression = expression.trim().trim_end_matches(';');
+    let (tree, start) = self.parse(&format!("{};", expression))?;
+
+    expect_expression(&tree, start)?;
+
+    return self.run(tree, start);
+  }
+
+  // run a program compiled ahead by `cache::compile` on the vm, e.g. one
+  // kept by an earlier run. `source` is the text it was compiled from, which
+  // the spans of its errors point into, so it must be the first thing the
+  // session runs.
+  pub fn run_compiled(
+    &mut self,
+    source: &str,
+    chunk: Chunk,
+  ) -> Result<Value, SamError> {
+    self.source.truncate(self.accepted);
+    self.source.push_str(source);
+    self.source.push('\n');
+    self.accepted = self.source.len();
+    self.ctx.config.interrupt.reset();
+
+    let ctx = &mut self.ctx;
+    let result = crash::catch(|| vm::run_compiled(chunk, ctx));
+
+    if result.is_err() {
+      self.ctx.unwind_to_global();
+    }
+
+    return result;
+  }
+
+  // every syntax error of `source` after the session source, without
+  // running it, e.g. for the REPL to report them all where `eval` stops at
+  // the first. The spans point into `source()`.
+  pub fn check_syntax(&mut self, source: &str) -> Vec<SamError> {
+    let (tree, _) = self.parse_unchecked(source);
+    return syntax_errors(tree.root_node(), self.source.as_bytes());
+  }
+
+  // the tree of the session source followed by `source`, and where
+  // `source` starts in it. The session is unchanged until `run`.
+  fn parse(&mut self, source: &str) -> Result<(Tree, usize), SamError> {
+    let (tree, start) = self.parse_unchecked(source);
+
+    if let Some(err) = syntax_errors(tree.root_node(), self.source.as_bytes())
+      .into_iter()
+      .next()
+    {
+      return Err(err);
+    }
+
+    return Ok((tree, start));
+  }
+
+  fn parse_unchecked(&mut self, source: &str) -> (Tree, usize) {
+    self.source.truncate(self.accepted);
+    let start = self.source.len();
+    self.source.push_str(source);
+    self.source.push('\n');
+
+    return (self.parser.parse(&self.source), start);
+  }
+
+  fn run(&mut self, tree: Tree, start: usize) -> Result<Value, SamError> {
+    self.accepted = self.source.len();
+    // an interrupt only stops the run it was meant for
+    self.ctx.config.interrupt.reset();
+
+    let root = tree.root_node();
+    let ctx = &mut self.ctx;
+    let text = self.source.as_bytes();
+    let result = crash::catch(|| evaluate_source(&root, text, ctx, start));
+
+    if result.is_err() {
+      self.ctx.unwind_to_global();
+    }
+
+    return result;
+  }
+
+  // a handle that stops the running `eval` from another thread, e.g. a
+  // Ctrl-C handler. Evaluation fails with a CANCELLED error before its next
+  // statement or expression, and a command it waits for is killed.
+  pub fn interrupt_handle(&self) -> InterruptHandle {
+    return self.ctx.config.interrupt.clone();
+  }
+
+  // make a Rust closure a function scripts can call by `name`, like a
+  // builtin but without starting a process. It replaces any variable of
+  // the same name.
+  pub fn register_fn(
+    &mut self,
+    name: &str,
+    f: impl Fn(&[Value]) -> Result<Value, SamError> + Send + Sync + 'static,
+  ) {
+    self.register(NativeFunction::new(name, f));
+  }
+
+  pub fn register(&mut self, function: NativeFunction) {
+    let name = function.name.clone();
+    self.ctx.set_global(&name, Value::from(function));
+  }
+
+  // seed a variable before evaluating, e.g. `set_global("config", value)`
+  pub fn set_global(&mut self, name: &str, value: impl Into<Value>) {
+    self.ctx.set_global(name, value);
+  }
+
+  // a variable the script left behind, by name
+  pub fn get_global(&self, name: &str) -> Option<&Value> {
+    return self.ctx.get_global(name);
+  }
+
+  // the text the spans of errors returned by `eval` point into, for
+  // rendering them with `Diagnostic`
+  pub fn source(&self) -> &str {
+    return &self.source;
+  }
+
+  // the variables, settings, and warnings of the session
+  pub fn context(&self) -> &Context {
+    return &self.ctx;
+  }
+
+  pub fn context_mut(&mut self) -> &mut Context {
+    return &mut self.ctx;
+  }
+}
+
+// the source after `start` must be exactly one expression statement
+fn expect_expression(tree: &Tree, start: usize) -> Result<(), SamError> {
+  let root = tree.root_node();
+  let mut walker = root.walk();
+  let statements: Vec<_> = root
+    .named_children(&mut walker)
+    .filter(|n| n.start_byte() >= start)
+    .collect();
+
+  match statements.as_slice() {
+    [statement] if statement.kind() == "expression_statement" => {}
+    [statement] => {
+      return Err(
+        SamError::parse(
+          codes::NOT_AN_EXPRESSION,
+          format!(
+            "Expected an expression, found a {}",
+            statement.kind().replace('_', " ")
+          ),
+        )
+        .at(statement),
+      );
+    }
+    [_, extra, ..] => {
+      return Err(
+        SamError::parse(
+          codes::NOT_AN_EXPRESSION,
+          "Expected a single expression, found more after it",
+        )
+        .at(extra),
+      );
+    }
+    [] => {
+      return Err(SamError::parse(
+        codes::NOT_AN_EXPRESSION,
+        "Expected an expression, found nothing",
+      ));
+    }
+  }
+
+  return Ok(());
+}
+
+impl Default for Interpreter {
+  fn default() -> Interpreter {
+    return Interpreter::new();
+  }
+}
+
+/* =========================
+Options
+========================= */
+
+// builds an interpreter one setting at a time, leaving everything not set
+// at its default, so new settings do not break existing embedders
+#[derive(Debug, Clone, Default)]
+pub struct InterpreterOptions {
+  config: Config,
+}
+
+impl InterpreterOptions {
+  // start from settings read elsewhere, e.g. a project's sam.toml
+  pub fn from_config(config: Config) -> Self {
+    return InterpreterOptions { config };
+  }
+
+  // operations on undefined or mismatched values are errors
+  pub fn strict(mut self, strict: bool) -> Self {
+    self.config.strict = strict;
+    return self;
+  }
+
+  // what runs the scripts, the tree-walker unless set
+  pub fn backend(mut self, backend: Backend) -> Self {
+    self.config.backend = backend;
+    return self;
+  }
+
+  // how deep sam function calls may nest before the script is stopped
+  pub fn max_depth(mut self, depth: usize) -> Self {
+    self.config.limits.call_depth = Some(depth);
+    return self;
+  }
+
+  // what the script may run, read, and connect to
+  pub fn permissions(mut self, permissions: Permissions) -> Self {
+    self.config.permissions = permissions;
+    return self;
+  }
+
+  // another directory searched for interface files, after those added
+  // before
+  pub fn interface_path(mut self, dir: impl Into<PathBuf>) -> Self {
+    self.config.interface_path.push(dir.into());
+    return self;
+  }
+
+  // where the script reads its input from, stdin unless set
+  pub fn stdin(mut self, input: Reader) -> Self {
+    self.config.output.stdin = input;
+    return self;
+  }
+
+  // where output meant for the user goes, stdout unless set
+  pub fn stdout(mut self, out: Writer) -> Self {
+    self.config.output.stdout = out;
+    return self;
+  }
+
+  // where diagnostics and traces go, stderr unless set
+  pub fn stderr(mut self, out: Writer) -> Self {
+    self.config.output.stderr = out;
+    return self;
+  }
+
+  // bounds on the work the script may do; replaces a depth set before
+  pub fn limits(mut self, limits: Limits) -> Self {
+    self.config.limits = limits;
+    return self;
+  }
+
+  pub fn build(self) -> Interpreter {
+    return Interpreter::with_config(self.config);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::value::Number;
+
+  #[test]
+  fn test_eval_keeps_state() {
+    let mut sam = Interpreter::new();
+
+    sam.eval("let a = 20;").unwrap();
+    sam.eval("let double = (x) => { return x * 2; };").unwrap();
+    assert_eq!(
+      sam.eval("double(a) + 2;").unwrap(),
+      Value::SamNumber(Number::SamInt(42))
+    );
+  }
+
+  #[test]
+  fn test_eval_rejects_syntax_errors() {
+    let mut sam = Interpreter::new();
+
+    sam.eval("let a = 1;").unwrap();
+    assert!(matches!(
+      sam.eval("let b = ;"),
+      Err(SamError::ParseError { .. })
+    ));
+    assert!(sam.source().ends_with("let b = ;\n"));
+
+    // the rejected source is gone once something else is evaluated
+    assert_eq!(sam.eval("a;").unwrap(), Value::SamNumber(Number::SamInt(1)));
+    assert_eq!(sam.source(), "let a = 1;\na;\n");
+  }
+
+  #[test]
+  fn test_check_syntax() {
+    let mut sam = Interpreter::new();
+    sam.eval("let a = 1;").unwrap();
+
+    // every error is reported, in the session source, and nothing runs
+    let errors = sam.check_syntax("let b = ;\nlet c = ;");
+    assert_eq!(errors.len(), 2);
+    assert!(sam.source().starts_with("let a = 1;\nlet b = ;"));
+    assert!(sam.get_global("b").is_none());
+
+    assert!(sam.check_syntax("a + 1;").is_empty());
+    assert_eq!(
+      sam.eval("a + 1;").unwrap(),
+      Value::SamNumber(Number::SamInt(2))
+    );
+  }
+
+  #[test]
+  fn test_eval_expr() {
+    let mut sam = Interpreter::new();
+    sam.eval("let a = 2; let b = 5;").unwrap();
+
+    assert_eq!(
+      sam.eval_expr("a + b * 2").unwrap(),
+      Value::SamNumber(Number::SamInt(12))
+    );
+
+    let err = sam.eval_expr("let c = 1").unwrap_err();
+    assert_eq!(err.code(), Some(codes::NOT_AN_EXPRESSION));
+    let err = sam.eval_expr("a; b").unwrap_err();
+    assert_eq!(err.code(), Some(codes::NOT_AN_EXPRESSION));
+    assert!(sam.eval_expr("c").is_err());
+  }
+
+  #[test]
+  fn test_register_fn() {
+    let mut sam = Interpreter::new();
+    sam.register_fn("twice", |args| match args {
+      [Value::SamString(s)] => Ok(Value::from(s.repeat(2))),
+      _ => Err(SamError::type_error(
+        codes::ARGUMENT_TYPE,
+        "expected a string",
+      )),
+    });
+
+    assert_eq!(sam.eval("twice('ab');").unwrap(), Value::from("abab"));
+
+    let err = sam.eval("twice(1);").unwrap_err();
+    assert_eq!(err.code(), Some(codes::ARGUMENT_TYPE));
+  }
+
+  #[test]
+  fn test_globals() {
+    let mut sam = Interpreter::new();
+    sam.set_global("limit", 10i64);
+    sam.set_global("names", vec!["a", "b"]);
+
+    sam
+      .eval("let f = (limit) => { return limit; }; let r = f(1) + limit;")
+      .unwrap();
+
+    assert_eq!(sam.get_global("r"), Some(&Value::from(11i64)));
+    assert_eq!(sam.get_global("names"), Some(&Value::from(vec!["a", "b"])));
+    assert_eq!(sam.get_global("missing"), None);
+  }
+
+  #[test]
+  fn test_options() {
+    let mut sam = Interpreter::options().strict(true).max_depth(8).build();
+
+    assert!(sam.context().config.strict);
+
+    sam
+      .eval("let down = (n) => { return down(n + 1); };")
+      .unwrap();
+    let err = sam.eval("down(0);").unwrap_err();
+    assert_eq!(err.code(), Some(codes::LIMIT_EXCEEDED));
+
+    // a failed call leaves no depth behind
+    sam.eval("let one = () => { return 1; };").unwrap();
+    assert_eq!(sam.eval("one();").unwrap(), Value::from(1i64));
+  }
+
+  #[test]
+  fn test_interrupt() {
+    let mut sam = Interpreter::new();
+    let handle = sam.interrupt_handle();
+    sam.register_fn("stop", move |_| {
+      handle.interrupt();
+      Ok(Value::Undefined)
+    });
+
+    let err = sam.eval("let a = 1; stop(); let b = 2;").unwrap_err();
+    assert_eq!(err.code(), Some(codes::CANCELLED));
+    assert_eq!(sam.get_global("a"), Some(&Value::from(1i64)));
+    assert_eq!(sam.get_global("b"), None);
+
+    // the next run is not affected
+    assert_eq!(sam.eval("a;").unwrap(), Value::from(1i64));
+  }
+}
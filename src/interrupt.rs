@@ -0,0 +1,47 @@
+#![allow(dead_code)]
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// how long an interrupt may go unnoticed while sleeping
+const POLL: Duration = Duration::from_millis(50);
+
+// stops a running script from another thread, e.g. a host's cancel button
+// or a Ctrl-C handler. The evaluator stops before its next step and running
+// external commands are killed.
+#[derive(Debug, Clone, Default)]
+pub struct InterruptHandle {
+  interrupted: Arc<AtomicBool>,
+}
+
+impl InterruptHandle {
+  pub fn interrupt(&self) {
+    self.interrupted.store(true, Ordering::Relaxed);
+  }
+
+  pub fn is_interrupted(&self) -> bool {
+    return self.interrupted.load(Ordering::Relaxed);
+  }
+
+  // false if interrupted before `duration` was over
+  pub fn sleep(&self, duration: Duration) -> bool {
+    let start = Instant::now();
+    loop {
+      if self.is_interrupted() {
+        return false;
+      }
+      let left = duration.saturating_sub(start.elapsed());
+      if left.is_zero() {
+        return true;
+      }
+      thread::sleep(left.min(POLL));
+    }
+  }
+
+  // ready for the next run
+  pub fn reset(&self) {
+    self.interrupted.store(false, Ordering::Relaxed);
+  }
+}
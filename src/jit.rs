@@ -0,0 +1,755 @@
+#![allow(dead_code)]
+
+// The jit tier of the vm backend, behind the `jit` feature: a sam function
+// the vm calls often is compiled to native code with cranelift, if all it
+// does is integer arithmetic on its parameters and locals, branches, and
+// calls of itself. Anything dynamic, e.g. a global, a string, or a loop,
+// keeps the function in the vm.
+//
+// Native code never fails. Where it could not go on like the vm would, e.g.
+// on an overflow or a return of undefined, it bails out and the vm runs the
+// call again from the start, which is safe since such functions have no
+// effects.
+
+use crate::ast::BinaryOp;
+use crate::bytecode::{Chunk, Op};
+use crate::symbol::Symbol;
+use crate::value::{Number, Value};
+use cranelift_codegen::ir::condcodes::IntCC;
+use cranelift_codegen::ir::{
+  self, AbiParam, Block, FuncRef, InstBuilder, MemFlags, types,
+};
+use cranelift_codegen::settings::{self, Configurable};
+use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext, Variable};
+use cranelift_jit::{JITBuilder, JITModule};
+use cranelift_module::{FuncId, Module, default_libcall_names};
+use std::collections::HashMap;
+
+// calls of a function before it is compiled, and bail outs of its native
+// code before it goes back to the vm for good
+pub const HOT_CALLS: u32 = 50;
+
+// native recursion runs on the Rust stack, deeper calls bail out
+const MAX_DEPTH: i64 = 1000;
+
+// arguments, bail out flag, and depth left
+type Entry = unsafe extern "C" fn(*const i64, *mut u8, i64) -> i64;
+
+// native code of a function, valid as long as the Jit that made it
+pub struct Compiled {
+  entry: Entry,
+  arity: usize,
+  // the name the function calls itself by
+  recursive: Option<Symbol>,
+}
+
+impl Compiled {
+  // the result of a call with integer arguments, none if an argument is not
+  // an integer or the code bailed out
+  pub fn call(&self, args: &[Value], depth: i64) -> Option<Value> {
+    let ints = args
+      .iter()
+      .map(|v| match v {
+        Value::SamNumber(Number::SamInt(i)) => Some(*i),
+        _ => None,
+      })
+      .collect::<Option<Vec<i64>>>()?;
+
+    if ints.len() != self.arity {
+      return None;
+    }
+
+    let mut failed = 0u8;
+    // the entry reads exactly `arity` arguments
+    let v =
+      unsafe { (self.entry)(ints.as_ptr(), &mut failed, depth.min(MAX_DEPTH)) };
+
+    if failed != 0 {
+      return None;
+    }
+
+    return Some(Value::from(v));
+  }
+
+  // the name the function calls itself by, which has to still be it for
+  // the native code to be used
+  pub fn recursive(&self) -> Option<Symbol> {
+    return self.recursive;
+  }
+}
+
+pub struct Jit {
+  // only none while dropped
+  module: Option<JITModule>,
+  builder: FunctionBuilderContext,
+}
+
+impl Jit {
+  // none if cranelift cannot generate code for this machine
+  pub fn new() -> Option<Jit> {
+    let mut flags = settings::builder();
+    flags.set("use_colocated_libcalls", "false").ok()?;
+    flags.set("is_pic", "false").ok()?;
+
+    let isa = cranelift_native::builder()
+      .ok()?
+      .finish(settings::Flags::new(flags))
+      .ok()?;
+
+    return Some(Jit {
+      module: Some(JITModule::new(JITBuilder::with_isa(
+        isa,
+        default_libcall_names(),
+      ))),
+      builder: FunctionBuilderContext::new(),
+    });
+  }
+
+  // native code of the function with `params` and body `chunk`, called by
+  // `name`; none if it does something the jit does not compile
+  pub fn compile(
+    &mut self,
+    chunk: &Chunk,
+    params: &[Symbol],
+    name: Symbol,
+  ) -> Option<Compiled> {
+    let plan = plan(chunk, params, name)?;
+    let module = self.module.as_mut().unwrap();
+    let pointer = module.target_config().pointer_type();
+
+    // the body: bail out flag, depth left, and the parameters
+    let mut signature = module.make_signature();
+    signature.params.push(AbiParam::new(pointer));
+    signature.params.push(AbiParam::new(types::I64));
+    for _ in params {
+      signature.params.push(AbiParam::new(types::I64));
+    }
+    signature.returns.push(AbiParam::new(types::I64));
+
+    let body = module.declare_anonymous_function(&signature).ok()?;
+    let mut ctx = module.make_context();
+    ctx.func.signature = signature;
+
+    let mut b = FunctionBuilder::new(&mut ctx.func, &mut self.builder);
+    let own = module.declare_func_in_func(body, b.func);
+    translate(&mut b, chunk, params, &plan, own);
+    b.seal_all_blocks();
+    b.finalize();
+
+    module.define_function(body, &mut ctx).ok()?;
+    module.clear_context(&mut ctx);
+
+    let entry = self.entry(body, params.len())?;
+    let module = self.module.as_mut().unwrap();
+    module.finalize_definitions().ok()?;
+
+    let code = module.get_finalized_function(entry);
+    return Some(Compiled {
+      // made with the signature of Entry
+      entry: unsafe { std::mem::transmute::<*const u8, Entry>(code) },
+      arity: params.len(),
+      recursive: plan.recursive.then_some(name),
+    });
+  }
+
+  // a function with the signature of Entry, calling the body with the
+  // arguments read from memory
+  fn entry(&mut self, body: FuncId, arity: usize) -> Option<FuncId> {
+    let module = self.module.as_mut().unwrap();
+    let pointer = module.target_config().pointer_type();
+
+    let mut signature = module.make_signature();
+    signature.params.push(AbiParam::new(pointer));
+    signature.params.push(AbiParam::new(pointer));
+    signature.params.push(AbiParam::new(types::I64));
+    signature.returns.push(AbiParam::new(types::I64));
+
+    let entry = module.declare_anonymous_function(&signature).ok()?;
+    let mut ctx = module.make_context();
+    ctx.func.signature = signature;
+
+    let mut b = FunctionBuilder::new(&mut ctx.func, &mut self.builder);
+    let body = module.declare_func_in_func(body, b.func);
+
+    let start = b.create_block();
+    b.append_block_params_for_function_params(start);
+    b.switch_to_block(start);
+    let (args, failed, depth) = {
+      let params = b.block_params(start);
+      (params[0], params[1], params[2])
+    };
+
+    let mut values = vec![failed, depth];
+    for i in 0..arity {
+      let offset = (i * 8) as i32;
+      values.push(b.ins().load(types::I64, MemFlags::trusted(), args, offset));
+    }
+
+    let call = b.ins().call(body, &values);
+    let result = b.inst_results(call)[0];
+    b.ins().return_(&[result]);
+    b.seal_all_blocks();
+    b.finalize();
+
+    module.define_function(entry, &mut ctx).ok()?;
+    module.clear_context(&mut ctx);
+
+    return Some(entry);
+  }
+}
+
+impl Drop for Jit {
+  fn drop(&mut self) {
+    // whoever holds Compiled code of this jit no longer runs it
+    if let Some(module) = self.module.take() {
+      unsafe { module.free_memory() };
+    }
+  }
+}
+
+/* =========================
+Planning
+========================= */
+
+// what the compiler needs to know about a body it can compile
+struct Plan {
+  // variables declared in the body
+  locals: Vec<Symbol>,
+  // the instructions that can run
+  reached: Vec<bool>,
+  // instructions jumped to by ones that can run
+  targets: Vec<usize>,
+  recursive: bool,
+}
+
+// a value on the stack while compiling
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+  Int,
+  Undefined,
+}
+
+// check that every instruction of the body that can run has native code,
+// following the stack and the scopes through it
+fn plan(chunk: &Chunk, params: &[Symbol], own: Symbol) -> Option<Plan> {
+  let code = &chunk.code;
+
+  let mut scopes = vec![params.to_vec()];
+  let mut locals = Vec::new();
+  let mut stack = Vec::new();
+  let mut reached = vec![false; code.len()];
+  let mut recursive = false;
+
+  // number of scopes open at the jumps to each instruction
+  let mut entries: HashMap<usize, usize> = HashMap::new();
+  let mut live = true;
+
+  let visible = |scopes: &[Vec<Symbol>], name: Symbol| {
+    return scopes.iter().any(|scope| scope.contains(&name));
+  };
+
+  for (at, &op) in code.iter().enumerate() {
+    if let Some(&depth) = entries.get(&at) {
+      // a jump and the instruction before agree on the scopes, and values
+      // are only left on the stack between jumps
+      if live && (depth != scopes.len() || !stack.is_empty()) {
+        return None;
+      }
+      if depth > scopes.len() {
+        return None;
+      }
+      scopes.truncate(depth);
+      stack.clear();
+      live = true;
+    }
+
+    if !live {
+      continue;
+    }
+    reached[at] = true;
+
+    let mut jump = |target: usize, scopes: usize| {
+      if target <= at || target >= code.len() {
+        return false;
+      }
+      return *entries.entry(target).or_insert(scopes) == scopes;
+    };
+
+    match op {
+      Op::Constant(n) => match chunk.constants[n] {
+        Value::SamNumber(Number::SamInt(_)) => stack.push(Kind::Int),
+        _ => return None,
+      },
+
+      Op::Undefined => stack.push(Kind::Undefined),
+
+      Op::Pop => {
+        stack.pop()?;
+      }
+
+      Op::Load(n) => {
+        if !visible(&scopes, chunk.name(n)) {
+          return None;
+        }
+        stack.push(Kind::Int);
+      }
+
+      // every variable is declared once, so that it needs no scope
+      Op::Declare(n) => {
+        let name = chunk.name(n);
+        if stack.pop()? != Kind::Int
+          || params.contains(&name)
+          || locals.contains(&name)
+        {
+          return None;
+        }
+        scopes.last_mut().unwrap().push(name);
+        locals.push(name);
+      }
+
+      Op::Store(n) => {
+        if !visible(&scopes, chunk.name(n)) || stack.last()? != &Kind::Int {
+          return None;
+        }
+      }
+
+      Op::Binary(op) => {
+        if stack.pop()? != Kind::Int
+          || stack.pop()? != Kind::Int
+          || !compiles(op)
+        {
+          return None;
+        }
+        stack.push(Kind::Int);
+      }
+
+      Op::BinaryConstant { op, constant } => {
+        let Value::SamNumber(Number::SamInt(_)) = chunk.constants[constant]
+        else {
+          return None;
+        };
+        if stack.pop()? != Kind::Int || !compiles(op) {
+          return None;
+        }
+        stack.push(Kind::Int);
+      }
+
+      Op::EnterScope => scopes.push(Vec::new()),
+
+      Op::ExitScope(n) => {
+        if n >= scopes.len() {
+          return None;
+        }
+        scopes.truncate(scopes.len() - n);
+      }
+
+      Op::Branch(target) => {
+        if stack.pop()? != Kind::Int
+          || !stack.is_empty()
+          || !jump(target, scopes.len())
+        {
+          return None;
+        }
+      }
+
+      Op::Jump(target) => {
+        if !stack.is_empty() || !jump(target, scopes.len()) {
+          return None;
+        }
+        live = false;
+      }
+
+      Op::CallName { name, argc } => {
+        if chunk.name(name) != own
+          || argc != params.len()
+          || visible(&scopes, own)
+        {
+          return None;
+        }
+        for _ in 0..argc {
+          if stack.pop()? != Kind::Int {
+            return None;
+          }
+        }
+        stack.push(Kind::Int);
+        recursive = true;
+      }
+
+      // a return of undefined bails out
+      Op::Return => {
+        stack.pop()?;
+        live = false;
+      }
+
+      _ => return None,
+    }
+  }
+
+  // function bodies end with a return
+  if live {
+    return None;
+  }
+
+  return Some(Plan {
+    locals,
+    reached,
+    targets: entries.into_keys().collect(),
+    recursive,
+  });
+}
+
+fn compiles(op: BinaryOp) -> bool {
+  // the others are on floats, or depend on the division policy
+  return !matches!(op, BinaryOp::Div | BinaryOp::Rem);
+}
+
+/* =========================
+Translation
+========================= */
+
+fn translate(
+  b: &mut FunctionBuilder,
+  chunk: &Chunk,
+  params: &[Symbol],
+  plan: &Plan,
+  own: FuncRef,
+) {
+  let start = b.create_block();
+  b.append_block_params_for_function_params(start);
+  b.switch_to_block(start);
+  let values = b.block_params(start).to_vec();
+  let (failed, depth) = (values[0], values[1]);
+
+  let mut variables = HashMap::new();
+  for (i, name) in params.iter().chain(&plan.locals).enumerate() {
+    let variable = Variable::from_u32(i as u32);
+    b.declare_var(variable, types::I64);
+    if i < params.len() {
+      b.def_var(variable, values[2 + i]);
+    }
+    variables.insert(*name, variable);
+  }
+
+  let bail = b.create_block();
+  let blocks: HashMap<usize, Block> = plan
+    .targets
+    .iter()
+    .map(|&at| (at, b.create_block()))
+    .collect();
+
+  // none for undefined
+  let mut stack: Vec<Option<ir::Value>> = Vec::new();
+  // whether the current block ended
+  let mut ended = false;
+
+  for (at, &op) in chunk.code.iter().enumerate() {
+    if let Some(&block) = blocks.get(&at) {
+      if !ended {
+        b.ins().jump(block, &[]);
+      }
+      b.switch_to_block(block);
+      stack.clear();
+      ended = false;
+    }
+
+    if ended || !plan.reached[at] {
+      continue;
+    }
+
+    match op {
+      Op::Constant(n) => {
+        let Value::SamNumber(Number::SamInt(i)) = chunk.constants[n] else {
+          unreachable!("planned constants are integers");
+        };
+        stack.push(Some(b.ins().iconst(types::I64, i)));
+      }
+
+      Op::Undefined => stack.push(None),
+
+      Op::Pop => {
+        stack.pop();
+      }
+
+      Op::Load(n) => {
+        let v = b.use_var(variables[&chunk.name(n)]);
+        stack.push(Some(v));
+      }
+
+      Op::Declare(n) | Op::Store(n) => {
+        let v = stack.pop().unwrap().unwrap();
+        b.def_var(variables[&chunk.name(n)], v);
+        if let Op::Store(_) = op {
+          stack.push(Some(v));
+        }
+      }
+
+      Op::Binary(op) => {
+        let right = stack.pop().unwrap().unwrap();
+        let left = stack.pop().unwrap().unwrap();
+        stack.push(Some(binary(b, op, left, right, bail)));
+      }
+
+      Op::BinaryConstant { op, constant } => {
+        let Value::SamNumber(Number::SamInt(i)) = chunk.constants[constant]
+        else {
+          unreachable!("planned constants are integers");
+        };
+        let right = b.ins().iconst(types::I64, i);
+        let left = stack.pop().unwrap().unwrap();
+        stack.push(Some(binary(b, op, left, right, bail)));
+      }
+
+      Op::EnterScope | Op::ExitScope(_) => {}
+
+      // continues at the target if the condition is false
+      Op::Branch(target) => {
+        let condition = stack.pop().unwrap().unwrap();
+        let next = b.create_block();
+        b.ins().brif(condition, next, &[], blocks[&target], &[]);
+        b.switch_to_block(next);
+      }
+
+      Op::Jump(target) => {
+        b.ins().jump(blocks[&target], &[]);
+        ended = true;
+      }
+
+      Op::CallName { argc, .. } => {
+        let args = stack.split_off(stack.len() - argc);
+
+        let left = b.ins().iadd_imm(depth, -1);
+        let too_deep = b.ins().icmp_imm(IntCC::SignedLessThanOrEqual, left, 0);
+        let call = b.create_block();
+        b.ins().brif(too_deep, bail, &[], call, &[]);
+        b.switch_to_block(call);
+
+        let mut values = vec![failed, left];
+        values.extend(args.into_iter().map(Option::unwrap));
+        let inst = b.ins().call(own, &values);
+        let result = b.inst_results(inst)[0];
+
+        // a bail out of the call is one of this call too
+        let flag = b.ins().load(types::I8, MemFlags::trusted(), failed, 0);
+        let next = b.create_block();
+        b.ins().brif(flag, bail, &[], next, &[]);
+        b.switch_to_block(next);
+
+        stack.push(Some(result));
+      }
+
+      Op::Return => {
+        match stack.pop().unwrap() {
+          Some(v) => b.ins().return_(&[v]),
+          None => b.ins().jump(bail, &[]),
+        };
+        ended = true;
+      }
+
+      op => unreachable!("{:?} is not planned", op),
+    }
+  }
+
+  b.switch_to_block(bail);
+  let one = b.ins().iconst(types::I8, 1);
+  b.ins().store(MemFlags::trusted(), one, failed, 0);
+  let zero = b.ins().iconst(types::I64, 0);
+  b.ins().return_(&[zero]);
+}
+
+fn binary(
+  b: &mut FunctionBuilder,
+  op: BinaryOp,
+  left: ir::Value,
+  right: ir::Value,
+  bail: Block,
+) -> ir::Value {
+  let cc = match op {
+    BinaryOp::Lt => Some(IntCC::SignedLessThan),
+    BinaryOp::Gt => Some(IntCC::SignedGreaterThan),
+    BinaryOp::Le => Some(IntCC::SignedLessThanOrEqual),
+    BinaryOp::Ge => Some(IntCC::SignedGreaterThanOrEqual),
+    BinaryOp::Eq => Some(IntCC::Equal),
+    BinaryOp::Ne => Some(IntCC::NotEqual),
+    _ => None,
+  };
+
+  // comparisons are 1 or 0
+  if let Some(cc) = cc {
+    let c = b.ins().icmp(cc, left, right);
+    return b.ins().uextend(types::I64, c);
+  }
+
+  let (v, overflow) = match op {
+    BinaryOp::Add => b.ins().sadd_overflow(left, right),
+    BinaryOp::Sub => b.ins().ssub_overflow(left, right),
+    BinaryOp::Mul => b.ins().smul_overflow(left, right),
+
+    BinaryOp::And | BinaryOp::Or => {
+      let l = b.ins().icmp_imm(IntCC::NotEqual, left, 0);
+      let r = b.ins().icmp_imm(IntCC::NotEqual, right, 0);
+      let c = match op {
+        BinaryOp::And => b.ins().band(l, r),
+        _ => b.ins().bor(l, r),
+      };
+      return b.ins().uextend(types::I64, c);
+    }
+
+    op => unreachable!("{:?} is not planned", op),
+  };
+
+  // the vm makes a float of a result out of the i64 range, see `Number`
+  let next = b.create_block();
+  b.ins().brif(overflow, bail, &[], next, &[]);
+  b.switch_to_block(next);
+
+  return v;
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::error::Span;
+  use tree_sitter::Point;
+
+  fn chunk(code: Vec<Op>, constants: Vec<i64>, names: &[&str]) -> Chunk {
+    let span = Span {
+      start: 0,
+      end: 0,
+      start_point: Point::default(),
+      end_point: Point::default(),
+    };
+
+    return Chunk {
+      spans: vec![span; code.len()],
+      code,
+      constants: constants.into_iter().map(Value::from).collect(),
+      names: names.iter().map(|n| Symbol::from(*n)).collect(),
+      ..Chunk::default()
+    };
+  }
+
+  fn ints(values: &[i64]) -> Vec<Value> {
+    return values.iter().map(|&i| Value::from(i)).collect();
+  }
+
+  #[test]
+  fn test_arithmetic() {
+    // (a, b) => { let c = a * b; return c + 1 > 40; }
+    let body = chunk(
+      vec![
+        Op::Load(0),
+        Op::Load(1),
+        Op::Binary(BinaryOp::Mul),
+        Op::Declare(2),
+        Op::Undefined,
+        Op::Pop,
+        Op::Load(2),
+        Op::BinaryConstant {
+          op: BinaryOp::Add,
+          constant: 0,
+        },
+        Op::BinaryConstant {
+          op: BinaryOp::Gt,
+          constant: 1,
+        },
+        Op::Return,
+        Op::Undefined,
+        Op::Return,
+      ],
+      vec![1, 40],
+      &["a", "b", "c"],
+    );
+
+    let mut jit = Jit::new().unwrap();
+    let f = jit
+      .compile(&body, &["a".into(), "b".into()], "f".into())
+      .unwrap();
+
+    assert_eq!(f.call(&ints(&[6, 7]), 10), Some(Value::from(1i64)));
+    assert_eq!(f.call(&ints(&[2, 3]), 10), Some(Value::from(0i64)));
+    assert!(f.recursive().is_none());
+
+    // left to the vm
+    assert_eq!(f.call(&[Value::from(1.5), Value::from(2i64)], 10), None);
+    assert_eq!(f.call(&ints(&[i64::MAX, 2]), 10), None);
+  }
+
+  #[test]
+  fn test_recursion() {
+    // fib = (n) => {
+    //   if (n < 2) { return n; }
+    //   return fib(n - 1) + fib(n - 2);
+    // }
+    let body = chunk(
+      vec![
+        Op::Load(0),
+        Op::BinaryConstant {
+          op: BinaryOp::Lt,
+          constant: 0,
+        },
+        Op::Branch(7),
+        Op::EnterScope,
+        Op::Load(0),
+        Op::Return,
+        Op::ExitScope(1),
+        Op::Undefined,
+        Op::Pop,
+        Op::Load(0),
+        Op::BinaryConstant {
+          op: BinaryOp::Sub,
+          constant: 1,
+        },
+        Op::CallName { name: 1, argc: 1 },
+        Op::Load(0),
+        Op::BinaryConstant {
+          op: BinaryOp::Sub,
+          constant: 0,
+        },
+        Op::CallName { name: 1, argc: 1 },
+        Op::Binary(BinaryOp::Add),
+        Op::Return,
+        Op::Undefined,
+        Op::Return,
+      ],
+      vec![2, 1],
+      &["n", "fib"],
+    );
+
+    let mut jit = Jit::new().unwrap();
+    let fib = jit.compile(&body, &["n".into()], "fib".into()).unwrap();
+
+    assert_eq!(fib.call(&ints(&[20]), 100), Some(Value::from(6765i64)));
+    assert_eq!(fib.recursive(), Some("fib".into()));
+
+    // deeper than allowed
+    assert_eq!(fib.call(&ints(&[20]), 10), None);
+  }
+
+  #[test]
+  fn test_dynamic_code() {
+    let mut jit = Jit::new().unwrap();
+    let params = ["a".into()];
+
+    // a global
+    let body = chunk(vec![Op::Load(0), Op::Return], vec![], &["g"]);
+    assert!(jit.compile(&body, &params, "f".into()).is_none());
+
+    // a string
+    let mut body = chunk(vec![Op::Constant(0), Op::Return], vec![], &[]);
+    body.constants.push(Value::from("s"));
+    assert!(jit.compile(&body, &params, "f".into()).is_none());
+
+    // a call of something else
+    let body = chunk(
+      vec![Op::Load(0), Op::CallName { name: 1, argc: 1 }, Op::Return],
+      vec![],
+      &["a", "print"],
+    );
+    assert!(jit.compile(&body, &params, "f".into()).is_none());
+
+    // returning undefined compiles, and bails out
+    let body = chunk(vec![Op::Undefined, Op::Return], vec![], &[]);
+    let f = jit.compile(&body, &params, "f".into()).unwrap();
+    assert_eq!(f.call(&ints(&[1]), 10), None);
+  }
+}
@@ -0,0 +1,846 @@
+#![allow(dead_code)]
+
+// `sam kernel`: a Jupyter kernel, so notebooks can run sam cells. It speaks
+// version 5.3 of the messaging protocol
+// (https://jupyter-client.readthedocs.io/en/stable/messaging.html) over the
+// sockets of `zmtp`, and keeps one interpreter session for the notebook, so
+// cells see what earlier ones declared, as lines of the REPL do.
+//
+// Requests on the shell channel are run one at a time on the main thread.
+// The control channel has threads of its own, so that a running cell can be
+// interrupted, and output is published on iopub as the script writes it.
+
+use crate::builtins;
+use crate::config::Config;
+use crate::diagnostic::Diagnostic;
+use crate::digest::{hex, hmac_sha256, sha256};
+use crate::ffi::FFI;
+use crate::interpreter::Interpreter;
+use crate::interrupt::InterruptHandle;
+use crate::output::{Reader, Writer};
+use crate::repl::open_brackets;
+use crate::report::ANSI;
+use crate::time::DateTime;
+use crate::value::Value;
+use crate::zmtp::{Connection, Message, Socket, SocketType};
+use serde::Deserialize;
+use serde_json::{Value as Json, json};
+use std::collections::BTreeSet;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const PROTOCOL_VERSION: &str = "5.3";
+const DELIMITER: &[u8] = b"<IDS|MSG>";
+// file name shown in diagnostics of cells
+const FILE: &str = "<cell>";
+
+// the connection file Jupyter starts the kernel with
+#[derive(Debug, Deserialize)]
+struct ConnectionInfo {
+  ip: String,
+  transport: String,
+  shell_port: u16,
+  iopub_port: u16,
+  stdin_port: u16,
+  control_port: u16,
+  hb_port: u16,
+  key: String,
+  signature_scheme: String,
+}
+
+/* =========================
+Messages
+========================= */
+
+// a request of the frontend
+#[derive(Debug)]
+struct Request {
+  // routing prefix, sent back with the reply
+  identities: Vec<Vec<u8>>,
+  header: Json,
+  content: Json,
+}
+
+impl Request {
+  fn msg_type(&self) -> &str {
+    return self.header["msg_type"].as_str().unwrap_or_default();
+  }
+}
+
+// signs and checks messages, and names the ones the kernel sends
+struct Session {
+  key: Vec<u8>,
+  id: String,
+  sent: AtomicU64,
+}
+
+impl Session {
+  fn new(key: &str) -> Session {
+    let now = SystemTime::now()
+      .duration_since(UNIX_EPOCH)
+      .unwrap_or_default()
+      .as_nanos();
+    let seed = format!("{}-{}", std::process::id(), now);
+
+    return Session {
+      key: key.as_bytes().to_vec(),
+      id: uuid(&sha256(seed.as_bytes())),
+      sent: AtomicU64::new(0),
+    };
+  }
+
+  // empty without a key, which turns signing off
+  fn sign(&self, parts: &[&[u8]]) -> String {
+    if self.key.is_empty() {
+      return String::new();
+    }
+    return hex(&hmac_sha256(&self.key, &parts.concat()));
+  }
+
+  // the frames of a message in reply to `parent`, after `identities`
+  fn encode(
+    &self,
+    identities: &[Vec<u8>],
+    msg_type: &str,
+    parent: &Json,
+    content: Json,
+  ) -> Message {
+    let n = self.sent.fetch_add(1, Ordering::Relaxed);
+    let header = json!({
+      "msg_id": format!("{}_{}", self.id, n),
+      "session": self.id,
+      "username": "sam",
+      "date": timestamp(SystemTime::now()),
+      "msg_type": msg_type,
+      "version": PROTOCOL_VERSION,
+    });
+
+    let parts = [
+      header.to_string().into_bytes(),
+      parent.to_string().into_bytes(),
+      b"{}".to_vec(),
+      content.to_string().into_bytes(),
+    ];
+    let signature = self.sign(&[&parts[0], &parts[1], &parts[2], &parts[3]]);
+
+    let mut frames = identities.to_vec();
+    frames.push(DELIMITER.to_vec());
+    frames.push(signature.into_bytes());
+    frames.extend(parts);
+
+    return frames;
+  }
+
+  // the request in `frames`, or `None` if it is malformed or its signature
+  // does not match
+  fn decode(&self, frames: Message) -> Option<Request> {
+    let delimiter = frames.iter().position(|f| f == DELIMITER)?;
+    let parts = frames.get(delimiter + 2..delimiter + 6)?;
+
+    let signature = &frames[delimiter + 1];
+    let expected = self.sign(&[&parts[0], &parts[1], &parts[2], &parts[3]]);
+    if signature != expected.as_bytes() {
+      return None;
+    }
+
+    return Some(Request {
+      identities: frames[..delimiter].to_vec(),
+      header: serde_json::from_slice(&parts[0]).ok()?,
+      content: serde_json::from_slice(&parts[3]).ok()?,
+    });
+  }
+}
+
+// `bytes` written like a UUID, 8-4-4-4-12 hex digits
+fn uuid(bytes: &[u8]) -> String {
+  let hex = hex(&bytes[..16]);
+  return format!(
+    "{}-{}-{}-{}-{}",
+    &hex[..8],
+    &hex[8..12],
+    &hex[12..16],
+    &hex[16..20],
+    &hex[20..]
+  );
+}
+
+// ISO 8601 in UTC, e.g. 2024-03-01T12:30:00.000000Z
+fn timestamp(time: SystemTime) -> String {
+  let date = DateTime::from_system(time);
+  return date.format("%Y-%m-%dT%H:%M:%S.%fZ").unwrap();
+}
+
+/* =========================
+Sockets
+========================= */
+
+// the subscribers of iopub, where status, output, and results go
+#[derive(Clone)]
+struct Iopub {
+  session: Arc<Session>,
+  peers: Arc<Mutex<Vec<Connection>>>,
+}
+
+impl Iopub {
+  fn publish(&self, msg_type: &str, parent: &Json, content: Json) {
+    // the topic, which clients subscribe to everything of anyway
+    let topic = vec![msg_type.as_bytes().to_vec()];
+    let message = self.session.encode(&topic, msg_type, parent, content);
+
+    // subscribers that went away are dropped
+    let mut peers = self.peers.lock().unwrap_or_else(|e| e.into_inner());
+    peers.retain_mut(|peer| peer.send(&message).is_ok());
+  }
+
+  fn status(&self, state: &str, parent: &Json) {
+    self.publish("status", parent, json!({ "execution_state": state }));
+  }
+}
+
+// output of the script, published as it is written
+struct StreamOutput {
+  name: &'static str,
+  iopub: Iopub,
+  // the header of the request being run
+  parent: Arc<Mutex<Json>>,
+}
+
+impl Write for StreamOutput {
+  fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    let parent = self.parent.lock().unwrap_or_else(|e| e.into_inner());
+    let text = String::from_utf8_lossy(buf);
+    self.iopub.publish(
+      "stream",
+      &parent,
+      json!({ "name": self.name, "text": text }),
+    );
+    return Ok(buf.len());
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    return Ok(());
+  }
+}
+
+// accept peers of `socket` for as long as the kernel runs, each handled by
+// `serve` on a thread of its own
+fn serve_peers(
+  socket: Socket,
+  serve: impl Fn(Connection) + Send + Sync + 'static,
+) {
+  let serve = Arc::new(serve);
+  thread::spawn(move || {
+    while let Ok(connection) = socket.accept() {
+      let serve = serve.clone();
+      thread::spawn(move || serve(connection));
+    }
+  });
+}
+
+// requests coming in on `connection`, with a handle to reply on
+fn requests(
+  mut connection: Connection,
+  session: &Session,
+  mut handle: impl FnMut(Request, &mut Connection),
+) {
+  let Ok(mut reply) = connection.try_clone() else {
+    return;
+  };
+
+  while let Ok(frames) = connection.recv() {
+    if let Some(request) = session.decode(frames) {
+      handle(request, &mut reply);
+    }
+  }
+}
+
+/* =========================
+Kernel
+========================= */
+
+pub fn run(mut config: Config, connection_file: &Path) -> ExitCode {
+  let info = fs::read_to_string(connection_file)
+    .map_err(|e| e.to_string())
+    .and_then(|text| {
+      serde_json::from_str::<ConnectionInfo>(&text).map_err(|e| e.to_string())
+    });
+  let info = match info {
+    Ok(info) => info,
+    Err(e) => {
+      eprintln!("error: could not read {}: {}", connection_file.display(), e);
+      return ExitCode::FAILURE;
+    }
+  };
+
+  if info.transport != "tcp"
+    || (!info.key.is_empty() && info.signature_scheme != "hmac-sha256")
+  {
+    eprintln!(
+      "error: only the tcp transport and hmac-sha256 signatures are supported"
+    );
+    return ExitCode::FAILURE;
+  }
+
+  let (shell, iopub_socket, stdin, control, heartbeat) = match open(&info) {
+    Ok(sockets) => sockets,
+    Err(e) => {
+      eprintln!("error: could not open the kernel's sockets: {}", e);
+      return ExitCode::FAILURE;
+    }
+  };
+
+  let session = Arc::new(Session::new(&info.key));
+  let iopub = Iopub {
+    session: session.clone(),
+    peers: Arc::default(),
+  };
+
+  // the frontend pings to see that the kernel is alive
+  serve_peers(heartbeat, |mut connection| {
+    while let Ok(message) = connection.recv() {
+      if connection.send(&message).is_err() {
+        return;
+      }
+    }
+  });
+
+  let peers = iopub.peers.clone();
+  thread::spawn(move || {
+    while let Ok(connection) = iopub_socket.accept() {
+      peers
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .push(connection);
+    }
+  });
+
+  // input is not asked for, `allow_stdin` notwithstanding
+  serve_peers(stdin, |mut connection| while connection.recv().is_ok() {});
+
+  // the interpreter writes to the frontend of the request it runs
+  let parent = Arc::new(Mutex::new(Json::Null));
+  config.output.stdout = Writer::new(StreamOutput {
+    name: "stdout",
+    iopub: iopub.clone(),
+    parent: parent.clone(),
+  });
+  config.output.stderr = Writer::new(StreamOutput {
+    name: "stderr",
+    iopub: iopub.clone(),
+    parent: parent.clone(),
+  });
+  // Jupyter's stdin is not the user's, and input is not asked for
+  config.output.stdin = Reader::empty();
+  // as in the REPL, assigning to a new name starts a variable
+  config.auto_declare.get_or_insert(true);
+
+  let mut kernel = Kernel {
+    interpreter: Interpreter::with_config(config),
+    iopub: iopub.clone(),
+    parent,
+    execution_count: 0,
+  };
+
+  let interrupt = kernel.interpreter.interrupt_handle();
+  let control_session = session.clone();
+  serve_peers(control, move |connection| {
+    requests(connection, &control_session, |request, reply| {
+      control_request(&request, reply, &control_session, &interrupt);
+    });
+  });
+
+  // shell requests of every peer, run in the order they come in
+  let (sender, receiver) = mpsc::channel();
+  let shell_session = session.clone();
+  serve_peers(shell, move |connection| {
+    let sender: Sender<(Request, Connection)> = sender.clone();
+    requests(connection, &shell_session, |request, reply| {
+      if let Ok(reply) = reply.try_clone() {
+        let _ = sender.send((request, reply));
+      }
+    });
+  });
+
+  for (request, mut reply) in receiver {
+    iopub.status("busy", &request.header);
+
+    let response = kernel.handle(&request);
+    if let Some((msg_type, content)) = response {
+      let message = session.encode(
+        &request.identities,
+        &msg_type,
+        &request.header,
+        content,
+      );
+      let _ = reply.send(&message);
+    }
+
+    iopub.status("idle", &request.header);
+
+    if request.msg_type() == "shutdown_request" {
+      break;
+    }
+  }
+
+  return ExitCode::SUCCESS;
+}
+
+// the shell, iopub, stdin, control, and heartbeat sockets
+fn open(
+  info: &ConnectionInfo,
+) -> io::Result<(Socket, Socket, Socket, Socket, Socket)> {
+  let bind = |port: u16, kind: SocketType| {
+    Socket::bind(&format!("{}:{}", info.ip, port), kind)
+  };
+
+  return Ok((
+    bind(info.shell_port, SocketType::Router)?,
+    bind(info.iopub_port, SocketType::Pub)?,
+    bind(info.stdin_port, SocketType::Router)?,
+    bind(info.control_port, SocketType::Router)?,
+    bind(info.hb_port, SocketType::Rep)?,
+  ));
+}
+
+// interrupts and shutdowns, answered while a cell may still be running
+fn control_request(
+  request: &Request,
+  reply: &mut Connection,
+  session: &Session,
+  interrupt: &InterruptHandle,
+) {
+  let content = match request.msg_type() {
+    "interrupt_request" => {
+      interrupt.interrupt();
+      json!({ "status": "ok" })
+    }
+    "shutdown_request" => {
+      interrupt.interrupt();
+      json!({ "status": "ok", "restart": request.content["restart"] })
+    }
+    "kernel_info_request" => kernel_info(),
+    _ => return,
+  };
+
+  let msg_type = request.msg_type().replace("_request", "_reply");
+  let message =
+    session.encode(&request.identities, &msg_type, &request.header, content);
+  let _ = reply.send(&message);
+
+  if request.msg_type() == "shutdown_request" {
+    std::process::exit(0);
+  }
+}
+
+fn kernel_info() -> Json {
+  let version = env!("CARGO_PKG_VERSION");
+
+  return json!({
+    "status": "ok",
+    "protocol_version": PROTOCOL_VERSION,
+    "implementation": "sam",
+    "implementation_version": version,
+    "language_info": {
+      "name": "sam",
+      "version": version,
+      "mimetype": "text/x-sam",
+      "file_extension": ".sam",
+    },
+    "banner": format!("sam {}", version),
+    "help_links": [],
+  });
+}
+
+struct Kernel {
+  interpreter: Interpreter,
+  iopub: Iopub,
+  // the header of the request being run, for the output of the script
+  parent: Arc<Mutex<Json>>,
+  execution_count: u64,
+}
+
+impl Kernel {
+  // the type and content of the reply to `request`, if it has one
+  fn handle(&mut self, request: &Request) -> Option<(String, Json)> {
+    *self.parent.lock().unwrap_or_else(|e| e.into_inner()) =
+      request.header.clone();
+
+    let content = &request.content;
+    let code = content["code"].as_str().unwrap_or_default();
+
+    let reply = match request.msg_type() {
+      "kernel_info_request" => kernel_info(),
+      "execute_request" => self.execute(code, content, &request.header),
+      "is_complete_request" => match open_brackets(code) > 0 {
+        true => json!({ "status": "incomplete", "indent": "  " }),
+        false => json!({ "status": "complete" }),
+      },
+      "complete_request" => {
+        let cursor = content["cursor_pos"].as_u64().unwrap_or(0) as usize;
+        self.complete(code, cursor)
+      }
+      "inspect_request" => {
+        let cursor = content["cursor_pos"].as_u64().unwrap_or(0) as usize;
+        self.inspect(code, cursor)
+      }
+      "history_request" => json!({ "status": "ok", "history": [] }),
+      "comm_info_request" => json!({ "status": "ok", "comms": {} }),
+      "shutdown_request" => {
+        json!({ "status": "ok", "restart": content["restart"] })
+      }
+      _ => return None,
+    };
+
+    let msg_type = request.msg_type().replace("_request", "_reply");
+    return Some((msg_type, reply));
+  }
+
+  fn execute(&mut self, code: &str, content: &Json, parent: &Json) -> Json {
+    let silent = content["silent"].as_bool().unwrap_or(false);
+    if !silent && content["store_history"].as_bool().unwrap_or(true) {
+      self.execution_count += 1;
+    }
+    let count = self.execution_count;
+
+    if !silent {
+      self.iopub.publish(
+        "execute_input",
+        parent,
+        json!({ "code": code, "execution_count": count }),
+      );
+    }
+
+    // every syntax error at once, `eval` would stop at the first
+    let mut errors = self.interpreter.check_syntax(code);
+    let result = match errors.is_empty() {
+      true => self.interpreter.eval(code),
+      false => Err(errors.remove(0)),
+    };
+
+    let warnings: Vec<Diagnostic> =
+      self.interpreter.context_mut().warnings.drain(..).collect();
+    let source = self.interpreter.source().to_owned();
+    for warning in &warnings {
+      let text = warning.render_with(FILE, &source, &ANSI);
+      self.iopub.publish(
+        "stream",
+        parent,
+        json!({ "name": "stderr", "text": text }),
+      );
+    }
+
+    let err = match result {
+      Ok(value) => {
+        if !silent && value != Value::Undefined {
+          self.iopub.publish(
+            "execute_result",
+            parent,
+            json!({
+              "execution_count": count,
+              "data": display_data(&value),
+              "metadata": {},
+            }),
+          );
+        }
+        return json!({
+          "status": "ok",
+          "execution_count": count,
+          "payload": [],
+          "user_expressions": {},
+        });
+      }
+      Err(err) => err,
+    };
+
+    let traceback: Vec<String> = std::iter::once(&err)
+      .chain(&errors)
+      .map(|e| Diagnostic::from(e).render_with(FILE, &source, &ANSI))
+      .collect();
+    let error = json!({
+      "ename": err.code().unwrap_or("error"),
+      "evalue": err.message(),
+      "traceback": traceback,
+    });
+
+    self.iopub.publish("error", parent, error.clone());
+
+    let mut reply = error;
+    reply["status"] = json!("error");
+    reply["execution_count"] = json!(count);
+    return reply;
+  }
+
+  // variables of the session and builtins starting with the word before
+  // the cursor
+  fn complete(&mut self, code: &str, cursor: usize) -> Json {
+    let (start, end) = word_at(code, cursor);
+    let prefix: String = code.chars().skip(start).take(end - start).collect();
+
+    let mut matches: BTreeSet<String> = builtins::names()
+      .filter(|name| name.starts_with(&prefix))
+      .map(str::to_owned)
+      .collect();
+    let globals = self.interpreter.context_mut().global_scope();
+    for (name, _) in globals.iter() {
+      if name.starts_with(&prefix) {
+        matches.insert(name.to_owned());
+      }
+    }
+
+    return json!({
+      "status": "ok",
+      "matches": matches,
+      "cursor_start": start,
+      "cursor_end": end,
+      "metadata": {},
+    });
+  }
+
+  // the value of the variable under the cursor
+  fn inspect(&self, code: &str, cursor: usize) -> Json {
+    let (start, end) = word_at(code, cursor);
+    let name: String = code.chars().skip(start).take(end - start).collect();
+
+    return match self.interpreter.get_global(&name) {
+      Some(value) => json!({
+        "status": "ok",
+        "found": true,
+        "data": { "text/plain": format!("{} = {}", name, value) },
+        "metadata": {},
+      }),
+      None => json!({
+        "status": "ok",
+        "found": false,
+        "data": {},
+        "metadata": {},
+      }),
+    };
+  }
+}
+
+// where the identifier around `cursor` starts and ends, counted in
+// characters as the protocol does
+fn word_at(code: &str, cursor: usize) -> (usize, usize) {
+  let chars: Vec<char> = code.chars().collect();
+  let cursor = cursor.min(chars.len());
+  let is_word = |c: &char| c.is_alphanumeric() || *c == '_';
+
+  let before = chars[..cursor].iter().rev().take_while(|c| is_word(c));
+  let after = chars[cursor..].iter().take_while(|c| is_word(c));
+
+  return (cursor - before.count(), cursor + after.count());
+}
+
+/* =========================
+Rich output
+========================= */
+
+// a value as the frontend may show it: plain text always, and arrays and
+// objects also as JSON and as an HTML table
+fn display_data(value: &Value) -> Json {
+  let mut data = json!({ "text/plain": value.to_string() });
+
+  if matches!(value, Value::SamArray(_) | Value::SamObject(_)) {
+    if let Ok(json) = FFI::value_to_json(value) {
+      data["application/json"] = json;
+    }
+    data["text/html"] = json!(html_table(value));
+  }
+
+  return data;
+}
+
+// an object as a table of its keys and values; an array as a table with a
+// row per element, and a column per key if its elements are objects
+fn html_table(value: &Value) -> String {
+  let mut html = String::from("<table>\n");
+
+  let row = |html: &mut String, cells: &[String]| {
+    html.push_str("<tr>");
+    for cell in cells {
+      html.push_str(&format!("<td>{}</td>", escape_html(cell)));
+    }
+    html.push_str("</tr>\n");
+  };
+  let head = |html: &mut String, cells: &[&str]| {
+    html.push_str("<tr>");
+    for cell in cells {
+      html.push_str(&format!("<th>{}</th>", escape_html(cell)));
+    }
+    html.push_str("</tr>\n");
+  };
+
+  match value {
+    Value::SamObject(fields) => {
+      let mut keys: Vec<&String> = fields.keys().collect();
+      keys.sort();
+      head(&mut html, &["key", "value"]);
+      for key in keys {
+        row(&mut html, &[key.clone(), fields[key].to_string()]);
+      }
+    }
+
+    Value::SamArray(items) => {
+      // the keys of every element, in order, if all of them are objects
+      let mut columns: Vec<String> = Vec::new();
+      let records = items.iter().all(|item| match item {
+        Value::SamObject(fields) => {
+          let mut keys: Vec<&String> = fields.keys().collect();
+          keys.sort();
+          for key in keys {
+            if !columns.contains(key) {
+              columns.push(key.clone());
+            }
+          }
+          true
+        }
+        _ => false,
+      });
+
+      match records && !items.is_empty() {
+        true => {
+          let mut header = vec![""];
+          header.extend(columns.iter().map(String::as_str));
+          head(&mut html, &header);
+
+          for (i, item) in items.iter().enumerate() {
+            let Value::SamObject(fields) = item else {
+              continue;
+            };
+            let mut cells = vec![i.to_string()];
+            cells.extend(columns.iter().map(|c| {
+              fields.get(c).map(Value::to_string).unwrap_or_default()
+            }));
+            row(&mut html, &cells);
+          }
+        }
+        false => {
+          head(&mut html, &["", "value"]);
+          for (i, item) in items.iter().enumerate() {
+            row(&mut html, &[i.to_string(), item.to_string()]);
+          }
+        }
+      }
+    }
+
+    _ => row(&mut html, &[value.to_string()]),
+  }
+
+  html.push_str("</table>");
+  return html;
+}
+
+fn escape_html(text: &str) -> String {
+  return text
+    .replace('&', "&amp;")
+    .replace('<', "&lt;")
+    .replace('>', "&gt;")
+    .replace('"', "&quot;");
+}
+
+/* =========================
+Installation
+========================= */
+
+// register the kernel with Jupyter for the current user, returning the
+// directory of its kernel spec
+pub fn install() -> io::Result<PathBuf> {
+  let data = match std::env::var_os("JUPYTER_DATA_DIR") {
+    Some(dir) => PathBuf::from(dir),
+    None => {
+      let home = std::env::var_os("HOME")
+        .map(PathBuf::from)
+        .ok_or_else(|| io::Error::other("HOME is not set"))?;
+      match cfg!(target_os = "macos") {
+        true => home.join("Library/Jupyter"),
+        false => home.join(".local/share/jupyter"),
+      }
+    }
+  };
+
+  let dir = data.join("kernels").join("sam");
+  fs::create_dir_all(&dir)?;
+
+  let exe = std::env::current_exe()?;
+  let spec = json!({
+    "argv": [exe.display().to_string(), "kernel", "{connection_file}"],
+    "display_name": "sam",
+    "language": "sam",
+  });
+  fs::write(dir.join("kernel.json"), format!("{:#}\n", spec))?;
+
+  return Ok(dir);
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::collections::HashMap;
+  use std::time::Duration;
+
+  #[test]
+  fn test_sign_and_decode() {
+    let session = Session::new("secret");
+    let request = json!({ "msg_type": "kernel_info_request" });
+    let frames = session.encode(
+      &[b"peer".to_vec()],
+      "kernel_info_request",
+      &request,
+      json!({ "code": "1 + 1;" }),
+    );
+
+    let decoded = session.decode(frames.clone()).unwrap();
+    assert_eq!(decoded.identities, vec![b"peer".to_vec()]);
+    assert_eq!(decoded.msg_type(), "kernel_info_request");
+    assert_eq!(decoded.content["code"], "1 + 1;");
+
+    // a changed message no longer matches its signature
+    let mut forged = frames;
+    forged[6] = br#"{"code": "exit();"}"#.to_vec();
+    assert!(session.decode(forged).is_none());
+  }
+
+  #[test]
+  fn test_timestamp() {
+    let time = UNIX_EPOCH + Duration::from_micros(951_827_696_000_123);
+    assert_eq!(timestamp(time), "2000-02-29T12:34:56.000123Z");
+    assert_eq!(timestamp(UNIX_EPOCH), "1970-01-01T00:00:00.000000Z");
+  }
+
+  #[test]
+  fn test_word_at() {
+    assert_eq!(word_at("let total = to", 14), (12, 14));
+    assert_eq!(word_at("f(na)", 3), (2, 4));
+    assert_eq!(word_at("é + x", 5), (4, 5));
+    assert_eq!(word_at("", 3), (0, 0));
+  }
+
+  #[test]
+  fn test_display_data() {
+    let data = display_data(&Value::from(3i64));
+    assert_eq!(data, json!({ "text/plain": "3" }));
+
+    let mut row = HashMap::new();
+    row.insert("name".to_owned(), Value::from("<b>"));
+    row.insert("size".to_owned(), Value::from(2i64));
+    let rows = Value::from(vec![Value::from(row)]);
+
+    let data = display_data(&rows);
+    assert_eq!(
+      data["application/json"],
+      json!([{ "name": "<b>", "size": 2 }])
+    );
+    assert_eq!(
+      data["text/html"],
+      "<table>\n<tr><th></th><th>name</th><th>size</th></tr>\n\
+       <tr><td>0</td><td>&lt;b&gt;</td><td>2</td></tr>\n</table>"
+    );
+  }
+}
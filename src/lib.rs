@@ -0,0 +1,114 @@
+//! The sam scripting language, for embedding in other programs.
+//!
+//! ```no_run
+//! let mut sam = sam::Interpreter::new();
+//! sam.eval("let total = 1 + 2;").unwrap();
+//! let value = sam.eval("total * 2;").unwrap();
+//! assert_eq!(value.to_string(), "6");
+//! ```
+
+pub mod analysis;
+pub mod ast;
+pub mod builtins;
+pub mod bytecode;
+pub mod cache;
+pub mod capi;
+pub mod codes;
+pub mod comments;
+pub mod config;
+pub mod context;
+pub mod coverage;
+pub mod crash;
+pub mod csv;
+pub mod dap;
+pub mod debugger;
+pub mod diagnostic;
+pub mod digest;
+pub mod doc;
+pub mod dylib;
+pub mod encoding;
+pub mod error;
+pub mod evaluate;
+pub mod ffi;
+pub mod fold;
+pub mod format;
+pub mod highlight;
+pub mod http;
+pub mod interpreter;
+pub mod interrupt;
+#[cfg(feature = "jit")]
+pub mod jit;
+pub mod kernel;
+pub mod lint;
+pub mod manifest;
+pub mod minify;
+pub mod modules;
+pub mod output;
+pub mod peephole;
+pub mod permissions;
+pub mod process;
+pub mod profile;
+pub mod prune;
+#[cfg(feature = "python")]
+pub mod python;
+pub mod query;
+pub mod random;
+pub mod reparse;
+pub mod repl;
+pub mod report;
+pub mod schema;
+pub mod suggest;
+pub mod symbol;
+pub mod syntax;
+pub mod template;
+pub mod testing;
+pub mod time;
+pub mod trace;
+pub mod transpile;
+pub mod value;
+pub mod vm;
+pub mod warnings;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+pub mod zmtp;
+
+pub use config::Config;
+pub use context::Context;
+pub use error::SamError;
+pub use interpreter::{Interpreter, InterpreterOptions};
+pub use value::Value;
+
+use tree_sitter::{Language, Parser, Tree};
+
+// retrieve Language struct from C code
+unsafe extern "C" {
+  fn tree_sitter_sam() -> Language;
+}
+
+// the tree-sitter grammar of sam
+pub fn language() -> Language {
+  return unsafe { tree_sitter_sam() };
+}
+
+// a parser for sam source, which blanks out comments before the grammar
+// sees them
+pub struct SamParser {
+  parser: Parser,
+}
+
+impl SamParser {
+  pub fn parse(
+    &mut self,
+    text: impl AsRef<[u8]>,
+    old_tree: Option<&Tree>,
+  ) -> Option<Tree> {
+    return self.parser.parse(comments::blank(text.as_ref()), old_tree);
+  }
+}
+
+pub fn parser() -> SamParser {
+  let mut parser = Parser::new();
+  parser.set_language(&language()).unwrap();
+
+  return SamParser { parser };
+}
@@ -0,0 +1,385 @@
+#![allow(dead_code)]
+
+// `sam lint`: the warnings reported before every run, see
+// src/warnings.rs, and checks only worth running on request: constant
+// conditions, `==` between values of different types, and bare names given
+// to external commands. Every check is a rule that can be allowed, i.e.
+// turned off, or denied, i.e. made an error, in the `[lint]` table of
+// sam.toml or with `--allow` and `--deny`:
+//
+//   [lint]
+//   allow = ["shadowing"]
+//   deny = ["unused-variable", "unquoted-argument"]
+
+use crate::ast::{
+  self, BinaryOp, Expr, ExprKind, Ident, Literal, Stmt, StmtKind, Visitor,
+};
+use crate::builtins;
+use crate::codes;
+use crate::config::Config;
+use crate::diagnostic::{Diagnostic, Severity};
+use crate::error::SamError;
+use crate::fold;
+use crate::prune;
+use crate::suggest::did_you_mean;
+use crate::warnings;
+use serde::Deserialize;
+use std::collections::HashMap;
+use tree_sitter::Node;
+
+pub struct Rule {
+  pub name: &'static str,
+  pub code: &'static str,
+}
+
+pub const RULES: &[Rule] = &[
+  Rule {
+    name: "unused-variable",
+    code: codes::UNUSED_VARIABLE,
+  },
+  Rule {
+    name: "unused-parameter",
+    code: codes::UNUSED_PARAMETER,
+  },
+  Rule {
+    name: "unused-interface",
+    code: codes::UNUSED_INTERFACE,
+  },
+  Rule {
+    name: "shadowing",
+    code: codes::SHADOWED_NAME,
+  },
+  Rule {
+    name: "unreachable",
+    code: codes::UNREACHABLE,
+  },
+  Rule {
+    name: "constant-condition",
+    code: codes::CONSTANT_CONDITION,
+  },
+  Rule {
+    name: "mismatched-comparison",
+    code: codes::MISMATCHED_COMPARISON,
+  },
+  Rule {
+    name: "unquoted-argument",
+    code: codes::UNQUOTED_ARGUMENT,
+  },
+];
+
+// rules turned off or made errors, by name; a rule both allowed and denied
+// is denied
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LintConfig {
+  pub allow: Vec<String>,
+  pub deny: Vec<String>,
+}
+
+impl LintConfig {
+  // every rule named must exist
+  pub fn validate(&self) -> Result<(), SamError> {
+    for name in self.allow.iter().chain(&self.deny) {
+      if RULES.iter().any(|r| r.name == name) {
+        continue;
+      }
+
+      let err = SamError::value(
+        codes::CONFIG_INVALID,
+        format!("Unknown lint rule `{}`", name),
+      );
+      return Err(match did_you_mean(name, RULES.iter().map(|r| r.name)) {
+        Some(help) => err.with_help(help),
+        None => err,
+      });
+    }
+
+    return Ok(());
+  }
+
+  // the diagnostic as configured, none if its rule is allowed
+  fn apply(&self, mut diagnostic: Diagnostic) -> Option<Diagnostic> {
+    let Some(rule) = RULES.iter().find(|r| Some(r.code) == diagnostic.code)
+    else {
+      return Some(diagnostic);
+    };
+
+    let named = |names: &Vec<String>| names.iter().any(|n| n == rule.name);
+
+    if named(&self.deny) {
+      diagnostic.severity = Severity::Error;
+    } else if named(&self.allow) {
+      return None;
+    }
+
+    return Some(diagnostic);
+  }
+}
+
+// everything the rules that are not allowed find in the script of `root`
+pub fn lint(
+  root: Node,
+  source: &str,
+  config: &Config,
+) -> Result<Vec<Diagnostic>, SamError> {
+  config.lint.validate()?;
+
+  let program = ast::lower(root, source.as_bytes())?;
+
+  let mut names = Names(HashMap::new());
+  names.visit_program(&program);
+
+  let mut found = warnings::check(root, source.as_bytes());
+
+  // comparisons of literals are folded away, so they are checked first
+  let mut linter = Linter {
+    names: &names.0,
+    found: Vec::new(),
+    folded: false,
+  };
+  linter.visit_program(&program);
+
+  let mut folded = program;
+  fold::fold_program(&mut folded.statements, config);
+  linter.folded = true;
+  linter.visit_program(&folded);
+
+  found.extend(linter.found);
+  found.sort_by_key(|d| d.span.map(|s| s.start));
+
+  return Ok(
+    found
+      .into_iter()
+      .filter_map(|d| config.lint.apply(d))
+      .collect(),
+  );
+}
+
+// every name the script declares, assigns, or takes as a parameter,
+// anywhere, with its type if it is only ever bound once, to a value whose
+// type is known
+struct Names(HashMap<String, Option<&'static str>>);
+
+impl Names {
+  fn bind(&mut self, name: &str, type_name: Option<&'static str>) {
+    self
+      .0
+      .entry(name.to_owned())
+      .and_modify(|t| *t = None)
+      .or_insert(type_name);
+  }
+}
+
+impl Visitor for Names {
+  fn visit_stmt(&mut self, stmt: &Stmt) {
+    let StmtKind::Declaration { declarators, .. } = &stmt.kind else {
+      return ast::walk_stmt(self, stmt);
+    };
+
+    for declarator in declarators {
+      let value = declarator.value.as_ref();
+      let type_name = value.and_then(|v| static_type(v, &self.0));
+      self.bind(declarator.name.name.as_str(), type_name);
+      if let Some(value) = value {
+        self.visit_expr(value);
+      }
+    }
+  }
+
+  fn visit_ident(&mut self, ident: &Ident) {
+    self.bind(ident.name.as_str(), None);
+  }
+}
+
+struct Linter<'n> {
+  names: &'n HashMap<String, Option<&'static str>>,
+  found: Vec<Diagnostic>,
+  // conditions are checked once folded, everything else before
+  folded: bool,
+}
+
+impl Visitor for Linter<'_> {
+  fn visit_expr(&mut self, expr: &Expr) {
+    match &expr.kind {
+      ExprKind::If(branch) if self.folded => {
+        let mut branch = Some(branch);
+        while let Some(b) = branch {
+          self.condition(&b.condition);
+          branch = match &b.alternative {
+            Some(ast::Else::If(next, _)) => Some(next),
+            _ => None,
+          };
+        }
+      }
+      ExprKind::Binary { op, left, right } if !self.folded => {
+        self.comparison(*op, left, right, expr);
+      }
+      ExprKind::Call(call) | ExprKind::Spawn(call) if !self.folded => {
+        self.arguments(&call.function, &call.args);
+      }
+      _ => {}
+    }
+
+    ast::walk_expr(self, expr);
+  }
+}
+
+impl Linter<'_> {
+  fn condition(&mut self, cond: &Expr) {
+    let Some(holds) = prune::constant(cond) else {
+      return;
+    };
+
+    self.found.push(
+      Diagnostic::warning("constant condition")
+        .with_code(codes::CONSTANT_CONDITION)
+        .with_span(Some(cond.span))
+        .with_label(format!("always {}", holds)),
+    );
+  }
+
+  fn comparison(
+    &mut self,
+    op: BinaryOp,
+    left: &Expr,
+    right: &Expr,
+    expr: &Expr,
+  ) {
+    let always = match op {
+      BinaryOp::Eq => false,
+      BinaryOp::Ne => true,
+      _ => return,
+    };
+
+    let types = (
+      static_type(left, self.names),
+      static_type(right, self.names),
+    );
+    let (Some(a), Some(b)) = types else {
+      return;
+    };
+    if a == b {
+      return;
+    }
+
+    self.found.push(
+      Diagnostic::warning(format!("comparison of a {} with a {}", a, b))
+        .with_code(codes::MISMATCHED_COMPARISON)
+        .with_span(Some(expr.span))
+        .with_label(format!("always {}", always))
+        .with_note("values of different types are never equal"),
+    );
+  }
+
+  // bare names given to a program, which are most likely words meant to be
+  // quoted
+  fn arguments(&mut self, function: &Expr, args: &[Expr]) {
+    let ExprKind::Identifier(command) = &function.kind else {
+      return;
+    };
+    if self.names.contains_key(command.as_str())
+      || builtins::lookup(command.as_str()).is_some()
+    {
+      return;
+    }
+
+    for arg in args {
+      let ExprKind::Identifier(name) = &arg.kind else {
+        continue;
+      };
+      if self.names.contains_key(name.as_str()) {
+        continue;
+      }
+
+      self.found.push(
+        Diagnostic::warning(format!("`{}` is not a variable", name))
+          .with_code(codes::UNQUOTED_ARGUMENT)
+          .with_span(Some(arg.span))
+          .with_label(format!("argument of `{}`", command))
+          .with_note(format!("quote it to pass the word: '{}'", name)),
+      );
+    }
+  }
+}
+
+// the type an expression always has, if it can be told without running it
+fn static_type(
+  expr: &Expr,
+  names: &HashMap<String, Option<&'static str>>,
+) -> Option<&'static str> {
+  return match &expr.kind {
+    ExprKind::Identifier(name) => names.get(name.as_str()).copied().flatten(),
+    ExprKind::Literal(Literal::Number(_)) => Some("number"),
+    ExprKind::Literal(Literal::String(_)) => Some("string"),
+    ExprKind::Array(_) => Some("array"),
+    ExprKind::Lambda { .. } => Some("function"),
+    _ => None,
+  };
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn lint_with(
+    source: &str,
+    lint: LintConfig,
+  ) -> Vec<(&'static str, Severity)> {
+    let tree = crate::parser().parse(source, None).unwrap();
+    let config = Config {
+      lint,
+      ..Config::default()
+    };
+
+    return super::lint(tree.root_node(), source, &config)
+      .unwrap()
+      .into_iter()
+      .map(|d| (d.code.unwrap(), d.severity))
+      .collect();
+  }
+
+  #[test]
+  fn test_rules() {
+    let found = lint_with(
+      "let n = 3;
+      if (n == '3') { grep(needle, 'notes.txt'); };
+      if (2 > 1) { let _a = n; };",
+      LintConfig::default(),
+    );
+    let codes: Vec<_> = found.iter().map(|(c, _)| *c).collect();
+    assert_eq!(
+      codes,
+      vec![
+        codes::MISMATCHED_COMPARISON,
+        codes::UNQUOTED_ARGUMENT,
+        codes::CONSTANT_CONDITION
+      ]
+    );
+  }
+
+  #[test]
+  fn test_allow_and_deny() {
+    let source = "let a = 1; let b = 2; let _c = b == 'x';";
+
+    let found = lint_with(
+      source,
+      LintConfig {
+        allow: vec!["mismatched-comparison".to_owned()],
+        deny: vec!["unused-variable".to_owned()],
+      },
+    );
+    assert_eq!(found, vec![(codes::UNUSED_VARIABLE, Severity::Error)]);
+  }
+
+  #[test]
+  fn test_unknown_rule() {
+    let config = LintConfig {
+      allow: vec!["shadowed".to_owned()],
+      deny: Vec::new(),
+    };
+    let err = config.validate().unwrap_err();
+    assert_eq!(err.code(), Some(codes::CONFIG_INVALID));
+
+    assert!(LintConfig::default().validate().is_ok());
+  }
+}
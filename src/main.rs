@@ -1,35 +1,1616 @@
-mod context;
-mod evaluate;
-mod ffi;
-mod value;
+use clap::{Parser as CliParser, Subcommand, ValueEnum};
+use sam::bytecode::{self, Chunk};
+use sam::config::{self, Backend, Config};
+use sam::coverage::{self, Coverage, FileCoverage};
+use sam::diagnostic::{Diagnostic, Severity};
+use sam::ffi::FFI;
+use sam::manifest::{self, Dependency};
+use sam::output::Writer;
+use sam::permissions::{Allow, Permissions};
+use sam::profile::Profiler;
+use sam::report::{ColorChoice, ErrorFormat, Reporter};
+use sam::syntax::syntax_errors;
+use sam::testing::Outcome;
+use sam::trace::{Trace, Tracer};
+use sam::warnings;
+use sam::{Context, Interpreter, SamError, Value};
+use sam::{
+  analysis, ast, cache, codes, crash, dap, doc, fold, format, highlight,
+  kernel, lint, minify, peephole, prune, query, repl, suggest, testing,
+  transpile,
+};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use tree_sitter::Node;
 
-use evaluate::evaluate;
-use tree_sitter::{Language, Parser};
+#[derive(CliParser)]
+#[command(
+  name = "sam",
+  about = "The sam scripting language",
+  args_conflicts_with_subcommands = true
+)]
+struct Cli {
+  #[command(subcommand)]
+  command: Option<Command>,
 
-// retrieve Language struct from C code
-unsafe extern "C" {
-  fn tree_sitter_sam() -> Language;
+  /// Script to run, or directory of a package to run its entry point;
+  /// starts an interactive session if omitted
+  file: Option<PathBuf>,
+
+  /// Treat warnings as errors
+  #[arg(long)]
+  deny_warnings: bool,
+
+  /// Make operations on undefined or mismatched values errors
+  #[arg(long)]
+  strict: bool,
+
+  /// Let assignments to undeclared names declare them, with a warning
+  #[arg(long)]
+  auto_declare: bool,
+
+  /// Also look for interface files in this directory, before those of
+  /// sam.toml and SAM_PATH
+  #[arg(long = "interface", value_name = "DIR")]
+  interface_path: Vec<PathBuf>,
+
+  /// Run in a sandbox: no commands, file reads, or network access unless
+  /// granted with the --allow flags, which imply it
+  #[arg(long)]
+  sandbox: bool,
+
+  /// Allow running commands, all or only the listed programs
+  #[arg(
+    long,
+    value_name = "PROGRAMS",
+    num_args = 0..=1,
+    require_equals = true,
+    value_delimiter = ','
+  )]
+  allow_run: Option<Vec<String>>,
+
+  /// Allow reading files, anywhere or only below the listed paths
+  #[arg(
+    long,
+    value_name = "PATHS",
+    num_args = 0..=1,
+    require_equals = true,
+    value_delimiter = ','
+  )]
+  allow_read: Option<Vec<String>>,
+
+  /// Allow network access, to any or only the listed hosts
+  #[arg(
+    long,
+    value_name = "HOSTS",
+    num_args = 0..=1,
+    require_equals = true,
+    value_delimiter = ','
+  )]
+  allow_net: Option<Vec<String>>,
+
+  /// Stop the script after evaluating this many statements and expressions
+  #[arg(long, value_name = "STEPS")]
+  max_steps: Option<u64>,
+
+  /// What runs the script: walking its syntax tree, or compiling it to
+  /// bytecode for a virtual machine
+  #[arg(long, value_enum)]
+  backend: Option<Backend>,
+
+  /// Keep the compiled script on disk for the next run, with the vm backend
+  #[arg(long)]
+  cache: bool,
+
+  /// Print the bytecode of the script, as compiled and after the peephole
+  /// pass, instead of running it
+  #[arg(long)]
+  disassemble: bool,
+
+  /// Kill external commands running longer than this many seconds
+  #[arg(long, value_name = "SECONDS")]
+  timeout: Option<f64>,
+
+  /// Print every external command run, with its duration, exit status, and
+  /// output size, to stderr
+  #[arg(long)]
+  trace_ffi: bool,
+
+  /// Print every statement and expression as it is evaluated, with its
+  /// location, its value, and the depth of calls, to stderr; the script is
+  /// run by walking its syntax tree
+  #[arg(long)]
+  trace: bool,
+
+  /// Time every function and external command, and print a table of the
+  /// calls and the time spent in each to stderr
+  #[arg(long)]
+  profile: bool,
+
+  /// Also write the call stacks timed by --profile to FILE, in the folded
+  /// format flamegraph tools read
+  #[arg(long, value_name = "FILE")]
+  profile_folded: Option<PathBuf>,
+
+  /// Count the statements run, and print the lines of those that were not
+  /// to stderr; the script is run by walking its syntax tree
+  #[arg(long)]
+  coverage: bool,
+
+  /// Also write the coverage of --coverage to FILE, in the lcov format
+  #[arg(long, value_name = "FILE")]
+  coverage_lcov: Option<PathBuf>,
+
+  /// How to print errors and warnings
+  #[arg(long, value_enum, default_value_t = ErrorFormat::Human)]
+  error_format: ErrorFormat,
+
+  /// Print the variables of the script once it finished, in this format
+  #[arg(long, value_enum)]
+  output: Option<OutputFormat>,
+
+  /// When to color errors and warnings, NO_COLOR is honored by `auto`
+  #[arg(long, value_enum, default_value_t = ColorChoice::Auto)]
+  color: ColorChoice,
+}
+
+impl Cli {
+  // command line flags take precedence over sam.toml
+  fn apply_flags(&self, mut config: Config) -> Config {
+    config.strict |= self.strict;
+    config.cache |= self.cache;
+
+    if self.auto_declare {
+      config.auto_declare = Some(true);
+    }
+
+    if let Some(backend) = self.backend {
+      config.backend = backend;
+    }
+
+    // only the tree walker sees each expression
+    if self.trace {
+      config.backend = Backend::Tree;
+    }
+
+    if self.timeout.is_some() {
+      config.timeout = self.timeout;
+    }
+
+    if self.max_steps.is_some() {
+      config.limits.steps = self.max_steps;
+    }
+
+    if self.trace_ffi {
+      config.trace = Some(Trace::to(config.output.stderr.clone()));
+    }
+
+    if self.profile || self.profile_folded.is_some() {
+      config.profile = Some(Profiler::new());
+    }
+
+    if self.coverage || self.coverage_lcov.is_some() {
+      config.backend = Backend::Tree;
+      config.coverage = Some(Coverage::new());
+    }
+
+    config
+      .interface_path
+      .splice(0..0, self.interface_path.iter().cloned());
+
+    let sandboxed = self.sandbox
+      || self.allow_run.is_some()
+      || self.allow_read.is_some()
+      || self.allow_net.is_some();
+
+    if sandboxed {
+      config.permissions = Permissions {
+        run: Allow::from_flag(&self.allow_run),
+        read: Allow::from_flag(&self.allow_read),
+        net: Allow::from_flag(&self.allow_net),
+      };
+    }
+
+    return config;
+  }
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum OutputFormat {
+  Debug,
+  // a JSON object of the global variables, functions left out
+  Json,
+}
+
+#[derive(Subcommand)]
+enum Command {
+  /// Print the extended description of a diagnostic code, e.g. E0001
+  Explain { code: String },
+
+  /// Format scripts in place, or only check that they are formatted
+  Fmt {
+    /// Scripts, or directories to format the scripts in, the current
+    /// directory if omitted
+    paths: Vec<PathBuf>,
+
+    /// Change nothing and fail if a script is not formatted, e.g. in CI
+    #[arg(long)]
+    check: bool,
+  },
+
+  /// Run the `test_*` functions of scripts, and the scripts in `tests`
+  /// directories
+  Test {
+    /// Scripts, or directories to run the tests of the scripts in, the
+    /// current directory if omitted
+    paths: Vec<PathBuf>,
+
+    /// Count the statements the tests run, and print the lines of those
+    /// that were not
+    #[arg(long)]
+    coverage: bool,
+
+    /// Also write the coverage of --coverage to FILE, in the lcov format
+    #[arg(long, value_name = "FILE")]
+    coverage_lcov: Option<PathBuf>,
+  },
+
+  /// Check scripts for likely mistakes, without running them
+  Lint {
+    /// Scripts, or directories to check the scripts in, the current
+    /// directory if omitted
+    paths: Vec<PathBuf>,
+
+    /// Turn a rule off, e.g. shadowing
+    #[arg(long, value_name = "RULE")]
+    allow: Vec<String>,
+
+    /// Report a rule as an error, failing the check
+    #[arg(long, value_name = "RULE")]
+    deny: Vec<String>,
+  },
+
+  /// Search scripts for syntax matching a pattern, e.g. 'chdir($dir)',
+  /// where `$name` stands for any expression
+  Query {
+    pattern: String,
+
+    /// Scripts, or directories to search the scripts in, the current
+    /// directory if omitted
+    paths: Vec<PathBuf>,
+
+    /// Read the pattern as a tree-sitter query, reporting the first
+    /// capture of each match, e.g. '(call_expression) @call'
+    #[arg(long)]
+    tree_sitter: bool,
+
+    /// Print a JSON object per match, with what `$name`s or captures matched
+    #[arg(long)]
+    json: bool,
+  },
+
+  /// Add a dependency to the sam.toml of the current directory, fetching
+  /// it if it is a git repository
+  Add {
+    /// Name the modules of the dependency are imported under
+    name: String,
+
+    /// Directory of the dependency
+    #[arg(long, conflicts_with = "git", required_unless_present = "git")]
+    path: Option<PathBuf>,
+
+    /// Repository of the dependency
+    #[arg(long, value_name = "URL")]
+    git: Option<String>,
+
+    /// Branch, tag, or commit of the repository, its default branch if
+    /// omitted
+    #[arg(long, requires = "git")]
+    rev: Option<String>,
+  },
+
+  /// Copy every dependency of the project in the current directory, and
+  /// theirs, into its vendor directory
+  Vendor,
+
+  /// Write the documentation of the functions, constants, and interfaces
+  /// of scripts, a page per script
+  Doc {
+    /// Scripts, or directories to document the scripts in, the current
+    /// directory if omitted
+    paths: Vec<PathBuf>,
+
+    #[arg(long, value_enum, default_value_t = doc::Format::Markdown)]
+    format: doc::Format,
+
+    /// Directory to write the pages to
+    #[arg(long, value_name = "DIR", default_value = "doc")]
+    out: PathBuf,
+  },
+
+  /// Print the bytecode of a script, as compiled and after the peephole
+  /// pass, under the lines it was compiled from
+  Dis { file: PathBuf },
+
+  /// Print the syntax tree of a script, syntax errors included
+  Ast { file: PathBuf },
+
+  /// Run as a Jupyter kernel, started by Jupyter with a connection file
+  Kernel {
+    connection_file: Option<PathBuf>,
+
+    /// Register the kernel with Jupyter for the current user instead
+    #[arg(long, conflicts_with = "connection_file")]
+    install: bool,
+  },
+
+  /// Serve the Debug Adapter Protocol on stdin and stdout, for editors to
+  /// debug scripts
+  Dap,
+
+  /// Write a script as JavaScript or Lua, running its external commands
+  /// with a small runtime
+  Compile {
+    file: PathBuf,
+
+    #[arg(long, value_enum, default_value_t = transpile::Target::Js)]
+    target: transpile::Target,
+
+    /// File to write the code to, printed if omitted
+    #[arg(short, long, value_name = "FILE")]
+    out: Option<PathBuf>,
+  },
+
+  /// Write a script on one line without comments, e.g. to embed it in
+  /// another program
+  Minify {
+    file: PathBuf,
+
+    /// Also give the variables of the script the shortest names possible
+    #[arg(long)]
+    shorten: bool,
+
+    /// File to write the script to, printed if omitted
+    #[arg(short, long, value_name = "FILE")]
+    out: Option<PathBuf>,
+  },
+}
+
+fn main() -> ExitCode {
+  crash::install_hook();
+
+  let cli = Cli::parse();
+
+  match &cli.command {
+    Some(Command::Explain { code }) => return explain(code),
+    Some(Command::Fmt { paths, check }) => {
+      return fmt(paths, *check, cli.error_format, cli.color.enabled());
+    }
+    Some(Command::Test {
+      paths,
+      coverage,
+      coverage_lcov,
+    }) => {
+      let coverage = match *coverage || coverage_lcov.is_some() {
+        true => Some(coverage_lcov.as_deref()),
+        false => None,
+      };
+      return test(paths, coverage, cli.error_format, cli.color.enabled());
+    }
+    Some(Command::Lint { paths, allow, deny }) => {
+      return lint(paths, allow, deny, cli.error_format, cli.color.enabled());
+    }
+    Some(Command::Query {
+      pattern,
+      paths,
+      tree_sitter,
+      json,
+    }) => {
+      return query(pattern, paths, *tree_sitter, *json, &cli);
+    }
+    Some(Command::Add {
+      name,
+      path,
+      git,
+      rev,
+    }) => {
+      let dependency = match (path, git) {
+        (Some(path), _) => Dependency::Path { path: path.clone() },
+        (None, git) => Dependency::Git {
+          git: git.clone().unwrap_or_default(),
+          rev: rev.clone(),
+        },
+      };
+      return add(name, &dependency, cli.error_format, cli.color.enabled());
+    }
+    Some(Command::Vendor) => {
+      return vendor(cli.error_format, cli.color.enabled());
+    }
+    Some(Command::Doc { paths, format, out }) => {
+      return doc(paths, *format, out, cli.error_format, cli.color.enabled());
+    }
+    Some(Command::Dis { file }) => {
+      return dis(file, cli.error_format, cli.color.enabled());
+    }
+    Some(Command::Ast { file }) => return ast(file, cli.color.enabled()),
+    Some(Command::Kernel {
+      connection_file,
+      install,
+    }) => {
+      return run_kernel(connection_file.as_deref(), *install, &cli);
+    }
+    Some(Command::Dap) => return run_dap(&cli),
+    Some(Command::Compile { file, target, out }) => {
+      return compile(
+        file,
+        *target,
+        out.as_deref(),
+        cli.error_format,
+        cli.color.enabled(),
+      );
+    }
+    Some(Command::Minify { file, shorten, out }) => {
+      return minify(
+        file,
+        *shorten,
+        out.as_deref(),
+        cli.error_format,
+        cli.color.enabled(),
+      );
+    }
+    None => {}
+  }
+
+  let color = cli.color.enabled();
+
+  let Some(path) = &cli.file else {
+    let config = match Config::find(Path::new(".")) {
+      Ok(config) => cli.apply_flags(config),
+      Err(e) => {
+        let reporter = Reporter {
+          format: cli.error_format,
+          color,
+          file: config::CONFIG_FILE,
+          text: "",
+          out: Writer::stderr(),
+        };
+        reporter.emit(&Diagnostic::from(&e));
+        return ExitCode::FAILURE;
+      }
+    };
+
+    return repl::run(config, cli.error_format, color);
+  };
+
+  // a package runs its entry point
+  let entry;
+  let path = match path.is_dir() {
+    true => {
+      entry = Config::load(path)
+        .ok()
+        .and_then(|config| config.package.entry)
+        .unwrap_or(path.join(manifest::DEFAULT_ENTRY));
+      &entry
+    }
+    false => path,
+  };
+
+  let file = path.display().to_string();
+
+  let text = match fs::read_to_string(path) {
+    Ok(text) => text,
+    Err(e) => {
+      let reporter = Reporter {
+        format: cli.error_format,
+        color,
+        file: &file,
+        text: "",
+        out: Writer::stderr(),
+      };
+      reporter.emit(&Diagnostic::error(format!(
+        "could not read {}: {}",
+        file, e
+      )));
+      return ExitCode::FAILURE;
+    }
+  };
+
+  let reporter = Reporter {
+    format: cli.error_format,
+    color,
+    file: &file,
+    text: &text,
+    out: Writer::stderr(),
+  };
+
+  // settings of the sam.toml of the script's project, overridden by flags
+  let dir = path.parent().unwrap_or(Path::new("."));
+  let mut config = match Config::find(dir) {
+    Ok(config) => cli.apply_flags(config),
+    Err(e) => {
+      reporter.emit(&Diagnostic::from(&e));
+      return ExitCode::FAILURE;
+    }
+  };
+  config.module_dir = Some(dir.to_path_buf());
+  if cli.trace {
+    config.tracer = Some(Tracer::to(config.output.stderr.clone(), &text));
+  }
+
+  // a script compiled by an earlier run is neither parsed nor checked again
+  let cache_file = match config.cache && config.backend == Backend::Vm {
+    true if !cli.disassemble => Some(cache::path(path, &config)),
+    _ => None,
+  };
+  let key = cache::key(&text, &config);
+  let cached = cache_file.as_ref().and_then(|f| cache::load(f, key));
+
+  let compiled = match cached {
+    Some(chunk) => Some(chunk),
+    None => {
+      let tree = sam::parser().parse(&text, None).unwrap();
+      let root = &tree.root_node();
+
+      // report every syntax error up front rather than only the first one
+      let errors = syntax_errors(*root, text.as_bytes());
+      if !errors.is_empty() {
+        for e in &errors {
+          reporter.emit(&Diagnostic::from(e));
+        }
+
+        reporter.emit(&Diagnostic::error(format!(
+          "could not run {} due to {} syntax error(s)",
+          file,
+          errors.len()
+        )));
+        return ExitCode::FAILURE;
+      }
+
+      // likewise for errors that can be found without running the script
+      let errors = analysis::check(*root, text.as_bytes());
+      if !errors.is_empty() {
+        for e in &errors {
+          reporter.emit(&Diagnostic::from(e));
+        }
+
+        reporter.emit(&Diagnostic::error(format!(
+          "could not run {} due to {} error(s)",
+          file,
+          errors.len()
+        )));
+        return ExitCode::FAILURE;
+      }
+
+      let warnings = warnings::check(*root, text.as_bytes());
+      for w in &warnings {
+        reporter.emit(w);
+      }
+
+      if cli.deny_warnings && !warnings.is_empty() {
+        reporter.emit(&Diagnostic::error(format!(
+          "could not run {} due to {} warning(s) (--deny-warnings)",
+          file,
+          warnings.len()
+        )));
+        return ExitCode::FAILURE;
+      }
+
+      if cli.disassemble {
+        match disassemble(*root, &text, &config) {
+          Ok(listing) => config.output.stdout.write_line(&listing),
+          Err(e) => {
+            reporter.emit(&Diagnostic::from(&e));
+            return ExitCode::FAILURE;
+          }
+        }
+        return ExitCode::SUCCESS;
+      }
+
+      // compiled here rather than by the interpreter so that it can be
+      // kept, unless it has warnings to report the next time too
+      match &cache_file {
+        Some(file) if warnings.is_empty() => {
+          match cache::compile(*root, &text, &config) {
+            Ok((chunk, dead_code)) => {
+              for w in &dead_code {
+                reporter.emit(w);
+              }
+              if dead_code.is_empty() {
+                cache::store(file, key, &chunk);
+              }
+              Some(chunk)
+            }
+            Err(e) => {
+              reporter.emit(&Diagnostic::from(&e));
+              return ExitCode::FAILURE;
+            }
+          }
+        }
+        _ => None,
+      }
+    }
+  };
+
+  let mut interpreter = Interpreter::with_config(config);
+  let result = match compiled {
+    Some(chunk) => interpreter.run_compiled(&text, chunk),
+    None => interpreter.eval(&text),
+  };
+
+  let ctx = interpreter.context_mut();
+  for w in &ctx.warnings {
+    reporter.emit(w);
+  }
+
+  // a failed run is profiled up to the error
+  if let Some(profiler) = &ctx.config.profile {
+    ctx
+      .config
+      .output
+      .stderr
+      .write_line(profiler.report().trim_end());
+
+    let written = match &cli.profile_folded {
+      Some(folded) => fs::write(folded, profiler.folded())
+        .map_err(|e| format!("could not write {}: {}", folded.display(), e)),
+      None => Ok(()),
+    };
+    if let Err(message) = written {
+      reporter.emit(&Diagnostic::error(message));
+    }
+  }
+
+  // and covered up to it
+  if let Some(coverage) = &ctx.config.coverage {
+    let tree = sam::parser().parse(&text, None).unwrap();
+    match coverage.report(tree.root_node(), &text, &file) {
+      Ok(f) => {
+        let lcov_file = cli.coverage_lcov.as_deref();
+        let stderr = &ctx.config.output.stderr;
+        if let Err(message) = report_coverage(&[f], lcov_file, stderr) {
+          reporter.emit(&Diagnostic::error(message));
+        }
+      }
+      Err(e) => reporter.emit(&Diagnostic::from(&e)),
+    }
+  }
+
+  if let Err(e) = result {
+    if let Some(status) = e.exit_status() {
+      return ExitCode::from(status);
+    }
+    reporter.emit(&Diagnostic::from(&e));
+    return ExitCode::FAILURE;
+  }
+
+  let stdout = ctx.config.output.stdout.clone();
+  match cli.output {
+    None => {}
+    Some(OutputFormat::Debug) => stdout.write_line(&format!("{:#?}", ctx)),
+    Some(OutputFormat::Json) => match globals_json(ctx) {
+      Ok(json) => stdout.write_line(&json.to_string()),
+      Err(e) => {
+        reporter.emit(&Diagnostic::from(&e));
+        return ExitCode::FAILURE;
+      }
+    },
+  }
+
+  return ExitCode::SUCCESS;
+}
+
+// the bytecode of the program and of each function in it, before and after
+// the peephole pass
+fn disassemble(
+  root: Node,
+  text: &str,
+  config: &Config,
+) -> Result<String, SamError> {
+  let mut program = ast::lower(root, text.as_bytes())?;
+  fold::fold_program(&mut program.statements, config);
+  prune::prune_program(&mut program.statements);
+
+  let mut listing = String::new();
+  disassemble_chunk(
+    bytecode::compile_program(&program.statements),
+    "program",
+    text,
+    &mut listing,
+  );
+
+  return Ok(listing.trim_end().to_owned());
+}
+
+fn disassemble_chunk(
+  chunk: Chunk,
+  title: &str,
+  text: &str,
+  listing: &mut String,
+) {
+  let mut optimized = chunk.clone();
+  peephole::optimize(&mut optimized);
+
+  for (stage, chunk) in [("compiled", &chunk), ("optimized", &optimized)] {
+    listing.push_str(&format!("; {}, {}\n", title, stage));
+    listing.push_str(&chunk.listing(text));
+    listing.push('\n');
+  }
+
+  for (n, (_, body)) in chunk.lambdas.iter().enumerate() {
+    disassemble_chunk(
+      bytecode::compile_function(body),
+      &format!("{}, lambda {}", title, n),
+      text,
+      listing,
+    );
+  }
+}
+
+fn globals_json(ctx: &mut Context) -> Result<serde_json::Value, SamError> {
+  let mut globals = serde_json::Map::new();
+
+  for (name, value) in ctx.global_scope().iter() {
+    if matches!(
+      value,
+      Value::SamFunction(_)
+        | Value::SamForeignFunction(_)
+        | Value::SamHandle(_)
+        | Value::SamStream(_)
+        | Value::SamNativeFunction(_)
+        // never read, so never computed
+        | Value::SamLazy(_)
+    ) {
+      continue;
+    }
+
+    globals.insert(name.to_owned(), FFI::value_to_json(value)?);
+  }
+
+  return Ok(serde_json::Value::Object(globals));
+}
+
+// format the scripts of `paths`, or with `check` list those that are not
+fn fmt(
+  paths: &[PathBuf],
+  check: bool,
+  error_format: ErrorFormat,
+  color: bool,
+) -> ExitCode {
+  let scripts = scripts(paths);
+
+  let mut failed = false;
+
+  for script in &scripts {
+    let file = script.display().to_string();
+    let text = fs::read_to_string(script).unwrap_or_default();
+    let reporter = Reporter {
+      format: error_format,
+      color,
+      file: &file,
+      text: &text,
+      out: Writer::stderr(),
+    };
+
+    let tree = sam::parser().parse(&text, None).unwrap();
+    let errors = syntax_errors(tree.root_node(), text.as_bytes());
+    if !errors.is_empty() {
+      for e in &errors {
+        reporter.emit(&Diagnostic::from(e));
+      }
+      failed = true;
+      continue;
+    }
+
+    let formatted = match format::format(tree.root_node(), &text) {
+      Ok(formatted) => formatted,
+      Err(e) => {
+        reporter.emit(&Diagnostic::from(&e));
+        failed = true;
+        continue;
+      }
+    };
+
+    if formatted == text {
+      continue;
+    }
+
+    if check {
+      eprintln!("{} is not formatted", file);
+      failed = true;
+    } else if let Err(e) = fs::write(script, formatted) {
+      reporter.emit(&Diagnostic::error(format!(
+        "could not write {}: {}",
+        file, e
+      )));
+      failed = true;
+    }
+  }
+
+  return match failed {
+    true => ExitCode::FAILURE,
+    false => ExitCode::SUCCESS,
+  };
+}
+
+// run the tests of the scripts of `paths`, each with the settings of the
+// sam.toml of its project, and print how many passed; with `coverage`, also the
+// statements they ran, written in the lcov format to the file given if any
+fn test(
+  paths: &[PathBuf],
+  coverage: Option<Option<&Path>>,
+  error_format: ErrorFormat,
+  color: bool,
+) -> ExitCode {
+  let scripts = scripts(paths);
+
+  let started = std::time::Instant::now();
+  let (mut passed, mut failed) = (0, 0);
+  let mut covered = Vec::new();
+
+  for script in &scripts {
+    let file = script.display().to_string();
+    let text = fs::read_to_string(script).unwrap_or_default();
+    let reporter = Reporter {
+      format: error_format,
+      color,
+      file: &file,
+      text: &text,
+      out: Writer::stderr(),
+    };
+
+    let dir = script.parent().unwrap_or(Path::new("."));
+    let mut config = match Config::find(dir) {
+      Ok(config) => config,
+      Err(e) => {
+        reporter.emit(&Diagnostic::from(&e));
+        failed += 1;
+        continue;
+      }
+    };
+    config.module_dir = Some(dir.to_path_buf());
+    if coverage.is_some() {
+      config.backend = Backend::Tree;
+      config.coverage = Some(Coverage::new());
+    }
+
+    let tree = sam::parser().parse(&text, None).unwrap();
+    let errors = syntax_errors(tree.root_node(), text.as_bytes());
+    if !errors.is_empty() {
+      for e in &errors {
+        reporter.emit(&Diagnostic::from(e));
+      }
+      failed += 1;
+      continue;
+    }
+
+    let is_test = script.components().any(|c| c.as_os_str() == "tests");
+    let mut interpreter = Interpreter::with_config(config);
+    let results =
+      testing::run(tree.root_node(), &text, &file, is_test, &mut interpreter);
+
+    let results = match results {
+      Ok(results) => results,
+      Err(e) => {
+        reporter.emit(&Diagnostic::from(&e));
+        failed += 1;
+        continue;
+      }
+    };
+
+    // scripts without tests are not run, and so not covered either
+    let config = &interpreter.context().config;
+    if let (Some(counted), false) = (&config.coverage, results.is_empty()) {
+      match counted.report(tree.root_node(), &text, &file) {
+        Ok(f) => covered.push(f),
+        Err(e) => reporter.emit(&Diagnostic::from(&e)),
+      }
+    }
+
+    for result in &results {
+      let name = match result.name == file {
+        true => file.clone(),
+        false => format!("{}::{}", file, result.name),
+      };
+
+      match &result.outcome {
+        Outcome::Passed => {
+          println!("test {} ... ok", name);
+          passed += 1;
+        }
+        Outcome::Failed(e) => {
+          println!("test {} ... FAILED", name);
+          reporter.emit(&Diagnostic::from(e));
+          failed += 1;
+        }
+      }
+    }
+  }
+
+  println!(
+    "\ntest result: {}. {} passed; {} failed; finished in {:.2}s",
+    if failed == 0 { "ok" } else { "FAILED" },
+    passed,
+    failed,
+    started.elapsed().as_secs_f64()
+  );
+
+  if let Some(lcov_file) = coverage {
+    println!();
+    if let Err(message) =
+      report_coverage(&covered, lcov_file, &Writer::stdout())
+    {
+      Reporter {
+        format: error_format,
+        color,
+        file: "",
+        text: "",
+        out: Writer::stderr(),
+      }
+      .emit(&Diagnostic::error(message));
+      failed += 1;
+    }
+  }
+
+  return match failed {
+    0 => ExitCode::SUCCESS,
+    _ => ExitCode::FAILURE,
+  };
+}
+
+// print the coverage of `files` to `out`, and write it to `lcov_file` if
+// given
+fn report_coverage(
+  files: &[FileCoverage],
+  lcov_file: Option<&Path>,
+  out: &Writer,
+) -> Result<(), String> {
+  out.write_line(coverage::summary(files).trim_end());
+
+  return match lcov_file {
+    Some(lcov_file) => fs::write(lcov_file, coverage::lcov(files))
+      .map_err(|e| format!("could not write {}: {}", lcov_file.display(), e)),
+    None => Ok(()),
+  };
+}
+
+// lint the scripts of `paths`, each with the rules of the sam.toml of its
+// project and the rules of the command line on top
+fn lint(
+  paths: &[PathBuf],
+  allow: &[String],
+  deny: &[String],
+  error_format: ErrorFormat,
+  color: bool,
+) -> ExitCode {
+  let scripts = scripts(paths);
+
+  let mut failed = false;
+
+  for script in &scripts {
+    let file = script.display().to_string();
+    let text = fs::read_to_string(script).unwrap_or_default();
+    let reporter = Reporter {
+      format: error_format,
+      color,
+      file: &file,
+      text: &text,
+      out: Writer::stderr(),
+    };
+
+    let dir = script.parent().unwrap_or(Path::new("."));
+    let mut config = match Config::find(dir) {
+      Ok(config) => config,
+      Err(e) => {
+        reporter.emit(&Diagnostic::from(&e));
+        failed = true;
+        continue;
+      }
+    };
+    for rule in allow {
+      config.lint.deny.retain(|r| r != rule);
+      config.lint.allow.push(rule.clone());
+    }
+    for rule in deny {
+      config.lint.allow.retain(|r| r != rule);
+      config.lint.deny.push(rule.clone());
+    }
+
+    let tree = sam::parser().parse(&text, None).unwrap();
+    let errors = syntax_errors(tree.root_node(), text.as_bytes());
+    if !errors.is_empty() {
+      for e in &errors {
+        reporter.emit(&Diagnostic::from(e));
+      }
+      failed = true;
+      continue;
+    }
+
+    match lint::lint(tree.root_node(), &text, &config) {
+      Ok(found) => {
+        for d in &found {
+          reporter.emit(d);
+        }
+        failed |= found.iter().any(|d| d.severity == Severity::Error);
+      }
+      Err(e) => {
+        reporter.emit(&Diagnostic::from(&e));
+        failed = true;
+      }
+    }
+  }
+
+  return match failed {
+    true => ExitCode::FAILURE,
+    false => ExitCode::SUCCESS,
+  };
+}
+
+// add the dependency `name` to the project in the current directory, and
+// vendor the project's dependencies if it has to be fetched
+fn add(
+  name: &str,
+  dependency: &Dependency,
+  error_format: ErrorFormat,
+  color: bool,
+) -> ExitCode {
+  let dir = Path::new(".");
+  let mut added = manifest::add(dir, name, dependency);
+
+  if added.is_ok() && matches!(dependency, Dependency::Git { .. }) {
+    added = manifest::vendor(dir, |name, _| eprintln!("vendored {}", name));
+  }
+
+  return match added {
+    Ok(()) => ExitCode::SUCCESS,
+    Err(e) => {
+      config_reporter(error_format, color).emit(&Diagnostic::from(&e));
+      ExitCode::FAILURE
+    }
+  };
+}
+
+// vendor the dependencies of the project in the current directory
+fn vendor(error_format: ErrorFormat, color: bool) -> ExitCode {
+  let vendored =
+    manifest::vendor(Path::new("."), |name, dependency| match dependency {
+      Dependency::Path { path } => {
+        eprintln!("vendored {} from {}", name, path.display())
+      }
+      Dependency::Git { git, .. } => {
+        eprintln!("vendored {} from {}", name, git)
+      }
+    });
+
+  return match vendored {
+    Ok(()) => ExitCode::SUCCESS,
+    Err(e) => {
+      config_reporter(error_format, color).emit(&Diagnostic::from(&e));
+      ExitCode::FAILURE
+    }
+  };
+}
+
+// reports errors of sam.toml and of what it names, which have no source
+fn config_reporter(
+  error_format: ErrorFormat,
+  color: bool,
+) -> Reporter<'static> {
+  return Reporter {
+    format: error_format,
+    color,
+    file: config::CONFIG_FILE,
+    text: "",
+    out: Writer::stderr(),
+  };
+}
+
+// document the scripts of `paths` in `out`, with an index of them, each
+// looking up interfaces as the sam.toml of its project says
+fn doc(
+  paths: &[PathBuf],
+  format: doc::Format,
+  out: &Path,
+  error_format: ErrorFormat,
+  color: bool,
+) -> ExitCode {
+  let scripts = scripts(paths);
+
+  let mut failed = false;
+  let mut modules = Vec::new();
+  let mut pages = Vec::new();
+
+  for script in &scripts {
+    let file = script.display().to_string();
+    let text = fs::read_to_string(script).unwrap_or_default();
+    let reporter = Reporter {
+      format: error_format,
+      color,
+      file: &file,
+      text: &text,
+      out: Writer::stderr(),
+    };
+
+    let dir = script.parent().unwrap_or(Path::new("."));
+    let config = match Config::find(dir) {
+      Ok(config) => config,
+      Err(e) => {
+        reporter.emit(&Diagnostic::from(&e));
+        failed = true;
+        continue;
+      }
+    };
+
+    let tree = sam::parser().parse(&text, None).unwrap();
+    let errors = syntax_errors(tree.root_node(), text.as_bytes());
+    if !errors.is_empty() {
+      for e in &errors {
+        reporter.emit(&Diagnostic::from(e));
+      }
+      failed = true;
+      continue;
+    }
+
+    let name = script
+      .file_stem()
+      .map(|s| s.to_string_lossy().into_owned())
+      .unwrap_or(file.clone());
+
+    match doc::extract(tree.root_node(), &text, &name, &config) {
+      Ok(module) => {
+        pages.push((name.clone(), doc::render(&module, format)));
+        modules.push(name);
+      }
+      Err(e) => {
+        reporter.emit(&Diagnostic::from(&e));
+        failed = true;
+      }
+    }
+  }
+
+  pages.push(("index".to_owned(), doc::index(&modules, format)));
+
+  let written = fs::create_dir_all(out).and_then(|_| {
+    for (name, page) in &pages {
+      let path = out.join(format!("{}.{}", name, format.extension()));
+      fs::write(path, page)?;
+    }
+    Ok(())
+  });
+  if let Err(e) = written {
+    eprintln!("error: could not write {}: {}", out.display(), e);
+    return ExitCode::FAILURE;
+  }
+
+  return match failed {
+    true => ExitCode::FAILURE,
+    false => ExitCode::SUCCESS,
+  };
 }
 
-fn main() {
-  // set parser language
-  let language = unsafe { tree_sitter_sam() };
-  let mut parser = Parser::new();
-  parser.set_language(&language).unwrap();
+// print the bytecode of the script `path`, folded with the settings of the
+// sam.toml of its project as when it runs
+fn dis(path: &Path, error_format: ErrorFormat, color: bool) -> ExitCode {
+  let file = path.display().to_string();
+  let text = match fs::read_to_string(path) {
+    Ok(text) => text,
+    Err(e) => {
+      eprintln!("error: could not read {}: {}", file, e);
+      return ExitCode::FAILURE;
+    }
+  };
+  let reporter = Reporter {
+    format: error_format,
+    color,
+    file: &file,
+    text: &text,
+    out: Writer::stderr(),
+  };
 
-  let text = r#"
-let a = ls();
-let b = a.stdout;
-let c = wc("-l", b);
-  "#;
+  let tree = sam::parser().parse(&text, None).unwrap();
+  let errors = syntax_errors(tree.root_node(), text.as_bytes());
+  if !errors.is_empty() {
+    for e in &errors {
+      reporter.emit(&Diagnostic::from(e));
+    }
+    return ExitCode::FAILURE;
+  }
+
+  let dir = path.parent().unwrap_or(Path::new("."));
+  let listing = Config::find(dir)
+    .and_then(|config| disassemble(tree.root_node(), &text, &config));
+  return match listing {
+    Ok(listing) => {
+      println!("{}", listing);
+      ExitCode::SUCCESS
+    }
+    Err(e) => {
+      reporter.emit(&Diagnostic::from(&e));
+      ExitCode::FAILURE
+    }
+  };
+}
+
+// serve a notebook with the settings of the sam.toml of the current
+// directory, or register the kernel with Jupyter
+fn run_kernel(
+  connection_file: Option<&Path>,
+  install: bool,
+  cli: &Cli,
+) -> ExitCode {
+  if install {
+    return match kernel::install() {
+      Ok(dir) => {
+        println!("installed the sam kernel in {}", dir.display());
+        ExitCode::SUCCESS
+      }
+      Err(e) => {
+        eprintln!("error: could not install the kernel: {}", e);
+        ExitCode::FAILURE
+      }
+    };
+  }
+
+  let Some(connection_file) = connection_file else {
+    eprintln!("error: give the connection file, or --install");
+    return ExitCode::FAILURE;
+  };
+
+  return match Config::find(Path::new(".")) {
+    Ok(config) => kernel::run(cli.apply_flags(config), connection_file),
+    Err(e) => {
+      let reporter = Reporter {
+        format: cli.error_format,
+        color: cli.color.enabled(),
+        file: config::CONFIG_FILE,
+        text: "",
+        out: Writer::stderr(),
+      };
+      reporter.emit(&Diagnostic::from(&e));
+      ExitCode::FAILURE
+    }
+  };
+}
+
+// debug scripts for an editor, with the settings of the sam.toml of the
+// current directory
+fn run_dap(cli: &Cli) -> ExitCode {
+  return match Config::find(Path::new(".")) {
+    Ok(config) => dap::run(cli.apply_flags(config)),
+    Err(e) => {
+      let reporter = Reporter {
+        format: cli.error_format,
+        color: cli.color.enabled(),
+        file: config::CONFIG_FILE,
+        text: "",
+        out: Writer::stderr(),
+      };
+      reporter.emit(&Diagnostic::from(&e));
+      ExitCode::FAILURE
+    }
+  };
+}
+
+// print the named nodes of the tree of `path`, one per line and indented
+// by depth, with the text of the leaves highlighted
+fn ast(path: &Path, color: bool) -> ExitCode {
+  let text = match fs::read_to_string(path) {
+    Ok(text) => text,
+    Err(e) => {
+      eprintln!("error: could not read {}: {}", path.display(), e);
+      return ExitCode::FAILURE;
+    }
+  };
+
+  let tree = sam::parser().parse(&text, None).unwrap();
+  let styles = match color {
+    true => highlight::styles(&text),
+    false => vec![""; text.len()],
+  };
+
+  let mut out = String::new();
+  syntax_tree(tree.root_node(), None, &text, &styles, 0, &mut out);
+  print!("{}", out);
+
+  return ExitCode::SUCCESS;
+}
+
+fn syntax_tree(
+  node: Node,
+  field: Option<&str>,
+  text: &str,
+  styles: &[&'static str],
+  depth: usize,
+  out: &mut String,
+) {
+  out.push_str(&"  ".repeat(depth));
+  if let Some(field) = field {
+    out.push_str(&format!("{}: ", field));
+  }
+  out.push_str(node.kind());
+
+  let start = node.start_position();
+  out.push_str(&format!(" {}:{}", start.row + 1, start.column + 1));
+
+  if node.named_child_count() == 0 && !node.is_missing() {
+    let range = node.byte_range();
+    let leaf = highlight::paint_range(text, styles, range.start, range.end);
+    out.push_str(&format!(" {}", leaf));
+  }
+  out.push('\n');
+
+  let mut walker = node.walk();
+  for (i, child) in node.children(&mut walker).enumerate() {
+    if child.is_named() {
+      let field = node.field_name_for_child(i as u32);
+      syntax_tree(child, field, text, styles, depth + 1, out);
+    }
+  }
+}
+
+// write the script `path` in `target`, to `out` or stdout, looking up its
+// interfaces as the sam.toml of its project says
+fn compile(
+  path: &Path,
+  target: transpile::Target,
+  out: Option<&Path>,
+  error_format: ErrorFormat,
+  color: bool,
+) -> ExitCode {
+  let file = path.display().to_string();
+  let text = match fs::read_to_string(path) {
+    Ok(text) => text,
+    Err(e) => {
+      eprintln!("error: could not read {}: {}", file, e);
+      return ExitCode::FAILURE;
+    }
+  };
+  let reporter = Reporter {
+    format: error_format,
+    color,
+    file: &file,
+    text: &text,
+    out: Writer::stderr(),
+  };
+
+  let tree = sam::parser().parse(&text, None).unwrap();
+  let errors = syntax_errors(tree.root_node(), text.as_bytes());
+  if !errors.is_empty() {
+    for e in &errors {
+      reporter.emit(&Diagnostic::from(e));
+    }
+    return ExitCode::FAILURE;
+  }
+
+  let dir = path.parent().unwrap_or(Path::new("."));
+  let code = Config::find(dir).and_then(|config| {
+    let program = ast::lower(tree.root_node(), text.as_bytes())?;
+    transpile::transpile(&program, target, &config)
+  });
+  let code = match code {
+    Ok(code) => code,
+    Err(e) => {
+      reporter.emit(&Diagnostic::from(&e));
+      return ExitCode::FAILURE;
+    }
+  };
+
+  let Some(out) = out else {
+    print!("{}", code);
+    return ExitCode::SUCCESS;
+  };
+  if let Err(e) = fs::write(out, code) {
+    eprintln!("error: could not write {}: {}", out.display(), e);
+    return ExitCode::FAILURE;
+  }
+
+  return ExitCode::SUCCESS;
+}
 
-  let tree = parser.parse(text, None).unwrap();
-  let root = &tree.root_node();
+// write the script `path` minified to `out` or stdout
+fn minify(
+  path: &Path,
+  shorten: bool,
+  out: Option<&Path>,
+  error_format: ErrorFormat,
+  color: bool,
+) -> ExitCode {
+  let file = path.display().to_string();
+  let text = match fs::read_to_string(path) {
+    Ok(text) => text,
+    Err(e) => {
+      eprintln!("error: could not read {}: {}", file, e);
+      return ExitCode::FAILURE;
+    }
+  };
+  let reporter = Reporter {
+    format: error_format,
+    color,
+    file: &file,
+    text: &text,
+    out: Writer::stderr(),
+  };
 
-  let ctx = evaluate(&root, text.as_bytes(), &tree);
+  let tree = sam::parser().parse(&text, None).unwrap();
+  let errors = syntax_errors(tree.root_node(), text.as_bytes());
+  if !errors.is_empty() {
+    for e in &errors {
+      reporter.emit(&Diagnostic::from(e));
+    }
+    return ExitCode::FAILURE;
+  }
+
+  let minified = match minify::minify(tree.root_node(), &text, shorten) {
+    Ok(minified) => minified,
+    Err(e) => {
+      reporter.emit(&Diagnostic::from(&e));
+      return ExitCode::FAILURE;
+    }
+  };
+
+  let Some(out) = out else {
+    print!("{}", minified);
+    return ExitCode::SUCCESS;
+  };
+  if let Err(e) = fs::write(out, minified) {
+    eprintln!("error: could not write {}: {}", out.display(), e);
+    return ExitCode::FAILURE;
+  }
+
+  return ExitCode::SUCCESS;
+}
+
+// print the matches of `pattern` in the scripts of `paths`, failing like
+// grep: with 1 if there were none, with 2 if something could not be searched
+fn query(
+  pattern: &str,
+  paths: &[PathBuf],
+  tree_sitter: bool,
+  json: bool,
+  cli: &Cli,
+) -> ExitCode {
+  let color = cli.color.enabled();
+  let pattern = match tree_sitter {
+    true => query::Pattern::query(pattern),
+    false => query::Pattern::code(pattern),
+  };
+  let pattern = match pattern {
+    Ok(pattern) => pattern,
+    Err(e) => {
+      let reporter = Reporter {
+        format: cli.error_format,
+        color,
+        file: "<pattern>",
+        text: "",
+        out: Writer::stderr(),
+      };
+      reporter.emit(&Diagnostic::from(&e));
+      return ExitCode::from(2);
+    }
+  };
+
+  let mut found = false;
+  let mut failed = false;
 
-  match ctx {
-    Err(e) => println!("{:#?}", e),
-    Ok(a) => println!("{:#?}", a),
+  for script in scripts(paths) {
+    let file = script.display().to_string();
+    let text = match fs::read_to_string(&script) {
+      Ok(text) => text,
+      Err(e) => {
+        eprintln!("error: could not read {}: {}", file, e);
+        failed = true;
+        continue;
+      }
+    };
+
+    let tree = sam::parser().parse(&text, None).unwrap();
+    let matches = pattern.find(tree.root_node(), &text);
+    found |= !matches.is_empty();
+
+    let styles = match color && !json {
+      true => highlight::styles(&text),
+      false => Vec::new(),
+    };
+
+    for m in matches {
+      let line = m.span.start_point.row + 1;
+      let column = m.span.start_point.column + 1;
+
+      if json {
+        let captures: serde_json::Map<String, serde_json::Value> = m
+          .captures
+          .into_iter()
+          .map(|(name, text)| (name, text.into()))
+          .collect();
+        let object = serde_json::json!({
+          "file": file,
+          "line": line,
+          "column": column,
+          "text": &text[m.span.start..m.span.end],
+          "captures": captures,
+        });
+        println!("{}", object);
+        continue;
+      }
+
+      // the first line of the match
+      let end = text[m.span.start..m.span.end]
+        .find('\n')
+        .map_or(m.span.end, |i| m.span.start + i);
+      let shown = match color {
+        true => highlight::paint_range(&text, &styles, m.span.start, end),
+        false => text[m.span.start..end].to_owned(),
+      };
+      println!("{}:{}:{}: {}", file, line, column, shown);
+    }
+  }
+
+  if failed {
+    return ExitCode::from(2);
   }
+  return match found {
+    true => ExitCode::SUCCESS,
+    false => ExitCode::FAILURE,
+  };
+}
+
+// the scripts `paths` name, those of the current directory if none
+fn scripts(paths: &[PathBuf]) -> Vec<PathBuf> {
+  let mut scripts = Vec::new();
+  match paths.is_empty() {
+    true => find_scripts(Path::new("."), &mut scripts),
+    false => {
+      for path in paths {
+        find_scripts(path, &mut scripts);
+      }
+    }
+  }
+
+  return scripts;
+}
+
+// `path` if it is a file, else the .sam files below it, leaving out hidden
+// directories and build output
+fn find_scripts(path: &Path, scripts: &mut Vec<PathBuf>) {
+  if !path.is_dir() {
+    scripts.push(path.to_owned());
+    return;
+  }
+
+  let Ok(entries) = fs::read_dir(path) else {
+    return;
+  };
+  let mut entries: Vec<PathBuf> =
+    entries.filter_map(|e| e.ok().map(|e| e.path())).collect();
+  entries.sort();
+
+  for entry in entries {
+    let name = entry.file_name().unwrap_or_default().to_string_lossy();
+    if entry.is_dir() {
+      if !name.starts_with('.') && name != "target" {
+        find_scripts(&entry, scripts);
+      }
+    } else if entry.extension().is_some_and(|e| e == "sam") {
+      scripts.push(entry);
+    }
+  }
+}
+
+fn explain(code: &str) -> ExitCode {
+  let Some(explanation) = codes::explain(code) else {
+    eprintln!("error: {} is not a known diagnostic code", code);
+
+    if let Some(help) =
+      suggest::did_you_mean(code, codes::CATALOG.iter().map(|e| e.code))
+    {
+      eprintln!("help: {}", help);
+    }
+
+    return ExitCode::FAILURE;
+  };
+
+  println!("{}: {}\n", explanation.code, explanation.title);
+  print!("{}", explanation.text);
+
+  return ExitCode::SUCCESS;
 }
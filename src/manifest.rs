@@ -0,0 +1,397 @@
+#![allow(dead_code)]
+
+// The project side of sam.toml: the package the scripts next to it make
+// up, and the packages they depend on:
+//
+//   [package]
+//   name = "deploy"
+//   root = "src"
+//   entry = "src/main.sam"
+//
+//   [dependencies]
+//   strings = { path = "../strings" }
+//   http = { git = "https://example.com/sam-http.git", rev = "v1.2.0" }
+//
+// Modules are imported from the source root, and from a dependency by
+// starting their path with its name, see src/modules.rs. `sam add` adds a
+// dependency to sam.toml, and `sam vendor` copies every dependency, and
+// theirs, into the `vendor` directory next to it. Git dependencies are only
+// ever imported from there, path dependencies from where they are unless
+// vendored too.
+
+use crate::codes;
+use crate::config::{CONFIG_FILE, Config};
+use crate::error::SamError;
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+// directory next to sam.toml the dependencies are vendored in
+pub const VENDOR_DIR: &str = "vendor";
+
+// the module run when the directory of a package is, and imported when the
+// package is by its name alone, in the source root
+pub const DEFAULT_ENTRY: &str = "main.sam";
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Package {
+  pub name: Option<String>,
+  pub version: Option<String>,
+  // directory modules are imported from, that of sam.toml if unset
+  pub root: Option<PathBuf>,
+  // the main module, `main.sam` in the source root if unset
+  pub entry: Option<PathBuf>,
+}
+
+impl Package {
+  // paths are relative to `dir`, that of sam.toml
+  pub fn resolve(&mut self, dir: &Path) {
+    let root = dir.join(self.root.take().unwrap_or_default());
+    self.entry = Some(match self.entry.take() {
+      Some(entry) => dir.join(entry),
+      None => root.join(DEFAULT_ENTRY),
+    });
+    self.root = Some(root);
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(untagged, deny_unknown_fields)]
+pub enum Dependency {
+  // relative to the directory of sam.toml
+  Path { path: PathBuf },
+  // `rev` is a branch, tag, or commit, the default branch if unset
+  Git { git: String, rev: Option<String> },
+}
+
+impl Dependency {
+  // the inline table it is written as in sam.toml
+  fn to_toml(&self) -> String {
+    let quote = |s: &str| toml::Value::String(s.to_owned()).to_string();
+
+    return match self {
+      Dependency::Path { path } => {
+        format!("{{ path = {} }}", quote(&path.to_string_lossy()))
+      }
+      Dependency::Git { git, rev: None } => {
+        format!("{{ git = {} }}", quote(git))
+      }
+      Dependency::Git {
+        git,
+        rev: Some(rev),
+      } => format!("{{ git = {}, rev = {} }}", quote(git), quote(rev)),
+    };
+  }
+}
+
+// the directory a dependency of the project in `project` is imported from
+pub fn dependency_dir(
+  project: Option<&Path>,
+  name: &str,
+  dependency: &Dependency,
+) -> Result<PathBuf, SamError> {
+  if let Some(project) = project {
+    let vendored = project.join(VENDOR_DIR).join(name);
+    if vendored.is_dir() {
+      return Ok(vendored);
+    }
+  }
+
+  return match dependency {
+    Dependency::Path { path } => Ok(path.clone()),
+    Dependency::Git { git, .. } => Err(
+      SamError::value(
+        codes::MODULE_NOT_FOUND,
+        format!("Dependency `{}` from {} is not vendored", name, git),
+      )
+      .with_help("fetch it with `sam vendor`"),
+    ),
+  };
+}
+
+/* =========================
+sam add
+========================= */
+
+// add `name` to the dependencies in the sam.toml of `dir`, or replace it,
+// keeping the rest of the file as written
+pub fn add(
+  dir: &Path,
+  name: &str,
+  dependency: &Dependency,
+) -> Result<(), SamError> {
+  let path = dir.join(CONFIG_FILE);
+  let text = match path.is_file() {
+    true => fs::read_to_string(&path).map_err(|e| {
+      SamError::value(
+        codes::CONFIG_INVALID,
+        format!("Could not read {}", path.display()),
+      )
+      .caused_by(e)
+    })?,
+    false => String::new(),
+  };
+
+  let added = with_dependency(&text, name, dependency);
+
+  // dependencies written in some other way, e.g. as a table of their own,
+  // are left alone rather than broken
+  let config = Config::parse(&added)?;
+  if config.dependencies.get(name) != Some(dependency) {
+    return Err(
+      SamError::value(
+        codes::CONFIG_INVALID,
+        format!("Could not add `{}` to {}", name, path.display()),
+      )
+      .with_help(format!(
+        "write it in the [dependencies] table yourself: {} = {}",
+        name,
+        dependency.to_toml()
+      )),
+    );
+  }
+
+  return fs::write(&path, added).map_err(|e| {
+    SamError::value(
+      codes::CONFIG_INVALID,
+      format!("Could not write {}", path.display()),
+    )
+    .caused_by(e)
+  });
+}
+
+// `text` with the line of `name` in its `[dependencies]` table replaced or
+// added, and the table too if there is none
+fn with_dependency(text: &str, name: &str, dependency: &Dependency) -> String {
+  let line = format!("{} = {}", name, dependency.to_toml());
+  let mut lines: Vec<String> = text.lines().map(|l| l.to_owned()).collect();
+
+  let Some(header) = lines.iter().position(|l| l.trim() == "[dependencies]")
+  else {
+    let mut out = text.trim_end().to_owned();
+    if !out.is_empty() {
+      out.push_str("\n\n");
+    }
+    out.push_str(&format!("[dependencies]\n{}\n", line));
+    return out;
+  };
+
+  let end = lines[header + 1..]
+    .iter()
+    .position(|l| l.trim_start().starts_with('['))
+    .map_or(lines.len(), |i| header + 1 + i);
+
+  let key = |l: &str| l.split('=').next().unwrap_or("").trim().to_owned();
+  match (header + 1..end).find(|&i| key(&lines[i]) == name) {
+    Some(i) => lines[i] = line,
+    None => {
+      // after the last entry, before the blank lines ending the table
+      let last = (header + 1..end)
+        .rev()
+        .find(|&i| !lines[i].trim().is_empty())
+        .unwrap_or(header);
+      lines.insert(last + 1, line);
+    }
+  }
+
+  return lines.join("\n") + "\n";
+}
+
+/* =========================
+sam vendor
+========================= */
+
+// copy every dependency of the project in `dir`, and theirs, into its
+// vendor directory, telling `fetched` about each; a dependency is fetched
+// once, as the first package to depend on it asks
+pub fn vendor(
+  dir: &Path,
+  mut fetched: impl FnMut(&str, &Dependency),
+) -> Result<(), SamError> {
+  let vendor = dir.join(VENDOR_DIR);
+  let config = Config::load(dir)?;
+
+  let mut queue: Vec<(String, Dependency)> =
+    config.dependencies.into_iter().collect();
+  queue.reverse();
+  let mut seen = HashSet::new();
+
+  while let Some((name, dependency)) = queue.pop() {
+    if !seen.insert(name.clone()) {
+      continue;
+    }
+
+    let target = vendor.join(&name);
+    if target.exists() {
+      fs::remove_dir_all(&target)
+        .map_err(|e| fetch_error(&name, "could not clear", &target, e))?;
+    }
+
+    // the dependencies of a path dependency are relative to where it is
+    let source = match &dependency {
+      Dependency::Path { path } => {
+        copy_dir(path, &target)
+          .map_err(|e| fetch_error(&name, "could not copy", path, e))?;
+        path.clone()
+      }
+      Dependency::Git { git, rev } => {
+        clone(git, rev.as_deref(), &target).map_err(|e| {
+          SamError::value(
+            codes::DEPENDENCY_FETCH,
+            format!("Could not fetch `{}` from {}: {}", name, git, e),
+          )
+        })?;
+        target.clone()
+      }
+    };
+    fetched(&name, &dependency);
+
+    let nested = Config::load(&source)?;
+    queue.extend(nested.dependencies.into_iter().rev());
+  }
+
+  return Ok(());
+}
+
+fn fetch_error(
+  name: &str,
+  what: &str,
+  path: &Path,
+  e: std::io::Error,
+) -> SamError {
+  return SamError::value(
+    codes::DEPENDENCY_FETCH,
+    format!("Could not fetch `{}`: {} {}", name, what, path.display()),
+  )
+  .caused_by(e);
+}
+
+// the files of `from` in `to`, but for hidden ones and vendored
+// dependencies, which are vendored next to it instead
+fn copy_dir(from: &Path, to: &Path) -> std::io::Result<()> {
+  fs::create_dir_all(to)?;
+
+  for entry in fs::read_dir(from)? {
+    let entry = entry?;
+    let name = entry.file_name();
+    if name.to_string_lossy().starts_with('.') || name == VENDOR_DIR {
+      continue;
+    }
+
+    let path = entry.path();
+    match path.is_dir() {
+      true => copy_dir(&path, &to.join(&name))?,
+      false => {
+        fs::copy(&path, to.join(&name))?;
+      }
+    }
+  }
+
+  return Ok(());
+}
+
+// the files of `url` at `rev` in `to`, without the history
+fn clone(url: &str, rev: Option<&str>, to: &Path) -> Result<(), String> {
+  let git = |args: &[&str]| -> Result<(), String> {
+    let output = Command::new("git")
+      .args(args)
+      .output()
+      .map_err(|e| format!("could not run git: {}", e))?;
+    return match output.status.success() {
+      true => Ok(()),
+      false => Err(String::from_utf8_lossy(&output.stderr).trim().to_owned()),
+    };
+  };
+
+  let to_str = to.to_string_lossy();
+  git(&["clone", "--quiet", url, &to_str])?;
+  if let Some(rev) = rev {
+    git(&["-C", &to_str, "checkout", "--quiet", rev])?;
+  }
+
+  return fs::remove_dir_all(to.join(".git")).map_err(|e| e.to_string());
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn path(p: &str) -> Dependency {
+    return Dependency::Path { path: p.into() };
+  }
+
+  #[test]
+  fn test_with_dependency() {
+    assert_eq!(
+      with_dependency("strict = true\n", "strings", &path("../strings")),
+      "strict = true\n\n[dependencies]\nstrings = { path = \"../strings\" }\n"
+    );
+
+    let text = "[dependencies]\na = { path = \"a\" }\n\n[lint]\nallow = []\n";
+    assert_eq!(
+      with_dependency(text, "b", &path("b")),
+      "[dependencies]\na = { path = \"a\" }\nb = { path = \"b\" }\n\n[lint]\n\
+       allow = []\n"
+    );
+
+    let git = Dependency::Git {
+      git: "https://example.com/a.git".to_owned(),
+      rev: Some("v1".to_owned()),
+    };
+    assert_eq!(
+      with_dependency(text, "a", &git),
+      "[dependencies]\na = { git = \"https://example.com/a.git\", rev = \
+       \"v1\" }\n\n[lint]\nallow = []\n"
+    );
+  }
+
+  #[test]
+  fn test_add_and_vendor() {
+    let dir =
+      std::env::temp_dir().join(format!("sam-manifest-{}", std::process::id()));
+    let library = dir.join("strings");
+    let project = dir.join("project");
+    fs::create_dir_all(&library).unwrap();
+    fs::create_dir_all(&project).unwrap();
+    fs::write(
+      library.join("main.sam"),
+      "let upper = (s) => { return s; };",
+    )
+    .unwrap();
+
+    add(&project, "strings", &path("../strings")).unwrap();
+    let config = Config::load(&project).unwrap();
+    assert_eq!(
+      config.dependencies.get("strings"),
+      Some(&path(&project.join("../strings").to_string_lossy()))
+    );
+
+    let mut names = Vec::new();
+    vendor(&project, |name, _| names.push(name.to_owned())).unwrap();
+    assert_eq!(names, vec!["strings"]);
+    assert!(project.join("vendor/strings/main.sam").is_file());
+
+    fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn test_parse_manifest() {
+    let config = Config::parse(
+      "[package]\nname = 'deploy'\nroot = 'src'\n\n[dependencies]\n\
+       a = { path = '../a' }\nb = { git = 'https://example.com/b.git' }",
+    )
+    .unwrap();
+    assert_eq!(config.package.name.as_deref(), Some("deploy"));
+    assert_eq!(config.dependencies.get("a"), Some(&path("../a")));
+    assert!(matches!(
+      config.dependencies.get("b"),
+      Some(Dependency::Git { rev: None, .. })
+    ));
+
+    let err = Config::parse("[dependencies]\na = { paht = '../a' }");
+    assert_eq!(err.unwrap_err().code(), Some(codes::CONFIG_INVALID));
+  }
+}
@@ -0,0 +1,327 @@
+#![allow(dead_code)]
+
+// Minifying of sam source for `sam minify`: the tokens of its syntax tree,
+// which leaves out comments, separated by a space only where two of them would
+// otherwise run together, on a single line. With `shorten`, the variables
+// the script declares get the shortest names not taken yet, the most used
+// first.
+//
+// Scoping is dynamic, so a name is renamed the same way everywhere rather
+// than per scope: a function reads the variables of whoever calls it, and
+// the two only still meet if both use the new name. As with `sam fmt`, the
+// tree of the result is compared with the original one.
+
+use crate::ast;
+use crate::codes;
+use crate::error::SamError;
+use crate::format::{ends_with_semicolon, is_statement};
+use std::collections::{HashMap, HashSet};
+use tree_sitter::Node;
+
+// keywords a short name must not be, as far as letters go
+const KEYWORDS: [&str; 15] = [
+  "as",
+  "await",
+  "break",
+  "const",
+  "continue",
+  "else",
+  "for",
+  "if",
+  "import",
+  "in",
+  "interface",
+  "let",
+  "load",
+  "return",
+  "spawn",
+];
+
+// `source` on one line, given its syntax tree, which must be free of syntax
+// errors
+pub fn minify(
+  root: Node,
+  source: &str,
+  shorten: bool,
+) -> Result<String, SamError> {
+  let names = match shorten {
+    true => short_names(root, source.as_bytes())?,
+    false => HashMap::new(),
+  };
+
+  let mut tokens = Vec::new();
+  collect(root, source.as_bytes(), &names, &mut tokens);
+
+  let mut minified = join(&tokens);
+  if !minified.is_empty() {
+    minified.push('\n');
+  }
+
+  let tree = crate::parser().parse(&minified, None).unwrap();
+  if tree.root_node().to_sexp() != root.to_sexp() {
+    return Err(SamError::runtime(
+      codes::INTERNAL_ERROR,
+      "Minifying would change the program, it was left as is",
+    ));
+  }
+
+  return Ok(minified);
+}
+
+// the tokens of `node` in order, renamed as `names` says, and with the
+// semicolons statements leave out
+fn collect(
+  node: Node,
+  source: &[u8],
+  names: &HashMap<String, String>,
+  tokens: &mut Vec<String>,
+) {
+  // strings are kept as written, escapes and all
+  if node.child_count() == 0 || matches!(node.kind(), "string" | "number") {
+    let text = node.utf8_text(source).unwrap_or_default();
+    let renamed = match is_variable(node) {
+      true => names.get(text).map(String::as_str),
+      false => None,
+    };
+    tokens.push(renamed.unwrap_or(text).to_owned());
+    return;
+  }
+
+  let mut walker = node.walk();
+  for child in node.children(&mut walker) {
+    collect(child, source, names, tokens);
+  }
+
+  if is_statement(node) && !ends_with_semicolon(node) {
+    tokens.push(";".to_owned());
+  }
+}
+
+// identifiers other than the property of `a.b`
+fn is_variable(node: Node) -> bool {
+  if node.kind() != "identifier" {
+    return false;
+  }
+
+  let Some(parent) = node.parent() else {
+    return true;
+  };
+
+  return parent.kind() != "nested_identifier"
+    || parent.child_by_field_name("name") != Some(node);
+}
+
+fn join(tokens: &[String]) -> String {
+  let mut out = String::new();
+
+  for token in tokens {
+    if needs_space(&out, token) {
+      out.push(' ');
+    }
+    out.push_str(token);
+  }
+
+  return out;
+}
+
+// whether `next` written right after `prev` would read as other tokens,
+// e.g. `let a` as `leta`, `a - -1` as `a--1`, or `a / /x/` as a comment
+fn needs_space(prev: &str, next: &str) -> bool {
+  let (Some(p), Some(n)) = (prev.chars().last(), next.chars().next()) else {
+    return false;
+  };
+
+  let word = |c: char| c.is_alphanumeric() || c == '_';
+  let operator = |c: char| "+-*/%<>=!&|".contains(c);
+
+  return (word(p) && word(n))
+    || (operator(p) && operator(n))
+    || (p.is_ascii_digit() && n == '.');
+}
+
+/* =========================
+Renaming
+========================= */
+
+// a short name for every variable declared in the script, the most used
+// getting the shortest
+fn short_names(
+  root: Node,
+  source: &[u8],
+) -> Result<HashMap<String, String>, SamError> {
+  let mut declared = HashSet::new();
+  let mut uses: HashMap<String, usize> = HashMap::new();
+  scan(root, source, &mut declared, &mut uses)?;
+
+  // commands, builtins, and the names of the host stay as they are, so no
+  // short name may be one of them
+  let mut taken: HashSet<String> = uses
+    .keys()
+    .filter(|name| !declared.contains(*name))
+    .cloned()
+    .collect();
+  taken.extend(KEYWORDS.iter().map(|k| k.to_string()));
+
+  let mut order: Vec<&String> = declared.iter().collect();
+  order.sort_by(|a, b| uses[*b].cmp(&uses[*a]).then(a.cmp(b)));
+
+  let mut names = HashMap::new();
+  let mut next = 0;
+  for name in order {
+    let short = loop {
+      let candidate = short_name(next);
+      next += 1;
+      if !taken.contains(&candidate) {
+        break candidate;
+      }
+    };
+    names.insert(name.clone(), short);
+  }
+
+  return Ok(names);
+}
+
+// the names of the variables `node` declares, and how often each variable
+// appears in it
+fn scan(
+  node: Node,
+  source: &[u8],
+  declared: &mut HashSet<String>,
+  uses: &mut HashMap<String, usize>,
+) -> Result<(), SamError> {
+  let text = |n: Node| n.utf8_text(source).unwrap_or_default().to_owned();
+
+  if node.kind() == "interfaces" || ast::import_call(node, source).is_some() {
+    return Err(
+      SamError::name(
+        codes::NOT_SHORTENABLE,
+        "Names cannot be shortened next to interfaces or imports",
+      )
+      .at(node)
+      .with_help("minify the script without `--shorten`"),
+    );
+  }
+
+  match node.kind() {
+    "variable_declaration" => {
+      let mut walker = node.walk();
+      for declarator in node.named_children(&mut walker) {
+        if let Some(variable) = declarator.child_by_field_name("variable") {
+          declared.insert(text(variable));
+        }
+      }
+    }
+
+    "for_expression" => {
+      if let Some(variable) = node.child_by_field_name("variable") {
+        declared.insert(text(variable));
+      }
+    }
+
+    "lambda_expression" => {
+      if let Some(params) = node.child_by_field_name("parameters") {
+        let mut walker = params.walk();
+        for param in params.named_children(&mut walker) {
+          if param.kind() == "identifier" {
+            declared.insert(text(param));
+          }
+        }
+      }
+    }
+
+    _ => {
+      if is_variable(node) {
+        *uses.entry(text(node)).or_default() += 1;
+      }
+    }
+  }
+
+  let mut walker = node.walk();
+  for child in node.children(&mut walker) {
+    scan(child, source, declared, uses)?;
+  }
+
+  return Ok(());
+}
+
+// the `n`th of `a` to `z`, then `aa` to `zz`, and so on
+fn short_name(n: usize) -> String {
+  let mut name = Vec::new();
+  let mut n = n + 1;
+
+  while n > 0 {
+    n -= 1;
+    name.push(b'a' + (n % 26) as u8);
+    n /= 26;
+  }
+
+  name.reverse();
+  return String::from_utf8(name).unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn minified(source: &str, shorten: bool) -> Result<String, SamError> {
+    let tree = crate::parser().parse(source, None).unwrap();
+    return minify(tree.root_node(), source, shorten);
+  }
+
+  #[test]
+  fn test_short_name() {
+    assert_eq!(short_name(0), "a");
+    assert_eq!(short_name(25), "z");
+    assert_eq!(short_name(26), "aa");
+    assert_eq!(short_name(27), "ab");
+    assert_eq!(short_name(26 + 26 * 26), "aaa");
+  }
+
+  #[test]
+  fn test_join() {
+    let tokens: Vec<String> = "let a = 1 - - 2 ; f ( a ) / / 3 ;"
+      .split(' ')
+      .map(str::to_owned)
+      .collect();
+    assert_eq!(join(&tokens), "let a=1- -2;f(a)/ /3;");
+  }
+
+  #[test]
+  fn test_minify() {
+    let source = "
+      // totals of the scores
+      let total = 0;
+      let add = (score) => {
+        total = total + score;  // dynamic, so `total` is the caller's
+      };
+      for score in [1, 2] { add(score) }
+      echo(total.size, 'a  b')
+    ";
+
+    assert_eq!(
+      minified(source, false).unwrap(),
+      "let total=0;let add=(score)=>{total=total+score;};\
+       for score in[1,2]{add(score);};echo(total.size,'a  b');\n"
+    );
+    assert_eq!(
+      minified(source, true).unwrap(),
+      "let b=0;let c=(a)=>{b=b+a;};\
+       for a in[1,2]{c(a);};echo(b.size,'a  b');\n"
+    );
+  }
+
+  #[test]
+  fn test_interfaces_not_shortened() {
+    let sources = [
+      "interface 'tools.json' load tool; tool.run();",
+      "import('tools'); run();",
+    ];
+
+    for source in sources {
+      assert!(minified(source, false).is_ok());
+
+      let err = minified(source, true).unwrap_err();
+      assert_eq!(err.code(), Some(codes::NOT_SHORTENABLE));
+    }
+  }
+}
@@ -0,0 +1,327 @@
+#![allow(dead_code)]
+
+// `import('util/paths');` runs the module util/paths.sam and defines its
+// top level names where the import is, as `interface` does with the entries
+// of an interface file. Functions see the names of the scope they are called
+// in, so the helpers of a module have to be defined beside the functions
+// that use them, underscored ones included.
+//
+// A path starting with `./` or `../` is relative to the importing script.
+// One starting with the name of a dependency is a module of that package,
+// its entry point if it is the name alone. Any other is looked up in the
+// source root of the project, then next to the importing script. Every
+// module runs once per run, with the settings of whoever imported it first
+// but the dependencies of its own package.
+
+use crate::ast::Import;
+use crate::codes;
+use crate::config::Config;
+use crate::context::Context;
+use crate::error::SamError;
+use crate::evaluate::evaluate_source;
+use crate::manifest::{self, DEFAULT_ENTRY};
+use crate::value::Value;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+pub const EXTENSION: &str = "sam";
+
+// the top level names of a module once it ran
+type Names = Arc<Vec<(String, Value)>>;
+
+enum State {
+  // still running, so importing it again is a cycle
+  Loading,
+  Loaded(Names),
+}
+
+// shared by every copy of the config, and so by the modules of a run
+#[derive(Clone, Default)]
+pub struct Modules(Arc<Mutex<HashMap<PathBuf, State>>>);
+
+impl std::fmt::Debug for Modules {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "Modules")
+  }
+}
+
+// a module found, and the settings it runs with
+#[derive(Debug)]
+pub struct Resolved {
+  pub path: PathBuf,
+  pub config: Config,
+}
+
+// define the names of the module `import` names in the current scope
+pub fn import(import: &Import, ctx: &mut Context) -> Result<(), SamError> {
+  let resolved =
+    resolve(&import.path, &ctx.config).map_err(|e| e.at(import.path_span))?;
+  ctx
+    .config
+    .permissions
+    .check_read(&resolved.path)
+    .map_err(|e| e.at(import.span))?;
+
+  let names = load(resolved).map_err(|e| e.at(import.span))?;
+
+  let scope = ctx.current_scope();
+  for (name, value) in names.iter() {
+    scope.insert(name.as_str(), value.clone());
+  }
+
+  return Ok(());
+}
+
+// where the module `spec` imported with `config` is
+pub fn resolve(spec: &str, config: &Config) -> Result<Resolved, SamError> {
+  let from = config
+    .module_dir
+    .clone()
+    .unwrap_or_else(|| config.working_dir());
+
+  let mut candidates = Vec::new();
+  let mut module_config = config.clone();
+
+  let (first, rest) = spec.split_once('/').unwrap_or((spec, ""));
+  match config.dependencies.get(first) {
+    _ if spec.starts_with("./") || spec.starts_with("../") => {
+      candidates.push(with_extension(from.join(spec)));
+    }
+
+    Some(dependency) => {
+      let dir =
+        manifest::dependency_dir(config.project.as_deref(), first, dependency)?;
+      let package = Config::load(&dir)?;
+
+      // vendored dependencies of dependencies are still in the vendor
+      // directory of the project
+      module_config.package = package.package;
+      module_config.dependencies = package.dependencies;
+
+      let root = module_config.package.root.clone().unwrap_or(dir.clone());
+      candidates.push(match rest.is_empty() {
+        true => module_config
+          .package
+          .entry
+          .clone()
+          .unwrap_or(root.join(DEFAULT_ENTRY)),
+        false => with_extension(root.join(rest)),
+      });
+    }
+
+    None => {
+      let root = config.package.root.as_ref().or(config.project.as_ref());
+      if let Some(root) = root {
+        candidates.push(with_extension(root.join(spec)));
+      }
+      candidates.push(with_extension(from.join(spec)));
+    }
+  }
+
+  let Some(path) = candidates.iter().find(|c| c.is_file()) else {
+    let tried: Vec<String> =
+      candidates.iter().map(|c| c.display().to_string()).collect();
+
+    return Err(
+      SamError::value(
+        codes::MODULE_NOT_FOUND,
+        format!("Module {} not found", spec),
+      )
+      .with_help(format!("looked for {}", tried.join(", "))),
+    );
+  };
+
+  module_config.module_dir = path.parent().map(|dir| dir.to_path_buf());
+
+  return Ok(Resolved {
+    path: path.clone(),
+    config: module_config,
+  });
+}
+
+fn with_extension(mut path: PathBuf) -> PathBuf {
+  if path.extension().is_none_or(|e| e != EXTENSION) {
+    path.as_mut_os_string().push(format!(".{}", EXTENSION));
+  }
+
+  return path;
+}
+
+// the names of a module, run unless it already was
+fn load(resolved: Resolved) -> Result<Names, SamError> {
+  let key = resolved
+    .path
+    .canonicalize()
+    .unwrap_or(resolved.path.clone());
+  let modules = resolved.config.modules.clone();
+
+  {
+    let mut modules = modules.0.lock().unwrap();
+    match modules.get(&key) {
+      Some(State::Loaded(names)) => return Ok(names.clone()),
+      Some(State::Loading) => {
+        return Err(SamError::runtime(
+          codes::IMPORT_CYCLE,
+          format!("Module {} imports itself", resolved.path.display()),
+        ));
+      }
+      None => {}
+    }
+    modules.insert(key.clone(), State::Loading);
+  }
+  let result = run(&resolved.path, resolved.config);
+
+  let mut modules = modules.0.lock().unwrap();
+  match &result {
+    Ok(names) => modules.insert(key, State::Loaded(names.clone())),
+    Err(_) => modules.remove(&key),
+  };
+
+  return result;
+}
+
+fn run(path: &Path, mut config: Config) -> Result<Names, SamError> {
+  let source = fs::read_to_string(path).map_err(|e| {
+    SamError::value(
+      codes::MODULE_NOT_FOUND,
+      format!("Could not read module {}", path.display()),
+    )
+    .caused_by(e)
+  })?;
+
+  // both report against the source of the script, not of the module
+  config.tracer = None;
+  config.coverage = None;
+
+  let tree = crate::parser().parse(&source, None).unwrap();
+  let mut ctx = Context::with_config(config);
+
+  // the error is in another file, its location goes in the message
+  evaluate_source(&tree.root_node(), source.as_bytes(), &mut ctx, 0).map_err(
+    |e| {
+      let location = match e.span() {
+        Some(span) => format!(
+          "{}:{}:{}",
+          path.display(),
+          span.start_point.row + 1,
+          span.start_point.column + 1
+        ),
+        None => path.display().to_string(),
+      };
+      match e.code() {
+        // the module that imports itself is named by the message already
+        Some(codes::IMPORT_CYCLE) => {
+          SamError::runtime(codes::IMPORT_CYCLE, e.message())
+        }
+        // a module that exits ends the script importing it
+        Some(codes::SCRIPT_EXIT) => e,
+        _ => SamError::runtime(
+          codes::MODULE_FAILED,
+          format!("Module failed to load: {}: {}", location, e.message()),
+        ),
+      }
+    },
+  )?;
+
+  let names = ctx
+    .global_scope()
+    .iter()
+    .map(|(name, value)| (name.to_owned(), value.clone()))
+    .collect();
+
+  return Ok(Arc::new(names));
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  // a project with a source root and a path dependency
+  fn project(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!(
+      "sam-modules-{}-{}",
+      name,
+      std::process::id()
+    ));
+    let files = [
+      (
+        "app/sam.toml",
+        "[package]\nroot = 'src'\n\n[dependencies]\nlib = { path = '../lib' }",
+      ),
+      ("app/src/util/paths.sam", ""),
+      ("app/src/main.sam", ""),
+      ("app/helpers.sam", ""),
+      ("lib/main.sam", ""),
+      ("lib/strings.sam", ""),
+    ];
+    for (file, text) in files {
+      let path = dir.join(file);
+      fs::create_dir_all(path.parent().unwrap()).unwrap();
+      fs::write(path, text).unwrap();
+    }
+
+    return dir;
+  }
+
+  #[test]
+  fn test_resolve() {
+    let dir = project("resolve");
+    let app = dir.join("app");
+    let mut config = Config::load(&app).unwrap();
+    config.module_dir = Some(app.clone());
+
+    let found = |spec: &str| {
+      let path = resolve(spec, &config).unwrap().path;
+      return path.canonicalize().unwrap();
+    };
+    let at = |file: &str| dir.join(file).canonicalize().unwrap();
+
+    assert_eq!(found("util/paths"), at("app/src/util/paths.sam"));
+    assert_eq!(found("util/paths.sam"), at("app/src/util/paths.sam"));
+    assert_eq!(found("./helpers"), at("app/helpers.sam"));
+    assert_eq!(found("helpers"), at("app/helpers.sam"));
+    assert_eq!(found("lib"), at("lib/main.sam"));
+    assert_eq!(found("lib/strings"), at("lib/strings.sam"));
+
+    let err = resolve("lib/missing", &config).unwrap_err();
+    assert_eq!(err.code(), Some(codes::MODULE_NOT_FOUND));
+
+    // modules of the dependency run with its own dependencies
+    let resolved = resolve("lib", &config).unwrap();
+    assert!(resolved.config.dependencies.is_empty());
+
+    fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn test_import() {
+    let dir = project("import");
+    let app = dir.join("app");
+    fs::write(
+      app.join("src/util/paths.sam"),
+      "let _sep = '/';\nlet join = (a, b) => { return a + _sep + b; };",
+    )
+    .unwrap();
+    fs::write(app.join("src/cycle.sam"), "import('cycle');").unwrap();
+
+    let mut config = Config::load(&app).unwrap();
+    config.module_dir = Some(app.clone());
+    let mut ctx = Context::with_config(config);
+
+    let source = "import('util/paths');\nlet p = join('a', 'b');";
+    let tree = crate::parser().parse(source, None).unwrap();
+    evaluate_source(&tree.root_node(), source.as_bytes(), &mut ctx, 0).unwrap();
+    assert_eq!(ctx.get_global("p"), Some(&Value::from("a/b")));
+
+    let source = "import('cycle');";
+    let tree = crate::parser().parse(source, None).unwrap();
+    let err =
+      evaluate_source(&tree.root_node(), source.as_bytes(), &mut ctx, 0)
+        .unwrap_err();
+    assert_eq!(err.code(), Some(codes::IMPORT_CYCLE));
+
+    fs::remove_dir_all(&dir).unwrap();
+  }
+}
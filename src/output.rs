@@ -0,0 +1,167 @@
+#![allow(dead_code)]
+
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Write};
+use std::sync::{Arc, Mutex};
+
+// somewhere the interpreter writes text to, shared by everything holding a
+// clone. Embedders pass their own to capture or redirect what would go to
+// the terminal.
+#[derive(Clone)]
+pub struct Writer(Arc<Mutex<dyn Write + Send>>);
+
+impl Writer {
+  pub fn new(w: impl Write + Send + 'static) -> Writer {
+    return Writer(Arc::new(Mutex::new(w)));
+  }
+
+  pub fn stdout() -> Writer {
+    return Writer::new(io::stdout());
+  }
+
+  pub fn stderr() -> Writer {
+    return Writer::new(io::stderr());
+  }
+
+  // `text` and a newline, flushed so it shows up in order with the output of
+  // commands the script runs. Failing to write is ignored, like a closed
+  // terminal.
+  pub fn write_line(&self, text: &str) {
+    self.write(format!("{}\n", text).as_bytes());
+  }
+
+  pub fn write(&self, bytes: &[u8]) {
+    // still usable after a panic elsewhere while it was locked
+    let mut w = self.0.lock().unwrap_or_else(|e| e.into_inner());
+    let _ = w.write_all(bytes).and_then(|_| w.flush());
+  }
+}
+
+impl fmt::Debug for Writer {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "Writer")
+  }
+}
+
+// somewhere the interpreter reads lines from, shared like `Writer`
+#[derive(Clone)]
+pub struct Reader(Arc<Mutex<dyn BufRead + Send>>);
+
+impl Reader {
+  pub fn new(r: impl BufRead + Send + 'static) -> Reader {
+    return Reader(Arc::new(Mutex::new(r)));
+  }
+
+  pub fn stdin() -> Reader {
+    return Reader::new(BufReader::new(io::stdin()));
+  }
+
+  // nothing to read, e.g. for a process whose stdin is not the user's
+  pub fn empty() -> Reader {
+    return Reader::new(io::empty());
+  }
+
+  // the next line without its line ending, none at the end of the input
+  pub fn read_line(&self) -> io::Result<Option<String>> {
+    let mut r = self.0.lock().unwrap_or_else(|e| e.into_inner());
+    let mut line = String::new();
+    if r.read_line(&mut line)? == 0 {
+      return Ok(None);
+    }
+
+    if line.ends_with('\n') {
+      line.pop();
+      if line.ends_with('\r') {
+        line.pop();
+      }
+    }
+
+    return Ok(Some(line));
+  }
+}
+
+impl fmt::Debug for Reader {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "Reader")
+  }
+}
+
+// where script input comes from and where its output and diagnostics go,
+// the process's own stdin, stdout, and stderr unless set otherwise
+#[derive(Debug, Clone)]
+pub struct Streams {
+  // lines read by the script
+  pub stdin: Reader,
+  // values printed by the script and dumps asked for by the user
+  pub stdout: Writer,
+  // diagnostics and traces
+  pub stderr: Writer,
+}
+
+impl Default for Streams {
+  fn default() -> Streams {
+    return Streams {
+      stdin: Reader::stdin(),
+      stdout: Writer::stdout(),
+      stderr: Writer::stderr(),
+    };
+  }
+}
+
+// an in-memory writer whose clones share what was written, e.g. to capture
+// script output in tests or to show it in a GUI:
+//
+//   let out = Captured::default();
+//   config.output.stdout = Writer::new(out.clone());
+//   ...
+//   out.contents()
+#[derive(Debug, Clone, Default)]
+pub struct Captured(Arc<Mutex<Vec<u8>>>);
+
+impl Captured {
+  // everything written so far, invalid UTF-8 replaced
+  pub fn contents(&self) -> String {
+    let bytes = self.0.lock().unwrap_or_else(|e| e.into_inner());
+    return String::from_utf8_lossy(&bytes).into_owned();
+  }
+}
+
+impl Write for Captured {
+  fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    let mut bytes = self.0.lock().unwrap_or_else(|e| e.into_inner());
+    bytes.extend_from_slice(buf);
+    return Ok(buf.len());
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    return Ok(());
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_captured_writer() {
+    let out = Captured::default();
+    let writer = Writer::new(out.clone());
+
+    writer.write_line("one");
+    writer.clone().write(b"two");
+
+    assert_eq!(out.contents(), "one\ntwo");
+  }
+
+  #[test]
+  fn test_reader_lines() {
+    let reader = Reader::new(io::Cursor::new("one\r\ntwo\n\nthree"));
+
+    let mut lines = Vec::new();
+    while let Some(line) = reader.read_line().unwrap() {
+      lines.push(line);
+    }
+
+    assert_eq!(lines, ["one", "two", "", "three"]);
+  }
+}
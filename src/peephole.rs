@@ -0,0 +1,264 @@
+#![allow(dead_code)]
+
+// The peephole pass: rewrites short sequences of compiled instructions into
+// fewer ones before the vm runs them. A constant operand is fused into its
+// operation, a branch on a constant becomes a jump or nothing, and jumps go
+// straight to where a chain of jumps ends. `sam dis` shows the
+// bytecode before and after.
+
+use crate::bytecode::{Chunk, Op};
+use crate::evaluate::condition;
+use std::collections::HashSet;
+
+pub fn optimize(chunk: &mut Chunk) {
+  prune_branches(chunk);
+  fuse_constants(chunk);
+  thread_jumps(chunk);
+  skip_jumps_to_next(chunk);
+}
+
+// `constant; branch` is decided before running, unless the constant is not a
+// condition, which fails when it runs
+fn prune_branches(chunk: &mut Chunk) {
+  let targets = targets(&chunk.code);
+  let mut dropped = vec![false; chunk.code.len()];
+
+  for at in 1..chunk.code.len() {
+    let (Op::Constant(n), Op::Branch(target)) =
+      (chunk.code[at - 1], chunk.code[at])
+    else {
+      continue;
+    };
+
+    // a jump to the branch has its condition elsewhere
+    if targets.contains(&at) {
+      continue;
+    }
+
+    let Ok(taken) = condition(chunk.constants[n].clone(), chunk.spans[at])
+    else {
+      continue;
+    };
+
+    dropped[at - 1] = true;
+    if taken {
+      dropped[at] = true;
+    } else {
+      chunk.code[at] = Op::Jump(target);
+    }
+  }
+
+  compact(chunk, &dropped);
+}
+
+// `constant; binary` becomes one instruction that takes its right operand
+// from the constants rather than the stack
+fn fuse_constants(chunk: &mut Chunk) {
+  let targets = targets(&chunk.code);
+  let mut dropped = vec![false; chunk.code.len()];
+
+  for at in 1..chunk.code.len() {
+    let (Op::Constant(constant), Op::Binary(op)) =
+      (chunk.code[at - 1], chunk.code[at])
+    else {
+      continue;
+    };
+
+    // a jump to the operation has its right operand elsewhere
+    if targets.contains(&at) {
+      continue;
+    }
+
+    chunk.code[at] = Op::BinaryConstant { op, constant };
+    dropped[at - 1] = true;
+  }
+
+  compact(chunk, &dropped);
+}
+
+// a jump to a jump continues where the second one goes
+fn thread_jumps(chunk: &mut Chunk) {
+  for at in 0..chunk.code.len() {
+    let Some(target) = chunk.code[at].target() else {
+      continue;
+    };
+
+    let end = follow(&chunk.code, target);
+    *chunk.code[at].target_mut().unwrap() = end;
+  }
+}
+
+fn follow(code: &[Op], mut target: usize) -> usize {
+  // bounded, in case jumps go around in a circle
+  for _ in 0..code.len() {
+    match code.get(target) {
+      Some(Op::Jump(next)) => target = *next,
+      _ => break,
+    }
+  }
+
+  return target;
+}
+
+// a jump to the instruction after it does nothing
+fn skip_jumps_to_next(chunk: &mut Chunk) {
+  let dropped: Vec<bool> = (0..chunk.code.len())
+    .map(|at| chunk.code[at] == Op::Jump(at + 1))
+    .collect();
+
+  compact(chunk, &dropped);
+}
+
+// instructions some jump continues at
+fn targets(code: &[Op]) -> HashSet<usize> {
+  return code.iter().filter_map(Op::target).collect();
+}
+
+// remove the dropped instructions, moving jumps along with the instructions
+// they point at
+fn compact(chunk: &mut Chunk, dropped: &[bool]) {
+  if !dropped.contains(&true) {
+    return;
+  }
+
+  // where each instruction ends up, a dropped one where the next kept one
+  // does; the last entry is the end of the code
+  let mut moved = Vec::with_capacity(dropped.len() + 1);
+  let mut kept = 0;
+  for &d in dropped {
+    moved.push(kept);
+    if !d {
+      kept += 1;
+    }
+  }
+  moved.push(kept);
+
+  let mut at = 0;
+  chunk.code.retain(|_| {
+    at += 1;
+    !dropped[at - 1]
+  });
+
+  let mut at = 0;
+  chunk.spans.retain(|_| {
+    at += 1;
+    !dropped[at - 1]
+  });
+
+  for op in &mut chunk.code {
+    if let Some(target) = op.target_mut() {
+      *target = moved[*target];
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::ast::BinaryOp;
+  use crate::error::Span;
+  use crate::value::Value;
+  use tree_sitter::Point;
+
+  fn chunk(code: Vec<Op>, constants: Vec<Value>) -> Chunk {
+    let span = Span {
+      start: 0,
+      end: 0,
+      start_point: Point::default(),
+      end_point: Point::default(),
+    };
+
+    return Chunk {
+      spans: vec![span; code.len()],
+      code,
+      constants,
+      ..Chunk::default()
+    };
+  }
+
+  #[test]
+  fn test_fuse_constants() {
+    // x + 1
+    let mut c = chunk(
+      vec![
+        Op::Load(0),
+        Op::Constant(0),
+        Op::Binary(BinaryOp::Add),
+        Op::Result,
+      ],
+      vec![Value::from(1i64)],
+    );
+    optimize(&mut c);
+
+    assert_eq!(
+      c.code,
+      vec![
+        Op::Load(0),
+        Op::BinaryConstant {
+          op: BinaryOp::Add,
+          constant: 0
+        },
+        Op::Result,
+      ]
+    );
+    assert_eq!(c.spans.len(), c.code.len());
+  }
+
+  #[test]
+  fn test_prune_branches() {
+    // if 0 { a } else { b }
+    let mut c = chunk(
+      vec![
+        Op::Constant(0),
+        Op::Branch(5),
+        Op::Load(0),
+        Op::Pop,
+        Op::Jump(7),
+        Op::Load(1),
+        Op::Pop,
+        Op::Undefined,
+      ],
+      vec![Value::from(0i64)],
+    );
+    optimize(&mut c);
+
+    assert_eq!(c.code[0], Op::Jump(4));
+    assert_eq!(c.code[3], Op::Jump(6));
+    assert_eq!(c.code[6], Op::Undefined);
+
+    // if 1 { a }, and a condition that fails is kept
+    let mut c = chunk(
+      vec![Op::Constant(0), Op::Branch(4), Op::Load(0), Op::Pop],
+      vec![Value::from(1i64)],
+    );
+    optimize(&mut c);
+    assert_eq!(c.code, vec![Op::Load(0), Op::Pop]);
+
+    let mut c = chunk(
+      vec![Op::Constant(0), Op::Branch(2), Op::Undefined],
+      vec![Value::from("yes")],
+    );
+    optimize(&mut c);
+    assert_eq!(c.code[1], Op::Branch(2));
+  }
+
+  #[test]
+  fn test_thread_jumps() {
+    let mut c = chunk(
+      vec![
+        Op::Branch(3),
+        Op::Undefined,
+        Op::Jump(5),
+        Op::Jump(2),
+        Op::Undefined,
+        Op::Result,
+      ],
+      vec![],
+    );
+    optimize(&mut c);
+
+    assert_eq!(c.code[0], Op::Branch(5));
+    assert_eq!(c.code[2], Op::Jump(5));
+    assert_eq!(c.code[3], Op::Jump(5));
+  }
+}
@@ -0,0 +1,246 @@
+#![allow(dead_code)]
+
+use crate::codes;
+use crate::error::SamError;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+// what a script may do outside of the interpreter. Everything is allowed
+// unless the command line asks for a sandbox, so that untrusted scripts can
+// be run with only the access they need.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Permissions {
+  // programs started by shell calls and foreign functions, and shared
+  // libraries loaded by interfaces
+  pub run: Allow,
+  // files read, e.g. interface files
+  pub read: Allow,
+  // hosts contacted by HTTP interface entries and `http_get` and
+  // `http_post`
+  pub net: Allow,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum Allow {
+  #[default]
+  Everything,
+  Nothing,
+  // program names, path prefixes, or hosts
+  Only(Vec<String>),
+}
+
+impl Allow {
+  // from a command line flag: absent is nothing, `--allow-x` is everything,
+  // `--allow-x=a,b` is just those
+  pub fn from_flag(flag: &Option<Vec<String>>) -> Allow {
+    match flag {
+      None => Allow::Nothing,
+      Some(list) if list.is_empty() => Allow::Everything,
+      Some(list) => Allow::Only(list.clone()),
+    }
+  }
+
+  fn permits(&self, matches: impl Fn(&str) -> bool) -> bool {
+    match self {
+      Allow::Everything => true,
+      Allow::Nothing => false,
+      Allow::Only(list) => list.iter().any(|item| matches(item)),
+    }
+  }
+}
+
+impl Permissions {
+  // a program is allowed by its name or by its full path
+  pub fn check_run(&self, program: &str) -> Result<(), SamError> {
+    let name = Path::new(program)
+      .file_name()
+      .and_then(|n| n.to_str())
+      .unwrap_or(program);
+
+    if self
+      .run
+      .permits(|p| same_program(p, program) || same_program(p, name))
+    {
+      return Ok(());
+    }
+
+    return Err(denied(
+      format!("Running `{}` is not allowed", program),
+      format!("--allow-run={}", name),
+    ));
+  }
+
+  // variables set for external calls by sam.toml or an interface file, e.g.
+  // PATH, LD_PRELOAD, or GIT_SSH_COMMAND, can make an allowed program run
+  // another, so only a script allowed to run everything may set them
+  pub fn check_env(
+    &self,
+    env: &HashMap<String, String>,
+  ) -> Result<(), SamError> {
+    if env.is_empty() || self.run == Allow::Everything {
+      return Ok(());
+    }
+
+    let mut names: Vec<&str> = env.keys().map(String::as_str).collect();
+    names.sort();
+
+    return Err(denied(
+      format!(
+        "Setting {} for external calls is not allowed",
+        names.join(", ")
+      ),
+      "--allow-run".to_owned(),
+    ));
+  }
+
+  // a file is allowed if it is inside one of the allowed paths
+  pub fn check_read(&self, path: &Path) -> Result<(), SamError> {
+    let path = absolute(path);
+
+    if self
+      .read
+      .permits(|p| path.starts_with(absolute(Path::new(p))))
+    {
+      return Ok(());
+    }
+
+    return Err(denied(
+      format!("Reading {} is not allowed", path.display()),
+      format!("--allow-read={}", path.display()),
+    ));
+  }
+
+  // a url is allowed by its host, with or without the port
+  pub fn check_net(&self, url: &str) -> Result<(), SamError> {
+    let authority = url
+      .split_once("://")
+      .map_or(url, |(_, rest)| rest)
+      .split(['/', '?', '#'])
+      .next()
+      .unwrap_or("");
+    let authority = authority.rsplit('@').next().unwrap_or(authority);
+    let host = match authority.rsplit_once(':') {
+      // not the colons of an IPv6 address
+      Some((host, port)) if !port.contains(']') => host,
+      _ => authority,
+    };
+
+    if self.net.permits(|h| h == authority || h == host) {
+      return Ok(());
+    }
+
+    return Err(denied(
+      format!("Connecting to {} is not allowed", authority),
+      format!("--allow-net={}", host),
+    ));
+  }
+}
+
+// Windows file names ignore case, and `git` also names `git.exe`
+#[cfg(windows)]
+fn same_program(allowed: &str, program: &str) -> bool {
+  let program = program.to_lowercase();
+  let program = program.strip_suffix(".exe").unwrap_or(&program);
+  return allowed.to_lowercase() == program;
+}
+
+#[cfg(not(windows))]
+fn same_program(allowed: &str, program: &str) -> bool {
+  return allowed == program;
+}
+
+fn denied(message: String, flag: String) -> SamError {
+  return SamError::ffi(codes::PERMISSION_DENIED, message)
+    .with_help(format!("run with {} to allow it", flag));
+}
+
+// resolve `..` and symlinks where the file exists, so a path cannot escape
+// an allowed directory
+fn absolute(path: &Path) -> PathBuf {
+  return path
+    .canonicalize()
+    .or_else(|_| std::path::absolute(path))
+    .unwrap_or_else(|_| path.to_path_buf());
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn only(items: &[&str]) -> Allow {
+    return Allow::Only(items.iter().map(|s| s.to_string()).collect());
+  }
+
+  #[test]
+  fn test_default_allows_everything() {
+    let permissions = Permissions::default();
+
+    assert!(permissions.check_run("rm").is_ok());
+    assert!(permissions.check_read(Path::new("/etc/passwd")).is_ok());
+    assert!(permissions.check_net("http://example.com").is_ok());
+  }
+
+  #[test]
+  fn test_check_run() {
+    let permissions = Permissions {
+      run: only(&["git"]),
+      ..Permissions::default()
+    };
+
+    assert!(permissions.check_run("git").is_ok());
+    assert!(permissions.check_run("/usr/bin/git").is_ok());
+
+    let err = permissions.check_run("curl").unwrap_err();
+    assert_eq!(err.code(), Some(codes::PERMISSION_DENIED));
+    assert_eq!(err.help(), Some("run with --allow-run=curl to allow it"));
+  }
+
+  #[test]
+  fn test_check_env() {
+    let env = HashMap::from([("LD_PRELOAD".to_owned(), "x.so".to_owned())]);
+    assert!(Permissions::default().check_env(&env).is_ok());
+
+    let permissions = Permissions {
+      run: only(&["git"]),
+      ..Permissions::default()
+    };
+    assert!(permissions.check_env(&HashMap::new()).is_ok());
+
+    let err = permissions.check_env(&env).unwrap_err();
+    assert_eq!(err.code(), Some(codes::PERMISSION_DENIED));
+    assert_eq!(err.help(), Some("run with --allow-run to allow it"));
+  }
+
+  #[test]
+  fn test_check_read() {
+    let dir = std::env::temp_dir();
+    let permissions = Permissions {
+      read: Allow::Only(vec![dir.display().to_string()]),
+      ..Permissions::default()
+    };
+
+    assert!(permissions.check_read(&dir.join("tools.json")).is_ok());
+    assert!(permissions.check_read(&dir.join("../etc/passwd")).is_err());
+  }
+
+  #[test]
+  fn test_check_net() {
+    let permissions = Permissions {
+      net: only(&["localhost", "api.example.com:443"]),
+      ..Permissions::default()
+    };
+
+    assert!(
+      permissions
+        .check_net("http://localhost:8080/predict")
+        .is_ok()
+    );
+    assert!(
+      permissions
+        .check_net("https://api.example.com:443/")
+        .is_ok()
+    );
+    assert!(permissions.check_net("https://api.example.com/").is_err());
+    assert!(permissions.check_net("http://localhost.evil.com/").is_err());
+  }
+}
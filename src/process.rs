@@ -0,0 +1,784 @@
+#![allow(dead_code)]
+
+use crate::interrupt::InterruptHandle;
+use crate::permissions::Permissions;
+use crate::trace::{Started, Trace};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Lines, PipeReader, Read, Write};
+use std::path::PathBuf;
+use std::process::{
+  Child, ChildStdin, ChildStdout, Command, ExitStatus, Stdio,
+};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+#[cfg(not(target_arch = "wasm32"))]
+use wait_timeout::ChildExt;
+
+// options shared by every external call, foreign functions and shell
+// commands alike
+#[derive(Debug, Clone, Default)]
+pub struct CallOptions {
+  // kill the child if it runs longer than this
+  pub timeout: Option<Duration>,
+  // variables set for the child, on top of the inherited environment
+  pub env: HashMap<String, String>,
+  // start the child with only the variables in `env`
+  pub clear_env: bool,
+  // what a foreign function exiting unsuccessfully evaluates to
+  pub exit_status: ExitPolicy,
+  // what the script may run, checked before anything is started
+  pub permissions: Permissions,
+  // directory the child runs in, ours if unset
+  pub cwd: Option<PathBuf>,
+  // where the child's stderr goes
+  pub stderr: StderrMode,
+  // told about every command once it ended, e.g. by `--trace-ffi`
+  pub trace: Option<Trace>,
+  // kills the child once triggered, see `Interpreter::interrupt_handle`
+  pub interrupt: InterruptHandle,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StderrMode {
+  // collected apart from stdout, e.g. for error messages
+  #[default]
+  Capture,
+  // written to our own stderr as it comes, e.g. progress bars
+  Inherit,
+  // mixed into stdout, in the order it was written
+  Merge,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExitPolicy {
+  // raise an error carrying the command's stderr
+  #[default]
+  Error,
+  // return `{ok, status, stdout, stderr}` from every call, failed or not
+  Result,
+}
+
+pub struct Output {
+  pub status: ExitStatus,
+  pub stdout: Vec<u8>,
+  pub stderr: Vec<u8>,
+}
+
+#[derive(Debug)]
+pub enum RunError {
+  // the program could not be started
+  Spawn(io::Error),
+  // waiting for or talking to the child failed
+  Io(io::Error),
+  // the child was killed after running for this long
+  Timeout(Duration),
+  // the child was killed because the script was interrupted
+  Interrupted,
+}
+
+// a running command whose stdout is read one line at a time
+pub struct LineReader {
+  child: Child,
+  // kept open for commands that are talked to, see `channel`
+  stdin: Option<ChildStdin>,
+  lines: Lines<BufReader<Pipe>>,
+  stderr: Option<JoinHandle<Vec<u8>>>,
+  // bytes of stdout read so far, for the trace
+  read: usize,
+  started: Option<Started>,
+}
+
+// run a command to completion, feeding it `input` on stdin if given and
+// collecting its output
+pub fn run(
+  cmd: Command,
+  input: Option<Vec<u8>>,
+  options: &CallOptions,
+) -> Result<Output, RunError> {
+  let started = Started::new(&cmd, &options.trace);
+  let result = collect(cmd, input, options);
+
+  if let Some(started) = started {
+    record(started, result.as_ref().ok());
+  }
+
+  return result;
+}
+
+fn collect(
+  cmd: Command,
+  input: Option<Vec<u8>>,
+  options: &CallOptions,
+) -> Result<Output, RunError> {
+  let (mut child, stdout, writer) = start(cmd, input, options)?;
+
+  // everything is read from other threads too, so a child filling one pipe
+  // while we block on another cannot deadlock us
+  let stdout = drain(Some(stdout));
+  let stderr = drain(child.stderr.take());
+
+  let status = wait(&mut child, options.timeout, &options.interrupt)?;
+
+  if let Some(writer) = writer {
+    let _ = writer.join();
+  }
+
+  return Ok(Output {
+    status,
+    stdout: stdout.join().unwrap_or_default(),
+    stderr: stderr.join().unwrap_or_default(),
+  });
+}
+
+// run commands with the stdout of each connected to the stdin of the next,
+// feeding `input` to the first. The data flows between the commands without
+// passing through us; only the output of the last one is collected. The
+// timeout and interrupt of the first command's options apply to the whole
+// pipeline.
+pub fn pipeline(
+  stages: Vec<(Command, CallOptions)>,
+  input: Option<Vec<u8>>,
+) -> Result<Vec<Output>, RunError> {
+  let started: Vec<Option<Started>> = stages
+    .iter()
+    .map(|(cmd, options)| Started::new(cmd, &options.trace))
+    .collect();
+  let result = connect(stages, input);
+
+  for (i, started) in started.into_iter().enumerate() {
+    if let Some(started) = started {
+      let output = result.as_ref().ok().map(|outputs| &outputs[i]);
+      record(started, output);
+    }
+  }
+
+  return result;
+}
+
+// a finished command, None if it failed to start or was killed
+fn record(started: Started, output: Option<&Output>) {
+  match output {
+    Some(output) => started.finish(
+      Some(output.status),
+      output.stdout.len(),
+      output.stderr.len(),
+    ),
+    None => started.finish(None, 0, 0),
+  }
+}
+
+fn connect(
+  stages: Vec<(Command, CallOptions)>,
+  input: Option<Vec<u8>>,
+) -> Result<Vec<Output>, RunError> {
+  let timeout = stages.first().and_then(|(_, options)| options.timeout);
+  let interrupt = stages
+    .first()
+    .map(|(_, options)| options.interrupt.clone())
+    .unwrap_or_default();
+
+  let mut children: Vec<Child> = Vec::new();
+  let mut stderrs = Vec::new();
+  let mut writer = None;
+  let mut previous: Option<Pipe> = None;
+
+  for (cmd, options) in stages {
+    let stdin = match previous.take() {
+      Some(stdout) => stdout.into(),
+      None if input.is_some() => Stdio::piped(),
+      None => Stdio::null(),
+    };
+
+    let (mut child, stdout) = match spawn(cmd, stdin, &options) {
+      Ok(spawned) => spawned,
+      Err(e) => {
+        kill_all(&mut children);
+        return Err(e);
+      }
+    };
+
+    if children.is_empty() {
+      writer = feed(&mut child, input.clone());
+    }
+
+    stderrs.push(drain(child.stderr.take()));
+    previous = Some(stdout);
+    children.push(child);
+  }
+
+  let stdout = drain(previous);
+
+  let deadline = timeout.map(|t| (Instant::now() + t, t));
+  let mut statuses = Vec::with_capacity(children.len());
+
+  for i in 0..children.len() {
+    let left =
+      deadline.map(|(at, _)| at.saturating_duration_since(Instant::now()));
+
+    match wait(&mut children[i], left, &interrupt) {
+      Ok(status) => statuses.push(status),
+      Err(e) => {
+        kill_all(&mut children);
+        return Err(match (e, deadline) {
+          (RunError::Timeout(_), Some((_, limit))) => RunError::Timeout(limit),
+          (e, _) => e,
+        });
+      }
+    }
+  }
+
+  if let Some(writer) = writer {
+    let _ = writer.join();
+  }
+
+  let last = statuses.len() - 1;
+  let mut stdout = Some(stdout.join().unwrap_or_default());
+
+  return Ok(
+    statuses
+      .into_iter()
+      .zip(stderrs)
+      .enumerate()
+      .map(|(i, (status, stderr))| Output {
+        status,
+        stdout: if i == last {
+          stdout.take().unwrap()
+        } else {
+          Vec::new()
+        },
+        stderr: stderr.join().unwrap_or_default(),
+      })
+      .collect(),
+  );
+}
+
+fn kill_all(children: &mut [Child]) {
+  for child in children {
+    let _ = child.kill();
+    let _ = child.wait();
+  }
+}
+
+// start a command whose stdout is handed out line by line as it is written.
+// No time limit applies, streams are meant for commands that run long.
+pub fn stream(
+  cmd: Command,
+  input: Option<Vec<u8>>,
+  options: &CallOptions,
+) -> Result<LineReader, RunError> {
+  let started = Started::new(&cmd, &options.trace);
+  let (mut child, stdout, _) = start(cmd, input, options)?;
+  let stderr = drain(child.stderr.take());
+
+  return Ok(LineReader {
+    child,
+    stdin: None,
+    lines: BufReader::new(stdout).lines(),
+    stderr: Some(stderr),
+    read: 0,
+    started,
+  });
+}
+
+// start a command that is talked to line by line: `input` is written first,
+// then `send_line` answers what it writes. No time limit applies, the
+// conversation lasts as long as the command needs.
+pub fn channel(
+  cmd: Command,
+  input: Option<Vec<u8>>,
+  options: &CallOptions,
+) -> Result<LineReader, RunError> {
+  let started = Started::new(&cmd, &options.trace);
+  let (mut child, stdout) = spawn(cmd, Stdio::piped(), options)?;
+  let stderr = drain(child.stderr.take());
+
+  let mut reader = LineReader {
+    stdin: child.stdin.take(),
+    child,
+    lines: BufReader::new(stdout).lines(),
+    stderr: Some(stderr),
+    read: 0,
+    started,
+  };
+
+  if let Some(input) = input {
+    reader.send(&input)?;
+  }
+
+  return Ok(reader);
+}
+
+impl LineReader {
+  // write one line to the command's stdin
+  pub fn send_line(&mut self, line: &str) -> Result<(), RunError> {
+    return self.send(format!("{}\n", line).as_bytes());
+  }
+
+  fn send(&mut self, bytes: &[u8]) -> Result<(), RunError> {
+    let Some(stdin) = self.stdin.as_mut() else {
+      return Err(RunError::Io(io::Error::from(io::ErrorKind::BrokenPipe)));
+    };
+
+    return stdin
+      .write_all(bytes)
+      .and_then(|_| stdin.flush())
+      .map_err(RunError::Io);
+  }
+
+  // the next line without its newline, None at the end of the output
+  pub fn next_line(&mut self) -> Option<Result<String, RunError>> {
+    let line = self.lines.next()?.map_err(RunError::Io);
+    if let Ok(line) = &line {
+      self.read += line.len() + 1;
+    }
+
+    return Some(line);
+  }
+
+  // false once the command exited
+  pub fn running(&mut self) -> bool {
+    return matches!(self.child.try_wait(), Ok(None));
+  }
+
+  // wait for the command to exit once its output was read to the end
+  pub fn finish(&mut self) -> Result<Output, RunError> {
+    // a command waiting for more input sees its end
+    self.stdin = None;
+    let status = self.child.wait().map_err(RunError::Io)?;
+    let stderr = self.stderr.take().map(|s| s.join().unwrap_or_default());
+    let stderr = stderr.unwrap_or_default();
+
+    if let Some(started) = self.started.take() {
+      started.finish(Some(status), self.read, stderr.len());
+    }
+
+    return Ok(Output {
+      status,
+      stdout: Vec::new(),
+      stderr,
+    });
+  }
+}
+
+impl Drop for LineReader {
+  // a stream abandoned halfway must not leave its command running
+  fn drop(&mut self) {
+    let status = match self.child.try_wait() {
+      Ok(Some(status)) => Some(status),
+      _ => {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        None
+      }
+    };
+
+    if let Some(started) = self.started.take() {
+      started.finish(status, self.read, 0);
+    }
+  }
+}
+
+// spawn, writing `input` to stdin from another thread
+fn start(
+  cmd: Command,
+  input: Option<Vec<u8>>,
+  options: &CallOptions,
+) -> Result<(Child, Pipe, Option<JoinHandle<()>>), RunError> {
+  let stdin = if input.is_some() {
+    Stdio::piped()
+  } else {
+    Stdio::null()
+  };
+
+  let (mut child, stdout) = spawn(cmd, stdin, options)?;
+  let writer = feed(&mut child, input);
+
+  return Ok((child, stdout, writer));
+}
+
+// write `input` to the stdin of `child` from another thread
+fn feed(child: &mut Child, input: Option<Vec<u8>>) -> Option<JoinHandle<()>> {
+  return input.map(|bytes| {
+    let mut pipe = child.stdin.take().unwrap();
+    thread::spawn(move || {
+      let _ = pipe.write_all(&bytes);
+    })
+  });
+}
+
+// stdout of a child, with stderr mixed in if it was merged
+enum Pipe {
+  Stdout(ChildStdout),
+  Merged(PipeReader),
+}
+
+impl Read for Pipe {
+  fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    match self {
+      Pipe::Stdout(pipe) => pipe.read(buf),
+      Pipe::Merged(pipe) => pipe.read(buf),
+    }
+  }
+}
+
+impl From<Pipe> for Stdio {
+  fn from(pipe: Pipe) -> Stdio {
+    match pipe {
+      Pipe::Stdout(pipe) => pipe.into(),
+      Pipe::Merged(pipe) => pipe.into(),
+    }
+  }
+}
+
+// spawn with piped output and the environment of `options`. The child's
+// stderr is piped too unless inherited or merged into stdout.
+fn spawn(
+  mut cmd: Command,
+  stdin: Stdio,
+  options: &CallOptions,
+) -> Result<(Child, Pipe), RunError> {
+  if options.clear_env {
+    cmd.env_clear();
+  }
+  cmd.envs(&options.env);
+
+  if let Some(dir) = &options.cwd {
+    cmd.current_dir(dir);
+  }
+
+  cmd.stdin(stdin);
+
+  let merged = match options.stderr {
+    StderrMode::Capture => {
+      cmd.stdout(Stdio::piped()).stderr(Stdio::piped());
+      None
+    }
+    StderrMode::Inherit => {
+      cmd.stdout(Stdio::piped()).stderr(Stdio::inherit());
+      None
+    }
+    // both ends write to one pipe, so the order is kept
+    StderrMode::Merge => {
+      let (reader, writer) = io::pipe().map_err(RunError::Spawn)?;
+      let writer2 = writer.try_clone().map_err(RunError::Spawn)?;
+      cmd.stdout(writer).stderr(writer2);
+      Some(reader)
+    }
+  };
+
+  let mut child = cmd.spawn().map_err(RunError::Spawn)?;
+
+  // our copies of the write ends must be closed, or the output never ends
+  drop(cmd);
+
+  let stdout = match merged {
+    Some(reader) => Pipe::Merged(reader),
+    None => Pipe::Stdout(child.stdout.take().unwrap()),
+  };
+
+  return Ok((child, stdout));
+}
+
+// how often a waiting call looks whether it was interrupted
+const INTERRUPT_POLL: Duration = Duration::from_millis(50);
+
+#[cfg(not(target_arch = "wasm32"))]
+fn wait(
+  child: &mut Child,
+  timeout: Option<Duration>,
+  interrupt: &InterruptHandle,
+) -> Result<ExitStatus, RunError> {
+  let deadline = timeout.map(|t| Instant::now() + t);
+
+  loop {
+    let slice = match deadline {
+      Some(at) => at.saturating_duration_since(Instant::now()),
+      None => INTERRUPT_POLL,
+    };
+
+    if let Some(status) = child
+      .wait_timeout(slice.min(INTERRUPT_POLL))
+      .map_err(RunError::Io)?
+    {
+      return Ok(status);
+    }
+
+    let error = match (timeout, deadline) {
+      _ if interrupt.is_interrupted() => RunError::Interrupted,
+      (Some(timeout), Some(at)) if Instant::now() >= at => {
+        RunError::Timeout(timeout)
+      }
+      _ => continue,
+    };
+
+    // the reader threads are left to finish once the pipes close
+    let _ = child.kill();
+    let _ = child.wait();
+    return Err(error);
+  }
+}
+
+// WebAssembly cannot start processes, there is never a child to time out
+#[cfg(target_arch = "wasm32")]
+fn wait(
+  child: &mut Child,
+  _timeout: Option<Duration>,
+  _interrupt: &InterruptHandle,
+) -> Result<ExitStatus, RunError> {
+  return child.wait().map_err(RunError::Io);
+}
+
+fn drain(pipe: Option<impl Read + Send + 'static>) -> JoinHandle<Vec<u8>> {
+  return thread::spawn(move || {
+    let mut bytes = Vec::new();
+    if let Some(mut pipe) = pipe {
+      let _ = pipe.read_to_end(&mut bytes);
+    }
+    bytes
+  });
+}
+
+// run `script` with the system shell; arguments added to the command follow
+// the script. With `sh` they are positional parameters and never parsed.
+#[cfg(not(windows))]
+pub fn shell(script: &str) -> Command {
+  let mut cmd = Command::new("sh");
+  cmd.arg("-c").arg(format!("{} \"$@\"", script)).arg(script);
+  return cmd;
+}
+
+// `cmd` has no positional parameters and would parse arguments appended to
+// the script, so shell entries take none here, see `FFI::command`. /D skips
+// AutoRun scripts.
+#[cfg(windows)]
+pub fn shell(script: &str) -> Command {
+  use std::os::windows::process::CommandExt;
+
+  let mut cmd = Command::new("cmd");
+  // passed as written, quoting it would change how cmd reads it
+  cmd.args(["/D", "/C"]).raw_arg(script);
+  return cmd;
+}
+
+// exit code of a finished child, -1 if it was killed by a signal
+pub fn status_code(status: ExitStatus) -> i64 {
+  return status.code().unwrap_or(-1) as i64;
+}
+
+// human readable duration for messages, e.g. 2s or 0.5s
+pub fn format_duration(duration: Duration) -> String {
+  return format!("{}s", duration.as_secs_f64());
+}
+
+// the tests run commands found on every unix
+#[cfg(all(test, unix))]
+mod tests {
+  use super::*;
+  use std::sync::{Arc, Mutex};
+  use std::time::Instant;
+
+  #[test]
+  fn test_run_collects_output() {
+    let mut cmd = Command::new("cat");
+    cmd.arg("-");
+
+    let output =
+      run(cmd, Some(b"hello".to_vec()), &CallOptions::default()).unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"hello");
+  }
+
+  #[test]
+  fn test_run_timeout() {
+    let mut cmd = Command::new("sleep");
+    cmd.arg("5");
+
+    let options = CallOptions {
+      timeout: Some(Duration::from_millis(100)),
+      ..CallOptions::default()
+    };
+
+    let started = Instant::now();
+    let result = run(cmd, None, &options);
+
+    assert!(matches!(result, Err(RunError::Timeout(_))));
+    assert!(started.elapsed() < Duration::from_secs(2));
+  }
+
+  #[test]
+  fn test_run_interrupted() {
+    let mut cmd = Command::new("sleep");
+    cmd.arg("5");
+
+    let options = CallOptions::default();
+    let interrupt = options.interrupt.clone();
+    thread::spawn(move || {
+      thread::sleep(Duration::from_millis(100));
+      interrupt.interrupt();
+    });
+
+    let started = Instant::now();
+    let result = run(cmd, None, &options);
+
+    assert!(matches!(result, Err(RunError::Interrupted)));
+    assert!(started.elapsed() < Duration::from_secs(2));
+  }
+
+  #[test]
+  fn test_stream_lines() {
+    let mut cmd = Command::new("printf");
+    cmd.arg("a\\nb\\n");
+
+    let mut reader = stream(cmd, None, &CallOptions::default()).unwrap();
+
+    assert_eq!(reader.next_line().unwrap().unwrap(), "a");
+    assert_eq!(reader.next_line().unwrap().unwrap(), "b");
+    assert!(reader.next_line().is_none());
+    assert!(reader.finish().unwrap().status.success());
+  }
+
+  #[test]
+  fn test_stream_dropped_early() {
+    let cmd = Command::new("yes");
+    let mut reader = stream(cmd, None, &CallOptions::default()).unwrap();
+    assert_eq!(reader.next_line().unwrap().unwrap(), "y");
+
+    // dropping kills `yes`, which would otherwise run forever
+    drop(reader);
+  }
+
+  #[test]
+  fn test_channel() {
+    let mut cmd = Command::new("sh");
+    cmd
+      .arg("-c")
+      .arg("read a; echo \"got $a\"; read b; echo \"got $b\"");
+
+    let mut reader =
+      channel(cmd, Some(b"one\n".to_vec()), &CallOptions::default()).unwrap();
+
+    assert_eq!(reader.next_line().unwrap().unwrap(), "got one");
+    reader.send_line("two").unwrap();
+    assert_eq!(reader.next_line().unwrap().unwrap(), "got two");
+    assert!(reader.next_line().is_none());
+    assert!(reader.finish().unwrap().status.success());
+  }
+
+  #[test]
+  fn test_pipeline() {
+    let mut grep = Command::new("grep");
+    grep.arg("b");
+    let stages = vec![
+      (Command::new("cat"), CallOptions::default()),
+      (grep, CallOptions::default()),
+      (Command::new("wc"), CallOptions::default()),
+    ];
+
+    let outputs = pipeline(stages, Some(b"a\nb\nab\n".to_vec())).unwrap();
+
+    assert_eq!(outputs.len(), 3);
+    assert!(outputs[0].stdout.is_empty());
+    assert_eq!(
+      String::from_utf8_lossy(&outputs[2].stdout)
+        .split_whitespace()
+        .collect::<Vec<_>>(),
+      ["2", "2", "5"]
+    );
+  }
+
+  #[test]
+  fn test_pipeline_timeout() {
+    let mut sleep = Command::new("sleep");
+    sleep.arg("5");
+    let options = CallOptions {
+      timeout: Some(Duration::from_millis(100)),
+      ..CallOptions::default()
+    };
+
+    let stages =
+      vec![(sleep, options), (Command::new("cat"), Default::default())];
+    let result = pipeline(stages, None);
+    assert!(matches!(result, Err(RunError::Timeout(_))));
+  }
+
+  #[test]
+  fn test_stderr_modes() {
+    let script = || shell("echo out; echo err >&2; echo out");
+
+    let output = run(script(), None, &CallOptions::default()).unwrap();
+    assert_eq!(output.stdout, b"out\nout\n");
+    assert_eq!(output.stderr, b"err\n");
+
+    let options = CallOptions {
+      stderr: StderrMode::Merge,
+      ..CallOptions::default()
+    };
+    let output = run(script(), None, &options).unwrap();
+    assert_eq!(output.stdout, b"out\nerr\nout\n");
+    assert!(output.stderr.is_empty());
+
+    let mut reader = stream(script(), None, &options).unwrap();
+    assert_eq!(reader.next_line().unwrap().unwrap(), "out");
+    assert_eq!(reader.next_line().unwrap().unwrap(), "err");
+  }
+
+  #[test]
+  fn test_shell_arguments() {
+    let mut cmd = shell("printf '%s,'");
+    cmd.args(["a b", "$HOME", "; ls"]);
+
+    let output = run(cmd, None, &CallOptions::default()).unwrap();
+    assert_eq!(output.stdout, b"a b,$HOME,; ls,");
+  }
+
+  #[test]
+  fn test_run_env() {
+    let mut options = CallOptions::default();
+    options.env.insert("STAGE".to_owned(), "prod".to_owned());
+
+    let output = run(Command::new("env"), None, &options).unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.lines().any(|l| l == "STAGE=prod"));
+    assert!(stdout.lines().any(|l| l.starts_with("PATH=")));
+
+    // only STAGE is left, so `env` has to be found by its full path
+    options.clear_env = true;
+    let output = run(Command::new("/usr/bin/env"), None, &options).unwrap();
+    assert_eq!(output.stdout, b"STAGE=prod\n");
+  }
+
+  #[test]
+  fn test_trace() {
+    let records = Arc::new(Mutex::new(Vec::new()));
+    let collected = records.clone();
+    let options = CallOptions {
+      trace: Some(Trace::new(move |r| {
+        collected.lock().unwrap().push(r.clone())
+      })),
+      ..CallOptions::default()
+    };
+
+    let mut cmd = shell("printf hello; echo oops >&2; exit 3");
+    cmd.arg("x");
+    run(cmd, None, &options).unwrap();
+
+    let mut reader =
+      stream(shell("echo one; echo two"), None, &options).unwrap();
+    while reader.next_line().is_some() {}
+    reader.finish().unwrap();
+
+    let records = records.lock().unwrap();
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[0].program, "sh");
+    assert_eq!(records[0].args.last().unwrap(), "x");
+    assert_eq!(records[0].status, Some(3));
+    assert_eq!(records[0].stdout_bytes, 5);
+    assert_eq!(records[0].stderr_bytes, 5);
+    assert_eq!(records[1].status, Some(0));
+    assert_eq!(records[1].stdout_bytes, 8);
+  }
+}
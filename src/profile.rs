@@ -0,0 +1,275 @@
+#![allow(dead_code)]
+
+// Time spent per function, set by `--profile`. Every call of a sam
+// function, foreign function, builtin, or program is timed: inclusive time
+// counts the calls it made, exclusive time does not. Calls started with
+// `spawn` and the stages of a pipe run beside the script and are not
+// timed.
+//
+// The report is a table sorted by exclusive time, and optionally the
+// call stacks in the folded format flamegraph tools read, e.g.
+// `main;fib;fib 1234` for 1234 microseconds spent in `fib` called by `fib`.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, ThreadId};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Kind {
+  Function,
+  Foreign,
+  Builtin,
+  Program,
+}
+
+impl fmt::Display for Kind {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let name = match self {
+      Kind::Function => "function",
+      Kind::Foreign => "foreign",
+      Kind::Builtin => "builtin",
+      Kind::Program => "program",
+    };
+    write!(f, "{}", name)
+  }
+}
+
+// the totals of one function
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Entry {
+  pub calls: u64,
+  pub inclusive: Duration,
+  pub exclusive: Duration,
+}
+
+// a call still running
+struct Open {
+  kind: Kind,
+  name: String,
+  // the names of the calls it is in and its own, joined by `;`
+  path: String,
+  started: Instant,
+  // time spent in the calls it made
+  children: Duration,
+}
+
+#[derive(Default)]
+struct State {
+  // calls running on each thread, innermost last; builtins like par_map
+  // call back on other threads
+  open: HashMap<ThreadId, Vec<Open>>,
+  entries: HashMap<(Kind, String), Entry>,
+  // exclusive time per call stack
+  stacks: HashMap<String, Duration>,
+}
+
+// shared by every copy of the config, and so by the threads of a run
+#[derive(Clone, Default)]
+pub struct Profiler(Arc<Mutex<State>>);
+
+impl Profiler {
+  pub fn new() -> Self {
+    return Profiler::default();
+  }
+
+  // time a call of `name` until the guard is dropped
+  pub fn enter(&self, kind: Kind, name: &str) -> Guard {
+    let mut state = self.0.lock().unwrap();
+    let open = state.open.entry(thread::current().id()).or_default();
+
+    let path = match open.last() {
+      Some(caller) => format!("{};{}", caller.path, name),
+      None => name.to_owned(),
+    };
+    open.push(Open {
+      kind,
+      name: name.to_owned(),
+      path,
+      started: Instant::now(),
+      children: Duration::ZERO,
+    });
+
+    return Guard(self.clone());
+  }
+
+  // end the innermost call running on this thread
+  fn exit(&self) {
+    let mut state = self.0.lock().unwrap();
+    let state = &mut *state;
+
+    let Some(open) = state.open.get_mut(&thread::current().id()) else {
+      return;
+    };
+    let Some(call) = open.pop() else {
+      return;
+    };
+
+    let inclusive = call.started.elapsed();
+    let exclusive = inclusive.saturating_sub(call.children);
+    if let Some(caller) = open.last_mut() {
+      caller.children += inclusive;
+    }
+
+    // the time of a recursive call is already in that of the call it is in
+    let recursive = open
+      .iter()
+      .any(|c| c.kind == call.kind && c.name == call.name);
+
+    let entry = state.entries.entry((call.kind, call.name)).or_default();
+    entry.calls += 1;
+    entry.exclusive += exclusive;
+    if !recursive {
+      entry.inclusive += inclusive;
+    }
+
+    *state.stacks.entry(call.path).or_default() += exclusive;
+  }
+
+  // the totals of every function called, most exclusive time first
+  pub fn entries(&self) -> Vec<(Kind, String, Entry)> {
+    let state = self.0.lock().unwrap();
+
+    let mut entries: Vec<_> = state
+      .entries
+      .iter()
+      .map(|((kind, name), entry)| (*kind, name.clone(), *entry))
+      .collect();
+    entries.sort_by(|a, b| {
+      b.2
+        .exclusive
+        .cmp(&a.2.exclusive)
+        .then_with(|| (a.0, &a.1).cmp(&(b.0, &b.1)))
+    });
+
+    return entries;
+  }
+
+  // the table printed after the run
+  pub fn report(&self) -> String {
+    let entries = self.entries();
+    let width = entries
+      .iter()
+      .map(|(_, name, _)| name.chars().count())
+      .chain(["name".len()])
+      .max()
+      .unwrap();
+
+    let mut out = format!(
+      "{:<width$}  {:<8}  {:>8}  {:>12}  {:>12}\n",
+      "name", "kind", "calls", "inclusive", "exclusive"
+    );
+    for (kind, name, entry) in entries {
+      out.push_str(&format!(
+        "{:<width$}  {:<8}  {:>8}  {:>12}  {:>12}\n",
+        name,
+        kind.to_string(),
+        entry.calls,
+        seconds(entry.inclusive),
+        seconds(entry.exclusive)
+      ));
+    }
+
+    return out;
+  }
+
+  // every call stack with the microseconds spent in it, one per line
+  pub fn folded(&self) -> String {
+    let state = self.0.lock().unwrap();
+
+    let mut stacks: Vec<_> = state.stacks.iter().collect();
+    stacks.sort();
+
+    return stacks
+      .into_iter()
+      .map(|(path, time)| format!("{} {}\n", path, time.as_micros()))
+      .collect();
+  }
+}
+
+impl fmt::Debug for Profiler {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "Profiler")
+  }
+}
+
+// a call being timed, which ends when this is dropped, also when the call
+// fails
+pub struct Guard(Profiler);
+
+impl Drop for Guard {
+  fn drop(&mut self) {
+    self.0.exit();
+  }
+}
+
+// time a call of `name` if the run is profiled
+pub fn enter(
+  profiler: &Option<Profiler>,
+  kind: Kind,
+  name: &str,
+) -> Option<Guard> {
+  return profiler.as_ref().map(|p| p.enter(kind, name));
+}
+
+fn seconds(d: Duration) -> String {
+  return format!("{:.6}s", d.as_secs_f64());
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_nested_calls() {
+    let profiler = Profiler::new();
+    {
+      let _main = profiler.enter(Kind::Function, "main");
+      for _ in 0..2 {
+        let _ls = profiler.enter(Kind::Program, "ls");
+        thread::sleep(Duration::from_millis(2));
+      }
+      // recursion is counted once in the inclusive time
+      let _outer = profiler.enter(Kind::Function, "fib");
+      let _inner = profiler.enter(Kind::Function, "fib");
+      thread::sleep(Duration::from_millis(2));
+    }
+
+    let entries = profiler.entries();
+    let find =
+      |name: &str| entries.iter().find(|(_, n, _)| n == name).unwrap().2;
+
+    let (main, ls, fib) = (find("main"), find("ls"), find("fib"));
+    assert_eq!((main.calls, ls.calls, fib.calls), (1, 2, 2));
+    assert!(ls.inclusive >= Duration::from_millis(4));
+    assert_eq!(ls.inclusive, ls.exclusive);
+    assert!(main.exclusive < main.inclusive);
+    assert!(main.inclusive >= ls.inclusive + fib.inclusive);
+    assert!(fib.inclusive <= main.inclusive);
+
+    let folded = profiler.folded();
+    assert!(folded.contains("main;ls "));
+    assert!(folded.contains("main;fib;fib "));
+
+    let report = profiler.report();
+    assert!(report.starts_with("name"));
+    assert_eq!(report.lines().count(), 4);
+  }
+
+  #[test]
+  fn test_threads() {
+    let profiler = Profiler::new();
+    let _main = profiler.enter(Kind::Builtin, "par_map");
+
+    // calls on other threads do not nest in the calls of this one
+    let other = profiler.clone();
+    thread::spawn(move || {
+      let _f = other.enter(Kind::Function, "f");
+    })
+    .join()
+    .unwrap();
+
+    assert!(profiler.folded().starts_with("f "));
+  }
+}
@@ -0,0 +1,269 @@
+#![allow(dead_code)]
+
+// Dead code elimination, run after constant folding: statements after a
+// return, break, or continue are dropped, and so are the branches an `if`
+// with a constant condition never takes, e.g. the body of `if (0)` or the
+// `else` of `if (2 > 1)`. Less code is compiled and walked. The statements
+// are already reported by src/warnings.rs, the branches are reported here.
+
+use crate::ast::{Block, Else, Expr, ExprKind, If, Literal, Stmt, StmtKind};
+use crate::codes;
+use crate::diagnostic::Diagnostic;
+use crate::error::Span;
+use crate::evaluate::condition;
+use std::sync::Arc;
+
+// drop the dead code of `statements`, returning a warning for every branch
+// dropped
+pub fn prune_program(statements: &mut Vec<Stmt>) -> Vec<Diagnostic> {
+  let mut warnings = Vec::new();
+  prune_statements(statements, &mut warnings);
+
+  return warnings;
+}
+
+fn prune_statements(
+  statements: &mut Vec<Stmt>,
+  warnings: &mut Vec<Diagnostic>,
+) {
+  let jump = statements.iter().position(|stmt| {
+    matches!(
+      stmt.kind,
+      StmtKind::Return(_) | StmtKind::Break | StmtKind::Continue
+    )
+  });
+  if let Some(at) = jump {
+    statements.truncate(at + 1);
+  }
+
+  for stmt in statements {
+    prune_statement(stmt, warnings);
+  }
+}
+
+fn prune_block(block: &mut Block, warnings: &mut Vec<Diagnostic>) {
+  prune_statements(&mut block.statements, warnings);
+}
+
+fn prune_statement(stmt: &mut Stmt, warnings: &mut Vec<Diagnostic>) {
+  match &mut stmt.kind {
+    StmtKind::Expression(expr) => prune_expression(expr, warnings),
+    StmtKind::Declaration { declarators, .. } => {
+      for value in declarators.iter_mut().filter_map(|d| d.value.as_mut()) {
+        prune_expression(value, warnings);
+      }
+    }
+    StmtKind::Assignment { value, .. } => prune_expression(value, warnings),
+    StmtKind::Return(Some(value)) => prune_expression(value, warnings),
+    StmtKind::Return(None)
+    | StmtKind::Break
+    | StmtKind::Continue
+    | StmtKind::Interfaces(_)
+    | StmtKind::Import(_) => {}
+  }
+}
+
+fn prune_expression(expr: &mut Expr, warnings: &mut Vec<Diagnostic>) {
+  match &mut expr.kind {
+    ExprKind::Binary { left, right, .. } => {
+      prune_expression(left, warnings);
+      prune_expression(right, warnings);
+    }
+    ExprKind::If(branch) => prune_if(branch, warnings),
+    ExprKind::For { iterable, body, .. } => {
+      prune_expression(iterable, warnings);
+      prune_block(body, warnings);
+    }
+    ExprKind::Lambda { body, .. } => prune_block(Arc::make_mut(body), warnings),
+    ExprKind::Call(call) | ExprKind::Spawn(call) => {
+      prune_expression(&mut call.function, warnings);
+      for arg in &mut call.args {
+        prune_expression(arg, warnings);
+      }
+    }
+    ExprKind::Member { object, .. } => prune_expression(object, warnings),
+    ExprKind::Array(items) => {
+      for item in items {
+        prune_expression(item, warnings);
+      }
+    }
+    ExprKind::Index { index, .. } => prune_expression(index, warnings),
+    ExprKind::Await(handle) => prune_expression(handle, warnings),
+    ExprKind::Literal(_) | ExprKind::Identifier(_) => {}
+  }
+}
+
+fn prune_if(branch: &mut If, warnings: &mut Vec<Diagnostic>) {
+  prune_expression(&mut branch.condition, warnings);
+
+  match constant(&branch.condition) {
+    // the body stays, empty, so that the `if` still checks its condition
+    // and gives the same value
+    Some(false) if !branch.consequence.statements.is_empty() => {
+      warnings.push(dead_branch(
+        branch.consequence.span,
+        "the condition is always false",
+      ));
+      branch.consequence.statements.clear();
+    }
+    Some(true) => {
+      let span = match &branch.alternative {
+        Some(Else::Block(block)) => Some(block.span),
+        Some(Else::If(_, span)) => Some(*span),
+        None => None,
+      };
+      if let Some(span) = span {
+        warnings.push(dead_branch(span, "the condition before is always true"));
+        branch.alternative = None;
+      }
+    }
+    _ => {}
+  }
+
+  prune_block(&mut branch.consequence, warnings);
+
+  match &mut branch.alternative {
+    Some(Else::Block(block)) => prune_block(block, warnings),
+    Some(Else::If(branch, _)) => prune_if(branch, warnings),
+    None => {}
+  }
+}
+
+// whether a literal condition holds; conditions that fail are left to fail
+// when they run
+pub(crate) fn constant(cond: &Expr) -> Option<bool> {
+  let ExprKind::Literal(literal @ Literal::Number(_)) = &cond.kind else {
+    return None;
+  };
+
+  return condition(literal.to_value(), cond.span).ok();
+}
+
+fn dead_branch(span: Span, reason: &str) -> Diagnostic {
+  return Diagnostic::warning("unreachable branch")
+    .with_code(codes::DEAD_BRANCH)
+    .with_span(Some(span))
+    .with_label("never runs")
+    .with_note(reason.to_owned());
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::value::Value;
+  use tree_sitter::Point;
+
+  fn span() -> Span {
+    return Span {
+      start: 0,
+      end: 0,
+      start_point: Point::default(),
+      end_point: Point::default(),
+    };
+  }
+
+  fn expr(kind: ExprKind) -> Expr {
+    return Expr { kind, span: span() };
+  }
+
+  fn stmt(kind: StmtKind) -> Stmt {
+    return Stmt { kind, span: span() };
+  }
+
+  fn number(n: i64) -> Expr {
+    let Some(literal) = Literal::from_value(&Value::from(n)) else {
+      unreachable!();
+    };
+    return expr(ExprKind::Literal(literal));
+  }
+
+  fn block(statements: Vec<Stmt>) -> Block {
+    return Block {
+      statements,
+      span: span(),
+    };
+  }
+
+  // `name;`
+  fn read(name: &str) -> Stmt {
+    return stmt(StmtKind::Expression(expr(ExprKind::Identifier(
+      name.into(),
+    ))));
+  }
+
+  #[test]
+  fn test_after_jumps() {
+    // () => { a; return; b; }
+    let body = block(vec![read("a"), stmt(StmtKind::Return(None)), read("b")]);
+    let mut program =
+      vec![stmt(StmtKind::Expression(expr(ExprKind::Lambda {
+        params: vec![],
+        body: Arc::new(body),
+      })))];
+
+    let warnings = prune_program(&mut program);
+    assert!(warnings.is_empty());
+
+    let StmtKind::Expression(Expr {
+      kind: ExprKind::Lambda { body, .. },
+      ..
+    }) = &program[0].kind
+    else {
+      panic!("expected a lambda");
+    };
+    assert_eq!(body.statements.len(), 2);
+  }
+
+  #[test]
+  fn test_constant_branches() {
+    // if (0) { a; } else { b; }
+    let mut program =
+      vec![stmt(StmtKind::Expression(expr(ExprKind::If(If {
+        condition: Box::new(number(0)),
+        consequence: block(vec![read("a")]),
+        alternative: Some(Else::Block(block(vec![read("b")]))),
+      }))))];
+
+    let warnings = prune_program(&mut program);
+    assert_eq!(warnings.len(), 1);
+    assert_eq!(warnings[0].code, Some(codes::DEAD_BRANCH));
+
+    let StmtKind::Expression(Expr {
+      kind: ExprKind::If(branch),
+      ..
+    }) = &program[0].kind
+    else {
+      panic!("expected an if");
+    };
+    assert!(branch.consequence.statements.is_empty());
+    assert!(branch.alternative.is_some());
+
+    // if (1) { a; } else if (x) { b; }
+    let mut program =
+      vec![stmt(StmtKind::Expression(expr(ExprKind::If(If {
+        condition: Box::new(number(1)),
+        consequence: block(vec![read("a")]),
+        alternative: Some(Else::If(
+          Box::new(If {
+            condition: Box::new(expr(ExprKind::Identifier("x".into()))),
+            consequence: block(vec![read("b")]),
+            alternative: None,
+          }),
+          span(),
+        )),
+      }))))];
+
+    let warnings = prune_program(&mut program);
+    assert_eq!(warnings.len(), 1);
+
+    let StmtKind::Expression(Expr {
+      kind: ExprKind::If(branch),
+      ..
+    }) = &program[0].kind
+    else {
+      panic!("expected an if");
+    };
+    assert_eq!(branch.consequence.statements.len(), 1);
+    assert!(branch.alternative.is_none());
+  }
+}
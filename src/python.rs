@@ -0,0 +1,240 @@
+#![allow(dead_code)]
+
+// Python module wrapping the interpreter, built with the `python` feature,
+// e.g. by maturin:
+//
+//   import sam
+//   sam.eval("1 + 2;")                        # 3
+//
+//   s = sam.Interpreter()
+//   s.register("shout", lambda text: text.upper() + "!")
+//   s.eval("let greeting = shout('hi');")
+//   s["greeting"]                             # 'HI!'
+//
+// Values convert like JSON: None is undefined, lists are arrays, dicts with
+// string keys are objects, and booleans become the integers 0 and 1.
+
+use crate::codes;
+use crate::error::SamError;
+use crate::interpreter::Interpreter;
+use crate::value::{NativeFunction, Number, Value};
+use pyo3::create_exception;
+use pyo3::exceptions::{PyException, PyKeyError};
+use pyo3::prelude::*;
+use pyo3::types::{
+  PyBool, PyBytes, PyDict, PyFloat, PyList, PyString, PyTuple,
+};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+create_exception!(sam, Error, PyException, "An error raised by sam code.");
+
+// an interpreter session, variables and functions stay between `eval`s
+#[pyclass(name = "Interpreter", module = "sam", unsendable)]
+pub struct PyInterpreter {
+  interpreter: Interpreter,
+}
+
+#[pymethods]
+impl PyInterpreter {
+  #[new]
+  fn new() -> Self {
+    return PyInterpreter {
+      interpreter: Interpreter::new(),
+    };
+  }
+
+  // the value of the last statement of `source`
+  fn eval<'py>(
+    &mut self,
+    py: Python<'py>,
+    source: &str,
+  ) -> PyResult<Bound<'py, PyAny>> {
+    let value = self.interpreter.eval(source).map_err(to_exception)?;
+    return to_python(py, &value);
+  }
+
+  // make a Python callable a function of the script, called with the
+  // converted arguments; what it returns is converted back
+  fn register(&mut self, name: &str, function: Py<PyAny>) {
+    self.interpreter.register(python_function(name, function));
+  }
+
+  // set a global variable of the script, e.g. its input
+  fn __setitem__(
+    &mut self,
+    name: &str,
+    value: &Bound<'_, PyAny>,
+  ) -> PyResult<()> {
+    let value = from_python(value).map_err(to_exception)?;
+    self.interpreter.set_global(name, value);
+
+    return Ok(());
+  }
+
+  // a global variable of the script
+  fn __getitem__<'py>(
+    &self,
+    py: Python<'py>,
+    name: &str,
+  ) -> PyResult<Bound<'py, PyAny>> {
+    let Some(value) = self.interpreter.get_global(name) else {
+      return Err(PyKeyError::new_err(name.to_owned()));
+    };
+
+    return to_python(py, value);
+  }
+}
+
+// evaluate `source` in a fresh interpreter
+#[pyfunction]
+fn eval<'py>(py: Python<'py>, source: &str) -> PyResult<Bound<'py, PyAny>> {
+  return PyInterpreter::new().eval(py, source);
+}
+
+#[pymodule]
+fn sam(m: &Bound<'_, PyModule>) -> PyResult<()> {
+  m.add_class::<PyInterpreter>()?;
+  m.add_function(wrap_pyfunction!(eval, m)?)?;
+  m.add("Error", m.py().get_type::<Error>())?;
+
+  return Ok(());
+}
+
+// e.g. `sam.Error: error[E0001]: Variable x not defined`
+fn to_exception(err: SamError) -> PyErr {
+  let message = match err.code() {
+    Some(code) => format!("error[{}]: {}", code, err),
+    None => format!("error: {}", err),
+  };
+
+  return Error::new_err(message);
+}
+
+fn python_function(name: &str, function: Py<PyAny>) -> NativeFunction {
+  let owner = name.to_owned();
+
+  return NativeFunction::new(name, move |args| {
+    Python::attach(|py| {
+      let raised = |e: PyErr| {
+        SamError::ffi(
+          codes::HOST_FUNCTION_FAILED,
+          format!("Python function `{}` raised {}", owner, e),
+        )
+      };
+
+      let args = args
+        .iter()
+        .map(|a| to_python(py, a))
+        .collect::<PyResult<Vec<_>>>()
+        .map_err(raised)?;
+      let args = PyTuple::new(py, args).map_err(raised)?;
+      let result = function.bind(py).call1(args).map_err(raised)?;
+
+      from_python(&result)
+    })
+  });
+}
+
+// a sam value as a Python object; functions, handles, and streams have no
+// Python counterpart
+pub fn to_python<'py>(
+  py: Python<'py>,
+  value: &Value,
+) -> PyResult<Bound<'py, PyAny>> {
+  match value {
+    Value::Undefined => Ok(py.None().into_bound(py)),
+    Value::SamNumber(Number::SamInt(i)) => Ok(i.into_pyobject(py)?.into_any()),
+    Value::SamNumber(Number::SamFloat(f)) => {
+      Ok(f.into_pyobject(py)?.into_any())
+    }
+    Value::SamString(s) => Ok(PyString::new(py, s).into_any()),
+    Value::SamBytes(b) => Ok(PyBytes::new(py, b).into_any()),
+    Value::SamArray(items) => {
+      let items = items
+        .iter()
+        .map(|v| to_python(py, v))
+        .collect::<PyResult<Vec<_>>>()?;
+      Ok(PyList::new(py, items)?.into_any())
+    }
+    Value::SamObject(fields) => {
+      let dict = PyDict::new(py);
+      for (key, v) in fields.iter() {
+        dict.set_item(key, to_python(py, v)?)?;
+      }
+      Ok(dict.into_any())
+    }
+    _ => Err(to_exception(SamError::type_error(
+      codes::NOT_SERIALIZABLE,
+      format!("A {} cannot be passed to Python", value.type_name()),
+    ))),
+  }
+}
+
+// a Python object as a sam value
+pub fn from_python(object: &Bound<'_, PyAny>) -> Result<Value, SamError> {
+  let unsupported = || {
+    SamError::type_error(
+      codes::NOT_SERIALIZABLE,
+      format!(
+        "A Python {} cannot be passed to sam",
+        object
+          .get_type()
+          .name()
+          .map_or("object".to_owned(), |n| n.to_string())
+      ),
+    )
+  };
+
+  if object.is_none() {
+    return Ok(Value::Undefined);
+  }
+
+  // before ints, which booleans also are
+  if let Ok(b) = object.cast::<PyBool>() {
+    return Ok(Value::SamNumber(Number::SamInt(b.is_true() as i64)));
+  }
+
+  if let Ok(i) = object.extract::<i64>() {
+    return Ok(Value::SamNumber(Number::SamInt(i)));
+  }
+
+  if let Ok(f) = object.cast::<PyFloat>() {
+    return Ok(Value::SamNumber(Number::SamFloat(f.value())));
+  }
+
+  if let Ok(s) = object.cast::<PyString>() {
+    return Ok(Value::from(s.to_string()));
+  }
+
+  if let Ok(b) = object.cast::<PyBytes>() {
+    return Ok(Value::SamBytes(Arc::new(b.as_bytes().to_vec())));
+  }
+
+  if let Ok(list) = object.cast::<PyList>() {
+    return list
+      .iter()
+      .map(|item| from_python(&item))
+      .collect::<Result<Vec<_>, _>>()
+      .map(Value::from);
+  }
+
+  if let Ok(tuple) = object.cast::<PyTuple>() {
+    return tuple
+      .iter()
+      .map(|item| from_python(&item))
+      .collect::<Result<Vec<_>, _>>()
+      .map(Value::from);
+  }
+
+  if let Ok(dict) = object.cast::<PyDict>() {
+    let mut fields = HashMap::new();
+    for (key, v) in dict.iter() {
+      let key = key.extract::<String>().map_err(|_| unsupported())?;
+      fields.insert(key, from_python(&v)?);
+    }
+    return Ok(Value::from(fields));
+  }
+
+  return Err(unsupported());
+}
@@ -0,0 +1,283 @@
+#![allow(dead_code)]
+
+// Structural search over scripts, for `sam query`: a grep that matches
+// syntax rather than text. A pattern is either a piece of sam code, whose
+// `$name` metavariables stand for any expression, e.g.
+//
+//   chdir($dir)          every call of chdir, whatever the argument
+//   $a == $a             comparisons of a thing with itself
+//   let $x = [];         declarations of empty arrays
+//
+// or a tree-sitter query over the grammar's node kinds, e.g.
+// `(call_expression) @call`, each match being reported by its first
+// capture. A metavariable written twice must match the same text both
+// times, `$_` matches anything without being bound, and comments are
+// ignored on both sides.
+
+use crate::codes;
+use crate::error::{SamError, Span};
+use crate::syntax::syntax_errors;
+use std::collections::HashMap;
+use tree_sitter::{Node, Query, QueryCursor, StreamingIterator, Tree};
+
+// what metavariables are turned into so that the pattern parses as sam
+const META: &str = "__sam_meta_";
+
+pub enum Pattern {
+  Code { tree: Tree, text: String },
+  Query(Query),
+}
+
+// a node matching a pattern, with what its metavariables or captures
+// matched, by name
+#[derive(Debug, Clone, PartialEq)]
+pub struct Match {
+  pub span: Span,
+  pub captures: Vec<(String, String)>,
+}
+
+impl Pattern {
+  pub fn code(pattern: &str) -> Result<Pattern, SamError> {
+    let text = pattern.trim().replace('$', META);
+
+    // a lone expression may leave out the semicolon of its statement
+    for text in [text.clone(), format!("{};", text)] {
+      let tree = crate::parser().parse(&text, None).unwrap();
+      let root = tree.root_node();
+
+      if !syntax_errors(root, text.as_bytes()).is_empty() {
+        continue;
+      }
+      if root.named_children(&mut root.walk()).count() != 1 {
+        return Err(
+          SamError::parse(
+            codes::INVALID_PATTERN,
+            "A pattern must be a single statement or expression",
+          )
+          .with_help("search for each statement on its own"),
+        );
+      }
+
+      return Ok(Pattern::Code { tree, text });
+    }
+
+    return Err(SamError::parse(
+      codes::INVALID_PATTERN,
+      format!("`{}` is not valid sam", pattern.trim()),
+    ));
+  }
+
+  pub fn query(query: &str) -> Result<Pattern, SamError> {
+    let query = Query::new(&crate::language(), query).map_err(|e| {
+      SamError::parse(
+        codes::INVALID_PATTERN,
+        format!("Invalid query at line {}: {}", e.row + 1, e.message),
+      )
+    })?;
+
+    if query.capture_names().is_empty() {
+      return Err(
+        SamError::parse(
+          codes::INVALID_PATTERN,
+          "The query captures nothing to report",
+        )
+        .with_help("capture the nodes to report, e.g. `(identifier) @name`"),
+      );
+    }
+
+    return Ok(Pattern::Query(query));
+  }
+
+  // the matches in the tree of `source`, in the order of the source
+  pub fn find(&self, root: Node, source: &str) -> Vec<Match> {
+    let mut found = match self {
+      Pattern::Code { tree, text } => {
+        let pattern = subject(tree.root_node());
+        let mut found = Vec::new();
+        search(pattern, text, root, source, &mut found);
+        found
+      }
+      Pattern::Query(query) => run_query(query, root, source),
+    };
+
+    found.sort_by_key(|m| (m.span.start, std::cmp::Reverse(m.span.end)));
+    found.dedup_by_key(|m| (m.span.start, m.span.end));
+    return found;
+  }
+}
+
+/* =========================
+Code patterns
+========================= */
+
+// what a pattern matches: its statement, or the expression of an
+// expression statement, so that `f($x)` finds calls inside expressions too
+fn subject(root: Node) -> Node {
+  let statement = root.named_child(0).unwrap_or(root);
+  return match statement.kind() {
+    "expression_statement" => statement.named_child(0).unwrap_or(statement),
+    _ => statement,
+  };
+}
+
+fn search(
+  pattern: Node,
+  pattern_text: &str,
+  node: Node,
+  source: &str,
+  found: &mut Vec<Match>,
+) {
+  let mut bindings = HashMap::new();
+  if matches(pattern, pattern_text, node, source, &mut bindings) {
+    let mut captures: Vec<(String, String)> = bindings.into_iter().collect();
+    captures.sort();
+    found.push(Match {
+      span: Span::from(node),
+      captures,
+    });
+  }
+
+  let mut walker = node.walk();
+  for child in node.named_children(&mut walker) {
+    search(pattern, pattern_text, child, source, found);
+  }
+}
+
+fn matches(
+  pattern: Node,
+  pattern_text: &str,
+  node: Node,
+  source: &str,
+  bindings: &mut HashMap<String, String>,
+) -> bool {
+  let text = &source[node.byte_range()];
+
+  if let Some(name) = metavariable(pattern, pattern_text) {
+    if !node.is_named() {
+      return false;
+    }
+    if name == "_" {
+      return true;
+    }
+    return match bindings.get(&name) {
+      Some(bound) => bound == text,
+      None => {
+        bindings.insert(name, text.to_owned());
+        true
+      }
+    };
+  }
+
+  if pattern.kind() != node.kind() {
+    return false;
+  }
+
+  let pattern_children = significant_children(pattern);
+  let children = significant_children(node);
+
+  // leaves, and tokens such as operators, compare by their text, where a
+  // `$` of a string is not a metavariable
+  if pattern_children.is_empty() || children.is_empty() {
+    return pattern_children.len() == children.len()
+      && pattern_text[pattern.byte_range()].replace(META, "$") == text;
+  }
+
+  return pattern_children.len() == children.len()
+    && pattern_children
+      .iter()
+      .zip(&children)
+      .all(|(p, n)| matches(*p, pattern_text, *n, source, bindings));
+}
+
+// the name of the metavariable `node` is, if it is one
+fn metavariable(node: Node, text: &str) -> Option<String> {
+  let node = match node.kind() {
+    "expression_statement" => node.named_child(0)?,
+    _ => node,
+  };
+  if node.kind() != "identifier" {
+    return None;
+  }
+
+  let name = text[node.byte_range()].strip_prefix(META)?;
+  return Some(name.to_owned());
+}
+
+fn significant_children(node: Node) -> Vec<Node> {
+  let mut walker = node.walk();
+  return node
+    .children(&mut walker)
+    .filter(|child| !child.is_extra() && !child.is_missing())
+    .collect();
+}
+
+/* =========================
+Tree-sitter queries
+========================= */
+
+fn run_query(query: &Query, root: Node, source: &str) -> Vec<Match> {
+  let names = query.capture_names();
+  let mut found = Vec::new();
+  let mut cursor = QueryCursor::new();
+  let mut matches = cursor.matches(query, root, source.as_bytes());
+
+  while let Some(m) = matches.next() {
+    let Some(first) = m.captures.first() else {
+      continue;
+    };
+
+    let captures = m
+      .captures
+      .iter()
+      .map(|c| {
+        let name = names[c.index as usize].to_owned();
+        (name, source[c.node.byte_range()].to_owned())
+      })
+      .collect();
+
+    found.push(Match {
+      span: Span::from(first.node),
+      captures,
+    });
+  }
+
+  return found;
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn find(pattern: &str, source: &str) -> Vec<String> {
+    let pattern = Pattern::code(pattern).unwrap();
+    let tree = crate::parser().parse(source, None).unwrap();
+    return pattern
+      .find(tree.root_node(), source)
+      .iter()
+      .map(|m| source[m.span.start..m.span.end].to_owned())
+      .collect();
+  }
+
+  #[test]
+  fn test_code_patterns() {
+    let source = "chdir('a'); let b = chdir(x + 1); cwd();";
+    assert_eq!(find("chdir($dir)", source), ["chdir('a')", "chdir(x + 1)"]);
+    assert_eq!(find("let $x = $_;", source), ["let b = chdir(x + 1);"]);
+
+    // a metavariable matches the same text each time
+    let source = "a == a; a == b; // a == a\n";
+    assert_eq!(find("$x == $x", source), ["a == a"]);
+  }
+
+  #[test]
+  fn test_invalid_patterns() {
+    let error = Pattern::code("let = ;").err().unwrap();
+    assert_eq!(error.code(), Some(codes::INVALID_PATTERN));
+
+    let error = Pattern::code("a; b;").err().unwrap();
+    assert_eq!(error.code(), Some(codes::INVALID_PATTERN));
+
+    let error = Pattern::query("(identifier)").err().unwrap();
+    assert_eq!(error.code(), Some(codes::INVALID_PATTERN));
+  }
+}
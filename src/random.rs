@@ -0,0 +1,131 @@
+#![allow(dead_code)]
+
+// Pseudo-random numbers for `rand`, `rand_int`, and `shuffle`:
+// xoshiro256** (https://prng.di.unimi.it/), its state spread from a single
+// seed by SplitMix64 as its authors suggest. Fast and good enough for
+// simulations and sampling, not for secrets.
+//
+// Each run starts from a seed of its own, unless the script calls `seed(n)`
+// to draw the same numbers every time.
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+
+#[derive(Debug, Clone)]
+pub struct Random {
+  state: [u64; 4],
+}
+
+impl Random {
+  pub fn new(seed: u64) -> Random {
+    let mut x = seed;
+    let mut state = [0; 4];
+    for word in &mut state {
+      *word = split_mix(&mut x);
+    }
+
+    return Random { state };
+  }
+
+  // seeded differently every time, from the keys std picks for hash maps
+  pub fn from_entropy() -> Random {
+    return Random::new(RandomState::new().build_hasher().finish());
+  }
+
+  pub fn next_u64(&mut self) -> u64 {
+    let s = &mut self.state;
+    let result = s[1].wrapping_mul(5).rotate_left(7).wrapping_mul(9);
+    let t = s[1] << 17;
+
+    s[2] ^= s[0];
+    s[3] ^= s[1];
+    s[1] ^= s[2];
+    s[0] ^= s[3];
+    s[2] ^= t;
+    s[3] = s[3].rotate_left(45);
+
+    return result;
+  }
+
+  // in [0, 1), with the 53 bits a float holds
+  pub fn next_f64(&mut self) -> f64 {
+    return (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64;
+  }
+
+  // in [low, high], both included; `low` must not be above `high`
+  pub fn range(&mut self, low: i64, high: i64) -> i64 {
+    let span = high.wrapping_sub(low) as u64;
+    if span == u64::MAX {
+      return self.next_u64() as i64;
+    }
+
+    // numbers past the last whole multiple of the range are drawn again,
+    // so that every result is as likely
+    let count = span + 1;
+    let limit = u64::MAX - u64::MAX % count;
+    loop {
+      let x = self.next_u64();
+      if x < limit {
+        return low.wrapping_add((x % count) as i64);
+      }
+    }
+  }
+
+  // Fisher-Yates
+  pub fn shuffle<T>(&mut self, items: &mut [T]) {
+    for i in (1..items.len()).rev() {
+      let j = self.range(0, i as i64) as usize;
+      items.swap(i, j);
+    }
+  }
+}
+
+fn split_mix(x: &mut u64) -> u64 {
+  *x = x.wrapping_add(0x9e3779b97f4a7c15);
+  let mut z = *x;
+  z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+  z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+  return z ^ (z >> 31);
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_seeded() {
+    // the first outputs of the reference implementation, seeded with
+    // SplitMix64 from 0
+    let mut random = Random::new(0);
+    assert_eq!(random.next_u64(), 0x99ec5f36cb75f2b4);
+    assert_eq!(random.next_u64(), 0xbf6e1f784956452a);
+
+    let (mut a, mut b) = (Random::new(7), Random::new(7));
+    let draws: Vec<i64> = (0..20).map(|_| a.range(1, 6)).collect();
+    assert_eq!(draws, (0..20).map(|_| b.range(1, 6)).collect::<Vec<_>>());
+    assert!(draws.iter().all(|n| (1..=6).contains(n)));
+  }
+
+  #[test]
+  fn test_range_bounds() {
+    let mut random = Random::new(1);
+    for _ in 0..1000 {
+      let x = random.next_f64();
+      assert!((0.0..1.0).contains(&x));
+    }
+
+    assert_eq!(random.range(5, 5), 5);
+    // the whole of i64, no overflow
+    random.range(i64::MIN, i64::MAX);
+  }
+
+  #[test]
+  fn test_shuffle() {
+    let mut items: Vec<i64> = (0..50).collect();
+    Random::new(3).shuffle(&mut items);
+
+    assert_ne!(items, (0..50).collect::<Vec<_>>());
+    items.sort();
+    assert_eq!(items, (0..50).collect::<Vec<_>>());
+  }
+}
@@ -0,0 +1,146 @@
+#![allow(dead_code)]
+
+// Incremental parsing: a parser that keeps the tree of the text it parsed
+// last. Parsing the next text tells tree-sitter which region changed, so
+// that only that region is parsed again and the rest of the tree is reused,
+// e.g. when the REPL appends a line to a long session. The same text
+// parsed twice, e.g. checked and then run, gets the same tree.
+
+use crate::SamParser;
+use tree_sitter::{InputEdit, Point, Tree};
+
+pub struct Reparser {
+  parser: SamParser,
+  // the last text parsed and its tree
+  last: Option<(String, Tree)>,
+}
+
+impl Reparser {
+  pub fn new() -> Reparser {
+    return Reparser {
+      parser: crate::parser(),
+      last: None,
+    };
+  }
+
+  // the tree of `text`, reusing what is unchanged of the last tree
+  pub fn parse(&mut self, text: &str) -> Tree {
+    let old = match self.last.take() {
+      Some((old_text, tree)) if old_text == text => {
+        self.last = Some((old_text, tree.clone()));
+        return tree;
+      }
+      Some((old_text, mut tree)) => {
+        tree.edit(&edit(&old_text, text));
+        Some(tree)
+      }
+      None => None,
+    };
+
+    let tree = self.parser.parse(text, old.as_ref()).unwrap();
+    self.last = Some((text.to_owned(), tree.clone()));
+
+    return tree;
+  }
+}
+
+impl Default for Reparser {
+  fn default() -> Self {
+    return Reparser::new();
+  }
+}
+
+// the edit turning `old` into `new`: the region between their common start
+// and common end
+pub fn edit(old: &str, new: &str) -> InputEdit {
+  let (old_bytes, new_bytes) = (old.as_bytes(), new.as_bytes());
+
+  let prefix = old_bytes
+    .iter()
+    .zip(new_bytes)
+    .take_while(|(a, b)| a == b)
+    .count();
+
+  // the common end must not reach into the common start
+  let shorter = old_bytes.len().min(new_bytes.len());
+  let suffix = old_bytes
+    .iter()
+    .rev()
+    .zip(new_bytes.iter().rev())
+    .take(shorter - prefix)
+    .take_while(|(a, b)| a == b)
+    .count();
+
+  let old_end = old_bytes.len() - suffix;
+  let new_end = new_bytes.len() - suffix;
+
+  return InputEdit {
+    start_byte: prefix,
+    old_end_byte: old_end,
+    new_end_byte: new_end,
+    start_position: point_at(old_bytes, prefix),
+    old_end_position: point_at(old_bytes, old_end),
+    new_end_position: point_at(new_bytes, new_end),
+  };
+}
+
+// row and byte column of an offset, as tree-sitter counts them
+fn point_at(text: &[u8], offset: usize) -> Point {
+  let before = &text[..offset];
+  let row = before.iter().filter(|&&b| b == b'\n').count();
+  let line_start = before
+    .iter()
+    .rposition(|&b| b == b'\n')
+    .map_or(0, |n| n + 1);
+
+  return Point::new(row, offset - line_start);
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_edit() {
+    // a line appended, as in the REPL
+    let e = edit("let a = 1;\n", "let a = 1;\nlet b = 2;\n");
+    assert_eq!((e.start_byte, e.old_end_byte, e.new_end_byte), (11, 11, 22));
+    assert_eq!(e.start_position, Point::new(1, 0));
+    assert_eq!(e.new_end_position, Point::new(2, 0));
+
+    // a value changed in the middle
+    let e = edit("let a = 1;\nlet b = 2;\n", "let a = 10;\nlet b = 2;\n");
+    assert_eq!((e.start_byte, e.old_end_byte, e.new_end_byte), (9, 9, 10));
+    assert_eq!(e.start_position, Point::new(0, 9));
+    assert_eq!(e.old_end_position, Point::new(0, 9));
+
+    // repeated text is not counted twice
+    let e = edit("aa", "aaa");
+    assert_eq!((e.start_byte, e.old_end_byte, e.new_end_byte), (2, 2, 3));
+  }
+
+  #[test]
+  fn test_reparse() {
+    let mut reparser = Reparser::new();
+    let mut parser = crate::parser();
+
+    let texts = [
+      "let a = 1;\n",
+      "let a = 1;\n",
+      "let a = 1;\nlet b = a + 1;\n",
+      "let a = 10;\nlet b = a + 1;\n",
+      "let b = 1;\n",
+    ];
+
+    for text in texts {
+      let tree = reparser.parse(text);
+      let fresh = parser.parse(text, None).unwrap();
+      assert_eq!(
+        tree.root_node().to_sexp(),
+        fresh.root_node().to_sexp(),
+        "{}",
+        text
+      );
+    }
+  }
+}
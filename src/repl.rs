@@ -0,0 +1,182 @@
+#![allow(dead_code)]
+
+use crate::config::Config;
+use crate::diagnostic::Diagnostic;
+use crate::highlight;
+use crate::interpreter::Interpreter;
+use crate::output::Writer;
+use crate::report::{ErrorFormat, Reporter};
+use crate::value::Value;
+use std::io::{self, BufRead, IsTerminal};
+use std::process::ExitCode;
+
+// file name shown in diagnostics of REPL input
+const FILE: &str = "<repl>";
+
+const PROMPT: &str = "> ";
+// shown while brackets are left open
+const CONTINUATION: &str = "... ";
+
+// read-eval-print loop over stdin, one interpreter session for all inputs
+pub fn run(mut config: Config, format: ErrorFormat, color: bool) -> ExitCode {
+  // assigning to a new name is the usual way to start a variable here
+  config.auto_declare.get_or_insert(true);
+
+  let output = config.output.clone();
+  let mut interpreter = Interpreter::with_config(config);
+
+  let stdin = io::stdin();
+  let echo = color && stdin.is_terminal();
+  let mut lines = stdin.lock().lines();
+
+  while let Some(input) = read_input(&mut lines, &output.stdout) {
+    if input.trim().is_empty() {
+      continue;
+    }
+
+    if echo {
+      output.stdout.write(highlighted_echo(&input).as_bytes());
+    }
+
+    // input that does not parse is reported in full here, `eval` would stop
+    // at its first error. The interpreter's parser is reused for both, so
+    // the session is parsed once per input.
+    let errors = interpreter.check_syntax(&input);
+    if !errors.is_empty() {
+      let reporter = Reporter {
+        format,
+        color,
+        file: FILE,
+        text: interpreter.source(),
+        out: output.stderr.clone(),
+      };
+      for e in &errors {
+        reporter.emit(&Diagnostic::from(e));
+      }
+      continue;
+    }
+
+    let result = interpreter.eval(&input);
+    let warnings: Vec<Diagnostic> =
+      interpreter.context_mut().warnings.drain(..).collect();
+
+    let reporter = Reporter {
+      format,
+      color,
+      file: FILE,
+      text: interpreter.source(),
+      out: output.stderr.clone(),
+    };
+
+    for w in &warnings {
+      reporter.emit(w);
+    }
+
+    match result {
+      Ok(Value::Undefined) => {}
+      Ok(v) => output.stdout.write_line(&v.to_string()),
+      Err(e) => match e.exit_status() {
+        Some(status) => return ExitCode::from(status),
+        None => reporter.emit(&Diagnostic::from(&e)),
+      },
+    }
+  }
+
+  return ExitCode::SUCCESS;
+}
+
+// one input, continued over several lines while brackets are left open
+fn read_input(
+  lines: &mut impl Iterator<Item = io::Result<String>>,
+  out: &Writer,
+) -> Option<String> {
+  let mut input = String::new();
+  let mut prompt = PROMPT;
+
+  loop {
+    out.write(prompt.as_bytes());
+
+    let Some(Ok(line)) = lines.next() else {
+      // end of input, keep the shell prompt on its own line
+      out.write_line("");
+      return None;
+    };
+
+    input.push_str(&line);
+
+    if open_brackets(&input) <= 0 {
+      return Some(input);
+    }
+
+    input.push('\n');
+    prompt = CONTINUATION;
+  }
+}
+
+// `input` highlighted, written over the lines the terminal echoed while it
+// was typed
+fn highlighted_echo(input: &str) -> String {
+  let lines = highlight::highlight(input);
+  let mut echo = format!("\x1b[{}A", lines.len());
+
+  for (i, line) in lines.iter().enumerate() {
+    let prompt = if i == 0 { PROMPT } else { CONTINUATION };
+    // back to the start of the row, cleared
+    echo.push_str(&format!("\r\x1b[2K{}{}\n", prompt, line));
+  }
+
+  return echo;
+}
+
+// brackets opened but not yet closed, ignoring those inside strings
+pub(crate) fn open_brackets(text: &str) -> i32 {
+  let mut depth = 0;
+  let mut quote = None;
+  let mut chars = text.chars();
+
+  while let Some(c) = chars.next() {
+    match (quote, c) {
+      (Some(_), '\\') => {
+        chars.next();
+      }
+      (Some(q), _) if c == q => quote = None,
+      (Some(_), _) => {}
+      (None, '\'' | '"') => quote = Some(c),
+      (None, '(' | '[' | '{') => depth += 1,
+      (None, ')' | ']' | '}') => depth -= 1,
+      (None, _) => {}
+    }
+  }
+
+  return depth;
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::output::Captured;
+
+  #[test]
+  fn test_open_brackets() {
+    assert_eq!(open_brackets("let f = () => {"), 1);
+    assert_eq!(open_brackets("let a = [1, [2]];"), 0);
+    assert_eq!(open_brackets("let s = '{ \\' (';"), 0);
+  }
+
+  #[test]
+  fn test_read_input_continues() {
+    let mut lines = ["let f = () => {", "  return 1;", "};", "f();"]
+      .into_iter()
+      .map(|l| Ok(l.to_owned()));
+    let prompts = Captured::default();
+    let out = Writer::new(prompts.clone());
+
+    assert_eq!(
+      read_input(&mut lines, &out).unwrap(),
+      "let f = () => {\n  return 1;\n};"
+    );
+    assert_eq!(read_input(&mut lines, &out).unwrap(), "f();");
+    assert!(read_input(&mut lines, &out).is_none());
+    assert_eq!(prompts.contents(), "> ... ... > > \n");
+  }
+}
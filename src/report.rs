@@ -0,0 +1,341 @@
+#![allow(dead_code)]
+
+use crate::diagnostic::{Diagnostic, Severity};
+use crate::highlight;
+use crate::output::Writer;
+use clap::ValueEnum;
+use std::env;
+use std::fmt::Write;
+use std::io::{self, IsTerminal};
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum ErrorFormat {
+  Human,
+  Json,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum ColorChoice {
+  // color when stderr is a terminal and NO_COLOR is not set
+  Auto,
+  Always,
+  Never,
+}
+
+impl ColorChoice {
+  pub fn enabled(self) -> bool {
+    match self {
+      ColorChoice::Always => true,
+      ColorChoice::Never => false,
+      ColorChoice::Auto => {
+        // https://no-color.org: any non-empty value disables color
+        let no_color = env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+        !no_color && io::stderr().is_terminal()
+      }
+    }
+  }
+}
+
+/* =========================
+Reporter
+========================= */
+
+// prints diagnostics for one source file in the requested format
+pub struct Reporter<'a> {
+  pub format: ErrorFormat,
+  pub color: bool,
+  pub file: &'a str,
+  pub text: &'a str,
+  // usually stderr, see `Streams`
+  pub out: Writer,
+}
+
+impl Reporter<'_> {
+  pub fn emit(&self, diagnostic: &Diagnostic) {
+    match self.format {
+      ErrorFormat::Human => {
+        let palette = if self.color { &ANSI } else { &PLAIN };
+        let rendered = diagnostic.render_with(self.file, self.text, palette);
+        self.out.write_line(&rendered);
+      }
+      // one object per line so consumers can stream them
+      ErrorFormat::Json => self
+        .out
+        .write_line(&diagnostic.to_json(self.file).to_string()),
+    }
+  }
+}
+
+/* =========================
+Palettes
+========================= */
+
+// escape sequences used for each part of a rendered diagnostic
+pub struct Palette {
+  error: &'static str,
+  warning: &'static str,
+  bold: &'static str,
+  accent: &'static str,
+  dim: &'static str,
+  reset: &'static str,
+  // whether the line of source shown is syntax highlighted
+  highlight: bool,
+}
+
+pub const PLAIN: Palette = Palette {
+  error: "",
+  warning: "",
+  bold: "",
+  accent: "",
+  dim: "",
+  reset: "",
+  highlight: false,
+};
+
+pub const ANSI: Palette = Palette {
+  error: "\x1b[1;31m",
+  warning: "\x1b[1;33m",
+  bold: "\x1b[1m",
+  accent: "\x1b[1;34m",
+  dim: "\x1b[2m",
+  reset: "\x1b[0m",
+  highlight: true,
+};
+
+impl Palette {
+  fn severity(&self, severity: Severity) -> &'static str {
+    match severity {
+      Severity::Error => self.error,
+      Severity::Warning => self.warning,
+    }
+  }
+}
+
+/* =========================
+Rendering
+========================= */
+
+impl Diagnostic {
+  pub fn render(&self, file: &str, source: &str) -> String {
+    return self.render_with(file, source, &PLAIN);
+  }
+
+  // render in the familiar compiler layout:
+  //
+  //   error: Variable b not defined
+  //    --> script.sam:2:9
+  //     |
+  //   2 | let a = b;
+  //     |         ^ undefined name
+  //
+  pub fn render_with(&self, file: &str, source: &str, p: &Palette) -> String {
+    let mut out = String::new();
+    let color = p.severity(self.severity);
+
+    write!(out, "{}{}", color, self.severity.as_str()).unwrap();
+    if let Some(code) = self.code {
+      write!(out, "[{}]", code).unwrap();
+    }
+    writeln!(out, "{}{}: {}{}", p.reset, p.bold, self.message, p.reset)
+      .unwrap();
+
+    let Some(span) = self.span else {
+      writeln!(out, " {}-->{} {}", p.accent, p.reset, file).unwrap();
+      self.render_notes(&mut out, 1, p);
+      self.render_trace(&mut out, file, p);
+      return out;
+    };
+
+    let row = span.start_point.row;
+    let line = source.lines().nth(row).unwrap_or("");
+    let gutter = (row + 1).to_string().len();
+    let pad = " ".repeat(gutter);
+
+    writeln!(out, "{}{}-->{} {}:{}", pad, p.accent, p.reset, file, span)
+      .unwrap();
+    writeln!(out, "{} {}|{}", pad, p.accent, p.reset).unwrap();
+    // the carets below are lined up with the plain line
+    let shown = match p.highlight {
+      true => highlight::highlight(source)
+        .into_iter()
+        .nth(row)
+        .unwrap_or_default(),
+      false => line.to_owned(),
+    };
+    writeln!(out, "{}{} |{} {}", p.accent, row + 1, p.reset, shown).unwrap();
+
+    // underline up to the end of the span, or the end of the line for spans
+    // covering several lines
+    let start = span.start_point.column.min(line.len());
+    let end = if span.end_point.row == row {
+      span.end_point.column.min(line.len())
+    } else {
+      line.len()
+    };
+
+    // keep tabs so the carets line up with the echoed source
+    let indent: String = line[..start]
+      .chars()
+      .map(|c| if c == '\t' { '\t' } else { ' ' })
+      .collect();
+    let carets = "^".repeat(line[start..end].chars().count().max(1));
+
+    write!(
+      out,
+      "{} {}|{} {}{}{}",
+      pad, p.accent, p.reset, indent, color, carets
+    )
+    .unwrap();
+
+    if let Some(label) = &self.label {
+      write!(out, " {}", label).unwrap();
+    }
+
+    writeln!(out, "{}", p.reset).unwrap();
+
+    self.render_notes(&mut out, gutter, p);
+    self.render_trace(&mut out, file, p);
+
+    return out;
+  }
+
+  fn render_notes(&self, out: &mut String, gutter: usize, p: &Palette) {
+    let pad = " ".repeat(gutter);
+
+    for note in &self.notes {
+      writeln!(out, "{} {}= note: {}{}", pad, p.dim, note, p.reset).unwrap();
+    }
+
+    if let Some(help) = &self.help {
+      writeln!(out, "{} {}= help:{} {}", pad, p.bold, p.reset, help).unwrap();
+    }
+  }
+
+  fn render_trace(&self, out: &mut String, file: &str, p: &Palette) {
+    if self.trace.is_empty() {
+      return;
+    }
+
+    writeln!(out, "{}call trace (most recent call first):", p.dim).unwrap();
+
+    for (i, frame) in self.trace.iter().enumerate() {
+      writeln!(
+        out,
+        "  {}: `{}` called at {}:{}",
+        i, frame.name, file, frame.call_site
+      )
+      .unwrap();
+    }
+
+    out.push_str(p.reset);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::codes;
+  use crate::error::{SamError, Span};
+  use crate::output::Captured;
+  use tree_sitter::Point;
+
+  fn span(row: usize, start: usize, end: usize) -> Span {
+    Span {
+      start: 0,
+      end: 0,
+      start_point: Point { row, column: start },
+      end_point: Point { row, column: end },
+    }
+  }
+
+  #[test]
+  fn test_render_underlines_span() {
+    let source = "let x = 1;\nlet a = foo;\n";
+    let err =
+      SamError::name(codes::UNDEFINED_VARIABLE, "Variable foo not defined")
+        .at(span(1, 8, 11));
+
+    let rendered = Diagnostic::from(&err).render("main.sam", source);
+
+    assert_eq!(
+      rendered,
+      "error[E0001]: Variable foo not defined\n \
+       --> main.sam:2:9\n  \
+       |\n\
+       2 | let a = foo;\n  \
+       |         ^^^ undefined name\n"
+    );
+  }
+
+  #[test]
+  fn test_render_without_span() {
+    let err = SamError::runtime(
+      codes::RETURN_OUTSIDE_FUNCTION,
+      "Return outside function",
+    );
+    let rendered = Diagnostic::from(&err).render("main.sam", "");
+
+    assert_eq!(
+      rendered,
+      "error[E0302]: Return outside function\n --> main.sam\n"
+    );
+  }
+
+  #[test]
+  fn test_render_notes_causes() {
+    let io = std::io::Error::new(std::io::ErrorKind::NotFound, "no such file");
+    let err =
+      SamError::ffi(codes::COMMAND_FAILED, "Could not run").caused_by(io);
+    let rendered = Diagnostic::from(&err).render("main.sam", "");
+
+    assert!(rendered.ends_with(" = note: caused by: no such file\n"));
+  }
+
+  #[test]
+  fn test_render_call_trace() {
+    let err =
+      SamError::runtime(codes::ARGUMENT_COUNT, "Argument count mismatch")
+        .at(span(0, 0, 1))
+        .with_frame("inner", span(2, 4, 9))
+        .with_frame("outer", span(5, 0, 5));
+    let rendered = Diagnostic::from(&err).render("main.sam", "x\n");
+
+    assert!(rendered.ends_with(
+      "call trace (most recent call first):\n  \
+       0: `inner` called at main.sam:3:5\n  \
+       1: `outer` called at main.sam:6:1\n"
+    ));
+  }
+
+  #[test]
+  fn test_render_colored() {
+    let err =
+      SamError::name(codes::UNDEFINED_VARIABLE, "Variable foo not defined");
+    let rendered = Diagnostic::from(&err).render_with("main.sam", "", &ANSI);
+
+    assert!(rendered.starts_with("\x1b[1;31merror[E0001]\x1b[0m"));
+  }
+
+  #[test]
+  fn test_reporter_writer() {
+    let out = Captured::default();
+    let reporter = Reporter {
+      format: ErrorFormat::Json,
+      color: false,
+      file: "main.sam",
+      text: "",
+      out: Writer::new(out.clone()),
+    };
+
+    reporter.emit(&Diagnostic::error("could not run main.sam"));
+
+    assert!(out.contents().starts_with('{'));
+    assert!(out.contents().ends_with("}\n"));
+  }
+
+  #[test]
+  fn test_color_choice() {
+    assert!(ColorChoice::Always.enabled());
+    assert!(!ColorChoice::Never.enabled());
+  }
+}
@@ -0,0 +1,471 @@
+#![allow(dead_code)]
+
+use crate::codes;
+use crate::dylib::CType;
+use crate::error::SamError;
+use crate::suggest::closest;
+use crate::value::Type;
+use serde::de::{self, Deserialize, Deserializer, IgnoredAny, MapAccess};
+use serde_json::Value;
+use std::fmt;
+use std::path::Path;
+
+// what a key of an entry must hold, described for messages
+type Check = (&'static str, fn(&Value) -> bool);
+
+const COMMAND_KEYS: &[(&str, Check)] = &[
+  ("command", ("a string", Value::is_string)),
+  ("shell", ("true or false", Value::is_boolean)),
+  (
+    "input",
+    ("\"argv\", \"json\", or \"bytes\"", |v| {
+      one_of(v, &["argv", "json", "bytes"])
+    }),
+  ),
+  (
+    "output",
+    ("\"json\" or \"bytes\"", |v| one_of(v, &["json", "bytes"])),
+  ),
+  ("timeout", ("a number of seconds", is_seconds)),
+  (
+    "env",
+    ("an object of strings", |v| {
+      v.as_object()
+        .is_some_and(|vars| vars.values().all(Value::is_string))
+    }),
+  ),
+  ("clear_env", ("true or false", Value::is_boolean)),
+  (
+    "exit_status",
+    ("\"error\" or \"result\"", |v| {
+      one_of(v, &["error", "result"])
+    }),
+  ),
+  (
+    "stderr",
+    ("\"capture\", \"inherit\", or \"merge\"", |v| {
+      one_of(v, &["capture", "inherit", "merge"])
+    }),
+  ),
+  ("stream", ("true or false", Value::is_boolean)),
+  (
+    "params",
+    ("an array of types", |v| {
+      v.as_array()
+        .is_some_and(|params| params.iter().all(is_param))
+    }),
+  ),
+  (
+    "returns",
+    ("a type", |v| v.as_str().and_then(Type::parse).is_some()),
+  ),
+  (
+    "cache",
+    ("true, false, or { \"ttl\": seconds }", |v| {
+      v.is_boolean()
+        || v.as_object().is_some_and(|cache| {
+          cache.len() == 1 && cache.get("ttl").is_some_and(is_seconds)
+        })
+    }),
+  ),
+  (
+    "callbacks",
+    ("an array of function names", |v| {
+      v.as_array()
+        .is_some_and(|names| names.iter().all(Value::is_string))
+    }),
+  ),
+  ("persistent", ("true or false", Value::is_boolean)),
+  ("cwd", ("a string", Value::is_string)),
+];
+
+const LIBRARY_KEYS: &[(&str, Check)] = &[
+  ("library", ("a string", Value::is_string)),
+  ("symbol", ("a string", Value::is_string)),
+  (
+    "params",
+    ("an array of i64, f64, or str", |v| {
+      v.as_array().is_some_and(|params| {
+        params
+          .iter()
+          .all(|p| p.as_str().and_then(CType::parse).is_some())
+      })
+    }),
+  ),
+  (
+    "returns",
+    ("i64, f64, str, or void", |v| {
+      v.as_str().and_then(CType::parse).is_some()
+    }),
+  ),
+];
+
+const URL_KEYS: &[(&str, Check)] = &[
+  ("url", ("a string", Value::is_string)),
+  ("timeout", ("a number of seconds", is_seconds)),
+];
+
+fn one_of(v: &Value, options: &[&str]) -> bool {
+  return v.as_str().is_some_and(|s| options.contains(&s));
+}
+
+fn is_seconds(v: &Value) -> bool {
+  return v.as_f64().is_some_and(|s| s.is_finite() && s >= 0.0);
+}
+
+// a type name, or `{ "name": "count", "type": "int" }`
+fn is_param(v: &Value) -> bool {
+  match v {
+    Value::String(ty) => Type::parse(ty).is_some(),
+    Value::Object(param) => {
+      param.get("name").is_some_and(Value::is_string)
+        && param
+          .get("type")
+          .and_then(|ty| ty.as_str())
+          .and_then(Type::parse)
+          .is_some()
+        && param.len() == 2
+    }
+    _ => false,
+  }
+}
+
+/* =========================
+Problems
+========================= */
+
+// one thing wrong with an interface file
+#[derive(Debug, Clone, PartialEq)]
+pub struct Problem {
+  // one-based, none if it could not be found in the text
+  pub line: Option<usize>,
+  pub message: String,
+}
+
+// every problem of a file, shown one per line
+#[derive(Debug)]
+pub struct Problems {
+  path: String,
+  problems: Vec<Problem>,
+}
+
+impl fmt::Display for Problems {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    for (i, problem) in self.problems.iter().enumerate() {
+      if i > 0 {
+        writeln!(f)?;
+      }
+      match problem.line {
+        Some(line) => write!(f, "{}:{}: {}", self.path, line, problem.message)?,
+        None => write!(f, "{}: {}", self.path, problem.message)?,
+      }
+    }
+    return Ok(());
+  }
+}
+
+impl std::error::Error for Problems {}
+
+// check every entry of the interface file at `path`, whose text is
+// `contents` and parsed form `json`, reporting all problems at once
+pub fn validate(
+  path: &str,
+  contents: &str,
+  json: &Value,
+) -> Result<(), SamError> {
+  let problems = check(path, contents, json);
+
+  if problems.is_empty() {
+    return Ok(());
+  }
+
+  let count = match problems.len() {
+    1 => "1 problem".to_owned(),
+    n => format!("{} problems", n),
+  };
+
+  return Err(
+    SamError::ffi(
+      codes::INTERFACE_ENTRY,
+      format!("Interface file {} has {}", path, count),
+    )
+    .caused_by(Problems {
+      path: path.to_owned(),
+      problems,
+    }),
+  );
+}
+
+pub fn check(path: &str, contents: &str, json: &Value) -> Vec<Problem> {
+  let mut problems = Vec::new();
+
+  let Value::Object(entries) = json else {
+    problems.push(Problem {
+      line: None,
+      message: "must be an object mapping names to entries".to_owned(),
+    });
+    return problems;
+  };
+
+  // JSON parsers keep the last of duplicate names, the other formats
+  // reject them while parsing
+  let is_json = !matches!(
+    Path::new(path).extension().and_then(|e| e.to_str()),
+    Some("toml" | "yaml" | "yml")
+  );
+  let names = match serde_json::from_str(contents) {
+    Ok(Names(names)) if is_json => names,
+    _ => Vec::new(),
+  };
+
+  for (i, name) in names.iter().enumerate() {
+    if names[..i].contains(name) {
+      let first = line_of(contents, name, 1).unwrap_or(1);
+      problems.push(Problem {
+        line: line_of(contents, name, first + 1),
+        message: format!("`{}` is defined more than once", name),
+      });
+    }
+  }
+
+  for (name, entry) in entries {
+    let line = line_of(contents, name, 1);
+    check_entry(name, entry, contents, line, &mut problems);
+  }
+
+  problems.sort_by_key(|p| p.line);
+
+  return problems;
+}
+
+fn check_entry(
+  name: &str,
+  entry: &Value,
+  contents: &str,
+  line: Option<usize>,
+  problems: &mut Vec<Problem>,
+) {
+  let mut problem = |line, message| problems.push(Problem { line, message });
+
+  let entry = match entry {
+    Value::String(cmd) if cmd.trim().is_empty() => {
+      problem(line, format!("`{}` has an empty command", name));
+      return;
+    }
+    Value::String(_) => return,
+    Value::Object(entry) => entry,
+    _ => {
+      problem(
+        line,
+        format!("`{}` must be a command string or an object", name),
+      );
+      return;
+    }
+  };
+
+  let kinds: Vec<_> = ["command", "library", "url"]
+    .into_iter()
+    .filter(|kind| entry.contains_key(*kind))
+    .collect();
+
+  let keys = match kinds.as_slice() {
+    ["command"] => COMMAND_KEYS,
+    ["library"] => LIBRARY_KEYS,
+    ["url"] => URL_KEYS,
+    [] => {
+      problem(
+        line,
+        format!("`{}` must have a `command`, `library`, or `url`", name),
+      );
+      return;
+    }
+    _ => {
+      problem(
+        line,
+        format!(
+          "`{}` can only have one of {}",
+          name,
+          kinds
+            .iter()
+            .map(|k| format!("`{}`", k))
+            .collect::<Vec<_>>()
+            .join(", ")
+        ),
+      );
+      return;
+    }
+  };
+
+  for (key, value) in entry {
+    let key_line = line.and_then(|l| line_of(contents, key, l)).or(line);
+
+    let Some((_, (expected, valid))) = keys.iter().find(|(k, _)| k == key)
+    else {
+      let mut message = format!("unknown key `{}` in `{}`", key, name);
+      if let Some(known) = closest(key, keys.iter().map(|(k, _)| *k)) {
+        message.push_str(&format!(", did you mean `{}`?", known));
+      }
+      problem(key_line, message);
+      continue;
+    };
+
+    if !valid(value) {
+      problem(
+        key_line,
+        format!("`{}` of `{}` must be {}", key, name, expected),
+      );
+    }
+  }
+}
+
+// top-level names in the order written, duplicates included
+struct Names(Vec<String>);
+
+impl<'de> Deserialize<'de> for Names {
+  fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+    struct Visitor;
+
+    impl<'de> de::Visitor<'de> for Visitor {
+      type Value = Names;
+
+      fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "an object of interface entries")
+      }
+
+      fn visit_map<A: MapAccess<'de>>(
+        self,
+        mut map: A,
+      ) -> Result<Names, A::Error> {
+        let mut names = Vec::new();
+        while let Some(name) = map.next_key::<String>()? {
+          map.next_value::<IgnoredAny>()?;
+          names.push(name);
+        }
+        return Ok(Names(names));
+      }
+    }
+
+    return d.deserialize_map(Visitor);
+  }
+}
+
+// first line at or after `from` where `key` is written as a key, quoted or
+// not, or as a TOML table header
+fn line_of(contents: &str, key: &str, from: usize) -> Option<usize> {
+  return contents
+    .lines()
+    .enumerate()
+    .skip(from.saturating_sub(1))
+    .find(|(_, line)| declares(line, key))
+    .map(|(i, _)| i + 1);
+}
+
+fn declares(line: &str, key: &str) -> bool {
+  if line.trim() == format!("[{}]", key) {
+    return true;
+  }
+
+  for written in [format!("\"{}\"", key), format!("'{}'", key), key.to_owned()]
+  {
+    for (at, _) in line.match_indices(&written) {
+      let before = line[..at].chars().next_back();
+      let after = line[at + written.len()..].trim_start();
+
+      let starts_word =
+        before.is_none_or(|c| !c.is_alphanumeric() && c != '_' && c != '"');
+      if starts_word && (after.starts_with(':') || after.starts_with('=')) {
+        return true;
+      }
+    }
+  }
+
+  return false;
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn problems(path: &str, contents: &str) -> Vec<Problem> {
+    let json = match path.ends_with(".toml") {
+      true => toml::from_str(contents).unwrap(),
+      false => serde_json::from_str(contents).unwrap(),
+    };
+    return check(path, contents, &json);
+  }
+
+  #[test]
+  fn test_valid_file() {
+    let contents = r#"{
+      "ls": "ls -la",
+      "tool": { "command": "tool", "timeout": 2, "params": ["int"] },
+      "cos": { "library": "libm.so.6", "params": ["f64"], "returns": "f64" },
+      "predict": { "url": "http://localhost:8080/predict" }
+    }"#;
+
+    assert_eq!(problems("tools.json", contents), []);
+  }
+
+  #[test]
+  fn test_every_problem_reported() {
+    let contents = r#"{
+  "tool": {
+    "comand": "tool",
+    "command": "tool",
+    "timeout": "soon"
+  },
+  "ls": "ls",
+  "other": 5,
+  "ls": "ls -la"
+}"#;
+
+    assert_eq!(
+      problems("tools.json", contents),
+      [
+        Problem {
+          line: Some(3),
+          message: "unknown key `comand` in `tool`, did you mean `command`?"
+            .to_owned(),
+        },
+        Problem {
+          line: Some(5),
+          message: "`timeout` of `tool` must be a number of seconds".to_owned(),
+        },
+        Problem {
+          line: Some(8),
+          message: "`other` must be a command string or an object".to_owned(),
+        },
+        Problem {
+          line: Some(9),
+          message: "`ls` is defined more than once".to_owned(),
+        },
+      ]
+    );
+  }
+
+  #[test]
+  fn test_toml_lines() {
+    let contents = "\
+[deploy]
+command = \"./deploy.sh\"
+stream = \"yes\"
+";
+
+    assert_eq!(
+      problems("tools.toml", contents),
+      [Problem {
+        line: Some(3),
+        message: "`stream` of `deploy` must be true or false".to_owned(),
+      }]
+    );
+  }
+
+  #[test]
+  fn test_validate_error() {
+    let json = serde_json::json!({ "a": 1, "b": { "url": 2 } });
+    let err = validate("tools.json", "", &json).unwrap_err();
+
+    assert_eq!(err.code(), Some(codes::INTERFACE_ENTRY));
+    assert_eq!(err.message(), "Interface file tools.json has 2 problems");
+  }
+}
@@ -0,0 +1,85 @@
+// edit distance between two strings, counting insertions, deletions,
+// substitutions, and swaps of adjacent characters as one edit each
+pub fn edit_distance(a: &str, b: &str) -> usize {
+  let a: Vec<char> = a.chars().collect();
+  let b: Vec<char> = b.chars().collect();
+
+  // dist[i][j] is the distance between a[..i] and b[..j]
+  let mut dist = vec![vec![0; b.len() + 1]; a.len() + 1];
+
+  for (i, row) in dist.iter_mut().enumerate() {
+    row[0] = i;
+  }
+  for (j, cell) in dist[0].iter_mut().enumerate() {
+    *cell = j;
+  }
+
+  for i in 1..=a.len() {
+    for j in 1..=b.len() {
+      let cost = if a[i - 1] == b[j - 1] { 0 } else { 1 };
+
+      dist[i][j] = (dist[i - 1][j] + 1)
+        .min(dist[i][j - 1] + 1)
+        .min(dist[i - 1][j - 1] + cost);
+
+      if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+        dist[i][j] = dist[i][j].min(dist[i - 2][j - 2] + 1);
+      }
+    }
+  }
+
+  return dist[a.len()][b.len()];
+}
+
+// the candidate closest to `name`, if any is close enough to be a typo
+pub fn closest<'c>(
+  name: &str,
+  candidates: impl IntoIterator<Item = &'c str>,
+) -> Option<&'c str> {
+  // allow roughly one typo per three characters
+  let limit = (name.chars().count() / 3).max(1);
+
+  return candidates
+    .into_iter()
+    .filter(|c| *c != name)
+    .map(|c| (edit_distance(name, c), c))
+    .filter(|(d, _)| *d <= limit)
+    .min_by_key(|(d, _)| *d)
+    .map(|(_, c)| c);
+}
+
+// help text suggesting the closest candidate
+pub fn did_you_mean<'c>(
+  name: &str,
+  candidates: impl IntoIterator<Item = &'c str>,
+) -> Option<String> {
+  return closest(name, candidates).map(|c| format!("did you mean `{}`?", c));
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_edit_distance() {
+    assert_eq!(edit_distance("kitten", "sitting"), 3);
+    assert_eq!(edit_distance("", "abc"), 3);
+    assert_eq!(edit_distance("<=", "=<"), 1);
+  }
+
+  #[test]
+  fn test_closest() {
+    let names = ["count", "counter", "total"];
+    assert_eq!(closest("coutn", names), Some("count"));
+    assert_eq!(closest("xyz", names), None);
+  }
+
+  #[test]
+  fn test_did_you_mean_operator() {
+    let ops = ["<=", ">=", "==", "!="];
+    assert_eq!(
+      did_you_mean("=>", ops),
+      Some("did you mean `>=`?".to_owned())
+    );
+  }
+}
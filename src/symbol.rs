@@ -0,0 +1,147 @@
+#![allow(dead_code)]
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{BuildHasherDefault, Hasher};
+use std::sync::{LazyLock, RwLock};
+
+// an interned name, e.g. of a variable or a property, compared and hashed as
+// a small integer. Names are interned once for the whole process and never
+// freed, so only names from source code are interned, not keys of data.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Symbol(u32);
+
+#[derive(Default)]
+struct Interner {
+  names: Vec<&'static str>,
+  symbols: HashMap<&'static str, Symbol>,
+}
+
+static INTERNER: LazyLock<RwLock<Interner>> = LazyLock::new(Default::default);
+
+impl Symbol {
+  pub fn intern(name: &str) -> Symbol {
+    // still usable after a panic elsewhere while it was locked
+    let interner = INTERNER.read().unwrap_or_else(|e| e.into_inner());
+    if let Some(&symbol) = interner.symbols.get(name) {
+      return symbol;
+    }
+    drop(interner);
+
+    let mut interner = INTERNER.write().unwrap_or_else(|e| e.into_inner());
+
+    // interned by another thread in the meantime
+    if let Some(&symbol) = interner.symbols.get(name) {
+      return symbol;
+    }
+
+    let name: &'static str = Box::leak(name.into());
+    let symbol = Symbol(interner.names.len() as u32);
+    interner.names.push(name);
+    interner.symbols.insert(name, symbol);
+
+    return symbol;
+  }
+
+  pub fn as_str(self) -> &'static str {
+    let interner = INTERNER.read().unwrap_or_else(|e| e.into_inner());
+    return interner.names[self.0 as usize];
+  }
+}
+
+impl From<&str> for Symbol {
+  fn from(name: &str) -> Symbol {
+    return Symbol::intern(name);
+  }
+}
+
+impl From<&String> for Symbol {
+  fn from(name: &String) -> Symbol {
+    return Symbol::intern(name);
+  }
+}
+
+impl From<String> for Symbol {
+  fn from(name: String) -> Symbol {
+    return Symbol::intern(&name);
+  }
+}
+
+// names are written out as text, and interned again when read back
+impl Serialize for Symbol {
+  fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+    return s.serialize_str(self.as_str());
+  }
+}
+
+impl<'de> Deserialize<'de> for Symbol {
+  fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Symbol, D::Error> {
+    return Ok(Symbol::intern(&String::deserialize(d)?));
+  }
+}
+
+impl PartialEq<str> for Symbol {
+  fn eq(&self, other: &str) -> bool {
+    return self.as_str() == other;
+  }
+}
+
+impl PartialEq<&str> for Symbol {
+  fn eq(&self, other: &&str) -> bool {
+    return self.as_str() == *other;
+  }
+}
+
+impl fmt::Display for Symbol {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(self.as_str())
+  }
+}
+
+impl fmt::Debug for Symbol {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{:?}", self.as_str())
+  }
+}
+
+// hashes a symbol by spreading its number over the bits, rather than
+// hashing the bytes of a name
+#[derive(Default)]
+pub struct SymbolHasher(u64);
+
+impl Hasher for SymbolHasher {
+  fn finish(&self) -> u64 {
+    return self.0;
+  }
+
+  fn write_u32(&mut self, n: u32) {
+    self.0 = (n as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+  }
+
+  // symbols only ever write a u32
+  fn write(&mut self, bytes: &[u8]) {
+    for b in bytes {
+      self.0 =
+        (self.0.rotate_left(8) ^ *b as u64).wrapping_mul(0x100_0000_01b3);
+    }
+  }
+}
+
+pub type SymbolMap<V> = HashMap<Symbol, V, BuildHasherDefault<SymbolHasher>>;
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_intern() {
+    let a = Symbol::intern("count");
+    assert_eq!(a, Symbol::from("count".to_owned()));
+    assert_ne!(a, Symbol::intern("total"));
+
+    assert_eq!(a.as_str(), "count");
+    assert_eq!(a, "count");
+    assert_eq!(a.to_string(), "count");
+  }
+}
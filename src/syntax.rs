@@ -0,0 +1,66 @@
+#![allow(dead_code)]
+
+use crate::codes;
+use crate::error::SamError;
+use tree_sitter::Node;
+
+// longest snippet of unexpected source quoted in a message
+const SNIPPET_LEN: usize = 20;
+
+// collect every ERROR and MISSING node of the tree, in source order, so that
+// all syntax errors can be reported at once instead of evaluating a broken
+// tree
+pub fn syntax_errors(root: Node, source: &[u8]) -> Vec<SamError> {
+  let mut errors = Vec::new();
+  collect(root, source, &mut errors);
+
+  return errors;
+}
+
+fn collect(node: Node, source: &[u8], errors: &mut Vec<SamError>) {
+  if node.is_missing() {
+    errors.push(
+      SamError::parse(
+        codes::MISSING_SYNTAX,
+        format!("Syntax error: missing `{}`", node.kind()),
+      )
+      .at(node),
+    );
+    return;
+  }
+
+  if node.is_error() {
+    // the children of an ERROR node are the tokens the parser skipped,
+    // reporting them separately would only repeat this error
+    errors.push(
+      SamError::parse(
+        codes::UNEXPECTED_SYNTAX,
+        format!("Syntax error: unexpected `{}`", snippet(node, source)),
+      )
+      .at(node),
+    );
+    return;
+  }
+
+  // only subtrees containing errors are worth walking
+  if !node.has_error() {
+    return;
+  }
+
+  let mut walker = node.walk();
+  for child in node.children(&mut walker) {
+    collect(child, source, errors);
+  }
+}
+
+fn snippet(node: Node, source: &[u8]) -> String {
+  let text = node.utf8_text(source).unwrap_or("").trim();
+  let line = text.lines().next().unwrap_or("");
+
+  if line.chars().count() > SNIPPET_LEN || line.len() < text.len() {
+    let short: String = line.chars().take(SNIPPET_LEN).collect();
+    return format!("{}...", short);
+  }
+
+  return line.to_owned();
+}
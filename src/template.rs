@@ -0,0 +1,440 @@
+#![allow(dead_code)]
+
+// Format strings of `format`, `print`, and `println`, in the style of
+// Rust's and Python's: text with placeholders in braces, each replaced by
+// an argument, e.g.
+//
+//   {}        the next argument, as `print` writes it
+//   {1}       the second argument, wherever it is
+//   {:>8}     right-aligned in 8 characters
+//   {:*^9}    centred, padded with stars
+//   {:+.2}    with a sign and 2 decimals
+//   {:08.3}   padded with zeros after the sign
+//   {:x}      an integer in hexadecimal, or `X`, `o`, `b`
+//
+// Numbers align right and everything else left unless told otherwise. A
+// precision cuts other values to that many characters. `{{` and `}}` are
+// literal braces.
+
+use crate::codes;
+use crate::error::SamError;
+use crate::value::{Number, Value};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+  pieces: Vec<Piece>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Piece {
+  Text(String),
+  Field { index: usize, spec: Spec },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Align {
+  Left,
+  Right,
+  Center,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind {
+  Display,
+  Binary,
+  Octal,
+  Hex,
+  UpperHex,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Spec {
+  fill: char,
+  align: Option<Align>,
+  sign: bool,
+  zero: bool,
+  width: usize,
+  precision: Option<usize>,
+  kind: Kind,
+}
+
+impl Template {
+  pub fn parse(template: &str) -> Result<Template, SamError> {
+    let mut pieces = Vec::new();
+    let mut text = String::new();
+    let mut next = 0;
+    let mut chars = template.chars().peekable();
+
+    while let Some(c) = chars.next() {
+      match c {
+        '{' if chars.peek() == Some(&'{') => {
+          chars.next();
+          text.push('{');
+        }
+        '}' if chars.peek() == Some(&'}') => {
+          chars.next();
+          text.push('}');
+        }
+        '}' => return Err(invalid("a `}` that closes nothing")),
+        '{' => {
+          let mut field = String::new();
+          let mut closed = false;
+          for c in chars.by_ref() {
+            if c == '}' {
+              closed = true;
+              break;
+            }
+            field.push(c);
+          }
+          if !closed {
+            return Err(invalid("a `{` that is never closed"));
+          }
+
+          let (index, spec) = field.split_once(':').unwrap_or((&field, ""));
+          let index = match index {
+            "" => {
+              next += 1;
+              next - 1
+            }
+            index => index.parse().map_err(|_| {
+              invalid(&format!("`{}` for the position of an argument", index))
+            })?,
+          };
+
+          pieces.push(Piece::Text(std::mem::take(&mut text)));
+          pieces.push(Piece::Field {
+            index,
+            spec: Spec::parse(spec)?,
+          });
+        }
+        c => text.push(c),
+      }
+    }
+
+    pieces.push(Piece::Text(text));
+    pieces.retain(|piece| *piece != Piece::Text(String::new()));
+
+    return Ok(Template { pieces });
+  }
+
+  // whether there is anything to replace
+  pub fn has_fields(&self) -> bool {
+    return self
+      .pieces
+      .iter()
+      .any(|piece| matches!(piece, Piece::Field { .. }));
+  }
+
+  // the text with the fields replaced by `args`, the values after the
+  // format string, each of which must be used; `name` is the builtin's for
+  // errors. No width or precision may be above `max`, the size of the
+  // largest value allowed.
+  pub fn render(
+    &self,
+    name: &str,
+    args: &[Value],
+    max: usize,
+  ) -> Result<String, SamError> {
+    let mut out = String::new();
+    let mut used = vec![false; args.len()];
+
+    for piece in &self.pieces {
+      match piece {
+        Piece::Text(text) => out.push_str(text),
+        Piece::Field { index, spec } => {
+          let Some(value) = args.get(*index) else {
+            return Err(SamError::runtime(
+              codes::ARGUMENT_COUNT,
+              format!(
+                "`{}` has a placeholder for value {} but {} were given",
+                name,
+                index + 1,
+                args.len()
+              ),
+            ));
+          };
+          used[*index] = true;
+          out.push_str(&spec.apply(name, *index, value, max)?);
+        }
+      }
+    }
+
+    if let Some(unused) = used.iter().position(|used| !used) {
+      return Err(
+        SamError::runtime(
+          codes::ARGUMENT_COUNT,
+          format!("Value {} of `{}` has no placeholder", unused + 1, name),
+        )
+        .with_help("add a `{}` for it to the format string"),
+      );
+    }
+
+    return Ok(out);
+  }
+}
+
+impl Spec {
+  // what follows the `:` of a placeholder:
+  // [[fill]align][+][0][width][.precision][kind]
+  fn parse(spec: &str) -> Result<Spec, SamError> {
+    let mut result = Spec {
+      fill: ' ',
+      align: None,
+      sign: false,
+      zero: false,
+      width: 0,
+      precision: None,
+      kind: Kind::Display,
+    };
+
+    let chars: Vec<char> = spec.chars().collect();
+    let mut i = 0;
+
+    let align = |c: Option<&char>| match c {
+      Some('<') => Some(Align::Left),
+      Some('>') => Some(Align::Right),
+      Some('^') => Some(Align::Center),
+      _ => None,
+    };
+    if let Some(a) = align(chars.get(1)) {
+      result.fill = chars[0];
+      result.align = Some(a);
+      i = 2;
+    } else if let Some(a) = align(chars.first()) {
+      result.align = Some(a);
+      i = 1;
+    }
+
+    if chars.get(i) == Some(&'+') {
+      result.sign = true;
+      i += 1;
+    }
+    if chars.get(i) == Some(&'0') {
+      result.zero = true;
+      i += 1;
+    }
+
+    let digits = |i: &mut usize| {
+      let start = *i;
+      while chars.get(*i).is_some_and(|c| c.is_ascii_digit()) {
+        *i += 1;
+      }
+      let digits: String = chars[start..*i].iter().collect();
+      // too many digits for a usize is past any size limit
+      return match digits.is_empty() {
+        true => None,
+        false => Some(digits.parse::<usize>().unwrap_or(usize::MAX)),
+      };
+    };
+
+    result.width = digits(&mut i).unwrap_or(0);
+    if chars.get(i) == Some(&'.') {
+      i += 1;
+      let Some(precision) = digits(&mut i) else {
+        return Err(invalid("a `.` without a precision after it"));
+      };
+      result.precision = Some(precision);
+    }
+
+    result.kind = match &chars[i..] {
+      [] => Kind::Display,
+      ['b'] => Kind::Binary,
+      ['o'] => Kind::Octal,
+      ['x'] => Kind::Hex,
+      ['X'] => Kind::UpperHex,
+      _ => return Err(invalid(&format!("`{{:{}}}`", spec))),
+    };
+
+    return Ok(result);
+  }
+
+  fn apply(
+    &self,
+    name: &str,
+    index: usize,
+    value: &Value,
+    max: usize,
+  ) -> Result<String, SamError> {
+    // padding and decimals are made in full, so a huge width or precision
+    // fails before taking all the memory
+    let largest = self.width.max(self.precision.unwrap_or(0));
+    if largest > max {
+      return Err(SamError::runtime(
+        codes::LIMIT_EXCEEDED,
+        format!(
+          "Value {} of `{}` would be written in {} characters, past the limit \
+           of {}",
+          index + 1,
+          name,
+          largest,
+          max
+        ),
+      ));
+    }
+
+    let number = match value {
+      Value::SamNumber(n) => Some(*n),
+      _ => None,
+    };
+
+    let text = match (self.kind, number) {
+      (Kind::Display, Some(n)) => match self.precision {
+        Some(precision) => format!("{:.*}", precision, n.as_f64().abs()),
+        None => format!("{}", magnitude(n)),
+      },
+      (Kind::Display, None) => {
+        let text = value.to_string();
+        match self.precision {
+          Some(precision) => text.chars().take(precision).collect(),
+          None => text,
+        }
+      }
+      (kind, Some(Number::SamInt(n))) => {
+        let n = n.unsigned_abs();
+        match kind {
+          Kind::Binary => format!("{:b}", n),
+          Kind::Octal => format!("{:o}", n),
+          Kind::Hex => format!("{:x}", n),
+          _ => format!("{:X}", n),
+        }
+      }
+      _ => {
+        return Err(SamError::type_error(
+          codes::ARGUMENT_TYPE,
+          format!(
+            "Value {} of `{}` must be an integer to be written in another \
+             base, found {}",
+            index + 1,
+            name,
+            value.type_name()
+          ),
+        ));
+      }
+    };
+
+    let sign = match number {
+      Some(n) if n.as_f64().is_sign_negative() && !is_zero(n) => "-",
+      Some(_) if self.sign => "+",
+      _ => "",
+    };
+
+    let length = sign.chars().count() + text.chars().count();
+    let padding = self.width.saturating_sub(length);
+
+    // zeros go between the sign and the digits, whatever the alignment
+    if self.zero && number.is_some() {
+      return Ok(format!("{}{}{}", sign, "0".repeat(padding), text));
+    }
+
+    let default = match number {
+      Some(_) => Align::Right,
+      None => Align::Left,
+    };
+    let (before, after) = match self.align.unwrap_or(default) {
+      Align::Left => (0, padding),
+      Align::Right => (padding, 0),
+      Align::Center => (padding / 2, padding - padding / 2),
+    };
+
+    let fill = |n: usize| self.fill.to_string().repeat(n);
+    return Ok(format!("{}{}{}{}", fill(before), sign, text, fill(after)));
+  }
+}
+
+// the number without its sign, which is written separately
+fn magnitude(n: Number) -> Number {
+  return match n {
+    Number::SamInt(i) => match i.checked_abs() {
+      Some(i) => Number::SamInt(i),
+      None => Number::SamFloat((i as f64).abs()),
+    },
+    Number::SamFloat(f) => Number::SamFloat(f.abs()),
+  };
+}
+
+fn is_zero(n: Number) -> bool {
+  return n.as_f64() == 0.0;
+}
+
+fn invalid(what: &str) -> SamError {
+  return SamError::value(
+    codes::INVALID_FORMAT,
+    format!("Invalid format string: {}", what),
+  )
+  .with_help("write `{{` and `}}` for literal braces");
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn format(template: &str, args: &[Value]) -> String {
+    let template = Template::parse(template).unwrap();
+    return template.render("format", args, 1 << 30).unwrap();
+  }
+
+  #[test]
+  fn test_alignment_and_precision() {
+    let name = Value::from("disk");
+    let pct = Value::from(0.4567 * 100.0);
+    let row = format("{:>8}|{:.2}%|{:<6}|", &[name, pct, Value::from(7i64)]);
+    assert_eq!(row, "    disk|45.67%|7     |");
+
+    assert_eq!(format("{:*^9}", &[Value::from("mid")]), "***mid***");
+    assert_eq!(format("{:.3}", &[Value::from("truncated")]), "tru");
+    assert_eq!(format("{:+}", &[Value::from(5i64)]), "+5");
+    assert_eq!(format("{:08.3}", &[Value::from(-1.23456)]), "-001.235");
+    assert_eq!(format("{{{}}}", &[Value::from(1i64)]), "{1}");
+  }
+
+  #[test]
+  fn test_bases_and_positions() {
+    let n = Value::from(255i64);
+    assert_eq!(
+      format("{0:x} {0:X} {0:o} {0:b}", &[n]),
+      "ff FF 377 11111111"
+    );
+    assert_eq!(format("{:#>6b}", &[Value::from(-5i64)]), "##-101");
+
+    let args = [Value::from("a"), Value::from("b")];
+    assert_eq!(format("{1}{0}{1}", &args), "bab");
+  }
+
+  #[test]
+  fn test_errors() {
+    for template in ["{", "}", "{:q}", "{:.}", "{x}"] {
+      let e = Template::parse(template).unwrap_err();
+      assert_eq!(e.code(), Some(codes::INVALID_FORMAT), "{}", template);
+    }
+
+    let template = Template::parse("{} {}").unwrap();
+    let e = template
+      .render("format", &[Value::from(1i64)], 10)
+      .unwrap_err();
+    assert_eq!(e.code(), Some(codes::ARGUMENT_COUNT));
+
+    let template = Template::parse("{:x}").unwrap();
+    let e = template
+      .render("format", &[Value::from(1.5)], 10)
+      .unwrap_err();
+    assert_eq!(e.code(), Some(codes::ARGUMENT_TYPE));
+  }
+
+  #[test]
+  fn test_size_limit() {
+    let one = [Value::from(1i64)];
+    for spec in ["{:11}", "{:.11}", "{:99999999999}", "{:.99999999999}"] {
+      let template = Template::parse(spec).unwrap();
+      let e = template.render("format", &one, 10).unwrap_err();
+      assert_eq!(e.code(), Some(codes::LIMIT_EXCEEDED), "{}", spec);
+    }
+
+    // more digits than a usize holds
+    let template = Template::parse("{:999999999999999999999}").unwrap();
+    let e = template.render("format", &one, 10).unwrap_err();
+    assert_eq!(e.code(), Some(codes::LIMIT_EXCEEDED));
+
+    let template = Template::parse("{:10.10}").unwrap();
+    assert_eq!(template.render("format", &one, 10).unwrap().len(), 12);
+  }
+}
@@ -0,0 +1,182 @@
+#![allow(dead_code)]
+
+// `sam test`: the tests of a script are its top level functions named
+// `test_*`, e.g.
+//
+//   let add = (a, b) => { return a + b; };
+//   let test_add = () => { assert_eq(add(2, 2), 4); };
+//
+// The script runs first, to declare them, and then every test is called on
+// a copy of the context it left, so that what one test changes is not seen
+// by the next. A test passes if it returns and fails with the error it
+// raised, usually that of a failed `assert`. Scripts in a `tests`
+// directory without such functions are a test each, passing if they run
+// without an error. Other scripts without tests are not run at all.
+
+use crate::ast::{self, ExprKind, Program, StmtKind};
+use crate::crash;
+use crate::error::{SamError, Span};
+use crate::evaluate::backend_call;
+use crate::interpreter::Interpreter;
+use crate::symbol::Symbol;
+use std::time::{Duration, Instant};
+use tree_sitter::Node;
+
+pub const PREFIX: &str = "test_";
+
+#[derive(Debug)]
+pub enum Outcome {
+  Passed,
+  Failed(SamError),
+}
+
+#[derive(Debug)]
+pub struct TestResult {
+  // the test function, or the script if it is the test
+  pub name: String,
+  pub outcome: Outcome,
+  pub duration: Duration,
+}
+
+impl TestResult {
+  pub fn passed(&self) -> bool {
+    return matches!(self.outcome, Outcome::Passed);
+  }
+}
+
+// the test functions of `program`, in the order they are declared
+pub fn discover(program: &Program) -> Vec<(Symbol, Span)> {
+  let mut tests = Vec::new();
+
+  for stmt in &program.statements {
+    let StmtKind::Declaration { declarators, .. } = &stmt.kind else {
+      continue;
+    };
+
+    for declarator in declarators {
+      let is_function = matches!(
+        declarator.value.as_ref().map(|v| &v.kind),
+        Some(ExprKind::Lambda { .. })
+      );
+      if is_function && declarator.name.name.as_str().starts_with(PREFIX) {
+        tests.push((declarator.name.name, declarator.span));
+      }
+    }
+  }
+
+  return tests;
+}
+
+// run the tests of the script `file`, of source `source` and tree `root`,
+// in `interpreter`; `is_test` if the script is a test itself
+pub fn run(
+  root: Node,
+  source: &str,
+  file: &str,
+  is_test: bool,
+  interpreter: &mut Interpreter,
+) -> Result<Vec<TestResult>, SamError> {
+  let program = ast::lower(root, source.as_bytes())?;
+  let tests = discover(&program);
+
+  if tests.is_empty() && !is_test {
+    return Ok(Vec::new());
+  }
+
+  // a script that does not even load fails as a whole
+  let started = Instant::now();
+  let outcome = match interpreter.eval(source) {
+    Ok(_) => Outcome::Passed,
+    Err(e) => Outcome::Failed(e),
+  };
+  if tests.is_empty() || !matches!(outcome, Outcome::Passed) {
+    return Ok(vec![TestResult {
+      name: file.to_owned(),
+      outcome,
+      duration: started.elapsed(),
+    }]);
+  }
+
+  let ctx = interpreter.context();
+  let call = backend_call(ctx.config.backend);
+
+  let mut results = Vec::new();
+  for (name, span) in tests {
+    let Some(f) = ctx.get_global(name.as_str()).cloned() else {
+      continue;
+    };
+
+    let mut child = ctx.child();
+    let started = Instant::now();
+    let outcome = match crash::catch(|| call(f, Vec::new(), span, &mut child)) {
+      Ok(_) => Outcome::Passed,
+      Err(e) => Outcome::Failed(e),
+    };
+
+    results.push(TestResult {
+      name: name.to_string(),
+      outcome,
+      duration: started.elapsed(),
+    });
+  }
+
+  return Ok(results);
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn run_source(source: &str, is_test: bool) -> Vec<(String, bool)> {
+    let tree = crate::parser().parse(source, None).unwrap();
+    let mut interpreter = Interpreter::new();
+
+    return run(tree.root_node(), source, "t.sam", is_test, &mut interpreter)
+      .unwrap()
+      .into_iter()
+      .map(|r| (r.name.clone(), r.passed()))
+      .collect();
+  }
+
+  #[test]
+  fn test_run() {
+    let source = "
+      let count = 1;
+      let add = (a, b) => { return a + b; };
+      let test_add = () => { assert_eq(add(2, 2), 4); };
+      let test_wrong = () => { assert(add(2, 2) == 5, 'bad math'); };
+      let test_isolated = () => { count = 2; assert_eq(count, 2); };
+      let test_unchanged = () => { assert_eq(count, 1); };
+    ";
+
+    assert_eq!(
+      run_source(source, false),
+      vec![
+        ("test_add".to_owned(), true),
+        ("test_wrong".to_owned(), false),
+        ("test_isolated".to_owned(), true),
+        ("test_unchanged".to_owned(), true),
+      ]
+    );
+  }
+
+  #[test]
+  fn test_scripts_without_tests() {
+    // not run unless the script is a test itself
+    assert!(run_source("assert(0);", false).is_empty());
+    assert_eq!(
+      run_source("assert(0);", true),
+      vec![("t.sam".to_owned(), false)]
+    );
+    assert_eq!(
+      run_source("assert(1);", true),
+      vec![("t.sam".to_owned(), true)]
+    );
+
+    // nor are its tests if it fails to load
+    assert_eq!(
+      run_source("let test_a = () => {}; assert(0);", false),
+      vec![("t.sam".to_owned(), false)]
+    );
+  }
+}
@@ -0,0 +1,109 @@
+#![allow(dead_code)]
+
+// Dates in UTC for `format_time` and the headers of the Jupyter kernel,
+// without a time zone database: local time would depend on the machine a
+// script runs on.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// ISO 8601, e.g. 2024-03-01T12:30:00Z
+pub const ISO_8601: &str = "%Y-%m-%dT%H:%M:%SZ";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DateTime {
+  pub year: i64,
+  pub month: u32,
+  pub day: u32,
+  pub hour: u32,
+  pub minute: u32,
+  pub second: u32,
+  pub micros: u32,
+}
+
+impl DateTime {
+  // `seconds` since the Unix epoch, before it if negative
+  pub fn from_unix(seconds: i64, micros: u32) -> DateTime {
+    let (days, of_day) = (seconds.div_euclid(86400), seconds.rem_euclid(86400));
+
+    // days to a civil date, from
+    // https://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    return DateTime {
+      year,
+      month: month as u32,
+      day: day as u32,
+      hour: (of_day / 3600) as u32,
+      minute: (of_day % 3600 / 60) as u32,
+      second: (of_day % 60) as u32,
+      micros,
+    };
+  }
+
+  pub fn from_system(time: SystemTime) -> DateTime {
+    let since = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    return DateTime::from_unix(since.as_secs() as i64, since.subsec_micros());
+  }
+
+  // `pattern` with strftime's directives replaced: %Y %m %d %H %M %S, %f
+  // for microseconds, and %% for a percent sign. An unknown directive is
+  // the error.
+  pub fn format(&self, pattern: &str) -> Result<String, String> {
+    let mut out = String::new();
+    let mut chars = pattern.chars();
+
+    while let Some(c) = chars.next() {
+      if c != '%' {
+        out.push(c);
+        continue;
+      }
+
+      let part = match chars.next() {
+        Some('Y') => format!("{:04}", self.year),
+        Some('m') => format!("{:02}", self.month),
+        Some('d') => format!("{:02}", self.day),
+        Some('H') => format!("{:02}", self.hour),
+        Some('M') => format!("{:02}", self.minute),
+        Some('S') => format!("{:02}", self.second),
+        Some('f') => format!("{:06}", self.micros),
+        Some('%') => "%".to_owned(),
+        Some(other) => return Err(format!("%{}", other)),
+        None => return Err("%".to_owned()),
+      };
+      out.push_str(&part);
+    }
+
+    return Ok(out);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_civil_dates() {
+    let leap_day = DateTime::from_unix(951_827_696, 123);
+    let formatted = leap_day.format("%Y-%m-%dT%H:%M:%S.%fZ").unwrap();
+    assert_eq!(formatted, "2000-02-29T12:34:56.000123Z");
+
+    let before = DateTime::from_unix(-1, 0);
+    assert_eq!(before.format(ISO_8601).unwrap(), "1969-12-31T23:59:59Z");
+  }
+
+  #[test]
+  fn test_directives() {
+    let epoch = DateTime::from_unix(0, 0);
+    assert_eq!(epoch.format("%d/%m/%Y 100%%").unwrap(), "01/01/1970 100%");
+    assert_eq!(epoch.format("%Q"), Err("%Q".to_owned()));
+    assert_eq!(epoch.format("50%"), Err("%".to_owned()));
+  }
+}
@@ -0,0 +1,250 @@
+#![allow(dead_code)]
+
+use crate::error::Span;
+use crate::output::Writer;
+use crate::process;
+use std::fmt;
+use std::process::{Command, ExitStatus};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+// what an external command did, reported once it ended
+#[derive(Debug, Clone, PartialEq)]
+pub struct CallRecord {
+  pub program: String,
+  pub args: Vec<String>,
+  pub duration: Duration,
+  // exit code, none if the command was killed or could not be started
+  pub status: Option<i64>,
+  pub stdout_bytes: usize,
+  pub stderr_bytes: usize,
+}
+
+// receives a record of every external command, e.g. to print an audit log
+// with `--trace-ffi`
+#[derive(Clone)]
+pub struct Trace(Arc<dyn Fn(&CallRecord) + Send + Sync>);
+
+impl Trace {
+  pub fn new(f: impl Fn(&CallRecord) + Send + Sync + 'static) -> Self {
+    return Trace(Arc::new(f));
+  }
+
+  // print every record to `out`, one per line
+  pub fn to(out: Writer) -> Self {
+    return Trace::new(move |record| {
+      out.write_line(&format!("trace: {}", record))
+    });
+  }
+}
+
+impl fmt::Debug for Trace {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "Trace")
+  }
+}
+
+// e.g. `git log '--format=%h %s' (0.012s, exit 0, 1234 bytes out, 0 err)`
+impl fmt::Display for CallRecord {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}", quote(&self.program))?;
+    for arg in &self.args {
+      write!(f, " {}", quote(arg))?;
+    }
+
+    let status = match self.status {
+      Some(code) => format!("exit {}", code),
+      None => "killed".to_owned(),
+    };
+
+    write!(
+      f,
+      " ({:.3}s, {}, {} bytes out, {} err)",
+      self.duration.as_secs_f64(),
+      status,
+      self.stdout_bytes,
+      self.stderr_bytes
+    )
+  }
+}
+
+// quote a word for the log the way a shell would need it
+fn quote(word: &str) -> String {
+  let plain = !word.is_empty()
+    && word
+      .chars()
+      .all(|c| c.is_alphanumeric() || "-_./=:,+@%".contains(c));
+
+  if plain {
+    return word.to_owned();
+  }
+
+  return format!("'{}'", word.replace('\'', "'\\''"));
+}
+
+// a traced command that is running
+pub struct Started {
+  trace: Trace,
+  program: String,
+  args: Vec<String>,
+  at: Instant,
+}
+
+impl Started {
+  // none if nothing is traced
+  pub fn new(cmd: &Command, trace: &Option<Trace>) -> Option<Started> {
+    let trace = trace.clone()?;
+
+    return Some(Started {
+      trace,
+      program: cmd.get_program().to_string_lossy().into_owned(),
+      args: cmd
+        .get_args()
+        .map(|a| a.to_string_lossy().into_owned())
+        .collect(),
+      at: Instant::now(),
+    });
+  }
+
+  pub fn finish(
+    self,
+    status: Option<ExitStatus>,
+    stdout_bytes: usize,
+    stderr_bytes: usize,
+  ) {
+    (self.trace.0)(&CallRecord {
+      program: self.program,
+      args: self.args,
+      duration: self.at.elapsed(),
+      status: status.map(process::status_code),
+      stdout_bytes,
+      stderr_bytes,
+    });
+  }
+}
+
+// a statement or expression the tree walker finished, reported with
+// `--trace`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Step {
+  pub span: Span,
+  // sam function calls it ran in
+  pub depth: usize,
+  // the value it evaluated to, or how else it ended
+  pub outcome: String,
+}
+
+// receives every step of the tree walker, a debugger of sorts for scripts
+// that misbehave
+#[derive(Clone)]
+pub struct Tracer(Arc<dyn Fn(&Step) + Send + Sync>);
+
+impl Tracer {
+  pub fn new(f: impl Fn(&Step) + Send + Sync + 'static) -> Self {
+    return Tracer(Arc::new(f));
+  }
+
+  // print every step to `out` with the part of `source` it ran
+  pub fn to(out: Writer, source: &str) -> Self {
+    let source: Arc<str> = source.into();
+    return Tracer::new(move |step| {
+      out.write_line(&format!("trace: {}", step.render(&source)))
+    });
+  }
+
+  pub fn step(&self, step: &Step) {
+    (self.0)(step);
+  }
+}
+
+impl fmt::Debug for Tracer {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "Tracer")
+  }
+}
+
+impl Step {
+  // e.g. `  3:9 a + 1 => 2`, indented by the depth
+  pub fn render(&self, source: &str) -> String {
+    let code = source.get(self.span.start..self.span.end).unwrap_or("");
+
+    return format!(
+      "{}{}:{} {} => {}",
+      "  ".repeat(self.depth),
+      self.span.start_point.row + 1,
+      self.span.start_point.column + 1,
+      compact(code),
+      compact(&self.outcome)
+    );
+  }
+}
+
+// `text` on one line and cut short, so that every step takes one line
+fn compact(text: &str) -> String {
+  const MAX: usize = 40;
+
+  let words = text.split_whitespace().collect::<Vec<_>>().join(" ");
+  if words.chars().count() <= MAX {
+    return words;
+  }
+
+  let cut: String = words.chars().take(MAX - 3).collect();
+  return format!("{}...", cut);
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use tree_sitter::Point;
+
+  #[test]
+  fn test_render_step() {
+    let source = "let a = 1;\nlet f = () => {\n  return a + 1;\n};\n";
+    let start = source.find("a + 1").unwrap();
+    let step = Step {
+      span: Span {
+        start,
+        end: start + 5,
+        start_point: Point::new(2, 9),
+        end_point: Point::new(2, 14),
+      },
+      depth: 1,
+      outcome: "2".to_owned(),
+    };
+    assert_eq!(step.render(source), "  3:10 a + 1 => 2");
+
+    // long code and values take one line
+    let start = source.find("let f").unwrap();
+    let step = Step {
+      span: Span {
+        start,
+        end: source.len() - 1,
+        start_point: Point::new(1, 0),
+        end_point: Point::new(3, 2),
+      },
+      depth: 0,
+      outcome: "[\n  1,\n  2\n]".to_owned(),
+    };
+    assert_eq!(
+      step.render(source),
+      "2:1 let f = () => { return a + 1; }; => [ 1, 2 ]"
+    );
+  }
+
+  #[test]
+  fn test_display() {
+    let record = CallRecord {
+      program: "git".to_owned(),
+      args: vec!["log".to_owned(), "--format=%h %s".to_owned()],
+      duration: Duration::from_millis(250),
+      status: Some(0),
+      stdout_bytes: 1234,
+      stderr_bytes: 0,
+    };
+
+    assert_eq!(
+      record.to_string(),
+      "git log '--format=%h %s' (0.250s, exit 0, 1234 bytes out, 0 err)"
+    );
+  }
+}
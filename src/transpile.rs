@@ -0,0 +1,2699 @@
+#![allow(dead_code)]
+
+// `sam compile --target js script.sam`: the script as JavaScript, or as Lua
+// with `--target lua`, to reuse its logic where sam cannot run, e.g. in a
+// Node service or a program embedding Lua.
+//
+// The output starts with a small runtime, `__sam`, which runs external
+// commands with child_process in JavaScript and io.popen in Lua and has the
+// builtins. Calls to names the script never declares run the command of
+// that name, as they do in sam:
+//
+//   let files = ls('-1');   ->   let files = __sam.run("ls", ["-1"]);
+//
+// Interface files are read while compiling, and their entries become
+// functions running their command. The rest is written with the operators
+// and statements of the target, to be read and changed by hand. Where the
+// target does things differently, so does the compiled script:
+//
+//   - functions see the variables where they are written, not where they
+//     are called
+//   - comparisons are true and false rather than 1 and 0, and `&&` and `||`
+//     stop at the operand that decides them
+//   - arrays and objects are only equal to themselves
+//   - spawned calls run to the end before `spawn` returns
+//   - remainders of floats have the sign of the dividend
+//   - `+` of a string and a number joins them, in JavaScript
+//   - `seed` makes random numbers repeat, but not those sam draws
+//   - in Lua, times are whole seconds and `clock` counts processor time
+//   - in Lua, integer results out of the i64 range wrap around rather than
+//     becoming floats
+//   - `http_get` and `http_post` run curl, and in Lua `sha256` and `md5` run
+//     sha256sum and md5sum
+//
+// Imports, `if` and `for` used as values, `lazy` values, and interface
+// entries other than plain commands cannot be compiled. Lua output needs Lua 5.4.
+
+use crate::ast::{
+  BinaryOp, Block, Call, Else, Expr, ExprKind, Ident, If, Interface, Literal,
+  Program, Stmt, StmtKind, Visitor,
+};
+use crate::builtins;
+use crate::codes;
+use crate::config::Config;
+use crate::error::{SamError, Span};
+use crate::ffi::{self, split_command};
+use crate::symbol::Symbol;
+use crate::value::Number;
+use clap::ValueEnum;
+use std::collections::HashSet;
+use std::fs;
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum Target {
+  Js,
+  Lua,
+}
+
+impl Target {
+  pub fn extension(self) -> &'static str {
+    match self {
+      Target::Js => "js",
+      Target::Lua => "lua",
+    }
+  }
+
+  pub fn name(self) -> &'static str {
+    match self {
+      Target::Js => "JavaScript",
+      Target::Lua => "Lua",
+    }
+  }
+
+  fn runtime(self) -> &'static str {
+    match self {
+      Target::Js => JS_RUNTIME,
+      Target::Lua => LUA_RUNTIME,
+    }
+  }
+}
+
+// `program` in `target`, its interface files looked up as `config` says
+pub fn transpile(
+  program: &Program,
+  target: Target,
+  config: &Config,
+) -> Result<String, SamError> {
+  let mut declared = Declared(HashSet::new());
+  declared.visit_program(program);
+
+  let mut emitter = Emitter::new(target, config, &declared.0, 0);
+  emitter.statements(&program.statements, None)?;
+
+  return Ok(format!("{}\n{}", target.runtime(), emitter.out));
+}
+
+// every name declared or assigned anywhere in a program; calls to any other
+// name run the command of that name
+struct Declared(HashSet<Symbol>);
+
+impl Visitor for Declared {
+  fn visit_ident(&mut self, ident: &Ident) {
+    self.0.insert(ident.name);
+  }
+}
+
+/* =========================
+Emitter
+========================= */
+
+// how tightly an expression binds, to know when it needs parentheses:
+// operators bind at their level, literals and lambdas cannot be called or
+// have members without them
+const LAMBDA: u8 = 0;
+const LITERAL: u8 = 99;
+const ATOM: u8 = 100;
+
+struct Emitter<'a> {
+  target: Target,
+  config: &'a Config,
+  declared: &'a HashSet<Symbol>,
+  out: String,
+  indent: usize,
+  // the names of each enclosing block declared before their statements,
+  // where the declarations are written as assignments
+  hoisted: Vec<HashSet<Symbol>>,
+  // loops around the code, in its function, naming the label Lua jumps to
+  // to continue the innermost
+  loops: usize,
+}
+
+impl<'a> Emitter<'a> {
+  fn new(
+    target: Target,
+    config: &'a Config,
+    declared: &'a HashSet<Symbol>,
+    indent: usize,
+  ) -> Self {
+    return Emitter {
+      target,
+      config,
+      declared,
+      out: String::new(),
+      indent,
+      hoisted: Vec::new(),
+      loops: 0,
+    };
+  }
+
+  fn line(&mut self, text: &str) {
+    self.out.push_str(&"  ".repeat(self.indent));
+    self.out.push_str(text);
+    self.out.push('\n');
+  }
+
+  fn unsupported(&self, what: &str, span: Span) -> SamError {
+    return SamError::runtime(
+      codes::NOT_COMPILABLE,
+      format!("{} cannot be compiled to {}", what, self.target.name()),
+    )
+    .at(span);
+  }
+
+  fn js(&self) -> bool {
+    return self.target == Target::Js;
+  }
+
+  /* =========================
+  Statements
+  ========================= */
+
+  // the statements of a block at the current indentation, ending with the
+  // label continuing the loop if it is the body of one
+  fn statements(
+    &mut self,
+    statements: &[Stmt],
+    label: Option<String>,
+  ) -> Result<(), SamError> {
+    let hoisted = self.hoist(statements);
+    self.hoisted.push(hoisted);
+
+    // nothing follows a return in Lua, not even the label
+    let last = statements.len().checked_sub(1).filter(|_| label.is_none());
+    for (i, stmt) in statements.iter().enumerate() {
+      self.statement(stmt, Some(i) == last)?;
+    }
+    if let Some(label) = label {
+      self.line(&format!("::{}::", label));
+    }
+
+    self.hoisted.pop();
+    return Ok(());
+  }
+
+  fn block(
+    &mut self,
+    block: &Block,
+    label: Option<String>,
+  ) -> Result<(), SamError> {
+    self.indent += 1;
+    let result = self.statements(&block.statements, label);
+    self.indent -= 1;
+
+    return result;
+  }
+
+  // declare the names of a block that its declarations cannot: in Lua all
+  // of them, so that functions can call those declared after them, and in
+  // JavaScript those declared twice
+  fn hoist(&mut self, statements: &[Stmt]) -> HashSet<Symbol> {
+    let mut names = Vec::new();
+    for stmt in statements {
+      match &stmt.kind {
+        StmtKind::Declaration { declarators, .. } => {
+          names.extend(declarators.iter().map(|d| d.name.name));
+        }
+        StmtKind::Interfaces(interfaces) => {
+          names.extend(interfaces.iter().map(|i| i.module.name));
+        }
+        _ => {}
+      }
+    }
+
+    let mut hoisted: Vec<Symbol> = Vec::new();
+    for (i, name) in names.iter().enumerate() {
+      let again = names[..i].contains(name);
+      let hoist = match self.target {
+        Target::Js => again,
+        Target::Lua => !again,
+      };
+      if hoist && !hoisted.contains(name) {
+        hoisted.push(*name);
+      }
+    }
+
+    if !hoisted.is_empty() {
+      let list: Vec<String> = hoisted.iter().map(|n| self.name(*n)).collect();
+      match self.target {
+        Target::Js => self.line(&format!("let {};", list.join(", "))),
+        Target::Lua => self.line(&format!("local {}", list.join(", "))),
+      }
+    }
+
+    return hoisted.into_iter().collect();
+  }
+
+  fn is_hoisted(&self, name: Symbol) -> bool {
+    return self.hoisted.last().is_some_and(|h| h.contains(&name));
+  }
+
+  fn statement(&mut self, stmt: &Stmt, last: bool) -> Result<(), SamError> {
+    let end = if self.js() { ";" } else { "" };
+
+    match &stmt.kind {
+      StmtKind::Expression(expr) => self.expression_statement(expr)?,
+
+      StmtKind::Declaration {
+        constant,
+        declarators,
+      } => {
+        let mut declared = Vec::new();
+        for declarator in declarators {
+          let name = self.name(declarator.name.name);
+          let value = match &declarator.value {
+            Some(value) => Some(self.expr(value)?.0),
+            None => None,
+          };
+
+          if self.is_hoisted(declarator.name.name) {
+            let value = value.unwrap_or_else(|| self.undefined().to_owned());
+            self.line(&format!("{} = {}{}", name, value, end));
+            continue;
+          }
+
+          declared.push(match value {
+            Some(value) => format!("{} = {}", name, value),
+            None => name,
+          });
+        }
+
+        // only JavaScript declares where sam does
+        if !declared.is_empty() {
+          let keyword = if *constant { "const" } else { "let" };
+          self.line(&format!("{} {};", keyword, declared.join(", ")));
+        }
+      }
+
+      StmtKind::Assignment { target, value } => {
+        let value = self.expr(value)?.0;
+        self.line(&format!("{} = {}{}", self.name(target.name), value, end));
+      }
+
+      StmtKind::Return(value) => {
+        let value = match value {
+          Some(value) => format!(" {}", self.expr(value)?.0),
+          None => String::new(),
+        };
+
+        // Lua only returns at the end of a block
+        match self.target {
+          Target::Js => self.line(&format!("return{};", value)),
+          Target::Lua if last => self.line(&format!("return{}", value)),
+          Target::Lua => self.line(&format!("do return{} end", value)),
+        }
+      }
+
+      StmtKind::Break => self.line(&format!("break{}", end)),
+
+      StmtKind::Continue => match (self.target, self.loops) {
+        (_, 0) => {
+          return Err(self.unsupported("`continue` outside a loop", stmt.span));
+        }
+        (Target::Js, _) => self.line("continue;"),
+        (Target::Lua, loops) => {
+          self.line(&format!("goto {}", continue_label(loops)));
+        }
+      },
+
+      StmtKind::Interfaces(interfaces) => {
+        for interface in interfaces {
+          let name = self.name(interface.module.name);
+          let entry = self.interface(interface)?;
+
+          match self.is_hoisted(interface.module.name) {
+            true => self.line(&format!("{} = {}{}", name, entry, end)),
+            false => self.line(&format!("const {} = {};", name, entry)),
+          }
+        }
+      }
+
+      StmtKind::Import(_) => {
+        return Err(self.unsupported("Imports", stmt.span));
+      }
+    }
+
+    return Ok(());
+  }
+
+  fn expression_statement(&mut self, expr: &Expr) -> Result<(), SamError> {
+    match &expr.kind {
+      ExprKind::If(branch) => return self.if_statement(branch),
+      ExprKind::For {
+        variable,
+        iterable,
+        body,
+      } => return self.for_statement(variable, iterable, body),
+      _ => {}
+    }
+
+    let code = self.expr(expr)?.0;
+    match self.target {
+      Target::Js => self.line(&format!("{};", code)),
+
+      // Lua only has calls as statements, and reads a line starting with a
+      // parenthesis as a call of the line before
+      Target::Lua => match &expr.kind {
+        ExprKind::Call(_) | ExprKind::Spawn(_) if code.starts_with('(') => {
+          self.line(&format!(";{}", code));
+        }
+        ExprKind::Call(_) | ExprKind::Spawn(_) => self.line(&code),
+        _ => self.line(&format!("local _ = {}", code)),
+      },
+    }
+
+    return Ok(());
+  }
+
+  fn if_statement(&mut self, branch: &If) -> Result<(), SamError> {
+    let condition = self.condition(&branch.condition)?;
+    match self.target {
+      Target::Js => self.line(&format!("if ({}) {{", condition)),
+      Target::Lua => self.line(&format!("if {} then", condition)),
+    }
+    self.block(&branch.consequence, None)?;
+
+    let mut alternative = &branch.alternative;
+    loop {
+      match alternative {
+        Some(Else::If(next, _)) => {
+          let condition = self.condition(&next.condition)?;
+          match self.target {
+            Target::Js => self.line(&format!("}} else if ({}) {{", condition)),
+            Target::Lua => self.line(&format!("elseif {} then", condition)),
+          }
+          self.block(&next.consequence, None)?;
+          alternative = &next.alternative;
+        }
+        Some(Else::Block(block)) if block.statements.is_empty() => break,
+        Some(Else::Block(block)) => {
+          self.line(if self.js() { "} else {" } else { "else" });
+          self.block(block, None)?;
+          break;
+        }
+        None => break,
+      }
+    }
+
+    self.line(if self.js() { "}" } else { "end" });
+    return Ok(());
+  }
+
+  fn for_statement(
+    &mut self,
+    variable: &Ident,
+    iterable: &Expr,
+    body: &Block,
+  ) -> Result<(), SamError> {
+    let name = self.name(variable.name);
+    let iterable = self.expr(iterable)?.0;
+
+    self.loops += 1;
+    let result = match self.target {
+      Target::Js => {
+        self.line(&format!("for (let {} of {}) {{", name, iterable));
+        self.block(body, None)
+      }
+      Target::Lua => {
+        self.line(&format!("for _, {} in ipairs({}) do", name, iterable));
+        let label =
+          continues(&body.statements).then(|| continue_label(self.loops));
+        self.block(body, label)
+      }
+    };
+    self.loops -= 1;
+    result?;
+
+    self.line(if self.js() { "}" } else { "end" });
+    return Ok(());
+  }
+
+  // the function an interface entry is, running its command
+  fn interface(&self, interface: &Interface) -> Result<String, SamError> {
+    let path = &interface.path;
+    let name = interface.module.name.as_str();
+
+    let candidates = self.config.interface_candidates(path);
+    let Some(found) = candidates.iter().find(|c| c.is_file()) else {
+      return Err(
+        SamError::ffi(
+          codes::INTERFACE_READ,
+          format!("Interface file {} not found", path),
+        )
+        .at(interface.path_span),
+      );
+    };
+
+    let contents = fs::read_to_string(found).map_err(|e| {
+      SamError::ffi(
+        codes::INTERFACE_READ,
+        format!("There was an error in reading from {}.", found.display()),
+      )
+      .caused_by(e)
+      .at(interface.path_span)
+    })?;
+    let json = ffi::parse_interface(path, &contents).map_err(|e| {
+      SamError::ffi(
+        codes::INTERFACE_PARSE,
+        format!("There was an error in parsing {} from {}.", name, path),
+      )
+      .caused_by(e)
+      .at(interface.span)
+    })?;
+
+    let invalid = SamError::ffi(
+      codes::INTERFACE_ENTRY,
+      format!(
+        "Interface entry {} must be a command string or an object with a \
+         `command`",
+        name
+      ),
+    )
+    .at(interface.span);
+
+    // the types of parameters and results are not checked
+    let (command, shell) = match json.get(name) {
+      Some(serde_json::Value::String(cmd)) => (cmd.clone(), false),
+      Some(serde_json::Value::Object(entry)) => {
+        let other = entry.keys().find(|k| {
+          !["command", "shell", "params", "returns"].contains(&k.as_str())
+        });
+        if let Some(key) = other {
+          let what = format!("Interface entry {} with `{}`", name, key);
+          return Err(self.unsupported(&what, interface.span));
+        }
+
+        let shell = entry.get("shell") == Some(&serde_json::Value::Bool(true));
+        match entry.get("command") {
+          Some(serde_json::Value::String(cmd)) => (cmd.clone(), shell),
+          _ => return Err(invalid),
+        }
+      }
+      _ => return Err(invalid),
+    };
+
+    if shell {
+      return Ok(format!("__sam.shell({})", self.string(&command)));
+    }
+
+    let argv = split_command(&command).ok_or_else(|| {
+      SamError::ffi(
+        codes::INTERFACE_ENTRY,
+        format!("Unterminated quote in command `{}`.", command),
+      )
+      .at(interface.span)
+    })?;
+    if argv.is_empty() {
+      return Err(
+        SamError::ffi(
+          codes::INTERFACE_ENTRY,
+          "Interface entry has an empty command.",
+        )
+        .at(interface.span),
+      );
+    }
+
+    let argv: Vec<String> = argv.iter().map(|a| self.string(a)).collect();
+    return Ok(format!("__sam.command({})", self.list(&argv)));
+  }
+
+  /* =========================
+  Expressions
+  ========================= */
+
+  // the code of `expr`, and how tightly it binds
+  fn expr(&self, expr: &Expr) -> Result<(String, u8), SamError> {
+    let code = match &expr.kind {
+      ExprKind::Literal(Literal::Number(Number::SamInt(i))) => {
+        return Ok((i.to_string(), LITERAL));
+      }
+      ExprKind::Literal(Literal::Number(Number::SamFloat(x))) => {
+        return Ok((format!("{:?}", x), LITERAL));
+      }
+      ExprKind::Literal(Literal::String(s)) => {
+        return Ok((self.string(s), LITERAL));
+      }
+
+      ExprKind::Identifier(name) => {
+        let builtin = builtins::lookup(name.as_str()).is_some();
+        match builtin && !self.declared.contains(name) {
+          true => format!("__sam.{}", name),
+          false => self.name(*name),
+        }
+      }
+
+      ExprKind::Binary { op, left, right } => {
+        return self.binary(*op, left, right);
+      }
+
+      ExprKind::If(_) => {
+        return Err(self.unsupported("`if` used as a value", expr.span));
+      }
+      ExprKind::For { .. } => {
+        return Err(self.unsupported("`for` used as a value", expr.span));
+      }
+
+      ExprKind::Lambda { params, body } => {
+        return Ok((self.lambda(params, body)?, LAMBDA));
+      }
+
+      ExprKind::Call(call) => self.call(call)?,
+
+      ExprKind::Member { object, property } => {
+        let object = self.atom(object)?;
+        let property = property.name.as_str();
+        match self.target {
+          Target::Lua if LUA_RESERVED.contains(&property) => {
+            format!("{}[{}]", object, self.string(property))
+          }
+          _ => format!("{}.{}", object, property),
+        }
+      }
+
+      ExprKind::Array(items) => {
+        let items = items
+          .iter()
+          .map(|item| Ok(self.expr(item)?.0))
+          .collect::<Result<Vec<_>, SamError>>()?;
+        self.list(&items)
+      }
+
+      // arrays of Lua start at 1
+      ExprKind::Index { array, index } => {
+        let array = self.name(array.name);
+        let index = match (self.target, &index.kind) {
+          (Target::Js, _) => self.expr(index)?.0,
+          (
+            Target::Lua,
+            ExprKind::Literal(Literal::Number(Number::SamInt(i))),
+          ) => (i + 1).to_string(),
+          (Target::Lua, _) => format!("{} + 1", self.operand(index, 5)?),
+        };
+        format!("{}[{}]", array, index)
+      }
+
+      // there are no threads to run the call on
+      ExprKind::Spawn(call) => {
+        let call = self.call(call)?;
+        match self.target {
+          Target::Js => format!("__sam.spawn(() => {})", call),
+          Target::Lua => format!("__sam.spawn(function() return {} end)", call),
+        }
+      }
+
+      ExprKind::Await(handle) => {
+        format!("__sam.await({})", self.expr(handle)?.0)
+      }
+    };
+
+    return Ok((code, ATOM));
+  }
+
+  // `expr` in parentheses unless it binds at least as tightly as `min`
+  fn operand(&self, expr: &Expr, min: u8) -> Result<String, SamError> {
+    let (code, prec) = self.expr(expr)?;
+    return Ok(match prec < min {
+      true => format!("({})", code),
+      false => code,
+    });
+  }
+
+  // `expr` as something called or with members
+  fn atom(&self, expr: &Expr) -> Result<String, SamError> {
+    return self.operand(expr, ATOM);
+  }
+
+  fn binary(
+    &self,
+    op: BinaryOp,
+    left: &Expr,
+    right: &Expr,
+  ) -> Result<(String, u8), SamError> {
+    use BinaryOp::*;
+
+    let call = |f: &str| -> Result<(String, u8), SamError> {
+      let (left, right) = (self.expr(left)?.0, self.expr(right)?.0);
+      return Ok((format!("{}({}, {})", f, left, right), ATOM));
+    };
+
+    let (symbol, prec) = match (self.target, op) {
+      (_, Or) => (if self.js() { "||" } else { "or" }, 1),
+      (_, And) => (if self.js() { "&&" } else { "and" }, 2),
+
+      (Target::Js, Eq) => ("===", 3),
+      (Target::Js, Ne) => ("!==", 3),
+      (Target::Js, Lt | Gt | Le | Ge) => (op.as_str(), 4),
+      (Target::Js, Add | Sub) => (op.as_str(), 5),
+      (Target::Js, Mul | Div | Rem) => (op.as_str(), 6),
+
+      (Target::Lua, Ne) => ("~=", 3),
+      (Target::Lua, Eq | Lt | Gt | Le | Ge) => (op.as_str(), 3),
+      // strings are joined with `..`, which is also the only way to
+      // join those only known when the script runs
+      (Target::Lua, Add) if is_string(left) || is_string(right) => {
+        let (left, right) = (self.operand(left, 4)?, self.operand(right, 4)?);
+        return Ok((format!("{} .. {}", left, right), 4));
+      }
+      (Target::Lua, Add) => return call("__sam.add"),
+      (Target::Lua, Sub) => (op.as_str(), 5),
+      (Target::Lua, Mul | Div) => (op.as_str(), 6),
+      // `%` of Lua rounds the quotient down, sam towards zero
+      (Target::Lua, Rem) => return call("math.fmod"),
+    };
+
+    // 0 is true in Lua, so the operands of `and` and `or` are made
+    // conditions
+    let (left, right) = match (self.target, op) {
+      (Target::Lua, And | Or) => (
+        self.condition_operand(left, prec)?,
+        self.condition_operand(right, prec + 1)?,
+      ),
+      _ => (self.operand(left, prec)?, self.operand(right, prec + 1)?),
+    };
+
+    return Ok((format!("{} {} {}", left, symbol, right), prec));
+  }
+
+  // `expr` where the target tests it, e.g. by `if`
+  fn condition(&self, expr: &Expr) -> Result<String, SamError> {
+    return self.condition_operand(expr, LAMBDA);
+  }
+
+  fn condition_operand(
+    &self,
+    expr: &Expr,
+    min: u8,
+  ) -> Result<String, SamError> {
+    if self.js() || is_boolean(expr) {
+      return self.operand(expr, min);
+    }
+
+    return Ok(format!("__sam.truthy({})", self.expr(expr)?.0));
+  }
+
+  fn lambda(&self, params: &[Ident], body: &Block) -> Result<String, SamError> {
+    let params: Vec<String> =
+      params.iter().map(|p| self.name(p.name)).collect();
+    let params = params.join(", ");
+
+    let mut inner =
+      Emitter::new(self.target, self.config, self.declared, self.indent + 1);
+    inner.statements(&body.statements, None)?;
+
+    let indent = "  ".repeat(self.indent);
+    return Ok(match (self.target, inner.out.is_empty()) {
+      (Target::Js, true) => format!("({}) => {{}}", params),
+      (Target::Js, false) => {
+        format!("({}) => {{\n{}{}}}", params, inner.out, indent)
+      }
+      (Target::Lua, true) => format!("function({}) end", params),
+      (Target::Lua, false) => {
+        format!("function({})\n{}{}end", params, inner.out, indent)
+      }
+    });
+  }
+
+  fn call(&self, call: &Call) -> Result<String, SamError> {
+    let args = call
+      .args
+      .iter()
+      .map(|arg| Ok(self.expr(arg)?.0))
+      .collect::<Result<Vec<_>, SamError>>()?;
+
+    let ExprKind::Identifier(name) = &call.function.kind else {
+      return Ok(format!(
+        "{}({})",
+        self.atom(&call.function)?,
+        args.join(", ")
+      ));
+    };
+    if self.declared.contains(name) {
+      return Ok(format!("{}({})", self.name(*name), args.join(", ")));
+    }
+
+    if *name == "pipe" {
+      return self.pipe(call);
+    }
+    if *name == "lazy" {
+      return Err(self.unsupported("Lazy values", call.function.span));
+    }
+    if builtins::lookup(name.as_str()).is_some() {
+      return Ok(format!("__sam.{}({})", name, args.join(", ")));
+    }
+
+    return Ok(format!(
+      "__sam.run({}, {})",
+      self.string(name.as_str()),
+      self.list(&args)
+    ));
+  }
+
+  // `pipe(ls(), grep('foo'))`, of commands only
+  fn pipe(&self, call: &Call) -> Result<String, SamError> {
+    let mut stages = Vec::new();
+
+    for arg in &call.args {
+      let stage = match &arg.kind {
+        ExprKind::Call(stage) => match &stage.function.kind {
+          ExprKind::Identifier(name)
+            if !self.declared.contains(name)
+              && builtins::lookup(name.as_str()).is_none() =>
+          {
+            Some((*name, &stage.args))
+          }
+          _ => None,
+        },
+        _ => None,
+      };
+      let Some((name, args)) = stage else {
+        return Err(
+          self.unsupported("Pipe stages other than commands", arg.span),
+        );
+      };
+
+      let args = args
+        .iter()
+        .map(|arg| Ok(self.expr(arg)?.0))
+        .collect::<Result<Vec<_>, SamError>>()?;
+      stages.push(self.list(&[self.string(name.as_str()), self.list(&args)]));
+    }
+
+    return Ok(format!("__sam.pipe({})", self.list(&stages)));
+  }
+
+  /* =========================
+  Names and literals
+  ========================= */
+
+  // a variable, renamed if it is a keyword of the target or the runtime
+  fn name(&self, name: Symbol) -> String {
+    let name = name.as_str();
+    let reserved = match self.target {
+      Target::Js => JS_RESERVED,
+      Target::Lua => LUA_RESERVED,
+    };
+
+    return match reserved.contains(&name) || name.starts_with("__sam") {
+      true => format!("{}_", name),
+      false => name.to_owned(),
+    };
+  }
+
+  fn string(&self, s: &str) -> String {
+    return match self.target {
+      Target::Js => serde_json::to_string(s).unwrap(),
+      Target::Lua => lua_string(s),
+    };
+  }
+
+  fn list(&self, items: &[String]) -> String {
+    return match self.target {
+      Target::Js => format!("[{}]", items.join(", ")),
+      Target::Lua => format!("{{{}}}", items.join(", ")),
+    };
+  }
+
+  fn undefined(&self) -> &'static str {
+    return if self.js() { "undefined" } else { "nil" };
+  }
+}
+
+fn is_string(expr: &Expr) -> bool {
+  return matches!(expr.kind, ExprKind::Literal(Literal::String(_)));
+}
+
+// whether the code of `expr` is already true or false
+fn is_boolean(expr: &Expr) -> bool {
+  use BinaryOp::*;
+
+  return matches!(
+    expr.kind,
+    ExprKind::Binary {
+      op: Lt | Gt | Eq | Le | Ge | Ne | And | Or,
+      ..
+    }
+  );
+}
+
+// whether a loop body continues, rather than a loop or function in it
+fn continues(statements: &[Stmt]) -> bool {
+  return statements.iter().any(|stmt| match &stmt.kind {
+    StmtKind::Continue => true,
+    StmtKind::Expression(Expr {
+      kind: ExprKind::If(branch),
+      ..
+    }) => if_continues(branch),
+    _ => false,
+  });
+}
+
+fn if_continues(branch: &If) -> bool {
+  return continues(&branch.consequence.statements)
+    || match &branch.alternative {
+      Some(Else::Block(block)) => continues(&block.statements),
+      Some(Else::If(next, _)) => if_continues(next),
+      None => false,
+    };
+}
+
+// labels of Lua are seen by the loops inside, so each loop of a nest has
+// its own
+fn continue_label(loops: usize) -> String {
+  return match loops {
+    1 => "continue".to_owned(),
+    n => format!("continue{}", n),
+  };
+}
+
+fn lua_string(s: &str) -> String {
+  let mut out = String::from("\"");
+  for c in s.chars() {
+    match c {
+      '"' => out.push_str("\\\""),
+      '\\' => out.push_str("\\\\"),
+      '\n' => out.push_str("\\n"),
+      '\r' => out.push_str("\\r"),
+      '\t' => out.push_str("\\t"),
+      c if c.is_control() => out.push_str(&format!("\\u{{{:x}}}", c as u32)),
+      c => out.push(c),
+    }
+  }
+  out.push('"');
+
+  return out;
+}
+
+const JS_RESERVED: &[&str] = &[
+  "arguments",
+  "await",
+  "break",
+  "case",
+  "catch",
+  "class",
+  "const",
+  "continue",
+  "debugger",
+  "default",
+  "delete",
+  "do",
+  "else",
+  "enum",
+  "eval",
+  "export",
+  "extends",
+  "false",
+  "finally",
+  "for",
+  "function",
+  "if",
+  "implements",
+  "import",
+  "in",
+  "instanceof",
+  "interface",
+  "let",
+  "new",
+  "null",
+  "package",
+  "private",
+  "protected",
+  "public",
+  "require",
+  "return",
+  "static",
+  "super",
+  "switch",
+  "this",
+  "throw",
+  "true",
+  "try",
+  "typeof",
+  "undefined",
+  "var",
+  "void",
+  "while",
+  "with",
+  "yield",
+];
+
+const LUA_RESERVED: &[&str] = &[
+  "and", "break", "do", "else", "elseif", "end", "false", "for", "function",
+  "goto", "if", "in", "local", "nil", "not", "or", "repeat", "return", "then",
+  "true", "until", "while",
+];
+
+/* =========================
+Runtimes
+========================= */
+
+const JS_RUNTIME: &str = r#"// the runtime of scripts compiled by `sam compile`
+const __sam = (() => {
+  const child_process = require("child_process");
+  const crypto = require("crypto");
+  const fs = require("fs");
+  const path = require("path");
+
+  // the working directory of external calls, changed by chdir
+  let dir = process.cwd();
+
+  // Math.random until seeded, then mulberry32
+  let random = Math.random;
+
+  const text = (v) => (typeof v === "object" ? JSON.stringify(v) : String(v));
+
+  const condition = (v) => {
+    if (typeof v === "boolean") return v;
+    if (!Number.isInteger(v)) throw new Error("Condition must be integer");
+    return v !== 0;
+  };
+
+  const equal = (a, b) => {
+    if (typeof a !== "object" || typeof b !== "object" || !a || !b) {
+      return a === b;
+    }
+    const keys = Object.keys(a);
+    return (
+      keys.length === Object.keys(b).length &&
+      keys.every((k) => equal(a[k], b[k]))
+    );
+  };
+
+  const exec = (file, args, input) => {
+    const r = child_process.spawnSync(file, args.map(text), {
+      cwd: dir,
+      input,
+      encoding: "utf8",
+      stdio: [input === undefined ? "inherit" : "pipe", "pipe", "pipe"],
+    });
+    if (r.error && r.error.code === "ENOENT") {
+      throw new Error(`\`${file}\` is neither a variable nor a command`);
+    }
+    if (r.error) throw r.error;
+    return r;
+  };
+
+  // the format strings of `format` and `print`, see template.rs: the
+  // text between placeholders, and the placeholders
+  const placeholders = (template) => {
+    const invalid = (what) => new Error(`Invalid format string: ${what}`);
+    const pieces = [];
+    const pattern = /\{\{|\}\}|\{([^{}]*)\}|[{}]/g;
+    let next = 0;
+    let last = 0;
+    for (const m of template.matchAll(pattern)) {
+      pieces.push(template.slice(last, m.index));
+      last = m.index + m[0].length;
+      if (m[0] === "{{" || m[0] === "}}") {
+        pieces.push(m[0][0]);
+        continue;
+      }
+      if (m[1] === undefined) throw invalid(`an unmatched \`${m[0]}\``);
+      const field = m[1].match(/^(\d*)(?::(.*))?$/s);
+      const spec = field && (field[2] ?? "").match(
+        /^(?:(.)?([<>^]))?(\+)?(0)?(\d*)(?:\.(\d+))?([boxX]?)$/su,
+      );
+      if (!spec) throw invalid(`\`{${m[1]}}\``);
+      const [, fill = " ", align, sign, zero, width, precision, kind] = spec;
+      pieces.push({
+        index: field[1] === "" ? next++ : +field[1],
+        fill,
+        align,
+        sign,
+        zero,
+        kind,
+        width: +width,
+        precision: precision === undefined ? undefined : +precision,
+      });
+    }
+    pieces.push(template.slice(last));
+    return pieces;
+  };
+
+  const field = (p, v) => {
+    const number = typeof v === "number";
+    let body;
+    if (p.kind) {
+      if (!Number.isInteger(v)) {
+        throw new Error("Only integers are written in other bases");
+      }
+      const base = { b: 2, o: 8, x: 16, X: 16 }[p.kind];
+      body = Math.abs(v).toString(base);
+      if (p.kind === "X") body = body.toUpperCase();
+    } else if (number) {
+      const n = Math.abs(v);
+      body = p.precision === undefined ? text(n) : n.toFixed(p.precision);
+    } else {
+      body = text(v);
+      if (p.precision !== undefined) {
+        body = [...body].slice(0, p.precision).join("");
+      }
+    }
+    const sign = number && v < 0 ? "-" : number && p.sign ? "+" : "";
+    const padding = Math.max(p.width - [...(sign + body)].length, 0);
+    if (p.zero && number) return sign + "0".repeat(padding) + body;
+    const align = p.align ?? (number ? ">" : "<");
+    const before =
+      align === ">" ? padding : align === "^" ? Math.floor(padding / 2) : 0;
+    return (
+      p.fill.repeat(before) + sign + body + p.fill.repeat(padding - before)
+    );
+  };
+
+  const render = (name, pieces, args) => {
+    const used = args.map(() => false);
+    const out = pieces.map((p) => {
+      if (typeof p === "string") return p;
+      if (p.index >= args.length) {
+        throw new Error(`\`${name}\` has no value ${p.index + 1}`);
+      }
+      used[p.index] = true;
+      return field(p, args[p.index]);
+    });
+    const unused = used.indexOf(false);
+    if (unused >= 0) {
+      throw new Error(`Value ${unused + 1} of \`${name}\` has no placeholder`);
+    }
+    return out.join("");
+  };
+
+  // what `print` writes: formatted if the first argument has placeholders
+  // for the rest
+  const printed = (name, args) => {
+    if (args.length > 1 && typeof args[0] === "string") {
+      try {
+        const pieces = placeholders(args[0]);
+        if (pieces.some((p) => typeof p !== "string")) {
+          return render(name, pieces, args.slice(1));
+        }
+      } catch (e) {
+        if (!e.message.startsWith("Invalid format string")) throw e;
+      }
+    }
+    return args.map(text).join(" ");
+  };
+
+  // decoded bytes as a string if they are UTF-8, as sam gives them back
+  const decoded = (bytes) => {
+    try {
+      return new TextDecoder("utf-8", { fatal: true }).decode(bytes);
+    } catch {
+      return bytes;
+    }
+  };
+
+  const digest = (algorithm, s) =>
+    crypto.createHash(algorithm).update(s).digest("hex");
+
+  // a request by curl, answered like `http_get` and `http_post` of sam
+  const http = (method, url, body, headers = {}) => {
+    const args = ["-sS", "-i", "-X", method];
+    for (const [k, v] of Object.entries(headers)) {
+      args.push("-H", `${k}: ${text(v)}`);
+    }
+    if (body !== undefined && typeof body !== "string") {
+      body = JSON.stringify(body);
+      args.push("-H", "Content-Type: application/json");
+    }
+    if (body !== undefined) args.push("--data-binary", "@-");
+    const r = exec("curl", [...args, url], body ?? "");
+    if (r.status !== 0) {
+      throw new Error(`Could not send a request to ${url}: ${r.stderr.trim()}`);
+    }
+    // the headers of informational responses come first
+    let rest = r.stdout;
+    let head;
+    do {
+      const end = rest.indexOf("\r\n\r\n");
+      head = rest.slice(0, end);
+      rest = rest.slice(end + 4);
+    } while (/^HTTP\/\S+ 1\d\d/.test(head));
+    const [status, ...lines] = head.split("\r\n");
+    const response = { status: +status.split(" ")[1], headers: {}, body: rest };
+    for (const line of lines) {
+      const i = line.indexOf(":");
+      const name = line.slice(0, i).trim().toLowerCase();
+      const value = line.slice(i + 1).trim();
+      const seen = response.headers[name];
+      response.headers[name] = seen === undefined ? value : `${seen}, ${value}`;
+    }
+    return response;
+  };
+
+  const result = (r) => ({
+    ok: r.status === 0 ? 1 : 0,
+    status: r.status ?? -1,
+    stdout: r.stdout,
+    stderr: r.stderr,
+  });
+
+  // an interface entry, whose command prints its result as JSON
+  const foreign = (name, file, fixed) => (...args) => {
+    const r = exec(file, [...fixed, ...args]);
+    if (r.status !== 0) {
+      const status = r.status ?? -1;
+      const message = `Foreign function \`${name}\` exited with status`;
+      throw new Error(`${message} ${status}`);
+    }
+    // booleans are 1 and 0, as in sam
+    const bool = (_, v) => (typeof v === "boolean" ? +v : v);
+    try {
+      return JSON.parse(r.stdout, bool);
+    } catch {
+      const message = "There was an error in parsing the output of";
+      throw new Error(`${message} \`${name}\`.`);
+    }
+  };
+
+  return {
+    run: (name, args) => result(exec(name, args)),
+    pipe: (stages) => {
+      let r;
+      for (const [name, args] of stages) r = exec(name, args, r && r.stdout);
+      return result(r);
+    },
+    command: (argv) => foreign(argv.join(" "), argv[0], argv.slice(1)),
+    shell: (script) => foreign(script, "sh", ["-c", `${script} "$@"`, script]),
+    spawn: (f) => ({ value: f() }),
+    await: (handle) => handle.value,
+    print: (...args) => process.stdout.write(printed("print", args)),
+    println: (...args) => console.log(printed("println", args)),
+    format: (template, ...args) =>
+      render("format", placeholders(template), args),
+    csv_parse: (s, options = {}) => {
+      const delimiter = options.delimiter ?? ",";
+      const records = [];
+      let record = [];
+      let field = "";
+      let quoted = false;
+      const end = () => {
+        if (record.length > 0 || field !== "" || quoted) {
+          records.push([...record, field]);
+        }
+        [record, field, quoted] = [[], "", false];
+      };
+      for (let i = 0; i < s.length; i++) {
+        const c = s[i];
+        if (c === '"' && field === "" && !quoted) {
+          quoted = true;
+          for (i++; s[i] !== '"' || s[i + 1] === '"'; i++) {
+            if (i >= s.length) throw new Error("A quoted field never ends");
+            if (s[i] === '"') i++;
+            field += s[i];
+          }
+        } else if (c === delimiter) {
+          record.push(field);
+          [field, quoted] = ["", false];
+        } else if (c === "\n") {
+          end();
+        } else if (c !== "\r" || s[i + 1] !== "\n") {
+          field += c;
+        }
+      }
+      end();
+      if (!condition(options.headers ?? 0)) return records;
+      const [header = [], ...rows] = records;
+      return rows.map((row, i) => {
+        if (row.length !== header.length) {
+          throw new Error(`Row ${i + 2} has ${row.length} fields`);
+        }
+        return Object.fromEntries(header.map((name, j) => [name, row[j]]));
+      });
+    },
+    csv_write: (rows, options = {}) => {
+      const delimiter = options.delimiter ?? ",";
+      const field = (v) => {
+        const s = v === undefined ? "" : text(v);
+        if (!/["\r\n]/.test(s) && !s.includes(delimiter)) return s;
+        return `"${s.replaceAll('"', '""')}"`;
+      };
+      let columns = options.columns;
+      if (columns === undefined && rows.length && !Array.isArray(rows[0])) {
+        columns = Object.keys(rows[0]).sort();
+      }
+      const records = columns === undefined ? [] : [columns];
+      for (const row of rows) {
+        records.push(Array.isArray(row) ? row : columns.map((c) => row[c]));
+      }
+      return records.map((r) => r.map(field).join(delimiter) + "\n").join("");
+    },
+    http_get: (url, headers) => http("GET", url, undefined, headers),
+    http_post: (url, body, headers) => http("POST", url, body, headers),
+    sha256: (s) => digest("sha256", s),
+    md5: (s) => digest("md5", s),
+    base64_encode: (s) => Buffer.from(s).toString("base64"),
+    base64_decode: (s) => {
+      // groups of 4, the last maybe shorter or padded
+      const d = "[A-Za-z0-9+/]";
+      const valid = `^(${d}{4})*(${d}{2}(==)?|${d}{3}=?)?$`;
+      if (!new RegExp(valid).test(s)) throw new Error("Invalid base64");
+      return decoded(Buffer.from(s, "base64"));
+    },
+    hex_encode: (s) => Buffer.from(s).toString("hex"),
+    hex_decode: (s) => {
+      if (!/^([0-9a-fA-F]{2})*$/.test(s)) {
+        throw new Error("Invalid hexadecimal");
+      }
+      return decoded(Buffer.from(s, "hex"));
+    },
+    url_encode: (s) => {
+      const byte = (b) => {
+        const c = String.fromCharCode(b);
+        if (/[A-Za-z0-9\-_.~]/.test(c)) return c;
+        return "%" + b.toString(16).toUpperCase().padStart(2, "0");
+      };
+      return [...Buffer.from(s)].map(byte).join("");
+    },
+    url_decode: (s) => {
+      const bytes = [...Buffer.from(s)];
+      const out = [];
+      for (let i = 0; i < bytes.length; i++) {
+        if (bytes[i] !== 37) {
+          out.push(bytes[i]);
+          continue;
+        }
+        // the 2 digits after a `%`
+        const digits = String.fromCharCode(...bytes.slice(i + 1, i + 3));
+        if (!/^[0-9a-fA-F]{2}$/.test(digits)) {
+          throw new Error("Invalid URL encoding");
+        }
+        out.push(parseInt(digits, 16));
+        i += 2;
+      }
+      return decoded(Buffer.from(out));
+    },
+    input: (prompt) => {
+      if (prompt !== undefined) process.stdout.write(text(prompt));
+      // read a byte at a time, so nothing past the line is taken
+      const bytes = [];
+      const byte = Buffer.alloc(1);
+      while (fs.readSync(0, byte, 0, 1, null) === 1 && byte[0] !== 10) {
+        bytes.push(byte[0]);
+      }
+      if (bytes.length === 0 && byte[0] !== 10) return undefined;
+      return Buffer.from(bytes).toString("utf8").replace(/\r$/, "");
+    },
+    abs: Math.abs,
+    floor: Math.floor,
+    ceil: Math.ceil,
+    // halves away from zero, as in sam
+    round: (x) => Math.sign(x) * Math.round(Math.abs(x)),
+    sqrt: Math.sqrt,
+    pow: Math.pow,
+    floor_div: (a, b) => Math.floor(a / b),
+    log: (x, base) => Math.log(x) / (base === undefined ? 1 : Math.log(base)),
+    exp: Math.exp,
+    sin: Math.sin,
+    cos: Math.cos,
+    tan: Math.tan,
+    min: (...args) => Math.min(...(Array.isArray(args[0]) ? args[0] : args)),
+    max: (...args) => Math.max(...(Array.isArray(args[0]) ? args[0] : args)),
+    len: (v) =>
+      typeof v === "string"
+        ? [...v].length
+        : Array.isArray(v)
+          ? v.length
+          : Object.keys(v).length,
+    upper: (s) => s.toUpperCase(),
+    lower: (s) => s.toLowerCase(),
+    trim: (s) => s.trim(),
+    split: (s, sep) => {
+      if (sep === undefined) return s.split(/\s+/).filter((w) => w !== "");
+      return sep === "" ? [...s] : s.split(sep);
+    },
+    join: (items, sep = "") => items.map(text).join(sep),
+    replace: (s, from, to) => (from === "" ? s : s.split(from).join(to)),
+    contains: (v, x) =>
+      +(typeof v === "string"
+        ? v.includes(x)
+        : v.some((item) => equal(item, x))),
+    starts_with: (s, prefix) => +s.startsWith(prefix),
+    ends_with: (s, suffix) => +s.endsWith(suffix),
+    find: (s, x) => {
+      const i = s.indexOf(x);
+      return i < 0 ? -1 : [...s.slice(0, i)].length;
+    },
+    pad: (v, width, fill = " ") => {
+      const s = text(v);
+      return width < 0 ? s.padEnd(-width, fill) : s.padStart(width, fill);
+    },
+    push: (items, ...values) => [...items, ...values],
+    pop: (items) => {
+      if (items.length === 0) throw new Error("`pop` of an empty array");
+      return [items.slice(0, -1), items[items.length - 1]];
+    },
+    insert: (items, i, value) => {
+      if (i < 0 || i > items.length) throw new Error(`no position ${i}`);
+      return [...items.slice(0, i), value, ...items.slice(i)];
+    },
+    remove: (v, i) => {
+      if (!Array.isArray(v)) {
+        const { [i]: _, ...rest } = v;
+        return rest;
+      }
+      if (i < 0 || i >= v.length) throw new Error(`no position ${i}`);
+      return [...v.slice(0, i), ...v.slice(i + 1)];
+    },
+    sort: (items) =>
+      [...items].sort((a, b) => (a < b ? -1 : a > b ? 1 : 0)),
+    reverse: (v) =>
+      typeof v === "string" ? [...v].reverse().join("") : [...v].reverse(),
+    slice: (v, start, end) =>
+      typeof v === "string"
+        ? [...v].slice(start, end).join("")
+        : v.slice(start, end),
+    index_of: (items, x) => items.findIndex((item) => equal(item, x)),
+    concat: (...arrays) => [].concat(...arrays),
+    flatten: (items) => items.flat(),
+    keys: (o) => Object.keys(o).sort(),
+    values: (o) => Object.keys(o).sort().map((k) => o[k]),
+    has: (o, k) => +Object.hasOwn(o, k),
+    get: (v, path, fallback) => {
+      for (const step of path.split(".")) {
+        if (v === null || typeof v !== "object" || !Object.hasOwn(v, step)) {
+          return fallback;
+        }
+        v = v[step];
+      }
+      return v;
+    },
+    merge: (...objects) => {
+      const isObject = (v) =>
+        v !== null && typeof v === "object" && !Array.isArray(v);
+      const merge = (a, b) => {
+        const out = { ...a };
+        for (const [k, v] of Object.entries(b)) {
+          out[k] = isObject(out[k]) && isObject(v) ? merge(out[k], v) : v;
+        }
+        return out;
+      };
+      return objects.reduce(merge, {});
+    },
+    json_parse: (s) =>
+      JSON.parse(s, (_, v) =>
+        v === null ? undefined : typeof v === "boolean" ? +v : v,
+      ),
+    json_stringify: (v, pretty) =>
+      JSON.stringify(v, null, condition(pretty ?? 0) ? 2 : 0),
+    map: (items, f) => items.map((item) => f(item)),
+    filter: (items, f) => items.filter((item) => condition(f(item))),
+    reduce: (items, f, ...start) => {
+      if (items.length === 0 && start.length === 0) {
+        const message = "`reduce` of an empty array needs a value";
+        throw new Error(`${message} to start from`);
+      }
+      return items.reduce((result, item) => f(result, item), ...start);
+    },
+    each: (items, f) => items.forEach((item) => f(item)),
+    rand: () => random(),
+    rand_int: (low, high) => low + Math.floor(random() * (high - low + 1)),
+    shuffle: (items) => {
+      const out = [...items];
+      for (let i = out.length - 1; i > 0; i--) {
+        const j = Math.floor(random() * (i + 1));
+        [out[i], out[j]] = [out[j], out[i]];
+      }
+      return out;
+    },
+    seed: (n) => {
+      let state = n >>> 0;
+      random = () => {
+        state = (state + 0x6d2b79f5) >>> 0;
+        let t = state;
+        t = Math.imul(t ^ (t >>> 15), t | 1);
+        t ^= t + Math.imul(t ^ (t >>> 7), t | 61);
+        return ((t ^ (t >>> 14)) >>> 0) / 4294967296;
+      };
+    },
+    now: () => Date.now() / 1000,
+    clock: () => performance.now() / 1000,
+    sleep: (seconds) => {
+      const cell = new Int32Array(new SharedArrayBuffer(4));
+      Atomics.wait(cell, 0, 0, Math.max(seconds, 0) * 1000);
+    },
+    format_time: (seconds, pattern = "%Y-%m-%dT%H:%M:%SZ") => {
+      const date = new Date(Math.floor(seconds) * 1000);
+      const pad = (n, width = 2) => String(n).padStart(width, "0");
+      const parts = {
+        Y: pad(date.getUTCFullYear(), 4),
+        m: pad(date.getUTCMonth() + 1),
+        d: pad(date.getUTCDate()),
+        H: pad(date.getUTCHours()),
+        M: pad(date.getUTCMinutes()),
+        S: pad(date.getUTCSeconds()),
+        f: pad(Math.floor((seconds - Math.floor(seconds)) * 1e6), 6),
+        "%": "%",
+      };
+      return pattern.replace(/%(.?)/gs, (_, c) => {
+        if (!Object.hasOwn(parts, c)) {
+          throw new Error(`\`format_time\` has no directive \`%${c}\``);
+        }
+        return parts[c];
+      });
+    },
+    cwd: () => dir,
+    chdir: (to) => {
+      const resolved = path.resolve(dir, to);
+      if (!fs.statSync(resolved, { throwIfNoEntry: false })?.isDirectory()) {
+        throw new Error(`${resolved} is not a directory`);
+      }
+      dir = resolved;
+    },
+    par_map: (items, f) => items.map((item) => f(item)),
+    par_filter: (items, f) => items.filter((item) => condition(f(item))),
+    exit: (status = 0) => process.exit(status),
+    assert: (cond, message) => {
+      if (condition(cond)) return;
+      throw new Error(
+        message === undefined
+          ? "Assertion failed"
+          : `Assertion failed: ${text(message)}`,
+      );
+    },
+    assert_eq: (a, b) => {
+      if (equal(a, b)) return;
+      throw new Error(`Assertion failed: ${text(a)} != ${text(b)}`);
+    },
+  };
+})();
+"#;
+
+const LUA_RUNTIME: &str = r#"-- the runtime of scripts compiled by `sam compile`
+local __sam = {}
+do
+  local function quote(s)
+    return "'" .. s:gsub("'", "'\\''") .. "'"
+  end
+
+  -- the output of a shell command line, and how it exited
+  local function capture(line)
+    local handle = io.popen(line)
+    local out = handle:read("a")
+    local _, how, status = handle:close()
+    return out, how == "exit" and status or -1
+  end
+
+  -- the working directory of external calls, changed by chdir
+  local dir = capture("pwd"):gsub("\n$", "")
+
+  local function encode(v)
+    if type(v) == "table" then
+      local items = {}
+      if #v > 0 or next(v) == nil then
+        for i, item in ipairs(v) do
+          items[i] = encode(item)
+        end
+        return "[" .. table.concat(items, ",") .. "]"
+      end
+      for k, item in pairs(v) do
+        items[#items + 1] = encode(tostring(k)) .. ":" .. encode(item)
+      end
+      return "{" .. table.concat(items, ",") .. "}"
+    elseif type(v) == "string" then
+      local escaped = v:gsub('[%c"\\]', function(c)
+        return string.format("\\u%04x", c:byte())
+      end)
+      return '"' .. escaped .. '"'
+    elseif v == nil then
+      return "null"
+    end
+    return tostring(v)
+  end
+
+  local function decode(s)
+    local i = 1
+    local escapes = { b = "\b", f = "\f", n = "\n", r = "\r", t = "\t" }
+    local value
+
+    local function skip()
+      i = s:find("[^ \t\r\n]", i) or #s + 1
+    end
+    local function expect(c)
+      skip()
+      if s:sub(i, i) ~= c then
+        error("expected " .. c .. " at character " .. i)
+      end
+      i = i + 1
+    end
+    local function str()
+      expect('"')
+      local out = {}
+      while true do
+        local c = s:sub(i, i)
+        if c == "" then
+          error("unterminated string")
+        elseif c == '"' then
+          i = i + 1
+          return table.concat(out)
+        elseif c == "\\" then
+          local e = s:sub(i + 1, i + 1)
+          if e == "u" then
+            out[#out + 1] = utf8.char(tonumber(s:sub(i + 2, i + 5), 16))
+            i = i + 6
+          else
+            out[#out + 1] = escapes[e] or e
+            i = i + 2
+          end
+        else
+          out[#out + 1] = c
+          i = i + 1
+        end
+      end
+    end
+    -- the items of an array or object, each read by `item`
+    local function items(close, item)
+      skip()
+      if s:sub(i, i) == close then
+        i = i + 1
+        return
+      end
+      repeat
+        item()
+        skip()
+        local c = s:sub(i, i)
+        i = i + 1
+      until c ~= ","
+      if s:sub(i - 1, i - 1) ~= close then
+        error("expected " .. close .. " at character " .. i - 1)
+      end
+    end
+
+    function value()
+      skip()
+      local c = s:sub(i, i)
+      if c == "{" then
+        local obj = {}
+        i = i + 1
+        items("}", function()
+          local key = str()
+          expect(":")
+          obj[key] = value()
+        end)
+        return obj
+      elseif c == "[" then
+        local arr = {}
+        i = i + 1
+        items("]", function()
+          arr[#arr + 1] = value()
+        end)
+        return arr
+      elseif c == '"' then
+        return str()
+      end
+      for word, v in pairs({ ["true"] = 1, ["false"] = 0, null = false }) do
+        if s:sub(i, i + #word - 1) == word then
+          i = i + #word
+          return v or nil
+        end
+      end
+      local n = s:match("^-?%d+%.?%d*", i)
+      if not n then
+        error("unexpected character at " .. i)
+      end
+      n = n .. (s:match("^[eE][-+]?%d+", i + #n) or "")
+      i = i + #n
+      return tonumber(n)
+    end
+
+    local v = value()
+    skip()
+    if i <= #s then
+      error("unexpected character at " .. i)
+    end
+    return v
+  end
+
+  local function text(v)
+    if type(v) == "table" then
+      return encode(v)
+    elseif v == nil then
+      return "undefined"
+    end
+    return tostring(v)
+  end
+
+  local function words(name, args)
+    local line = { quote(name) }
+    for _, arg in ipairs(args) do
+      line[#line + 1] = quote(text(arg))
+    end
+    return table.concat(line, " ")
+  end
+
+  -- run `line` in the working directory, keeping what it prints
+  local function exec(line)
+    local err = os.tmpname()
+    local out, status =
+      capture("cd " .. quote(dir) .. " && " .. line .. " 2>" .. quote(err))
+    local file = io.open(err)
+    local stderr = file and file:read("a") or ""
+    if file then
+      file:close()
+    end
+    os.remove(err)
+    return {
+      ok = status == 0 and 1 or 0,
+      status = status,
+      stdout = out,
+      stderr = stderr,
+    }
+  end
+
+  local function condition(v)
+    if type(v) == "boolean" then
+      return v
+    elseif math.type(v) ~= "integer" then
+      error("Condition must be integer", 3)
+    end
+    return v ~= 0
+  end
+
+  local function equal(a, b)
+    if type(a) ~= "table" or type(b) ~= "table" then
+      return a == b
+    end
+    for k, v in pairs(a) do
+      if not equal(v, b[k]) then
+        return false
+      end
+    end
+    for k in pairs(b) do
+      if a[k] == nil then
+        return false
+      end
+    end
+    return true
+  end
+
+  -- an interface entry, whose command prints its result as JSON
+  local function foreign(name, prefix)
+    return function(...)
+      local line = { prefix }
+      for _, arg in ipairs({ ... }) do
+        line[#line + 1] = quote(text(arg))
+      end
+      local result = exec(table.concat(line, " "))
+      if result.status ~= 0 then
+        error(string.format(
+          "Foreign function `%s` exited with status %d", name, result.status
+        ), 2)
+      end
+      local ok, value = pcall(decode, result.stdout)
+      if not ok then
+        local message = "There was an error in parsing the output of"
+        error(message .. " `" .. name .. "`.", 2)
+      end
+      return value
+    end
+  end
+
+  function __sam.run(name, args)
+    local result = exec(words(name, args))
+    if result.status == 127 then
+      error("`" .. name .. "` is neither a variable nor a command", 2)
+    end
+    return result
+  end
+
+  function __sam.pipe(stages)
+    local line = {}
+    for i, stage in ipairs(stages) do
+      line[i] = words(stage[1], stage[2])
+    end
+    return exec(table.concat(line, " | "))
+  end
+
+  function __sam.command(argv)
+    return foreign(
+      table.concat(argv, " "),
+      words(argv[1], { table.unpack(argv, 2) })
+    )
+  end
+
+  function __sam.shell(script)
+    return foreign(
+      script,
+      "sh -c " .. quote(script .. ' "$@"') .. " " .. quote(script)
+    )
+  end
+
+  function __sam.truthy(v)
+    return condition(v)
+  end
+
+  function __sam.add(a, b)
+    if type(a) == "string" and type(b) == "string" then
+      return a .. b
+    elseif type(a) == "number" and type(b) == "number" then
+      return a + b
+    end
+    return nil
+  end
+
+  function __sam.spawn(f)
+    return { value = f() }
+  end
+
+  function __sam.await(handle)
+    return handle.value
+  end
+
+  local function joined(...)
+    local args = table.pack(...)
+    local texts = {}
+    for i = 1, args.n do
+      texts[i] = text(args[i])
+    end
+    return table.concat(texts, " ")
+  end
+
+  -- the format strings of `format` and `print`, see template.rs: the
+  -- text between placeholders, and the placeholders
+  local function placeholders(template)
+    local function invalid(what)
+      error("Invalid format string: " .. what, 0)
+    end
+    local pieces, next, i = {}, 0, 1
+    while i <= #template do
+      local c, pair = template:sub(i, i), template:sub(i, i + 1)
+      if pair == "{{" or pair == "}}" then
+        pieces[#pieces + 1] = c
+        i = i + 2
+      elseif c == "}" then
+        invalid("a `}` that closes nothing")
+      elseif c == "{" then
+        local close = template:find("}", i, true)
+        if not close then
+          invalid("a `{` that is never closed")
+        end
+        local field = template:sub(i + 1, close - 1)
+        local index, spec = field:match("^(%d*)(.*)$")
+        spec = spec == "" and "" or spec:match("^:(.*)$")
+        local fill, align, rest = (spec or ""):match("^(.?)([<>^])(.*)$")
+        if not align then
+          fill, rest = "", spec or ""
+        end
+        local sign, zero, width, dot, precision, kind =
+          rest:match("^(%+?)(0?)(%d*)(%.?)(%d*)([boxX]?)$")
+        if not spec or not sign or (dot == ".") ~= (precision ~= "") then
+          invalid("`{" .. field .. "}`")
+        end
+        if index == "" then
+          index, next = next, next + 1
+        end
+        pieces[#pieces + 1] = {
+          index = tonumber(index),
+          fill = fill == "" and " " or fill,
+          align = align,
+          sign = sign == "+",
+          zero = zero == "0",
+          width = tonumber(width) or 0,
+          precision = tonumber(precision),
+          kind = kind,
+        }
+        i = close + 1
+      else
+        pieces[#pieces + 1] = c
+        i = i + 1
+      end
+    end
+    return pieces
+  end
+
+  local function field(p, v)
+    local number = type(v) == "number"
+    local body
+    if p.kind ~= "" then
+      if math.type(v) ~= "integer" then
+        error("Only integers are written in other bases", 3)
+      end
+      local n = math.abs(v)
+      if p.kind == "b" then
+        body = n == 0 and "0" or ""
+        while n > 0 do
+          body = n % 2 .. body
+          n = n // 2
+        end
+      else
+        body = string.format("%" .. p.kind, n)
+      end
+    elseif number and p.precision then
+      body = string.format("%." .. p.precision .. "f", math.abs(v))
+    elseif number then
+      body = text(math.abs(v))
+    else
+      body = text(v)
+      if p.precision then
+        body = body:sub(1, (utf8.offset(body, p.precision + 1) or 0) - 1)
+      end
+    end
+    local sign = ""
+    if number and v < 0 then
+      sign = "-"
+    elseif number and p.sign then
+      sign = "+"
+    end
+    local padding = math.max(p.width - utf8.len(sign .. body), 0)
+    if p.zero and number then
+      return sign .. string.rep("0", padding) .. body
+    end
+    local align = p.align or (number and ">" or "<")
+    local before = 0
+    if align == ">" then
+      before = padding
+    elseif align == "^" then
+      before = padding // 2
+    end
+    return string.rep(p.fill, before) .. sign .. body
+      .. string.rep(p.fill, padding - before)
+  end
+
+  local function render(name, pieces, args)
+    local out, used = {}, {}
+    for i, p in ipairs(pieces) do
+      if type(p) == "string" then
+        out[i] = p
+      elseif p.index >= args.n then
+        error("`" .. name .. "` has no value " .. p.index + 1, 3)
+      else
+        used[p.index + 1] = true
+        out[i] = field(p, args[p.index + 1])
+      end
+    end
+    for i = 1, args.n do
+      if not used[i] then
+        error("Value " .. i .. " of `" .. name .. "` has no placeholder", 3)
+      end
+    end
+    return table.concat(out)
+  end
+
+  -- what `print` writes: formatted if the first argument has placeholders
+  -- for the rest
+  local function printed(name, ...)
+    local first = ...
+    if select('#', ...) > 1 and type(first) == "string" then
+      local ok, pieces = pcall(placeholders, first)
+      for _, p in ipairs(ok and pieces or {}) do
+        if type(p) == "table" then
+          return render(name, pieces, table.pack(select(2, ...)))
+        end
+      end
+    end
+    return joined(...)
+  end
+
+  function __sam.print(...)
+    io.write(printed("print", ...))
+  end
+
+  function __sam.println(...)
+    io.write(printed("println", ...), "\n")
+  end
+
+  function __sam.format(template, ...)
+    return render("format", placeholders(template), table.pack(...))
+  end
+
+  -- a request by curl, answered like `http_get` and `http_post` of sam
+  local function http(method, url, body, headers)
+    local args = { "curl", "-sS", "-i", "-X", method }
+    for k, v in pairs(headers or {}) do
+      args[#args + 1] = "-H " .. quote(k .. ": " .. text(v))
+    end
+    if body ~= nil and type(body) ~= "string" then
+      body = encode(body)
+      args[#args + 1] = "-H 'Content-Type: application/json'"
+    end
+    local file
+    if body ~= nil then
+      file = os.tmpname()
+      local f = io.open(file, "wb")
+      f:write(body)
+      f:close()
+      args[#args + 1] = "--data-binary " .. quote("@" .. file)
+    end
+    args[#args + 1] = quote(url)
+    local out, status = capture(table.concat(args, " "))
+    if file then
+      os.remove(file)
+    end
+    if status ~= 0 then
+      error("Could not send a request to " .. url, 3)
+    end
+
+    -- the headers of informational responses come first
+    local head, rest
+    repeat
+      head, rest = out:match("^(.-)\r\n\r\n(.*)$")
+      out = rest
+    until not head:match("^HTTP/%S+ 1%d%d")
+    local response = { headers = {}, body = rest }
+    response.status = tonumber(head:match("^HTTP/%S+ (%d+)"))
+    for name, value in head:gmatch("\r\n([^:\r\n]+):[ \t]*([^\r\n]*)") do
+      name = name:lower()
+      local seen = response.headers[name]
+      response.headers[name] = seen and seen .. ", " .. value or value
+    end
+    return response
+  end
+
+  function __sam.http_get(url, headers)
+    return http("GET", url, nil, headers)
+  end
+
+  function __sam.http_post(url, body, headers)
+    return http("POST", url, body, headers)
+  end
+
+  function __sam.input(prompt)
+    if prompt ~= nil then
+      io.write(text(prompt))
+      io.flush()
+    end
+    local line = io.read("L")
+    return line and line:gsub("\r?\n$", "")
+  end
+
+  __sam.abs = math.abs
+  __sam.floor = math.floor
+  __sam.ceil = math.ceil
+  __sam.sqrt = math.sqrt
+  __sam.log = math.log
+  __sam.exp = math.exp
+  __sam.sin = math.sin
+  __sam.cos = math.cos
+  __sam.tan = math.tan
+
+  -- halves away from zero, as in sam
+  function __sam.round(x)
+    if x < 0 then
+      return -__sam.round(-x)
+    end
+    return __sam.floor(x + 0.5)
+  end
+
+  -- integers of a whole power stay integers while they fit
+  function __sam.pow(base, exponent)
+    local r = base ^ exponent
+    if math.type(base) == "integer" and math.type(exponent) == "integer"
+      and exponent >= 0 and math.abs(r) < 2 ^ 63 then
+      return math.tointeger(r) or r
+    end
+    return r
+  end
+
+  function __sam.floor_div(a, b)
+    return a // b
+  end
+
+  function __sam.min(first, ...)
+    if type(first) == "table" then
+      return math.min(table.unpack(first))
+    end
+    return math.min(first, ...)
+  end
+
+  function __sam.max(first, ...)
+    if type(first) == "table" then
+      return math.max(table.unpack(first))
+    end
+    return math.max(first, ...)
+  end
+
+  function __sam.len(v)
+    if type(v) == "string" then
+      return utf8.len(v)
+    end
+    local n = 0
+    for _ in pairs(v) do
+      n = n + 1
+    end
+    return n
+  end
+
+  -- of ASCII letters only
+  __sam.upper = string.upper
+  __sam.lower = string.lower
+
+  function __sam.trim(s)
+    return (s:gsub("^%s+", ""):gsub("%s+$", ""))
+  end
+
+  function __sam.split(s, sep)
+    local out = {}
+    if sep == nil then
+      for word in s:gmatch("%S+") do
+        out[#out + 1] = word
+      end
+    elseif sep == "" then
+      for _, c in utf8.codes(s) do
+        out[#out + 1] = utf8.char(c)
+      end
+    else
+      local start = 1
+      while true do
+        local i, j = s:find(sep, start, true)
+        if not i then
+          break
+        end
+        out[#out + 1] = s:sub(start, i - 1)
+        start = j + 1
+      end
+      out[#out + 1] = s:sub(start)
+    end
+    return out
+  end
+
+  function __sam.join(items, sep)
+    local texts = {}
+    for i, item in ipairs(items) do
+      texts[i] = text(item)
+    end
+    return table.concat(texts, sep or "")
+  end
+
+  function __sam.replace(s, from, to)
+    if from == "" then
+      return s
+    end
+    return (s:gsub(from:gsub("%p", "%%%0"), (to:gsub("%%", "%%%%"))))
+  end
+
+  function __sam.contains(v, x)
+    if type(v) == "string" then
+      return v:find(x, 1, true) and 1 or 0
+    end
+    for _, item in ipairs(v) do
+      if equal(item, x) then
+        return 1
+      end
+    end
+    return 0
+  end
+
+  function __sam.starts_with(s, prefix)
+    return s:sub(1, #prefix) == prefix and 1 or 0
+  end
+
+  function __sam.ends_with(s, suffix)
+    return (#suffix == 0 or s:sub(-#suffix) == suffix) and 1 or 0
+  end
+
+  function __sam.find(s, x)
+    local i = s:find(x, 1, true)
+    return i and utf8.len(s:sub(1, i - 1)) or -1
+  end
+
+  function __sam.pad(v, width, fill)
+    local s = text(v)
+    local padding = string.rep(fill or " ", math.abs(width) - utf8.len(s))
+    if width < 0 then
+      return s .. padding
+    end
+    return padding .. s
+  end
+
+  local function copy(items)
+    return table.move(items, 1, #items, 1, {})
+  end
+
+  function __sam.push(items, ...)
+    local out = copy(items)
+    for _, value in ipairs({ ... }) do
+      out[#out + 1] = value
+    end
+    return out
+  end
+
+  function __sam.pop(items)
+    if #items == 0 then
+      error("`pop` of an empty array", 2)
+    end
+    local out = copy(items)
+    local last = out[#out]
+    out[#out] = nil
+    return { out, last }
+  end
+
+  function __sam.insert(items, i, value)
+    if i < 0 or i > #items then
+      error("`insert` has no position " .. i, 2)
+    end
+    local out = copy(items)
+    table.insert(out, i + 1, value)
+    return out
+  end
+
+  function __sam.remove(items, i)
+    if type(i) == "string" then
+      local out = {}
+      for k, v in pairs(items) do
+        out[k] = v
+      end
+      out[i] = nil
+      return out
+    end
+    if i < 0 or i >= #items then
+      error("`remove` has no position " .. i, 2)
+    end
+    local out = copy(items)
+    table.remove(out, i + 1)
+    return out
+  end
+
+  function __sam.sort(items)
+    local out = copy(items)
+    table.sort(out)
+    return out
+  end
+
+  function __sam.reverse(v)
+    if type(v) == "string" then
+      return __sam.join(__sam.reverse(__sam.split(v, "")))
+    end
+    local out = {}
+    for i = #v, 1, -1 do
+      out[#out + 1] = v[i]
+    end
+    return out
+  end
+
+  -- from `start` up to `stop`, counted from 0, negative ones from the end
+  function __sam.slice(v, start, stop)
+    local items = type(v) == "string" and __sam.split(v, "") or v
+    local function bound(n)
+      if n < 0 then
+        return math.max(#items + n, 0)
+      end
+      return math.min(n, #items)
+    end
+    local first, last = bound(start), stop and bound(stop) or #items
+    local out = table.move(items, first + 1, last, 1, {})
+    if type(v) == "string" then
+      return __sam.join(out)
+    end
+    return out
+  end
+
+  function __sam.index_of(items, x)
+    for i, item in ipairs(items) do
+      if equal(item, x) then
+        return i - 1
+      end
+    end
+    return -1
+  end
+
+  function __sam.concat(...)
+    local out = {}
+    for _, items in ipairs({ ... }) do
+      table.move(items, 1, #items, #out + 1, out)
+    end
+    return out
+  end
+
+  function __sam.flatten(items)
+    local out = {}
+    for _, item in ipairs(items) do
+      if type(item) == "table" and (#item > 0 or next(item) == nil) then
+        table.move(item, 1, #item, #out + 1, out)
+      else
+        out[#out + 1] = item
+      end
+    end
+    return out
+  end
+
+  -- tables with fields of their own, not arrays
+  local function is_object(v)
+    return type(v) == "table" and #v == 0 and next(v) ~= nil
+  end
+
+  function __sam.keys(o)
+    local keys = {}
+    for k in pairs(o) do
+      keys[#keys + 1] = k
+    end
+    table.sort(keys)
+    return keys
+  end
+
+  function __sam.values(o)
+    local values = {}
+    for i, k in ipairs(__sam.keys(o)) do
+      values[i] = o[k]
+    end
+    return values
+  end
+
+  function __sam.has(o, k)
+    return o[k] ~= nil and 1 or 0
+  end
+
+  function __sam.get(v, path, fallback)
+    for step in path:gmatch("[^.]+") do
+      if type(v) ~= "table" then
+        return fallback
+      end
+      local i = tonumber(step)
+      v = v[i and i + 1 or step]
+      if v == nil then
+        return fallback
+      end
+    end
+    return v
+  end
+
+  local function merge(a, b)
+    local out = {}
+    for k, v in pairs(a) do
+      out[k] = v
+    end
+    for k, v in pairs(b) do
+      out[k] = is_object(out[k]) and is_object(v) and merge(out[k], v) or v
+    end
+    return out
+  end
+
+  function __sam.merge(...)
+    local out = {}
+    for _, o in ipairs({ ... }) do
+      out = merge(out, o)
+    end
+    return out
+  end
+
+  function __sam.json_parse(s)
+    return decode(s)
+  end
+
+  -- on one line, even when asked to indent
+  function __sam.json_stringify(v)
+    return encode(v)
+  end
+
+  function __sam.csv_parse(s, options)
+    options = options or {}
+    local delimiter = options.delimiter or ","
+    local records, record, field, quoted = {}, {}, {}, false
+    local function push()
+      record[#record + 1] = table.concat(field)
+      field, quoted = {}, false
+    end
+    local function finish()
+      if #record > 0 or #field > 0 or quoted then
+        push()
+        records[#records + 1] = record
+      end
+      record, field, quoted = {}, {}, false
+    end
+    local i = 1
+    while i <= #s do
+      local c = s:sub(i, i)
+      if c == '"' and #field == 0 and not quoted then
+        quoted = true
+        local closed = false
+        while not closed do
+          i = i + 1
+          local q = s:sub(i, i)
+          if q == "" then
+            error("A quoted field is never closed", 2)
+          elseif q == '"' and s:sub(i + 1, i + 1) == '"' then
+            field[#field + 1] = '"'
+            i = i + 1
+          elseif q == '"' then
+            closed = true
+          else
+            field[#field + 1] = q
+          end
+        end
+      elseif c == delimiter then
+        push()
+      elseif c == "\n" then
+        finish()
+      elseif c ~= "\r" or s:sub(i + 1, i + 1) ~= "\n" then
+        field[#field + 1] = c
+      end
+      i = i + 1
+    end
+    finish()
+    if not condition(options.headers or 0) then
+      return records
+    end
+    local header, rows = records[1] or {}, {}
+    for n = 2, #records do
+      if #records[n] ~= #header then
+        error("Row " .. n .. " has " .. #records[n] .. " fields", 2)
+      end
+      local row = {}
+      for j, name in ipairs(header) do
+        row[name] = records[n][j]
+      end
+      rows[#rows + 1] = row
+    end
+    return rows
+  end
+
+  function __sam.csv_write(rows, options)
+    options = options or {}
+    local delimiter = options.delimiter or ","
+    local function field(v)
+      local s = v == nil and "" or text(v)
+      if s:find('["\r\n]') or s:find(delimiter, 1, true) then
+        return '"' .. s:gsub('"', '""') .. '"'
+      end
+      return s
+    end
+    local columns = options.columns
+    if columns == nil and is_object(rows[1]) then
+      columns = __sam.keys(rows[1])
+    end
+    local lines = {}
+    local function add(record)
+      local fields = {}
+      for j = 1, #record do
+        fields[j] = field(record[j])
+      end
+      lines[#lines + 1] = table.concat(fields, delimiter) .. "\n"
+    end
+    if columns then
+      add(columns)
+    end
+    for _, row in ipairs(rows) do
+      if is_object(row) then
+        local record = {}
+        for j, name in ipairs(columns) do
+          record[j] = row[name]
+        end
+        add(record)
+      else
+        add(row)
+      end
+    end
+    return table.concat(lines)
+  end
+
+  -- digests by the coreutils of that name, from a file so that any bytes
+  -- get through
+  local function digest(command, s)
+    local file = os.tmpname()
+    local f = io.open(file, "wb")
+    f:write(s)
+    f:close()
+    local out, status = capture(command .. " " .. quote(file))
+    os.remove(file)
+    if status ~= 0 then
+      error("Could not run " .. command, 3)
+    end
+    return out:match("^%x+")
+  end
+
+  function __sam.sha256(s)
+    return digest("sha256sum", s)
+  end
+
+  function __sam.md5(s)
+    return digest("md5sum", s)
+  end
+
+  local base64 =
+    "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/"
+
+  function __sam.base64_encode(s)
+    local out = {}
+    for i = 1, #s, 3 do
+      local a, b, c = s:byte(i, i + 2)
+      local n = (a << 16) | ((b or 0) << 8) | (c or 0)
+      -- a character for each 6 bits there are bytes for, then padding
+      local count = c and 4 or b and 3 or 2
+      for j = 1, 4 do
+        local k = (n >> (24 - 6 * j)) & 63
+        out[#out + 1] = j <= count and base64:sub(k + 1, k + 1) or "="
+      end
+    end
+    return table.concat(out)
+  end
+
+  function __sam.base64_decode(s)
+    local digits = s:gsub("=+$", "")
+    local padding = #s - #digits
+    local out, n, bits = {}, 0, 0
+    for i = 1, #digits do
+      local c = digits:sub(i, i)
+      local k = base64:find(c, 1, true)
+      if k == nil then
+        error("Invalid base64: " .. c .. " at position " .. i, 2)
+      end
+      n = ((n << 6) | (k - 1)) & 0x3fff
+      bits = bits + 6
+      if bits >= 8 then
+        bits = bits - 8
+        out[#out + 1] = string.char((n >> bits) & 0xff)
+      end
+    end
+    if bits == 6 or padding > 2 or (padding > 0 and #s % 4 ~= 0) then
+      error("Invalid base64: the text ends part way through a byte", 2)
+    end
+    return table.concat(out)
+  end
+
+  local function byte_of(digits)
+    return string.char(tonumber(digits, 16))
+  end
+
+  function __sam.hex_encode(s)
+    return (s:gsub(".", function(c)
+      return string.format("%02x", c:byte())
+    end))
+  end
+
+  function __sam.hex_decode(s)
+    if #s % 2 ~= 0 or s:find("%X") then
+      error("Invalid hexadecimal: " .. s, 2)
+    end
+    return (s:gsub("%x%x", byte_of))
+  end
+
+  function __sam.url_encode(s)
+    return (s:gsub("[^%w%-_.~]", function(c)
+      return string.format("%%%02X", c:byte())
+    end))
+  end
+
+  -- `+` is left as is
+  function __sam.url_decode(s)
+    if s:gsub("%%%x%x", ""):find("%", 1, true) then
+      error("Invalid URL encoding: a % without two digits after it", 2)
+    end
+    return (s:gsub("%%(%x%x)", byte_of))
+  end
+
+  function __sam.reduce(items, f, ...)
+    local first, result = 1, ...
+    if select('#', ...) == 0 then
+      if #items == 0 then
+        error("`reduce` of an empty array needs a value to start from", 2)
+      end
+      first, result = 2, items[1]
+    end
+    for i = first, #items do
+      result = f(result, items[i])
+    end
+    return result
+  end
+
+  function __sam.each(items, f)
+    for _, item in ipairs(items) do
+      f(item)
+    end
+  end
+
+  function __sam.rand()
+    return math.random()
+  end
+
+  function __sam.rand_int(low, high)
+    return math.random(low, high)
+  end
+
+  function __sam.shuffle(items)
+    local out = table.move(items, 1, #items, 1, {})
+    for i = #out, 2, -1 do
+      local j = math.random(i)
+      out[i], out[j] = out[j], out[i]
+    end
+    return out
+  end
+
+  function __sam.seed(n)
+    math.randomseed(n)
+  end
+
+  function __sam.now()
+    return os.time()
+  end
+
+  function __sam.clock()
+    return os.clock()
+  end
+
+  function __sam.sleep(seconds)
+    os.execute("sleep " .. math.max(seconds, 0))
+  end
+
+  -- with the directives of os.date, which has no %f
+  function __sam.format_time(seconds, pattern)
+    local utc = "!" .. (pattern or "%Y-%m-%dT%H:%M:%SZ")
+    return os.date(utc, math.floor(seconds))
+  end
+
+  function __sam.cwd()
+    return dir
+  end
+
+  function __sam.chdir(to)
+    local line = "cd " .. quote(dir) .. " && cd " .. quote(to)
+    local out, status = capture(line .. " 2>/dev/null && pwd")
+    if status ~= 0 then
+      error(to .. " is not a directory", 2)
+    end
+    dir = out:gsub("\n$", "")
+  end
+
+  function __sam.par_map(items, f)
+    local out = {}
+    for i, item in ipairs(items) do
+      out[i] = f(item)
+    end
+    return out
+  end
+
+  function __sam.par_filter(items, f)
+    local out = {}
+    for _, item in ipairs(items) do
+      if condition(f(item)) then
+        out[#out + 1] = item
+      end
+    end
+    return out
+  end
+
+  -- the parallel ones run in order here anyway
+  __sam.map = __sam.par_map
+  __sam.filter = __sam.par_filter
+
+  function __sam.exit(status)
+    os.exit(status or 0)
+  end
+
+  function __sam.assert(cond, message)
+    if condition(cond) then
+      return
+    elseif message == nil then
+      error("Assertion failed", 2)
+    end
+    error("Assertion failed: " .. text(message), 2)
+  end
+
+  function __sam.assert_eq(a, b)
+    if not equal(a, b) then
+      error("Assertion failed: " .. text(a) .. " != " .. text(b), 2)
+    end
+  end
+end
+"#;
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::ast;
+
+  fn compile(source: &str, target: Target) -> Result<String, SamError> {
+    let program = ast::parse(source)?;
+    let code = transpile(&program, target, &Config::default())?;
+
+    // without the runtime
+    return Ok(code[target.runtime().len() + 1..].to_owned());
+  }
+
+  #[test]
+  fn test_strings() {
+    assert_eq!(lua_string("a \"b\"\n\\"), "\"a \\\"b\\\"\\n\\\\\"");
+    assert_eq!(lua_string("\u{1b}[0m é"), "\"\\u{1b}[0m é\"");
+    assert_eq!(continue_label(1), "continue");
+    assert_eq!(continue_label(2), "continue2");
+  }
+
+  #[test]
+  fn test_javascript() {
+    let source = "\
+let files = ls('-1');
+let count = (items) => {
+  let n = 0;
+  for item in items {
+    if (item == 'skip') { continue; }
+    n = n + 1;
+  }
+  return n / (2 - 1);
+};
+let n = count([1, 2]);
+let n = n * (1 + 2);";
+
+    assert_eq!(
+      compile(source, Target::Js).unwrap(),
+      "\
+let n;
+let files = __sam.run(\"ls\", [\"-1\"]);
+let count = (items) => {
+  let n = 0;
+  for (let item of items) {
+    if (item === \"skip\") {
+      continue;
+    }
+    n = n + 1;
+  }
+  return n / (2 - 1);
+};
+n = count([1, 2]);
+n = n * (1 + 2);
+"
+    );
+  }
+
+  #[test]
+  fn test_lua() {
+    let source = "\
+let greet = (name) => { return 'hi ' + name; };
+let first = (items) => { return items[0] + items[i]; };
+if (ok && found) { greet('you'); } else if (a < 2) { x = 1 % 2; }";
+
+    assert_eq!(
+      compile(source, Target::Lua).unwrap(),
+      "\
+local greet, first
+greet = function(name)
+  return \"hi \" .. name
+end
+first = function(items)
+  return __sam.add(items[1], items[i + 1])
+end
+if __sam.truthy(ok) and __sam.truthy(found) then
+  greet(\"you\")
+elseif a < 2 then
+  x = math.fmod(1, 2)
+end
+"
+    );
+  }
+
+  #[test]
+  fn test_unsupported() {
+    for source in [
+      "import('util');",
+      "let a = if (b) {};",
+      "let c = lazy(() => { return 1; });",
+    ] {
+      let err = compile(source, Target::Js).unwrap_err();
+      assert_eq!(err.code(), Some(codes::NOT_COMPILABLE));
+    }
+  }
+}
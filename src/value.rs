@@ -2,40 +2,159 @@
 
 use std::collections::HashMap;
 use std::fmt;
-use std::ops::{Range, *};
-use tree_sitter::Node;
+use std::ops::*;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
 
 use crate::{
-  context::{Context, EvalControl},
-  evaluate::evaluate_expression,
+  ast::Block,
+  codes,
+  config::DivisionPolicy,
+  error::{SamError, Span},
+  ffi::{CallCache, Coprocess, Stream},
+  process::{ExitPolicy, StderrMode},
+  symbol::Symbol,
 };
+use serde::{Deserialize, Serialize};
 
 // TODO: Arrays
+// strings, bytes, objects, and arrays are shared between clones, so that
+// passing e.g. a large command result around does not copy it; they are
+// copied on write with `Arc::make_mut`. Every payload bigger than a pointer
+// is behind one, which keeps a value at 16 bytes, see test_value_size
 #[derive(Debug, Clone)]
 pub enum Value {
   SamNumber(Number),
-  // byte range of function for lazy evaluation
-  SamFunction(Function),
-  SamForeignFunction(ForeignFunction),
-  SamString(String),
-  SamObject(HashMap<String, Value>),
-  SamArray(Vec<Value>),
+  // a lambda of the script
+  SamFunction(Arc<Function>),
+  SamForeignFunction(Arc<ForeignFunction>),
+  SamString(Arc<String>),
+  // raw output of a command, e.g. an archive or an image
+  SamBytes(Arc<Vec<u8>>),
+  SamObject(Arc<HashMap<String, Value>>),
+  SamArray(Arc<Vec<Value>>),
+  SamHandle(Arc<Handle>),
+  SamStream(Arc<Stream>),
+  // implemented in Rust, e.g. a function loaded from a shared library
+  SamNativeFunction(Arc<NativeFunction>),
+  // `lazy(() => { return load(); })`, a function run the first time the
+  // variable holding it is read, which then holds its result instead
+  SamLazy(Arc<Function>),
   Undefined,
 }
 
 #[derive(Debug, Clone)]
 pub struct Function {
-  // functions are represented as their byte range and parameter list
-  pub body: Range<usize>,
-  pub params: Vec<String>,
+  pub params: Vec<Symbol>,
+  // the lowered body, shared by every function made from the same lambda
+  pub body: Arc<Block>,
 }
 
 #[derive(Debug, Clone)]
 pub struct ForeignFunction {
   pub cmd: String,
+  // run the command through `sh -c` instead of executing it directly
+  pub shell: bool,
+  pub input: ArgPassing,
+  pub output: OutputFormat,
+  // overrides the global timeout for calls of this function
+  pub timeout: Option<Duration>,
+  // environment variables set for every call of this function
+  pub env: HashMap<String, String>,
+  // run with only the variables in `env` instead of inheriting ours
+  pub clear_env: bool,
+  // overrides the global exit status policy for calls of this function
+  pub exit_status: Option<ExitPolicy>,
+  // overrides where the command's stderr goes
+  pub stderr: Option<StderrMode>,
+  // calls return the output as a stream of lines while the command runs
+  pub stream: bool,
+  // declared parameters and result, checked on every call
+  pub signature: Option<ForeignSignature>,
+  // earlier results reused for calls with the same arguments
+  pub cache: Option<Arc<CallCache>>,
+  // sam functions the command may call back while it runs, over JSON-RPC
+  // on its stdin and stdout
+  pub callbacks: Vec<String>,
+  // a process kept running between calls, which answers each call with a
+  // line of JSON
+  pub coprocess: Option<Arc<Coprocess>>,
+  // directory the command runs in, instead of the script's working directory
+  pub cwd: Option<PathBuf>,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ForeignSignature {
+  pub params: Vec<Param>,
+  pub returns: Type,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Param {
+  // only used in messages
+  pub name: Option<String>,
+  pub ty: Type,
+}
+
+// types a foreign function can declare for its parameters and result
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Type {
+  Any,
+  Number,
+  // a number without a fractional part
+  Int,
+  String,
+  Bytes,
+  Array,
+  Object,
+}
+
+pub type NativeCall = dyn Fn(&[Value]) -> Result<Value, SamError> + Send + Sync;
+
+#[derive(Clone)]
+pub struct NativeFunction {
+  pub name: String,
+  call: Arc<NativeCall>,
+}
+
+// how call arguments reach a foreign function
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ArgPassing {
+  // one command line argument per value, formatted as text
+  Argv,
+  // a JSON array of all values written to stdin
+  JsonStdin,
+  // the first value written to stdin as is, the others as with Argv
+  RawStdin,
+}
+
+// what the stdout of a foreign function becomes
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum OutputFormat {
+  // parsed as one JSON value
+  #[default]
+  Json,
+  // kept as bytes, unparsed
+  Bytes,
+}
+
+// result of `spawn`, a call running on another thread until awaited
+#[derive(Clone)]
+pub struct Handle {
+  state: Arc<Mutex<HandleState>>,
+  // where the call was spawned, errors it raises are reported there
+  pub spawned_at: Span,
+}
+
+enum HandleState {
+  Running(JoinHandle<Result<Value, SamError>>),
+  Finished(Value),
+  Failed,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub enum Number {
   SamInt(i64),
   SamFloat(f64),
@@ -46,19 +165,46 @@ Value internal representation
 ========================= */
 
 impl Value {
-  pub fn get_attr(&self, node: &Node, key: &str) -> Result<&Value, String> {
+  // name of the type of the value, as shown in error messages
+  pub fn type_name(&self) -> &'static str {
+    match self {
+      Value::SamNumber(_) => "number",
+      Value::SamFunction(_) => "function",
+      Value::SamForeignFunction(_) => "foreign function",
+      Value::SamString(_) => "string",
+      Value::SamBytes(_) => "bytes",
+      Value::SamObject(_) => "object",
+      Value::SamArray(_) => "array",
+      Value::SamHandle(_) => "handle",
+      Value::SamStream(_) => "stream",
+      Value::SamNativeFunction(_) => "native function",
+      Value::SamLazy(_) => "lazy value",
+      Value::Undefined => "undefined",
+    }
+  }
+
+  pub fn get_attr(&self, span: Span, key: &str) -> Result<&Value, SamError> {
     match self {
       Value::SamObject(map) => Ok(map.get(key).unwrap_or(&Value::Undefined)),
-      _ => Err(format!(
-        "Cannot access property '{}' on non-object {:?}",
-        key,
-        node.range()
-      )),
+      _ => Err(
+        SamError::type_error(
+          codes::NOT_AN_OBJECT,
+          format!("Cannot access property '{}' on non-object", key),
+        )
+        .at(span),
+      ),
     }
   }
 
-  pub fn decode_escape(esc: &str) -> Result<char, String> {
-    let body = &esc[1..]; // strip leading '\'
+  pub fn decode_escape(esc: &str) -> Result<char, SamError> {
+    // strip leading '\'
+    let body = esc.strip_prefix('\\').unwrap_or(esc);
+    let invalid = |kind: &str| {
+      SamError::value(
+        codes::INVALID_ESCAPE,
+        format!("Invalid {} escape: \\{}", kind, body),
+      )
+    };
 
     let c = match body {
       "n" => '\n',
@@ -70,14 +216,16 @@ impl Value {
 
       // Octal: \123
       _ if body.chars().all(|c| c.is_digit(8)) => {
-        let value = u32::from_str_radix(body, 8).unwrap();
-        char::from_u32(value).ok_or("Invalid octal escape")?
+        let value = u32::from_str_radix(body, 8)
+          .map_err(|e| invalid("octal").caused_by(e))?;
+        char::from_u32(value).ok_or_else(|| invalid("octal"))?
       }
 
       // Hex: \xFF
       _ if body.starts_with('x') => {
-        let value = u32::from_str_radix(&body[1..], 16).unwrap();
-        char::from_u32(value).ok_or("Invalid hex escape")?
+        let value = u32::from_str_radix(&body[1..], 16)
+          .map_err(|e| invalid("hex").caused_by(e))?;
+        char::from_u32(value).ok_or_else(|| invalid("hex"))?
       }
 
       // Unicode: \u1234 or \u{1F600}
@@ -86,11 +234,17 @@ impl Value {
           .trim_start_matches("u")
           .trim_start_matches('{')
           .trim_end_matches('}');
-        let value = u32::from_str_radix(hex, 16).unwrap();
-        char::from_u32(value).ok_or("Invalid unicode escape")?
+        let value = u32::from_str_radix(hex, 16)
+          .map_err(|e| invalid("unicode").caused_by(e))?;
+        char::from_u32(value).ok_or_else(|| invalid("unicode"))?
       }
 
-      _ => return Err(format!("Unknown escape sequence: \\{}", body)),
+      _ => {
+        return Err(SamError::value(
+          codes::INVALID_ESCAPE,
+          format!("Unknown escape sequence: \\{}", body),
+        ));
+      }
     };
 
     return Ok(c);
@@ -102,60 +256,160 @@ Function internal representation
 ========================= */
 
 impl Function {
-  pub fn new(body: Range<usize>, params: Vec<String>) -> Self {
-    return Function { body, params };
-  }
-
-  pub fn extract_params(
-    node: Node,
-    source: &[u8],
-  ) -> Result<Vec<String>, String> {
-    let mut params = Vec::new();
-    let mut walker = node.walk();
-
-    for child in node.named_children(&mut walker) {
-      if child.kind() == "identifier" {
-        let Ok(varname) = child.utf8_text(source) else {
-          return Err(format!(
-            "There was an error when parsing the variable name of a parameter."
-          ));
-        };
+  pub fn new(params: Vec<Symbol>, body: Arc<Block>) -> Self {
+    return Function { params, body };
+  }
+}
 
-        params.push(varname.to_owned());
-      }
+impl ForeignFunction {
+  pub fn new(cmd: String) -> Self {
+    return ForeignFunction {
+      cmd,
+      shell: false,
+      input: ArgPassing::Argv,
+      output: OutputFormat::Json,
+      timeout: None,
+      env: HashMap::new(),
+      clear_env: false,
+      exit_status: None,
+      stderr: None,
+      stream: false,
+      signature: None,
+      cache: None,
+      callbacks: Vec::new(),
+      coprocess: None,
+      cwd: None,
+    };
+  }
+
+  pub fn shell(cmd: String) -> Self {
+    return ForeignFunction {
+      shell: true,
+      ..ForeignFunction::new(cmd)
+    };
+  }
+}
+
+impl NativeFunction {
+  pub fn new(
+    name: &str,
+    call: impl Fn(&[Value]) -> Result<Value, SamError> + Send + Sync + 'static,
+  ) -> Self {
+    return NativeFunction {
+      name: name.to_owned(),
+      call: Arc::new(call),
+    };
+  }
+
+  pub fn call(&self, args: &[Value]) -> Result<Value, SamError> {
+    return (self.call)(args);
+  }
+}
+
+impl fmt::Debug for NativeFunction {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "NativeFunction({})", self.name)
+  }
+}
+
+impl Type {
+  pub fn parse(name: &str) -> Option<Type> {
+    match name {
+      "any" => Some(Type::Any),
+      "number" => Some(Type::Number),
+      "int" => Some(Type::Int),
+      "string" => Some(Type::String),
+      "bytes" => Some(Type::Bytes),
+      "array" => Some(Type::Array),
+      "object" => Some(Type::Object),
+      _ => None,
     }
+  }
 
-    Ok(params)
-  }
-
-  pub fn extract_args(
-    node: Node,
-    ctx: &mut Context,
-    source: &[u8],
-  ) -> Result<Vec<Value>, String> {
-    let mut args = Vec::new();
-    let mut walker = node.walk();
-
-    for arg in node.named_children(&mut walker) {
-      match evaluate_expression(arg, ctx, source)? {
-        EvalControl::Value(a) => args.push(a),
-        EvalControl::Reference(a) => args.push(a.clone()),
-        _ => {
-          return Err(format!(
-            "Unexpected return expression. {:#?}",
-            node.range()
-          ));
-        }
-      }
+  pub fn name(self) -> &'static str {
+    match self {
+      Type::Any => "any",
+      Type::Number => "number",
+      Type::Int => "int",
+      Type::String => "string",
+      Type::Bytes => "bytes",
+      Type::Array => "array",
+      Type::Object => "object",
     }
+  }
 
-    Ok(args)
+  pub fn accepts(self, v: &Value) -> bool {
+    return matches!(
+      (self, v),
+      (Type::Any, _)
+        | (Type::Number, Value::SamNumber(_))
+        | (Type::Int, Value::SamNumber(Number::SamInt(_)))
+        | (Type::String, Value::SamString(_))
+        | (Type::Bytes, Value::SamBytes(_))
+        | (Type::Array, Value::SamArray(_))
+        | (Type::Object, Value::SamObject(_))
+    );
   }
 }
 
-impl ForeignFunction {
-  pub fn new(cmd: String) -> Self {
-    return ForeignFunction { cmd };
+/* =========================
+Handles
+========================= */
+
+impl Handle {
+  pub fn spawn(
+    spawned_at: Span,
+    call: impl FnOnce() -> Result<Value, SamError> + Send + 'static,
+  ) -> Self {
+    return Handle {
+      state: Arc::new(Mutex::new(HandleState::Running(thread::spawn(call)))),
+      spawned_at,
+    };
+  }
+
+  pub fn finished(spawned_at: Span, value: Value) -> Self {
+    return Handle {
+      state: Arc::new(Mutex::new(HandleState::Finished(value))),
+      spawned_at,
+    };
+  }
+
+  // wait for the call to finish; awaiting again gives the same value
+  pub fn join(&self) -> Result<Value, SamError> {
+    let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+
+    match std::mem::replace(&mut *state, HandleState::Failed) {
+      HandleState::Finished(v) => {
+        *state = HandleState::Finished(v.clone());
+        Ok(v)
+      }
+
+      HandleState::Running(thread) => match thread.join() {
+        Ok(Ok(v)) => {
+          *state = HandleState::Finished(v.clone());
+          Ok(v)
+        }
+        Ok(Err(e)) => Err(e.at(self.spawned_at)),
+        Err(_) => Err(
+          SamError::runtime(codes::INTERNAL_ERROR, "The spawned call crashed")
+            .at(self.spawned_at),
+        ),
+      },
+
+      HandleState::Failed => Err(
+        SamError::runtime(
+          codes::AWAIT_FAILED,
+          "The spawned call already failed when it was awaited before",
+        )
+        .at(self.spawned_at),
+      ),
+    }
+  }
+}
+
+impl fmt::Debug for Handle {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "Handle({})", self.spawned_at)
   }
 }
 
@@ -164,20 +418,46 @@ Number arithmetic
 ========================= */
 
 impl Number {
-  fn as_f64(self) -> f64 {
+  pub fn as_f64(self) -> f64 {
     match self {
       Number::SamInt(i) => i as f64,
       Number::SamFloat(f) => f,
     }
   }
+
+  fn is_zero(self) -> bool {
+    return self.as_f64() == 0.0;
+  }
+
+  // division rounding towards negative infinity, integral for integers
+  // unless the quotient does not fit one, as for i64::MIN and -1
+  pub fn floor_div(self, rhs: Number) -> Number {
+    match (self, rhs) {
+      (Number::SamInt(a), Number::SamInt(b)) => {
+        match (a.checked_div(b), a.checked_rem(b)) {
+          (Some(q), Some(r)) if r != 0 && (a < 0) != (b < 0) => {
+            Number::SamInt(q - 1)
+          }
+          (Some(q), Some(_)) => Number::SamInt(q),
+          _ => Number::SamFloat((a as f64 / b as f64).floor()),
+        }
+      }
+      (a, b) => Number::SamFloat((a.as_f64() / b.as_f64()).floor()),
+    }
+  }
 }
 
+// integer sums, differences, and products that do not fit an i64 are
+// floats, like every other result out of the integer range
 impl Add for Number {
   type Output = Number;
 
   fn add(self, rhs: Number) -> Number {
     match (self, rhs) {
-      (Number::SamInt(a), Number::SamInt(b)) => Number::SamInt(a + b),
+      (Number::SamInt(a), Number::SamInt(b)) => match a.checked_add(b) {
+        Some(n) => Number::SamInt(n),
+        None => Number::SamFloat(a as f64 + b as f64),
+      },
       (a, b) => Number::SamFloat(a.as_f64() + b.as_f64()),
     }
   }
@@ -188,7 +468,10 @@ impl Sub for Number {
 
   fn sub(self, rhs: Number) -> Number {
     match (self, rhs) {
-      (Number::SamInt(a), Number::SamInt(b)) => Number::SamInt(a - b),
+      (Number::SamInt(a), Number::SamInt(b)) => match a.checked_sub(b) {
+        Some(n) => Number::SamInt(n),
+        None => Number::SamFloat(a as f64 - b as f64),
+      },
       (a, b) => Number::SamFloat(a.as_f64() - b.as_f64()),
     }
   }
@@ -199,7 +482,10 @@ impl Mul for Number {
 
   fn mul(self, rhs: Number) -> Number {
     match (self, rhs) {
-      (Number::SamInt(a), Number::SamInt(b)) => Number::SamInt(a * b),
+      (Number::SamInt(a), Number::SamInt(b)) => match a.checked_mul(b) {
+        Some(n) => Number::SamInt(n),
+        None => Number::SamFloat(a as f64 * b as f64),
+      },
       (a, b) => Number::SamFloat(a.as_f64() * b.as_f64()),
     }
   }
@@ -223,11 +509,9 @@ impl Add for Value {
   fn add(self, rhs: Value) -> Value {
     match (self, rhs) {
       (Value::SamNumber(a), Value::SamNumber(b)) => Value::SamNumber(a + b),
-      (Value::SamString(a), Value::SamString(b)) => {
-        let mut a = a.to_owned();
-        let b = b.to_owned();
-
-        a.push_str(&b);
+      (Value::SamString(mut a), Value::SamString(b)) => {
+        // appends in place unless the left string is shared
+        Arc::make_mut(&mut a).push_str(&b);
         Value::SamString(a)
       }
       _ => Value::Undefined,
@@ -268,6 +552,112 @@ impl Div for Value {
   }
 }
 
+/* =========================
+Division
+========================= */
+
+// the divisions of `Value::divide`, all under the same zero divisor policy
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Division {
+  // `a / b`
+  Div,
+  // `a % b`
+  Rem,
+  // `floor_div(a, b)`
+  Floor,
+}
+
+impl Value {
+  // `/`, `%`, and `floor_div`, with a zero divisor handled by the given
+  // policy
+  pub fn divide(
+    self,
+    op: Division,
+    rhs: Value,
+    policy: DivisionPolicy,
+  ) -> Result<Value, SamError> {
+    let (Value::SamNumber(a), Value::SamNumber(b)) = (&self, &rhs) else {
+      return Ok(Value::Undefined);
+    };
+    let (a, b) = (*a, *b);
+
+    if !b.is_zero() {
+      return Ok(match op {
+        Division::Div => self / rhs,
+        Division::Rem => self % rhs,
+        Division::Floor => Value::SamNumber(a.floor_div(b)),
+      });
+    }
+
+    match policy {
+      DivisionPolicy::Error => {
+        let expression = match op {
+          Division::Div => format!("{} / {}", a, b),
+          Division::Rem => format!("{} % {}", a, b),
+          Division::Floor => format!("floor_div({}, {})", a, b),
+        };
+        Err(SamError::value(
+          codes::DIVISION_BY_ZERO,
+          format!("Division by zero in `{}`", expression),
+        ))
+      }
+      DivisionPolicy::Undefined => Ok(Value::Undefined),
+      DivisionPolicy::Ieee => {
+        let (a, b) = (a.as_f64(), b.as_f64());
+        let result = match op {
+          Division::Div => a / b,
+          Division::Rem => a % b,
+          Division::Floor => (a / b).floor(),
+        };
+        Ok(Value::SamNumber(Number::SamFloat(result)))
+      }
+    }
+  }
+}
+
+/* =========================
+Strict operand checks
+========================= */
+
+impl Value {
+  // in strict mode an operator may only be applied to operands it has a
+  // meaning for, instead of silently producing undefined
+  pub fn check_operands(
+    op: &str,
+    left: &Value,
+    right: &Value,
+  ) -> Result<(), SamError> {
+    use Value::{SamNumber, SamString, Undefined};
+
+    let ok = match op {
+      "+" | "<" | ">" | "<=" | ">=" => matches!(
+        (left, right),
+        (SamNumber(_), SamNumber(_)) | (SamString(_), SamString(_))
+      ),
+      "==" | "!=" => true,
+      _ => matches!((left, right), (SamNumber(_), SamNumber(_))),
+    };
+
+    let code = if matches!(left, Undefined) || matches!(right, Undefined) {
+      codes::UNDEFINED_OPERAND
+    } else if !ok {
+      codes::OPERAND_MISMATCH
+    } else {
+      return Ok(());
+    };
+
+    return Err(SamError::type_error(
+      code,
+      format!(
+        "Cannot apply `{}` to {} and {}",
+        op,
+        left.type_name(),
+        right.type_name()
+      ),
+    ));
+  }
+}
+
 /* =========================
 Number modulo
 ========================= */
@@ -277,7 +667,12 @@ impl Rem for Number {
 
   fn rem(self, rhs: Number) -> Number {
     match (self, rhs) {
-      (Number::SamInt(a), Number::SamInt(b)) => Number::SamInt(a % b),
+      (Number::SamInt(a), Number::SamInt(b)) => match a.checked_rem(b) {
+        Some(r) => Number::SamInt(r),
+        // i64::MIN % -1 overflows while dividing, though the remainder is 0
+        None if b == -1 => Number::SamInt(0),
+        None => Number::SamFloat(a as f64 % b as f64),
+      },
       (a, b) => Number::SamFloat(a.as_f64().rem_euclid(b.as_f64())),
     }
   }
@@ -324,6 +719,153 @@ impl From<Value> for bool {
   }
 }
 
+impl From<i64> for Value {
+  fn from(i: i64) -> Self {
+    Value::SamNumber(Number::SamInt(i))
+  }
+}
+
+impl From<f64> for Value {
+  fn from(f: f64) -> Self {
+    Value::SamNumber(Number::SamFloat(f))
+  }
+}
+
+impl From<&str> for Value {
+  fn from(s: &str) -> Self {
+    Value::SamString(Arc::new(s.to_owned()))
+  }
+}
+
+impl From<String> for Value {
+  fn from(s: String) -> Self {
+    Value::SamString(Arc::new(s))
+  }
+}
+
+impl<T: Into<Value>> From<Vec<T>> for Value {
+  fn from(items: Vec<T>) -> Self {
+    Value::SamArray(Arc::new(items.into_iter().map(Into::into).collect()))
+  }
+}
+
+impl<T: Into<Value>> From<HashMap<String, T>> for Value {
+  fn from(fields: HashMap<String, T>) -> Self {
+    let fields = fields.into_iter().map(|(k, v)| (k, v.into())).collect();
+    Value::SamObject(Arc::new(fields))
+  }
+}
+
+impl From<Function> for Value {
+  fn from(f: Function) -> Self {
+    Value::SamFunction(Arc::new(f))
+  }
+}
+
+impl From<ForeignFunction> for Value {
+  fn from(f: ForeignFunction) -> Self {
+    Value::SamForeignFunction(Arc::new(f))
+  }
+}
+
+impl From<NativeFunction> for Value {
+  fn from(f: NativeFunction) -> Self {
+    Value::SamNativeFunction(Arc::new(f))
+  }
+}
+
+impl From<Handle> for Value {
+  fn from(h: Handle) -> Self {
+    Value::SamHandle(Arc::new(h))
+  }
+}
+
+impl From<Stream> for Value {
+  fn from(s: Stream) -> Self {
+    Value::SamStream(Arc::new(s))
+  }
+}
+
+// none is undefined
+impl<T: Into<Value>> From<Option<T>> for Value {
+  fn from(v: Option<T>) -> Self {
+    v.map_or(Value::Undefined, Into::into)
+  }
+}
+
+// the error of a failed conversion back to a Rust type
+fn expected(expected: &str, found: &Value) -> SamError {
+  SamError::type_error(
+    codes::ARGUMENT_TYPE,
+    format!("Expected {}, found {}", expected, found.type_name()),
+  )
+}
+
+// only integers, a float is not silently truncated
+impl TryFrom<Value> for i64 {
+  type Error = SamError;
+
+  fn try_from(v: Value) -> Result<Self, SamError> {
+    match v {
+      Value::SamNumber(Number::SamInt(i)) => Ok(i),
+      _ => Err(expected("an integer", &v)),
+    }
+  }
+}
+
+// any number, integers are widened
+impl TryFrom<Value> for f64 {
+  type Error = SamError;
+
+  fn try_from(v: Value) -> Result<Self, SamError> {
+    match v {
+      Value::SamNumber(n) => Ok(n.as_f64()),
+      _ => Err(expected("a number", &v)),
+    }
+  }
+}
+
+impl TryFrom<Value> for String {
+  type Error = SamError;
+
+  fn try_from(v: Value) -> Result<Self, SamError> {
+    match v {
+      Value::SamString(s) => Ok(Arc::unwrap_or_clone(s)),
+      _ => Err(expected("a string", &v)),
+    }
+  }
+}
+
+impl<T: TryFrom<Value, Error = SamError>> TryFrom<Value> for Vec<T> {
+  type Error = SamError;
+
+  fn try_from(v: Value) -> Result<Self, SamError> {
+    match v {
+      Value::SamArray(items) => Arc::unwrap_or_clone(items)
+        .into_iter()
+        .map(T::try_from)
+        .collect(),
+      _ => Err(expected("an array", &v)),
+    }
+  }
+}
+
+impl<T: TryFrom<Value, Error = SamError>> TryFrom<Value>
+  for HashMap<String, T>
+{
+  type Error = SamError;
+
+  fn try_from(v: Value) -> Result<Self, SamError> {
+    match v {
+      Value::SamObject(fields) => Arc::unwrap_or_clone(fields)
+        .into_iter()
+        .map(|(k, v)| T::try_from(v).map(|v| (k, v)))
+        .collect(),
+      _ => Err(expected("an object", &v)),
+    }
+  }
+}
+
 /* =========================
 Number comparison
 ========================= */
@@ -349,10 +891,18 @@ impl PartialEq for Value {
     match (self, other) {
       (Value::SamNumber(a), Value::SamNumber(b)) => a == b,
       (Value::SamString(a), Value::SamString(b)) => a == b,
+      (Value::SamBytes(a), Value::SamBytes(b)) => a == b,
       (Value::SamArray(a), Value::SamArray(b)) => a == b,
       (Value::Undefined, Value::Undefined) => true,
+      (Value::SamHandle(a), Value::SamHandle(b)) => {
+        Arc::ptr_eq(&a.state, &b.state)
+      }
+      (Value::SamStream(a), Value::SamStream(b)) => a.same(b),
+      (Value::SamNativeFunction(a), Value::SamNativeFunction(b)) => {
+        Arc::ptr_eq(&a.call, &b.call)
+      }
       (Value::SamForeignFunction(a), Value::SamForeignFunction(b)) => {
-        a.cmd == b.cmd
+        a.cmd == b.cmd && a.shell == b.shell && a.input == b.input
       }
       _ => false,
     }
@@ -380,17 +930,27 @@ impl fmt::Display for Value {
 
       Value::SamString(s) => write!(f, "{s}"),
 
+      Value::SamBytes(b) => write!(f, "<{} bytes>", b.len()),
+
       Value::SamFunction(_) => write!(f, "<function>"),
 
       Value::SamForeignFunction(_) => write!(f, "<foreign-function>"),
 
       Value::SamArray(a) => write!(f, "{:#?}", a),
 
+      Value::SamHandle(_) => write!(f, "<handle>"),
+
+      Value::SamStream(_) => write!(f, "<stream>"),
+
+      Value::SamNativeFunction(_) => write!(f, "<native-function>"),
+
+      Value::SamLazy(_) => write!(f, "<lazy>"),
+
       Value::SamObject(obj) => {
         write!(f, "{{")?;
         let mut first = true;
 
-        for (k, v) in obj {
+        for (k, v) in obj.iter() {
           if !first {
             write!(f, ", ")?;
           }
@@ -444,6 +1004,25 @@ mod tests {
     assert_eq!(a * b, Number::SamInt(20));
   }
 
+  #[test]
+  fn test_number_overflow() {
+    let max = Number::SamInt(i64::MAX);
+    let min = Number::SamInt(i64::MIN);
+    let one = Number::SamInt(1);
+
+    // Number equality compares as floats, so the variants are matched
+    assert!(matches!(max + one, Number::SamFloat(f) if f == 2f64.powi(63)));
+    assert!(matches!(min - one, Number::SamFloat(f) if f == -(2f64.powi(63))));
+    let two = Number::SamInt(2);
+    assert!(matches!(max * two, Number::SamFloat(f) if f == 2f64.powi(64)));
+    let minus_one = Number::SamInt(-1);
+    assert!(matches!(min * minus_one, Number::SamFloat(_)));
+
+    // up to the limit they stay integers
+    assert!(matches!(max - one + one, Number::SamInt(i64::MAX)));
+    assert!(matches!(min + one - one, Number::SamInt(i64::MIN)));
+  }
+
   #[test]
   fn test_number_div() {
     let a = Number::SamInt(5);
@@ -456,6 +1035,13 @@ mod tests {
     let a = Number::SamInt(7);
     let b = Number::SamInt(4);
     assert_eq!(a % b, Number::SamInt(3));
+
+    let min = Number::SamInt(i64::MIN);
+    assert_eq!(min % Number::SamInt(-1), Number::SamInt(0));
+    let min = Value::SamNumber(min);
+    let rem =
+      min.divide(Division::Rem, Value::from(-1i64), DivisionPolicy::Error);
+    assert_eq!(rem.unwrap(), Value::from(0i64));
   }
 
   /* =========================
@@ -501,9 +1087,180 @@ mod tests {
     assert!(a < b);
   }
 
+  #[test]
+  fn test_decode_escape() {
+    assert_eq!(Value::decode_escape("\\n").unwrap(), '\n');
+    assert_eq!(Value::decode_escape("\\x41").unwrap(), 'A');
+    assert_eq!(Value::decode_escape("\\u{1F600}").unwrap(), '😀');
+  }
+
+  #[test]
+  fn test_decode_escape_malformed() {
+    assert!(Value::decode_escape("\\x").is_err());
+    assert!(Value::decode_escape("\\u{110000}").is_err());
+    assert!(Value::decode_escape("\\q").is_err());
+  }
+
+  #[test]
+  fn test_number_floor_div() {
+    assert_eq!(
+      Number::SamInt(7).floor_div(Number::SamInt(2)),
+      Number::SamInt(3)
+    );
+    assert_eq!(
+      Number::SamInt(-7).floor_div(Number::SamInt(2)),
+      Number::SamInt(-4)
+    );
+    assert_eq!(
+      Number::SamFloat(7.5).floor_div(Number::SamInt(2)),
+      Number::SamFloat(3.0)
+    );
+
+    // 2^63 is past i64::MAX
+    let min = Number::SamInt(i64::MIN);
+    assert_eq!(
+      min.floor_div(Number::SamInt(-1)),
+      Number::SamFloat(9_223_372_036_854_775_808.0)
+    );
+    let min = Value::SamNumber(min);
+    let quotient =
+      min.divide(Division::Floor, Value::from(-1i64), DivisionPolicy::Error);
+    assert_eq!(quotient.unwrap(), Value::from(9_223_372_036_854_775_808.0));
+  }
+
+  #[test]
+  fn test_division_policy() {
+    let one = || Value::SamNumber(Number::SamInt(1));
+    let zero = || Value::SamNumber(Number::SamInt(0));
+
+    for op in [Division::Div, Division::Rem, Division::Floor] {
+      let undefined = one().divide(op, zero(), DivisionPolicy::Undefined);
+      assert_eq!(undefined.unwrap(), Value::Undefined);
+
+      let err = one().divide(op, zero(), DivisionPolicy::Error).unwrap_err();
+      assert_eq!(err.code(), Some(codes::DIVISION_BY_ZERO));
+    }
+    let err = one().divide(Division::Rem, zero(), DivisionPolicy::Error);
+    assert_eq!(err.unwrap_err().message(), "Division by zero in `1 % 0`");
+
+    let ieee = one().divide(Division::Div, zero(), DivisionPolicy::Ieee);
+    let ieee = ieee.unwrap();
+    assert_eq!(ieee, Value::SamNumber(Number::SamFloat(f64::INFINITY)));
+    let ieee = one().divide(Division::Floor, zero(), DivisionPolicy::Ieee);
+    assert_eq!(
+      ieee.unwrap(),
+      Value::SamNumber(Number::SamFloat(f64::INFINITY))
+    );
+
+    let Value::SamNumber(Number::SamFloat(nan)) = one()
+      .divide(Division::Rem, zero(), DivisionPolicy::Ieee)
+      .unwrap()
+    else {
+      panic!("expected a float");
+    };
+    assert!(nan.is_nan());
+  }
+
+  #[test]
+  fn test_check_operands() {
+    let one = Value::SamNumber(Number::SamInt(1));
+    let s = Value::from("a");
+
+    assert!(Value::check_operands("+", &one, &one).is_ok());
+    assert!(Value::check_operands("+", &s, &s).is_ok());
+    assert!(Value::check_operands("==", &s, &one).is_ok());
+
+    let err = Value::check_operands("-", &s, &one).unwrap_err();
+    assert_eq!(err.code(), Some(codes::OPERAND_MISMATCH));
+    assert_eq!(err.message(), "Cannot apply `-` to string and number");
+
+    let err = Value::check_operands("==", &one, &Value::Undefined).unwrap_err();
+    assert_eq!(err.code(), Some(codes::UNDEFINED_OPERAND));
+  }
+
+  #[test]
+  fn test_shared_payloads() {
+    let a = Value::from(vec![1i64, 2, 3]);
+    let b = a.clone();
+    let (Value::SamArray(x), Value::SamArray(y)) = (&a, &b) else {
+      panic!("expected arrays");
+    };
+    assert!(Arc::ptr_eq(x, y));
+
+    // adding to a shared string copies it first
+    let s = Value::from("ab");
+    let sum = s.clone() + Value::from("c");
+    assert_eq!(s, Value::from("ab"));
+    assert_eq!(sum, Value::from("abc"));
+  }
+
+  #[test]
+  fn test_value_size() {
+    // a tag and a number or a pointer, arrays and vm stacks stay dense
+    assert_eq!(std::mem::size_of::<Value>(), 16);
+  }
+
+  fn spawn_site() -> Span {
+    Span {
+      start: 0,
+      end: 1,
+      start_point: tree_sitter::Point::default(),
+      end_point: tree_sitter::Point::default(),
+    }
+  }
+
+  #[test]
+  fn test_handle_join() {
+    let span = spawn_site();
+    let handle = Handle::spawn(span, || Ok(Value::from("x")));
+
+    // awaiting twice gives the same value
+    assert_eq!(handle.join().unwrap(), Value::from("x"));
+    assert_eq!(handle.join().unwrap(), Value::from("x"));
+  }
+
+  #[test]
+  fn test_handle_join_failed() {
+    let span = spawn_site();
+    let handle = Handle::spawn(span, || {
+      Err(SamError::ffi(codes::COMMAND_FAILED, "Could not run"))
+    });
+
+    assert_eq!(
+      handle.join().unwrap_err().code(),
+      Some(codes::COMMAND_FAILED)
+    );
+    assert_eq!(handle.join().unwrap_err().code(), Some(codes::AWAIT_FAILED));
+  }
+
   #[test]
   fn test_bool_into_value() {
     let v: Value = true.into();
     assert_eq!(v, Value::SamNumber(Number::SamInt(1)));
   }
+
+  #[test]
+  fn test_rust_conversions() {
+    let v = Value::from(vec![1i64, 2]);
+    assert_eq!(
+      v,
+      Value::from(vec![
+        Value::SamNumber(Number::SamInt(1)),
+        Value::SamNumber(Number::SamInt(2)),
+      ])
+    );
+    assert_eq!(Vec::<i64>::try_from(v).unwrap(), vec![1, 2]);
+
+    let fields = HashMap::from([("name".to_owned(), "sam")]);
+    let v = Value::from(fields);
+    let back: HashMap<String, String> = v.try_into().unwrap();
+    assert_eq!(back["name"], "sam");
+
+    assert_eq!(f64::try_from(Value::from(2i64)).unwrap(), 2.0);
+    assert_eq!(Value::from(None::<i64>), Value::Undefined);
+
+    let err = i64::try_from(Value::from(1.5)).unwrap_err();
+    assert_eq!(err.code(), Some(codes::ARGUMENT_TYPE));
+    assert!(String::try_from(Value::from(vec!["a", "b"])).is_err());
+  }
 }
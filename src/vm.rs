@@ -0,0 +1,918 @@
+#![allow(dead_code)]
+
+// The vm backend: runs the bytecode of src/bytecode.rs on a stack machine.
+// Calls between sam functions push frames of the machine rather than
+// recursing on the Rust stack. Variables live in the scopes of the context
+// like with the tree-walker, and every operation shares its implementation
+// with it, so that both give the same results.
+
+use crate::ast::{Block, Stmt};
+use crate::bytecode::{Chunk, Failure, Op, compile_function, compile_program};
+use crate::codes;
+use crate::context::Context;
+use crate::crash;
+use crate::error::{SamError, Span};
+use crate::evaluate::{
+  Items, assign, binary, bind_args, call_foreign, check_size, condition,
+  count_iteration, element, evaluate_interface, force, iterate, lookup,
+  not_callable, run_command, stage, step,
+};
+use crate::ffi::{FFI, Shell, Stage};
+#[cfg(feature = "jit")]
+use crate::jit::{self, Compiled, Jit};
+use crate::modules;
+use crate::peephole;
+use crate::profile::{self, Kind};
+use crate::symbol::Symbol;
+use crate::value::{Function, Handle, Value};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+// run lowered top level statements, returning the value of the last
+pub fn run(statements: &[Stmt], ctx: &mut Context) -> Result<Value, SamError> {
+  return run_compiled(compile(statements), ctx);
+}
+
+// the bytecode of lowered top level statements, optimized
+pub fn compile(statements: &[Stmt]) -> Chunk {
+  let mut chunk = compile_program(statements);
+  peephole::optimize(&mut chunk);
+
+  return chunk;
+}
+
+// run the bytecode of top level statements, e.g. loaded from src/cache.rs
+pub fn run_compiled(
+  chunk: Chunk,
+  ctx: &mut Context,
+) -> Result<Value, SamError> {
+  // every run gets the whole step budget
+  ctx.steps = 0;
+
+  let mut vm = Vm::new();
+  vm.frames.push(Frame {
+    chunk: Arc::new(chunk),
+    ip: 0,
+    stack: 0,
+    scopes: ctx.call_stack.len(),
+    loops: 0,
+    call: None,
+    timed: None,
+  });
+
+  return vm.execute(ctx);
+}
+
+// run a sam function to completion, e.g. one called back by a command
+fn call_now(
+  f: Value,
+  args: Vec<Value>,
+  name: Symbol,
+  span: Span,
+  ctx: &mut Context,
+) -> Result<Value, SamError> {
+  let Value::SamFunction(func) = f else {
+    return Err(not_callable(span));
+  };
+
+  let mut vm = Vm::new();
+  vm.enter(&func, args, name, span, ctx)?;
+
+  return vm.execute(ctx);
+}
+
+// call a function value without a call instruction, for builtins that are
+// given functions
+pub(crate) fn call_value(
+  f: Value,
+  args: Vec<Value>,
+  span: Span,
+  ctx: &mut Context,
+) -> Result<Value, SamError> {
+  let name = Symbol::from("<anonymous>");
+
+  match f {
+    Value::SamForeignFunction(ff) => {
+      call_foreign(&ff, &args, span, ctx, |f, args, ctx| {
+        call_now(f, args, name, span, ctx)
+      })
+    }
+    Value::SamNativeFunction(f) => f.call(&args).map_err(|e| e.at(span)),
+    f => call_now(f, args, name, span, ctx),
+  }
+}
+
+// code being run, the top level or a call of a sam function
+struct Frame {
+  chunk: Arc<Chunk>,
+  ip: usize,
+  // where the values, scopes, and loops of the frame start
+  stack: usize,
+  scopes: usize,
+  loops: usize,
+  // callee and call site shown in call traces, none at the top level
+  call: Option<(Symbol, Span)>,
+  // the call being profiled, which ends with the frame
+  timed: Option<profile::Guard>,
+}
+
+// a running for loop
+struct Iteration {
+  items: Items,
+  iterations: u64,
+  // location of the iterable, where errors reading an item point
+  span: Span,
+}
+
+struct Vm {
+  frames: Vec<Frame>,
+  stack: Vec<Value>,
+  loops: Vec<Iteration>,
+  // calls set aside for the next pipe
+  stages: Vec<Stage>,
+  // function bodies compiled so far, the block is kept so that its address
+  // is not reused
+  functions: HashMap<*const Block, (Arc<Block>, Arc<Chunk>)>,
+  // value of the last top level statement
+  result: Value,
+  // how often each function was called, and its native code once it was
+  // called often enough, see src/jit.rs
+  #[cfg(feature = "jit")]
+  tiers: HashMap<*const Block, Tier>,
+  #[cfg(feature = "jit")]
+  jit: Option<Jit>,
+}
+
+#[cfg(feature = "jit")]
+enum Tier {
+  Counting(u32),
+  Native { code: Compiled, bails: u32 },
+  // not compiled, or native code that bailed out too often
+  Interpreted,
+}
+
+impl Vm {
+  fn new() -> Vm {
+    return Vm {
+      frames: Vec::new(),
+      stack: Vec::new(),
+      loops: Vec::new(),
+      stages: Vec::new(),
+      functions: HashMap::new(),
+      result: Value::Undefined,
+      #[cfg(feature = "jit")]
+      tiers: HashMap::new(),
+      #[cfg(feature = "jit")]
+      jit: None,
+    };
+  }
+
+  // run until the frame on top returns, or the top level ends
+  fn execute(&mut self, ctx: &mut Context) -> Result<Value, SamError> {
+    let base = self.frames.len() - 1;
+
+    return self.run_frames(base, ctx).map_err(|mut err| {
+      // leave the frames the error went through, innermost first
+      while self.frames.len() > base {
+        let frame = self.frames.pop().unwrap();
+        if let Some((name, call_site)) = &frame.call {
+          err = err.with_frame(name.as_str(), *call_site);
+        }
+        self.leave(&frame, ctx);
+      }
+      err
+    });
+  }
+
+  fn run_frames(
+    &mut self,
+    base: usize,
+    ctx: &mut Context,
+  ) -> Result<Value, SamError> {
+    loop {
+      let frame = self.frames.last_mut().unwrap();
+
+      // only the top level runs off its end, function bodies return
+      let Some(&op) = frame.chunk.code.get(frame.ip) else {
+        return Ok(std::mem::replace(&mut self.result, Value::Undefined));
+      };
+      let span = frame.chunk.spans[frame.ip];
+      frame.ip += 1;
+
+      crash::enter(span);
+      step(span, ctx)?;
+
+      match op {
+        Op::Constant(n) => {
+          let v = self.chunk().constants[n].clone();
+          self.push_checked(v, span, ctx)?;
+        }
+
+        Op::Undefined => self.stack.push(Value::Undefined),
+
+        Op::Pop => {
+          self.pop();
+        }
+
+        Op::Result => self.result = self.pop(),
+
+        Op::Load(n) => {
+          let name = self.chunk().name(n);
+          force(name, span, ctx, call_value)?;
+          let v = lookup(name, span, ctx)?.clone();
+          self.stack.push(v);
+        }
+
+        Op::Declare(n) => {
+          let v = self.pop();
+          let name = self.chunk().name(n);
+          ctx.current_scope().insert(name, v);
+        }
+
+        Op::DeclareUndefined(n) => {
+          let name = self.chunk().name(n);
+          ctx.current_scope().entry(name).or_insert(Value::Undefined);
+        }
+
+        Op::Store(n) => {
+          let v = self.pop();
+          let v = assign(self.chunk().name(n), v, span, ctx)?;
+          self.stack.push(v);
+        }
+
+        Op::Binary(op) => {
+          let right = self.pop();
+          let left = self.pop();
+          let v = binary(op, left, right, span, &ctx.config)?;
+          self.push_checked(v, span, ctx)?;
+        }
+
+        Op::BinaryConstant { op, constant } => {
+          let right = self.chunk().constants[constant].clone();
+          let left = self.pop();
+          let v = binary(op, left, right, span, &ctx.config)?;
+          self.push_checked(v, span, ctx)?;
+        }
+
+        Op::Member(n) => {
+          let object = self.pop();
+          let v = object
+            .get_attr(span, self.chunk().name(n).as_str())?
+            .clone();
+          self.stack.push(v);
+        }
+
+        Op::Index { array, index_at } => {
+          let index = self.pop();
+          let chunk = &self.frames.last().unwrap().chunk;
+          let (name, index_span) =
+            (chunk.name(array), chunk.locations[index_at]);
+          force(name, span, ctx, call_value)?;
+          let v = element(name, span, index, index_span, ctx)?.clone();
+          self.stack.push(v);
+        }
+
+        Op::Array(n) => {
+          let items = self.stack.split_off(self.stack.len() - n);
+          self.push_checked(Value::SamArray(Arc::new(items)), span, ctx)?;
+        }
+
+        Op::Lambda(n) => {
+          let (params, body) = &self.chunk().lambdas[n];
+          let f = Function::new(params.clone(), body.clone());
+          self.stack.push(Value::from(f));
+        }
+
+        Op::Call { argc, name } => {
+          let f = self.pop();
+          let args = self.stack.split_off(self.stack.len() - argc);
+          let name = self.chunk().name(name);
+          self.call(f, args, name, span, ctx)?;
+        }
+
+        Op::CallName { name, argc } => {
+          let args = self.stack.split_off(self.stack.len() - argc);
+          let name = self.chunk().name(name);
+          force(name, span, ctx, call_value)?;
+
+          match ctx.search_in_stack(name).cloned() {
+            Some(f) => self.call(f, args, name, span, ctx)?,
+            // names that are not variables run as programs of the same name
+            None => {
+              let v = run_command(name.as_str(), args, span, ctx, call_value)?;
+              self.push_checked(v, span, ctx)?;
+            }
+          }
+        }
+
+        Op::Spawn {
+          argc,
+          name,
+          callee_at,
+        } => {
+          let f = self.pop();
+          let args = self.stack.split_off(self.stack.len() - argc);
+          let chunk = self.chunk();
+          let (name, callee_span) =
+            (chunk.name(name), chunk.locations[callee_at]);
+          let v = spawn(f, args, name, callee_span, span, ctx)?;
+          self.stack.push(v);
+        }
+
+        Op::SpawnName {
+          name,
+          argc,
+          callee_at,
+        } => {
+          let args = self.stack.split_off(self.stack.len() - argc);
+          let chunk = self.chunk();
+          let (name, callee_span) =
+            (chunk.name(name), chunk.locations[callee_at]);
+
+          force(name, callee_span, ctx, call_value)?;
+          let v = match ctx.search_in_stack(name).cloned() {
+            Some(f) => spawn(f, args, name, callee_span, span, ctx)?,
+            None => {
+              let options = ctx.config.call_options();
+              Value::from(Handle::spawn(span, move || {
+                Shell::call(name.as_str(), args, &options)
+              }))
+            }
+          };
+          self.stack.push(v);
+        }
+
+        Op::Await { handle_at } => {
+          let Value::SamHandle(h) = self.pop() else {
+            return Err(
+              SamError::type_error(
+                codes::NOT_AWAITABLE,
+                "Only handles returned by spawn can be awaited",
+              )
+              .at(self.chunk().locations[handle_at]),
+            );
+          };
+
+          let v = h.join().map_err(|e| e.at(span))?;
+          self.push_checked(v, span, ctx)?;
+        }
+
+        Op::Stage { argc } => {
+          let f = self.pop();
+          let args = self.stack.split_off(self.stack.len() - argc);
+          self.stages.push(stage(f, args, span)?);
+        }
+
+        Op::StageName { name, argc } => {
+          let args = self.stack.split_off(self.stack.len() - argc);
+          let name = self.chunk().name(name);
+          force(name, span, ctx, call_value)?;
+
+          let s = match ctx.search_in_stack(name).cloned() {
+            Some(f) => stage(f, args, span)?,
+            None => Stage::Shell(name.to_string(), args),
+          };
+          self.stages.push(s);
+        }
+
+        Op::Pipe(n) => {
+          let stages = self.stages.split_off(self.stages.len() - n);
+          let options = ctx.config.call_options();
+          let v = FFI::pipe(stages, &options).map_err(|e| e.at(span))?;
+          self.push_checked(v, span, ctx)?;
+        }
+
+        Op::Interface(n) => {
+          let interface = &self.frames.last().unwrap().chunk.interfaces[n];
+          evaluate_interface(interface, ctx)?;
+        }
+
+        Op::Import(n) => {
+          let import = &self.frames.last().unwrap().chunk.imports[n];
+          modules::import(import, ctx)?;
+        }
+
+        Op::EnterScope => ctx.init_scope(),
+
+        Op::ExitScope(n) => {
+          for _ in 0..n {
+            ctx.destroy_scope();
+          }
+        }
+
+        Op::Jump(target) => self.jump(target),
+
+        Op::Branch(target) => {
+          let cond = self.pop();
+          if !condition(cond, span)? {
+            self.jump(target);
+          }
+        }
+
+        Op::JumpIfDefined { name, target } => {
+          let name = self.chunk().name(name);
+          if ctx.search_in_stack(name).is_some() {
+            self.jump(target);
+          }
+        }
+
+        Op::Iterate => {
+          let iterable = self.pop();
+          let items = iterate(iterable, span)?;
+          self.loops.push(Iteration {
+            items,
+            iterations: 0,
+            span,
+          });
+        }
+
+        Op::Next(target) => {
+          let inner = self.loops.last_mut().unwrap();
+
+          let Some(item) = inner.items.next() else {
+            self.loops.pop();
+            self.jump(target);
+            continue;
+          };
+
+          inner.iterations += 1;
+          count_iteration(inner.iterations, span, ctx)?;
+
+          let v = item.map_err(|e| e.at(inner.span))?;
+          self.stack.push(v);
+        }
+
+        Op::EndIterate => {
+          self.loops.pop();
+        }
+
+        Op::Return => {
+          let v = self.pop();
+
+          let call = self.frames.last().unwrap().call.as_ref();
+          let Some(call_site) = call.map(|(_, call_site)| *call_site) else {
+            return Err(
+              SamError::runtime(
+                codes::RETURN_OUTSIDE_FUNCTION,
+                "Return outside function",
+              )
+              .at(span),
+            );
+          };
+          let frame = self.frames.pop().unwrap();
+          self.leave(&frame, ctx);
+
+          if let Some(max) = ctx.config.limits.value_size {
+            check_size(&v, max, call_site)?;
+          }
+
+          if self.frames.len() == base {
+            return Ok(v);
+          }
+          self.stack.push(v);
+        }
+
+        Op::Fail(failure) => return Err(fail(failure, span)),
+      }
+    }
+  }
+
+  // the chunk of the frame on top
+  fn chunk(&self) -> &Chunk {
+    return &self.frames.last().unwrap().chunk;
+  }
+
+  fn jump(&mut self, target: usize) {
+    self.frames.last_mut().unwrap().ip = target;
+  }
+
+  fn pop(&mut self) -> Value {
+    return self.stack.pop().unwrap();
+  }
+
+  // push a value the script computed, which must fit the size limit
+  fn push_checked(
+    &mut self,
+    v: Value,
+    span: Span,
+    ctx: &Context,
+  ) -> Result<(), SamError> {
+    if let Some(max) = ctx.config.limits.value_size {
+      check_size(&v, max, span)?;
+    }
+
+    self.stack.push(v);
+    return Ok(());
+  }
+
+  // call a value, sam functions get a frame and the others push their
+  // result right away
+  fn call(
+    &mut self,
+    f: Value,
+    args: Vec<Value>,
+    name: Symbol,
+    span: Span,
+    ctx: &mut Context,
+  ) -> Result<(), SamError> {
+    let v = match f {
+      // interface entries run as external commands, whichever scope they
+      // were loaded into
+      Value::SamForeignFunction(ff) => {
+        call_foreign(&ff, &args, span, ctx, |f, args, ctx| {
+          call_now(f, args, name, span, ctx)
+        })?
+      }
+      Value::SamNativeFunction(f) => f.call(&args).map_err(|e| e.at(span))?,
+      Value::SamFunction(func) => {
+        #[cfg(feature = "jit")]
+        if let Some(v) = self.native(&func, &args, name, ctx) {
+          self.stack.push(v);
+          return Ok(());
+        }
+
+        return self.enter(&func, args, name, span, ctx);
+      }
+      _ => return Err(not_callable(span)),
+    };
+
+    return self.push_checked(v, span, ctx);
+  }
+
+  // start running a sam function in a new frame
+  fn enter(
+    &mut self,
+    func: &Function,
+    args: Vec<Value>,
+    name: Symbol,
+    span: Span,
+    ctx: &mut Context,
+  ) -> Result<(), SamError> {
+    let bindings = bind_args(func, args, span, ctx)?;
+
+    let (_, chunk) = self
+      .functions
+      .entry(Arc::as_ptr(&func.body))
+      .or_insert_with(|| {
+        let mut chunk = compile_function(&func.body);
+        peephole::optimize(&mut chunk);
+        (func.body.clone(), Arc::new(chunk))
+      });
+
+    ctx.init_scope();
+    ctx.current_scope().extend(bindings);
+    ctx.calls += 1;
+
+    self.frames.push(Frame {
+      chunk: chunk.clone(),
+      ip: 0,
+      stack: self.stack.len(),
+      // the scope of the parameters is left with the frame
+      scopes: ctx.call_stack.len() - 1,
+      loops: self.loops.len(),
+      call: Some((name, span)),
+      timed: profile::enter(&ctx.config.profile, Kind::Function, name.as_str()),
+    });
+
+    return Ok(());
+  }
+
+  // the result of a call run as native code, none if the function is not
+  // hot yet, has no native code, or its code bailed out
+  #[cfg(feature = "jit")]
+  fn native(
+    &mut self,
+    func: &Function,
+    args: &[Value],
+    name: Symbol,
+    ctx: &mut Context,
+  ) -> Option<Value> {
+    // native code neither counts steps nor stops for an interrupt, and its
+    // calls are not profiled
+    if ctx.config.limits.steps.is_some() || ctx.config.profile.is_some() {
+      return None;
+    }
+
+    // nested calls the limit leaves, the vm raises the error
+    let depth = match ctx.config.limits.call_depth {
+      Some(max) if ctx.calls >= max => return None,
+      Some(max) => (max - ctx.calls) as i64,
+      None => i64::MAX,
+    };
+
+    let key = Arc::as_ptr(&func.body);
+    match self.tiers.get_mut(&key) {
+      None => {
+        self.tiers.insert(key, Tier::Counting(1));
+        return None;
+      }
+      Some(Tier::Counting(calls)) if *calls + 1 < jit::HOT_CALLS => {
+        *calls += 1;
+        return None;
+      }
+      Some(Tier::Counting(_)) => {
+        let tier = self.compile(key, func, name);
+        self.tiers.insert(key, tier);
+      }
+      Some(_) => {}
+    }
+
+    let Some(Tier::Native { code, bails }) = self.tiers.get_mut(&key) else {
+      return None;
+    };
+
+    // the name it calls itself by must still be this function
+    if let Some(own) = code.recursive() {
+      match ctx.search_in_stack(own) {
+        Some(Value::SamFunction(f)) if Arc::ptr_eq(&f.body, &func.body) => {}
+        _ => return None,
+      }
+    }
+
+    let v = code.call(args, depth);
+    if v.is_none() {
+      *bails += 1;
+      if *bails >= jit::HOT_CALLS {
+        self.tiers.insert(key, Tier::Interpreted);
+      }
+    }
+
+    return v;
+  }
+
+  #[cfg(feature = "jit")]
+  fn compile(
+    &mut self,
+    key: *const Block,
+    func: &Function,
+    name: Symbol,
+  ) -> Tier {
+    let Some((_, chunk)) = self.functions.get(&key) else {
+      return Tier::Interpreted;
+    };
+
+    if self.jit.is_none() {
+      self.jit = Jit::new();
+    }
+
+    let code = self
+      .jit
+      .as_mut()
+      .and_then(|jit| jit.compile(chunk, &func.params, name));
+
+    match code {
+      Some(code) => Tier::Native { code, bails: 0 },
+      None => Tier::Interpreted,
+    }
+  }
+
+  // drop what a frame left on the stacks, after a return or an error
+  fn leave(&mut self, frame: &Frame, ctx: &mut Context) {
+    self.stack.truncate(frame.stack);
+    self.loops.truncate(frame.loops);
+    ctx.call_stack.truncate(frame.scopes);
+
+    if frame.call.is_some() {
+      ctx.calls -= 1;
+    }
+  }
+}
+
+// a handle to a call of `f` running in the background
+fn spawn(
+  f: Value,
+  args: Vec<Value>,
+  name: Symbol,
+  callee_span: Span,
+  span: Span,
+  ctx: &mut Context,
+) -> Result<Value, SamError> {
+  let options = ctx.config.call_options();
+
+  let handle = match f {
+    Value::SamForeignFunction(ff) => {
+      Handle::spawn(span, move || FFI::call(&ff, &args, &options))
+    }
+
+    Value::SamNativeFunction(f) => Handle::spawn(span, move || f.call(&args)),
+
+    // sam functions share the interpreter state, so they run right away
+    // and the handle is finished from the start
+    f => Handle::finished(span, call_now(f, args, name, callee_span, ctx)?),
+  };
+
+  return Ok(Value::from(handle));
+}
+
+fn fail(failure: Failure, span: Span) -> SamError {
+  let err = match failure {
+    Failure::NotAnObject => {
+      SamError::type_error(codes::NOT_AN_OBJECT, "Expected identifier")
+    }
+    Failure::ValueExpected => SamError::runtime(
+      codes::RETURN_IN_EXPRESSION,
+      "Unexpected return statement.",
+    ),
+    Failure::NotPipeable => SamError::type_error(
+      codes::NOT_CALLABLE,
+      "Every argument of pipe must be a call",
+    ),
+    Failure::LoopControl => {
+      SamError::parse(codes::BREAK_OUTSIDE_LOOP, "Loop control outside loop")
+    }
+  };
+
+  return err.at(span);
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::config::{Backend, Config, Limits};
+  use crate::evaluate::evaluate_with_config;
+  use crate::value::Number;
+
+  // run a script with both backends, which must agree on the globals
+  fn run_both(source: &str, limits: Limits) -> Result<Context, SamError> {
+    let mut parser = crate::parser();
+    let tree = parser.parse(source, None).unwrap();
+    let root = tree.root_node();
+
+    let run = |backend: Backend| {
+      let config = Config {
+        backend,
+        limits,
+        ..Config::default()
+      };
+      evaluate_with_config(&root, source.as_bytes(), config)
+    };
+
+    match (run(Backend::Tree), run(Backend::Vm)) {
+      (Ok(tree), Ok(vm)) => {
+        assert_eq!(tree.call_stack, vm.call_stack);
+        Ok(vm)
+      }
+      (Err(tree), Err(vm)) => {
+        assert_eq!(tree.code(), vm.code());
+        assert_eq!(tree.message(), vm.message());
+        Err(vm)
+      }
+      (tree, vm) => panic!("backends disagree: {:?} and {:?}", tree, vm),
+    }
+  }
+
+  #[test]
+  fn test_same_results() {
+    let sources = [
+      "let a = 1 + 2 * 3; let b = a > 5;",
+      "let total = 0;
+       for x in [1, 2, 3, 4, 5] {
+         if (x == 2) { continue; }
+         if (x == 4) { break; }
+         total = total + x;
+       }",
+      "let sign = (n) => {
+         if (n < 0) { return 0 - 1; } else if (n == 0) { return 0; }
+         return 1;
+       };
+       let a = sign(0 - 5);
+       let b = sign(0);
+       let c = sign(7);",
+      "let first = (xs) => { for x in xs { return x; } };
+       let a = first([4, 5]);
+       let b = first([]);",
+      "let a = [1, 2, 3]; let b = a[1];",
+      "let f = () => { return 42; }; f(); let b = f();",
+      "let s = 'ab' + 'cd';",
+      // rewritten by the peephole pass
+      "let n = 2; let a = n * 2 + 1; if (1) { a = a - 1; } else { a = 0; }",
+      "let a = 0; if (0) { a = 1; } else if (1 > 2) { a = 2; }",
+    ];
+
+    for source in sources {
+      assert!(run_both(source, Limits::default()).is_ok(), "{}", source);
+    }
+  }
+
+  #[test]
+  fn test_recursion() {
+    let source = "
+      let fib = (n) => {
+        if (n < 2) { return n; }
+        return fib(n - 1) + fib(n - 2);
+      };
+      let a = fib(15);
+    ";
+
+    let ctx = run_both(source, Limits::default()).unwrap();
+    assert_eq!(
+      ctx.call_stack[0]["a"],
+      Value::SamNumber(Number::SamInt(610))
+    );
+  }
+
+  // past HOT_CALLS calls `grow` runs natively with the jit feature, and
+  // bails out to the vm when it overflows
+  #[test]
+  fn test_overflow() {
+    let source = "
+      let grow = (n, x) => {
+        if (n == 0) { return x; }
+        return grow(n - 1, x * 2);
+      };
+      let a = grow(62, 1);
+      let b = grow(70, 1);
+      let c = 9223372036854775807 + 1;
+    ";
+
+    let ctx = run_both(source, Limits::default()).unwrap();
+    let globals = &ctx.call_stack[0];
+    assert!(matches!(
+      globals["a"],
+      Value::SamNumber(Number::SamInt(n)) if n == 1 << 62
+    ));
+    assert!(matches!(
+      globals["b"],
+      Value::SamNumber(Number::SamFloat(f)) if f == 2f64.powi(70)
+    ));
+    assert!(matches!(
+      globals["c"],
+      Value::SamNumber(Number::SamFloat(f)) if f == 2f64.powi(63)
+    ));
+  }
+
+  // `//` starts a comment outside strings only, and floor division is
+  // `floor_div`
+  #[test]
+  fn test_slashes() {
+    let source = "
+      let url = 'https://example.com'; // where it lives
+      let path = \"a//b\"; // 'quoted'
+      let half = 7 / 2; // 3.5
+      let floored = floor_div(0 - 7, 2);
+    ";
+
+    let ctx = run_both(source, Limits::default()).unwrap();
+    let globals = &ctx.call_stack[0];
+    assert_eq!(globals["url"], Value::from("https://example.com"));
+    assert_eq!(globals["path"], Value::from("a//b"));
+    assert_eq!(globals["half"], Value::from(3.5));
+    assert!(matches!(
+      globals["floored"],
+      Value::SamNumber(Number::SamInt(-4))
+    ));
+  }
+
+  #[test]
+  fn test_same_errors() {
+    let sources = [
+      "let a = b;",
+      "let a = [1, 2]; let b = a[2];",
+      "if ('yes') { let a = 1; }",
+      "for x in 1 { let a = x; }",
+      "let f = (x) => { return x; }; let b = f();",
+      "let a = 1; let b = [a];",
+    ];
+
+    for source in sources {
+      assert!(run_both(source, Limits::default()).is_err(), "{}", source);
+    }
+  }
+
+  #[test]
+  fn test_call_trace() {
+    let source = "
+      let inner = (x) => { return x; };
+      let outer = () => { return inner(); };
+      let b = outer();
+    ";
+
+    let err = run_both(source, Limits::default()).unwrap_err();
+
+    let names: Vec<_> = err.trace().iter().map(|f| f.name.as_str()).collect();
+    assert_eq!(names, vec!["inner", "outer"]);
+  }
+
+  #[test]
+  fn test_limits() {
+    let source = "
+      let down = (n) => { if (n > 0) { return down(n - 1); } return 0; };
+      let a = down(50);
+      for x in [1, 2, 3, 4] { a = a + x; }
+    ";
+
+    for limits in [
+      Limits {
+        call_depth: Some(10),
+        ..Limits::default()
+      },
+      Limits {
+        loop_iterations: Some(3),
+        ..Limits::default()
+      },
+    ] {
+      let err = run_both(source, limits).unwrap_err();
+      assert_eq!(err.code(), Some(codes::LIMIT_EXCEEDED));
+    }
+  }
+}
@@ -0,0 +1,371 @@
+#![allow(dead_code)]
+
+use crate::ast;
+use crate::codes;
+use crate::diagnostic::Diagnostic;
+use crate::error::Span;
+use tree_sitter::Node;
+
+// non-fatal checks run over the tree before evaluation: unused variables
+// and interface entries, shadowed names, and unreachable statements
+pub fn check(root: Node, source: &[u8]) -> Vec<Diagnostic> {
+  let mut checker = Checker {
+    source,
+    scopes: Vec::new(),
+    warnings: Vec::new(),
+  };
+
+  checker.push_scope();
+
+  let mut walker = root.walk();
+  for child in root.named_children(&mut walker) {
+    match child.kind() {
+      "interfaces" => checker.interfaces(child),
+      _ => checker.statement(child),
+    }
+  }
+
+  checker.pop_scope();
+
+  let mut warnings = checker.warnings;
+  warnings.sort_by_key(|w| w.span.map(|s| s.start));
+
+  return warnings;
+}
+
+#[derive(PartialEq)]
+enum DeclKind {
+  Variable,
+  Parameter,
+  Interface,
+}
+
+struct Decl {
+  name: String,
+  span: Span,
+  kind: DeclKind,
+  used: bool,
+}
+
+struct Scope<'t> {
+  decls: Vec<Decl>,
+  // function bodies run later, so they are checked once every declaration
+  // of the enclosing scope is known
+  deferred: Vec<Node<'t>>,
+}
+
+struct Checker<'s, 't> {
+  source: &'s [u8],
+  scopes: Vec<Scope<'t>>,
+  warnings: Vec<Diagnostic>,
+}
+
+impl<'s, 't> Checker<'s, 't> {
+  /* =========================
+  Scopes
+  ========================= */
+
+  fn push_scope(&mut self) {
+    self.scopes.push(Scope {
+      decls: Vec::new(),
+      deferred: Vec::new(),
+    });
+  }
+
+  fn pop_scope(&mut self) {
+    // check the functions defined in this scope before it disappears
+    while let Some(lambda) = self.scopes.last_mut().unwrap().deferred.pop() {
+      self.lambda(lambda);
+    }
+
+    let scope = self.scopes.pop().unwrap();
+
+    for decl in scope.decls {
+      if decl.used || decl.name.starts_with('_') {
+        continue;
+      }
+
+      let (code, message) = match decl.kind {
+        DeclKind::Variable => (
+          codes::UNUSED_VARIABLE,
+          format!("unused variable `{}`", decl.name),
+        ),
+        DeclKind::Parameter => (
+          codes::UNUSED_PARAMETER,
+          format!("unused parameter `{}`", decl.name),
+        ),
+        DeclKind::Interface => (
+          codes::UNUSED_INTERFACE,
+          format!("unused interface entry `{}`", decl.name),
+        ),
+      };
+
+      self.warn(
+        Diagnostic::warning(message)
+          .with_code(code)
+          .with_span(Some(decl.span))
+          .with_label("never used")
+          .with_note(format!(
+            "prefix it with an underscore to silence this: `_{}`",
+            decl.name
+          )),
+      );
+    }
+  }
+
+  fn declare(&mut self, node: Node, kind: DeclKind) {
+    let name = self.text(node);
+
+    // only outer scopes count, redeclaring in place is a plain overwrite
+    let (current, outer) = self.scopes.split_last_mut().unwrap();
+    let shadows = outer
+      .iter()
+      .any(|scope| scope.decls.iter().any(|d| d.name == name));
+
+    if shadows && kind != DeclKind::Interface {
+      self.warnings.push(
+        Diagnostic::warning(format!("`{}` shadows an outer variable", name))
+          .with_code(codes::SHADOWED_NAME)
+          .with_span(Some(node.into()))
+          .with_label("shadows the outer declaration"),
+      );
+    }
+
+    // a redeclaration replaces the previous entry of the same scope
+    let used = current
+      .decls
+      .iter()
+      .position(|d| d.name == name)
+      .map(|i| current.decls.remove(i).used)
+      .unwrap_or(false);
+
+    current.decls.push(Decl {
+      name,
+      span: node.into(),
+      kind,
+      used,
+    });
+  }
+
+  fn use_name(&mut self, node: Node) {
+    let name = self.text(node);
+
+    for scope in self.scopes.iter_mut().rev() {
+      if let Some(decl) = scope.decls.iter_mut().find(|d| d.name == name) {
+        decl.used = true;
+        return;
+      }
+    }
+  }
+
+  fn warn(&mut self, warning: Diagnostic) {
+    self.warnings.push(warning);
+  }
+
+  fn text(&self, node: Node) -> String {
+    node.utf8_text(self.source).unwrap_or("").to_owned()
+  }
+
+  /* =========================
+  Statements
+  ========================= */
+
+  fn interfaces(&mut self, node: Node<'t>) {
+    let mut walker = node.walk();
+    for interface in node.named_children(&mut walker) {
+      if let Some(module) = interface.child_by_field_name("module") {
+        self.declare(module, DeclKind::Interface);
+      }
+    }
+  }
+
+  fn statement(&mut self, node: Node<'t>) {
+    match node.kind() {
+      "variable_declaration" => {
+        let mut walker = node.walk();
+        for declarator in node.named_children(&mut walker) {
+          if let Some(value) = declarator.child_by_field_name("value") {
+            self.expression(value);
+          }
+          if let Some(variable) = declarator.child_by_field_name("variable") {
+            self.declare(variable, DeclKind::Variable);
+          }
+        }
+      }
+
+      // assigning is not reading, only the right hand side counts as use
+      "assignment" => {
+        if let Some(rhs) = node.child_by_field_name("rhs") {
+          self.expression(rhs);
+        }
+      }
+
+      _ => self.children(node),
+    }
+  }
+
+  // statements of a block, with the given names already declared in it
+  fn block(&mut self, node: Node<'t>, declared: Vec<(Node<'t>, DeclKind)>) {
+    self.push_scope();
+
+    for (name, kind) in declared {
+      self.declare(name, kind);
+    }
+
+    // the jump ending the block early, if any so far
+    let mut jumped = None;
+    let mut walker = node.walk();
+
+    for stmt in node.named_children(&mut walker) {
+      if let Some(jump) = jumped {
+        self.warn(
+          Diagnostic::warning("unreachable statement")
+            .with_code(codes::UNREACHABLE)
+            .with_span(Some(stmt.into()))
+            .with_label(format!("after a {}", jump))
+            .with_note("this statement will never run"),
+        );
+        break;
+      }
+
+      self.statement(stmt);
+      jumped = match stmt.kind() {
+        "return_statement" => Some("return"),
+        _ => ast::jump(stmt, self.source),
+      };
+    }
+
+    self.pop_scope();
+  }
+
+  fn lambda(&mut self, node: Node<'t>) {
+    let mut params = Vec::new();
+
+    if let Some(params_node) = node.child_by_field_name("parameters") {
+      let mut walker = params_node.walk();
+      for param in params_node.named_children(&mut walker) {
+        if param.kind() == "identifier" {
+          params.push((param, DeclKind::Parameter));
+        }
+      }
+    }
+
+    if let Some(body) = node.child_by_field_name("body") {
+      self.block(body, params);
+    }
+  }
+
+  /* =========================
+  Expressions
+  ========================= */
+
+  fn expression(&mut self, node: Node<'t>) {
+    match node.kind() {
+      "identifier" => self.use_name(node),
+
+      "lambda_expression" => {
+        self.scopes.last_mut().unwrap().deferred.push(node);
+      }
+
+      "statement_block" => self.block(node, Vec::new()),
+
+      "for_expression" => {
+        if let Some(iterable) = node.child_by_field_name("iterable") {
+          self.expression(iterable);
+        }
+
+        let variable = node
+          .child_by_field_name("variable")
+          .map(|v| vec![(v, DeclKind::Variable)])
+          .unwrap_or_default();
+
+        if let Some(body) = node.child_by_field_name("body") {
+          self.block(body, variable);
+        }
+      }
+
+      // only the object is a variable, the property name is not
+      "nested_identifier" => {
+        if let Some(parent) = node.child_by_field_name("parent") {
+          self.expression(parent);
+        }
+      }
+
+      _ => self.children(node),
+    }
+  }
+
+  fn children(&mut self, node: Node<'t>) {
+    let mut walker = node.walk();
+    for child in node.named_children(&mut walker) {
+      self.expression(child);
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use tree_sitter::{Language, Parser};
+
+  // retrieve Language struct from C code
+  unsafe extern "C" {
+    fn tree_sitter_sam() -> Language;
+  }
+
+  fn warnings_for(source: &[u8]) -> Vec<String> {
+    let language = unsafe { tree_sitter_sam() };
+    let mut parser = Parser::new();
+    parser.set_language(&language).unwrap();
+
+    let tree = parser.parse(source, None).unwrap();
+
+    return check(tree.root_node(), source)
+      .into_iter()
+      .map(|w| w.message)
+      .collect();
+  }
+
+  #[test]
+  fn test_unused_variable() {
+    let warnings = warnings_for(b"let a = 1; let b = 2; let c = a + 1;");
+    assert_eq!(warnings, vec!["unused variable `b`", "unused variable `c`"]);
+  }
+
+  #[test]
+  fn test_used_inside_function() {
+    let warnings = warnings_for(
+      b"
+      let f = (x) => { return x + y; };
+      let y = 1;
+      let _z = f(2);
+    ",
+    );
+    assert!(warnings.is_empty());
+  }
+
+  #[test]
+  fn test_shadowing_and_unreachable() {
+    let warnings = warnings_for(
+      b"
+      let x = 1;
+      let f = () => { let x = 2; return x; x = 3; };
+      let _r = f() + x;
+    ",
+    );
+    assert_eq!(
+      warnings,
+      vec!["`x` shadows an outer variable", "unreachable statement"]
+    );
+
+    let warnings = warnings_for(
+      b"
+      for x in [1, 2] { if (x) { break; x = 0; } continue; x = 1; }
+    ",
+    );
+    assert_eq!(
+      warnings,
+      vec!["unreachable statement", "unreachable statement"]
+    );
+  }
+}
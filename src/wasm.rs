@@ -0,0 +1,72 @@
+#![allow(dead_code)]
+
+// JavaScript interface for running sam in the browser, e.g. in a
+// playground. Built with the `wasm` feature for wasm32-unknown-unknown, the
+// grammar needs a clang that can target WebAssembly:
+//
+//   cargo build --lib --release --target wasm32-unknown-unknown \
+//     --features wasm
+//   wasm-bindgen --target web --out-dir pkg \
+//     target/wasm32-unknown-unknown/release/sam.wasm
+//
+// There are no processes or shared libraries in the browser, so shell
+// commands and interface entries fail with an error when called.
+
+use crate::diagnostic::Diagnostic;
+use crate::interpreter::Interpreter;
+use crate::value::Value;
+use wasm_bindgen::prelude::*;
+
+// file name shown in diagnostics
+const FILE: &str = "<playground>";
+
+// an interpreter session kept between evaluations, like the REPL
+#[wasm_bindgen]
+pub struct Session {
+  interpreter: Interpreter,
+}
+
+#[wasm_bindgen]
+impl Session {
+  #[wasm_bindgen(constructor)]
+  pub fn new() -> Session {
+    return Session {
+      interpreter: Interpreter::new(),
+    };
+  }
+
+  // the value of the last statement as text, empty if it has none; errors
+  // are thrown with the rendered diagnostic as their message
+  pub fn eval(&mut self, source: &str) -> Result<String, JsError> {
+    match self.interpreter.eval(source) {
+      Ok(Value::Undefined) => Ok(String::new()),
+      Ok(value) => Ok(value.to_string()),
+      Err(e) => Err(JsError::new(
+        &Diagnostic::from(&e).render(FILE, self.interpreter.source()),
+      )),
+    }
+  }
+
+  // warnings found since the last call, rendered like errors
+  pub fn warnings(&mut self) -> Vec<String> {
+    let warnings: Vec<Diagnostic> =
+      self.interpreter.context_mut().warnings.drain(..).collect();
+
+    return warnings
+      .iter()
+      .map(|w| w.render(FILE, self.interpreter.source()))
+      .collect();
+  }
+}
+
+impl Default for Session {
+  fn default() -> Session {
+    return Session::new();
+  }
+}
+
+// evaluate a whole script in a fresh session
+#[wasm_bindgen]
+pub fn eval(source: &str) -> Result<String, JsError> {
+  return Session::new().eval(source);
+}
@@ -0,0 +1,247 @@
+#![allow(dead_code)]
+
+// The little of ZeroMQ the Jupyter kernel needs: ZMTP 3.0 over TCP with the
+// NULL mechanism (https://rfc.zeromq.org/spec/23/), for the kernel's end of
+// ROUTER, PUB, and REP sockets. Each accepted connection is one peer.
+// Replies go back on the connection a message came from, so the routing
+// identities of ROUTER sockets are never needed, and PUB sends every
+// message to every subscriber, topics being left to the client to filter.
+
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+
+const MORE: u8 = 0x01;
+const LONG: u8 = 0x02;
+const COMMAND: u8 = 0x04;
+
+// the frames of one message
+pub type Message = Vec<Vec<u8>>;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SocketType {
+  Router,
+  Pub,
+  Rep,
+}
+
+impl SocketType {
+  fn name(self) -> &'static str {
+    match self {
+      SocketType::Router => "ROUTER",
+      SocketType::Pub => "PUB",
+      SocketType::Rep => "REP",
+    }
+  }
+}
+
+// a peer connected to one of our sockets, past the handshake
+pub struct Connection {
+  stream: TcpStream,
+}
+
+impl Connection {
+  // greet the peer that connected on `stream` and exchange READY commands
+  pub fn accept(
+    mut stream: TcpStream,
+    kind: SocketType,
+  ) -> io::Result<Connection> {
+    stream.set_nodelay(true)?;
+    stream.write_all(&greeting())?;
+
+    let mut theirs = [0; 64];
+    stream.read_exact(&mut theirs)?;
+    if theirs[0] != 0xff || theirs[9] != 0x7f || theirs[10] < 3 {
+      return Err(invalid("not a ZMTP 3 peer"));
+    }
+    if !theirs[12..32].starts_with(b"NULL\0") {
+      return Err(invalid("only the NULL mechanism is supported"));
+    }
+
+    let mut connection = Connection { stream };
+    connection.send_frame(&ready(kind), COMMAND)?;
+
+    let (body, flags) = connection.read_frame()?;
+    if flags & COMMAND == 0 || !body.starts_with(b"\x05READY") {
+      return Err(invalid("expected a READY command"));
+    }
+
+    return Ok(connection);
+  }
+
+  // another handle to the same connection, e.g. to reply from a thread
+  // other than the one reading
+  pub fn try_clone(&self) -> io::Result<Connection> {
+    return Ok(Connection {
+      stream: self.stream.try_clone()?,
+    });
+  }
+
+  // the next message, skipping commands such as PING
+  pub fn recv(&mut self) -> io::Result<Message> {
+    let mut frames = Vec::new();
+
+    loop {
+      let (body, flags) = self.read_frame()?;
+      if flags & COMMAND != 0 {
+        continue;
+      }
+
+      frames.push(body);
+      if flags & MORE == 0 {
+        return Ok(frames);
+      }
+    }
+  }
+
+  pub fn send(&mut self, message: &[Vec<u8>]) -> io::Result<()> {
+    // written at once, so that messages of several threads never interleave
+    let mut bytes = Vec::new();
+    for (i, frame) in message.iter().enumerate() {
+      let more = if i + 1 < message.len() { MORE } else { 0 };
+      encode_frame(&mut bytes, frame, more);
+    }
+
+    self.stream.write_all(&bytes)?;
+    return self.stream.flush();
+  }
+
+  fn send_frame(&mut self, body: &[u8], flags: u8) -> io::Result<()> {
+    let mut bytes = Vec::new();
+    encode_frame(&mut bytes, body, flags);
+    return self.stream.write_all(&bytes);
+  }
+
+  fn read_frame(&mut self) -> io::Result<(Vec<u8>, u8)> {
+    let mut flags = [0; 1];
+    self.stream.read_exact(&mut flags)?;
+
+    let size = match flags[0] & LONG {
+      0 => {
+        let mut size = [0; 1];
+        self.stream.read_exact(&mut size)?;
+        size[0] as u64
+      }
+      _ => {
+        let mut size = [0; 8];
+        self.stream.read_exact(&mut size)?;
+        u64::from_be_bytes(size)
+      }
+    };
+
+    let mut body = Vec::new();
+    (&mut self.stream).take(size).read_to_end(&mut body)?;
+    if body.len() as u64 != size {
+      return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+
+    return Ok((body, flags[0]));
+  }
+}
+
+// accepts peers of one socket bound to `address`, e.g. 127.0.0.1:5555
+pub struct Socket {
+  listener: TcpListener,
+  kind: SocketType,
+}
+
+impl Socket {
+  pub fn bind(address: &str, kind: SocketType) -> io::Result<Socket> {
+    return Ok(Socket {
+      listener: TcpListener::bind(address)?,
+      kind,
+    });
+  }
+
+  pub fn port(&self) -> io::Result<u16> {
+    return Ok(self.listener.local_addr()?.port());
+  }
+
+  // the next peer, blocking until one connects and greets us; peers that
+  // fail the handshake are dropped
+  pub fn accept(&self) -> io::Result<Connection> {
+    loop {
+      let (stream, _) = self.listener.accept()?;
+      if let Ok(connection) = Connection::accept(stream, self.kind) {
+        return Ok(connection);
+      }
+    }
+  }
+}
+
+fn greeting() -> [u8; 64] {
+  let mut greeting = [0; 64];
+  greeting[0] = 0xff;
+  greeting[9] = 0x7f;
+  // version 3.0
+  greeting[10] = 3;
+  greeting[12..16].copy_from_slice(b"NULL");
+  // as-server and the filler stay zero
+  return greeting;
+}
+
+// the READY command with the metadata of our socket
+fn ready(kind: SocketType) -> Vec<u8> {
+  let mut body = b"\x05READY".to_vec();
+  let name = b"Socket-Type";
+  body.push(name.len() as u8);
+  body.extend_from_slice(name);
+  body.extend_from_slice(&(kind.name().len() as u32).to_be_bytes());
+  body.extend_from_slice(kind.name().as_bytes());
+  return body;
+}
+
+fn encode_frame(bytes: &mut Vec<u8>, body: &[u8], flags: u8) {
+  match body.len() {
+    0..=255 => {
+      bytes.push(flags);
+      bytes.push(body.len() as u8);
+    }
+    _ => {
+      bytes.push(flags | LONG);
+      bytes.extend_from_slice(&(body.len() as u64).to_be_bytes());
+    }
+  }
+  bytes.extend_from_slice(body);
+}
+
+fn invalid(message: &str) -> io::Error {
+  return io::Error::new(io::ErrorKind::InvalidData, message.to_owned());
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::thread;
+
+  #[test]
+  fn test_message_round_trip() {
+    let socket = Socket::bind("127.0.0.1:0", SocketType::Router).unwrap();
+    let address = format!("127.0.0.1:{}", socket.port().unwrap());
+
+    // the other end is a peer like ours, as far as the wire goes
+    let peer = thread::spawn(move || {
+      let stream = TcpStream::connect(address).unwrap();
+      let mut peer = Connection::accept(stream, SocketType::Rep).unwrap();
+      let message = peer.recv().unwrap();
+      peer.send(&message).unwrap();
+    });
+
+    let mut connection = socket.accept().unwrap();
+    let message = vec![b"<IDS|MSG>".to_vec(), Vec::new(), vec![7; 300]];
+    connection.send(&message).unwrap();
+
+    assert_eq!(connection.recv().unwrap(), message);
+    peer.join().unwrap();
+  }
+
+  #[test]
+  fn test_encode_frame() {
+    let mut bytes = Vec::new();
+    encode_frame(&mut bytes, b"ab", MORE);
+    assert_eq!(bytes, b"\x01\x02ab");
+
+    let mut bytes = Vec::new();
+    encode_frame(&mut bytes, &[0; 256], 0);
+    assert_eq!(&bytes[..9], b"\x02\x00\x00\x00\x00\x00\x00\x01\x00");
+  }
+}